@@ -64,7 +64,7 @@ pub enum TicTacToeAction {
 }
 
 /// Tic Tac Toe rule errors.
-#[derive(Debug, Error)]
+#[derive(Debug, Clone, Error, Serialize, Deserialize)]
 pub enum GameError {
     #[error("Not your turn")]
     NotYourTurn,
@@ -90,6 +90,8 @@ impl GameLogic for TicTacToeLogic {
     type GameAction = TicTacToeAction;
     type PlayerRole = PlayerRole;
     type PlayerLeaveReason = ();
+    type Phase = ();
+    type GameMessage = ();
     type GameError = GameError;
 
     fn is_observer_role(&self, role: &Self::PlayerRole) -> bool {
@@ -106,7 +108,10 @@ impl GameLogic for TicTacToeLogic {
         player_ids.sort();
 
         for player_id in player_ids {
-            let role = player_roles.next().unwrap_or(PlayerRole::Observer);
+            let role = match players[&player_id].is_observer {
+                true => PlayerRole::Observer,
+                false => player_roles.next().unwrap_or(PlayerRole::Observer),
+            };
             roles.insert(player_id, role);
         }
         Ok(roles)
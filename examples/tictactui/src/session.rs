@@ -143,7 +143,7 @@ impl RoomSession {
             None => match self.snapshot.app_state {
                 AppState::Lobby => "Waiting in lobby".to_string(),
                 AppState::Paused => "Paused; host offline".to_string(),
-                AppState::InGame => "Waiting for game state".to_string(),
+                AppState::InGame | AppState::Custom(_) => "Waiting for game state".to_string(),
                 AppState::Finished => "Finished".to_string(),
             },
         }
@@ -173,19 +173,53 @@ impl RoomSession {
                 self.chat_log.push(line.clone());
                 chat_message = Some(line);
             }
-            UiEvent::ActionResult(result) => {
-                if !result.accepted {
-                    self.notice(format!(
-                        "Action rejected: {}",
-                        result.error.unwrap_or_else(|| "unknown error".to_string())
-                    ));
-                }
+            UiEvent::ChatUpdated(_) => self.notice("Chat updated"),
+            UiEvent::ActionAccepted { .. } => {}
+            UiEvent::ActionRejected { error, .. } => {
+                self.notice(format!("Action rejected: {error}"))
+            }
+            UiEvent::Annotation(annotation) => {
+                self.notice(format!("Move annotated: {}", annotation.text))
             }
+            UiEvent::TeamChanged { .. } => self.notice("Teams updated"),
             UiEvent::Host(HostEvent::Online) => self.notice("Host reconnected"),
+            UiEvent::PlayerForfeited { .. } => self.notice("A player forfeited"),
+            UiEvent::SeatRequested { .. } => self.notice("An observer asked to play"),
             UiEvent::Host(HostEvent::Offline) => self.notice("Host disconnected; game paused"),
             UiEvent::Host(HostEvent::Changed { to }) => {
                 self.notice(format!("Host changed to {to}"))
             }
+            UiEvent::Rehosted { ticket } => {
+                self.notice(format!("Host started a new room: {ticket}"))
+            }
+            UiEvent::InactivityWarning { finishes_in } => self.notice(format!(
+                "Game ends for inactivity in {}s",
+                finishes_in.as_secs()
+            )),
+            UiEvent::TurnChanged { .. } => {}
+            UiEvent::Realtime { .. } => {}
+            UiEvent::PredictionCorrected(_) => {}
+            UiEvent::ProposalMade(request) => {
+                self.notice(format!("A player proposed a {}", request.proposal))
+            }
+            UiEvent::ProposalVoted { .. } => {}
+            UiEvent::ProposalResolved(outcome) => match outcome.accepted {
+                true => self.notice(format!("The {} was agreed", outcome.proposal)),
+                false => self.notice(format!("The {} was refused", outcome.proposal)),
+            },
+            UiEvent::RolledBack { label } => {
+                self.notice(format!("Host rolled back to '{label}'"))
+            }
+            UiEvent::JoinDenied(reason) => self.notice(format!("Could not join: {reason:?}")),
+            UiEvent::MetaUpdated(_) => {}
+            UiEvent::Syncing {
+                entries_done,
+                entries_total,
+            } => self.notice(format!("Syncing room {entries_done}/{entries_total}")),
+            UiEvent::InitialSnapshot { app_state, .. } => {
+                self.notice(format!("Joined room, now {app_state:?}"))
+            }
+            UiEvent::NetworkStats(_) => {}
             UiEvent::Error(error) => self.notice(format!("Error: {error}")),
         }
         self.refresh().await?;
@@ -216,7 +250,7 @@ impl RoomSession {
             .submit_action(TicTacToeAction::Place(self.selected_cell as u8))
             .await
         {
-            Ok(()) => self.notice(format!("Submitted cell {}", self.selected_cell + 1)),
+            Ok(_) => self.notice(format!("Submitted cell {}", self.selected_cell + 1)),
             Err(err) => self.notice(format!("Move not sent: {err}")),
         }
         Ok(())
@@ -262,8 +296,7 @@ impl RoomSession {
     }
 
     pub async fn leave(self) -> Result<()> {
-        self.room
-            .announce_leave(&LeaveReason::ApplicationClosed)
-            .await
+        self.room.leave(&LeaveReason::ApplicationClosed, true).await?;
+        Ok(())
     }
 }
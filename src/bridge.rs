@@ -0,0 +1,340 @@
+//! JSON-RPC bridge
+//!
+//! This module runs a local WebSocket server for a room, behind the `bridge` feature, so a
+//! frontend written in any language, e.g. an Electron, Tauri or Godot app, can drive a room
+//! process without Rust bindings.
+//!
+//! Clients send [JSON-RPC 2.0](https://www.jsonrpc.org/specification) requests as text
+//! messages, and receive the room's events as `event` notifications:
+//!
+//! ```json
+//! {"jsonrpc": "2.0", "id": 1, "method": "submit_action", "params": {"action": "Increment"}}
+//! {"jsonrpc": "2.0", "id": 1, "result": 1}
+//! {"jsonrpc": "2.0", "method": "event", "params": {"GameState": {"counter": 1}}}
+//! ```
+//!
+//! Events are the JSON form of [UiEvent], and actions and game states the JSON forms of the
+//! game's `GameAction` and `GameState`.
+//!
+//! Any web page the user visits can reach a local port, so clients must present the bridge's
+//! [Bridge::token], handed to them out of band, as a URL-encoded `token` query parameter, e.g.
+//! `ws://127.0.0.1:9000/?token=...`, or an `Authorization: Bearer` header. Connections from
+//! browsers are also refused unless their `Origin` is allowed with [BridgeConfig::allow_origin].
+//!
+//! The methods are `announce_presence` (`nickname`), `set_ready` (`ready`), `start_game`,
+//! `set_phase` (`phase`), `submit_action` (`action`), `send_chat` (`message`), `ticket`,
+//! `get_peers`, `get_app_state` and `get_game_state`.
+
+use std::{borrow::Cow, net::SocketAddr, sync::Arc};
+
+use anyhow::{Result, anyhow};
+use crypto_secretbox::aead::{OsRng, rand_core::RngCore as _};
+use n0_future::{SinkExt as _, StreamExt as _};
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+use tokio::{
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    task::{JoinHandle, JoinSet},
+};
+use tokio_tungstenite::tungstenite::{
+    Message,
+    handshake::server::{Callback, ErrorResponse, Request, Response},
+    http::StatusCode,
+};
+
+use crate::{EventFilter, GameLogic, GameRoom};
+
+/// JSON-RPC error code for a message that is not valid JSON.
+const PARSE_ERROR: i64 = -32700;
+/// JSON-RPC error code for a method the bridge does not have.
+const METHOD_NOT_FOUND: i64 = -32601;
+/// JSON-RPC error code for missing or mistyped parameters.
+const INVALID_PARAMS: i64 = -32602;
+/// JSON-RPC error code for a request the room failed to carry out.
+const ROOM_ERROR: i64 = -32000;
+
+/// Who may connect to a bridge, see [Bridge::serve_with_config].
+#[derive(Debug, Clone, Default)]
+pub struct BridgeConfig {
+    /// The token clients must present, or `None` to generate a random one.
+    pub token: Option<String>,
+    /// The `Origin`s browser clients may connect from, e.g. `http://localhost:5173`.
+    pub allowed_origins: Vec<String>,
+}
+
+impl BridgeConfig {
+    /// Require clients to present `token`, e.g. one the app generated and passed to its
+    /// frontend process, in place of a random one.
+    pub fn token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Let browser clients connect from `origin`.
+    pub fn allow_origin(mut self, origin: impl Into<String>) -> Self {
+        self.allowed_origins.push(origin.into());
+        self
+    }
+}
+
+/// A running WebSocket bridge to a room. Dropping it closes the server and every connection.
+pub struct Bridge {
+    addr: SocketAddr,
+    token: String,
+    task: JoinHandle<()>,
+}
+
+impl Drop for Bridge {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl Bridge {
+    /// Serve `room` to WebSocket clients connecting on `addr`, e.g. `"127.0.0.1:0"`, with a
+    /// random [Bridge::token] and no browser origins allowed.
+    ///
+    /// Each client receives the room's events from the moment it connects.
+    pub async fn serve<G: GameLogic>(
+        room: Arc<GameRoom<G>>,
+        addr: impl ToSocketAddrs,
+    ) -> Result<Self> {
+        Self::serve_with_config(room, addr, BridgeConfig::default()).await
+    }
+
+    /// Serve `room` to WebSocket clients connecting on `addr`, letting in those `config` allows.
+    pub async fn serve_with_config<G: GameLogic>(
+        room: Arc<GameRoom<G>>,
+        addr: impl ToSocketAddrs,
+        config: BridgeConfig,
+    ) -> Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        let token = config.token.clone().unwrap_or_else(random_token);
+        let access = Arc::new(Access {
+            token: token.clone(),
+            allowed_origins: config.allowed_origins,
+        });
+        let task = tokio::spawn(async move {
+            let mut connections = JoinSet::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                connections.spawn(serve_client(room.clone(), stream, access.clone()));
+            }
+        });
+        Ok(Self { addr, token, task })
+    }
+
+    /// The address the bridge is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The token clients must present to connect, to hand to the frontend out of band, e.g.
+    /// on its command line.
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+}
+
+/// What a client must show to be let in.
+struct Access {
+    token: String,
+    allowed_origins: Vec<String>,
+}
+
+/// Checks a client's handshake, refusing it unless it has the token and, if it comes from a
+/// browser, an allowed origin.
+impl Callback for &Access {
+    fn on_request(self, request: &Request, response: Response) -> Result<Response, ErrorResponse> {
+        let Some((status, reason)) = self.refusal(request) else {
+            return Ok(response);
+        };
+        let mut refusal = ErrorResponse::new(Some(reason.to_string()));
+        *refusal.status_mut() = status;
+        Err(refusal)
+    }
+}
+
+impl Access {
+    /// Why a client's handshake is refused, if it is.
+    fn refusal(&self, request: &Request) -> Option<(StatusCode, &'static str)> {
+        if let Some(origin) = request.headers().get("origin") {
+            let allowed = origin
+                .to_str()
+                .is_ok_and(|origin| self.allowed_origins.iter().any(|allowed| allowed == origin));
+            if !allowed {
+                return Some((StatusCode::FORBIDDEN, "Origin not allowed"));
+            }
+        }
+        let bearer = request
+            .headers()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        let query = request.uri().query().into_iter().flat_map(|query| {
+            form_urlencoded::parse(query.as_bytes())
+                .filter(|(name, _)| name == "token")
+                .map(|(_, token)| token)
+        });
+        match bearer
+            .map(Cow::Borrowed)
+            .into_iter()
+            .chain(query)
+            .any(|token| self.matches(&token))
+        {
+            true => None,
+            false => Some((StatusCode::UNAUTHORIZED, "Missing or wrong token")),
+        }
+    }
+
+    /// Compare a presented token with the bridge's without stopping at the first difference.
+    fn matches(&self, token: &str) -> bool {
+        let (expected, token) = (self.token.as_bytes(), token.as_bytes());
+        expected.len() == token.len()
+            && expected
+                .iter()
+                .zip(token)
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+    }
+}
+
+/// A random token, as 64 hex digits.
+fn random_token() -> String {
+    let mut bytes = [0; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Answer a client's requests and forward the room's events to it until either side closes.
+async fn serve_client<G: GameLogic>(
+    room: Arc<GameRoom<G>>,
+    stream: TcpStream,
+    access: Arc<Access>,
+) -> Result<()> {
+    let mut socket = tokio_tungstenite::accept_hdr_async(stream, &*access).await?;
+    let mut events = room.subscribe(EventFilter::default());
+    loop {
+        tokio::select! {
+            message = socket.next() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return Ok(()),
+                    Some(Ok(_)) => continue,
+                };
+                let response = answer(&room, &text).await;
+                socket.send(Message::text(response.to_string())).await?;
+            }
+            event = events.recv() => {
+                let Some(event) = event else {
+                    return Ok(());
+                };
+                let notification = json!({
+                    "jsonrpc": "2.0",
+                    "method": "event",
+                    "params": to_json(&event)?,
+                });
+                socket.send(Message::text(notification.to_string())).await?;
+            }
+        }
+    }
+}
+
+/// Carry out a JSON-RPC request, returning the response to send back.
+async fn answer<G: GameLogic>(room: &GameRoom<G>, text: &str) -> Value {
+    let request: Value = match serde_json::from_str(text) {
+        Ok(request) => request,
+        Err(e) => return error_response(Value::Null, PARSE_ERROR, e.to_string()),
+    };
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let Some(method) = request.get("method").and_then(Value::as_str) else {
+        return error_response(id, METHOD_NOT_FOUND, "Missing method".to_string());
+    };
+    let params = request.get("params").cloned().unwrap_or(Value::Null);
+    match call(room, method, &params).await {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(RpcError { code, message }) => error_response(id, code, message),
+    }
+}
+
+/// A JSON-RPC error, as sent back to the client.
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(error: anyhow::Error) -> Self {
+        Self {
+            code: ROOM_ERROR,
+            message: error.to_string(),
+        }
+    }
+}
+
+fn error_response(id: Value, code: i64, message: String) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+/// Read the parameter called `name` from a request's parameters.
+fn param<T: DeserializeOwned>(params: &Value, name: &str) -> Result<T, RpcError> {
+    let value = params.get(name).cloned().ok_or_else(|| RpcError {
+        code: INVALID_PARAMS,
+        message: format!("Missing parameter '{name}'"),
+    })?;
+    serde_json::from_value(value).map_err(|e| RpcError {
+        code: INVALID_PARAMS,
+        message: format!("Invalid parameter '{name}': {e}"),
+    })
+}
+
+async fn call<G: GameLogic>(
+    room: &GameRoom<G>,
+    method: &str,
+    params: &Value,
+) -> Result<Value, RpcError> {
+    let result = match method {
+        "announce_presence" => {
+            let nickname: String = param(params, "nickname")?;
+            room.announce_presence(nickname.as_str()).await?;
+            Value::Null
+        }
+        "set_ready" => {
+            room.set_ready(param(params, "ready")?).await?;
+            Value::Null
+        }
+        "start_game" => {
+            room.start_game().await?;
+            Value::Null
+        }
+        "set_phase" => {
+            room.set_phase(param(params, "phase")?).await?;
+            Value::Null
+        }
+        "submit_action" => json!(room.submit_action(param(params, "action")?).await?),
+        "send_chat" => {
+            let message: String = param(params, "message")?;
+            room.send_chat(&message).await?;
+            Value::Null
+        }
+        "ticket" => json!(room.ticket().await?.to_string()),
+        "get_peers" => to_json(&room.get_peer_list().await?)?,
+        "get_app_state" => to_json(&room.get_app_state().await?)?,
+        "get_game_state" => to_json(&room.get_game_state().await?)?,
+        method => {
+            return Err(RpcError {
+                code: METHOD_NOT_FOUND,
+                message: format!("Unknown method '{method}'"),
+            });
+        }
+    };
+    Ok(result)
+}
+
+fn to_json(value: &impl serde::Serialize) -> Result<Value> {
+    serde_json::to_value(value).map_err(|e| anyhow!("Failed to encode JSON: {e}"))
+}
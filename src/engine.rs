@@ -0,0 +1,101 @@
+//! Pure game state machine
+//!
+//! This module contains the `Engine` struct, the part of the host that applies actions to the
+//! game state. It has no room, document or network behind it, so the same code that runs a
+//! live session can replay a room's action log anywhere, e.g. in a property test that checks
+//! every replay of a session reaches the state the session itself reached.
+
+use crate::{AppliedAction, GameLogic, GameOutcome};
+use iroh::EndpointId;
+use std::{collections::BTreeMap, sync::Arc};
+
+/// A game's state and the rules that change it, as applied by the host.
+#[derive(Debug)]
+pub struct Engine<G: GameLogic> {
+    logic: Arc<G>,
+    state: G::GameState,
+}
+
+impl<G: GameLogic> Engine<G> {
+    /// Start from `state`, e.g. the game's initial state.
+    pub fn new(logic: impl Into<Arc<G>>, state: G::GameState) -> Self {
+        Self {
+            logic: logic.into(),
+            state,
+        }
+    }
+
+    /// The current game state.
+    pub fn state(&self) -> &G::GameState {
+        &self.state
+    }
+
+    /// Take the current game state, leaving the engine behind.
+    pub fn into_state(self) -> G::GameState {
+        self.state
+    }
+
+    /// Apply a player's action, as the host does when it receives one.
+    ///
+    /// An action the game rejects leaves the state as it was.
+    pub fn apply(
+        &mut self,
+        player: &EndpointId,
+        action: &G::GameAction,
+    ) -> Result<(), G::GameError> {
+        let mut next = self.state.clone();
+        self.logic.apply_action(&mut next, player, action)?;
+        self.state = next;
+        Ok(())
+    }
+
+    /// Apply a round of simultaneous actions, as the host does when the round closes, see
+    /// [crate::RoomConfig::rounds].
+    ///
+    /// A round the game rejects leaves the state as it was.
+    pub fn apply_round(
+        &mut self,
+        round: u64,
+        actions: &BTreeMap<EndpointId, G::GameAction>,
+    ) -> Result<(), G::GameError> {
+        let mut next = self.state.clone();
+        self.logic.apply_round(&mut next, round, actions)?;
+        self.state = next;
+        Ok(())
+    }
+
+    /// Apply the actions of an action log in order, see [crate::GameRoom::get_action_log].
+    ///
+    /// Actions logged as part of a round are applied together, as the round they were.
+    /// Only applied actions are logged, so a rejection means the replay has gone off track.
+    pub fn replay<'a>(
+        &mut self,
+        log: impl IntoIterator<Item = &'a AppliedAction<G::GameAction>>,
+    ) -> Result<(), G::GameError>
+    where
+        G::GameAction: 'a,
+    {
+        let mut round: Option<(u64, BTreeMap<EndpointId, G::GameAction>)> = None;
+        for applied in log {
+            if let Some((id, actions)) = round.take_if(|(id, _)| applied.round != Some(*id)) {
+                self.apply_round(id, &actions)?;
+            }
+            match applied.round {
+                Some(id) => {
+                    let (_, actions) = round.get_or_insert_with(|| (id, BTreeMap::new()));
+                    actions.insert(applied.player, applied.action.clone());
+                }
+                None => self.apply(&applied.player, &applied.action)?,
+            }
+        }
+        if let Some((id, actions)) = round {
+            self.apply_round(id, &actions)?;
+        }
+        Ok(())
+    }
+
+    /// The game's outcome, once the current state has decided it.
+    pub fn outcome(&self) -> Option<GameOutcome> {
+        self.logic.game_outcome(&self.state)
+    }
+}
@@ -0,0 +1,443 @@
+//! Foreign Function Interface
+//!
+//! This module exposes rooms to Kotlin, Swift and other languages through UniFFI, behind the
+//! `ffi` feature. Games are written in the host language by implementing the
+//! `ForeignGameLogic` callback interface, whose states and actions are opaque bytes the
+//! engine stores and syncs without looking inside, e.g. JSON of the app's choosing.
+//! `FfiRoom` wraps a `GameRoom` for such a game, and reports its events as `FfiEvent`s.
+//!
+//! Build the library as a `cdylib` or `staticlib` with `--features ffi` and generate the
+//! bindings from it with `uniffi-bindgen`.
+
+use std::{collections::HashMap, fmt::Debug, path::PathBuf, sync::Arc};
+
+use iroh::EndpointId;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Mutex, RwLock, RwLockReadGuard, mpsc};
+
+use crate::{
+    ConnectionEffect, FinishReason, GameLogic, GameOutcome, GameRoom, LeaveReason, PeerInfo,
+    PeerMap, UiEvent,
+};
+
+/// An error reported across the FFI boundary.
+#[derive(uniffi::Error, thiserror::Error, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum FfiError {
+    /// The game's rules refused something, as reported by the foreign game logic.
+    #[error("{reason}")]
+    Game { reason: String },
+    /// The room could not do what was asked, e.g. the network failed or the room was left.
+    #[error("{reason}")]
+    Room { reason: String },
+}
+
+impl From<anyhow::Error> for FfiError {
+    fn from(error: anyhow::Error) -> Self {
+        FfiError::Room {
+            reason: error.to_string(),
+        }
+    }
+}
+
+/// A peer in the room, as the foreign game logic and application see it.
+#[derive(uniffi::Record, Debug, Clone, PartialEq, Eq)]
+pub struct FfiPeer {
+    pub id: String,
+    pub nickname: String,
+    pub online: bool,
+    pub ready: bool,
+    pub is_observer: bool,
+}
+
+impl From<&PeerInfo> for FfiPeer {
+    fn from(peer: &PeerInfo) -> Self {
+        Self {
+            id: peer.id.to_string(),
+            nickname: peer.profile.nickname.clone(),
+            online: peer.status.is_online(),
+            ready: peer.ready,
+            is_observer: peer.is_observer,
+        }
+    }
+}
+
+/// The rules of a game written in another language, see [crate::GameLogic].
+///
+/// Game states and actions are opaque bytes. Peers leaving, returning and forfeiting do not
+/// change the game state.
+#[uniffi::export(with_foreign)]
+pub trait ForeignGameLogic: Send + Sync {
+    /// The name of the game, which tells its rooms apart from other games' rooms. Read once,
+    /// when a room is created or joined.
+    fn game_name(&self) -> String;
+    /// Give each player a role at the start of the game, keyed by peer ID.
+    fn assign_roles(&self, players: Vec<FfiPeer>) -> Result<HashMap<String, String>, FfiError>;
+    /// Check that the game can start with these players.
+    fn validate_start(&self, players: Vec<FfiPeer>) -> Result<(), FfiError>;
+    /// Create the state the game starts from.
+    fn initial_state(&self, players: Vec<FfiPeer>) -> Result<Vec<u8>, FfiError>;
+    /// Apply a player's action, returning the new state.
+    fn apply_action(
+        &self,
+        state: Vec<u8>,
+        player: String,
+        action: Vec<u8>,
+    ) -> Result<Vec<u8>, FfiError>;
+    /// The IDs of the winners once the game is decided, empty for a draw. An action whose
+    /// state names a winner that is not a peer ID is rejected.
+    fn winners(&self, state: Vec<u8>) -> Option<Vec<String>>;
+}
+
+/// A game whose rules live on the other side of the FFI boundary.
+#[derive(Clone)]
+pub struct ForeignGame {
+    logic: Arc<dyn ForeignGameLogic>,
+    name: String,
+}
+
+impl ForeignGame {
+    fn new(logic: Arc<dyn ForeignGameLogic>) -> Self {
+        let name = logic.game_name();
+        Self { logic, name }
+    }
+}
+
+impl ForeignGame {
+    /// The outcome the foreign logic reports for `state`, failing on a winner that is not a
+    /// peer ID.
+    fn outcome(&self, state: &[u8]) -> Result<Option<GameOutcome>, FfiError> {
+        let Some(winners) = self.logic.winners(state.to_vec()) else {
+            return Ok(None);
+        };
+        winners
+            .iter()
+            .try_fold(GameOutcome::new(FinishReason::Completed), |outcome, id| {
+                Ok(outcome.winner(parse_id(id)?))
+            })
+            .map(Some)
+    }
+}
+
+impl Debug for ForeignGame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ForeignGame({})", self.name)
+    }
+}
+
+fn ffi_peers(players: &PeerMap) -> Vec<FfiPeer> {
+    players.values().map(FfiPeer::from).collect()
+}
+
+fn parse_id(id: &str) -> Result<EndpointId, FfiError> {
+    id.parse().map_err(|e| FfiError::Game {
+        reason: format!("Invalid peer ID '{id}': {e}"),
+    })
+}
+
+impl GameLogic for ForeignGame {
+    /// Only a fallback: every foreign game reports its own [ForeignGameLogic::game_name].
+    const GAME_NAME: &'static str = "ForeignGame";
+    type GameState = Vec<u8>;
+    type GameAction = Vec<u8>;
+    type PlayerRole = String;
+    type PlayerLeaveReason = String;
+    type Phase = u32;
+    type GameMessage = Vec<u8>;
+    type GameError = FfiError;
+
+    fn game_name(&self) -> &str {
+        &self.name
+    }
+
+    fn game_type(&self) -> String {
+        format!("{}::{}", Self::GAME_NAME, self.name)
+    }
+
+    /// A foreign game's state is already the bytes its own logic wrote.
+    fn canonical_state(&self, state: &Vec<u8>) -> Option<Vec<u8>> {
+        Some(state.clone())
+    }
+
+    fn assign_roles(&self, players: &PeerMap) -> Result<HashMap<EndpointId, String>, FfiError> {
+        self.logic
+            .assign_roles(ffi_peers(players))?
+            .into_iter()
+            .map(|(id, role)| Ok((parse_id(&id)?, role)))
+            .collect()
+    }
+
+    fn validate_start(
+        &self,
+        players: &PeerMap,
+        _roles: &HashMap<EndpointId, String>,
+    ) -> Result<(), FfiError> {
+        self.logic.validate_start(ffi_peers(players))
+    }
+
+    fn initial_state(
+        &self,
+        players: &PeerMap,
+        _roles: &HashMap<EndpointId, String>,
+    ) -> Result<Vec<u8>, FfiError> {
+        self.logic.initial_state(ffi_peers(players))
+    }
+
+    fn apply_action(
+        &self,
+        current_state: &mut Vec<u8>,
+        player_id: &EndpointId,
+        action: &Vec<u8>,
+    ) -> Result<(), FfiError> {
+        let next_state = self.logic.apply_action(
+            current_state.clone(),
+            player_id.to_string(),
+            action.clone(),
+        )?;
+        self.outcome(&next_state)?;
+        *current_state = next_state;
+        Ok(())
+    }
+
+    fn game_outcome(&self, current_state: &Vec<u8>) -> Option<GameOutcome> {
+        // The winners of every state an action produced were checked in `apply_action`.
+        self.outcome(current_state).ok().flatten()
+    }
+
+    fn handle_player_disconnect(
+        &self,
+        _players: &mut PeerMap,
+        _player_id: &EndpointId,
+        _current_state: &mut Vec<u8>,
+    ) -> Result<ConnectionEffect, FfiError> {
+        Ok(ConnectionEffect::NoChange)
+    }
+
+    fn handle_player_reconnect(
+        &self,
+        _players: &mut PeerMap,
+        _player_id: &EndpointId,
+        _current_state: &mut Vec<u8>,
+    ) -> Result<ConnectionEffect, FfiError> {
+        Ok(ConnectionEffect::NoChange)
+    }
+
+    fn handle_player_forfeit(
+        &self,
+        _players: &mut PeerMap,
+        _player_id: &EndpointId,
+        _current_state: &mut Vec<u8>,
+    ) -> Result<ConnectionEffect, FfiError> {
+        Ok(ConnectionEffect::NoChange)
+    }
+}
+
+/// Something that happened in the room, see [crate::UiEvent].
+#[derive(uniffi::Enum, Debug, Clone, PartialEq, Eq)]
+pub enum FfiEvent {
+    /// The game moved on to a new state.
+    GameState { state: Vec<u8> },
+    /// The room moved to a new phase, e.g. "Lobby", "InGame" or "Custom(2)".
+    AppState { state: String },
+    /// The host started the game from this state.
+    GameStarted { state: Vec<u8> },
+    /// The host finished the game, won by these peers.
+    GameOver { winners: Vec<String> },
+    /// The host sent one of this endpoint's players a payload only they may see.
+    PrivateState { to: String, payload: Vec<u8> },
+    /// The peer list changed.
+    Peers { peers: Vec<FfiPeer> },
+    /// A chat message arrived.
+    Chat { sender: String, text: String },
+    /// The host accepted one of this endpoint's actions.
+    ActionAccepted { seq: u64 },
+    /// The host rejected one of this endpoint's actions.
+    ActionRejected { seq: u64, error: String },
+    /// The host went away, came back or changed.
+    Host { description: String },
+    /// Any other event, described in words.
+    Other { description: String },
+}
+
+impl From<UiEvent<ForeignGame>> for FfiEvent {
+    fn from(event: UiEvent<ForeignGame>) -> Self {
+        match event {
+            UiEvent::GameState(state) | UiEvent::PredictionCorrected(state) => {
+                FfiEvent::GameState { state }
+            }
+            UiEvent::AppState(state) => FfiEvent::AppState {
+                state: format!("{state:?}"),
+            },
+            UiEvent::GameStarted { state } => FfiEvent::GameStarted { state },
+            UiEvent::GameOver(result) => FfiEvent::GameOver {
+                winners: result.winners.iter().map(ToString::to_string).collect(),
+            },
+            UiEvent::PrivateState { to, payload } => FfiEvent::PrivateState {
+                to: to.to_string(),
+                payload: payload.to_vec(),
+            },
+            UiEvent::Peer(peers) => FfiEvent::Peers {
+                peers: ffi_peers(&peers),
+            },
+            UiEvent::Chat { sender, msg } => FfiEvent::Chat {
+                sender,
+                text: msg.message,
+            },
+            UiEvent::ActionAccepted { seq, .. } => FfiEvent::ActionAccepted { seq },
+            UiEvent::ActionRejected { seq, error, .. } => FfiEvent::ActionRejected {
+                seq,
+                error: error.to_string(),
+            },
+            UiEvent::Host(event) => FfiEvent::Host {
+                description: format!("{event:?}"),
+            },
+            event => FfiEvent::Other {
+                description: event.to_string(),
+            },
+        }
+    }
+}
+
+/// A game room for a [ForeignGameLogic], see [crate::GameRoom].
+#[derive(uniffi::Object)]
+pub struct FfiRoom {
+    /// `None` once the room has been left.
+    room: RwLock<Option<GameRoom<ForeignGame>>>,
+    events: Mutex<mpsc::Receiver<UiEvent<ForeignGame>>>,
+}
+
+impl FfiRoom {
+    fn new((room, events): (GameRoom<ForeignGame>, mpsc::Receiver<UiEvent<ForeignGame>>)) -> Self {
+        Self {
+            room: RwLock::new(Some(room)),
+            events: Mutex::new(events),
+        }
+    }
+
+    async fn room(&self) -> Result<RwLockReadGuard<'_, GameRoom<ForeignGame>>, FfiError> {
+        RwLockReadGuard::try_map(self.room.read().await, Option::as_ref).map_err(|_| {
+            FfiError::Room {
+                reason: "The room has been left".to_string(),
+            }
+        })
+    }
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl FfiRoom {
+    /// Create a room and host it, storing it under `data_dir` if given.
+    #[uniffi::constructor]
+    pub async fn create(
+        logic: Arc<dyn ForeignGameLogic>,
+        name: Option<String>,
+        data_dir: Option<String>,
+    ) -> Result<Arc<Self>, FfiError> {
+        let room = GameRoom::create(
+            ForeignGame::new(logic),
+            data_dir.map(PathBuf::from),
+            name.as_deref(),
+        )
+        .await?;
+        Ok(Arc::new(Self::new(room)))
+    }
+
+    /// Join a room with a ticket from one of its peers, storing it under `data_dir` if given.
+    #[uniffi::constructor]
+    pub async fn join(
+        logic: Arc<dyn ForeignGameLogic>,
+        ticket: String,
+        data_dir: Option<String>,
+    ) -> Result<Arc<Self>, FfiError> {
+        let room = GameRoom::join(
+            ForeignGame::new(logic),
+            &ticket,
+            data_dir.map(PathBuf::from),
+        )
+        .await?;
+        Ok(Arc::new(Self::new(room)))
+    }
+
+    /// This endpoint's peer ID.
+    pub async fn id(&self) -> Result<String, FfiError> {
+        Ok(self.room().await?.id().to_string())
+    }
+
+    /// A fresh ticket others can join the room with.
+    pub async fn ticket(&self) -> Result<String, FfiError> {
+        Ok(self.room().await?.ticket().await?.to_string())
+    }
+
+    /// Enter the room under `nickname`.
+    pub async fn announce_presence(&self, nickname: String) -> Result<(), FfiError> {
+        Ok(self
+            .room()
+            .await?
+            .announce_presence(nickname.as_str())
+            .await?)
+    }
+
+    /// Say whether this peer is ready to start.
+    pub async fn set_ready(&self, ready: bool) -> Result<(), FfiError> {
+        Ok(self.room().await?.set_ready(ready).await?)
+    }
+
+    /// Start the game, if this peer is the host.
+    pub async fn start_game(&self) -> Result<(), FfiError> {
+        Ok(self.room().await?.start_game().await?)
+    }
+
+    /// Move the running game into one of its own phases, numbered by the game.
+    pub async fn set_phase(&self, phase: u32) -> Result<(), FfiError> {
+        Ok(self.room().await?.set_phase(phase).await?)
+    }
+
+    /// Send one player a payload only they may see, if this peer is the host.
+    pub async fn send_private_state(&self, to: String, payload: Vec<u8>) -> Result<(), FfiError> {
+        let to = parse_id(&to)?;
+        Ok(self.room().await?.send_private_state(&to, payload).await?)
+    }
+
+    /// Publish an asset for every peer to fetch, if this peer is the host.
+    pub async fn publish_asset(&self, name: String, bytes: Vec<u8>) -> Result<(), FfiError> {
+        self.room().await?.publish_asset(&name, bytes).await?;
+        Ok(())
+    }
+
+    /// Get an asset's bytes, downloading it from the host the first time.
+    pub async fn fetch_asset(&self, name: String) -> Result<Vec<u8>, FfiError> {
+        Ok(self.room().await?.fetch_asset(&name).await?.to_vec())
+    }
+
+    /// Send an action to the host, returning its sequence number.
+    pub async fn submit_action(&self, action: Vec<u8>) -> Result<u64, FfiError> {
+        Ok(self.room().await?.submit_action(action).await?)
+    }
+
+    /// Send a chat message to the room.
+    pub async fn send_chat(&self, message: String) -> Result<(), FfiError> {
+        Ok(self.room().await?.send_chat(&message).await?)
+    }
+
+    /// Everyone in the room.
+    pub async fn peers(&self) -> Result<Vec<FfiPeer>, FfiError> {
+        Ok(ffi_peers(&self.room().await?.get_peer_list().await?))
+    }
+
+    /// The latest game state.
+    pub async fn game_state(&self) -> Result<Vec<u8>, FfiError> {
+        Ok(self.room().await?.get_game_state().await?)
+    }
+
+    /// Wait for the next event, or `None` once the room has stopped.
+    pub async fn next_event(&self) -> Option<FfiEvent> {
+        self.events.lock().await.recv().await.map(FfiEvent::from)
+    }
+
+    /// Leave the room and shut down its node, returning whether another peer synced the
+    /// announcement.
+    pub async fn leave(&self) -> Result<bool, FfiError> {
+        let Some(room) = self.room.write().await.take() else {
+            return Ok(false);
+        };
+        Ok(room.leave(&LeaveReason::ApplicationClosed, true).await?)
+    }
+}
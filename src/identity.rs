@@ -0,0 +1,141 @@
+//! Player Identity
+//!
+//! This module contains the `Identity` struct, which ties a persistent node key to the player's
+//! default profile and lifetime stats. An identity lives in a data directory, so reusing that
+//! directory for every room keeps the same EndpointId, document author, name and avatar.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Result;
+use iroh::{EndpointId, SecretKey};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    FileKeyStore, IrohConfig, KeyStore, MatchRecord, PeerProfile,
+    networking::{load_match_history, load_or_create_secret_key},
+};
+
+/// File name of the saved profile and stats within the identity's data directory.
+const IDENTITY_FILE: &str = "identity.json";
+
+/// Running totals for the games played under an identity.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct PlayerStats {
+    /// Rooms created with this identity.
+    pub games_hosted: u32,
+    /// Rooms joined with this identity.
+    pub games_joined: u32,
+}
+
+/// The saved part of an identity.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct IdentityFile {
+    profile: PeerProfile,
+    stats: PlayerStats,
+}
+
+/// A player's persistent identity: node key, default profile and stats.
+#[derive(Debug, Clone)]
+pub struct Identity {
+    data_dir: PathBuf,
+    endpoint_id: EndpointId,
+    key_store: Option<Arc<dyn KeyStore>>,
+    /// Profile announced when entering a room with this identity.
+    pub profile: PeerProfile,
+    /// Lifetime stats, updated as rooms are created and joined.
+    pub stats: PlayerStats,
+}
+
+impl Identity {
+    /// Load the identity stored in `data_dir`, creating one with `nickname` if there is none yet.
+    pub async fn load_or_create(data_dir: impl Into<PathBuf>, nickname: &str) -> Result<Self> {
+        Self::load_or_create_with(data_dir, nickname, None).await
+    }
+
+    /// Load or create an identity whose node key and author live in `key_store` rather than
+    /// in plain files in `data_dir`, see [IrohConfig::key_store].
+    ///
+    /// Rooms opened with the identity use the same key store.
+    pub async fn load_or_create_with_key_store(
+        data_dir: impl Into<PathBuf>,
+        nickname: &str,
+        key_store: Arc<dyn KeyStore>,
+    ) -> Result<Self> {
+        Self::load_or_create_with(data_dir, nickname, Some(key_store)).await
+    }
+
+    async fn load_or_create_with(
+        data_dir: impl Into<PathBuf>,
+        nickname: &str,
+        key_store: Option<Arc<dyn KeyStore>>,
+    ) -> Result<Self> {
+        let data_dir = data_dir.into();
+        tokio::fs::create_dir_all(&data_dir).await?;
+        let key = match &key_store {
+            Some(key_store) => load_or_create_secret_key(key_store.as_ref())?,
+            None => load_or_create_secret_key(&FileKeyStore::new(&data_dir))?,
+        };
+        let saved = match tokio::fs::read(data_dir.join(IDENTITY_FILE)).await {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => IdentityFile {
+                profile: nickname.into(),
+                stats: PlayerStats::default(),
+            },
+            Err(e) => return Err(e.into()),
+        };
+        let identity = Self {
+            data_dir,
+            endpoint_id: key.public(),
+            key_store,
+            profile: saved.profile,
+            stats: saved.stats,
+        };
+        identity.save().await?;
+        Ok(identity)
+    }
+
+    /// Write the profile and stats back to the data directory.
+    pub async fn save(&self) -> Result<()> {
+        let saved = IdentityFile {
+            profile: self.profile.clone(),
+            stats: self.stats.clone(),
+        };
+        let bytes = serde_json::to_vec_pretty(&saved)?;
+        tokio::fs::write(self.data_dir.join(IDENTITY_FILE), bytes).await?;
+        Ok(())
+    }
+
+    /// The EndpointId every room opened with this identity will use.
+    pub fn id(&self) -> EndpointId {
+        self.endpoint_id
+    }
+
+    /// The data directory holding this identity's key, profile and node store.
+    pub fn data_dir(&self) -> &Path {
+        &self.data_dir
+    }
+
+    /// The games played under this identity, most recent first, see [crate::MatchRecord].
+    pub async fn match_history(&self) -> Result<Vec<MatchRecord>> {
+        load_match_history(&self.data_dir).await
+    }
+
+    /// The identity's node key, e.g. to sign what it says outside of a room.
+    pub(crate) fn secret_key(&self) -> Result<SecretKey> {
+        match &self.key_store {
+            Some(key_store) => load_or_create_secret_key(key_store.as_ref()),
+            None => load_or_create_secret_key(&FileKeyStore::new(&self.data_dir)),
+        }
+    }
+
+    /// Use this identity's key store for a room's node, unless `network` names one already.
+    pub(crate) fn network(&self, mut network: IrohConfig) -> IrohConfig {
+        if network.key_store.is_none() {
+            network.key_store = self.key_store.clone();
+        }
+        network
+    }
+}
@@ -1,17 +1,51 @@
 #![doc = include_str!("../README.md")]
 
+#[cfg(feature = "ffi")]
+uniffi::setup_scaffolding!();
+
+#[cfg(feature = "bridge")]
+pub mod bridge;
+mod engine;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod identity;
 mod logic;
 mod networking;
 mod peer;
+#[cfg(feature = "ratings")]
+pub mod ratings;
 mod room;
+#[cfg(feature = "runner")]
+pub mod runner;
+#[cfg(feature = "sync")]
+pub mod sync;
+mod trace;
+mod turns;
 
-pub use logic::{ConnectionEffect, GameLogic};
+pub use engine::Engine;
+pub use identity::{Identity, PlayerStats};
+pub use logic::{ChatFilter, ConnectionEffect, GameLogic};
 use networking::Iroh;
-pub use peer::{PeerInfo, PeerMap, PeerProfile, PeerStatus};
+pub use networking::{
+    ConnectionPath, CustomStorage, FileKeyStore, IrohConfig, KeyStore, MatchRecord, OpenStorage,
+    PeerConnectionInfo, PeerNetworkStats, PeerStream, StorageBackend, StoredRoom,
+};
+pub use peer::{PeerInfo, PeerMap, PeerProfile, PeerStatus, TeamId};
+#[cfg(feature = "testing")]
+pub use room::testing;
 pub use room::{
-    ActionResult, AppState, ChatMessage, GameRoom, GameTicket, HostEvent, LeaveReason,
-    RoomSnapshot, UiError, UiEvent,
+    ADVERTISE_INTERVAL, ActionError, Activity, Annotation, AppState, AppliedAction, Asset,
+    ChatMessage, ChatReaction, ChatUpdate, Coalesce, CompactionPolicy, Compression,
+    DedicatedPolicy, DocStats, ENGINE_KEY_PREFIXES, EventCoalescing, EventFilter, FinishReason,
+    Friend, Friends, GameOutcome, GameResult, GameRoom, GameRoomBuilder, HeartbeatPolicy,
+    HostEvent, InactivityPolicy, JoinDeniedReason, LeaveReason, LobbyAdvert, LobbyRegistry,
+    MAX_ANNOTATION_LEN, MAX_AVATAR_BYTES, MAX_DECOMPRESSED_LEN, Match, MatchPlayerStats,
+    MatchStats, PRESENCE_INTERVAL, Presence, Proposal, ProposalOutcome, ProposalRequest, RateLimit,
+    RateLimits, RoomConfig, RoomListing, RoomMeta, RoomSecret, RoomSnapshot, RoomTicket,
+    RoomVisibility, RoundPolicy, StateCadence, TicketError, UiError, UiEvent, VoteCall, VoteKind,
+    VoteResult, WireFormat, is_engine_key,
 };
+pub use turns::TurnManager;
 
 #[cfg(feature = "iroh")]
 pub mod iroh {
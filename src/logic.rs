@@ -1,19 +1,21 @@
 //! Game Logic
 //!
 //! This module contains the `GameLogic` trait, which defines the core logic of a turn-based game,
-//! including how to apply actions, assign roles, and handle player disconnects and reconnects.
+//! including how to apply actions, assign roles and teams, and handle player disconnects and reconnects.
 //! It also defines the `ConnectionEffect` enum, which indicates how the game state should be updated
-//! in response to player connections and disconnections.
+//! in response to player connections and disconnections, and the `ChatFilter` enum used to mute or
+//! rewrite chat messages.
 
 use iroh::EndpointId;
 use serde::{Serialize, de::DeserializeOwned};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     error::Error,
     fmt::{Debug, Display},
+    time::Duration,
 };
 
-use crate::{PeerInfo, PeerMap};
+use crate::{AppState, ChatMessage, GameOutcome, PeerInfo, PeerMap, TeamId, TurnManager};
 
 /// The effect of a player connection or disconnection on the game state,
 /// indicating whether the state or peer list has changed.
@@ -29,10 +31,24 @@ pub enum ConnectionEffect {
     StateAndPeersChanged,
 }
 
+/// What the engine should do with a chat message, as decided by [GameLogic::filter_chat].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChatFilter {
+    /// Deliver the message unchanged.
+    Allow,
+    /// Drop the message so it is never shown.
+    Block,
+    /// Deliver the message with its text replaced.
+    Replace(String),
+}
+
 /// Generic Trait for p2p turn based games.
 pub trait GameLogic: Debug + Send + Sync + 'static {
     /// The name of the game, used for display and routing purposes.
     const GAME_NAME: &'static str;
+    /// The version of the layout of [GameLogic::GameState]. Increase it whenever a new build
+    /// changes how the state serializes, and read older states in [GameLogic::migrate_state].
+    const STATE_VERSION: u32 = 0;
     /// Current State of the game
     type GameState: Serialize + DeserializeOwned + Clone + Debug + Send + Sync;
     /// Actions that can be taken in the game
@@ -41,9 +57,42 @@ pub trait GameLogic: Debug + Send + Sync + 'static {
     type PlayerRole: Serialize + DeserializeOwned + Clone + Debug + Send + Sync;
     /// Game specific reasons for a player to leave the game
     /// Common non-specific reasons are also available via [LeaveReason]
-    type PlayerLeaveReason: Serialize + DeserializeOwned + Clone + Debug + Send + Sync;
+    ///
+    /// Reasons are reported to the other peers in [crate::UiEvent::PeerLeft], so they can be compared.
+    type PlayerLeaveReason: Serialize
+        + DeserializeOwned
+        + Clone
+        + Debug
+        + PartialEq
+        + Eq
+        + Send
+        + Sync;
+    /// Phases of the game's own, such as setup, drafting or scoring, that the host can move the
+    /// room into with [crate::GameRoom::set_phase]; peers see them as [crate::AppState::Custom].
+    /// Games without phases of their own can use `()`.
+    type Phase: Serialize + DeserializeOwned + Clone + Copy + Debug + PartialEq + Eq + Send + Sync;
+    /// Messages the game broadcasts that do not change its state, such as emotes, hints or
+    /// "thinking…" notices, see [crate::GameRoom::broadcast_game_message]. Games without them
+    /// can use `()`.
+    type GameMessage: Serialize + DeserializeOwned + Clone + Debug + Send + Sync;
     /// Errors specific to this game
-    type GameError: Error + Send + Sync;
+    ///
+    /// Rejections are sent back to the peer that submitted the action, so errors must be serializable.
+    type GameError: Error + Serialize + DeserializeOwned + Clone + Send + Sync;
+
+    /// The name of the game, for logic that only learns it at runtime, e.g. rules written in
+    /// another language. Defaults to [GameLogic::GAME_NAME].
+    fn game_name(&self) -> &str {
+        Self::GAME_NAME
+    }
+
+    /// What tells this game's rooms apart from other games' rooms, checked when peers join.
+    ///
+    /// Defaults to the Rust type name of the logic. Logic that stands in for many games
+    /// should tell them apart, e.g. by their [GameLogic::game_name].
+    fn game_type(&self) -> String {
+        std::any::type_name::<Self>().to_string()
+    }
 
     /// Returns true when a role should be treated as a non-acting observer.
     fn is_observer_role(&self, _role: &Self::PlayerRole) -> bool {
@@ -59,12 +108,47 @@ pub trait GameLogic: Debug + Send + Sync + 'static {
         peer.status.is_online()
     }
 
+    /// Decide whether a chat message may be shown, given its sender and the current phase.
+    ///
+    /// Every peer, the host included, applies this to outgoing chat, incoming
+    /// chat and chat history, so games can mute eliminated players or night-phase
+    /// talk. The default allows every message.
+    fn filter_chat(
+        &self,
+        _sender: &PeerInfo,
+        _phase: &AppState<Self>,
+        _message: &ChatMessage,
+    ) -> ChatFilter {
+        ChatFilter::Allow
+    }
+
     /// Assigns roles to players at the start of the game.
+    ///
+    /// Peers the host has demoted with [crate::GameRoom::demote_to_observer] have
+    /// `is_observer` set and stay observers whatever role they are given. The host also
+    /// runs this in the lobby whenever it changes who plays, refusing the change if it fails.
     fn assign_roles(
         &self,
         players: &PeerMap,
     ) -> Result<HashMap<EndpointId, Self::PlayerRole>, Self::GameError>;
 
+    /// Assigns players to teams at the start of the game, after roles have been assigned.
+    ///
+    /// Players left out of the returned map have no team. The default keeps any teams the
+    /// host picked in the lobby with [crate::GameRoom::set_team]. The assigned teams are
+    /// visible in the peer list passed to [GameLogic::validate_start] and
+    /// [GameLogic::initial_state].
+    fn assign_teams(
+        &self,
+        players: &PeerMap,
+        _roles: &HashMap<EndpointId, Self::PlayerRole>,
+    ) -> Result<HashMap<EndpointId, TeamId>, Self::GameError> {
+        Ok(players
+            .iter()
+            .filter_map(|(id, peer)| peer.team.map(|team| (*id, team)))
+            .collect())
+    }
+
     /// Check that all game specific conditions are met for starting this game.
     fn validate_start(
         &self,
@@ -87,6 +171,79 @@ pub trait GameLogic: Debug + Send + Sync + 'static {
         action: &Self::GameAction,
     ) -> Result<(), Self::GameError>;
 
+    /// Apply one round of simultaneous actions at once, see [crate::RoomConfig::rounds].
+    ///
+    /// `actions` holds the action of every player who submitted one before the round closed,
+    /// ordered by player ID, so every host resolves the round the same way. Rejecting the
+    /// round rejects every action in it and leaves the state as it was. The default applies
+    /// the actions one after another with [GameLogic::apply_action].
+    fn apply_round(
+        &self,
+        current_state: &mut Self::GameState,
+        _round: u64,
+        actions: &BTreeMap<EndpointId, Self::GameAction>,
+    ) -> Result<(), Self::GameError> {
+        for (player_id, action) in actions {
+            self.apply_action(current_state, player_id, action)?;
+        }
+        Ok(())
+    }
+
+    /// Report the outcome once the game has been decided, e.g. by a win or a draw.
+    ///
+    /// The host checks this after every applied action. Returning `Some` finishes the
+    /// game and records the outcome as the room's [crate::GameResult]. The default never
+    /// finishes the game.
+    fn game_outcome(&self, _current_state: &Self::GameState) -> Option<GameOutcome> {
+        None
+    }
+
+    /// Advance time-dependent state, e.g. auction timers or resource regeneration.
+    ///
+    /// While a game is in progress, the host calls this at the interval set with
+    /// [crate::RoomConfig::tick], passing the time since the previous call. Return the new
+    /// state to publish it, or `None` to leave the state as it is. The host checks
+    /// [GameLogic::game_outcome] after every published tick, so a timer can end the game.
+    /// The default never changes the state.
+    fn tick(
+        &self,
+        _current_state: &Self::GameState,
+        _elapsed: Duration,
+    ) -> Option<Self::GameState> {
+        None
+    }
+
+    /// Report the game's turn order, for games that keep a [TurnManager] in their state.
+    ///
+    /// The host checks this whenever the game state changes and publishes any change, so
+    /// peers receive a [crate::UiEvent::TurnChanged] and can query the turn order with
+    /// [crate::GameRoom::turns]. The engine does not enforce it: reject out-of-turn actions
+    /// in [GameLogic::apply_action]. The default has no turn order.
+    fn turns(&self, _current_state: &Self::GameState) -> Option<TurnManager> {
+        None
+    }
+
+    /// Encode a game state canonically, for [crate::RoomConfig::lockstep] play.
+    ///
+    /// Lock-step players check that they reached the same state by comparing digests of these
+    /// bytes, so equal states must encode the same on every peer: keep collections in a fixed
+    /// order, e.g. a `BTreeMap` or a sorted `Vec` rather than a `HashMap`, whose order differs
+    /// between peers. A lock-step game won't start without it, and the default has none.
+    fn canonical_state(&self, _state: &Self::GameState) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Read a game state written by a build with an older [GameLogic::STATE_VERSION], e.g.
+    /// when a host running a new build resumes a persistent room.
+    ///
+    /// `bytes` hold the old state serialized in the room's [crate::WireFormat], see
+    /// [crate::WireFormat::decode]. Return `None` if that version can no longer be read, so
+    /// the state is reported as an error rather than misread. States written by a newer
+    /// version are always refused. The default reads no older versions.
+    fn migrate_state(_version: u32, _bytes: &[u8]) -> Option<Self::GameState> {
+        None
+    }
+
     /// Deal with a player disconnecting from the game.
     fn handle_player_disconnect(
         &self,
@@ -111,6 +268,21 @@ pub trait GameLogic: Debug + Send + Sync + 'static {
         current_state: &mut Self::GameState,
     ) -> Result<ConnectionEffect, Self::GameError>;
 
+    /// Deal with a game that has seen no actions for longer than the room's inactivity policy allows.
+    ///
+    /// The host calls this just before finishing the game, so games can record
+    /// the outcome (e.g. a draw or abandonment) in the final state. If
+    /// [GameLogic::game_outcome] then reports an outcome it becomes the game's
+    /// result; otherwise the result has no winners. The default leaves the state
+    /// unchanged.
+    fn handle_inactivity_timeout(
+        &self,
+        _players: &PeerMap,
+        _current_state: &mut Self::GameState,
+    ) -> Result<(), Self::GameError> {
+        Ok(())
+    }
+
     /// Get a preview of the game state for a specific player, if supported by this game.
     fn get_preview<P: Display>(&self) -> Option<P> {
         None
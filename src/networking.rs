@@ -2,29 +2,63 @@
 //!
 //! This module contains the `Iroh` struct, which is the main interface for interacting with the Iroh network,
 //! including creating a node, connecting to other nodes, and accessing the Blobs and Docs protocols.
-//! It also contains a helper function for loading or generating a secret key for the node.
+//! Persistent nodes load their secret key and default author from a [KeyStore].
 //! The `Iroh` struct provides a high-level API for interacting with the Iroh network, abstracting away the
 //! details of the underlying protocols and allowing the rest of the application to focus on game logic and
 //! state management.
 
+mod config;
+mod frames;
+mod history;
+mod key_store;
+mod rooms;
+mod stats;
+mod storage;
+mod streams;
+
+pub use config::IrohConfig;
+pub(crate) use frames::StateFrame;
+use frames::{FRAMES_ALPN, FrameProtocol};
+pub use history::MatchRecord;
+pub(crate) use history::load_match_history;
+pub(crate) use key_store::load_or_create_secret_key;
+use key_store::restore_default_author;
+pub use key_store::{FileKeyStore, KeyStore};
+pub use rooms::StoredRoom;
+pub(crate) use rooms::room_tag_prefix;
+use stats::ConnectionTracker;
+pub use stats::{ConnectionPath, PeerConnectionInfo, PeerNetworkStats};
+pub use storage::{CustomStorage, OpenStorage, StorageBackend};
+pub use streams::PeerStream;
+use streams::{StreamProtocol, stream_alpn};
+
 use std::{
-    net::{Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6},
-    path::PathBuf,
+    collections::{BTreeSet, HashMap},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
 };
 
 use anyhow::Result;
 use bytes::Bytes;
-use iroh::SecretKey;
+use iroh::address_lookup::memory::MemoryLookup;
 use iroh::endpoint::presets;
 use iroh::protocol::Router;
+use iroh::{EndpointAddr, EndpointId, RelayMode, SecretKey, TransportAddr, Watcher as _};
 use iroh_blobs::{
-    ALPN as BLOBS_ALPN, BlobsProtocol,
+    ALPN as BLOBS_ALPN, BlobsProtocol, Hash,
     api::{Store, blobs::Blobs},
-    store::{fs::FsStore, mem::MemStore},
 };
-use iroh_docs::{ALPN as DOCS_ALPN, protocol::Docs};
-use iroh_gossip::{ALPN as GOSSIP_ALPN, net::Gossip};
-use serde::de::DeserializeOwned;
+use iroh_docs::{ALPN as DOCS_ALPN, AuthorId, NamespaceId, protocol::Docs};
+use iroh_gossip::{ALPN as GOSSIP_ALPN, TopicId, api::GossipTopic, net::Gossip};
+use n0_future::{Stream, StreamExt as _};
+use tokio::sync::mpsc;
+
+/// ALPN of the gossip instance that carries real-time messages. It is kept apart from the
+/// one the documents sync over, so joining or leaving a real-time topic never disturbs the
+/// neighbour events the rooms rely on to see peers come and go.
+const REALTIME_ALPN: &[u8] = b"/p2p-game-engine/realtime/0";
 
 /// The main interface for interacting with the Iroh network, including creating a node,
 /// connecting to other nodes, and accessing the Blobs and Docs protocols.
@@ -34,8 +68,18 @@ use serde::de::DeserializeOwned;
 #[derive(Clone, Debug)]
 pub struct Iroh {
     router: Router,
+    store: Store,
     blobs: Blobs,
     docs: Docs,
+    realtime: Gossip,
+    /// Ticked states sent straight to one peer, see [crate::StateCadence].
+    frames: FrameProtocol,
+    known_addrs: MemoryLookup,
+    connections: ConnectionTracker,
+    /// The game's own stream protocols, by suffix.
+    streams: HashMap<String, StreamProtocol>,
+    config: IrohConfig,
+    data_dir: Option<PathBuf>,
 }
 
 impl Iroh {
@@ -45,61 +89,160 @@ impl Iroh {
         store: Store,
         docs: Docs,
         gossip: Gossip,
+        connections: ConnectionTracker,
+        config: IrohConfig,
+        data_dir: Option<PathBuf>,
     ) -> Result<Self> {
         // Get the generic client interface
         let blobs = store.blobs().clone();
-        let router = iroh::protocol::Router::builder(endpoint)
+        let known_addrs = MemoryLookup::new();
+        endpoint.address_lookup()?.add(known_addrs.clone());
+        let realtime = Gossip::builder()
+            .alpn(REALTIME_ALPN)
+            .spawn(endpoint.clone());
+        let frames = FrameProtocol::default();
+        let mut router = iroh::protocol::Router::builder(endpoint)
             .accept(BLOBS_ALPN, BlobsProtocol::new(&store, None))
             .accept(GOSSIP_ALPN, gossip)
-            .accept(DOCS_ALPN, docs.clone())
-            .spawn();
+            .accept(REALTIME_ALPN, realtime.clone())
+            .accept(FRAMES_ALPN, frames.clone())
+            .accept(DOCS_ALPN, docs.clone());
+        let mut streams = HashMap::new();
+        for protocol in &config.stream_protocols {
+            let handler = StreamProtocol::new(protocol);
+            router = router.accept(stream_alpn(protocol), handler.clone());
+            streams.insert(protocol.clone(), handler);
+        }
+        let router = router.spawn();
         Ok(Self {
             router,
+            store,
             docs,
             blobs,
+            realtime,
+            frames,
+            known_addrs,
+            connections,
+            streams,
+            config,
+            data_dir,
         })
     }
 
     /// Create an In-Memory Iroh Node (Strictly for Tests)
-    pub async fn memory() -> Result<Self> {
-        let key = load_secret_key(None).await?; // Generate random key
-
-        // Bind to Random Port (0) to prevent test collisions
-        let endpoint = iroh::Endpoint::builder(presets::N0)
-            .secret_key(key)
-            .bind_addr(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0))?
-            .bind_addr(SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, 0, 0, 0))?
-            .bind()
-            .await?;
+    pub async fn memory(config: &IrohConfig) -> Result<Self> {
+        Self::spawn(StorageBackend::Memory, config).await
+    }
+
+    /// Create an Iroh Node that keeps its blobs and documents in `storage`.
+    ///
+    /// Nodes with a data directory or a [KeyStore] keep their key and default author across
+    /// restarts; in-memory nodes always start with fresh ones.
+    pub async fn spawn(storage: StorageBackend, config: &IrohConfig) -> Result<Self> {
+        let data_dir = storage.data_dir().map(Path::to_path_buf);
+        if let Some(data_dir) = &data_dir {
+            tokio::fs::create_dir_all(data_dir).await?;
+        }
+        let key_store: Option<Arc<dyn KeyStore>> = match (&storage, &data_dir) {
+            (StorageBackend::Memory, _) => None,
+            (_, Some(data_dir)) => Some(
+                config
+                    .key_store
+                    .clone()
+                    .unwrap_or_else(|| Arc::new(FileKeyStore::new(data_dir))),
+            ),
+            (_, None) => config.key_store.clone(),
+        };
+        let key = match &key_store {
+            Some(key_store) => load_or_create_secret_key(key_store.as_ref())?,
+            None => SecretKey::generate(),
+        };
+        let connections = ConnectionTracker::default();
+        let endpoint = bind_endpoint(key, config, &connections).await?;
         let gossip = Gossip::builder().spawn(endpoint.clone());
-        let blobs_store: Store = MemStore::new().into();
-        let docs = Docs::memory()
-            .spawn(endpoint.clone(), blobs_store.clone(), gossip.clone())
-            .await?;
+        let (stores, protect) = storage.open().await?;
+        let engine = iroh_docs::engine::Engine::spawn(
+            endpoint.clone(),
+            gossip.clone(),
+            stores.docs,
+            stores.blobs.clone(),
+            stores.blobs.downloader(&endpoint),
+            storage.default_author_storage(),
+            protect,
+        )
+        .await?;
+        let docs = Docs::new(engine);
+        if let Some(key_store) = &key_store {
+            restore_default_author(&docs, key_store.as_ref()).await?;
+        }
 
-        Self::build(endpoint, blobs_store, docs, gossip).await
+        Self::build(
+            endpoint,
+            stores.blobs,
+            docs,
+            gossip,
+            connections,
+            config.clone(),
+            data_dir,
+        )
+        .await
     }
 
-    /// Create a Persistent Iroh Node (For the actual App)
-    pub async fn persistent(path: PathBuf) -> Result<Self> {
-        // create dir if it doesn't already exist
-        tokio::fs::create_dir_all(&path).await?;
-        let key = load_secret_key(Some(path.clone().join("keypair"))).await?;
+    /// Join a real-time gossip topic, starting from the given peers.
+    pub async fn subscribe_realtime(
+        &self,
+        topic: TopicId,
+        bootstrap: Vec<EndpointId>,
+    ) -> Result<GossipTopic> {
+        Ok(self.realtime.subscribe(topic, bootstrap).await?)
+    }
 
-        // Bind to default port 11204, or fail if taken (standard app behavior)
-        let endpoint = iroh::Endpoint::builder(presets::N0)
-            .secret_key(key)
-            .bind_addr(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0))?
-            .bind_addr(SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, 0, 0, 0))?
-            .bind()
-            .await?;
-        let gossip = Gossip::builder().spawn(endpoint.clone());
-        let blobs_store: Store = FsStore::load(&path).await?.into();
-        let docs = Docs::persistent(path.clone())
-            .spawn(endpoint.clone(), blobs_store.clone(), gossip.clone())
-            .await?;
+    /// Send `peer` one frame of a room's state, for it alone.
+    pub(crate) async fn send_state_frame(
+        &self,
+        peer: EndpointId,
+        room: NamespaceId,
+        state: Bytes,
+    ) -> Result<()> {
+        self.frames.send(self.endpoint(), peer, room, state).await
+    }
+
+    /// Receive the state frames peers send for `room`, in place of any earlier receiver for it.
+    pub(crate) fn accept_state_frames(&self, room: NamespaceId) -> mpsc::Receiver<StateFrame> {
+        self.frames.listen(room)
+    }
+
+    /// Open a bidirectional stream to `peer` on the game's own protocol `suffix`.
+    ///
+    /// The peer must have registered the protocol, see [IrohConfig::stream_protocol].
+    pub async fn open_stream(&self, peer: EndpointId, suffix: &str) -> Result<PeerStream> {
+        let connection = self.endpoint().connect(peer, &stream_alpn(suffix)).await?;
+        let (send, recv) = connection.open_bi().await?;
+        Ok(PeerStream {
+            peer,
+            protocol: suffix.to_string(),
+            send,
+            recv,
+        })
+    }
+
+    /// Receive the streams peers open on the game's own protocol `suffix`, in place of any
+    /// earlier receiver for it.
+    pub fn accept_streams(&self, suffix: &str) -> Result<mpsc::Receiver<PeerStream>> {
+        match self.streams.get(suffix) {
+            Some(protocol) => Ok(protocol.listen()),
+            None => Err(anyhow::anyhow!(
+                "Stream protocol '{suffix}' is not registered on this node"
+            )),
+        }
+    }
 
-        Self::build(endpoint, blobs_store, docs, gossip).await
+    /// Remember how to reach these peers, so they can be dialled by ID alone, e.g. as gossip
+    /// bootstrap peers.
+    pub fn add_known_addrs(&self, addrs: impl IntoIterator<Item = EndpointAddr>) {
+        for addr in addrs {
+            self.known_addrs.add_endpoint_info(addr);
+        }
     }
 
     /// Get the latest state of the requested entry as raw bytes
@@ -107,13 +250,44 @@ impl Iroh {
         Ok(self.blobs().get_bytes(entry.content_hash()).await?)
     }
 
-    /// Get the latest state of the requested entry deserialized
-    pub async fn get_content_as<T: DeserializeOwned>(
+    /// Store bytes under a named tag, releasing whatever the tag kept before.
+    pub async fn set_named_blob(&self, name: &str, bytes: impl Into<Bytes>) -> Result<Hash> {
+        let tagged = self
+            .blobs
+            .add_bytes(bytes.into())
+            .with_named_tag(name)
+            .await?;
+        Ok(tagged.hash)
+    }
+
+    /// Get a blob's bytes, downloading it from `provider` if needed, and keep it under a
+    /// named tag in place of whatever the tag kept before.
+    pub async fn fetch_named_blob(
         &self,
-        entry: &iroh_docs::sync::Entry,
-    ) -> Result<T> {
-        let bytes = self.get_content_bytes(entry).await?;
-        Ok(postcard::from_bytes(&bytes)?)
+        name: &str,
+        hash: Hash,
+        provider: EndpointId,
+    ) -> Result<Bytes> {
+        let bytes = self.fetch_blob(hash, provider).await?;
+        self.store.tags().set(name, hash).await?;
+        Ok(bytes)
+    }
+
+    /// Get a blob's bytes, downloading it from `provider` if it is not stored locally.
+    pub async fn fetch_blob(&self, hash: Hash, provider: EndpointId) -> Result<Bytes> {
+        self.download_blob(hash, vec![provider]).await?;
+        Ok(self.blobs.get_bytes(hash).await?)
+    }
+
+    /// Download a blob from any of `providers`, unless it is already stored locally.
+    pub async fn download_blob(&self, hash: Hash, providers: Vec<EndpointId>) -> Result<()> {
+        if !self.blobs.has(hash).await? {
+            self.store
+                .downloader(self.endpoint())
+                .download(hash, providers)
+                .await?;
+        }
+        Ok(())
     }
 
     /// Get this Node's endpoint
@@ -121,6 +295,98 @@ impl Iroh {
         self.router.endpoint()
     }
 
+    /// The network settings this node was started with.
+    pub fn config(&self) -> &IrohConfig {
+        &self.config
+    }
+
+    /// The directory a persistent node keeps its key and stores in, or `None` in memory.
+    pub fn data_dir(&self) -> Option<&Path> {
+        self.data_dir.as_deref()
+    }
+
+    /// Wait until the endpoint knows at least one of its direct addresses.
+    ///
+    /// Without relays, a ticket that carries no direct address cannot be dialled at all.
+    pub async fn wait_for_direct_addrs(&self, timeout: Duration) -> Result<()> {
+        let mut addr = self.endpoint().watch_addr();
+        tokio::time::timeout(timeout, async {
+            while addr.get().ip_addrs().next().is_none() {
+                addr.updated().await?;
+            }
+            anyhow::Ok(())
+        })
+        .await
+        .map_err(|_| anyhow::anyhow!("No direct address found for this endpoint"))?
+    }
+
+    /// Tell the endpoint the network may have changed, so it rebinds its sockets and reconnects
+    /// to its relay, then wait up to `timeout` for the relay to answer.
+    ///
+    /// Most platforms report network changes to iroh by themselves, but some, like Android,
+    /// only tell the app. Nodes without relays carry on once their sockets are bound again.
+    pub async fn reconnect(&self, timeout: Duration) {
+        self.endpoint().network_change().await;
+        if !matches!(self.config.relay_mode, RelayMode::Disabled) {
+            tokio::time::timeout(timeout, self.endpoint().online())
+                .await
+                .ok();
+        }
+    }
+
+    /// Report each time one of the endpoint's direct addresses goes away, e.g. as the device
+    /// moves from Wi-Fi to cellular, after which connections over it are gone.
+    ///
+    /// Addresses found for the first time are not reported, so a node still discovering its
+    /// addresses is left alone.
+    pub(crate) fn network_changes(&self) -> impl Stream<Item = ()> + Send + 'static {
+        let mut known = BTreeSet::new();
+        self.endpoint()
+            .watch_addr()
+            .stream()
+            .filter_map(move |addr| {
+                let current: BTreeSet<SocketAddr> = addr.ip_addrs().copied().collect();
+                let lost = known.iter().any(|addr| !current.contains(addr));
+                known = current;
+                lost.then_some(())
+            })
+    }
+
+    /// Connection quality to every peer this node currently has a connection to.
+    pub fn network_stats(&self) -> Vec<PeerNetworkStats> {
+        self.connections.stats()
+    }
+
+    /// Report `rtt` as the round trip to `peer`, as if the link to it were that slow.
+    #[cfg(feature = "testing")]
+    pub(crate) fn simulate_rtt(&self, peer: EndpointId, rtt: Duration) {
+        self.connections.simulate_rtt(peer, rtt);
+    }
+
+    /// How this node reaches `peer`: its [PeerNetworkStats], and its known addresses and relay.
+    pub async fn connection_info(&self, peer: EndpointId) -> PeerConnectionInfo {
+        let mut info = PeerConnectionInfo {
+            stats: self.connections.stats_for(peer),
+            relay: None,
+            addrs: Vec::new(),
+        };
+        if let Some(remote) = self.endpoint().remote_info(peer).await {
+            for addr in remote.into_addrs() {
+                match addr.into_addr() {
+                    TransportAddr::Relay(url) => info.relay = Some(url),
+                    TransportAddr::Ip(addr) => info.addrs.push(addr),
+                    _ => {}
+                }
+            }
+        }
+        info
+    }
+
+    /// The path traffic takes to each peer this node currently has a connection to.
+    pub fn connection_paths(&self) -> HashMap<EndpointId, ConnectionPath> {
+        self.connections.paths()
+    }
+
     /// Get the Blobs interface
     pub fn blobs(&self) -> &Blobs {
         &self.blobs
@@ -131,6 +397,19 @@ impl Iroh {
         &self.docs
     }
 
+    /// Get the node's default document author.
+    ///
+    /// On persistent nodes this author is saved in the data directory, so it is
+    /// the same for every room opened from that store.
+    pub async fn get_default_author(&self) -> Result<AuthorId> {
+        self.docs.author_default().await
+    }
+
+    /// Create a new document author that is only used by the caller.
+    pub async fn create_author(&self) -> Result<AuthorId> {
+        self.docs.author_create().await
+    }
+
     /// Shutdown this Endpoint
     pub async fn shutdown(self) -> Result<()> {
         self.router.shutdown().await?;
@@ -138,23 +417,22 @@ impl Iroh {
     }
 }
 
-/// Helper to load key from disk OR generate if path is None
-async fn load_secret_key(key_path: Option<PathBuf>) -> Result<SecretKey> {
-    let Some(key_path) = key_path else {
-        return Ok(SecretKey::generate());
-    };
-    if key_path.exists() {
-        let key_bytes = tokio::fs::read(key_path).await?;
-        return Ok(SecretKey::try_from(&key_bytes[0..32])?);
-    }
-
-    let secret_key = SecretKey::generate();
-    // Try to canonicalize if possible
-    let key_path = key_path.canonicalize().unwrap_or(key_path);
-    let key_path_parent = key_path
-        .parent()
-        .ok_or_else(|| anyhow::anyhow!("no parent directory found for '{}'", key_path.display()))?;
-    tokio::fs::create_dir_all(&key_path_parent).await?;
-    tokio::fs::write(&key_path, &secret_key.to_bytes()).await?;
-    Ok(secret_key)
+/// Bind an endpoint with the requested relay, discovery and socket settings.
+async fn bind_endpoint(
+    key: SecretKey,
+    config: &IrohConfig,
+    connections: &ConnectionTracker,
+) -> Result<iroh::Endpoint> {
+    let mut builder = iroh::Endpoint::builder(presets::N0)
+        .secret_key(key)
+        .hooks(connections.clone())
+        .relay_mode(config.relay_mode.clone())
+        .bind_addr(config.bind_v4)?;
+    if let Some(bind_v6) = config.bind_v6 {
+        builder = builder.bind_addr(bind_v6)?;
+    }
+    if !config.public_discovery {
+        builder = builder.clear_address_lookup();
+    }
+    Ok(builder.bind().await?)
 }
@@ -0,0 +1,133 @@
+//! Network settings for the node
+//!
+//! The defaults match a typical internet game: n0's relay servers and DNS address lookup,
+//! bound to any free port. Self-hosters can point the node at their own relays, and
+//! deployments without internet access can turn relays and public lookup off.
+
+use std::{
+    net::{Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6},
+    sync::Arc,
+};
+
+use iroh::{RelayMode, endpoint::default_relay_mode};
+
+use super::{KeyStore, StorageBackend};
+
+/// How the node's endpoint finds and reaches other peers.
+#[derive(Debug, Clone)]
+pub struct IrohConfig {
+    /// Which relay servers the endpoint may use, e.g. `RelayMode::custom([url])`.
+    pub relay_mode: RelayMode,
+    /// Publish and look up endpoint addresses through n0's public DNS service.
+    pub public_discovery: bool,
+    /// IPv4 address to bind. Port 0 picks any free port.
+    pub bind_v4: SocketAddrV4,
+    /// IPv6 address to bind, if any. Port 0 picks any free port.
+    pub bind_v6: Option<SocketAddrV6>,
+    /// Where a persistent node keeps its secret key and default author, or `None` for plain
+    /// files in its data directory.
+    pub key_store: Option<Arc<dyn KeyStore>>,
+    /// Where the node keeps its blobs and documents, in place of the store path a room is
+    /// created or joined with.
+    pub storage: Option<StorageBackend>,
+    /// Suffixes of the game's own stream protocols the node accepts, see
+    /// [crate::GameRoom::open_stream].
+    pub stream_protocols: Vec<String>,
+}
+
+// Written out by hand, as key stores can only be told apart by identity.
+impl PartialEq for IrohConfig {
+    fn eq(&self, other: &Self) -> bool {
+        self.relay_mode == other.relay_mode
+            && self.public_discovery == other.public_discovery
+            && self.bind_v4 == other.bind_v4
+            && self.bind_v6 == other.bind_v6
+            && match (&self.key_store, &other.key_store) {
+                (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+                (a, b) => a.is_none() && b.is_none(),
+            }
+            && self.storage == other.storage
+            && self.stream_protocols == other.stream_protocols
+    }
+}
+
+impl Eq for IrohConfig {}
+
+impl Default for IrohConfig {
+    fn default() -> Self {
+        Self {
+            relay_mode: default_relay_mode(),
+            public_discovery: true,
+            bind_v4: SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0),
+            bind_v6: Some(SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, 0, 0, 0)),
+            key_store: None,
+            storage: None,
+            stream_protocols: Vec::new(),
+        }
+    }
+}
+
+impl IrohConfig {
+    /// Work without internet access, e.g. at a LAN party.
+    ///
+    /// Relays and public address lookup are turned off, so peers reach each other only through
+    /// the direct addresses carried in room tickets.
+    pub fn lan() -> Self {
+        Self::default()
+            .relay_mode(RelayMode::Disabled)
+            .public_discovery(false)
+    }
+
+    /// Whether peers can only be reached through direct addresses.
+    pub fn is_lan_only(&self) -> bool {
+        self.relay_mode == RelayMode::Disabled && !self.public_discovery
+    }
+
+    /// Set which relay servers the endpoint may use.
+    pub fn relay_mode(mut self, relay_mode: RelayMode) -> Self {
+        self.relay_mode = relay_mode;
+        self
+    }
+
+    /// Set whether to publish and look up addresses through n0's public DNS service.
+    pub fn public_discovery(mut self, enabled: bool) -> Self {
+        self.public_discovery = enabled;
+        self
+    }
+
+    /// Bind to a specific IPv4 address and port.
+    pub fn bind_v4(mut self, addr: SocketAddrV4) -> Self {
+        self.bind_v4 = addr;
+        self
+    }
+
+    /// Bind to a specific IPv6 address and port, or `None` to skip IPv6.
+    pub fn bind_v6(mut self, addr: Option<SocketAddrV6>) -> Self {
+        self.bind_v6 = addr;
+        self
+    }
+
+    /// Keep a persistent node's secret key and default author in `key_store`, e.g. the OS
+    /// keychain, rather than in plain files.
+    pub fn key_store(mut self, key_store: Arc<dyn KeyStore>) -> Self {
+        self.key_store = Some(key_store);
+        self
+    }
+
+    /// Keep the node's blobs and documents in `storage`, e.g. stores the app opens itself,
+    /// whatever store path a room is opened with.
+    pub fn storage(mut self, storage: StorageBackend) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// Accept streams on the game's own protocol `suffix`, e.g. "voice", so peers can open them
+    /// with [crate::GameRoom::open_stream].
+    pub fn stream_protocol(mut self, suffix: impl Into<String>) -> Self {
+        let suffix = suffix.into();
+        if !self.stream_protocols.contains(&suffix) {
+            self.stream_protocols.push(suffix);
+        }
+        self
+    }
+}
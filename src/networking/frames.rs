@@ -0,0 +1,123 @@
+//! Direct state frames
+//!
+//! A ticking host with a [crate::StateCadence] sends each peer the states it is due over a
+//! connection of their own, rather than to the whole room, so a peer on a slow link is never
+//! handed frames it would only throw away. Each frame is one unidirectional stream tagged with
+//! the room it belongs to; the connection itself proves which endpoint sent it.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use anyhow::Result;
+use bytes::Bytes;
+use iroh::{
+    Endpoint, EndpointId,
+    endpoint::Connection,
+    protocol::{AcceptError, ProtocolHandler},
+};
+use iroh_docs::NamespaceId;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+/// ALPN state frames are sent on.
+pub(crate) const FRAMES_ALPN: &[u8] = b"/p2p-game-engine/frames/0";
+
+/// Largest frame accepted. Bigger states are left to the room document.
+const MAX_FRAME_SIZE: usize = 4 * 1024 * 1024;
+
+/// How many frames wait for a room's event loop before new ones are dropped.
+const FRAME_BACKLOG: usize = 16;
+
+/// A frame on the wire: the room it is for, and the state in it.
+#[derive(Serialize, Deserialize, Debug)]
+struct Frame {
+    room: NamespaceId,
+    state: Bytes,
+}
+
+/// A state frame received from a peer.
+#[derive(Debug)]
+pub(crate) struct StateFrame {
+    /// The endpoint that sent it.
+    pub(crate) from: EndpointId,
+    /// The encoded state.
+    pub(crate) state: Bytes,
+}
+
+/// Sends state frames, and hands those received to the event loop of the room they are for.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FrameProtocol {
+    listeners: Arc<Mutex<HashMap<NamespaceId, mpsc::Sender<StateFrame>>>>,
+    /// Connections frames have been sent over, kept for the next frame to the same peer.
+    connections: Arc<Mutex<HashMap<EndpointId, Connection>>>,
+}
+
+impl FrameProtocol {
+    /// Start handing a room's frames to a new listener, in place of any earlier one.
+    pub(crate) fn listen(&self, room: NamespaceId) -> mpsc::Receiver<StateFrame> {
+        let (tx, rx) = mpsc::channel(FRAME_BACKLOG);
+        lock(&self.listeners).insert(room, tx);
+        rx
+    }
+
+    /// Send one frame of `room`'s state to `peer`, over the connection earlier frames used if
+    /// it is still open.
+    pub(crate) async fn send(
+        &self,
+        endpoint: &Endpoint,
+        peer: EndpointId,
+        room: NamespaceId,
+        state: Bytes,
+    ) -> Result<()> {
+        let open = lock(&self.connections)
+            .get(&peer)
+            .filter(|connection| connection.close_reason().is_none())
+            .cloned();
+        let connection = match open {
+            Some(connection) => connection,
+            None => {
+                let connection = endpoint.connect(peer, FRAMES_ALPN).await?;
+                lock(&self.connections).insert(peer, connection.clone());
+                connection
+            }
+        };
+        let mut stream = connection.open_uni().await?;
+        stream
+            .write_all(&postcard::to_stdvec(&Frame { room, state })?)
+            .await?;
+        stream.finish()?;
+        Ok(())
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // Entries are only ever inserted or removed whole, so a poisoned lock is still usable.
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl ProtocolHandler for FrameProtocol {
+    async fn accept(&self, connection: Connection) -> Result<(), AcceptError> {
+        let from = connection.remote_id();
+        while let Ok(mut stream) = connection.accept_uni().await {
+            // Frames are lossy, so one that is cut short, garbled or unwanted is dropped.
+            let Ok(bytes) = stream.read_to_end(MAX_FRAME_SIZE).await else {
+                continue;
+            };
+            let Ok(frame) = postcard::from_bytes::<Frame>(&bytes) else {
+                continue;
+            };
+            let Some(listener) = lock(&self.listeners).get(&frame.room).cloned() else {
+                continue;
+            };
+            let _ = listener.try_send(StateFrame {
+                from,
+                state: frame.state,
+            });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+//! Local match history.
+//!
+//! A persistent node remembers every game it saw finish in `match_history.jsonl` within its
+//! data directory, one [MatchRecord] per line, so apps can list a player's recent games
+//! without a server or the rooms' documents. Nodes in memory keep no history.
+
+use super::Iroh;
+use crate::{GameResult, PeerMap};
+use anyhow::{Context as _, Result};
+use iroh_blobs::Hash;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tokio::io::AsyncWriteExt as _;
+
+/// File name of the match history within a persistent data directory.
+const MATCH_HISTORY_FILE: &str = "match_history.jsonl";
+
+/// A finished game, as remembered in the local match history.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MatchRecord {
+    /// The [crate::GameLogic::game_name] of the game that was played.
+    pub game: String,
+    /// The ID of the room's document.
+    pub room_id: String,
+    /// The name the host gave the room.
+    pub room_name: String,
+    /// When the game started, in milliseconds since the Unix epoch. Together with the room ID
+    /// it tells apart the games played in one room.
+    pub started_at: u64,
+    /// Every peer in the room when the game finished.
+    pub players: PeerMap,
+    /// The game's result, including how long it ran.
+    pub result: GameResult,
+    /// The hash of the action log the result was reached with, to check a review or an
+    /// exported match against.
+    pub action_log_hash: Hash,
+}
+
+impl Iroh {
+    /// The games this node saw finish, most recent first.
+    ///
+    /// Empty for nodes in memory, which keep no history.
+    pub async fn match_history(&self) -> Result<Vec<MatchRecord>> {
+        match self.data_dir() {
+            Some(data_dir) => load_match_history(data_dir).await,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Add a finished game to the match history, unless it is there already or the node
+    /// keeps no history.
+    pub(crate) async fn record_match(&self, record: &MatchRecord) -> Result<()> {
+        let Some(data_dir) = self.data_dir() else {
+            return Ok(());
+        };
+        let recorded = load_match_history(data_dir)
+            .await?
+            .iter()
+            .any(|known| known.room_id == record.room_id && known.started_at == record.started_at);
+        if recorded {
+            return Ok(());
+        }
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(data_dir.join(MATCH_HISTORY_FILE))
+            .await?;
+        file.write_all(&line).await?;
+        Ok(())
+    }
+}
+
+/// Read the match history kept in a persistent data directory, most recent first.
+pub(crate) async fn load_match_history(data_dir: &Path) -> Result<Vec<MatchRecord>> {
+    let path = data_dir.join(MATCH_HISTORY_FILE);
+    let contents = match tokio::fs::read_to_string(&path).await {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut history = contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(serde_json::from_str)
+        .collect::<Result<Vec<MatchRecord>, _>>()
+        .with_context(|| format!("Invalid match history {}", path.display()))?;
+    history.reverse();
+    Ok(history)
+}
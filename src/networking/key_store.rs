@@ -0,0 +1,134 @@
+//! Key storage
+//!
+//! A persistent node keeps its secret key, which fixes its EndpointId, and its default
+//! document author from one run to the next. By default both are written as plain files in the
+//! node's data directory. Apps can implement [KeyStore] to keep them somewhere safer instead,
+//! e.g. the OS keychain on desktop or a secure enclave on mobile, and set it with
+//! [crate::IrohConfig::key_store]. A store that is safe to keep secrets in can also keep room
+//! secrets, so encrypted rooms can be resumed without asking for theirs again.
+
+use std::{fmt, path::PathBuf};
+
+use anyhow::{Result, anyhow};
+use iroh::SecretKey;
+use iroh_docs::{Author, NamespaceId, protocol::Docs};
+
+use crate::RoomSecret;
+
+/// File name of the node's secret key within a persistent data directory.
+const KEYPAIR_FILE: &str = "keypair";
+/// File name of the node's default document author within a persistent data directory.
+const AUTHOR_FILE: &str = "author";
+
+/// Where a persistent node loads and saves its secret key and default document author.
+///
+/// Nothing is saved until the node first needs it, so each `load_` returns `None` for a new
+/// node, which then generates the key or author and saves it.
+pub trait KeyStore: fmt::Debug + Send + Sync {
+    /// Load the node's secret key, if one has been saved.
+    fn load_secret_key(&self) -> Result<Option<SecretKey>>;
+    /// Save the node's secret key.
+    fn save_secret_key(&self, key: &SecretKey) -> Result<()>;
+    /// Load the node's default document author, if one has been saved.
+    fn load_author(&self) -> Result<Option<Author>>;
+    /// Save the node's default document author.
+    fn save_author(&self, author: &Author) -> Result<()>;
+    /// Load the secret of the room whose document is `room`, if one has been saved.
+    ///
+    /// Room secrets are not kept unless a store overrides this and [KeyStore::save_room_secret],
+    /// so by default resuming an encrypted room needs its secret again.
+    fn load_room_secret(&self, room: &NamespaceId) -> Result<Option<RoomSecret>> {
+        let _ = room;
+        Ok(None)
+    }
+    /// Save the secret of the room whose document is `room`, so it can be resumed without it.
+    fn save_room_secret(&self, room: &NamespaceId, secret: &RoomSecret) -> Result<()> {
+        let _ = (room, secret);
+        Ok(())
+    }
+}
+
+/// The default [KeyStore], keeping the raw key and author as files in a data directory.
+///
+/// It does not keep room secrets, which would otherwise sit next to the rooms they unlock.
+#[derive(Debug, Clone)]
+pub struct FileKeyStore {
+    dir: PathBuf,
+}
+
+impl FileKeyStore {
+    /// Keep the key and author in `dir`.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn read(&self, name: &str) -> Result<Option<[u8; 32]>> {
+        let path = self.dir.join(name);
+        let bytes = match std::fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let bytes = bytes
+            .get(..32)
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| anyhow!("'{}' is too short to hold a key", path.display()))?;
+        Ok(Some(bytes))
+    }
+
+    fn write(&self, name: &str, bytes: &[u8]) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        std::fs::write(self.dir.join(name), bytes)?;
+        Ok(())
+    }
+}
+
+impl KeyStore for FileKeyStore {
+    fn load_secret_key(&self) -> Result<Option<SecretKey>> {
+        Ok(self
+            .read(KEYPAIR_FILE)?
+            .map(|bytes| SecretKey::from_bytes(&bytes)))
+    }
+
+    fn save_secret_key(&self, key: &SecretKey) -> Result<()> {
+        self.write(KEYPAIR_FILE, &key.to_bytes())
+    }
+
+    fn load_author(&self) -> Result<Option<Author>> {
+        Ok(self
+            .read(AUTHOR_FILE)?
+            .map(|bytes| Author::from_bytes(&bytes)))
+    }
+
+    fn save_author(&self, author: &Author) -> Result<()> {
+        self.write(AUTHOR_FILE, &author.to_bytes())
+    }
+}
+
+/// Load the node's secret key from `key_store`, generating and saving one if there is none.
+pub(crate) fn load_or_create_secret_key(key_store: &dyn KeyStore) -> Result<SecretKey> {
+    if let Some(key) = key_store.load_secret_key()? {
+        return Ok(key);
+    }
+    let key = SecretKey::generate();
+    key_store.save_secret_key(&key)?;
+    Ok(key)
+}
+
+/// Make the author in `key_store` the node's default, or save the default if there is none.
+pub(crate) async fn restore_default_author(docs: &Docs, key_store: &dyn KeyStore) -> Result<()> {
+    match key_store.load_author()? {
+        Some(author) => {
+            let id = author.id();
+            docs.author_import(author).await?;
+            docs.author_set_default(id).await?;
+        }
+        None => {
+            let id = docs.author_default().await?;
+            if let Some(author) = docs.author_export(id).await? {
+                key_store.save_author(&author)?;
+            }
+        }
+    }
+    Ok(())
+}
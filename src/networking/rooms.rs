@@ -0,0 +1,198 @@
+//! Rooms kept in a persistent data directory.
+//!
+//! A persistent node lists every room opened on it in `rooms.json` within its data
+//! directory, so apps can show the rooms it keeps, report the space they take, and clear out
+//! old ones. Nodes in memory keep no list, as nothing they store outlives them.
+//!
+//! Rooms share the node's stores, but what each one keeps there is scoped to it: its entries
+//! live in its own documents, every blob it pins is tagged under the room's [room_tag_prefix],
+//! and an author created just for it is only ever used in it. Deleting a room removes all of
+//! that, and the blob store's garbage collection frees whatever content no other room or tag
+//! still refers to.
+
+use super::Iroh;
+use anyhow::{Context as _, Result, anyhow};
+use iroh_blobs::api::proto::BlobStatus;
+use iroh_docs::{AuthorId, NamespaceId, store::Query};
+use n0_future::StreamExt as _;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, fmt::Display, path::Path, str::FromStr as _};
+
+/// File name of the list of rooms within a persistent data directory.
+const ROOMS_FILE: &str = "rooms.json";
+
+/// A room whose documents a node keeps, see [Iroh::list_rooms].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StoredRoom {
+    /// The ID of the room's document, as in [crate::MatchRecord::room_id].
+    pub room_id: String,
+    /// The ID of the room's chat document, if it has chat.
+    pub chat_id: Option<String>,
+    /// The room's name.
+    pub room_name: String,
+    /// The [crate::GameLogic::game_name] of the game played in the room.
+    pub game: String,
+    /// When the room was last opened on this node, in milliseconds since the Unix epoch.
+    pub last_opened: u64,
+    /// Roughly how many bytes the room's entries and pinned blobs take, worked out when the
+    /// rooms are listed.
+    #[serde(skip)]
+    pub size: u64,
+    /// The author this node writes to the room as.
+    #[serde(default)]
+    pub(crate) author: Option<AuthorId>,
+}
+
+/// The start of the name of every blob tag a room keeps, e.g. for its game state or assets.
+pub(crate) fn room_tag_prefix(room_id: &impl Display) -> String {
+    format!("room.{room_id}.")
+}
+
+impl Iroh {
+    /// The rooms this node keeps, most recently opened first.
+    ///
+    /// Empty for nodes in memory, which keep no list of their rooms.
+    pub async fn list_rooms(&self) -> Result<Vec<StoredRoom>> {
+        let Some(data_dir) = self.data_dir() else {
+            return Ok(Vec::new());
+        };
+        let mut kept = HashSet::new();
+        let mut docs = self.docs.list().await?;
+        while let Some((id, _)) = docs.next().await.transpose()? {
+            kept.insert(id);
+        }
+        let mut rooms = Vec::new();
+        for mut room in load_rooms(data_dir).await? {
+            let room_id = NamespaceId::from_str(&room.room_id)?;
+            if !kept.contains(&room_id) {
+                continue;
+            }
+            room.size = self.doc_size(room_id).await? + self.tagged_size(&room_id).await?;
+            if let Some(chat_id) = &room.chat_id {
+                let chat_id = NamespaceId::from_str(chat_id)?;
+                if kept.contains(&chat_id) {
+                    room.size += self.doc_size(chat_id).await?;
+                }
+            }
+            rooms.push(room);
+        }
+        rooms.sort_by_key(|room| std::cmp::Reverse(room.last_opened));
+        Ok(rooms)
+    }
+
+    /// Delete a room's documents from this node, along with its chat, game state and assets.
+    ///
+    /// The room must not be open on this node. Content no other room refers to is freed by
+    /// the blob store shortly afterwards. The room can still be joined again with a ticket,
+    /// from anyone who kept it.
+    pub async fn delete_room(&self, room_id: &str) -> Result<()> {
+        let id = NamespaceId::from_str(room_id).map_err(|e| anyhow!("Invalid room ID: {e}"))?;
+        let stored = match self.data_dir() {
+            Some(data_dir) => load_rooms(data_dir).await?,
+            None => Vec::new(),
+        };
+        let known = stored.iter().find(|room| room.room_id == room_id);
+        self.docs.drop_doc(id).await?;
+        if let Some(chat_id) = known.and_then(|room| room.chat_id.as_deref()) {
+            self.docs.drop_doc(NamespaceId::from_str(chat_id)?).await?;
+        }
+        self.store
+            .tags()
+            .delete_prefix(room_tag_prefix(&id))
+            .await?;
+        // The default author writes to every room that reuses it, so only a room's own goes.
+        if let Some(author) = known.and_then(|room| room.author)
+            && author != self.docs.author_default().await?
+        {
+            self.docs.author_delete(author).await?;
+        }
+
+        if let Some(data_dir) = self.data_dir() {
+            let rooms: Vec<_> = stored
+                .into_iter()
+                .filter(|room| room.room_id != room_id)
+                .collect();
+            save_rooms(data_dir, &rooms).await?;
+        }
+        Ok(())
+    }
+
+    /// How many bytes the node's data directory takes on disk, or zero for nodes in memory.
+    ///
+    /// Stores opened by the embedder are only counted if they live in the data directory.
+    pub async fn storage_usage(&self) -> Result<u64> {
+        let Some(data_dir) = self.data_dir() else {
+            return Ok(0);
+        };
+        let mut total = 0;
+        let mut dirs = vec![data_dir.to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            let mut entries = tokio::fs::read_dir(&dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let metadata = entry.metadata().await?;
+                if metadata.is_dir() {
+                    dirs.push(entry.path());
+                } else {
+                    total += metadata.len();
+                }
+            }
+        }
+        Ok(total)
+    }
+
+    /// Add a room to the list of rooms this node keeps, or mark it as just opened, unless
+    /// the node keeps no list.
+    pub(crate) async fn remember_room(&self, room: StoredRoom) -> Result<()> {
+        let Some(data_dir) = self.data_dir() else {
+            return Ok(());
+        };
+        let mut rooms = load_rooms(data_dir).await?;
+        rooms.retain(|known| known.room_id != room.room_id);
+        rooms.push(room);
+        save_rooms(data_dir, &rooms).await
+    }
+
+    /// Add up the content of every entry in a document, past versions included.
+    async fn doc_size(&self, id: NamespaceId) -> Result<u64> {
+        let Some(doc) = self.docs.open(id).await? else {
+            return Ok(0);
+        };
+        let mut size = 0;
+        let mut entries = Box::pin(doc.get_many(Query::all()).await?);
+        while let Some(entry) = entries.next().await.transpose()? {
+            size += entry.content_len();
+        }
+        doc.close().await?;
+        Ok(size)
+    }
+
+    /// Add up the blobs a room keeps tagged.
+    async fn tagged_size(&self, id: &NamespaceId) -> Result<u64> {
+        let mut size = 0;
+        let mut tags = self.store.tags().list_prefix(room_tag_prefix(id)).await?;
+        while let Some(tag) = tags.next().await.transpose()? {
+            if let BlobStatus::Complete { size: len } = self.blobs.status(tag.hash).await? {
+                size += len;
+            }
+        }
+        Ok(size)
+    }
+}
+
+/// Read the list of rooms kept in a persistent data directory.
+async fn load_rooms(data_dir: &Path) -> Result<Vec<StoredRoom>> {
+    let path = data_dir.join(ROOMS_FILE);
+    let bytes = match tokio::fs::read(&path).await {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    serde_json::from_slice(&bytes).with_context(|| format!("Invalid room list {}", path.display()))
+}
+
+/// Write the list of rooms back to a persistent data directory.
+async fn save_rooms(data_dir: &Path, rooms: &[StoredRoom]) -> Result<()> {
+    let bytes = serde_json::to_vec_pretty(rooms)?;
+    tokio::fs::write(data_dir.join(ROOMS_FILE), bytes).await?;
+    Ok(())
+}
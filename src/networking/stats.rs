@@ -0,0 +1,196 @@
+//! Connection quality reporting
+//!
+//! Gossip, docs and blobs each open their own connections, so the node records every
+//! connection as its handshake completes and reads their paths and counters on demand.
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use iroh::{
+    EndpointId, RelayUrl, TransportAddr,
+    endpoint::{AfterHandshakeOutcome, ConnectionInfo, EndpointHooks, PathInfo},
+};
+use serde::{Deserialize, Serialize};
+
+/// Connection quality to one remote peer, as seen from this endpoint.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PeerNetworkStats {
+    /// The remote peer.
+    pub peer: EndpointId,
+    /// Best current round-trip-time estimate across our connections to the peer.
+    pub rtt: Option<Duration>,
+    /// The path game traffic takes, over the fastest of our connections to the peer, or
+    /// `None` while there is no open connection to it.
+    pub path: Option<ConnectionPath>,
+    /// Bytes sent to the peer over the connections that are still open.
+    pub bytes_sent: u64,
+    /// Bytes received from the peer over the connections that are still open.
+    pub bytes_received: u64,
+    /// When data last arrived from the peer, as far as this endpoint has noticed.
+    pub last_activity: Option<SystemTime>,
+}
+
+impl PeerNetworkStats {
+    /// Whether traffic goes through a relay server rather than a direct path.
+    pub fn is_relayed(&self) -> bool {
+        matches!(self.path, Some(ConnectionPath::Relayed(_)))
+    }
+}
+
+/// The network path traffic to a peer currently takes.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionPath {
+    /// Straight to one of the peer's addresses.
+    Direct(SocketAddr),
+    /// Through a relay server.
+    Relayed(RelayUrl),
+}
+
+impl ConnectionPath {
+    fn of(path: &PathInfo) -> Option<Self> {
+        match path.remote_addr() {
+            TransportAddr::Ip(addr) => Some(Self::Direct(*addr)),
+            TransportAddr::Relay(url) => Some(Self::Relayed(url.clone())),
+            _ => None,
+        }
+    }
+}
+
+/// How this endpoint reaches one remote peer, for working out why a match is laggy: its
+/// [PeerNetworkStats], and where else the peer could be reached.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PeerConnectionInfo {
+    /// Connection quality to the peer, with no path or counters while there is no open
+    /// connection to it.
+    pub stats: PeerNetworkStats,
+    /// The relay server the peer can be reached through, whether or not it is in use.
+    pub relay: Option<RelayUrl>,
+    /// The peer's direct addresses this endpoint knows of.
+    pub addrs: Vec<SocketAddr>,
+}
+
+/// Endpoint hook that keeps a weak handle to every connection the node makes or accepts.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ConnectionTracker {
+    connections: Arc<Mutex<Vec<ConnectionInfo>>>,
+    /// The bytes received from each peer when they were last seen to grow, and when that was.
+    activity: Arc<Mutex<HashMap<EndpointId, (u64, SystemTime)>>>,
+    /// Round trips reported in place of the measured ones, see [ConnectionTracker::simulate_rtt].
+    #[cfg(feature = "testing")]
+    simulated_rtts: Arc<Mutex<HashMap<EndpointId, Duration>>>,
+}
+
+impl EndpointHooks for ConnectionTracker {
+    async fn after_handshake<'a>(&'a self, conn: &'a ConnectionInfo) -> AfterHandshakeOutcome {
+        if let Ok(mut connections) = self.connections.lock() {
+            connections.retain(ConnectionInfo::is_alive);
+            connections.push(conn.clone());
+        }
+        AfterHandshakeOutcome::accept()
+    }
+}
+
+impl ConnectionTracker {
+    /// Summarise the open connections, one entry per remote peer.
+    pub(crate) fn stats(&self) -> Vec<PeerNetworkStats> {
+        let Ok(mut connections) = self.connections.lock() else {
+            return Vec::new();
+        };
+        connections.retain(ConnectionInfo::is_alive);
+
+        let mut by_peer: HashMap<EndpointId, PeerNetworkStats> = HashMap::new();
+        for conn in connections.iter() {
+            let Some(totals) = conn.stats() else {
+                continue;
+            };
+            let selected = conn.selected_path();
+            let rtt = selected.as_ref().and_then(|path| path.rtt());
+            let path = selected.as_ref().and_then(ConnectionPath::of);
+
+            let stats = by_peer
+                .entry(conn.remote_id())
+                .or_insert_with(|| PeerNetworkStats {
+                    peer: conn.remote_id(),
+                    rtt: None,
+                    path: path.clone(),
+                    bytes_sent: 0,
+                    bytes_received: 0,
+                    last_activity: None,
+                });
+            // Report the fastest connection's path, since that is where game traffic will be.
+            if rtt.is_some() && (stats.rtt.is_none() || rtt < stats.rtt) {
+                stats.rtt = rtt;
+                stats.path = path;
+            }
+            stats.bytes_sent += totals.udp_tx.bytes;
+            stats.bytes_received += totals.udp_rx.bytes;
+        }
+        drop(connections);
+        for stats in by_peer.values_mut() {
+            stats.last_activity = self.note_activity(stats.peer, stats.bytes_received);
+            #[cfg(feature = "testing")]
+            if let Some(rtt) = self
+                .simulated_rtts
+                .lock()
+                .ok()
+                .and_then(|rtts| rtts.get(&stats.peer).copied())
+            {
+                stats.rtt = Some(rtt);
+            }
+        }
+        by_peer.into_values().collect()
+    }
+
+    /// The path game traffic takes to each peer with an open connection.
+    pub(crate) fn paths(&self) -> HashMap<EndpointId, ConnectionPath> {
+        self.stats()
+            .into_iter()
+            .filter_map(|stats| Some((stats.peer, stats.path?)))
+            .collect()
+    }
+
+    /// Connection quality to `peer`, even once there is no open connection to it.
+    pub(crate) fn stats_for(&self, peer: EndpointId) -> PeerNetworkStats {
+        let last_activity = self
+            .activity
+            .lock()
+            .ok()
+            .and_then(|activity| activity.get(&peer).map(|(_, at)| *at));
+        self.stats()
+            .into_iter()
+            .find(|stats| stats.peer == peer)
+            .unwrap_or(PeerNetworkStats {
+                peer,
+                rtt: None,
+                path: None,
+                bytes_sent: 0,
+                bytes_received: 0,
+                last_activity,
+            })
+    }
+
+    /// Report `rtt` as the round trip to `peer` while connected, whatever is measured.
+    #[cfg(feature = "testing")]
+    pub(crate) fn simulate_rtt(&self, peer: EndpointId, rtt: Duration) {
+        if let Ok(mut rtts) = self.simulated_rtts.lock() {
+            rtts.insert(peer, rtt);
+        }
+    }
+
+    /// Record the bytes received from `peer` so far, noting the time if they have grown, and
+    /// return when data last arrived from it.
+    fn note_activity(&self, peer: EndpointId, received: u64) -> Option<SystemTime> {
+        let now = SystemTime::now();
+        let mut activity = self.activity.lock().ok()?;
+        let (seen, at) = activity.entry(peer).or_insert((received, now));
+        if received > *seen {
+            *at = now;
+        }
+        *seen = received;
+        Some(*at)
+    }
+}
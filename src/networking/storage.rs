@@ -0,0 +1,139 @@
+//! Storage backends
+//!
+//! A node keeps the blobs and documents of its rooms in memory, in a data directory, or in
+//! stores the embedder opens itself, e.g. encrypted at rest, or redb files inside a sandboxed
+//! app directory. The choice is made once, when the node starts.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Result;
+use iroh_blobs::{
+    api::Store as BlobStore,
+    store::{
+        GcConfig,
+        fs::{FsStore, options::Options},
+        mem::MemStore,
+    },
+};
+use iroh_docs::{
+    engine::{DefaultAuthorStorage, ProtectCallbackHandler},
+    store::Store as DocStore,
+};
+use n0_future::boxed::BoxFuture;
+
+/// How often a data directory's blob store frees content that no document or tag refers to
+/// any more, e.g. once a room has been deleted.
+const BLOB_GC_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Where a node keeps its blobs and documents.
+#[derive(Debug, Clone)]
+pub enum StorageBackend {
+    /// Everything in memory, gone once the node shuts down.
+    Memory,
+    /// Blobs, documents and keys in a data directory.
+    Fs(PathBuf),
+    /// Stores opened by the embedder.
+    Custom(Arc<dyn CustomStorage>),
+}
+
+// Written out by hand, as custom stores can only be told apart by identity.
+impl PartialEq for StorageBackend {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Memory, Self::Memory) => true,
+            (Self::Fs(a), Self::Fs(b)) => a == b,
+            (Self::Custom(a), Self::Custom(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for StorageBackend {}
+
+impl StorageBackend {
+    /// The backend for a room opened with `store_path`: a data directory if there is one,
+    /// memory otherwise.
+    pub fn for_path(store_path: Option<PathBuf>) -> Self {
+        match store_path {
+            Some(path) => Self::Fs(path),
+            None => Self::Memory,
+        }
+    }
+
+    /// The directory the node keeps its other files in, e.g. its key and the last room it
+    /// opened, if it has one.
+    pub fn data_dir(&self) -> Option<&Path> {
+        match self {
+            Self::Memory => None,
+            Self::Fs(path) => Some(path),
+            Self::Custom(storage) => storage.data_dir(),
+        }
+    }
+
+    /// Open the backend's stores.
+    ///
+    /// A data directory's blob store frees unused content, so it comes with the handler the
+    /// docs engine keeps the content of every document from being freed through.
+    pub(crate) async fn open(&self) -> Result<(OpenStorage, Option<ProtectCallbackHandler>)> {
+        match self {
+            Self::Memory => Ok((
+                OpenStorage {
+                    blobs: MemStore::new().into(),
+                    docs: DocStore::memory(),
+                },
+                None,
+            )),
+            Self::Fs(path) => {
+                tokio::fs::create_dir_all(path).await?;
+                let (protect, add_protected) = ProtectCallbackHandler::new();
+                let mut options = Options::new(path);
+                options.gc = Some(GcConfig {
+                    interval: BLOB_GC_INTERVAL,
+                    add_protected: Some(add_protected),
+                });
+                let blobs = FsStore::load_with_opts(path.join("blobs.db"), options).await?;
+                let stores = OpenStorage {
+                    blobs: blobs.into(),
+                    docs: DocStore::persistent(path.join("docs.redb"))?,
+                };
+                Ok((stores, Some(protect)))
+            }
+            Self::Custom(storage) => Ok((storage.open().await?, None)),
+        }
+    }
+
+    /// Where the docs engine keeps the default author, alongside the documents.
+    pub(crate) fn default_author_storage(&self) -> DefaultAuthorStorage {
+        match self.data_dir() {
+            Some(path) => DefaultAuthorStorage::Persistent(path.join("default-author")),
+            None => DefaultAuthorStorage::Mem,
+        }
+    }
+}
+
+/// A blob store and a document store, opened and ready for a node to use.
+pub struct OpenStorage {
+    /// Where blobs, including the content of document entries, are kept.
+    pub blobs: BlobStore,
+    /// Where document replicas and authors are kept.
+    pub docs: DocStore,
+}
+
+/// Stores supplied by the embedder, see [StorageBackend::Custom].
+pub trait CustomStorage: fmt::Debug + Send + Sync + 'static {
+    /// Open the stores, once, as the node starts.
+    fn open(&self) -> BoxFuture<Result<OpenStorage>>;
+
+    /// A directory for the node's other files, see [StorageBackend::data_dir].
+    ///
+    /// Without one the node gets a fresh key on every start, unless it is given a
+    /// [crate::KeyStore], and rooms opened on it cannot be resumed.
+    fn data_dir(&self) -> Option<&Path> {
+        None
+    }
+}
@@ -0,0 +1,94 @@
+//! Game-defined stream protocols
+//!
+//! Games can layer their own protocols, e.g. voice or bulk transfers, over the node's endpoint
+//! instead of standing up a second one. Each protocol is named by a suffix, registered with the
+//! router under its own ALPN when the node starts, see [crate::IrohConfig::stream_protocol],
+//! and carries raw bidirectional QUIC streams that the engine never reads from or writes to.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use iroh::{
+    EndpointId,
+    endpoint::{Connection, RecvStream, SendStream},
+    protocol::{AcceptError, ProtocolHandler},
+};
+use tokio::sync::mpsc;
+
+/// Prefix of the ALPN a stream protocol is registered under, ahead of its suffix.
+const STREAM_ALPN_PREFIX: &str = "/p2p-game-engine/stream/0/";
+
+/// How many accepted streams wait for the application before new ones are turned away.
+const STREAM_BACKLOG: usize = 16;
+
+/// The ALPN a stream protocol is registered under.
+pub(crate) fn stream_alpn(protocol: &str) -> Vec<u8> {
+    format!("{STREAM_ALPN_PREFIX}{protocol}").into_bytes()
+}
+
+/// A bidirectional stream to a peer, on one of the game's own protocols.
+///
+/// The peer accepting the stream only hears of it once the opener writes to it.
+#[derive(Debug)]
+pub struct PeerStream {
+    /// The peer at the other end.
+    pub peer: EndpointId,
+    /// The suffix of the protocol the stream was opened on.
+    pub protocol: String,
+    /// Writes to the peer.
+    pub send: SendStream,
+    /// Reads from the peer.
+    pub recv: RecvStream,
+}
+
+/// Accepts connections on one stream protocol and hands their streams to whoever listens.
+#[derive(Debug, Clone)]
+pub(crate) struct StreamProtocol {
+    protocol: String,
+    listener: Arc<Mutex<Option<mpsc::Sender<PeerStream>>>>,
+}
+
+impl StreamProtocol {
+    pub(crate) fn new(protocol: &str) -> Self {
+        Self {
+            protocol: protocol.to_string(),
+            listener: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Start handing accepted streams to a new listener, in place of any earlier one.
+    pub(crate) fn listen(&self) -> mpsc::Receiver<PeerStream> {
+        let (tx, rx) = mpsc::channel(STREAM_BACKLOG);
+        *self.lock_listener() = Some(tx);
+        rx
+    }
+
+    fn listener(&self) -> Option<mpsc::Sender<PeerStream>> {
+        self.lock_listener().clone()
+    }
+
+    fn lock_listener(&self) -> MutexGuard<'_, Option<mpsc::Sender<PeerStream>>> {
+        // The listener is only ever replaced whole, so a poisoned lock is still usable.
+        self.listener
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl ProtocolHandler for StreamProtocol {
+    async fn accept(&self, connection: Connection) -> Result<(), AcceptError> {
+        let peer = connection.remote_id();
+        while let Ok((send, recv)) = connection.accept_bi().await {
+            // Without a listener, or with a full backlog, the stream is dropped, which resets it.
+            let Some(listener) = self.listener() else {
+                continue;
+            };
+            let _ = listener.try_send(PeerStream {
+                peer,
+                protocol: self.protocol.clone(),
+                send,
+                recv,
+            });
+        }
+        Ok(())
+    }
+}
@@ -10,6 +10,7 @@ use std::{
 };
 
 use iroh::EndpointId;
+use iroh_blobs::Hash;
 use iroh_docs::AuthorId;
 use serde::{Deserialize, Serialize};
 
@@ -29,6 +30,16 @@ impl PeerStatus {
     }
 }
 
+/// A team that players can be grouped into, e.g. for 2v2 games.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TeamId(pub u32);
+
+impl Display for TeamId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Team {}", self.0)
+    }
+}
+
 /// Personalisation Information about this peer
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct PeerProfile {
@@ -36,6 +47,10 @@ pub struct PeerProfile {
     pub nickname: String,
     /// Avatar URL
     pub avatar: Option<String>,
+    /// Hash of an avatar image shared through the peer's blob store.
+    pub avatar_blob: Option<Hash>,
+    /// The peer's skill rating as they see it, for lobbies to display.
+    pub rating: Option<i32>,
 }
 
 impl From<&str> for PeerProfile {
@@ -43,6 +58,8 @@ impl From<&str> for PeerProfile {
         PeerProfile {
             nickname: val.to_string(),
             avatar: None,
+            avatar_blob: None,
+            rating: None,
         }
     }
 }
@@ -56,6 +73,10 @@ pub struct PeerInfo {
     pub status: PeerStatus,
     pub ready: bool,
     pub is_observer: bool,
+    /// The endpoint this player shares, if they are a local seat rather than the endpoint's own player.
+    pub seat_of: Option<EndpointId>,
+    /// The team the host has placed this player in, if the game has teams.
+    pub team: Option<TeamId>,
 }
 
 impl Display for PeerInfo {
@@ -73,18 +94,42 @@ impl PeerInfo {
             status: PeerStatus::Online,
             ready: false,
             is_observer: true,
+            seat_of: None,
+            team: None,
         }
     }
 
-    /// Update identity details for a returning peer without resetting game participation flags.
-    pub fn reintroduced(&self, author_id: AuthorId, profile: PeerProfile) -> Self {
+    /// Create a local seat sharing another endpoint, e.g. a second player at the same screen.
+    pub fn seat(
+        id: EndpointId,
+        owner: EndpointId,
+        author_id: AuthorId,
+        profile: PeerProfile,
+    ) -> Self {
+        Self {
+            seat_of: Some(owner),
+            ..Self::new(id, author_id, profile)
+        }
+    }
+
+    /// Check whether this player is a local seat on another endpoint.
+    pub fn is_seat(&self) -> bool {
+        self.seat_of.is_some()
+    }
+
+    /// Update the profile of a returning peer without resetting game participation flags.
+    ///
+    /// The author stays the one the peer first joined with.
+    pub fn reintroduced(&self, profile: PeerProfile) -> Self {
         Self {
             id: self.id,
-            author_id,
+            author_id: self.author_id,
             profile,
             status: PeerStatus::Online,
             ready: self.ready,
             is_observer: self.is_observer,
+            seat_of: self.seat_of,
+            team: self.team,
         }
     }
 }
@@ -108,6 +153,13 @@ impl DerefMut for PeerMap {
     }
 }
 
+impl PeerMap {
+    /// Get the players on a team.
+    pub fn team(&self, team: TeamId) -> impl Iterator<Item = &PeerInfo> {
+        self.0.values().filter(move |peer| peer.team == Some(team))
+    }
+}
+
 impl Display for PeerMap {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for (id, peer_info) in self.0.iter() {
@@ -0,0 +1,217 @@
+//! Player ratings
+//!
+//! This module keeps skill ratings for the players an identity has played with, behind the
+//! `ratings` feature. [Ratings] updates them from each [GameResult] with a [RatingSystem],
+//! [Elo] unless another one is given, and saves them in the identity's data directory, so
+//! they carry over between sessions.
+//!
+//! Ratings are worked out locally, from the games this identity saw finish, so two players
+//! may rate a third differently. [Ratings::share] puts the identity's own rating in its
+//! [PeerProfile] for lobbies to display.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use anyhow::Result;
+use iroh::EndpointId;
+use serde::{Deserialize, Serialize};
+
+use crate::{FinishReason, GameResult, Identity, MatchRecord, PeerProfile};
+
+/// File name of the saved ratings within an identity's data directory.
+const RATINGS_FILE: &str = "ratings.json";
+
+/// How ratings change when a game finishes.
+pub trait RatingSystem: Send + Sync {
+    /// The rating of a player with no rated games.
+    fn initial_rating(&self) -> i32;
+
+    /// Work out the players' new ratings from their current ones and the game's result.
+    ///
+    /// `ratings` has an entry for every player in the game. Players left out of the
+    /// returned map keep their rating.
+    fn rate(
+        &self,
+        ratings: &HashMap<EndpointId, i32>,
+        result: &GameResult,
+    ) -> HashMap<EndpointId, i32>;
+}
+
+/// The Elo rating system, extended to more than two players by rating each pair of players
+/// as a separate game between them.
+///
+/// Winners beat everyone else and draw with each other. A game with no winners is a draw
+/// between all the players, unless it finished through inactivity, which is not rated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elo {
+    /// The most a rating can change by in one game.
+    pub k_factor: u32,
+    /// The rating of a player with no rated games.
+    pub initial: i32,
+}
+
+impl Default for Elo {
+    fn default() -> Self {
+        Self {
+            k_factor: 32,
+            initial: 1200,
+        }
+    }
+}
+
+impl RatingSystem for Elo {
+    fn initial_rating(&self) -> i32 {
+        self.initial
+    }
+
+    fn rate(
+        &self,
+        ratings: &HashMap<EndpointId, i32>,
+        result: &GameResult,
+    ) -> HashMap<EndpointId, i32> {
+        if ratings.len() < 2
+            || (result.winners.is_empty() && result.reason == FinishReason::Inactivity)
+        {
+            return HashMap::new();
+        }
+        let score = |player: &EndpointId| match result.winners.contains(player) {
+            true => 1.0,
+            false => 0.0,
+        };
+        let opponents = (ratings.len() - 1) as f64;
+        ratings
+            .iter()
+            .map(|(player, rating)| {
+                let change: f64 = ratings
+                    .iter()
+                    .filter(|(opponent, _)| *opponent != player)
+                    .map(|(opponent, opponent_rating)| {
+                        let actual = (1.0 + score(player) - score(opponent)) / 2.0;
+                        let expected =
+                            1.0 / (1.0 + 10f64.powf(f64::from(opponent_rating - rating) / 400.0));
+                        actual - expected
+                    })
+                    .sum();
+                let change = f64::from(self.k_factor) * change / opponents;
+                (*player, rating + change.round() as i32)
+            })
+            .collect()
+    }
+}
+
+/// The saved part of an identity's ratings.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct RatingsFile {
+    ratings: HashMap<EndpointId, i32>,
+    /// The games already rated from the match history, as `<room_id>.<started_at>`.
+    rated: HashSet<String>,
+}
+
+/// The ratings of every player an identity has played with.
+#[derive(Clone)]
+pub struct Ratings {
+    data_dir: PathBuf,
+    system: Arc<dyn RatingSystem>,
+    saved: RatingsFile,
+}
+
+impl std::fmt::Debug for Ratings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Ratings")
+            .field("data_dir", &self.data_dir)
+            .field("ratings", &self.saved.ratings)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Ratings {
+    /// Load the ratings saved in an identity's data directory, rated with [Elo].
+    pub async fn load(identity: &Identity) -> Result<Self> {
+        Self::load_with(identity, Elo::default()).await
+    }
+
+    /// Load the ratings saved in an identity's data directory, rated with `system`.
+    pub async fn load_with(
+        identity: &Identity,
+        system: impl RatingSystem + 'static,
+    ) -> Result<Self> {
+        let data_dir = identity.data_dir().to_path_buf();
+        let saved = match tokio::fs::read(data_dir.join(RATINGS_FILE)).await {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => RatingsFile::default(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            data_dir,
+            system: Arc::new(system),
+            saved,
+        })
+    }
+
+    /// Get a player's rating, or the initial rating if they have no rated games.
+    pub fn get_rating(&self, peer_id: &EndpointId) -> i32 {
+        self.saved
+            .ratings
+            .get(peer_id)
+            .copied()
+            .unwrap_or_else(|| self.system.initial_rating())
+    }
+
+    /// Update the ratings of the players in a finished game and save them.
+    ///
+    /// The players are the ones in the result's [crate::MatchStats].
+    pub async fn record(&mut self, result: &GameResult) -> Result<()> {
+        self.apply(result);
+        self.save().await
+    }
+
+    /// Rate every game in the identity's match history that has not been rated yet, oldest
+    /// first, and save the ratings.
+    pub async fn record_history(&mut self, identity: &Identity) -> Result<()> {
+        let mut history = identity.match_history().await?;
+        history.reverse();
+        for record in &history {
+            if self.saved.rated.insert(match_key(record)) {
+                self.apply(&record.result);
+            }
+        }
+        self.save().await
+    }
+
+    /// Put an identity's own rating in its profile, so the rooms it enters show it.
+    pub fn share(&self, identity: &mut Identity) {
+        identity.profile.rating = Some(self.get_rating(&identity.id()));
+    }
+
+    /// Put a player's rating in a profile.
+    pub fn share_in(&self, profile: &mut PeerProfile, peer_id: &EndpointId) {
+        profile.rating = Some(self.get_rating(peer_id));
+    }
+
+    /// Update the ratings of the players in a finished game.
+    fn apply(&mut self, result: &GameResult) {
+        let current: HashMap<_, _> = result
+            .stats
+            .players
+            .keys()
+            .map(|player| (*player, self.get_rating(player)))
+            .collect();
+        let updated = self.system.rate(&current, result);
+        self.saved.ratings.extend(updated);
+    }
+
+    /// Write the ratings back to the data directory.
+    async fn save(&self) -> Result<()> {
+        let bytes = serde_json::to_vec_pretty(&self.saved)?;
+        tokio::fs::write(self.data_dir.join(RATINGS_FILE), bytes).await?;
+        Ok(())
+    }
+}
+
+/// The key a game is remembered by once rated from the match history.
+fn match_key(record: &MatchRecord) -> String {
+    format!("{}.{}", record.room_id, record.started_at)
+}
@@ -7,17 +7,51 @@
 //! The `GameRoom` struct is responsible for managing the game state, processing events, and providing an API for the
 //! UI to interact with the game.
 
+mod annotation;
+mod builder;
 mod chat;
+mod config;
+mod export;
+mod lobby_registry;
+mod meta;
+mod private;
+mod proposal;
+mod realtime;
+mod result;
+mod secret;
+mod session;
+mod social;
+mod stats;
+#[cfg(feature = "testing")]
+pub mod testing;
 mod ticket;
+mod vote;
 mod events {
     mod actions;
+    mod catch_up;
+    mod compaction;
     mod connections;
+    mod debounce;
+    mod dedicated;
+    mod delivery;
     mod entries;
     mod event_loop;
+    mod heartbeat;
+    mod inactivity;
+    mod metrics;
     mod network;
+    mod peer_changes;
     mod process;
+    mod proposals;
+    mod rounds;
+    mod supervisor;
+    mod tick;
+    mod typing;
     mod ui;
+    mod votes;
+    pub(crate) use {catch_up::initial_snapshot, delivery::Subscribers};
     pub use {
+        delivery::{Coalesce, EventCoalescing, EventFilter},
         event_loop::HostEvent,
         ui::{UiError, UiEvent},
     };
@@ -25,21 +59,54 @@ mod events {
 mod snapshot;
 mod state;
 
-use crate::{GameLogic, PeerMap, PeerProfile};
+use crate::{
+    GameLogic, Identity, PeerConnectionInfo, PeerMap, PeerNetworkStats, PeerProfile, PeerStream,
+    TeamId, TurnManager,
+};
 use anyhow::Result;
+use bytes::Bytes;
 use iroh::EndpointId;
+use iroh_gossip::api::GossipSender;
+use realtime::{GAME_MESSAGE_KEY, RealtimePayload};
 use state::StateData;
+use std::ops::RangeBounds;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
-use std::{collections::HashMap, str::FromStr as _};
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
 
-pub use chat::ChatMessage;
-pub use events::{HostEvent, UiError, UiEvent};
+pub use annotation::{Annotation, MAX_ANNOTATION_LEN};
+pub use builder::GameRoomBuilder;
+pub use chat::{ChatMessage, ChatReaction, ChatUpdate};
+pub(crate) use config::timer;
+pub use config::{
+    CompactionPolicy, DedicatedPolicy, HeartbeatPolicy, InactivityPolicy, RateLimit, RateLimits,
+    RoomConfig, RoundPolicy, StateCadence,
+};
+pub use events::{Coalesce, EventCoalescing, EventFilter, HostEvent, UiError, UiEvent};
+pub use export::Match;
+pub use lobby_registry::{ADVERTISE_INTERVAL, LobbyAdvert, LobbyRegistry, RoomListing};
+pub use meta::RoomMeta;
+pub use proposal::{Proposal, ProposalOutcome, ProposalRequest};
+pub use result::{FinishReason, GameOutcome, GameResult};
+pub use secret::RoomSecret;
 pub use snapshot::RoomSnapshot;
-pub use state::{ActionResult, AppState, LeaveReason};
-pub use ticket::GameTicket;
+pub use social::{Friend, Friends, PRESENCE_INTERVAL, Presence};
+pub use state::{
+    ActionError, Activity, AppState, AppliedAction, Asset, Compression, DocStats,
+    ENGINE_KEY_PREFIXES, JoinDeniedReason, LeaveReason, MAX_AVATAR_BYTES, MAX_DECOMPRESSED_LEN,
+    WireFormat, is_engine_key,
+};
+pub use stats::{MatchPlayerStats, MatchStats};
+pub use ticket::{RoomTicket, RoomVisibility, TicketError};
+pub use vote::{VoteCall, VoteKind, VoteResult};
+
+/// How long a joiner waits for the room's metadata to sync.
+const JOIN_TIMEOUT: Duration = Duration::from_secs(5);
+/// Without relays, joining relies on dialling direct addresses, which may take several attempts.
+const LAN_JOIN_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long [GameRoom::reconnect] waits for the relay to answer after rebinding the endpoint.
+const RECONNECT_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// The main interface for creating and joining game rooms,
 /// as well as the main API for interacting with the game state.
@@ -50,6 +117,15 @@ pub struct GameRoom<G: GameLogic> {
     pub(self) logic: Arc<G>,
     /// UI event loop handle
     pub(self) event_handle: Option<tokio::task::JoinHandle<()>>,
+    /// Settings this room was created or joined with
+    pub(self) config: RoomConfig,
+    /// Sends on the room's real-time channel, once the event loop has joined it.
+    pub(self) realtime: Option<GossipSender>,
+    /// Extra receivers of the room's events, see [GameRoom::subscribe].
+    pub(self) subscribers: events::Subscribers<G>,
+    /// Faults injected into the event loop, see [GameRoom::faults].
+    #[cfg(feature = "testing")]
+    pub(self) faults: testing::Faults,
     /// The name of the game room created by the host, used for display purposes.
     pub name: String,
 }
@@ -63,11 +139,16 @@ impl<G: GameLogic> Drop for GameRoom<G> {
 }
 
 impl<G: GameLogic> GameRoom<G> {
-    fn new(state: StateData<G>, logic: G, name: &str) -> Self {
+    fn new(state: StateData<G>, logic: Arc<G>, name: &str, config: RoomConfig) -> Self {
         Self {
             state: Arc::new(state),
-            logic: Arc::new(logic),
+            logic,
             event_handle: None,
+            subscribers: events::Subscribers::new(config.replay_buffer),
+            config,
+            realtime: None,
+            #[cfg(feature = "testing")]
+            faults: testing::Faults::default(),
             name: name.to_string(),
         }
     }
@@ -76,12 +157,17 @@ impl<G: GameLogic> GameRoom<G> {
     pub fn id(&self) -> EndpointId {
         self.state.endpoint_id
     }
-    /// Get a fresh join ticket for this room, including all known peer addresses.
-    pub async fn ticket(&self) -> Result<GameTicket> {
-        Ok(GameTicket {
-            doc_ticket: self.state.ticket().await?,
-            room_id: self.name.clone(),
-        })
+    /// Get a fresh join ticket for this room, with the current addresses of every online peer
+    /// this one is connected to, so joiners can sync from any of them, even while the host is
+    /// away.
+    pub async fn ticket(&self) -> Result<RoomTicket> {
+        Ok(RoomTicket::new(
+            self.logic.as_ref(),
+            self.state.ticket().await?,
+            self.state.chat_ticket().await?,
+            &self.name,
+            self.config.visibility,
+        ))
     }
 
     /// Start the Game
@@ -92,31 +178,43 @@ impl<G: GameLogic> GameRoom<G> {
         if self.get_app_state().await? != AppState::Lobby {
             return Err(anyhow::anyhow!("Game has already started"));
         }
+        self.state.start_game(&self.logic).await
+    }
 
-        let players: PeerMap = self.get_peer_list().await?;
-        let roles: HashMap<EndpointId, G::PlayerRole> = self.logic.assign_roles(&players)?;
-        if let Some(peer) = players.iter().find_map(|(peer_id, peer)| {
-            roles
-                .get(peer_id)
-                .filter(|role| !self.logic.is_observer_role(role))
-                .filter(|_| !peer.ready)
-                .map(|_| peer)
-        }) {
-            return Err(anyhow::anyhow!("Peer {peer} is not ready"));
+    /// Move the running game into one of its own phases, see [GameLogic::Phase].
+    ///
+    /// Only the host can set the phase, and only once the game has started. Every peer
+    /// receives the new phase as [UiEvent::AppState] with [AppState::Custom].
+    pub async fn set_phase(&self, phase: G::Phase) -> Result<()> {
+        if !self.is_host().await? {
+            return Err(anyhow::anyhow!("Only the host can set the game's phase"));
+        }
+        if !self.get_app_state().await?.is_in_game() {
+            return Err(anyhow::anyhow!("Phases can only be set during a game"));
         }
-        self.logic.validate_start(&players, &roles)?;
-        let initial_state: G::GameState = self.logic.initial_state(&players, &roles)?;
+        self.state.set_app_state(&AppState::Custom(phase)).await
+    }
 
-        for (peer_id, role) in roles.iter() {
-            self.state
-                .set_peer_observer(peer_id, self.logic.is_observer_role(role))
-                .await?;
+    /// Close the room for good, e.g. once its players are done with it, so its tickets no
+    /// longer lead to an open lobby.
+    ///
+    /// Only the host can close the room. Every peer receives [UiEvent::RoomClosed] with
+    /// `reason` and the room moves to [AppState::Finished], ending any game in progress
+    /// without a result. Anyone who tries to join afterwards is refused with
+    /// [JoinDeniedReason::RoomClosed].
+    pub async fn close(&self, reason: &str) -> Result<()> {
+        if !self.is_host().await? {
+            return Err(anyhow::anyhow!("Only the host can close the room"));
+        }
+        if self.state.get_close_reason().await?.is_some() {
+            return Err(anyhow::anyhow!("Room is already closed"));
         }
+        self.state.close_room(reason).await
+    }
 
-        // Broadast the initial game state before setting the game to active.
-        self.state.set_game_state(&initial_state).await?;
-        self.state.set_app_state(&AppState::InGame).await?;
-        Ok(())
+    /// Get why the host closed the room, or `None` while it is open.
+    pub async fn get_close_reason(&self) -> Result<Option<String>> {
+        self.state.get_close_reason().await
     }
 
     /// Create a new GameRoom
@@ -125,40 +223,199 @@ impl<G: GameLogic> GameRoom<G> {
         store_path: Option<PathBuf>,
         name: Option<&str>,
     ) -> Result<(Self, mpsc::Receiver<UiEvent<G>>)> {
-        let state = StateData::new(store_path, None).await?;
+        Self::create_with_config(logic, store_path, name, RoomConfig::default()).await
+    }
+
+    /// Create a new GameRoom with custom room settings.
+    pub async fn create_with_config(
+        logic: G,
+        store_path: Option<PathBuf>,
+        name: Option<&str>,
+        config: RoomConfig,
+    ) -> Result<(Self, mpsc::Receiver<UiEvent<G>>)> {
+        let state = StateData::new(store_path, None, &config).await?;
+        let name = name.unwrap_or(logic.game_name()).to_string();
+        Self::open_as_host(state, Arc::new(logic), &name, config).await
+    }
+
+    /// Create a room run by a dedicated host that never plays, e.g. an always-on community
+    /// room on a server.
+    ///
+    /// The host never announces itself, so it is not in the peer list and is never given a
+    /// role. It starts each game once the room's [DedicatedPolicy] is met, by default when
+    /// two online players are ready, and sends heartbeats and compacts the room's history
+    /// unless `config` sets those policies itself. The room's rate limits and other host
+    /// policies apply as usual.
+    ///
+    /// The room runs headless: its events are drained in the background, so nothing needs to
+    /// receive them. Use [GameRoom::subscribe] to follow them, e.g. for logging. The node is
+    /// kept in memory unless `config`'s network settings name a storage backend.
+    pub async fn create_dedicated(logic: G, mut config: RoomConfig) -> Result<Self> {
+        config.dedicated.get_or_insert_default();
+        config.heartbeat.get_or_insert_default();
+        config.compaction.get_or_insert_default();
+        let (room, mut events) = Self::create_with_config(logic, None, None, config).await?;
+        tokio::spawn(async move { while events.recv().await.is_some() {} });
+        Ok(room)
+    }
+
+    /// Create a fresh room for the same group, e.g. for another game once this one has finished.
+    ///
+    /// The new room runs on the same node as `previous`, so this peer keeps its ID, and it
+    /// is created with the same name and settings. Our profile and local seats are announced
+    /// again straight away. Everyone else in `previous` is invited with a [UiEvent::Rehosted]
+    /// event carrying the new room's ticket, so keep `previous` open until they have had a
+    /// chance to receive it.
+    pub async fn rehost_from(previous: &GameRoom<G>) -> Result<(Self, mpsc::Receiver<UiEvent<G>>)> {
+        if !previous.is_host().await? {
+            return Err(anyhow::anyhow!("Only the host can rehost a room"));
+        }
+        if previous.get_app_state().await? != AppState::Finished {
+            return Err(anyhow::anyhow!("Only a finished game can be rehosted"));
+        }
+
+        let state = StateData::with_node(previous.state.node()?, None, &previous.config).await?;
+        let logic = previous.logic.clone();
+        let (room, events) =
+            Self::open_as_host(state, logic, &previous.name, previous.config.clone()).await?;
+
+        let players = previous.get_peer_list().await?;
+        if let Some(host) = players.get(&previous.id()) {
+            room.announce_presence(host.profile.clone()).await?;
+        }
+        for (index, seat) in (1..).zip(previous.seats().await?.iter().skip(1)) {
+            if let Some(seat) = players.get(seat) {
+                room.state
+                    .announce_seat(index, seat.profile.clone())
+                    .await?;
+            }
+        }
+
+        if let Some(meta) = previous.get_meta().await? {
+            room.state.set_meta(&meta).await?;
+        }
+        // The entry is only readable by the previous room's players, so it can carry the secret.
+        let mut next_room = room.ticket().await?;
+        next_room.secret = room.config.secret.clone();
+        previous.state.set_next_room(&next_room).await?;
+        Ok((room, events))
+    }
 
+    /// Set up a newly created room with this peer as its host and start its event loop.
+    async fn open_as_host(
+        state: StateData<G>,
+        logic: Arc<G>,
+        name: &str,
+        config: RoomConfig,
+    ) -> Result<(Self, mpsc::Receiver<UiEvent<G>>)> {
         // Host immediately sets the initial lobby state and its own ID.
-        state
-            .set_room_metadata(&state::RoomMetadata::for_game::<G>())
-            .await?;
+        state.adopt_compression(config.compression);
+        state.adopt_wire_format(config.wire_format);
+        state.adopt_lockstep(config.lockstep);
+        let metadata = state::RoomMetadata::for_game(logic.as_ref())
+            .with_compression(config.compression)
+            .with_wire_format(config.wire_format)
+            .with_lockstep(config.lockstep)
+            .with_key_check(state.key_check());
+        state.set_room_metadata(&metadata).await?;
         state.set_app_state(&AppState::Lobby).await?;
         state.set_host(&state.endpoint_id).await?;
+        state
+            .set_meta(&RoomMeta::new(logic.as_ref(), name, config.visibility))
+            .await?;
 
-        let mut room = Self::new(state, logic, name.unwrap_or_else(|| G::GAME_NAME));
-        let (event_inbox, event_handle) = room.start_event_loop().await?;
+        let mut room = Self::new(state, logic, name, config);
+        let (event_inbox, event_handle) = room.start_event_loop(Vec::new()).await?;
         room.event_handle = Some(event_handle);
+        room.remember(None).await?;
         Ok((room, event_inbox))
     }
 
+    /// Create a new GameRoom as a persistent player identity.
+    ///
+    /// The room uses the identity's data directory, so it keeps the same EndpointId and
+    /// document author as every other room opened with it, and the identity's profile is
+    /// announced straight away.
+    pub async fn create_with_identity(
+        logic: G,
+        identity: &mut Identity,
+        name: Option<&str>,
+        mut config: RoomConfig,
+    ) -> Result<(Self, mpsc::Receiver<UiEvent<G>>)> {
+        let store_path = Some(identity.data_dir().to_path_buf());
+        config.network = identity.network(config.network);
+        let (room, events) = Self::create_with_config(logic, store_path, name, config).await?;
+        room.announce_presence(identity.profile.clone()).await?;
+        identity.stats.games_hosted += 1;
+        identity.save().await?;
+        Ok((room, events))
+    }
+
     /// Join a GameRoom
     pub async fn join(
         logic: G,
         ticket: &str,
         store_path: Option<PathBuf>,
     ) -> Result<(Self, mpsc::Receiver<UiEvent<G>>)> {
-        let ticket = GameTicket::from_str(ticket)?;
-        let room_name = ticket.room_id.clone();
-        let state = StateData::new(store_path, Some(ticket)).await?;
+        Self::join_with_config(logic, ticket, store_path, RoomConfig::default()).await
+    }
+
+    /// Join a GameRoom with custom room settings.
+    ///
+    /// Unless [RoomConfig::await_initial_sync] is turned off, this returns once the room's
+    /// state has synced, and the first event is a [UiEvent::InitialSnapshot] of it. Otherwise
+    /// it returns as soon as the room is found, and [UiEvent::Syncing] progress is reported
+    /// until the snapshot follows.
+    pub async fn join_with_config(
+        logic: G,
+        ticket: &str,
+        store_path: Option<PathBuf>,
+        mut config: RoomConfig,
+    ) -> Result<(Self, mpsc::Receiver<UiEvent<G>>)> {
+        let ticket = RoomTicket::parse(ticket)?;
+        if config.secret.is_none() {
+            config.secret = ticket.secret.clone();
+        }
+        ticket.validate_for(&logic)?;
+        let room_name = ticket.room_name.clone();
+        let peers = ticket.doc_ticket.nodes.clone();
+        let join_timeout = match config.network.is_lan_only() {
+            true => LAN_JOIN_TIMEOUT,
+            false => JOIN_TIMEOUT,
+        };
+        let state = StateData::new(store_path, Some(ticket.clone()), &config).await?;
         state
-            .wait_for_valid_room_metadata(Duration::from_secs(5))
+            .wait_for_valid_room_metadata(&logic, join_timeout, &peers, false)
             .await?;
+        let mut initial = Vec::new();
+        if config.await_initial_sync {
+            state.wait_for_initial_sync(join_timeout, &peers).await?;
+            initial.push(events::initial_snapshot(&state).await?);
+        }
 
-        let mut room = Self::new(state, logic, &room_name);
-        let (event_inbox, event_handle) = room.start_event_loop().await?;
+        let mut room = Self::new(state, Arc::new(logic), &room_name, config);
+        let (event_inbox, event_handle) = room.start_event_loop(initial).await?;
         room.event_handle = Some(event_handle);
+        room.remember(Some(ticket)).await?;
         Ok((room, event_inbox))
     }
 
+    /// Join a GameRoom as a persistent player identity, announcing the identity's profile.
+    pub async fn join_with_identity(
+        logic: G,
+        identity: &mut Identity,
+        ticket: &str,
+        mut config: RoomConfig,
+    ) -> Result<(Self, mpsc::Receiver<UiEvent<G>>)> {
+        let store_path = Some(identity.data_dir().to_path_buf());
+        config.network = identity.network(config.network);
+        let (room, events) = Self::join_with_config(logic, ticket, store_path, config).await?;
+        room.announce_presence(identity.profile.clone()).await?;
+        identity.stats.games_joined += 1;
+        identity.save().await?;
+        Ok((room, events))
+    }
+
     /// Check whether this room instance is the current host.
     pub async fn is_host(&self) -> Result<bool> {
         self.state.is_host().await
@@ -169,8 +426,29 @@ impl<G: GameLogic> GameRoom<G> {
         self.state.claim_host(&self.logic).await
     }
 
+    /// Hand hosting authority to another peer, e.g. before leaving in the middle of a game.
+    ///
+    /// Only the host can do this, and only to an online peer that [GameLogic::can_host]
+    /// allows. The peer takes over once the handover reaches it, and every peer receives a
+    /// [HostEvent::Changed] event, so the game carries on without being paused.
+    pub async fn transfer_host(&self, to: &EndpointId) -> Result<()> {
+        if !self.is_host().await? {
+            return Err(anyhow::anyhow!("Only the host can hand over hosting"));
+        }
+        if *to == self.id() {
+            return Err(anyhow::anyhow!("This peer is already the host"));
+        }
+        let Some(peer) = self.state.get_peer_info(to).await? else {
+            return Err(anyhow::anyhow!("Peer has not joined the room"));
+        };
+        if peer.is_seat() || !peer.status.is_online() || !self.logic.can_host(&peer) {
+            return Err(anyhow::anyhow!("Peer {peer} cannot take over hosting"));
+        }
+        self.state.hand_over_host(to).await
+    }
+
     /// Get the current application lifecycle state.
-    pub async fn get_app_state(&self) -> Result<AppState> {
+    pub async fn get_app_state(&self) -> Result<AppState<G>> {
         self.state.get_app_state().await
     }
 
@@ -179,6 +457,286 @@ impl<G: GameLogic> GameRoom<G> {
         self.state.get_game_state().await
     }
 
+    /// Get the final result of the game, once the host has finished it.
+    ///
+    /// Returns `None` while the game is still running, or if the result has not synced yet.
+    pub async fn get_result(&self) -> Result<Option<GameResult>> {
+        self.state.get_game_result().await
+    }
+
+    /// Watch the room's app state, which is [AppState::Paused] while the host is offline.
+    ///
+    /// The event loop keeps it up to date, e.g. for disabling input while the game is paused.
+    pub fn watch_app_state(&self) -> watch::Receiver<AppState<G>> {
+        self.state.watch_app_state()
+    }
+
+    /// Watch whether the host is connected, as seen by this peer.
+    pub fn watch_host_online(&self) -> watch::Receiver<bool> {
+        self.state.watch_host_online()
+    }
+
+    /// Tell the room whether the app is in use, e.g. from a mobile app's lifecycle callbacks.
+    ///
+    /// In [Activity::Background] the room keeps syncing the game, but sends heartbeats only
+    /// as often as it must to stay online, stops listening to the real-time channel, and
+    /// holds game state and peer list updates back for longer, so fewer events wake the app.
+    /// Back in [Activity::Active], it syncs with every peer straight away and reports
+    /// whatever it held back.
+    pub async fn set_activity(&self, activity: Activity) -> Result<()> {
+        let previous = self.state.set_activity(activity);
+        if previous == Activity::Background && activity == Activity::Active {
+            self.state.restart_sync().await?;
+        }
+        Ok(())
+    }
+
+    /// Get the room going again after the device changed networks, e.g. from Wi-Fi to cellular.
+    ///
+    /// The endpoint rebinds its sockets and reconnects to its relay, and the room syncs its
+    /// documents with every peer and rejoins the real-time channel, reporting
+    /// [UiEvent::Reconnected] once it has. Rooms notice most network changes by themselves,
+    /// but platforms like Android only tell the app, which should call this when they do.
+    pub async fn reconnect(&self) -> Result<()> {
+        self.state.reconnect(RECONNECT_TIMEOUT).await
+    }
+
+    /// Whether the app is in use, as last set with [GameRoom::set_activity].
+    pub fn activity(&self) -> Activity {
+        self.state.activity()
+    }
+
+    /// Receive the room's events that pass `filter` on a receiver of their own, e.g. for a
+    /// rendering layer that only cares about the game state.
+    ///
+    /// Subscribers start with the room's recent events that pass `filter`, see
+    /// [RoomConfig::replay_buffer], then get events as they happen, coalesced like the room's
+    /// own receiver. A slow subscriber never holds up the others, and one that falls more than
+    /// [RoomConfig::queue_limit] events behind misses the oldest, see [UiEvent::Lagged].
+    pub fn subscribe(&self, filter: EventFilter) -> mpsc::Receiver<UiEvent<G>> {
+        let config = &self.config;
+        self.subscribers.subscribe(
+            filter,
+            config.coalescing,
+            config.channel_capacity,
+            config.queue_limit,
+        )
+    }
+
+    /// Up to `n` of the room's most recent events, oldest first, e.g. for a view created after
+    /// the room to rebuild its state from. How many are kept is set with
+    /// [RoomConfig::replay_buffer].
+    pub fn replay_recent(&self, n: usize) -> Vec<UiEvent<G>> {
+        self.subscribers.recent(n)
+    }
+
+    /// Call `handler` with every event from now on, e.g. for engines with a scheduler of their
+    /// own that would rather not spawn a task to drain a receiver.
+    ///
+    /// Handlers run on the room's event loop as each event is produced, before any coalescing,
+    /// so they should hand the event off quickly rather than block. A handler may register
+    /// others, which are called from the next event on.
+    pub fn on_event(&self, handler: impl FnMut(UiEvent<G>) + Send + 'static) {
+        self.subscribers.on_event(handler);
+    }
+
+    /// Get the room's details as the host last published them, see [GameRoom::set_meta].
+    ///
+    /// Returns `None` if they have not synced yet.
+    pub async fn get_meta(&self) -> Result<Option<RoomMeta>> {
+        self.state.get_meta().await
+    }
+
+    /// Replace the room's details shown in lobbies. Only the host can do this.
+    ///
+    /// Every peer receives the new details as a [UiEvent::MetaUpdated] event.
+    pub async fn set_meta(&self, meta: RoomMeta) -> Result<()> {
+        if !self.is_host().await? {
+            return Err(anyhow::anyhow!(
+                "Only the host can change the room's details"
+            ));
+        }
+        self.state.set_meta(&meta).await
+    }
+
+    /// Get the turn order the host last published, for games that report a [crate::TurnManager].
+    pub async fn turns(&self) -> Result<Option<TurnManager>> {
+        self.state.get_turns().await
+    }
+
+    /// Get the actions the host applied with log sequence numbers in `range`, in the order
+    /// they were applied, e.g. `room.get_action_log(..)` for the whole game.
+    ///
+    /// Rejected actions are not logged. Entries that have not synced yet are missing.
+    pub async fn get_action_log(
+        &self,
+        range: impl RangeBounds<u64>,
+    ) -> Result<Vec<AppliedAction<G::GameAction>>> {
+        self.state.get_action_log(range).await
+    }
+
+    /// Get how each player has taken part in the current game so far, or in the last one
+    /// played: actions, think time, chat messages and disconnects.
+    ///
+    /// The same figures are recorded in the [GameResult] when the game finishes.
+    pub async fn stats(&self) -> Result<MatchStats> {
+        self.state.get_match_stats().await
+    }
+
+    /// Get the ticket for the room the host created for the next game, if it has rehosted.
+    ///
+    /// Peers who were offline when [UiEvent::Rehosted] was sent can use this to catch up.
+    pub async fn get_next_room(&self) -> Result<Option<RoomTicket>> {
+        self.state.get_next_room().await
+    }
+
+    /// Send one player a payload only they may see, e.g. the hand of cards they were dealt or
+    /// their secret role.
+    ///
+    /// Only the host can send private payloads. The payload is sealed to the key of the
+    /// endpoint the player is on, so although every peer syncs it, only that endpoint can open
+    /// it and receives it as [UiEvent::PrivateState]. A new payload replaces the last one sent
+    /// to the same player.
+    pub async fn send_private_state(
+        &self,
+        to: &EndpointId,
+        payload: impl AsRef<[u8]>,
+    ) -> Result<()> {
+        if !self.is_host().await? {
+            return Err(anyhow::anyhow!("Only the host can send private payloads"));
+        }
+        self.state.set_private_state(to, payload.as_ref()).await
+    }
+
+    /// Get the latest private payload the host sent this endpoint's player, if any.
+    pub async fn get_private_state(&self) -> Result<Option<Bytes>> {
+        self.state.get_private_state(&self.id()).await
+    }
+
+    /// Publish an asset, e.g. a custom map or a mod, for every peer to fetch from this host,
+    /// replacing any asset published under the same name.
+    ///
+    /// Only the host can publish assets. The bytes are kept in this node's blob store and
+    /// listed in the room's manifest, see [GameRoom::assets], and peers are told with
+    /// [UiEvent::AssetAvailable].
+    pub async fn publish_asset(&self, name: &str, bytes: impl AsRef<[u8]>) -> Result<Asset> {
+        if !self.is_host().await? {
+            return Err(anyhow::anyhow!("Only the host can publish assets"));
+        }
+        self.state.set_asset(name, bytes.as_ref()).await
+    }
+
+    /// Get the assets the host has published, ordered by name.
+    pub async fn assets(&self) -> Result<Vec<Asset>> {
+        self.state.get_assets().await
+    }
+
+    /// Get an asset's bytes, downloading it from the host the first time.
+    pub async fn fetch_asset(&self, name: &str) -> Result<Bytes> {
+        let Some(asset) = self.state.get_asset(name).await? else {
+            return Err(anyhow::anyhow!("No asset named '{name}'"));
+        };
+        self.state.fetch_asset(&asset).await
+    }
+
+    /// Send a message on the room's real-time channel, e.g. a drag preview or a cursor position.
+    ///
+    /// Real-time messages skip the room's documents: they are not stored, not ordered, and
+    /// may be dropped, and only peers connected at the time receive them, as a
+    /// [UiEvent::Realtime]. Keep them small, a few KiB at most, and use actions for anything
+    /// that has to arrive.
+    pub async fn send_realtime(&self, bytes: impl Into<Bytes>) -> Result<()> {
+        self.broadcast_realtime(RealtimePayload::Game(bytes.into()))
+            .await
+    }
+
+    /// Broadcast one of the game's own messages, e.g. an emote or a hint, to the room's peers.
+    ///
+    /// Any peer may broadcast, and peers receive the message as [UiEvent::GameMessage]. Like
+    /// [GameRoom::send_realtime], it travels on the real-time channel rather than the
+    /// documents, so it never changes the game state and may be dropped, but it is packed like
+    /// the room's values, so it is encrypted in a room with a secret.
+    pub async fn broadcast_game_message(&self, msg: &G::GameMessage) -> Result<()> {
+        let bytes = self
+            .state
+            .pack(GAME_MESSAGE_KEY, &self.state.encode(msg)?)?;
+        self.broadcast_realtime(RealtimePayload::GameMessage(bytes.into()))
+            .await
+    }
+
+    /// Tell the room's peers whether this player is typing a chat message, so chat UIs can
+    /// show "Alice is typing…".
+    ///
+    /// The indicator travels on the real-time channel, not the documents, and peers receive it
+    /// as [UiEvent::PeerTyping]. Each peer stops showing it after a few seconds without another
+    /// `set_typing(true)`, so call it again while the player keeps typing.
+    pub async fn set_typing(&self, typing: bool) -> Result<()> {
+        self.broadcast_realtime(RealtimePayload::Typing(typing))
+            .await
+    }
+
+    /// Sign a payload and send it on the room's real-time channel.
+    async fn broadcast_realtime(&self, payload: RealtimePayload) -> Result<()> {
+        let Some(sender) = &self.realtime else {
+            return Err(anyhow::anyhow!(
+                "Real-time messages are disabled for this room"
+            ));
+        };
+        let message = self.state.realtime_message(payload)?;
+        sender
+            .broadcast(postcard::to_stdvec(&message)?.into())
+            .await?;
+        Ok(())
+    }
+
+    /// Get the connection quality to each peer in the room that this endpoint is connected to.
+    pub async fn network_stats(&self) -> Result<Vec<PeerNetworkStats>> {
+        self.state.network_stats().await
+    }
+
+    /// Get how this endpoint reaches a peer in the room: its [PeerNetworkStats], including
+    /// whether traffic goes direct or through a relay and when data last arrived from it, and
+    /// the peer's relay and addresses.
+    pub async fn connection_info(&self, peer_id: &EndpointId) -> Result<PeerConnectionInfo> {
+        self.state.connection_info(peer_id).await
+    }
+
+    /// Open a raw bidirectional QUIC stream to the endpoint a peer in the room is on, for a
+    /// protocol of the game's own, e.g. voice or a bulk transfer.
+    ///
+    /// The stream runs over this node's endpoint, on the ALPN registered for `alpn_suffix`,
+    /// which the peer must accept, see [crate::IrohConfig::stream_protocol]. The engine never
+    /// touches the stream's bytes, and the peer only hears of it once something is written.
+    pub async fn open_stream(&self, peer_id: &EndpointId, alpn_suffix: &str) -> Result<PeerStream> {
+        self.state.open_stream(peer_id, alpn_suffix).await
+    }
+
+    /// Receive the streams the room's peers open to this endpoint with
+    /// [GameRoom::open_stream], in place of any earlier receiver for `alpn_suffix`.
+    ///
+    /// Streams from endpoints that are not in the room are dropped.
+    pub fn accept_streams(&self, alpn_suffix: &str) -> Result<mpsc::Receiver<PeerStream>> {
+        let mut incoming = self.state.iroh()?.accept_streams(alpn_suffix)?;
+        let (tx, rx) = mpsc::channel(1);
+        let state = self.state.clone();
+        tokio::spawn(async move {
+            while let Some(stream) = incoming.recv().await {
+                if !matches!(state.get_peer_info(&stream.peer).await, Ok(Some(_))) {
+                    continue;
+                }
+                if tx.send(stream).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(rx)
+    }
+
+    /// Count the entries in the room's documents and the bytes they store.
+    pub async fn doc_stats(&self) -> Result<DocStats> {
+        self.state.doc_stats().await
+    }
+
     /// Get the latest known peer list.
     pub async fn get_peer_list(&self) -> Result<PeerMap> {
         self.state.get_peer_list().await
@@ -189,6 +747,42 @@ impl<G: GameLogic> GameRoom<G> {
         self.state.announce_presence(introduction).await
     }
 
+    /// Seat several local players on this endpoint, e.g. for hot-seat play on one screen.
+    ///
+    /// The first profile is announced as this endpoint's own player and the rest join as seats
+    /// sharing its connection. Returns every player's ID in the order given, so the first is
+    /// always [GameRoom::id]. Seat IDs are stable for a given endpoint, so rejoining with the
+    /// same store seats the same players again.
+    pub async fn announce_presence_multi<I, P>(&self, introductions: I) -> Result<Vec<EndpointId>>
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PeerProfile>,
+    {
+        let mut introductions = introductions.into_iter();
+        let Some(own) = introductions.next() else {
+            return Err(anyhow::anyhow!("At least one player is needed"));
+        };
+        self.announce_presence(own).await?;
+        let mut seats = vec![self.id()];
+        for (index, introduction) in (1..).zip(introductions) {
+            seats.push(self.state.announce_seat(index, introduction).await?);
+        }
+        Ok(seats)
+    }
+
+    /// Get this endpoint's own player followed by every seat the host has registered for it.
+    pub async fn seats(&self) -> Result<Vec<EndpointId>> {
+        let mut seats = vec![self.id()];
+        for index in 1.. {
+            let seat = self.state.seat_id(index)?;
+            if !self.state.is_local_player(&seat).await? {
+                break;
+            }
+            seats.push(seat);
+        }
+        Ok(seats)
+    }
+
     /// Announce this peer's profile and enter the lobby as not ready.
     ///
     /// This is the default lobby path for interactive clients. New peers start
@@ -203,14 +797,244 @@ impl<G: GameLogic> GameRoom<G> {
         self.state.set_peer_ready(&self.id(), ready).await
     }
 
+    /// Update the lobby readiness of one of this endpoint's seats.
+    pub async fn set_ready_as(&self, seat: &EndpointId, ready: bool) -> Result<()> {
+        if !self.state.is_local_player(seat).await? {
+            return Err(anyhow::anyhow!(
+                "Seat {seat} is not seated on this endpoint"
+            ));
+        }
+        self.state.set_peer_ready(seat, ready).await
+    }
+
+    /// Save the current game state under `label`, so the game can be rolled back to it.
+    ///
+    /// Only the host can save checkpoints. Saving under a label again replaces the checkpoint.
+    pub async fn checkpoint(&self, label: &str) -> Result<()> {
+        if !self.is_host().await? {
+            return Err(anyhow::anyhow!("Only the host can save checkpoints"));
+        }
+        self.state.save_checkpoint(label).await
+    }
+
+    /// Restore the game state saved under `label`, e.g. to undo the last round.
+    ///
+    /// Only the host can roll back, and only while a game is running. Every peer receives a
+    /// [UiEvent::RolledBack] event along with the restored state.
+    pub async fn rollback(&self, label: &str) -> Result<()> {
+        if !self.is_host().await? {
+            return Err(anyhow::anyhow!("Only the host can roll back the game"));
+        }
+        if !matches!(
+            self.get_app_state().await?,
+            AppState::InGame | AppState::Custom(_) | AppState::Paused
+        ) {
+            return Err(anyhow::anyhow!("Only a running game can be rolled back"));
+        }
+        if self.state.is_lockstep() {
+            return Err(anyhow::anyhow!("Lock-step games cannot be rolled back"));
+        }
+        self.state.restore_checkpoint(label).await
+    }
+
+    /// Propose a change to the running game, returning the proposal's ID.
+    ///
+    /// Every other player is sent a [UiEvent::ProposalMade] and answers with
+    /// [GameRoom::respond]. Once they have all accepted, or anyone has refused, the host
+    /// resolves the proposal, applying it if it was accepted, and everyone receives a
+    /// [UiEvent::ProposalResolved]. Only players can make proposals.
+    pub async fn propose(&self, proposal: Proposal) -> Result<String> {
+        if !self.get_app_state().await?.is_in_game() {
+            return Err(anyhow::anyhow!("Proposals can only be made during a game"));
+        }
+        if !self.state.has_player_on(&self.id()).await? {
+            return Err(anyhow::anyhow!("Only players can make proposals"));
+        }
+        if self.state.is_lockstep() && proposal != Proposal::Draw {
+            return Err(anyhow::anyhow!(
+                "Only draws can be proposed in lock-step games"
+            ));
+        }
+        let request = ProposalRequest::new(self.id(), proposal)?;
+        self.state.write_proposal(&request).await?;
+        Ok(request.id)
+    }
+
+    /// Accept or refuse another player's proposal.
+    pub async fn respond(&self, proposal_id: &str, accept: bool) -> Result<()> {
+        self.state.vote_on_proposal(proposal_id, accept).await
+    }
+
+    /// Put a decision about the room to a vote, returning the vote's ID.
+    ///
+    /// Every peer is sent a [UiEvent::VoteCalled] and answers with [GameRoom::cast_vote];
+    /// calling the vote counts as voting for it. Once more than half of the online peers
+    /// have voted for it, or at least half against it, the host carries out the decision if
+    /// it passed and everyone receives a [UiEvent::VoteResult]. A peer being kicked has no
+    /// say, and the host cannot be kicked.
+    pub async fn call_vote(&self, kind: VoteKind) -> Result<String> {
+        if self.state.get_peer_info(&self.id()).await?.is_none() {
+            return Err(anyhow::anyhow!("Only peers in the room can call votes"));
+        }
+        match &kind {
+            VoteKind::Kick(peer) if *peer == self.id() => {
+                return Err(anyhow::anyhow!("Peers cannot vote to kick themselves"));
+            }
+            VoteKind::Kick(peer) if self.state.is_peer_host(peer).await? => {
+                return Err(anyhow::anyhow!("The host cannot be kicked"));
+            }
+            VoteKind::Kick(peer) if self.state.get_peer_info(peer).await?.is_none() => {
+                return Err(anyhow::anyhow!("Peer {peer} is not in the room"));
+            }
+            VoteKind::Restart if !self.get_app_state().await?.is_in_game() => {
+                return Err(anyhow::anyhow!("Only a running game can be restarted"));
+            }
+            VoteKind::Restart if self.state.is_lockstep() => {
+                return Err(anyhow::anyhow!("Lock-step games cannot be restarted"));
+            }
+            _ => {}
+        }
+        let call = VoteCall::new(self.id(), kind)?;
+        self.state.write_vote_call(&call).await?;
+        self.state.cast_ballot(&call.id, true).await?;
+        Ok(call.id)
+    }
+
+    /// Vote for or against a decision another peer put to a vote.
+    pub async fn cast_vote(&self, vote_id: &str, yes: bool) -> Result<()> {
+        self.state.cast_ballot(vote_id, yes).await
+    }
+
+    /// Move a peer onto a team, or take them off their team with `None`.
+    ///
+    /// Only the host can change teams. Teams picked in the lobby are kept when the game
+    /// starts, unless [GameLogic::assign_teams] decides otherwise.
+    pub async fn set_team(&self, peer_id: &EndpointId, team: Option<TeamId>) -> Result<()> {
+        if !self.is_host().await? {
+            return Err(anyhow::anyhow!("Only the host can change teams"));
+        }
+        self.state.set_peer_team(peer_id, team).await
+    }
+
+    /// Give an observer a place as a player.
+    ///
+    /// Only the host can change who plays. In the lobby the game's [GameLogic::assign_roles]
+    /// is run again for the new line-up, and the promotion is refused if it fails.
+    pub async fn promote_to_player(&self, peer_id: &EndpointId) -> Result<()> {
+        self.set_observer(peer_id, false).await
+    }
+
+    /// Take a player's place away, leaving them in the room as an observer.
+    ///
+    /// Host only, and refused like [GameRoom::promote_to_player] if the roles don't fit.
+    pub async fn demote_to_observer(&self, peer_id: &EndpointId) -> Result<()> {
+        self.set_observer(peer_id, true).await
+    }
+
+    /// Ask the host for a place as a player, e.g. to join the next game after watching.
+    ///
+    /// The host is told with a [UiEvent::SeatRequested] event and decides whether to call
+    /// [GameRoom::promote_to_player].
+    pub async fn request_seat(&self) -> Result<()> {
+        match self.state.get_peer_info(&self.id()).await? {
+            Some(peer) if !peer.is_observer => Err(anyhow::anyhow!("Peer is already a player")),
+            Some(_) => self.state.request_to_play().await,
+            None => Err(anyhow::anyhow!("Peer has not joined the room")),
+        }
+    }
+
+    /// Change a peer's observer flag on behalf of the host.
+    async fn set_observer(&self, peer_id: &EndpointId, is_observer: bool) -> Result<()> {
+        if !self.is_host().await? {
+            return Err(anyhow::anyhow!("Only the host can change who plays"));
+        }
+        let mut players = self.get_peer_list().await?;
+        let Some(peer) = players.get_mut(peer_id) else {
+            return Err(anyhow::anyhow!("Peer has not joined the room"));
+        };
+        peer.is_observer = is_observer;
+        if self.get_app_state().await? == AppState::Lobby {
+            self.logic.assign_roles(&players)?;
+        }
+        self.state.set_peer_observer(peer_id, is_observer).await
+    }
+
+    /// Share an avatar image with the room.
+    ///
+    /// The image is stored in this node's blob store and its hash is added to our profile,
+    /// so it must be called after announcing presence. Images larger than [MAX_AVATAR_BYTES]
+    /// are refused.
+    pub async fn set_avatar(&self, image: impl Into<Bytes>) -> Result<()> {
+        self.state.set_avatar(image).await
+    }
+
+    /// Get a peer's avatar image, fetching it from their blob store if it is not cached yet.
+    pub async fn fetch_avatar(&self, peer_id: &EndpointId) -> Result<Option<Bytes>> {
+        self.state.fetch_avatar(peer_id).await
+    }
+
     /// Send a chat message to room participants.
+    ///
+    /// Peers only show chat from those in the peer list, so announce presence first.
+    /// Returns an error if the game's chat filter blocks this peer from talking.
     pub async fn send_chat(&self, message: &str) -> Result<()> {
-        self.state.send_chat(message).await
+        self.state.send_chat(&self.logic, message).await
+    }
+
+    /// Replace the text of one of this peer's chat messages.
+    pub async fn edit_chat(&self, message_id: &str, new_text: &str) -> Result<()> {
+        self.state
+            .edit_chat(&self.logic, message_id, new_text)
+            .await
+    }
+
+    /// Delete one of this peer's chat messages.
+    pub async fn delete_chat(&self, message_id: &str) -> Result<()> {
+        self.state.delete_chat(message_id).await
+    }
+
+    /// React to a chat message, typically with a single emoji.
+    pub async fn react(&self, message_id: &str, emoji: &str) -> Result<()> {
+        self.state.react_to_chat(message_id, emoji).await
+    }
+
+    /// Get every reaction left on a chat message.
+    pub async fn get_chat_reactions(&self, message_id: &str) -> Result<Vec<ChatReaction>> {
+        self.state.get_chat_reactions(message_id).await
     }
 
     /// Get persisted chat messages for this room, ordered oldest to newest.
     pub async fn get_chat_history(&self) -> Result<Vec<ChatMessage>> {
-        self.state.get_chat_history().await
+        self.state.get_chat_history(&self.logic).await
+    }
+
+    /// Attach a short annotation to an action, e.g. "!?" for post-game review.
+    ///
+    /// The action is identified by the `action_id` reported when the host accepts or rejects it.
+    /// Annotations are limited to [MAX_ANNOTATION_LEN] characters.
+    pub async fn annotate_action(&self, action_id: &str, text: &str) -> Result<()> {
+        self.state.annotate_action(action_id, text).await
+    }
+
+    /// Get persisted annotations, optionally filtered to one action, ordered oldest to newest.
+    pub async fn get_annotations(&self, action_id: Option<&str>) -> Result<Vec<Annotation>> {
+        self.state.get_annotations(action_id).await
+    }
+
+    /// Write a game-defined entry straight to the room document, bypassing the host.
+    ///
+    /// This is an escape hatch for data the engine has no model for, e.g. shared
+    /// settings written by an extension. Keys the engine uses are refused, see
+    /// [is_engine_key]. Any peer can overwrite any custom entry.
+    pub async fn set_custom_entry(&self, key: &[u8], value: impl Into<Bytes>) -> Result<()> {
+        self.state.set_custom(key, &value.into()).await
+    }
+
+    /// Get the latest value of a game-defined entry written with [GameRoom::set_custom_entry].
+    ///
+    /// Returns `None` until the entry and its content have synced.
+    pub async fn get_custom_entry(&self, key: &[u8]) -> Result<Option<Bytes>> {
+        self.state.get_custom(key).await
     }
 
     /// Submit a game action for the host to validate and apply.
@@ -218,9 +1042,64 @@ impl<G: GameLogic> GameRoom<G> {
     /// This performs local lifecycle checks before publishing the request so UI
     /// callers get immediate feedback for obviously invalid states. The host
     /// still performs authoritative validation when the request is processed.
-    pub async fn submit_action(&self, action: G::GameAction) -> Result<()> {
+    ///
+    /// Returns the action's sequence number, as echoed in [UiEvent::ActionAccepted].
+    ///
+    /// With [RoomConfig::predict] enabled, a peer other than the host also applies the action to
+    /// its own view of the game straight away.
+    ///
+    /// In a [RoomConfig::lockstep] room the action is applied straight away instead, and sent
+    /// to the other player as the game's next step; the returned number is the step's. An
+    /// action the game rejects is refused with an error, and one that loses its step to the
+    /// other player's is reported as a [UiEvent::ActionRejected].
+    pub async fn submit_action(&self, action: G::GameAction) -> Result<u64> {
+        self.submit_action_as(&self.id(), action).await
+    }
+
+    /// Submit a game action on behalf of one of this endpoint's seats.
+    ///
+    /// Behaves like [GameRoom::submit_action], with the action applied as the given seat.
+    pub async fn submit_action_as(&self, seat: &EndpointId, action: G::GameAction) -> Result<u64> {
+        self.check_can_act(seat).await?;
+        if self.state.is_lockstep() {
+            return self.state.take_step(&self.logic, seat, action).await;
+        }
+        if !self.config.predict || self.is_host().await? {
+            return self.state.submit_action(seat, action).await;
+        }
+        let seq = self.state.submit_action(seat, action.clone()).await?;
+        self.state.predict(&self.logic, seat, seq, action).await?;
+        Ok(seq)
+    }
+
+    /// Commit to a game action without showing it to anyone, returning the commitment's number.
+    ///
+    /// Only a salted hash of the action is written, and peers see a [UiEvent::ActionCommitted].
+    /// Once every player in the game has made a commitment with the same number, the action is
+    /// submitted by itself, and the host rejects it if it does not match. Use it with
+    /// [RoomConfig::rounds] for secret simultaneous choices, e.g. rock-paper-scissors, so no
+    /// player can wait for the others' choices before making their own.
+    pub async fn commit_action(&self, action: G::GameAction) -> Result<u64> {
+        self.commit_action_as(&self.id(), action).await
+    }
+
+    /// Commit to a game action on behalf of one of this endpoint's seats.
+    ///
+    /// Behaves like [GameRoom::commit_action], with the action revealed as the given seat.
+    pub async fn commit_action_as(&self, seat: &EndpointId, action: G::GameAction) -> Result<u64> {
+        self.check_can_act(seat).await?;
+        if self.state.is_lockstep() {
+            return Err(anyhow::anyhow!(
+                "Actions cannot be committed to in a lock-step room"
+            ));
+        }
+        self.state.commit_action(seat, action).await
+    }
+
+    /// Check that one of this endpoint's seats may act in the current game.
+    async fn check_can_act(&self, seat: &EndpointId) -> Result<()> {
         match self.get_app_state().await? {
-            AppState::InGame => {}
+            AppState::InGame | AppState::Custom(_) => {}
             AppState::Lobby => return Err(anyhow::anyhow!("Cannot submit action from lobby")),
             AppState::Paused => return Err(anyhow::anyhow!("Cannot submit action while paused")),
             AppState::Finished => {
@@ -230,15 +1109,22 @@ impl<G: GameLogic> GameRoom<G> {
             }
         }
 
-        match self.state.get_peer_info(&self.id()).await? {
-            Some(peer) if peer.is_observer => {
-                return Err(anyhow::anyhow!("Peer is an observer"));
-            }
-            Some(_) => {}
-            None => return Err(anyhow::anyhow!("Peer has not joined the room")),
+        if !self.state.is_local_player(seat).await? {
+            return Err(anyhow::anyhow!(
+                "Seat {seat} is not seated on this endpoint"
+            ));
+        }
+        match self.state.get_peer_info(seat).await? {
+            Some(peer) if peer.is_observer => Err(anyhow::anyhow!("Peer is an observer")),
+            Some(_) => Ok(()),
+            None => Err(anyhow::anyhow!("Peer has not joined the room")),
         }
+    }
 
-        self.state.submit_action(action).await
+    /// Get this peer's predicted view of the game, while any of its own actions are still
+    /// waiting for the host. See [RoomConfig::predict].
+    pub fn get_predicted_game_state(&self) -> Option<G::GameState> {
+        self.state.predicted_game_state()
     }
 
     /// Announce that this peer has forfeited active play.
@@ -247,7 +1133,35 @@ impl<G: GameLogic> GameRoom<G> {
     }
 
     /// Announce that this peer is leaving the room, then drop it.
-    pub async fn announce_leave(self, reason: &LeaveReason<G>) -> Result<()> {
+    ///
+    /// Returns whether another peer synced the announcement before giving up on it. When it
+    /// did not, the others only notice the leave once this peer's heartbeats stop.
+    pub async fn announce_leave(self, reason: &LeaveReason<G>) -> Result<bool> {
         self.state.announce_leave(reason).await
     }
+
+    /// Leave the room gracefully.
+    ///
+    /// This announces the leave reason, waits for the announcement to sync with
+    /// the other peers, and stops the event loop and document sync. When
+    /// `shutdown_node` is true the owned Iroh node is shut down before
+    /// returning, unless a room created with [GameRoom::rehost_from] still
+    /// runs on it; otherwise it is released in the background once the room is
+    /// dropped.
+    ///
+    /// Returns whether another peer synced the announcement before giving up on it, see
+    /// [GameRoom::announce_leave].
+    pub async fn leave(mut self, reason: &LeaveReason<G>, shutdown_node: bool) -> Result<bool> {
+        let synced = self.state.announce_leave(reason).await?;
+        if let Some(handle) = self.event_handle.take() {
+            handle.abort();
+            handle.await.ok();
+        }
+        if shutdown_node {
+            self.state.shutdown().await?;
+        } else {
+            self.state.stop_sync().await?;
+        }
+        Ok(synced)
+    }
 }
@@ -0,0 +1,55 @@
+//! Action annotations
+//!
+//! This module contains the `Annotation` struct, which represents a short comment attached by a peer
+//! to a specific action in the game log, e.g. "!?" or "missed the fork here" for post-game review.
+
+use std::fmt::Display;
+
+use anyhow::{Result, anyhow};
+use iroh::EndpointId;
+use serde::{Deserialize, Serialize};
+
+/// Longest annotation text accepted, in characters.
+pub const MAX_ANNOTATION_LEN: usize = 280;
+
+/// A short comment attached by a peer to an action, identified by the action's ID.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Annotation {
+    /// The ID of the action this annotation refers to.
+    pub action_id: String,
+    /// The ID of the peer who wrote this annotation.
+    pub from: EndpointId,
+    /// The content of the annotation.
+    pub text: String,
+    /// The timestamp for when this annotation was created, represented as milliseconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+impl Annotation {
+    /// Create a new annotation for the given action, with the current timestamp.
+    pub fn new(from: EndpointId, action_id: &str, text: &str) -> Result<Self> {
+        if text.chars().count() > MAX_ANNOTATION_LEN {
+            return Err(anyhow!(
+                "Annotation is longer than {MAX_ANNOTATION_LEN} characters"
+            ));
+        }
+        if action_id.is_empty() || action_id.contains('.') {
+            return Err(anyhow!("Invalid action id '{action_id}'"));
+        }
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_millis() as u64;
+        Ok(Self {
+            action_id: action_id.to_string(),
+            from,
+            text: text.to_string(),
+            timestamp,
+        })
+    }
+}
+
+impl Display for Annotation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] {}: {}", self.action_id, self.from, self.text)
+    }
+}
@@ -0,0 +1,143 @@
+//! Structured room configuration
+//!
+//! This module contains the `GameRoomBuilder`, which collects everything needed to create, join
+//! or resume a room, so new options can be added without changing the signature of every
+//! constructor.
+
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::{Result, anyhow};
+use tokio::sync::mpsc;
+
+use super::{CompactionPolicy, HeartbeatPolicy, InactivityPolicy, RoomConfig, UiEvent};
+use crate::{GameLogic, GameRoom, IrohConfig, PeerProfile};
+
+/// Builds a [GameRoom], see [GameRoom::builder].
+///
+/// Anything not set falls back to the defaults of [GameRoom::create] and [GameRoom::join].
+pub struct GameRoomBuilder<G: GameLogic> {
+    logic: G,
+    store_path: Option<PathBuf>,
+    name: Option<String>,
+    profile: Option<PeerProfile>,
+    config: RoomConfig,
+}
+
+impl<G: GameLogic> GameRoom<G> {
+    /// Start building a room for `logic`, to be created, joined or resumed.
+    pub fn builder(logic: G) -> GameRoomBuilder<G> {
+        GameRoomBuilder {
+            logic,
+            store_path: None,
+            name: None,
+            profile: None,
+            config: RoomConfig::default(),
+        }
+    }
+}
+
+impl<G: GameLogic> GameRoomBuilder<G> {
+    /// Keep the room's node, identity and documents in `store_path` rather than in memory.
+    pub fn persistent(mut self, store_path: PathBuf) -> Self {
+        self.store_path = Some(store_path);
+        self
+    }
+
+    /// Name the room, when this peer creates it. Defaults to [GameLogic::game_name].
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Announce this peer under `nickname` as soon as the room is open.
+    pub fn nickname(self, nickname: &str) -> Self {
+        self.profile(nickname)
+    }
+
+    /// Announce this peer with `profile` as soon as the room is open.
+    pub fn profile(mut self, profile: impl Into<PeerProfile>) -> Self {
+        self.profile = Some(profile.into());
+        self
+    }
+
+    /// Replace every room setting at once.
+    pub fn config(mut self, config: RoomConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Set the inactivity policy the host applies while the game is in progress.
+    pub fn inactivity_policy(mut self, policy: InactivityPolicy) -> Self {
+        self.config = self.config.inactivity(policy);
+        self
+    }
+
+    /// Send heartbeats, and mark peers offline after missed beats while hosting.
+    pub fn heartbeat_policy(mut self, policy: HeartbeatPolicy) -> Self {
+        self.config = self.config.heartbeat(policy);
+        self
+    }
+
+    /// Trim old chat and action history while hosting.
+    pub fn compaction_policy(mut self, policy: CompactionPolicy) -> Self {
+        self.config = self.config.compaction(policy);
+        self
+    }
+
+    /// Let the host advance timed games with [GameLogic::tick] at this interval.
+    pub fn tick(mut self, interval: Duration) -> Self {
+        self.config = self.config.tick(interval);
+        self
+    }
+
+    /// Let the application's receivers hold `capacity` events, see [RoomConfig::channel_capacity].
+    pub fn channel_capacity(mut self, capacity: usize) -> Self {
+        self.config = self.config.channel_capacity(capacity);
+        self
+    }
+
+    /// Set the relay, discovery and socket settings for the room's node.
+    pub fn network(mut self, network: IrohConfig) -> Self {
+        self.config = self.config.network(network);
+        self
+    }
+
+    /// Create a new room, hosted by this peer.
+    pub async fn create(self) -> Result<(GameRoom<G>, mpsc::Receiver<UiEvent<G>>)> {
+        let (room, events) = GameRoom::create_with_config(
+            self.logic,
+            self.store_path,
+            self.name.as_deref(),
+            self.config,
+        )
+        .await?;
+        announce(&room, self.profile).await?;
+        Ok((room, events))
+    }
+
+    /// Join the room with the given ticket.
+    pub async fn join(self, ticket: &str) -> Result<(GameRoom<G>, mpsc::Receiver<UiEvent<G>>)> {
+        let (room, events) =
+            GameRoom::join_with_config(self.logic, ticket, self.store_path, self.config).await?;
+        announce(&room, self.profile).await?;
+        Ok((room, events))
+    }
+
+    /// Re-open the last room used with the persistent store, see [GameRoom::resume_with_config].
+    ///
+    /// The room already knows this peer, so no profile is announced.
+    pub async fn resume(self) -> Result<(GameRoom<G>, mpsc::Receiver<UiEvent<G>>)> {
+        let store_path = self
+            .store_path
+            .ok_or_else(|| anyhow!("Only a persistent room can be resumed"))?;
+        GameRoom::resume_with_config(self.logic, store_path, self.config).await
+    }
+}
+
+/// Introduce this peer to a freshly opened room, if it was given a profile.
+async fn announce<G: GameLogic>(room: &GameRoom<G>, profile: Option<PeerProfile>) -> Result<()> {
+    match profile {
+        Some(profile) => room.announce_presence(profile).await,
+        None => Ok(()),
+    }
+}
@@ -1,36 +1,55 @@
 //! Chat messages
 //!
 //! This module contains the `ChatMessage` struct, which represents a chat message sent by a peer in the game room.
-//! It includes the sender's endpoint ID, the message content, and a timestamp for when the message was created.
+//! It includes a stable message ID, the sender's endpoint ID, the message content, and a timestamp for when the
+//! message was created. It also contains the `ChatReaction` struct and the `ChatUpdate` enum, which describe
+//! reactions, edits and deletions applied to messages after they were sent.
 
 use std::fmt::Display;
 
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use iroh::EndpointId;
 use serde::{Deserialize, Serialize};
 
+/// Longest reaction accepted, in characters.
+const MAX_REACTION_LEN: usize = 16;
+
 /// A chat message sent by a peer in the game room, containing the sender's endpoint ID, the message content,
 /// and a timestamp for when the message was created.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct ChatMessage {
+    /// A stable ID for this message, used to react to, edit or delete it.
+    pub id: String,
     /// The ID of the peer who sent this message.
     pub from: EndpointId,
     /// The content of the message.
     pub message: String,
     /// The timestamp for when this message was created, represented as milliseconds since the Unix epoch.
     pub timestamp: u64,
+    /// The room's logical clock when this message was sent, which orders it the same way on
+    /// every peer. Edits keep the original reading, so an edited message stays where it was.
+    pub clock: u64,
+    /// Whether the sender has edited this message since it was sent.
+    pub edited: bool,
+    /// Whether the sender has deleted this message.
+    pub deleted: bool,
 }
 
 impl ChatMessage {
     /// Create a new chat message from the given sender and message content, with the current timestamp.
+    ///
+    /// The logical clock starts at the timestamp, and the room stamps its own reading when the
+    /// message is sent.
     pub fn new(from: EndpointId, message: &str) -> Result<Self> {
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)?
-            .as_millis() as u64;
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
         Ok(Self {
+            id: now.as_nanos().to_string(),
             from,
             message: message.to_string(),
-            timestamp,
+            timestamp: now.as_millis() as u64,
+            clock: now.as_millis() as u64,
+            edited: false,
+            deleted: false,
         })
     }
 
@@ -45,3 +64,39 @@ impl Display for ChatMessage {
         write!(f, "[{}] {}: {}", self.timestamp, self.from, self.message)
     }
 }
+
+/// A reaction left by a peer on a chat message.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChatReaction {
+    /// The ID of the message being reacted to.
+    pub message_id: String,
+    /// The ID of the peer who reacted.
+    pub from: EndpointId,
+    /// The reaction, typically a single emoji.
+    pub emoji: String,
+}
+
+impl ChatReaction {
+    /// Create a new reaction, checking that it is short enough to store in a document key.
+    pub fn new(from: EndpointId, message_id: &str, emoji: &str) -> Result<Self> {
+        if emoji.is_empty() || emoji.chars().count() > MAX_REACTION_LEN || emoji.contains('.') {
+            return Err(anyhow!("Invalid reaction '{emoji}'"));
+        }
+        Ok(Self {
+            message_id: message_id.to_string(),
+            from,
+            emoji: emoji.to_string(),
+        })
+    }
+}
+
+/// A change to a chat message that has already been delivered.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum ChatUpdate {
+    /// The sender has replaced the text of a message.
+    Edited(ChatMessage),
+    /// The sender has deleted a message.
+    Deleted { id: String },
+    /// A peer has reacted to a message.
+    Reacted(ChatReaction),
+}
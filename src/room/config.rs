@@ -0,0 +1,630 @@
+//! Room configuration
+//!
+//! This module contains the `RoomConfig` struct, which collects the optional settings used when
+//! creating or joining a game room.
+
+use std::time::Duration;
+
+use super::{Compression, EventCoalescing, RoomSecret, RoomVisibility, WireFormat};
+use crate::IrohConfig;
+
+/// Game states up to this size are written straight into the room document.
+const DEFAULT_STATE_BLOB_THRESHOLD: usize = 16 * 1024;
+/// How many events each receiver's channel holds before the delivery stage starts queueing.
+const DEFAULT_CHANNEL_CAPACITY: usize = 32;
+/// How many events may wait for a slow receiver before the oldest are dropped.
+const DEFAULT_QUEUE_LIMIT: usize = 1024;
+/// How many recent events a room keeps for late subscribers.
+const DEFAULT_REPLAY_BUFFER: usize = 64;
+/// Peer list and game state updates arriving within this window are reported once.
+const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(25);
+/// Shortest period any of a room's timers runs at, however the room was configured.
+pub(crate) const MIN_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A timer firing every `period`, or every [MIN_INTERVAL] if that is longer, which waits a
+/// full period after a late tick rather than firing again straight away to catch up.
+pub(crate) fn timer(period: Duration) -> tokio::time::Interval {
+    let mut timer = tokio::time::interval(period.max(MIN_INTERVAL));
+    timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    timer
+}
+
+/// Optional settings applied when creating or joining a game room.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoomConfig {
+    /// Reuse the node's default document author for this room.
+    ///
+    /// With a persistent store this keeps one authorship identity across every room the
+    /// player creates or joins. Disable it to write to this room with a fresh author.
+    pub reuse_author: bool,
+    /// Finish games that have seen no actions for too long, while this peer is the host.
+    pub inactivity: Option<InactivityPolicy>,
+    /// Announce that this peer is still here, and mark silent peers offline while hosting.
+    pub heartbeat: Option<HeartbeatPolicy>,
+    /// Trim old chat and action history, while this peer is the host.
+    pub compaction: Option<CompactionPolicy>,
+    /// How often the host calls [crate::GameLogic::tick] while a game is in progress, if at all.
+    pub tick: Option<Duration>,
+    /// Send each peer the host's ticked states directly, as often as its connection keeps up
+    /// with, and only write them to the room document now and then.
+    pub state_cadence: Option<StateCadence>,
+    /// Collect every player's action for a round before applying any of them, while this peer
+    /// is the host, e.g. for rock-paper-scissors or sealed-bid auctions.
+    pub rounds: Option<RoundPolicy>,
+    /// Run the room's lobby without a host player, see [crate::GameRoom::create_dedicated].
+    pub dedicated: Option<DedicatedPolicy>,
+    /// Encoded game states larger than this many bytes are stored as blobs, which peers
+    /// fetch from the host, instead of in the room document.
+    pub state_blob_threshold: usize,
+    /// How a host compresses the room's values. Joiners use whatever the host chose.
+    pub compression: Compression,
+    /// How a host serializes the room's values, chosen like [RoomConfig::compression].
+    pub wire_format: WireFormat,
+    /// Encrypt the room's values with a key derived from this secret, when this peer creates
+    /// the room, or the secret needed to read them when it joins one.
+    ///
+    /// A joiner without it falls back on the secret in the ticket, if the invite carried one,
+    /// see [crate::RoomTicket::with_secret].
+    pub secret: Option<RoomSecret>,
+    /// How often to send [crate::UiEvent::NetworkStats], and check for a
+    /// [crate::UiEvent::ConnectionPathChanged], if at all.
+    pub network_stats: Option<Duration>,
+    /// When joining, wait for the room's state to sync before returning, and report it as a
+    /// [crate::UiEvent::InitialSnapshot] ahead of any other event.
+    ///
+    /// Without it, joining returns as soon as the room is found, and the room reports
+    /// [crate::UiEvent::Syncing] progress until the snapshot follows, e.g. for a loading screen
+    /// when joining a room with a long history.
+    pub await_initial_sync: bool,
+    /// Sync the room's chat document.
+    ///
+    /// Chat lives in its own document so it never delays game-state sync. Bots and headless
+    /// hosts can disable it to skip chat traffic entirely; a host that disables it creates
+    /// a room without chat.
+    pub chat: bool,
+    /// Join the room's real-time channel, see [crate::GameRoom::send_realtime].
+    pub realtime: bool,
+    /// Download each asset the host publishes as soon as it is announced, rather than when it
+    /// is first asked for, see [crate::GameRoom::fetch_asset].
+    ///
+    /// The [crate::UiEvent::AssetAvailable] then only arrives once the asset is stored locally.
+    pub fetch_assets: bool,
+    /// Apply this peer's own actions locally as soon as they are submitted, ahead of the host.
+    ///
+    /// The predicted state arrives as a [crate::UiEvent::GameState] straight away. If the
+    /// host's state turns out different, e.g. because another player moved first, the view
+    /// is replaced with a [crate::UiEvent::PredictionCorrected].
+    pub predict: bool,
+    /// Let the two players apply each other's actions themselves, in lock-step, when this
+    /// peer creates the room. Joiners play however the host chose.
+    ///
+    /// A move then reaches the other player without a round trip through the host. Every
+    /// peer must compute the same state from the same actions, compared by
+    /// [crate::GameLogic::canonical_state], and a step that produces a different state is
+    /// reported as a [crate::UiEvent::LockstepDiverged]. The game state
+    /// only changes through the players' actions, so ticks, connection hooks, rollbacks,
+    /// takebacks and restarts are not available.
+    pub lockstep: bool,
+    /// How many actions and chat messages each peer may write, while this peer is the host.
+    pub rate_limits: RateLimits,
+    /// Who the room is advertised to, recorded in the tickets this peer hands out.
+    pub visibility: RoomVisibility,
+    /// How events are coalesced while the application is slow to receive them.
+    pub coalescing: EventCoalescing,
+    /// How many events the application's receivers hold before the room starts queueing
+    /// and coalescing them.
+    pub channel_capacity: usize,
+    /// How many events may wait for a receiver that is slow to take them, on top of its
+    /// channel, before the oldest are dropped and the receiver is sent a
+    /// [crate::UiEvent::Lagged].
+    pub queue_limit: usize,
+    /// How many of the most recent events the room keeps, to hand to subscribers that come
+    /// late, see [crate::GameRoom::replay_recent]. Zero keeps none.
+    pub replay_buffer: usize,
+    /// How long a burst of peer or game state entries is collected before the latest peer
+    /// list and game state are reported, once each. Zero reports every update as it arrives.
+    pub debounce: Duration,
+    /// Relay, discovery and socket settings for the node the room starts.
+    ///
+    /// Ignored when the room runs on a node that is already running, e.g. after a rehost.
+    pub network: IrohConfig,
+}
+
+impl Default for RoomConfig {
+    fn default() -> Self {
+        Self {
+            reuse_author: true,
+            inactivity: None,
+            heartbeat: None,
+            compaction: None,
+            tick: None,
+            state_cadence: None,
+            rounds: None,
+            dedicated: None,
+            state_blob_threshold: DEFAULT_STATE_BLOB_THRESHOLD,
+            compression: Compression::None,
+            wire_format: WireFormat::Postcard,
+            secret: None,
+            network_stats: None,
+            await_initial_sync: true,
+            chat: true,
+            realtime: true,
+            fetch_assets: false,
+            predict: false,
+            lockstep: false,
+            rate_limits: RateLimits::default(),
+            visibility: RoomVisibility::default(),
+            coalescing: EventCoalescing::default(),
+            channel_capacity: DEFAULT_CHANNEL_CAPACITY,
+            queue_limit: DEFAULT_QUEUE_LIMIT,
+            replay_buffer: DEFAULT_REPLAY_BUFFER,
+            debounce: DEFAULT_DEBOUNCE,
+            network: IrohConfig::default(),
+        }
+    }
+}
+
+/// How long a game may go without any submitted actions before the host steps in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InactivityPolicy {
+    /// Idle time after which the host warns every peer that the game will be finished.
+    pub warn_after: Duration,
+    /// Idle time after which the host finishes the game.
+    pub finish_after: Duration,
+}
+
+impl InactivityPolicy {
+    /// Finish the game after `finish_after` idle time, warning peers at `warn_after`.
+    pub fn new(warn_after: Duration, finish_after: Duration) -> Self {
+        Self {
+            warn_after: warn_after.min(finish_after),
+            finish_after,
+        }
+    }
+
+    /// How often the host should check for inactivity.
+    pub(crate) fn check_interval(&self) -> Duration {
+        self.warn_after / 4
+    }
+}
+
+/// How much chat and action history a long session keeps.
+///
+/// Entries are removed once they are older than `max_age`, or once `max_entries` newer entries
+/// of the same kind exist. Each player's latest actions and any action still waiting for the
+/// host are always kept. Chat lives in its own document and can be kept for longer or shorter
+/// than actions, see [CompactionPolicy::chat].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactionPolicy {
+    /// Age after which actions are removed.
+    pub max_age: Duration,
+    /// Number of actions to keep at most.
+    pub max_entries: usize,
+    /// Age after which chat messages are removed.
+    pub chat_max_age: Duration,
+    /// Number of chat messages to keep at most.
+    pub chat_max_entries: usize,
+    /// How often the host compacts the room.
+    pub interval: Duration,
+}
+
+impl Default for CompactionPolicy {
+    fn default() -> Self {
+        Self {
+            max_age: Duration::from_secs(60 * 60),
+            max_entries: 1000,
+            chat_max_age: Duration::from_secs(60 * 60),
+            chat_max_entries: 1000,
+            interval: Duration::from_secs(60),
+        }
+    }
+}
+
+impl CompactionPolicy {
+    /// Keep at most `max_entries` chat messages and actions, none older than `max_age`.
+    pub fn new(max_age: Duration, max_entries: usize) -> Self {
+        Self {
+            max_age,
+            max_entries,
+            chat_max_age: max_age,
+            chat_max_entries: max_entries,
+            ..Self::default()
+        }
+    }
+
+    /// Keep at most `max_entries` chat messages, none older than `max_age`, whatever is kept
+    /// of the actions.
+    pub fn chat(mut self, max_age: Duration, max_entries: usize) -> Self {
+        self.chat_max_age = max_age;
+        self.chat_max_entries = max_entries;
+        self
+    }
+
+    /// Compact the room at this interval, at least 50ms apart.
+    pub fn every(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+}
+
+/// How often peers announce that they are still in the room.
+///
+/// Gossip only reports direct neighbours going away, so a peer can be gone for minutes before
+/// anyone notices. With a heartbeat the host marks a peer offline once it has missed enough
+/// beats in a row. Peers that do not send heartbeats are never marked offline this way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeartbeatPolicy {
+    /// Time between this peer's heartbeats.
+    pub interval: Duration,
+    /// Missed beats after which the host marks a peer offline.
+    pub missed_beats: u32,
+}
+
+impl Default for HeartbeatPolicy {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(5),
+            missed_beats: 3,
+        }
+    }
+}
+
+impl HeartbeatPolicy {
+    /// Beat every `interval`, and drop peers after `missed_beats` missed beats.
+    pub fn new(interval: Duration, missed_beats: u32) -> Self {
+        Self {
+            interval,
+            missed_beats,
+        }
+    }
+
+    /// Time between this peer's heartbeats, at least [MIN_INTERVAL] like every other timer.
+    pub(crate) fn period(&self) -> Duration {
+        self.interval.max(MIN_INTERVAL)
+    }
+
+    /// How long a peer may stay silent before the host marks it offline.
+    pub(crate) fn timeout(&self) -> Duration {
+        self.period() * self.missed_beats.max(1)
+    }
+
+    /// Time between heartbeats while the app is in the background, as long as it can be
+    /// while leaving a beat's slack before the host would mark the peer offline.
+    pub(crate) fn background_interval(&self) -> Duration {
+        self.timeout()
+            .saturating_sub(self.period())
+            .max(self.period())
+    }
+}
+
+/// How a host sends the states its [crate::GameLogic::tick] produces.
+///
+/// Writing every tick to the room document makes every peer sync every tick, however slow its
+/// connection. With a cadence the host only writes a ticked state every `persist_interval`,
+/// and in between sends the latest state straight to each peer once per round trip to it, at
+/// most once a tick and at least every `max_interval`, so a slow peer is sent fewer frames
+/// rather than sent every frame and left to drop most of them. Peers whose round trip is not
+/// known yet get the slowest cadence. States produced by actions, and the state a game
+/// finishes on, are still written straight away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StateCadence {
+    /// Time between writes of ticked states to the room document.
+    pub persist_interval: Duration,
+    /// Longest time a peer goes without a ticked state in between.
+    pub max_interval: Duration,
+}
+
+impl Default for StateCadence {
+    fn default() -> Self {
+        Self {
+            persist_interval: Duration::from_secs(1),
+            max_interval: Duration::from_millis(250),
+        }
+    }
+}
+
+impl StateCadence {
+    /// Write ticked states every `persist_interval`. States are only written as the game
+    /// ticks, so an interval shorter than the tick writes every ticked state.
+    pub fn new(persist_interval: Duration) -> Self {
+        Self {
+            persist_interval,
+            ..Self::default()
+        }
+    }
+
+    /// Send each peer a ticked state at least this often. Frames are only sent as the game
+    /// ticks, so an interval shorter than the tick sends every peer every ticked state.
+    pub fn max_interval(mut self, interval: Duration) -> Self {
+        self.max_interval = interval;
+        self
+    }
+
+    /// Time between the states sent to a peer with this round trip.
+    pub(crate) fn interval_for(&self, rtt: Option<Duration>) -> Duration {
+        rtt.map_or(self.max_interval, |rtt| rtt.min(self.max_interval))
+    }
+}
+
+/// How the host collects simultaneous actions into rounds.
+///
+/// Each player's next action is held until every player has submitted one, or the deadline
+/// passes, and the round is then applied at once with [crate::GameLogic::apply_round]. A player
+/// who submits again before the round closes has that action held for the next round.
+/// Observers do not take part, and players who have not submitted when the deadline passes
+/// sit the round out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RoundPolicy {
+    /// How long after the first submission the round closes without the missing players, if
+    /// at all.
+    pub deadline: Option<Duration>,
+}
+
+impl RoundPolicy {
+    /// Wait for every player before closing a round.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Close a round this long after its first submission, whoever is still missing.
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+}
+
+/// How a dedicated host runs its room, see [crate::GameRoom::create_dedicated].
+///
+/// With nobody to press start, the host starts a game as soon as enough players are in the
+/// lobby and every one of them is ready, and can bring the room back to the lobby after each
+/// game so it keeps running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DedicatedPolicy {
+    /// How many online players must be ready before a game starts.
+    pub min_players: usize,
+    /// How long after a game finishes the room goes back to the lobby, if at all.
+    pub reopen_after: Option<Duration>,
+}
+
+impl Default for DedicatedPolicy {
+    fn default() -> Self {
+        Self {
+            min_players: 2,
+            reopen_after: None,
+        }
+    }
+}
+
+impl DedicatedPolicy {
+    /// Start a game once `min_players` online players are ready, at least one.
+    pub fn new(min_players: usize) -> Self {
+        Self {
+            min_players: min_players.max(1),
+            ..Self::default()
+        }
+    }
+
+    /// Go back to the lobby this long after each game finishes, with every player not ready.
+    pub fn reopen_after(mut self, delay: Duration) -> Self {
+        self.reopen_after = Some(delay);
+        self
+    }
+}
+
+/// A token bucket: a peer may write `burst` entries at once, and one more every `refill`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Entries a peer may write in a burst.
+    pub burst: u32,
+    /// Time it takes a peer to earn back one entry.
+    pub refill: Duration,
+}
+
+impl RateLimit {
+    /// Allow bursts of `burst` entries, and one more entry every `refill`.
+    pub fn new(burst: u32, refill: Duration) -> Self {
+        Self {
+            burst: burst.max(1),
+            refill,
+        }
+    }
+}
+
+/// How many entries of each kind a peer may write before the host drops the excess.
+///
+/// The host rejects actions over the limit and hides chat messages and reactions over it,
+/// reporting a [crate::UiEvent::RateLimited] when a peer first goes over. The host's own
+/// players are never limited. By default nothing is limited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateLimits {
+    /// Limit on action requests, per player.
+    pub actions: Option<RateLimit>,
+    /// Limit on chat messages, edits and reactions, per peer.
+    pub chat: Option<RateLimit>,
+}
+
+impl RateLimits {
+    /// Limit each player's action requests.
+    pub fn actions(mut self, limit: RateLimit) -> Self {
+        self.actions = Some(limit);
+        self
+    }
+
+    /// Limit each peer's chat messages, edits and reactions.
+    pub fn chat(mut self, limit: RateLimit) -> Self {
+        self.chat = Some(limit);
+        self
+    }
+}
+
+impl RoomConfig {
+    /// Set whether the node's default author is reused for this room.
+    pub fn reuse_author(mut self, reuse_author: bool) -> Self {
+        self.reuse_author = reuse_author;
+        self
+    }
+
+    /// Set the inactivity policy the host applies while the game is in progress.
+    pub fn inactivity(mut self, policy: InactivityPolicy) -> Self {
+        self.inactivity = Some(policy);
+        self
+    }
+
+    /// Send heartbeats, and mark peers offline after missed beats while hosting.
+    pub fn heartbeat(mut self, policy: HeartbeatPolicy) -> Self {
+        self.heartbeat = Some(policy);
+        self
+    }
+
+    /// Trim old chat and action history while hosting.
+    pub fn compaction(mut self, policy: CompactionPolicy) -> Self {
+        self.compaction = Some(policy);
+        self
+    }
+
+    /// Let the host advance timed games with [crate::GameLogic::tick] at this interval, at
+    /// least every 50ms.
+    pub fn tick(mut self, interval: Duration) -> Self {
+        self.tick = Some(interval);
+        self
+    }
+
+    /// Send ticked states to each peer at its own pace, see [RoomConfig::state_cadence].
+    pub fn state_cadence(mut self, policy: StateCadence) -> Self {
+        self.state_cadence = Some(policy);
+        self
+    }
+
+    /// Let the host collect simultaneous actions into rounds, see [RoomConfig::rounds].
+    pub fn rounds(mut self, policy: RoundPolicy) -> Self {
+        self.rounds = Some(policy);
+        self
+    }
+
+    /// Set how a dedicated host runs the room, see [crate::GameRoom::create_dedicated].
+    pub fn dedicated(mut self, policy: DedicatedPolicy) -> Self {
+        self.dedicated = Some(policy);
+        self
+    }
+
+    /// Store encoded game states larger than `bytes` as blobs rather than in the document.
+    pub fn state_blob_threshold(mut self, bytes: usize) -> Self {
+        self.state_blob_threshold = bytes;
+        self
+    }
+
+    /// Compress the room's values with `compression`, when this peer creates the room.
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Serialize the room's values in `wire_format`, when this peer creates the room, e.g.
+    /// JSON so peers written in other languages can read them.
+    pub fn wire_format(mut self, wire_format: WireFormat) -> Self {
+        self.wire_format = wire_format;
+        self
+    }
+
+    /// Encrypt the room's values with `secret`, or read an encrypted room with it.
+    pub fn secret(mut self, secret: RoomSecret) -> Self {
+        self.secret = Some(secret);
+        self
+    }
+
+    /// Report connection quality to every peer in the room, and any change in the path to
+    /// each, at this interval, at least every 50ms.
+    pub fn network_stats(mut self, interval: Duration) -> Self {
+        self.network_stats = Some(interval);
+        self
+    }
+
+    /// Set the relay, discovery and socket settings for the room's node.
+    pub fn network(mut self, network: IrohConfig) -> Self {
+        self.network = network;
+        self
+    }
+
+    /// Set who the room is advertised to.
+    pub fn visibility(mut self, visibility: RoomVisibility) -> Self {
+        self.visibility = visibility;
+        self
+    }
+
+    /// Set how events are coalesced while the application is slow to receive them.
+    pub fn coalescing(mut self, coalescing: EventCoalescing) -> Self {
+        self.coalescing = coalescing;
+        self
+    }
+
+    /// Let the application's receivers hold `capacity` events, at least one.
+    pub fn channel_capacity(mut self, capacity: usize) -> Self {
+        self.channel_capacity = capacity.max(1);
+        self
+    }
+
+    /// Let `limit` events wait for a slow receiver, at least one, before dropping the oldest.
+    pub fn queue_limit(mut self, limit: usize) -> Self {
+        self.queue_limit = limit.max(1);
+        self
+    }
+
+    /// Keep the `capacity` most recent events for subscribers that come late, or none if zero.
+    pub fn replay_buffer(mut self, capacity: usize) -> Self {
+        self.replay_buffer = capacity;
+        self
+    }
+
+    /// Collect bursts of peer and game state updates for `window` before reporting them.
+    ///
+    /// A zero window reports every update as soon as it arrives.
+    pub fn debounce(mut self, window: Duration) -> Self {
+        self.debounce = window;
+        self
+    }
+
+    /// Set whether joining waits for the room's state to sync, see
+    /// [RoomConfig::await_initial_sync].
+    pub fn await_initial_sync(mut self, await_initial_sync: bool) -> Self {
+        self.await_initial_sync = await_initial_sync;
+        self
+    }
+
+    /// Set whether this peer syncs the room's chat document.
+    pub fn chat(mut self, chat: bool) -> Self {
+        self.chat = chat;
+        self
+    }
+
+    /// Enable or disable the room's real-time channel.
+    pub fn realtime(mut self, realtime: bool) -> Self {
+        self.realtime = realtime;
+        self
+    }
+
+    /// Set whether assets are downloaded as soon as the host announces them, see
+    /// [RoomConfig::fetch_assets].
+    pub fn fetch_assets(mut self, fetch_assets: bool) -> Self {
+        self.fetch_assets = fetch_assets;
+        self
+    }
+
+    /// Enable or disable predicting this peer's own actions, see [RoomConfig::predict].
+    pub fn predict(mut self, predict: bool) -> Self {
+        self.predict = predict;
+        self
+    }
+
+    /// Set how many actions and chat messages each peer may write while this peer hosts.
+    pub fn rate_limits(mut self, limits: RateLimits) -> Self {
+        self.rate_limits = limits;
+        self
+    }
+
+    /// Enable or disable lock-step play for a room this peer creates, see
+    /// [RoomConfig::lockstep].
+    pub fn lockstep(mut self, lockstep: bool) -> Self {
+        self.lockstep = lockstep;
+        self
+    }
+}
@@ -1,43 +1,60 @@
 //! Action request handling for room events.
 
+use super::metrics;
 use crate::{
-    ActionResult, GameLogic,
-    room::state::{ActionRequest, StateData},
+    Engine, GameLogic,
+    room::state::{ActionError, ActionRequest, ActionResponse, StateData},
+    trace,
 };
 use anyhow::Result;
 use iroh::EndpointId;
 use std::sync::Arc;
 
-/// Apply a parsed action request and produce an accept/reject result.
+/// Apply a parsed action request and produce an accept/reject response.
 pub(super) async fn apply_action_request<G: GameLogic>(
     data: &StateData<G>,
     logic: &Arc<G>,
     node_id: &EndpointId,
     request: ActionRequest<G::GameAction>,
-) -> Result<ActionResult> {
+) -> Result<ActionResponse<G::GameError>> {
     let action_id = request.id;
-    let mut current_state = match data.get_game_state().await {
-        Ok(state) => state,
+    let seq = request.seq;
+    let previous = match data.get_game_state_entry().await {
+        Ok(entry) => entry,
         Err(e) => {
-            return Ok(ActionResult {
+            return Ok(ActionResponse::engine_rejection(
                 action_id,
-                accepted: false,
-                error: Some(format!("No game state available: {e}")),
-            });
+                seq,
+                format!("No game state available: {e}"),
+            ));
         }
     };
 
-    match logic.apply_action(&mut current_state, node_id, &request.action) {
-        Err(e) => Ok(ActionResult {
-            action_id,
-            accepted: false,
-            error: Some(e.to_string()),
-        }),
+    let mut engine = Engine::<G>::new(logic.clone(), previous.state.clone());
+    match engine.apply(node_id, &request.action) {
+        Err(e) => {
+            trace::debug!(peer = %node_id, seq, error = %e, "Action rejected");
+            metrics::action_rejected(data);
+            Ok(ActionResponse {
+                action_id,
+                seq,
+                error: Some(ActionError::Game(e)),
+            })
+        }
         Ok(()) => {
-            data.set_game_state(&current_state).await?;
-            Ok(ActionResult {
+            trace::debug!(peer = %node_id, seq, "Action applied");
+            metrics::action_applied(data);
+            data.set_undo_point(Some(&previous)).await?;
+            data.set_action_game_state(engine.state(), node_id, seq)
+                .await?;
+            data.log_applied_action(node_id, seq, &request.action, None)
+                .await?;
+            if let Some(outcome) = engine.outcome() {
+                data.finish_game(outcome).await?;
+            }
+            Ok(ActionResponse {
                 action_id,
-                accepted: true,
+                seq,
                 error: None,
             })
         }
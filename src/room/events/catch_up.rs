@@ -0,0 +1,65 @@
+//! Progress reporting for joiners that do not wait for the initial sync.
+//!
+//! With `RoomConfig::await_initial_sync` turned off, joining returns as soon as the room is
+//! found. The event loop then polls the room's documents, reporting `UiEvent::Syncing` as their
+//! content arrives and a `UiEvent::InitialSnapshot` once the room's state can be read.
+
+use super::ui::UiEvent;
+use crate::{GameLogic, room::state::StateData};
+use anyhow::Result;
+use std::time::Duration;
+
+/// How often a joiner checks how far the room has synced.
+pub(super) const CATCH_UP_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Tracks a joiner's progress until the room's state has synced.
+pub(super) struct CatchUp {
+    active: bool,
+    /// The last progress reported, as `(done, total)`.
+    reported: Option<(usize, usize)>,
+}
+
+impl CatchUp {
+    pub(super) fn new(active: bool) -> Self {
+        Self {
+            active,
+            reported: None,
+        }
+    }
+
+    pub(super) fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Report any progress since the last check, and the room itself once its state has synced.
+    pub(super) async fn check<G: GameLogic>(
+        &mut self,
+        data: &StateData<G>,
+    ) -> Result<Vec<UiEvent<G>>> {
+        let (entries_done, entries_total) = data.sync_progress().await?;
+        let mut events = Vec::new();
+        if self.reported != Some((entries_done, entries_total)) {
+            self.reported = Some((entries_done, entries_total));
+            events.push(UiEvent::Syncing {
+                entries_done,
+                entries_total,
+            });
+        }
+        if data.has_initial_state().await {
+            events.push(initial_snapshot(data).await?);
+            self.active = false;
+        }
+        Ok(events)
+    }
+}
+
+/// Describe the room as it stands, for a peer that has just caught up with it.
+pub(crate) async fn initial_snapshot<G: GameLogic>(data: &StateData<G>) -> Result<UiEvent<G>> {
+    let app_state = data.get_app_state().await?;
+    data.app_state_synced(app_state);
+    Ok(UiEvent::InitialSnapshot {
+        app_state,
+        peers: data.get_peer_list().await?,
+        game_state: data.get_game_state().await.ok(),
+    })
+}
@@ -0,0 +1,19 @@
+//! Host-side compaction of chat and action history.
+
+use crate::{CompactionPolicy, GameLogic, room::state::StateData};
+use anyhow::Result;
+
+/// Publish how much history peers may now remove.
+///
+/// The host cannot delete entries other peers wrote, so it only moves the mark; every peer,
+/// the host included, deletes its own entries once the mark reaches it.
+pub(super) async fn publish_compaction_mark<G: GameLogic>(
+    data: &StateData<G>,
+    policy: &CompactionPolicy,
+) -> Result<()> {
+    if !data.is_host().await? {
+        return Ok(());
+    }
+    let mark = data.compaction_mark(policy).await?;
+    data.set_compaction_mark(&mark).await
+}
@@ -1,7 +1,7 @@
 //! Peer connection and forfeit handling for room events.
 
 use super::{HostEvent, ui::UiEvent};
-use crate::{ConnectionEffect, GameLogic, PeerMap, PeerStatus, room::state::StateData};
+use crate::{ConnectionEffect, GameLogic, PeerMap, PeerStatus, room::state::StateData, trace};
 use anyhow::Result;
 use iroh::EndpointId;
 use std::sync::Arc;
@@ -9,25 +9,13 @@ use std::sync::Arc;
 /// Process a peer join event from the iroh doc.
 pub(super) async fn process_joiner<G: GameLogic>(
     id: EndpointId,
-    state_data: &Arc<StateData<G>>,
+    state_data: &StateData<G>,
     logic: &Arc<G>,
 ) -> Option<UiEvent<G>> {
+    trace::info!(peer = %id, "Peer connected");
     if state_data.is_host().await.unwrap_or_default() {
-        state_data
-            .set_peer_status(&id, PeerStatus::Online)
-            .await
-            .ok();
-
-        if let Ok(mut current_state) = state_data.get_game_state().await {
-            let mut players = state_data.get_peer_list().await.unwrap_or_default();
-            if players.contains_key(&id)
-                && let Ok(effect) =
-                    logic.handle_player_reconnect(&mut players, &id, &mut current_state)
-            {
-                persist_connection_effect(state_data, &players, &current_state, effect)
-                    .await
-                    .ok();
-            }
+        for id in with_seats(state_data, id).await {
+            reconnect_player(state_data, logic, &id).await.ok();
         }
     } else if state_data.is_peer_host(&id).await.unwrap_or_default() {
         state_data.host_online();
@@ -39,24 +27,13 @@ pub(super) async fn process_joiner<G: GameLogic>(
 /// Process a peer leave event from the iroh doc.
 pub(super) async fn process_leaver<G: GameLogic>(
     id: EndpointId,
-    state_data: &Arc<StateData<G>>,
+    state_data: &StateData<G>,
     logic: &Arc<G>,
 ) -> Option<UiEvent<G>> {
+    trace::info!(peer = %id, "Peer disconnected");
     if state_data.is_host().await.unwrap_or_default() {
-        state_data
-            .set_peer_status(&id, PeerStatus::Offline)
-            .await
-            .ok();
-
-        if let Ok(mut current_state) = state_data.get_game_state().await {
-            let mut players = state_data.get_peer_list().await.unwrap_or_default();
-            if let Ok(effect) =
-                logic.handle_player_disconnect(&mut players, &id, &mut current_state)
-            {
-                persist_connection_effect(state_data, &players, &current_state, effect)
-                    .await
-                    .ok();
-            }
+        for id in with_seats(state_data, id).await {
+            disconnect_player(state_data, logic, &id).await.ok();
         }
     } else if state_data.is_peer_host(&id).await.unwrap_or_default() {
         state_data.host_offline();
@@ -65,6 +42,61 @@ pub(super) async fn process_leaver<G: GameLogic>(
     None
 }
 
+/// Mark a player offline and let the game deal with their absence.
+///
+/// Players already marked offline are left alone, so a peer that announced its leave is not
+/// disconnected a second time when its connection drops.
+pub(super) async fn disconnect_player<G: GameLogic>(
+    data: &StateData<G>,
+    logic: &Arc<G>,
+    id: &EndpointId,
+) -> Result<()> {
+    match data.get_peer_info(id).await? {
+        Some(peer) if peer.status != PeerStatus::Offline => {}
+        _ => return Ok(()),
+    }
+    data.set_peer_status(id, PeerStatus::Offline).await?;
+    let Ok(mut current_state) = data.get_game_state().await else {
+        return Ok(()); // The game has not started yet
+    };
+    data.record_disconnect(id).await?;
+    let mut players = data.get_peer_list().await?;
+    let effect = logic.handle_player_disconnect(&mut players, id, &mut current_state)?;
+    persist_connection_effect(data, &players, &current_state, effect).await
+}
+
+/// Mark a player back online and let the game welcome them back.
+///
+/// Only players that were offline are reconnected, so the game hears of each return once and
+/// only after hearing that the player was gone.
+async fn reconnect_player<G: GameLogic>(
+    data: &StateData<G>,
+    logic: &Arc<G>,
+    id: &EndpointId,
+) -> Result<()> {
+    match data.get_peer_info(id).await? {
+        Some(peer) if peer.status == PeerStatus::Offline => {}
+        _ => return Ok(()),
+    }
+    data.set_peer_status(id, PeerStatus::Online).await?;
+    let Ok(mut current_state) = data.get_game_state().await else {
+        return Ok(()); // The game has not started yet
+    };
+    let mut players = data.get_peer_list().await?;
+    let effect = logic.handle_player_reconnect(&mut players, id, &mut current_state)?;
+    persist_connection_effect(data, &players, &current_state, effect).await
+}
+
+/// An endpoint's own player followed by the players seated on it, which share its connection.
+pub(super) async fn with_seats<G: GameLogic>(
+    state_data: &StateData<G>,
+    id: EndpointId,
+) -> Vec<EndpointId> {
+    let mut players = vec![id];
+    players.extend(state_data.get_seats_of(&id).await.unwrap_or_default());
+    players
+}
+
 /// Apply standard forfeit behavior and game-specific forfeit hooks.
 pub(super) async fn process_forfeit<G: GameLogic>(
     data: &StateData<G>,
@@ -93,6 +125,11 @@ async fn persist_connection_effect<G: GameLogic>(
 ) -> Result<()> {
     match effect {
         ConnectionEffect::NoChange => {}
+        // Lock-step games only move on through the players' own actions.
+        ConnectionEffect::StateChanged if data.is_lockstep() => {}
+        ConnectionEffect::StateAndPeersChanged if data.is_lockstep() => {
+            data.persist_peer_list(players).await?
+        }
         ConnectionEffect::StateChanged => data.set_game_state(current_state).await?,
         ConnectionEffect::PeersChanged => data.persist_peer_list(players).await?,
         ConnectionEffect::StateAndPeersChanged => {
@@ -0,0 +1,118 @@
+//! Debouncing of peer list and game state updates.
+//!
+//! A join storm or a burst of bot turns writes many entries in quick succession, and each one
+//! would otherwise re-read the whole `PeerMap` or deliver another game state. The `Debouncer`
+//! holds these back for a short window and then reports the latest of each once.
+//!
+//! Any other event releases what is held first, so events still arrive in the order their
+//! entries did, e.g. an action's new game state before its `UiEvent::ActionAccepted`.
+
+use super::{process::process_update, ui::UiEvent};
+use crate::{
+    GameLogic,
+    room::state::{GameKey as _, StateData},
+};
+use iroh_docs::sync::Entry;
+use std::{sync::Arc, time::Duration};
+use tokio::time::Instant;
+
+/// Holds back `UiEvent::Peer` and `UiEvent::GameState` updates until a window has passed.
+pub(super) struct Debouncer<G: GameLogic> {
+    /// How long to hold updates back for, or zero to report them straight away.
+    window: Duration,
+    /// The latest peer entry since the peer list was last reported.
+    peer_entry: Option<Entry>,
+    /// The latest game state that has not been reported yet.
+    game_state: Option<G::GameState>,
+    /// When the held updates are due, if any are held.
+    deadline: Option<Instant>,
+}
+
+impl<G: GameLogic> Debouncer<G> {
+    pub(super) fn new(window: Duration) -> Self {
+        Self {
+            window,
+            peer_entry: None,
+            game_state: None,
+            deadline: None,
+        }
+    }
+
+    /// Hold updates back for a different window from now on, e.g. while the app is in the
+    /// background. A shorter window takes effect straight away.
+    pub(super) fn set_window(&mut self, window: Duration) {
+        self.window = window;
+        if let Some(deadline) = self.deadline {
+            self.deadline = Some(deadline.min(Instant::now() + window));
+        }
+    }
+
+    /// Start the window, unless one is already running.
+    fn arm(&mut self) {
+        self.deadline
+            .get_or_insert_with(|| Instant::now() + self.window);
+    }
+
+    /// Hold back a peer entry, so the peer list is read once at the end of the window.
+    ///
+    /// Returns `false` if the entry should be processed as usual.
+    pub(super) fn hold_entry(&mut self, entry: &Entry) -> bool {
+        if self.window.is_zero() || !entry.is_peer_entry() {
+            return false;
+        }
+        self.peer_entry = Some(entry.clone());
+        self.arm();
+        true
+    }
+
+    /// Hold back a game state update, or pass any other event through after whatever is held.
+    pub(super) async fn hold(
+        &mut self,
+        event: UiEvent<G>,
+        data: &Arc<StateData<G>>,
+        logic: &Arc<G>,
+    ) -> Vec<UiEvent<G>> {
+        if self.window.is_zero() {
+            return vec![event];
+        }
+        if let UiEvent::GameState(state) = event {
+            self.game_state = Some(state);
+            self.arm();
+            return Vec::new();
+        }
+        if let UiEvent::PredictionCorrected(_) = event {
+            // The corrected view replaces whatever was held back.
+            self.game_state = None;
+        }
+        let mut events = self.release(data, logic).await;
+        events.push(event);
+        events
+    }
+
+    /// Wait until the held updates are due.
+    pub(super) async fn elapsed(&self) {
+        match self.deadline {
+            Some(deadline) => tokio::time::sleep_until(deadline).await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Take the held updates: the current peer list, then the latest game state.
+    pub(super) async fn release(
+        &mut self,
+        data: &Arc<StateData<G>>,
+        logic: &Arc<G>,
+    ) -> Vec<UiEvent<G>> {
+        if self.deadline.take().is_none() {
+            return Vec::new();
+        }
+        let mut events = Vec::new();
+        if let Some(entry) = self.peer_entry.take() {
+            events.extend(process_update(&entry, data, logic).await);
+        }
+        if let Some(state) = self.game_state.take() {
+            events.push(UiEvent::GameState(state));
+        }
+        events
+    }
+}
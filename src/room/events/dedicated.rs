@@ -0,0 +1,50 @@
+//! Host-side lobby automation for dedicated rooms.
+
+use crate::{AppState, DedicatedPolicy, GameLogic, room::state::StateData, trace};
+use anyhow::Result;
+use std::{sync::Arc, time::Duration};
+use tokio::time::Instant;
+
+/// How often a dedicated host checks whether to start a game or reopen the lobby.
+pub(super) const DEDICATED_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Start a game once enough players are ready, and reopen the lobby a while after each game.
+///
+/// `finished_since` tracks when this host first saw the current game finished.
+pub(super) async fn run_dedicated_lobby<G: GameLogic>(
+    data: &StateData<G>,
+    logic: &Arc<G>,
+    policy: &DedicatedPolicy,
+    finished_since: &mut Option<Instant>,
+) -> Result<()> {
+    if !data.is_host().await.unwrap_or_default() || data.get_close_reason().await?.is_some() {
+        return Ok(());
+    }
+    match data.get_app_state().await? {
+        AppState::Lobby => {
+            *finished_since = None;
+            let players = data.get_peer_list().await?;
+            let mut players = players
+                .values()
+                .filter(|peer| !peer.is_observer && peer.status.is_online());
+            let ready = players.clone().count() >= policy.min_players.max(1)
+                && players.all(|peer| peer.ready);
+            if ready && let Err(_e) = data.start_game(logic).await {
+                // The game may refuse these players, e.g. too many of them for its roles.
+                trace::debug!(error = %_e, "Dedicated host could not start the game");
+            }
+        }
+        AppState::Finished => {
+            let since = *finished_since.get_or_insert_with(Instant::now);
+            if policy
+                .reopen_after
+                .is_some_and(|after| since.elapsed() >= after)
+            {
+                data.reopen_lobby().await?;
+                *finished_since = None;
+            }
+        }
+        _ => *finished_since = None,
+    }
+    Ok(())
+}
@@ -0,0 +1,512 @@
+//! Delivery stage between the event loop and the application's event receiver.
+//!
+//! Events wait in an `EventQueue` until the application has room for them. While they wait,
+//! snapshot-style events can be coalesced so a slow consumer only sees the latest value
+//! instead of every intermediate one, e.g. when the host applies a burst of bot turns. No more
+//! than `RoomConfig::queue_limit` events wait at once: past that the oldest are dropped, and
+//! the consumer is told how many it missed with a `UiEvent::Lagged`.
+//!
+//! Extra receivers made with `GameRoom::subscribe` are handed every event that passes their
+//! `EventFilter` as it is queued, and each has a delivery stage of its own, so a slow
+//! subscriber never holds up the others. Handlers registered with `GameRoom::on_event` are
+//! called with every event as it is queued, on the event loop itself. The most recent events
+//! are kept too, so a subscriber that comes late starts with them and can rebuild its view.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::mpsc;
+
+use super::ui::UiEvent;
+use crate::{GameLogic, trace};
+
+/// How queued events of one kind are treated while the application is catching up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Coalesce {
+    /// Deliver every event.
+    KeepAll,
+    /// Drop a queued event when a newer one of the same kind arrives.
+    LatestWins,
+}
+
+/// Per-event-kind coalescing settings for the delivery stage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventCoalescing {
+    /// `UiEvent::Peer` updates.
+    pub peers: Coalesce,
+    /// `UiEvent::GameState` updates.
+    pub game_state: Coalesce,
+    /// `UiEvent::AppState` updates.
+    pub app_state: Coalesce,
+    /// `UiEvent::Chat` and `UiEvent::ChatUpdated` events.
+    pub chat: Coalesce,
+}
+
+impl Default for EventCoalescing {
+    fn default() -> Self {
+        Self {
+            peers: Coalesce::KeepAll,
+            game_state: Coalesce::LatestWins,
+            app_state: Coalesce::LatestWins,
+            chat: Coalesce::KeepAll,
+        }
+    }
+}
+
+impl EventCoalescing {
+    /// Deliver every event, as produced.
+    pub fn keep_all() -> Self {
+        Self {
+            peers: Coalesce::KeepAll,
+            game_state: Coalesce::KeepAll,
+            app_state: Coalesce::KeepAll,
+            chat: Coalesce::KeepAll,
+        }
+    }
+
+    /// Set how `UiEvent::Peer` updates are coalesced.
+    pub fn peers(mut self, coalesce: Coalesce) -> Self {
+        self.peers = coalesce;
+        self
+    }
+
+    /// Set how `UiEvent::GameState` updates are coalesced.
+    pub fn game_state(mut self, coalesce: Coalesce) -> Self {
+        self.game_state = coalesce;
+        self
+    }
+
+    /// Set how `UiEvent::AppState` updates are coalesced.
+    pub fn app_state(mut self, coalesce: Coalesce) -> Self {
+        self.app_state = coalesce;
+        self
+    }
+
+    /// Set how chat events are coalesced.
+    pub fn chat(mut self, coalesce: Coalesce) -> Self {
+        self.chat = coalesce;
+        self
+    }
+
+    /// The coalescing rule that applies to an event.
+    fn rule_for<G: GameLogic>(&self, event: &UiEvent<G>) -> Coalesce {
+        match event {
+            UiEvent::Peer(_) => self.peers,
+            UiEvent::GameState(_) => self.game_state,
+            UiEvent::AppState(_) | UiEvent::RoomClosed(_) => self.app_state,
+            UiEvent::Chat { .. } | UiEvent::ChatUpdated(_) | UiEvent::PeerTyping { .. } => {
+                self.chat
+            }
+            // Only the latest measurement is worth showing.
+            UiEvent::NetworkStats(_) | UiEvent::Syncing { .. } => Coalesce::LatestWins,
+            _ => Coalesce::KeepAll,
+        }
+    }
+}
+
+/// Which kinds of events a subscriber receives, see [crate::GameRoom::subscribe].
+///
+/// The default lets everything through; start from [EventFilter::none] to pick out only a
+/// few kinds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventFilter {
+    /// `UiEvent::GameState`, `UiEvent::PredictionCorrected` and `UiEvent::PrivateState`.
+    pub game_state: bool,
+    /// `UiEvent::AppState`.
+    pub app_state: bool,
+    /// `UiEvent::Peer` and other changes to who is in the room and how they take part.
+    pub peer_updates: bool,
+    /// `UiEvent::Chat`, `UiEvent::ChatUpdated` and `UiEvent::PeerTyping`.
+    pub chat: bool,
+    /// `UiEvent::ActionAccepted`, `UiEvent::ActionRejected` and `UiEvent::ActionCommitted`.
+    pub actions: bool,
+    /// `UiEvent::Host`.
+    pub host: bool,
+    /// `UiEvent::Realtime` and `UiEvent::GameMessage`.
+    pub realtime: bool,
+    /// `UiEvent::NetworkStats` and `UiEvent::ConnectionPathChanged`.
+    pub network_stats: bool,
+    /// `UiEvent::Error`.
+    pub errors: bool,
+    /// Every other event, e.g. turns, proposals and annotations.
+    pub other: bool,
+}
+
+impl Default for EventFilter {
+    fn default() -> Self {
+        Self {
+            game_state: true,
+            app_state: true,
+            peer_updates: true,
+            chat: true,
+            actions: true,
+            host: true,
+            realtime: true,
+            network_stats: true,
+            errors: true,
+            other: true,
+        }
+    }
+}
+
+impl EventFilter {
+    /// Let nothing through.
+    pub fn none() -> Self {
+        Self {
+            game_state: false,
+            app_state: false,
+            peer_updates: false,
+            chat: false,
+            actions: false,
+            host: false,
+            realtime: false,
+            network_stats: false,
+            errors: false,
+            other: false,
+        }
+    }
+
+    /// Set whether game state updates are let through.
+    pub fn game_state(mut self, allow: bool) -> Self {
+        self.game_state = allow;
+        self
+    }
+
+    /// Set whether app state changes are let through.
+    pub fn app_state(mut self, allow: bool) -> Self {
+        self.app_state = allow;
+        self
+    }
+
+    /// Set whether peer list and team changes are let through.
+    pub fn peer_updates(mut self, allow: bool) -> Self {
+        self.peer_updates = allow;
+        self
+    }
+
+    /// Set whether chat events are let through.
+    pub fn chat(mut self, allow: bool) -> Self {
+        self.chat = allow;
+        self
+    }
+
+    /// Set whether action results are let through.
+    pub fn actions(mut self, allow: bool) -> Self {
+        self.actions = allow;
+        self
+    }
+
+    /// Set whether host changes are let through.
+    pub fn host(mut self, allow: bool) -> Self {
+        self.host = allow;
+        self
+    }
+
+    /// Set whether real-time messages are let through.
+    pub fn realtime(mut self, allow: bool) -> Self {
+        self.realtime = allow;
+        self
+    }
+
+    /// Set whether network stats are let through.
+    pub fn network_stats(mut self, allow: bool) -> Self {
+        self.network_stats = allow;
+        self
+    }
+
+    /// Set whether errors are let through.
+    pub fn errors(mut self, allow: bool) -> Self {
+        self.errors = allow;
+        self
+    }
+
+    /// Set whether every other kind of event is let through.
+    pub fn other(mut self, allow: bool) -> Self {
+        self.other = allow;
+        self
+    }
+
+    /// Check whether an event passes the filter.
+    pub fn allows<G: GameLogic>(&self, event: &UiEvent<G>) -> bool {
+        match event {
+            UiEvent::GameState(_)
+            | UiEvent::PredictionCorrected(_)
+            | UiEvent::PrivateState { .. } => self.game_state,
+            UiEvent::AppState(_) => self.app_state,
+            UiEvent::Peer(_)
+            | UiEvent::PeerJoined(_)
+            | UiEvent::PeerLeft { .. }
+            | UiEvent::PeerUpdated(_)
+            | UiEvent::TeamChanged { .. }
+            | UiEvent::PlayerForfeited { .. }
+            | UiEvent::SeatRequested { .. }
+            | UiEvent::JoinDenied(_) => self.peer_updates,
+            UiEvent::Chat { .. } | UiEvent::ChatUpdated(_) | UiEvent::PeerTyping { .. } => {
+                self.chat
+            }
+            UiEvent::ActionAccepted { .. }
+            | UiEvent::ActionRejected { .. }
+            | UiEvent::ActionCommitted { .. } => self.actions,
+            UiEvent::Host(_) => self.host,
+            UiEvent::Realtime { .. } | UiEvent::GameMessage { .. } => self.realtime,
+            UiEvent::NetworkStats(_) | UiEvent::ConnectionPathChanged { .. } => self.network_stats,
+            UiEvent::Error(_) => self.errors,
+            UiEvent::GameStarted { .. } | UiEvent::GameOver(_) => self.game_state || self.app_state,
+            // It stands in for all three, so it is wanted by anyone following one of them.
+            UiEvent::InitialSnapshot { .. } => {
+                self.game_state || self.app_state || self.peer_updates
+            }
+            _ => self.other,
+        }
+    }
+}
+
+/// A receiver made with [crate::GameRoom::subscribe].
+struct Subscriber<G: GameLogic> {
+    filter: EventFilter,
+    /// Feeds the subscriber's own delivery stage, which never blocks the event loop.
+    inbox: mpsc::Sender<UiEvent<G>>,
+    /// Events dropped since the inbox last had room, reported once it does.
+    missed: u64,
+}
+
+/// A callback registered with [crate::GameRoom::on_event].
+type Handler<G> = Box<dyn FnMut(UiEvent<G>) + Send>;
+
+/// The extra receivers and handlers of a room's events, shared between the room and its
+/// event loop.
+pub(crate) struct Subscribers<G: GameLogic> {
+    receivers: Arc<Mutex<Vec<Subscriber<G>>>>,
+    /// Handlers being called, only ever locked by the event loop as it publishes.
+    handlers: Arc<Mutex<Vec<Handler<G>>>>,
+    /// Handlers registered since the last event was published, so registering one never
+    /// waits on the handlers being called, even from within one of them.
+    new_handlers: Arc<Mutex<Vec<Handler<G>>>>,
+    /// The most recent events, oldest first, see [crate::RoomConfig::replay_buffer].
+    recent: Arc<Mutex<VecDeque<UiEvent<G>>>>,
+    replay_buffer: usize,
+}
+
+impl<G: GameLogic> Clone for Subscribers<G> {
+    fn clone(&self) -> Self {
+        Self {
+            receivers: self.receivers.clone(),
+            handlers: self.handlers.clone(),
+            new_handlers: self.new_handlers.clone(),
+            recent: self.recent.clone(),
+            replay_buffer: self.replay_buffer,
+        }
+    }
+}
+
+impl<G: GameLogic> Default for Subscribers<G> {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl<G: GameLogic> Subscribers<G> {
+    /// No subscribers yet, keeping the `replay_buffer` most recent events for those to come.
+    pub(crate) fn new(replay_buffer: usize) -> Self {
+        Self {
+            receivers: Arc::new(Mutex::new(Vec::new())),
+            handlers: Arc::new(Mutex::new(Vec::new())),
+            new_handlers: Arc::new(Mutex::new(Vec::new())),
+            recent: Arc::new(Mutex::new(VecDeque::with_capacity(replay_buffer))),
+            replay_buffer,
+        }
+    }
+
+    fn lock<T>(list: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+        // Entries are only ever added or removed whole, so a poisoned lock is still usable.
+        list.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Up to `n` of the most recent events, oldest first.
+    pub(crate) fn recent(&self, n: usize) -> Vec<UiEvent<G>> {
+        let recent = Self::lock(&self.recent);
+        recent
+            .iter()
+            .skip(recent.len().saturating_sub(n))
+            .cloned()
+            .collect()
+    }
+
+    /// Add a receiver for the events that pass `filter`, coalesced like the room's own,
+    /// starting with the recent events that pass it.
+    pub(crate) fn subscribe(
+        &self,
+        filter: EventFilter,
+        coalescing: EventCoalescing,
+        capacity: usize,
+        limit: usize,
+    ) -> mpsc::Receiver<UiEvent<G>> {
+        // The config's fields can be set directly, so hold them to the setters' minimum here.
+        let (capacity, limit) = (capacity.max(1), limit.max(1));
+        let (inbox, mut incoming) = mpsc::channel(limit);
+        let (sender, receiver) = mpsc::channel(capacity);
+        let mut queue = EventQueue::new(sender.clone(), coalescing, limit, Subscribers::default());
+        // Hold the buffer while subscribing so no event is missed or seen twice.
+        let recent = Self::lock(&self.recent);
+        for event in recent.iter().filter(|event| filter.allows(*event)) {
+            queue.push(event.clone());
+        }
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    next = incoming.recv() => match next {
+                        Some(event) => queue.push(event),
+                        None => break, // The room has closed
+                    },
+                    permit = sender.reserve(), if !queue.is_empty() => {
+                        let Ok(permit) = permit else {
+                            break; // Receiver dropped
+                        };
+                        if let Some(event) = queue.pop() {
+                            permit.send(event);
+                        }
+                    },
+                }
+            }
+        });
+        Self::lock(&self.receivers).push(Subscriber {
+            filter,
+            inbox,
+            missed: 0,
+        });
+        drop(recent);
+        receiver
+    }
+
+    /// Call `handler` with every event published from now on.
+    pub(crate) fn on_event(&self, handler: impl FnMut(UiEvent<G>) + Send + 'static) {
+        Self::lock(&self.new_handlers).push(Box::new(handler));
+    }
+
+    /// Call every handler with an event, keep it as a recent one, and hand it to every
+    /// subscriber it passes the filter of, forgetting closed ones.
+    fn publish(&self, event: &UiEvent<G>) {
+        let mut handlers = Self::lock(&self.handlers);
+        handlers.append(&mut Self::lock(&self.new_handlers));
+        for handler in handlers.iter_mut() {
+            handler(event.clone());
+        }
+        drop(handlers);
+        let mut recent = Self::lock(&self.recent);
+        if self.replay_buffer > 0 {
+            if recent.len() == self.replay_buffer {
+                recent.pop_front();
+            }
+            recent.push_back(event.clone());
+        }
+        Self::lock(&self.receivers).retain_mut(|subscriber| {
+            if !subscriber.filter.allows(event) {
+                return !subscriber.inbox.is_closed();
+            }
+            if subscriber.missed > 0 {
+                let lagged = UiEvent::Lagged {
+                    missed: subscriber.missed,
+                };
+                match subscriber.inbox.try_send(lagged) {
+                    Ok(()) => subscriber.missed = 0,
+                    Err(mpsc::error::TrySendError::Full(_)) => {
+                        subscriber.missed += 1;
+                        return true;
+                    }
+                    Err(mpsc::error::TrySendError::Closed(_)) => return false,
+                }
+            }
+            match subscriber.inbox.try_send(event.clone()) {
+                Ok(()) => true,
+                // The subscriber's delivery stage has fallen behind, so it misses this one.
+                Err(mpsc::error::TrySendError::Full(_)) => {
+                    subscriber.missed += 1;
+                    true
+                }
+                Err(mpsc::error::TrySendError::Closed(_)) => false,
+            }
+        });
+    }
+}
+
+/// Events waiting to be delivered to the application.
+pub(super) struct EventQueue<G: GameLogic> {
+    /// The application's receiver, which the queue drains into.
+    sender: mpsc::Sender<UiEvent<G>>,
+    coalescing: EventCoalescing,
+    events: VecDeque<UiEvent<G>>,
+    /// How many events may be queued before the oldest are dropped.
+    limit: usize,
+    /// Events dropped since the application last took one, reported before the next.
+    missed: u64,
+    subscribers: Subscribers<G>,
+}
+
+impl<G: GameLogic> EventQueue<G> {
+    pub(super) fn new(
+        sender: mpsc::Sender<UiEvent<G>>,
+        coalescing: EventCoalescing,
+        limit: usize,
+        subscribers: Subscribers<G>,
+    ) -> Self {
+        Self {
+            sender,
+            coalescing,
+            events: VecDeque::new(),
+            limit: limit.max(1),
+            missed: 0,
+            subscribers,
+        }
+    }
+
+    /// Queue an event, replacing an older queued event of the same kind if it is latest-wins,
+    /// and dropping the oldest if the queue is full.
+    ///
+    /// Subscribers whose filter it passes, and handlers, get a copy straight away.
+    pub(super) fn push(&mut self, event: UiEvent<G>) {
+        if matches!(event, UiEvent::Error(_)) {
+            trace::warning!(%event, "Room error");
+        }
+        // Events a subscriber missed before they reached its queue count with the queue's own.
+        if let UiEvent::Lagged { missed } = event {
+            self.missed += missed;
+            return;
+        }
+        self.subscribers.publish(&event);
+        if self.coalescing.rule_for(&event) == Coalesce::LatestWins {
+            let kind = std::mem::discriminant(&event);
+            self.events
+                .retain(|queued| std::mem::discriminant(queued) != kind);
+        }
+        if self.events.len() >= self.limit {
+            self.events.pop_front();
+            self.missed += 1;
+        }
+        self.events.push_back(event);
+    }
+
+    /// The sender for the application's receiver.
+    pub(super) fn sender(&self) -> mpsc::Sender<UiEvent<G>> {
+        self.sender.clone()
+    }
+
+    /// Take the oldest queued event, after telling the application about any it missed.
+    pub(super) fn pop(&mut self) -> Option<UiEvent<G>> {
+        if self.missed > 0 {
+            let missed = std::mem::take(&mut self.missed);
+            return Some(UiEvent::Lagged { missed });
+        }
+        self.events.pop_front()
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.events.is_empty() && self.missed == 0
+    }
+
+    /// How many events are waiting for the application, queued or already in its channel.
+    pub(super) fn depth(&self) -> usize {
+        self.events.len() + self.sender.max_capacity() - self.sender.capacity()
+    }
+}
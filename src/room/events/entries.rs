@@ -1,13 +1,26 @@
 //! Document entry processing for room events.
 
-use super::{HostEvent, actions::apply_action_request, connections::process_forfeit, ui::UiEvent};
+use super::{
+    HostEvent,
+    actions::apply_action_request,
+    connections::{disconnect_player, process_forfeit, process_joiner, with_seats},
+    proposals::resolve_proposal,
+    rounds::close_ready_rounds,
+    ui::UiEvent,
+    votes::tally_vote,
+};
 use crate::{
-    ActionResult, AppState, GameLogic, PeerProfile, PeerStatus,
-    room::{chat::ChatMessage, state::*},
+    Annotation, AppState, GameLogic, GameResult, PeerProfile, PeerStatus, ProposalOutcome,
+    ProposalRequest, RoomMeta, RoomTicket, TeamId, TurnManager, VoteCall, VoteResult,
+    room::{
+        chat::{ChatMessage, ChatReaction, ChatUpdate},
+        state::*,
+    },
+    trace,
 };
 use anyhow::{Result, anyhow};
-use iroh_docs::sync::Entry;
-use std::sync::Arc;
+use iroh_docs::{AuthorId, sync::Entry};
+use std::{sync::Arc, time::Duration};
 
 /// Process a single iroh log entry and produce an optional UI event.
 pub async fn process_entry<G: GameLogic>(
@@ -27,6 +40,11 @@ async fn process_host_entry<G: GameLogic>(
     data: &StateData<G>,
     logic: &Arc<G>,
 ) -> Result<Option<UiEvent<G>>> {
+    // Every change to the game state, whatever caused it, may have moved the turn on.
+    if (entry.is_game_state_update() || entry.is_game_start()) && data.is_host().await? {
+        publish_turns(data, logic).await?;
+    }
+
     if let Some(node_id) = entry.is_join() {
         if !data.is_host().await? {
             return Ok(None);
@@ -36,7 +54,61 @@ async fn process_host_entry<G: GameLogic>(
             .parse::<PeerProfile>(entry)
             .await
             .map_err(|e| anyhow!("Failed to parse PeerInfo for {}: {e}", &node_id))?;
+        // A closed room keeps the peers it had, but takes nobody new.
+        if data.get_close_reason().await?.is_some() && data.get_peer_info(&node_id).await?.is_none()
+        {
+            data.deny_join(&node_id, JoinDeniedReason::RoomClosed)
+                .await?;
+            return Ok(None);
+        }
+        if data.is_full_for(&node_id).await? {
+            data.deny_join(&node_id, JoinDeniedReason::RoomFull).await?;
+            return Ok(None);
+        }
         data.insert_peer(&node_id, entry.author(), profile).await?;
+        // Seat requests may have synced before the endpoint that owns them joined.
+        register_seats(data, &node_id).await?;
+        return Ok(None);
+    }
+
+    if let Some(seat_id) = entry.is_seat_request() {
+        if !data.is_host().await? {
+            return Ok(None);
+        }
+        let seat_id = seat_id?;
+        let request = data
+            .parse::<SeatRequest>(entry)
+            .await
+            .map_err(|e| anyhow!("Failed to parse SeatRequest for {seat_id}: {e}"))?;
+        register_seats(data, &request.owner).await?;
+        return Ok(None);
+    }
+
+    if let Some(node_id) = entry.is_play_request() {
+        let node_id = node_id?;
+        if !data.is_host().await? || !data.peer_author_matches(&node_id, &entry.author()).await? {
+            return Ok(None);
+        }
+        return Ok(Some(UiEvent::SeatRequested { peer: node_id }));
+    }
+
+    if let Some(node_id) = entry.is_heartbeat() {
+        let node_id = node_id?;
+        if node_id == data.endpoint_id
+            || !data.is_host().await?
+            || !data.peer_author_matches(&node_id, &entry.author()).await?
+        {
+            return Ok(None);
+        }
+        data.record_heartbeat(&node_id);
+        // A peer marked offline for missing beats is back as soon as one arrives again.
+        let peers = data.get_peer_list().await?;
+        if peers
+            .get(&node_id)
+            .is_some_and(|peer| peer.status == PeerStatus::Offline)
+        {
+            process_joiner(node_id, data, logic).await;
+        }
         return Ok(None);
     }
 
@@ -44,36 +116,181 @@ async fn process_host_entry<G: GameLogic>(
         if !data.is_host().await? {
             return Ok(None);
         }
-        process_action_entry(entry, data, logic, action_key?).await?;
+        let (node_id, seq) = action_key?;
+        if let Some(forged) = process_action_entry(entry, data, logic, (node_id, seq)).await? {
+            return Ok(Some(forged));
+        }
+        return Ok(rate_limited(data, WriteKind::Action, node_id));
+    }
+
+    let proposal_id = match (entry.is_proposal(), entry.is_proposal_vote()) {
+        (Some(id), _) => Some(id),
+        (_, Some(vote)) => Some(vote?.0),
+        _ => None,
+    };
+    if let Some(id) = proposal_id {
+        if data.is_host().await? {
+            resolve_proposal(data, logic, &id).await?;
+        }
+        return Ok(None);
+    }
+
+    let vote_id = match (entry.is_vote_call(), entry.is_ballot()) {
+        (Some(id), _) => Some(id),
+        (_, Some(ballot)) => Some(ballot?.0),
+        _ => None,
+    };
+    if let Some(id) = vote_id {
+        if data.is_host().await? {
+            tally_vote(data, logic, &id).await?;
+        }
         return Ok(None);
     }
 
     Ok(None)
 }
 
+/// Seat every local player requested by a registered endpoint.
+///
+/// Only requests written by the endpoint's own author are considered, so nobody can seat
+/// players on an endpoint they do not control.
+async fn register_seats<G: GameLogic>(data: &StateData<G>, owner: &iroh::EndpointId) -> Result<()> {
+    for (seat_id, entry) in data.get_seat_requests(owner).await? {
+        let request = data.parse::<SeatRequest>(&entry).await?;
+        if request.owner != *owner {
+            continue;
+        }
+        data.insert_seat(&seat_id, entry.author(), request).await?;
+    }
+    Ok(())
+}
+
 /// Process entries that every peer may translate into UI events.
 async fn process_peer_entry<G: GameLogic>(
     entry: &Entry,
     data: &StateData<G>,
     logic: &Arc<G>,
 ) -> Result<Option<UiEvent<G>>> {
+    if let Some(node_id) = entry.is_join_denial() {
+        let node_id = node_id?;
+        // A refusal is stale once the host has let us in after all.
+        if node_id != data.endpoint_id
+            || !data.host_author_matches(&entry.author()).await?
+            || data.get_peer_info(&node_id).await?.is_some()
+        {
+            return Ok(None);
+        }
+        return match data.parse::<JoinDeniedReason>(entry).await {
+            Err(e) => Err(anyhow!("Failed to parse join refusal: {e}")),
+            Ok(reason) => Ok(Some(UiEvent::JoinDenied(reason))),
+        };
+    }
+
     if let Some(action_result_key) = entry.is_action_result() {
-        let (node_id, _action_id) = action_result_key?;
-        if node_id != data.endpoint_id {
+        let (node_id, seq) = action_result_key?;
+        if !data.host_author_matches(&entry.author()).await?
+            || !data.is_local_player(&node_id).await?
+        {
             return Ok(None);
         }
-        return match data.parse::<ActionResult>(entry).await {
-            Err(e) => Err(anyhow!("Failed to parse ActionResult: {e}")),
-            Ok(result) => Ok(Some(UiEvent::ActionResult(result))),
+        data.settle_prediction(logic, &node_id, seq).await?;
+        return match data.parse::<ActionResponse<G::GameError>>(entry).await {
+            Err(e) => Err(anyhow!("Failed to parse ActionResponse: {e}")),
+            Ok(ActionResponse {
+                action_id,
+                seq,
+                error: None,
+            }) => Ok(Some(UiEvent::ActionAccepted {
+                player: node_id,
+                seq,
+                action_id,
+            })),
+            Ok(ActionResponse {
+                action_id,
+                seq,
+                error: Some(error),
+            }) => Ok(Some(UiEvent::ActionRejected {
+                player: node_id,
+                seq,
+                action_id,
+                error,
+            })),
         };
     }
 
+    if let Some(commitment) = entry.is_commitment() {
+        let (player, commitment) = commitment?;
+        if !data.peer_author_matches(&player, &entry.author()).await? {
+            return Ok(None);
+        }
+        data.reveal_ready_actions().await?;
+        return Ok(Some(UiEvent::ActionCommitted { player, commitment }));
+    }
+
     if let Some(node_id) = entry.is_chat_message() {
         let node_id = node_id?;
+        // A message keyed under another peer must not count against, or speak for, them.
+        if !data.peer_author_matches(&node_id, &entry.author()).await? {
+            return Ok(None);
+        }
+        if !data.within_rate_limit(WriteKind::Chat, &node_id).await? {
+            return Ok(rate_limited(data, WriteKind::Chat, node_id));
+        }
         let sender = data.get_peer_name(&node_id).await?;
-        return match data.parse::<ChatMessage>(entry).await {
-            Err(e) => Err(anyhow!("Failed to parse ChatMessage from {sender}: {e}")),
-            Ok(msg) => Ok(Some(UiEvent::Chat { sender, msg })),
+        let msg = match data.parse::<ChatMessage>(entry).await {
+            Err(e) => return Err(anyhow!("Failed to parse ChatMessage from {sender}: {e}")),
+            Ok(msg) => msg,
+        };
+        data.observe_clock(msg.clock);
+        if !msg.edited && !msg.deleted {
+            return Ok(data
+                .filter_chat(logic, msg)
+                .await?
+                .map(|msg| UiEvent::Chat { sender, msg }));
+        }
+        // Only the original sender may change a message after it was sent.
+        if msg.from != node_id {
+            return Ok(None);
+        }
+        if msg.deleted {
+            return Ok(Some(UiEvent::ChatUpdated(ChatUpdate::Deleted {
+                id: msg.id,
+            })));
+        }
+        return Ok(data
+            .filter_chat(logic, msg)
+            .await?
+            .map(|msg| UiEvent::ChatUpdated(ChatUpdate::Edited(msg))));
+    }
+
+    if let Some(node_id) = entry.is_chat_reaction() {
+        let node_id = node_id?;
+        if !data.peer_author_matches(&node_id, &entry.author()).await? {
+            return Ok(None);
+        }
+        if !data.within_rate_limit(WriteKind::Chat, &node_id).await? {
+            return Ok(rate_limited(data, WriteKind::Chat, node_id));
+        }
+        return match data.parse::<ChatReaction>(entry).await {
+            Err(e) => Err(anyhow!("Failed to parse ChatReaction from {node_id}: {e}")),
+            Ok(reaction) if reaction.from == node_id => {
+                Ok(Some(UiEvent::ChatUpdated(ChatUpdate::Reacted(reaction))))
+            }
+            Ok(_) => Ok(None),
+        };
+    }
+
+    if let Some(node_id) = entry.is_annotation() {
+        let node_id = node_id?;
+        if !data.peer_author_matches(&node_id, &entry.author()).await? {
+            return Ok(None);
+        }
+        return match data.parse::<Annotation>(entry).await {
+            Err(e) => Err(anyhow!("Failed to parse Annotation from {node_id}: {e}")),
+            Ok(annotation) if annotation.from == node_id => {
+                Ok(Some(UiEvent::Annotation(annotation)))
+            }
+            Ok(_) => Ok(None),
         };
     }
 
@@ -88,106 +305,550 @@ async fn process_peer_entry<G: GameLogic>(
         if !data.host_author_matches(&entry.author()).await? {
             return Ok(None);
         }
-        return match data.parse::<G::GameState>(entry).await {
+        // The host's state is where a lock-step game starts, or the state it finished in.
+        if data.is_lockstep() {
+            data.advance_lockstep(logic, None).await?;
+            return Ok(Some(UiEvent::GameState(data.get_game_state().await?)));
+        }
+        let stored = data.parse::<StoredGameState>(entry).await;
+        let state = match stored {
+            Ok(stored) => data.load_game_state(stored).await,
+            Err(e) => Err(e),
+        };
+        return match state {
             Err(e) => Err(anyhow!("Failed to parse GameState: {e}")),
-            Ok(state) => Ok(Some(UiEvent::GameState(state))),
+            Ok(entry) => Ok(match data.reconcile(logic, &entry) {
+                Reconciled::NotPredicting => Some(UiEvent::GameState(entry.state)),
+                Reconciled::Confirmed => None,
+                Reconciled::Corrected(state) => Some(UiEvent::PredictionCorrected(state)),
+            }),
+        };
+    }
+
+    if entry.is_game_start() {
+        if !data.host_author_matches(&entry.author()).await? {
+            return Ok(None);
+        }
+        data.app_state_synced(AppState::InGame);
+        if data.is_lockstep() {
+            data.advance_lockstep(logic, None).await?;
+            let state = data.get_game_state().await?;
+            return Ok(Some(UiEvent::GameStarted { state }));
+        }
+        let start = data.parse::<GameStart<G::PlayerRole>>(entry).await;
+        let state = match start {
+            Ok(start) => data.load_game_state(start.state).await,
+            Err(e) => Err(e),
+        };
+        return match state {
+            Err(e) => Err(anyhow!("Failed to parse game start: {e}")),
+            // A restart replaces the game, but any actions still pending carry over to it.
+            Ok(entry) => Ok(Some(UiEvent::GameStarted {
+                state: match data.reconcile(logic, &entry) {
+                    Reconciled::Corrected(state) => state,
+                    Reconciled::NotPredicting | Reconciled::Confirmed => entry.state,
+                },
+            })),
+        };
+    }
+
+    if entry.is_game_result() {
+        if !data.host_author_matches(&entry.author()).await? {
+            return Ok(None);
+        }
+        data.app_state_synced(AppState::Finished);
+        return match data.parse::<GameResult>(entry).await {
+            Err(e) => Err(anyhow!("Failed to parse GameResult: {e}")),
+            Ok(result) => {
+                if let Err(_e) = data.record_match(logic, &result).await {
+                    trace::warning!(error = %_e, "Could not record the match in the history");
+                }
+                Ok(Some(UiEvent::GameOver(result)))
+            }
         };
     }
 
+    if let Some(to) = entry.is_private_state() {
+        let to = to?;
+        if !data.host_author_matches(&entry.author()).await? || !data.is_local_player(&to).await? {
+            return Ok(None);
+        }
+        let sealed = data.content_bytes(entry).await?;
+        let payload = data.open_private(&sealed)?;
+        return Ok(Some(UiEvent::PrivateState { to, payload }));
+    }
+
+    if entry.is_asset().is_some() {
+        if !data.host_author_matches(&entry.author()).await? {
+            return Ok(None);
+        }
+        let asset = data
+            .parse::<Asset>(entry)
+            .await
+            .map_err(|e| anyhow!("Failed to parse Asset: {e}"))?;
+        if data.fetches_assets() && !data.is_host().await? {
+            data.fetch_asset(&asset).await?;
+        }
+        return Ok(Some(UiEvent::AssetAvailable(asset)));
+    }
+
+    if let Some(step) = entry.is_lockstep_step() {
+        let step = step?;
+        if data.is_lockstep() {
+            data.advance_lockstep(logic, Some(step)).await?;
+        }
+        return Ok(None);
+    }
+
     if entry.is_app_state_update() {
         if !data.host_author_matches(&entry.author()).await? {
             return Ok(None);
         }
-        return match data.parse::<AppState>(entry).await {
+        return match data.parse::<AppState<G>>(entry).await {
             Err(e) => Err(anyhow!("Failed to parse AppState: {e}")),
-            Ok(app_state) => Ok(Some(UiEvent::AppState(app_state))),
+            Ok(app_state) => {
+                data.app_state_synced(app_state);
+                Ok(Some(UiEvent::AppState(app_state)))
+            }
         };
     }
 
     if entry.is_host_update() {
-        return process_host_update(entry, data).await;
+        return process_host_update(entry, data, logic).await;
+    }
+
+    if entry.is_host_handover() {
+        let handover = data
+            .parse::<HostHandover>(entry)
+            .await
+            .map_err(|e| anyhow!("Failed to parse host handover: {e}"))?;
+        // Everyone hears about the new host once its claim syncs.
+        data.accept_handover(&handover, &entry.author()).await?;
+        return Ok(None);
+    }
+
+    if entry.is_inactivity_warning() {
+        if !data.host_author_matches(&entry.author()).await? {
+            return Ok(None);
+        }
+        return match data.parse::<Duration>(entry).await {
+            Err(e) => Err(anyhow!("Failed to parse inactivity warning: {e}")),
+            Ok(finishes_in) => Ok(Some(UiEvent::InactivityWarning { finishes_in })),
+        };
+    }
+
+    if entry.is_proposal().is_some() {
+        return match data.parse::<ProposalRequest>(entry).await {
+            Err(e) => Err(anyhow!("Failed to parse proposal: {e}")),
+            Ok(request)
+                if data
+                    .peer_author_matches(&request.from, &entry.author())
+                    .await? =>
+            {
+                Ok(Some(UiEvent::ProposalMade(request)))
+            }
+            Ok(_) => Ok(None),
+        };
+    }
+
+    if let Some(vote) = entry.is_proposal_vote() {
+        let (id, voter) = vote?;
+        if !data.peer_author_matches(&voter, &entry.author()).await? {
+            return Ok(None);
+        }
+        return match data.parse::<bool>(entry).await {
+            Err(e) => Err(anyhow!("Failed to parse vote from {voter}: {e}")),
+            Ok(accept) => Ok(Some(UiEvent::ProposalVoted {
+                id,
+                from: voter,
+                accept,
+            })),
+        };
+    }
+
+    if entry.is_proposal_result().is_some() {
+        if !data.host_author_matches(&entry.author()).await? {
+            return Ok(None);
+        }
+        return match data.parse::<ProposalOutcome>(entry).await {
+            Err(e) => Err(anyhow!("Failed to parse proposal outcome: {e}")),
+            Ok(outcome) => Ok(Some(UiEvent::ProposalResolved(outcome))),
+        };
+    }
+
+    if entry.is_vote_call().is_some() {
+        return match data.parse::<VoteCall>(entry).await {
+            Err(e) => Err(anyhow!("Failed to parse vote: {e}")),
+            Ok(call)
+                if data
+                    .peer_author_matches(&call.from, &entry.author())
+                    .await? =>
+            {
+                Ok(Some(UiEvent::VoteCalled(call)))
+            }
+            Ok(_) => Ok(None),
+        };
+    }
+
+    if entry.is_vote_result().is_some() {
+        if !data.host_author_matches(&entry.author()).await? {
+            return Ok(None);
+        }
+        return match data.parse::<VoteResult>(entry).await {
+            Err(e) => Err(anyhow!("Failed to parse vote result: {e}")),
+            Ok(result) => Ok(Some(UiEvent::VoteResult(result))),
+        };
+    }
+
+    if entry.is_turn_update() {
+        if !data.host_author_matches(&entry.author()).await? {
+            return Ok(None);
+        }
+        return match data.parse::<Option<TurnManager>>(entry).await {
+            Err(e) => Err(anyhow!("Failed to parse turn order: {e}")),
+            Ok(turns) => Ok(turns.and_then(|turns| {
+                let player = turns.current()?;
+                Some(UiEvent::TurnChanged {
+                    player,
+                    round: turns.round(),
+                })
+            })),
+        };
+    }
+
+    if entry.is_meta_update() {
+        if !data.host_author_matches(&entry.author()).await? {
+            return Ok(None);
+        }
+        return match data.parse::<RoomMeta>(entry).await {
+            Err(e) => Err(anyhow!("Failed to parse room details: {e}")),
+            Ok(meta) => Ok(Some(UiEvent::MetaUpdated(meta))),
+        };
+    }
+
+    if entry.is_rollback() {
+        if !data.host_author_matches(&entry.author()).await? {
+            return Ok(None);
+        }
+        return match data.parse::<RollbackNotice>(entry).await {
+            Err(e) => Err(anyhow!("Failed to parse rollback: {e}")),
+            Ok(notice) => Ok(Some(UiEvent::RolledBack {
+                label: notice.label,
+            })),
+        };
+    }
+
+    if let Some(node_id) = entry.is_team_update() {
+        let node_id = node_id?;
+        if !data.host_author_matches(&entry.author()).await? {
+            return Ok(None);
+        }
+        return match data.parse::<Option<TeamId>>(entry).await {
+            Err(e) => Err(anyhow!("Failed to parse team for {node_id}: {e}")),
+            Ok(team) => Ok(Some(UiEvent::TeamChanged {
+                peer: node_id,
+                team,
+            })),
+        };
+    }
+
+    if entry.is_compaction_mark() {
+        if !data.host_author_matches(&entry.author()).await? {
+            return Ok(None);
+        }
+        let mark = data
+            .parse::<CompactionMark>(entry)
+            .await
+            .map_err(|e| anyhow!("Failed to parse compaction mark: {e}"))?;
+        data.compact_own_entries(&mark).await?;
+        return Ok(None);
+    }
+
+    if entry.is_next_room() {
+        if !data.host_author_matches(&entry.author()).await? {
+            return Ok(None);
+        }
+        let ticket = data.content_bytes(entry).await?;
+        let ticket = String::from_utf8_lossy(&ticket).to_string();
+        return match RoomTicket::parse(&ticket) {
+            Err(e) => Err(anyhow!("Failed to parse next room ticket: {e}")),
+            Ok(_) => Ok(Some(UiEvent::Rehosted { ticket })),
+        };
+    }
+
+    if entry.is_room_closed() {
+        if !data.host_author_matches(&entry.author()).await? {
+            return Ok(None);
+        }
+        data.app_state_synced(AppState::Finished);
+        let reason = data.content_bytes(entry).await?;
+        let reason = String::from_utf8_lossy(&reason).to_string();
+        return Ok(Some(UiEvent::RoomClosed(reason)));
     }
 
     if let Some(node_id) = entry.is_quit_request() {
-        process_quit_entry(
-            data,
-            logic,
-            node_id?,
-            data.parse::<LeaveReason<G>>(entry).await?,
-        )
-        .await?;
+        let node_id = node_id?;
+        // Only the peer itself may say that it is leaving.
+        if !data.peer_author_matches(&node_id, &entry.author()).await? {
+            return Ok(None);
+        }
+        let reason = data.parse::<LeaveReason<G>>(entry).await?;
+        let forfeited = matches!(reason, LeaveReason::Forfeit);
+        process_quit_entry(data, logic, node_id, reason).await?;
+        if forfeited {
+            return Ok(Some(UiEvent::PlayerForfeited { id: node_id }));
+        }
     }
 
     Ok(None)
 }
 
-/// Process an action request entry on the host.
+/// Report a peer whose writes are being dropped, once per run of dropped writes.
+fn rate_limited<G: GameLogic>(
+    data: &StateData<G>,
+    kind: WriteKind,
+    peer: iroh::EndpointId,
+) -> Option<UiEvent<G>> {
+    data.take_rate_limit_report(kind, &peer)
+        .then_some(UiEvent::RateLimited { peer })
+}
+
+/// Publish the turn order the game reports for the latest state, if it changed.
+async fn publish_turns<G: GameLogic>(data: &StateData<G>, logic: &Arc<G>) -> Result<()> {
+    let turns = logic.turns(&data.get_game_state().await?);
+    if data.get_turns().await? != turns {
+        data.set_turns(turns.as_ref()).await?;
+    }
+    Ok(())
+}
+
+/// Process an action request entry on the host, along with any later actions from the same
+/// peer that were already waiting for it.
+///
+/// Each peer's actions are applied strictly in sequence order. Requests that arrive early stay
+/// in the doc until the gap before them is filled, and requests already handled are skipped.
 async fn process_action_entry<G: GameLogic>(
     entry: &Entry,
     data: &StateData<G>,
     logic: &Arc<G>,
-    (node_id, action_id): (iroh::EndpointId, String),
+    (node_id, seq): (iroh::EndpointId, u64),
+) -> Result<Option<UiEvent<G>>> {
+    let expected = data.last_processed_action_seq(&node_id).await? + 1;
+    let mut forged = None;
+    if seq == expected {
+        match process_action_request(entry, data, logic, node_id, seq).await? {
+            Handled::Processed => process_queued_actions(data, logic, node_id, seq + 1).await?,
+            Handled::Waiting => {}
+            Handled::Forged(author) => {
+                forged = Some(UiEvent::ForgedEntry {
+                    claimed: node_id,
+                    author: author.to_string(),
+                });
+            }
+        }
+    }
+    close_ready_rounds(data, logic).await?;
+    Ok(forged)
+}
+
+/// Process a peer's action requests that are already in the doc, starting from `seq`.
+pub(super) async fn process_queued_actions<G: GameLogic>(
+    data: &StateData<G>,
+    logic: &Arc<G>,
+    node_id: iroh::EndpointId,
+    mut seq: u64,
+) -> Result<()> {
+    while let Some(entry) = data.get_action_request(&node_id, seq).await? {
+        if !process_action_request(&entry, data, logic, node_id, seq)
+            .await?
+            .is_processed()
+        {
+            break;
+        }
+        seq += 1;
+    }
+    Ok(())
+}
+
+/// Apply every action request that reached the doc but was never processed by a host.
+///
+/// The doc doubles as the host's journal: requests stay in it until the host marks them
+/// processed, so a host that restarts, or a peer that is elected host, picks up whatever the
+/// previous host received but did not get to apply.
+///
+/// Each peer's requests are still applied in sequence order, but between peers the request
+/// with the earliest logical clock reading goes first, so every host replays them the same way.
+pub(super) async fn replay_pending_actions<G: GameLogic>(
+    data: &StateData<G>,
+    logic: &Arc<G>,
 ) -> Result<()> {
-    if data.has_processed_action(&node_id, &action_id).await? {
+    if !data.is_host().await.unwrap_or_default() {
         return Ok(());
     }
+    let mut queues = Vec::new();
+    for node_id in data.get_action_requesters().await? {
+        let next = data.last_processed_action_seq(&node_id).await? + 1;
+        queues.push((node_id, next));
+    }
+    loop {
+        let mut earliest: Option<(u64, iroh::EndpointId, usize, Entry)> = None;
+        for (index, (node_id, seq)) in queues.iter().enumerate() {
+            let Some(entry) = data.get_action_request(node_id, *seq).await? else {
+                continue;
+            };
+            // A request that fails to parse goes first, to be rejected.
+            let clock = data
+                .parse::<ActionRequest<G::GameAction>>(&entry)
+                .await
+                .map_or(0, |request| request.clock);
+            if earliest
+                .as_ref()
+                .is_none_or(|(first, first_id, ..)| (clock, node_id) < (*first, first_id))
+            {
+                earliest = Some((clock, *node_id, index, entry));
+            }
+        }
+        let Some((_, node_id, index, entry)) = earliest else {
+            break;
+        };
+        if process_action_request(&entry, data, logic, node_id, queues[index].1)
+            .await?
+            .is_processed()
+        {
+            queues[index].1 += 1;
+        } else {
+            queues.remove(index);
+        }
+    }
+    close_ready_rounds(data, logic).await
+}
+
+/// What the host made of the next action request in a peer's sequence.
+enum Handled {
+    /// Answered, so the peer's next request can follow.
+    Processed,
+    /// Not answered yet, e.g. held for its round.
+    Waiting,
+    /// Written by another author than the one the peer joined with, so ignored.
+    Forged(AuthorId),
+}
+
+impl Handled {
+    fn is_processed(&self) -> bool {
+        matches!(self, Self::Processed)
+    }
+}
+
+/// Handle the next action request in a peer's sequence.
+async fn process_action_request<G: GameLogic>(
+    entry: &Entry,
+    data: &StateData<G>,
+    logic: &Arc<G>,
+    node_id: iroh::EndpointId,
+    seq: u64,
+) -> Result<Handled> {
+    data.record_activity();
+    let request = data.parse::<ActionRequest<G::GameAction>>(entry).await;
+    if let Ok(request) = &request {
+        data.observe_clock(request.clock);
+    }
+    let action_id = request
+        .as_ref()
+        .map_or_else(|_| String::new(), |request| request.id.clone());
+    let reject = |reason: String| ActionResponse::engine_rejection(action_id.clone(), seq, reason);
+
+    // The response slot is the real peer's, so a forged request is left unanswered.
+    if !data.peer_author_matches(&node_id, &entry.author()).await? {
+        return Ok(Handled::Forged(entry.author()));
+    }
 
     if data
         .get_peer_info(&node_id)
         .await?
         .is_some_and(|peer| peer.is_observer)
     {
-        let result = ActionResult {
-            action_id,
-            accepted: false,
-            error: Some("Peer is an observer".to_string()),
-        };
-        data.set_action_result(&node_id, &result).await?;
-        data.mark_action_processed(&node_id, &result.action_id)
+        data.set_action_response(&node_id, &reject("Peer is an observer".to_string()))
             .await?;
-        return Ok(());
+        data.mark_action_processed(&node_id, seq).await?;
+        return Ok(Handled::Processed);
     }
 
-    if !data.peer_author_matches(&node_id, &entry.author()).await? {
-        let result = ActionResult {
-            action_id,
-            accepted: false,
-            error: Some("Action author does not match registered peer".to_string()),
-        };
-        data.set_action_result(&node_id, &result).await?;
-        return Ok(());
+    if !data.within_rate_limit(WriteKind::Action, &node_id).await? {
+        data.set_action_response(&node_id, &reject("Rate limit exceeded".to_string()))
+            .await?;
+        data.mark_action_processed(&node_id, seq).await?;
+        return Ok(Handled::Processed);
+    }
+
+    if let Ok(request) = &request {
+        if let Some(error) = data.reveal_error(&node_id, request).await? {
+            data.set_action_response(&node_id, &reject(error)).await?;
+            data.mark_action_processed(&node_id, seq).await?;
+            return Ok(Handled::Processed);
+        }
+        if let Some(reveal) = &request.reveal {
+            data.mark_revealed(&node_id, reveal.commitment).await?;
+        }
     }
 
-    let result = match data.parse::<ActionRequest<G::GameAction>>(entry).await {
-        Ok(request) if request.id == action_id => {
+    let request = match request {
+        // Held for its round, and processed once the round closes.
+        Ok(request)
+            if request.seq == seq
+                && data.collects_rounds().await
+                && !already_applied(data, &node_id, seq).await =>
+        {
+            data.hold_round_action(&node_id, request).await?;
+            return Ok(Handled::Waiting);
+        }
+        request => request,
+    };
+    let response = match request {
+        // A previous host applied this action but went down before responding to it.
+        Ok(request) if request.seq == seq && already_applied(data, &node_id, seq).await => {
+            ActionResponse {
+                action_id: request.id,
+                seq,
+                error: None,
+            }
+        }
+        Ok(request) if request.seq == seq => {
             apply_action_request(data, logic, &node_id, request).await?
         }
-        Ok(_) => ActionResult {
-            action_id,
-            accepted: false,
-            error: Some("Action id did not match action key".to_string()),
-        },
-        Err(e) => ActionResult {
-            action_id,
-            accepted: false,
-            error: Some(format!("Failed to parse action: {e}")),
-        },
+        Ok(_) => reject("Action sequence did not match action key".to_string()),
+        Err(e) => reject(format!("Failed to parse action: {e}")),
     };
-    data.set_action_result(&node_id, &result).await?;
-    data.mark_action_processed(&node_id, &result.action_id)
+    data.set_action_response(&node_id, &response).await?;
+    data.mark_action_processed(&node_id, seq).await?;
+    Ok(Handled::Processed)
+}
+
+/// Check whether the current game state was produced by a peer's action.
+async fn already_applied<G: GameLogic>(
+    data: &StateData<G>,
+    node_id: &iroh::EndpointId,
+    seq: u64,
+) -> bool {
+    data.get_game_state_entry()
         .await
+        .is_ok_and(|entry| entry.applied_action == Some((*node_id, seq)))
 }
 
 /// Process a host id update.
 async fn process_host_update<G: GameLogic>(
     entry: &Entry,
     data: &StateData<G>,
+    logic: &Arc<G>,
 ) -> Result<Option<UiEvent<G>>> {
-    match data.iroh()?.get_content_bytes(entry).await {
+    match data.content_bytes(entry).await {
         Err(e) => Err(anyhow!("Failed to parse HostId: {e}")),
         Ok(host_id) => {
             data.host_online();
             let host_id = endpoint_id_from_str(&String::from_utf8_lossy(&host_id))?;
+            trace::info!(host = %host_id, "Host changed");
+            if host_id == data.endpoint_id {
+                replay_pending_actions(data, logic).await?;
+            }
             let peer = data.get_peer_name(&host_id).await?;
             Ok(Some(UiEvent::Host(HostEvent::Changed { to: peer })))
         }
@@ -222,10 +883,12 @@ async fn process_quit_entry<G: GameLogic>(
     }
 
     if data.is_host().await.unwrap_or_default() {
-        if matches!(reason, LeaveReason::Forfeit) {
-            process_forfeit(data, logic, &node_id).await?;
-        } else {
-            data.set_peer_status(&node_id, PeerStatus::Offline).await?;
+        for node_id in with_seats(data, node_id).await {
+            if matches!(reason, LeaveReason::Forfeit) {
+                process_forfeit(data, logic, &node_id).await?;
+            } else {
+                disconnect_player(data, logic, &node_id).await?;
+            }
         }
     }
 
@@ -239,6 +902,7 @@ async fn elect_next_host<G: GameLogic>(
     old_host: &iroh::EndpointId,
 ) -> Result<()> {
     if let Some(new_host) = data.next_host_candidate(logic, Some(old_host)).await? {
+        trace::info!(old_host = %old_host, new_host = %new_host, "Electing the next host");
         data.set_host(&new_host).await?;
     }
     Ok(())
@@ -1,25 +1,54 @@
 use super::{
-    network::NetworkEvent,
+    network::{
+        ContentDownloads, NetworkEvent, PENDING_CONTENT_RETRY, path_changes,
+        recover_pending_entries,
+    },
+    supervisor::{Subscriptions, Supervisor},
     ui::{UiError, UiEvent},
 };
 use crate::{
-    GameLogic, GameRoom,
+    Activity, GameLogic, GameRoom, HeartbeatPolicy, RoomConfig,
     room::{
-        events::process::{process_joiner, process_leaver, process_update},
-        state::StateData,
+        events::{
+            catch_up::{CATCH_UP_INTERVAL, CatchUp},
+            compaction::publish_compaction_mark,
+            debounce::Debouncer,
+            dedicated::{DEDICATED_CHECK_INTERVAL, run_dedicated_lobby},
+            delivery::EventQueue,
+            entries::replay_pending_actions,
+            heartbeat::beat_and_check,
+            inactivity::check_inactivity,
+            metrics::{self, METRICS_INTERVAL},
+            peer_changes::PeerChanges,
+            process::{
+                process_joiner, process_leaver, process_realtime, process_state_frame,
+                process_turn, process_update,
+            },
+            rounds::close_ready_rounds,
+            tick::GameClock,
+            typing::{TYPING_TIMEOUT, TypingPeers},
+        },
+        state::{ActionError, GameKey as _, LockstepUpdate, PredictionUpdate, StateData},
+        timer,
     },
+    trace,
 };
 use anyhow::Result;
 
 use iroh_blobs::Hash;
-use iroh_docs::{Entry, engine::LiveEvent};
-use n0_future::{Stream, StreamExt as _};
-use std::{collections::HashMap, sync::Arc};
+use iroh_docs::Entry;
+use n0_future::{StreamExt as _, stream};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 use tokio::{sync::mpsc, task::JoinHandle};
 
+/// How long game state and peer list updates are held back while the app is in the
+/// background, see [crate::GameRoom::set_activity].
+const BACKGROUND_DEBOUNCE: Duration = Duration::from_secs(10);
+
 /// Public events your library will send to the game UI
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub enum HostEvent {
     /// Host has connected
     Online,
@@ -30,50 +59,439 @@ pub enum HostEvent {
 }
 
 impl<G: GameLogic> GameRoom<G> {
+    /// Start the room's event loop, reporting `initial` events before anything it receives.
     pub(crate) async fn start_event_loop(
         &mut self,
+        initial: Vec<UiEvent<G>>,
     ) -> Result<(mpsc::Receiver<UiEvent<G>>, JoinHandle<()>)> {
-        let sub = self.state.doc.subscribe().await?;
-        let (sender, receiver) = mpsc::channel(32); // Event channel for the UI
-
-        let state_data = self.state.clone();
-        let logic = self.logic.clone();
+        let (sender, receiver) = mpsc::channel(self.config.channel_capacity.max(1)); // Event channel for the UI
+        let supervisor = Supervisor {
+            sender,
+            coalescing: self.config.coalescing,
+            subscribers: self.subscribers.clone(),
+            state_data: self.state.clone(),
+            logic: self.logic.clone(),
+            config: self.config.clone(),
+            #[cfg(feature = "testing")]
+            faults: self.faults.clone(),
+        };
+        let (subscriptions, realtime) = supervisor.subscribe().await?;
+        self.realtime = realtime;
+        for ui_event in initial {
+            supervisor.sender.send(ui_event).await?;
+        }
 
-        let task_handle = tokio::spawn(async move {
-            event_loop(sub, sender, state_data, &logic).await;
-        });
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!(
+            "room",
+            id = %self.state.doc.id(),
+            peer = %self.state.endpoint_id,
+        );
+        let task = supervisor.run(subscriptions);
+        #[cfg(feature = "tracing")]
+        let task = tracing::Instrument::instrument(task, span);
+        let task_handle = tokio::spawn(task);
         Ok((receiver, task_handle))
     }
 }
 
 /// Main event loop that listens for iroh doc events and processes them.
-async fn event_loop<G: GameLogic>(
-    mut sub: impl Stream<Item = Result<LiveEvent>> + Unpin,
-    sender: mpsc::Sender<UiEvent<G>>,
+pub(super) async fn event_loop<G: GameLogic>(
+    subscriptions: Subscriptions,
+    mut queue: EventQueue<G>,
     state_data: Arc<StateData<G>>,
-    logic: &Arc<G>,
+    logic: Arc<G>,
+    config: RoomConfig,
 ) {
+    let Subscriptions {
+        mut sub,
+        chat_sub,
+        realtime,
+        mut frames,
+    } = subscriptions;
+    let logic = &logic;
+    let inactivity = config.inactivity;
+    let sender = queue.sender();
     let mut pending_entries: HashMap<Hash, Entry> = HashMap::new();
+    let mut content_downloads = ContentDownloads::new();
+    let mut inactivity_check =
+        timer(inactivity.map_or(Duration::from_secs(60), |policy| policy.check_interval()));
+    let mut inactivity_warned = false;
+    let mut activity = state_data.watch_activity();
+    let mut background = *activity.borrow_and_update() == Activity::Background;
+    let heartbeat = config.heartbeat;
+    let mut heartbeat_tick = timer(heartbeat_period(heartbeat, background));
+    let compaction = config.compaction;
+    let mut compaction_tick =
+        timer(compaction.map_or(Duration::from_secs(60), |policy| policy.interval));
+    let tick = config.tick;
+    let mut game_tick = timer(tick.unwrap_or(Duration::from_secs(60)));
+    let mut game_clock = GameClock::new(config.state_cadence);
+    let round_deadline = state_data.round_deadline();
+    let mut round_tick = timer(round_deadline.map_or(Duration::from_secs(60), |deadline| {
+        (deadline / 4).min(Duration::from_secs(1))
+    }));
+    let mut debouncer = Debouncer::new(match background {
+        true => config.debounce.max(BACKGROUND_DEBOUNCE),
+        false => config.debounce,
+    });
+    let mut peer_changes = PeerChanges::new(&state_data).await;
+    let mut catch_up =
+        CatchUp::new(!config.await_initial_sync && !state_data.is_host().await.unwrap_or_default());
+    let dedicated = config.dedicated;
+    let mut dedicated_tick = tokio::time::interval(DEDICATED_CHECK_INTERVAL);
+    dedicated_tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut finished_since = None;
+    let mut catch_up_tick = tokio::time::interval(CATCH_UP_INTERVAL);
+    catch_up_tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let network_stats = config.network_stats;
+    let mut network_stats_tick = timer(network_stats.unwrap_or(Duration::from_secs(60)));
+    let mut connection_paths = HashMap::new();
+    let mut metrics_tick = tokio::time::interval(METRICS_INTERVAL);
+    metrics_tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    // Pick up actions that a previous session of this host received but never applied
+    if let Err(e) = replay_pending_actions(&state_data, logic).await {
+        let error = UiError::HostTask {
+            task: "journal".to_string(),
+            message: e.to_string(),
+        };
+        queue.push(UiEvent::Error(error));
+    }
+    // Catch up with the steps a lock-step game took while this peer was away
+    let lockstep = state_data.is_lockstep();
+    if lockstep && let Err(e) = state_data.advance_lockstep(logic, None).await {
+        let error = UiError::HostTask {
+            task: "lockstep".to_string(),
+            message: e.to_string(),
+        };
+        queue.push(UiEvent::Error(error));
+    }
+    let mut content_retry = tokio::time::interval(PENDING_CONTENT_RETRY);
+    content_retry.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut has_chat = chat_sub.is_some();
+    let mut chat_sub = chat_sub.unwrap_or_else(|| stream::pending().boxed());
+    let mut has_realtime = realtime.is_some();
+    let mut typing = TypingPeers::default();
+    let mut typing_tick = tokio::time::interval(TYPING_TIMEOUT / 5);
+    typing_tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut realtime = match realtime {
+        Some(realtime) if !background => realtime.boxed(),
+        _ => stream::pending().boxed(),
+    };
+    let mut network_changes = match state_data.node() {
+        Ok(node) => node.network_changes().boxed(),
+        Err(_) => stream::pending().boxed(),
+    };
     loop {
+        if sender.is_closed() {
+            trace::debug!("Event receiver dropped, stopping the event loop");
+            break; // Receiver dropped, exit loop
+        }
         tokio::select! {
             // Listen for iroh doc events
-            Some(Ok(event)) = sub.next() => {
+            next = sub.next() => {
+                let event = match next {
+                    Some(Ok(event)) => event,
+                    Some(Err(_e)) => {
+                        trace::warning!(error = %_e, "Game document subscription error");
+                        continue;
+                    }
+                    None => break, // Stream finished
+                };
                 let network_event = match NetworkEvent::parse(event, &mut pending_entries)  {
                     Some(event) => event,
                     None => continue,
                 };
+                if let NetworkEvent::Update(entry) = &network_event
+                    && entry.is_peer_entry()
+                    && let Some(ui_event) = peer_changes.entry_changed(entry, &state_data).await
+                {
+                    queue.push(ui_event);
+                }
+                let your_turn = match &network_event {
+                    NetworkEvent::Update(entry) => process_turn(entry, &state_data).await,
+                    _ => None,
+                };
                 let maybe_event = match network_event {
+                    NetworkEvent::Update(entry) if debouncer.hold_entry(&entry) => None,
                     NetworkEvent::Update(entry) => process_update(&entry, &state_data, logic).await,
                     NetworkEvent::Joiner(id) => process_joiner(id, &state_data, logic ).await,
                     NetworkEvent::Leaver(id) => process_leaver(id, &state_data, logic).await,
-                    NetworkEvent::SyncFailed(reason) => Some(UiEvent::Error(UiError::SyncFailed(reason))),
-                    NetworkEvent::SyncSucceeded => None, /* Do nothing for now */
+                    NetworkEvent::SyncFailed(reason) => {
+                        metrics::sync_failed(&state_data);
+                        Some(UiEvent::Error(UiError::SyncFailed(reason)))
+                    }
+                    NetworkEvent::SyncSucceeded => {
+                        trace::debug!("Sync succeeded");
+                        None
+                    }
+                };
+                for ui_event in maybe_event.into_iter().chain(your_turn) {
+                    for ui_event in debouncer.hold(ui_event, &state_data, logic).await {
+                        queue.push(ui_event);
+                    }
+                }
+            },
+            // Chat entries arrive on their own document; its peer and sync events are
+            // already covered by the game document's subscription.
+            next = chat_sub.next(), if has_chat => {
+                let event = match next {
+                    Some(Ok(event)) => event,
+                    Some(Err(_e)) => {
+                        trace::warning!(error = %_e, "Chat document subscription error");
+                        continue;
+                    }
+                    // Losing chat must not take the game down with it.
+                    None => {
+                        has_chat = false;
+                        continue;
+                    }
+                };
+                let Some(NetworkEvent::Update(entry)) = NetworkEvent::parse(event, &mut pending_entries) else {
+                    continue;
+                };
+                if let Some(ui_event) = process_update(&entry, &state_data, logic).await {
+                    queue.push(ui_event);
+                }
+            },
+            // Lossy real-time traffic goes straight to the application
+            next = realtime.next(), if has_realtime => {
+                // The channel is lossy anyway, so losing it must not take the room down.
+                let Some(next) = next else {
+                    has_realtime = false;
+                    continue;
+                };
+                let Ok(event) = next else { continue };
+                if let Some(ui_event) = process_realtime(event, &state_data, &mut typing).await {
+                    queue.push(ui_event);
+                }
+            },
+            // Show the host's ticked states between the ones it writes
+            Some(frame) = frames.recv() => {
+                if let Some(ui_event) = process_state_frame(frame, &state_data).await {
+                    queue.push(ui_event);
+                }
+            },
+            // Stop typing indicators whose peers have gone quiet
+            _ = typing_tick.tick(), if !typing.is_empty() => {
+                for ui_event in typing.expire() {
+                    queue.push(ui_event);
+                }
+            },
+            // Report this peer's own actions without waiting for the host
+            updates = state_data.prediction_updates(), if config.predict => {
+                for update in updates {
+                    let ui_event = match update {
+                        PredictionUpdate::Predicted(state) => UiEvent::GameState(state),
+                        PredictionUpdate::Corrected(state) => UiEvent::PredictionCorrected(state),
+                    };
+                    for ui_event in debouncer.hold(ui_event, &state_data, logic).await {
+                        queue.push(ui_event);
+                    }
+                }
+            },
+            // Report steps of a lock-step game, ours and the other player's
+            updates = state_data.lockstep_updates(), if lockstep => {
+                for update in updates {
+                    let ui_event = match update {
+                        LockstepUpdate::Advanced(state) => UiEvent::GameState(state),
+                        LockstepUpdate::Overtaken { player, step, action_id } => UiEvent::ActionRejected {
+                            player,
+                            seq: step,
+                            action_id,
+                            error: ActionError::Engine("Another player took this step first".to_string()),
+                        },
+                        LockstepUpdate::Diverged { step, peer } => UiEvent::LockstepDiverged { step, peer },
+                    };
+                    for ui_event in debouncer.hold(ui_event, &state_data, logic).await {
+                        queue.push(ui_event);
+                    }
+                }
+            },
+            // Don't leave entries waiting on content that iroh-docs will never report as ready
+            _ = content_retry.tick(), if !pending_entries.is_empty() => {
+                let recovered =
+                    recover_pending_entries(&state_data, &mut pending_entries, &mut content_downloads);
+                let Ok(entries) = recovered.await else {
+                    continue; // Try again on the next tick
+                };
+                for entry in entries {
+                    if entry.is_peer_entry()
+                        && let Some(ui_event) = peer_changes.entry_changed(&entry, &state_data).await
+                    {
+                        queue.push(ui_event);
+                    }
+                    if debouncer.hold_entry(&entry) {
+                        continue;
+                    }
+                    let your_turn = process_turn(&entry, &state_data).await;
+                    let maybe_event = process_update(&entry, &state_data, logic).await;
+                    for ui_event in maybe_event.into_iter().chain(your_turn) {
+                        for ui_event in debouncer.hold(ui_event, &state_data, logic).await {
+                            queue.push(ui_event);
+                        }
+                    }
+                }
+            },
+            // Report the peer list and game state once a burst of updates has settled
+            _ = debouncer.elapsed() => {
+                for ui_event in debouncer.release(&state_data, logic).await {
+                    queue.push(ui_event);
+                }
+            },
+            // Report a joiner's progress until the room's state has synced
+            _ = catch_up_tick.tick(), if catch_up.is_active() => {
+                let Ok(events) = catch_up.check(&state_data).await else {
+                    continue; // Try again on the next tick
                 };
-                if let Some(ui_event) = maybe_event && sender.send(ui_event).await.is_err() {
+                for ui_event in events {
+                    queue.push(ui_event);
+                }
+            },
+            // Let the host step in when an in-progress game has gone quiet
+            _ = inactivity_check.tick(), if inactivity.is_some() => {
+                let Some(policy) = inactivity.as_ref() else { continue };
+                if let Err(e) = check_inactivity(&state_data, logic, policy, &mut inactivity_warned).await {
+                    let error = UiError::HostTask {
+                        task: "inactivity".to_string(),
+                        message: e.to_string(),
+                    };
+                    queue.push(UiEvent::Error(error));
+                }
+            },
+            // Tell the room we're still here, and notice peers that have stopped saying so
+            _ = heartbeat_tick.tick(), if heartbeat.is_some() => {
+                let Some(policy) = heartbeat.as_ref() else { continue };
+                if let Err(e) = beat_and_check(&state_data, logic, policy).await {
+                    let error = UiError::HostTask {
+                        task: "heartbeat".to_string(),
+                        message: e.to_string(),
+                    };
+                    queue.push(UiEvent::Error(error));
+                }
+            },
+            // Let go of chat and action history the room no longer needs
+            _ = compaction_tick.tick(), if compaction.is_some() => {
+                let Some(policy) = compaction.as_ref() else { continue };
+                if let Err(e) = publish_compaction_mark(&state_data, policy).await {
+                    let error = UiError::HostTask {
+                        task: "compaction".to_string(),
+                        message: e.to_string(),
+                    };
+                    queue.push(UiEvent::Error(error));
+                }
+            },
+            // Move timed games along even when nobody acts
+            _ = game_tick.tick(), if tick.is_some() => {
+                match game_clock.advance(&state_data, logic).await {
+                    Ok(Some(ui_event)) => queue.push(ui_event),
+                    Ok(None) => {}
+                    Err(e) => {
+                        let error = UiError::HostTask {
+                            task: "tick".to_string(),
+                            message: e.to_string(),
+                        };
+                        queue.push(UiEvent::Error(error));
+                    }
+                }
+            },
+            // Close rounds whose deadline has passed without every player
+            _ = round_tick.tick(), if round_deadline.is_some() => {
+                if let Err(e) = close_ready_rounds(&state_data, logic).await {
+                    let error = UiError::HostTask {
+                        task: "rounds".to_string(),
+                        message: e.to_string(),
+                    };
+                    queue.push(UiEvent::Error(error));
+                }
+            },
+            // Start games and reopen the lobby without a host player
+            _ = dedicated_tick.tick(), if dedicated.is_some() => {
+                let Some(policy) = dedicated.as_ref() else { continue };
+                if let Err(e) = run_dedicated_lobby(&state_data, logic, policy, &mut finished_since).await {
+                    let error = UiError::HostTask {
+                        task: "dedicated".to_string(),
+                        message: e.to_string(),
+                    };
+                    queue.push(UiEvent::Error(error));
+                }
+            },
+            // Do as little as possible while the app is in the background, and catch up as
+            // soon as it comes back
+            Ok(()) = activity.changed() => {
+                let now_background = *activity.borrow_and_update() == Activity::Background;
+                if now_background == background {
+                    continue;
+                }
+                background = now_background;
+                heartbeat_tick = timer(heartbeat_period(heartbeat, background));
+                if background {
+                    debouncer.set_window(config.debounce.max(BACKGROUND_DEBOUNCE));
+                    // Leaving the topic's receiver stops gossip handing us cursor moves and
+                    // typing indicators nobody is there to see.
+                    realtime = stream::pending().boxed();
+                    continue;
+                }
+                debouncer.set_window(config.debounce);
+                for ui_event in debouncer.release(&state_data, logic).await {
+                    queue.push(ui_event);
+                }
+                if config.realtime && let Ok(topic) = state_data.join_realtime().await {
+                    let (_, receiver) = topic.split();
+                    realtime = receiver.boxed();
+                    has_realtime = true;
+                }
+            },
+            // Sync again once the device has moved to another network, as the connections the
+            // room's documents and real-time channel relied on may be gone
+            _ = state_data.network_changed(&mut network_changes) => {
+                if let Err(e) = state_data.restart_sync().await {
+                    queue.push(UiEvent::Error(UiError::SyncFailed(e.to_string())));
+                    continue;
+                }
+                if config.realtime
+                    && !background
+                    && let Ok(topic) = state_data.join_realtime().await
+                {
+                    let (_, receiver) = topic.split();
+                    realtime = receiver.boxed();
+                    has_realtime = true;
+                }
+                queue.push(UiEvent::Reconnected);
+            },
+            // Keep ping indicators up to date
+            _ = network_stats_tick.tick(), if network_stats.is_some() && !background => {
+                if let Ok(stats) = state_data.network_stats().await {
+                    queue.push(UiEvent::NetworkStats(stats));
+                }
+                if let Ok(changes) = path_changes(&state_data, &mut connection_paths).await {
+                    for change in changes {
+                        queue.push(change);
+                    }
+                }
+            },
+            // Keep the room's gauges up to date for whoever collects metrics
+            _ = metrics_tick.tick(), if cfg!(feature = "metrics") => {
+                metrics::sample(&state_data, queue.depth()).await;
+            },
+            // Hand queued events to the application as it makes room for them
+            permit = sender.reserve(), if !queue.is_empty() => {
+                let Ok(permit) = permit else {
                     break; // Receiver dropped, exit loop
+                };
+                if let Some(ui_event) = queue.pop() {
+                    permit.send(ui_event);
                 }
             },
-            else => break, // Stream finished
         }
     }
 }
+
+/// Time between this peer's heartbeats, if it sends any.
+fn heartbeat_period(heartbeat: Option<HeartbeatPolicy>, background: bool) -> Duration {
+    match heartbeat {
+        Some(policy) if background => policy.background_interval(),
+        Some(policy) => policy.period(),
+        None => Duration::from_secs(60),
+    }
+}
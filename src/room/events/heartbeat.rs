@@ -0,0 +1,32 @@
+//! Heartbeats, and the host's check for peers that have gone silent.
+
+use super::connections::process_leaver;
+use crate::{GameLogic, HeartbeatPolicy, PeerStatus, room::state::StateData};
+use anyhow::Result;
+use std::sync::Arc;
+
+/// Announce that this peer is still here and, while hosting, mark silent peers offline.
+///
+/// Only peers that have sent at least one heartbeat are checked, so peers running without a
+/// heartbeat policy are left to gossip's own neighbour tracking.
+pub(super) async fn beat_and_check<G: GameLogic>(
+    data: &StateData<G>,
+    logic: &Arc<G>,
+    policy: &HeartbeatPolicy,
+) -> Result<()> {
+    data.send_heartbeat().await?;
+    if !data.is_host().await? {
+        return Ok(());
+    }
+
+    let peers = data.get_peer_list().await?;
+    for peer_id in data.silent_peers(policy.timeout()) {
+        let still_online = peers
+            .get(&peer_id)
+            .is_some_and(|peer| peer.status == PeerStatus::Online);
+        if peer_id != data.endpoint_id && still_online {
+            process_leaver(peer_id, data, logic).await;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,48 @@
+//! Host-side inactivity policy for games in progress.
+
+use crate::{FinishReason, GameLogic, GameOutcome, InactivityPolicy, room::state::StateData};
+use anyhow::Result;
+use std::sync::Arc;
+
+/// Warn about, or finish, a game that has gone without actions for too long.
+///
+/// `warned` tracks whether a warning has already been published for the current
+/// idle stretch, so peers are only warned once.
+pub(super) async fn check_inactivity<G: GameLogic>(
+    data: &StateData<G>,
+    logic: &Arc<G>,
+    policy: &InactivityPolicy,
+    warned: &mut bool,
+) -> Result<()> {
+    if !data.is_host().await.unwrap_or_default()
+        || !data
+            .get_app_state()
+            .await
+            .is_ok_and(|state| state.is_in_game())
+    {
+        *warned = false;
+        return Ok(());
+    }
+
+    let idle_for = data.idle_for();
+    if idle_for >= policy.finish_after {
+        let mut current_state = data.get_game_state().await?;
+        let players = data.get_peer_list().await?;
+        logic.handle_inactivity_timeout(&players, &mut current_state)?;
+        data.set_game_state(&current_state).await?;
+        let outcome = logic
+            .game_outcome(&current_state)
+            .unwrap_or_else(|| GameOutcome::new(FinishReason::Inactivity));
+        data.finish_game(outcome).await?;
+        *warned = false;
+    } else if idle_for >= policy.warn_after {
+        if !*warned {
+            data.set_inactivity_warning(policy.finish_after - idle_for)
+                .await?;
+            *warned = true;
+        }
+    } else {
+        *warned = false;
+    }
+    Ok(())
+}
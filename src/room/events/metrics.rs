@@ -0,0 +1,79 @@
+//! Room metrics, published through the `metrics` crate behind the `metrics` feature.
+//!
+//! Every metric carries a `room` label holding the room's document ID, so a server hosting
+//! many rooms can tell them apart. Install any `metrics` recorder, e.g. a Prometheus exporter,
+//! to collect them. Without the feature these functions do nothing.
+#![cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+
+use crate::{GameLogic, room::state::StateData};
+use std::time::Duration;
+
+/// How often the event loop samples the room's gauges.
+pub(super) const METRICS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Entries the event loop has processed, from both documents.
+#[cfg(feature = "metrics")]
+const ENTRIES_PROCESSED: &str = "p2p_game_entries_processed_total";
+/// Actions the host applied.
+#[cfg(feature = "metrics")]
+const ACTIONS_APPLIED: &str = "p2p_game_actions_applied_total";
+/// Actions the host rejected, by the game or by the engine.
+#[cfg(feature = "metrics")]
+const ACTIONS_REJECTED: &str = "p2p_game_actions_rejected_total";
+/// Syncs with a peer that failed.
+#[cfg(feature = "metrics")]
+const SYNC_FAILURES: &str = "p2p_game_sync_failures_total";
+/// Peers in the room this endpoint is connected to.
+#[cfg(feature = "metrics")]
+const CONNECTED_PEERS: &str = "p2p_game_connected_peers";
+/// Total size of the values in the room's documents.
+#[cfg(feature = "metrics")]
+const DOC_BYTES: &str = "p2p_game_doc_bytes";
+/// Entries in the room's documents.
+#[cfg(feature = "metrics")]
+const DOC_ENTRIES: &str = "p2p_game_doc_entries";
+/// Events waiting for the application to receive them.
+#[cfg(feature = "metrics")]
+const EVENT_QUEUE_DEPTH: &str = "p2p_game_event_queue_depth";
+
+#[cfg(feature = "metrics")]
+fn room<G: GameLogic>(data: &StateData<G>) -> [(&'static str, String); 1] {
+    [("room", data.doc.id().to_string())]
+}
+
+pub(super) fn entry_processed<G: GameLogic>(data: &StateData<G>) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(ENTRIES_PROCESSED, &room(data)).increment(1);
+}
+
+pub(super) fn action_applied<G: GameLogic>(data: &StateData<G>) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(ACTIONS_APPLIED, &room(data)).increment(1);
+}
+
+pub(super) fn action_rejected<G: GameLogic>(data: &StateData<G>) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(ACTIONS_REJECTED, &room(data)).increment(1);
+}
+
+pub(super) fn sync_failed<G: GameLogic>(data: &StateData<G>) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(SYNC_FAILURES, &room(data)).increment(1);
+}
+
+/// Sample the room's gauges, with `queue_depth` events waiting for the application.
+pub(super) async fn sample<G: GameLogic>(data: &StateData<G>, queue_depth: usize) {
+    #[cfg(feature = "metrics")]
+    {
+        let labels = room(data);
+        if let Ok(stats) = data.network_stats().await {
+            metrics::gauge!(CONNECTED_PEERS, &labels).set(stats.len() as f64);
+        }
+        if let Ok(stats) = data.doc_stats().await {
+            metrics::gauge!(DOC_BYTES, &labels).set(stats.blob_bytes as f64);
+            let entries = stats.game_entries + stats.chat_entries;
+            metrics::gauge!(DOC_ENTRIES, &labels).set(entries as f64);
+        }
+        metrics::gauge!(EVENT_QUEUE_DEPTH, &labels).set(queue_depth as f64);
+    }
+}
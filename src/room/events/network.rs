@@ -2,10 +2,12 @@
 //!
 //! This module handles events such as new document entries, neighbor changes, and synchronization results.
 //! The main function is `NetworkEvent::parse`, which takes a live event from the iroh engine and produces
-//! an optional `NetworkEvent` that can be emitted to the UI.
+//! an optional `NetworkEvent` that can be emitted to the UI, and reports changes to the paths
+//! traffic to the room's peers takes.
 
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Duration};
 
+use anyhow::Result;
 use iroh::EndpointId;
 use iroh_blobs::Hash;
 use iroh_docs::{
@@ -13,6 +15,33 @@ use iroh_docs::{
     engine::{LiveEvent, SyncEvent},
 };
 
+use crate::{ConnectionPath, GameLogic, UiEvent, room::state::StateData};
+use n0_future::{Stream, StreamExt as _, stream, task::AbortOnDropHandle};
+use tokio::sync::mpsc;
+
+/// How often entries still waiting for their content are checked again.
+pub(super) const PENDING_CONTENT_RETRY: Duration = Duration::from_secs(1);
+
+/// Drain a document subscription into an unbounded buffer as fast as it produces events.
+///
+/// iroh-docs waits for room in a subscriber's channel before it handles any other request
+/// for the document. The event loop reads and writes the document while it handles an event,
+/// so a burst of entries, e.g. a backlog of queued actions, could otherwise fill the channel
+/// while the loop waits on a request that only completes once the channel drains.
+pub(super) fn buffered<T: Send + 'static>(
+    mut events: impl Stream<Item = T> + Send + Unpin + 'static,
+) -> impl Stream<Item = T> + Send + Unpin + 'static {
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Some(event) = events.next().await {
+            if sender.send(event).is_err() {
+                break; // Event loop finished
+            }
+        }
+    });
+    stream::poll_fn(move |cx| receiver.poll_recv(cx))
+}
+
 /// Network events that can be emitted to the UI.
 #[derive(Debug)]
 pub enum NetworkEvent {
@@ -33,6 +62,12 @@ impl NetworkEvent {
     pub fn parse(event: LiveEvent, pending_entries: &mut HashMap<Hash, Entry>) -> Option<Self> {
         use iroh_docs::ContentStatus::{Complete, Incomplete, Missing};
         match event {
+            // Deletions leave an empty entry behind, which has nothing to report.
+            LiveEvent::InsertLocal { entry } | LiveEvent::InsertRemote { entry, .. }
+                if entry.content_len() == 0 =>
+            {
+                None
+            }
             LiveEvent::InsertLocal { entry } => Some(Self::Update(entry)),
             LiveEvent::InsertRemote {
                 entry,
@@ -58,3 +93,89 @@ impl NetworkEvent {
         }
     }
 }
+
+/// Downloads of pending entries' content that are still running, stopped when dropped.
+pub(super) type ContentDownloads = HashMap<Hash, AbortOnDropHandle<()>>;
+
+/// Release pending entries whose content is now stored locally, and ask the room's online peers
+/// for the content of the rest.
+///
+/// iroh-docs only reports content as ready after downloading it, and it only starts that download
+/// when a neighbour announces the content after this peer has seen the entry. An entry relayed by
+/// a peer that did not have its content yet could otherwise stay pending forever.
+///
+/// Content already being downloaded is not asked for again until that download finishes.
+pub(super) async fn recover_pending_entries<G: GameLogic>(
+    data: &StateData<G>,
+    pending_entries: &mut HashMap<Hash, Entry>,
+    downloads: &mut ContentDownloads,
+) -> Result<Vec<Entry>> {
+    downloads
+        .retain(|hash, download| pending_entries.contains_key(hash) && !download.is_finished());
+    let iroh = data.iroh()?;
+    let mut ready = Vec::new();
+    let mut missing = Vec::new();
+    for hash in pending_entries.keys() {
+        if iroh.blobs().has(*hash).await? {
+            ready.push(*hash);
+        } else {
+            missing.push(*hash);
+        }
+    }
+
+    let providers: Vec<EndpointId> = data
+        .get_peer_list()
+        .await?
+        .iter()
+        .filter(|(id, peer)| **id != data.endpoint_id && peer.status.is_online())
+        .map(|(id, _)| *id)
+        .collect();
+    if !providers.is_empty() {
+        for hash in missing {
+            if downloads.contains_key(&hash) {
+                continue;
+            }
+            let iroh = iroh.clone();
+            let providers = providers.clone();
+            let download = tokio::spawn(async move {
+                let download = iroh.download_blob(hash, providers);
+                tokio::time::timeout(PENDING_CONTENT_RETRY * 5, download)
+                    .await
+                    .ok();
+            });
+            downloads.insert(hash, AbortOnDropHandle::new(download));
+        }
+    }
+
+    Ok(ready
+        .into_iter()
+        .filter_map(|hash| pending_entries.remove(&hash))
+        .collect())
+}
+
+/// Report the peers whose connection path has changed since `paths` was last updated.
+pub(super) async fn path_changes<G: GameLogic>(
+    data: &StateData<G>,
+    paths: &mut HashMap<EndpointId, ConnectionPath>,
+) -> Result<Vec<UiEvent<G>>> {
+    let current = data.connection_paths().await?;
+    let mut changes: Vec<UiEvent<G>> = current
+        .iter()
+        .filter(|(peer, path)| paths.get(*peer) != Some(*path))
+        .map(|(peer, path)| UiEvent::ConnectionPathChanged {
+            peer: *peer,
+            path: Some(path.clone()),
+        })
+        .collect();
+    changes.extend(
+        paths
+            .keys()
+            .filter(|peer| !current.contains_key(*peer))
+            .map(|peer| UiEvent::ConnectionPathChanged {
+                peer: *peer,
+                path: None,
+            }),
+    );
+    *paths = current;
+    Ok(changes)
+}
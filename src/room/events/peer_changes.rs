@@ -0,0 +1,58 @@
+//! Granular peer events.
+//!
+//! [UiEvent::Peer] carries the whole peer list, which leaves the UI to work out what changed.
+//! The event loop also compares each peer it hears about with how it last reported them, and
+//! reports the difference as [UiEvent::PeerJoined], [UiEvent::PeerLeft] or
+//! [UiEvent::PeerUpdated].
+
+use super::ui::UiEvent;
+use crate::{GameLogic, PeerMap, room::state::StateData};
+use iroh_docs::Entry;
+
+/// The peers as they were last reported.
+pub(super) struct PeerChanges {
+    reported: PeerMap,
+}
+
+impl PeerChanges {
+    /// Start from the peers already in the room, whose arrival was reported with the room's
+    /// first [UiEvent::Peer].
+    pub(super) async fn new<G: GameLogic>(data: &StateData<G>) -> Self {
+        Self {
+            reported: data.mirrored_peer_list().await.unwrap_or_default(),
+        }
+    }
+
+    /// Update the mirrored peer list with a peer entry, and report how the peer changed.
+    pub(super) async fn entry_changed<G: GameLogic>(
+        &mut self,
+        entry: &Entry,
+        data: &StateData<G>,
+    ) -> Option<UiEvent<G>> {
+        let peer_id = data.refresh_peer(entry.key()).await?;
+        let peer = data.mirrored_peer(&peer_id).await.ok()?;
+        let before = match &peer {
+            Some(peer) => self.reported.insert(peer_id, peer.clone()),
+            None => self.reported.remove(&peer_id),
+        };
+        let left = || async {
+            UiEvent::PeerLeft {
+                id: peer_id,
+                reason: data.get_leave_reason(&peer_id).await.unwrap_or_default(),
+            }
+        };
+        match (before, peer) {
+            (None, None) => None,
+            (None, Some(peer)) => Some(UiEvent::PeerJoined(peer)),
+            (Some(_), None) => Some(left().await),
+            (Some(before), Some(peer)) if before == peer => None,
+            (Some(before), Some(peer)) => {
+                match (before.status.is_online(), peer.status.is_online()) {
+                    (true, false) => Some(left().await),
+                    (false, true) => Some(UiEvent::PeerJoined(peer)),
+                    _ => Some(UiEvent::PeerUpdated(peer)),
+                }
+            }
+        }
+    }
+}
@@ -3,12 +3,26 @@
 use super::{
     connections,
     entries::process_entry,
+    metrics,
+    typing::TypingPeers,
     ui::{UiError, UiEvent},
 };
-use crate::{GameLogic, room::state::StateData};
+use crate::{
+    GameLogic, TurnManager,
+    networking::StateFrame,
+    room::{
+        realtime::{GAME_MESSAGE_KEY, RealtimeMessage, RealtimePayload},
+        state::{GameKey as _, StateData},
+    },
+    trace,
+};
 use iroh::EndpointId;
 use iroh_docs::sync::Entry;
-use std::sync::Arc;
+use iroh_gossip::api::Event;
+use std::{
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
 
 /// Process an update event from the iroh doc.
 pub(super) async fn process_update<G: GameLogic>(
@@ -16,6 +30,12 @@ pub(super) async fn process_update<G: GameLogic>(
     state_data: &Arc<StateData<G>>,
     logic: &Arc<G>,
 ) -> Option<UiEvent<G>> {
+    trace::debug!(
+        key = %String::from_utf8_lossy(entry.key()),
+        author = %entry.author(),
+        "Processing entry",
+    );
+    metrics::entry_processed(state_data);
     match process_entry(entry, state_data, logic).await {
         Ok(maybe_event) => maybe_event,
         Err(e) => Some(UiEvent::Error(UiError::EventProcessing {
@@ -26,6 +46,33 @@ pub(super) async fn process_update<G: GameLogic>(
     }
 }
 
+/// Tell this endpoint when a turn update hands the turn to one of its own players.
+///
+/// The deadline counts from when the host passed the turn on, so a peer catching up late
+/// still learns how long is left.
+pub(super) async fn process_turn<G: GameLogic>(
+    entry: &Entry,
+    state_data: &Arc<StateData<G>>,
+) -> Option<UiEvent<G>> {
+    if !entry.is_turn_update() || !state_data.host_author_matches(&entry.author()).await.ok()? {
+        return None;
+    }
+    let turns = state_data
+        .parse::<Option<TurnManager>>(entry)
+        .await
+        .ok()??;
+    let player = turns.current()?;
+    if !state_data.is_local_player(&player).await.ok()? {
+        return None;
+    }
+    let passed_at = UNIX_EPOCH + Duration::from_micros(entry.timestamp());
+    Some(UiEvent::YourTurn {
+        player,
+        round: turns.round(),
+        deadline: turns.time_limit().map(|limit| passed_at + limit),
+    })
+}
+
 /// Process a peer connection event.
 pub(super) async fn process_joiner<G: GameLogic>(
     id: EndpointId,
@@ -43,3 +90,53 @@ pub(super) async fn process_leaver<G: GameLogic>(
 ) -> Option<UiEvent<G>> {
     connections::process_leaver(id, state_data, logic).await
 }
+
+/// Process a message from the room's real-time channel.
+///
+/// Messages that fail to decode, carry a bad signature, or come from someone outside the
+/// room are dropped, like any other lost real-time message.
+pub(super) async fn process_realtime<G: GameLogic>(
+    event: Event,
+    state_data: &Arc<StateData<G>>,
+    typing: &mut TypingPeers,
+) -> Option<UiEvent<G>> {
+    let Event::Received(message) = event else {
+        return None;
+    };
+    let message: RealtimeMessage = postcard::from_bytes(&message.content).ok()?;
+    if message.from == state_data.endpoint_id || message.verify().is_err() {
+        return None;
+    }
+    state_data.get_peer_info(&message.from).await.ok()??;
+    match message.payload {
+        RealtimePayload::Game(bytes) => Some(UiEvent::Realtime {
+            from: message.from,
+            bytes,
+        }),
+        RealtimePayload::GameMessage(bytes) => {
+            let msg = state_data
+                .decode(&state_data.unpack(GAME_MESSAGE_KEY, bytes).ok()?)
+                .ok()?;
+            Some(UiEvent::GameMessage {
+                from: message.from,
+                msg,
+            })
+        }
+        RealtimePayload::Typing(is_typing) => typing.update(message.from, is_typing),
+        RealtimePayload::Listing(_) | RealtimePayload::Presence(_) => None,
+    }
+}
+
+/// Process a ticked state the host sent this peer alone.
+///
+/// Frames from anyone but the host, or that fail to decode, are dropped like a lost frame.
+pub(super) async fn process_state_frame<G: GameLogic>(
+    frame: StateFrame,
+    state_data: &Arc<StateData<G>>,
+) -> Option<UiEvent<G>> {
+    if !state_data.is_peer_host(&frame.from).await.ok()? {
+        return None;
+    }
+    let state = state_data.decode_state_frame(frame.state).ok()?;
+    Some(UiEvent::GameState(state))
+}
@@ -0,0 +1,78 @@
+//! Host resolution of proposals.
+
+use crate::{
+    FinishReason, GameLogic, GameOutcome, Proposal, ProposalOutcome, room::state::StateData,
+};
+use anyhow::Result;
+use std::sync::Arc;
+
+/// Resolve a proposal once every other player has agreed to it or anyone has refused it,
+/// applying it if it was accepted.
+///
+/// Votes can sync before the proposal they belong to, so this is run for both.
+pub(super) async fn resolve_proposal<G: GameLogic>(
+    data: &StateData<G>,
+    logic: &Arc<G>,
+    id: &str,
+) -> Result<()> {
+    if data.get_proposal_outcome(id).await?.is_some() {
+        return Ok(());
+    }
+    let Some(request) = data.get_proposal(id).await? else {
+        return Ok(());
+    };
+
+    let playable = data.get_app_state().await?.is_in_game()
+        && data.has_player_on(&request.from).await?
+        && (request.proposal != Proposal::Takeback || data.has_undo_point().await?);
+    let accepted = match playable {
+        false => false,
+        true => {
+            let voters = data.proposal_voters(&request.from).await?;
+            let votes = data.get_proposal_votes(id).await?;
+            if voters.iter().any(|voter| votes.get(voter) == Some(&false)) {
+                false
+            } else if voters.iter().all(|voter| votes.get(voter) == Some(&true)) {
+                true
+            } else {
+                // Still waiting for someone to vote.
+                return Ok(());
+            }
+        }
+    };
+
+    if accepted {
+        apply_proposal(data, logic, request.proposal).await?;
+    }
+    data.set_proposal_outcome(&ProposalOutcome {
+        id: request.id,
+        proposal: request.proposal,
+        accepted,
+    })
+    .await
+}
+
+/// Make the change every player agreed to.
+async fn apply_proposal<G: GameLogic>(
+    data: &StateData<G>,
+    logic: &Arc<G>,
+    proposal: Proposal,
+) -> Result<()> {
+    match proposal {
+        Proposal::Takeback => data.restore_undo_point().await,
+        Proposal::Draw => {
+            data.finish_game(GameOutcome::new(FinishReason::Agreed))
+                .await
+        }
+        Proposal::Restart => restart_game(data, logic).await,
+    }
+}
+
+/// Restart the game with the current players, as the host.
+pub(super) async fn restart_game<G: GameLogic>(data: &StateData<G>, logic: &Arc<G>) -> Result<()> {
+    let players = data.get_peer_list().await?;
+    let roles = logic.assign_roles(&players)?;
+    let initial_state = logic.initial_state(&players, &roles)?;
+    data.set_game_start(roles, &initial_state).await?;
+    data.set_undo_point(None).await
+}
@@ -0,0 +1,82 @@
+//! Simultaneous rounds, closed and applied by the host.
+
+use super::{entries::process_queued_actions, metrics};
+use crate::{
+    Engine, GameLogic,
+    room::state::{ActionError, ActionResponse, Round, StateData},
+    trace,
+};
+use anyhow::Result;
+use std::{collections::BTreeMap, sync::Arc};
+
+/// Apply every round that is ready to close, collecting the actions players already
+/// submitted for the rounds after it as it goes.
+pub(super) async fn close_ready_rounds<G: GameLogic>(
+    data: &StateData<G>,
+    logic: &Arc<G>,
+) -> Result<()> {
+    while data.round_ready().await? {
+        let Some(round) = data.close_round() else {
+            break;
+        };
+        let players: Vec<_> = round.actions.keys().copied().collect();
+        apply_round(data, logic, round).await?;
+        for player in players {
+            let next = data.last_processed_action_seq(&player).await? + 1;
+            process_queued_actions(data, logic, player, next).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Apply a closed round in one go, and respond to every action in it alike.
+async fn apply_round<G: GameLogic>(
+    data: &StateData<G>,
+    logic: &Arc<G>,
+    round: Round<G::GameAction>,
+) -> Result<()> {
+    let actions: BTreeMap<_, _> = round
+        .actions
+        .iter()
+        .map(|(player, held)| (*player, held.action.clone()))
+        .collect();
+    let error = match data.get_game_state_entry().await {
+        Err(e) => Some(ActionError::Engine(format!("No game state available: {e}"))),
+        Ok(previous) => {
+            let mut engine = Engine::<G>::new(logic.clone(), previous.state.clone());
+            match engine.apply_round(round.id, &actions) {
+                Err(e) => {
+                    trace::debug!(round = round.id, error = %e, "Round rejected");
+                    Some(ActionError::Game(e))
+                }
+                Ok(()) => {
+                    trace::debug!(round = round.id, actions = actions.len(), "Round applied");
+                    data.set_undo_point(Some(&previous)).await?;
+                    data.set_game_state(engine.state()).await?;
+                    for (player, held) in &round.actions {
+                        data.log_applied_action(player, held.seq, &held.action, Some(round.id))
+                            .await?;
+                    }
+                    if let Some(outcome) = engine.outcome() {
+                        data.finish_game(outcome).await?;
+                    }
+                    None
+                }
+            }
+        }
+    };
+    for (player, held) in round.actions {
+        match error {
+            Some(_) => metrics::action_rejected(data),
+            None => metrics::action_applied(data),
+        }
+        let response = ActionResponse {
+            action_id: held.id,
+            seq: held.seq,
+            error: error.clone(),
+        };
+        data.set_action_response(&player, &response).await?;
+        data.mark_action_processed(&player, held.seq).await?;
+    }
+    Ok(())
+}
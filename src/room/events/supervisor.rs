@@ -0,0 +1,137 @@
+//! Supervision of the room's event loop.
+//!
+//! The event loop runs game logic and parses whatever peers write, so a bug in either can make
+//! it panic, after which the room would never report another event. The `Supervisor` runs the
+//! loop in a task of its own and, if it panics, starts it again on fresh subscriptions, telling
+//! the application with `UiError::EventLoopRestarted` that events may have been missed.
+
+use super::{
+    delivery::{EventQueue, Subscribers},
+    event_loop::event_loop,
+    network::buffered,
+    ui::{UiError, UiEvent},
+};
+use crate::{
+    EventCoalescing, GameLogic, RoomConfig, networking::StateFrame, room::state::StateData, trace,
+};
+use anyhow::Result;
+use iroh_docs::engine::LiveEvent;
+use iroh_gossip::api::{GossipReceiver, GossipSender};
+use n0_future::{StreamExt as _, stream::Boxed, task::AbortOnDropHandle};
+use std::{sync::Arc, time::Duration};
+use tokio::{sync::mpsc, time::Instant};
+
+/// How many times in a row the event loop is started again after panicking soon after it
+/// started, before it is left stopped.
+const MAX_RAPID_RESTARTS: u32 = 3;
+/// How long the event loop must run for a panic not to count towards [MAX_RAPID_RESTARTS].
+const RAPID_RESTART_WINDOW: Duration = Duration::from_secs(30);
+
+/// The streams the event loop listens to.
+pub(super) struct Subscriptions {
+    /// The game document's events.
+    pub(super) sub: Boxed<Result<LiveEvent>>,
+    /// The chat document's events, unless chat is disabled.
+    pub(super) chat_sub: Option<Boxed<Result<LiveEvent>>>,
+    /// Real-time messages, see [crate::RoomConfig::realtime].
+    pub(super) realtime: Option<GossipReceiver>,
+    /// Ticked states the host sends this peer between writes, see
+    /// [crate::RoomConfig::state_cadence].
+    pub(super) frames: mpsc::Receiver<StateFrame>,
+}
+
+/// Everything needed to start the event loop, and start it again.
+pub(super) struct Supervisor<G: GameLogic> {
+    pub(super) sender: mpsc::Sender<UiEvent<G>>,
+    pub(super) coalescing: EventCoalescing,
+    pub(super) subscribers: Subscribers<G>,
+    pub(super) state_data: Arc<StateData<G>>,
+    pub(super) logic: Arc<G>,
+    pub(super) config: RoomConfig,
+    #[cfg(feature = "testing")]
+    pub(super) faults: crate::room::testing::Faults,
+}
+
+impl<G: GameLogic> Supervisor<G> {
+    /// Subscribe to the room's documents, and to its real-time topic if it has one, returning
+    /// the topic's sender half for the room to send with.
+    pub(super) async fn subscribe(&self) -> Result<(Subscriptions, Option<GossipSender>)> {
+        let sub = buffered(self.state_data.doc.subscribe().await?);
+        // Peer entries synced before the subscription never reach the mirrored peer list.
+        self.state_data.forget_peer_list().await;
+        #[cfg(feature = "testing")]
+        let sub = self.faults.apply(sub);
+        let chat_sub = match &self.state_data.chat_doc {
+            Some(chat_doc) => Some(buffered(chat_doc.subscribe().await?).boxed()),
+            None => None,
+        };
+        let (realtime_sender, realtime) = match self.config.realtime {
+            true => {
+                let (sender, receiver) = self.state_data.join_realtime().await?.split();
+                (Some(sender), Some(receiver))
+            }
+            false => (None, None),
+        };
+        // Entries synced before the subscription are never processed, so start watchers off here.
+        if let Ok(app_state) = self.state_data.get_app_state().await {
+            self.state_data.app_state_synced(app_state);
+        }
+        let subscriptions = Subscriptions {
+            sub: sub.boxed(),
+            chat_sub,
+            realtime,
+            frames: self.state_data.accept_state_frames()?,
+        };
+        Ok((subscriptions, realtime_sender))
+    }
+
+    /// Run the event loop until it stops, starting it again whenever it panics.
+    pub(super) async fn run(self, mut subscriptions: Subscriptions) {
+        let mut rapid_restarts = 0;
+        let mut restarted = false;
+        loop {
+            let mut queue = EventQueue::new(
+                self.sender.clone(),
+                self.coalescing,
+                self.config.queue_limit,
+                self.subscribers.clone(),
+            );
+            if restarted {
+                queue.push(UiEvent::Error(UiError::EventLoopRestarted));
+            }
+            let started = Instant::now();
+            let task = event_loop(
+                subscriptions,
+                queue,
+                self.state_data.clone(),
+                self.logic.clone(),
+                self.config.clone(),
+            );
+            #[cfg(feature = "tracing")]
+            let task = tracing::Instrument::in_current_span(task);
+            // Stopping the supervisor stops the loop with it.
+            match AbortOnDropHandle::new(tokio::spawn(task)).await {
+                Err(e) if e.is_panic() => {}
+                _ => return, // The loop stopped on its own
+            }
+            match started.elapsed() < RAPID_RESTART_WINDOW {
+                true => rapid_restarts += 1,
+                false => rapid_restarts = 1,
+            }
+            if rapid_restarts > MAX_RAPID_RESTARTS {
+                trace::warning!("Event loop keeps panicking, leaving it stopped");
+                return;
+            }
+            trace::warning!("Event loop panicked, restarting it");
+            subscriptions = match self.subscribe().await {
+                // The room keeps sending on the topic it joined first.
+                Ok((subscriptions, _realtime_sender)) => subscriptions,
+                Err(_e) => {
+                    trace::warning!(error = %_e, "Could not restart the event loop");
+                    return;
+                }
+            };
+            restarted = true;
+        }
+    }
+}
@@ -0,0 +1,116 @@
+//! Host-side game clock for timed games.
+
+use crate::{GameLogic, StateCadence, UiEvent, room::state::StateData};
+use anyhow::Result;
+use iroh::EndpointId;
+use std::{collections::HashMap, sync::Arc, time::Instant};
+
+/// The host's clock for a timed game, and when each peer last heard where it got to.
+#[derive(Debug, Default)]
+pub(super) struct GameClock {
+    last_tick: Option<Instant>,
+    /// How ticked states reach the peers, see [crate::RoomConfig::state_cadence].
+    cadence: Option<StateCadence>,
+    /// When a ticked state was last written to the document.
+    last_write: Option<Instant>,
+    /// When each peer was last sent a ticked state of its own.
+    last_frames: HashMap<EndpointId, Instant>,
+}
+
+impl GameClock {
+    pub(super) fn new(cadence: Option<StateCadence>) -> Self {
+        Self {
+            cadence,
+            ..Self::default()
+        }
+    }
+
+    /// Let the game advance its state by the time since the last tick.
+    ///
+    /// The clock stops whenever this peer is not hosting a game in progress, so time spent
+    /// paused, in the lobby or as a client is never handed to the game. With a cadence, a
+    /// state that is not written is returned for the host's own view.
+    pub(super) async fn advance<G: GameLogic>(
+        &mut self,
+        data: &StateData<G>,
+        logic: &Arc<G>,
+    ) -> Result<Option<UiEvent<G>>> {
+        if !data.is_host().await.unwrap_or_default()
+            || data.is_lockstep()
+            || !data
+                .get_app_state()
+                .await
+                .is_ok_and(|state| state.is_in_game())
+        {
+            self.last_tick = None;
+            self.last_write = None;
+            self.last_frames.clear();
+            data.clear_ticked_state();
+            return Ok(None);
+        }
+
+        let now = data.now();
+        let Some(elapsed) = self.last_tick.replace(now).map(|last| now - last) else {
+            return Ok(None); // Start the clock
+        };
+        let current_state = data.get_game_state().await?;
+        let Some(next_state) = logic.tick(&current_state, elapsed) else {
+            return Ok(None);
+        };
+        let outcome = logic.game_outcome(&next_state);
+        if let (Some(cadence), None) = (self.cadence, &outcome)
+            && self
+                .last_write
+                .is_some_and(|at| now - at < cadence.persist_interval)
+        {
+            self.send_frames(data, &cadence, &next_state, now).await?;
+            data.hold_ticked_state(next_state.clone());
+            return Ok(Some(UiEvent::GameState(next_state)));
+        }
+        data.set_game_state(&next_state).await?;
+        self.last_write = Some(now);
+        if let Some(outcome) = outcome {
+            data.finish_game(outcome).await?;
+        }
+        Ok(None)
+    }
+
+    /// Send `state` to each peer whose round trip has passed since it was last sent one.
+    ///
+    /// Every frame goes to its peer alone, so peers that are not due hear nothing at all.
+    async fn send_frames<G: GameLogic>(
+        &mut self,
+        data: &StateData<G>,
+        cadence: &StateCadence,
+        state: &G::GameState,
+        now: Instant,
+    ) -> Result<()> {
+        let rtts: HashMap<EndpointId, _> = data
+            .network_stats()
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .map(|stats| (stats.peer, stats.rtt))
+            .collect();
+        let peers = data.get_peer_list().await?;
+        let due: Vec<EndpointId> = peers
+            .iter()
+            .filter(|(id, peer)| **id != data.endpoint_id && !peer.is_seat())
+            .map(|(id, _)| *id)
+            .filter(|id| {
+                let interval = cadence.interval_for(rtts.get(id).copied().flatten());
+                self.last_frames
+                    .get(id)
+                    .is_none_or(|at| now - *at >= interval)
+            })
+            .collect();
+        self.last_frames.retain(|id, _| peers.contains_key(id));
+        if due.is_empty() {
+            return Ok(());
+        }
+        for id in &due {
+            self.last_frames.insert(*id, now);
+        }
+        data.send_state_frames(due, data.encode_state_frame(state)?)
+    }
+}
@@ -0,0 +1,57 @@
+//! Typing indicators, from the room's real-time channel.
+
+use super::ui::UiEvent;
+use crate::GameLogic;
+use iroh::EndpointId;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// How long a peer counts as typing after it last said so.
+///
+/// Typing indicators travel on the lossy real-time channel, so a peer that stops without
+/// saying so, or whose message is lost, must not be left typing for good.
+pub(super) const TYPING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The peers currently typing, and when each last said so.
+#[derive(Debug, Default)]
+pub(super) struct TypingPeers {
+    since: HashMap<EndpointId, Instant>,
+}
+
+impl TypingPeers {
+    /// Record what a peer said, reporting it if the peer started or stopped typing.
+    pub(super) fn update<G: GameLogic>(
+        &mut self,
+        id: EndpointId,
+        typing: bool,
+    ) -> Option<UiEvent<G>> {
+        let changed = match typing {
+            true => self.since.insert(id, Instant::now()).is_none(),
+            false => self.since.remove(&id).is_some(),
+        };
+        changed.then_some(UiEvent::PeerTyping { id, typing })
+    }
+
+    /// Stop the peers that have not said they are typing for too long.
+    pub(super) fn expire<G: GameLogic>(&mut self) -> Vec<UiEvent<G>> {
+        let mut stopped = Vec::new();
+        self.since.retain(|id, since| {
+            let typing = since.elapsed() < TYPING_TIMEOUT;
+            if !typing {
+                stopped.push(UiEvent::PeerTyping {
+                    id: *id,
+                    typing: false,
+                });
+            }
+            typing
+        });
+        stopped
+    }
+
+    /// Whether any peer is typing.
+    pub(super) fn is_empty(&self) -> bool {
+        self.since.is_empty()
+    }
+}
@@ -1,9 +1,20 @@
-use std::fmt::Display;
+use std::{
+    fmt::Display,
+    time::{Duration, SystemTime},
+};
 
-use crate::{ActionResult, AppState, ChatMessage, GameLogic, HostEvent, PeerMap};
+use bytes::Bytes;
+use iroh::EndpointId;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ActionError, Annotation, AppState, Asset, ChatMessage, ChatUpdate, ConnectionPath, Friend,
+    GameLogic, GameResult, HostEvent, JoinDeniedReason, LeaveReason, PeerInfo, PeerMap,
+    PeerNetworkStats, ProposalOutcome, ProposalRequest, RoomMeta, TeamId, VoteCall, VoteResult,
+};
 
 /// UI error events that the game room emits to the application layer.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub enum UiError {
     SyncFailed(String),
     EventProcessing {
@@ -11,6 +22,13 @@ pub enum UiError {
         author: String,
         message: String,
     },
+    HostTask {
+        task: String,
+        message: String,
+    },
+    /// The event loop panicked and was started again, so events may have been missed.
+    /// Read the room's state again to catch up, e.g. with [crate::GameRoom::snapshot].
+    EventLoopRestarted,
 }
 
 impl Display for UiError {
@@ -20,33 +38,468 @@ impl Display for UiError {
             UiError::EventProcessing { key, message, .. } => {
                 write!(f, "Failed to process event '{key}': {message}")
             }
+            UiError::HostTask { task, message } => {
+                write!(f, "Host task '{task}' failed: {message}")
+            }
+            UiError::EventLoopRestarted => write!(f, "Event loop restarted after a panic"),
         }
     }
 }
 
 /// UI events that the game room emits to the application layer.
-#[derive(Debug, Clone, PartialEq, Eq)]
+///
+/// Events can be serialized as they are, e.g. to forward them to a frontend in another
+/// process, with game types in their own `serde` representation.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(bound = "")]
 pub enum UiEvent<G: GameLogic> {
+    /// The whole peer list, sent after joining and whenever it changes.
     Peer(PeerMap),
+    /// A peer joined the room, or came back online.
+    PeerJoined(PeerInfo),
+    /// A peer went offline, with the reason they gave if they left on purpose.
+    PeerLeft {
+        id: EndpointId,
+        reason: Option<LeaveReason<G>>,
+    },
+    /// A peer's details changed while they stayed in the room, e.g. their readiness.
+    PeerUpdated(PeerInfo),
     GameState(G::GameState),
-    AppState(AppState),
-    Chat { sender: String, msg: ChatMessage },
-    ActionResult(ActionResult),
+    AppState(AppState<G>),
+    /// The host started the game, moving the room to [AppState::InGame] with this state.
+    ///
+    /// It stands in for the [UiEvent::AppState] and [UiEvent::GameState] the start would
+    /// otherwise be, so the two are never seen apart.
+    GameStarted {
+        state: G::GameState,
+    },
+    /// The host finished the game, moving the room to [AppState::Finished] with this result.
+    ///
+    /// It stands in for the [UiEvent::AppState] the finish would otherwise be, so the result
+    /// is never seen apart from it.
+    GameOver(GameResult),
+    /// The host sent one of this endpoint's players a payload only they may see, e.g. their
+    /// hand of cards, with [crate::GameRoom::send_private_state].
+    PrivateState {
+        to: EndpointId,
+        payload: Bytes,
+    },
+    /// The host published an asset with [crate::GameRoom::publish_asset], or replaced one.
+    ///
+    /// Fetch it with [crate::GameRoom::fetch_asset], unless [crate::RoomConfig::fetch_assets]
+    /// already has.
+    AssetAvailable(Asset),
+    Chat {
+        sender: String,
+        msg: ChatMessage,
+    },
+    /// A delivered chat message has been edited, deleted or reacted to.
+    ChatUpdated(ChatUpdate),
+    /// The host applied one of this endpoint's actions.
+    ActionAccepted {
+        /// The local player the action was submitted for.
+        player: EndpointId,
+        seq: u64,
+        action_id: String,
+    },
+    /// A player committed to an action without revealing it yet, see
+    /// [crate::GameRoom::commit_action].
+    ActionCommitted {
+        player: EndpointId,
+        /// The commitment's number, counting each player's commitments from 1.
+        commitment: u64,
+    },
+    /// The host rejected one of this endpoint's actions.
+    ActionRejected {
+        /// The local player the action was submitted for.
+        player: EndpointId,
+        seq: u64,
+        action_id: String,
+        error: ActionError<G::GameError>,
+    },
+    Annotation(Annotation),
+    /// The host moved a peer onto a team, or took them off their team.
+    TeamChanged {
+        peer: EndpointId,
+        team: Option<TeamId>,
+    },
     Host(HostEvent),
+    /// A player forfeited and now watches the rest of the game as an observer.
+    PlayerForfeited {
+        id: EndpointId,
+    },
+    /// An observer asked the host for a place as a player. Only sent to the host.
+    SeatRequested {
+        peer: EndpointId,
+    },
+    /// The host created a new room for the next game and invited everyone in this one to it.
+    Rehosted {
+        /// The new room's ticket, ready to pass to [crate::GameRoom::join].
+        ticket: String,
+    },
+    /// A friend announced their presence, see [crate::Presence].
+    FriendOnline(Friend),
+    /// A friend has not been heard from for a while, see [crate::Presence].
+    FriendOffline(Friend),
+    /// The host closed the room for good, for the given reason, see [crate::GameRoom::close].
+    RoomClosed(String),
+    /// The host will finish the game for inactivity unless an action arrives soon.
+    InactivityWarning {
+        finishes_in: Duration,
+    },
+    /// The turn passed to `player`, for games that report a [crate::TurnManager].
+    TurnChanged {
+        player: EndpointId,
+        round: u32,
+    },
+    /// The turn passed to `player`, one of this endpoint's own players, e.g. to raise a
+    /// notification while the app is in the background. Sent after the [UiEvent::TurnChanged].
+    ///
+    /// `deadline` is when the turn runs out, if the game gave it a
+    /// [crate::TurnManager::time_limit].
+    YourTurn {
+        player: EndpointId,
+        round: u32,
+        deadline: Option<SystemTime>,
+    },
+    /// A player proposed a change to the game, see [crate::GameRoom::propose].
+    ProposalMade(ProposalRequest),
+    /// A player accepted or refused a proposal.
+    ProposalVoted {
+        id: String,
+        from: EndpointId,
+        accept: bool,
+    },
+    /// The host resolved a proposal, applying it if everyone agreed.
+    ProposalResolved(ProposalOutcome),
+    /// A peer put a decision about the room to a vote, see [crate::GameRoom::call_vote].
+    VoteCalled(VoteCall),
+    /// The host tallied a vote, carrying it out if a majority voted for it.
+    VoteResult(VoteResult),
+    /// The host rolled the game back to the checkpoint saved under `label`.
+    ///
+    /// The restored state arrives as a [UiEvent::GameState] of its own.
+    RolledBack {
+        label: String,
+    },
+    /// The host's state disagreed with this peer's predicted view, see
+    /// [crate::RoomConfig::predict]. Show this state in place of the last one received.
+    PredictionCorrected(G::GameState),
+    /// A player's step in a lock-step game produced a different state for this peer than for
+    /// them, so the game cannot go on, see [crate::RoomConfig::lockstep].
+    LockstepDiverged {
+        step: u64,
+        peer: EndpointId,
+    },
+    /// A peer sent a message on the room's real-time channel, see
+    /// [crate::GameRoom::send_realtime].
+    Realtime {
+        from: EndpointId,
+        bytes: Bytes,
+    },
+    /// A peer broadcast one of the game's own messages, see
+    /// [crate::GameRoom::broadcast_game_message].
+    GameMessage {
+        from: EndpointId,
+        msg: G::GameMessage,
+    },
+    /// A peer started or stopped typing a chat message, see [crate::GameRoom::set_typing].
+    ///
+    /// A peer that stops saying it is typing is reported as stopped after a few seconds.
+    PeerTyping {
+        id: EndpointId,
+        typing: bool,
+    },
+    /// A peer wrote more actions or chat messages than the room allows, so the host is
+    /// dropping them, see [crate::RoomConfig::rate_limits]. Sent to the host once each time
+    /// the peer goes over.
+    RateLimited {
+        peer: EndpointId,
+    },
+    /// An action claimed to come from a peer but was written by a different author than the
+    /// one the peer joined with, so the host ignored it. Sent to the host.
+    ForgedEntry {
+        claimed: EndpointId,
+        author: String,
+    },
+    /// The host refused to add this peer to the room, so it will not be taking part.
+    JoinDenied(JoinDeniedReason),
+    /// The host changed the room's details, see [crate::GameRoom::set_meta].
+    MetaUpdated(RoomMeta),
+    /// How far a joiner has synced the room's entries, sent until the room's
+    /// [UiEvent::InitialSnapshot] arrives, see [crate::RoomConfig::await_initial_sync].
+    Syncing {
+        entries_done: usize,
+        entries_total: usize,
+    },
+    /// The room as it was once its state had synced, sent after joining, see
+    /// [crate::RoomConfig::await_initial_sync].
+    InitialSnapshot {
+        app_state: AppState<G>,
+        peers: PeerMap,
+        game_state: Option<G::GameState>,
+    },
+    /// Connection quality to each connected peer, sent at the interval set with
+    /// [crate::RoomConfig::network_stats].
+    NetworkStats(Vec<PeerNetworkStats>),
+    /// Traffic to a peer has moved to another path, e.g. from a relay to a direct connection,
+    /// or `None` once the connection has closed. Checked at the interval set with
+    /// [crate::RoomConfig::network_stats].
+    ConnectionPathChanged {
+        peer: EndpointId,
+        path: Option<ConnectionPath>,
+    },
+    /// The room synced with its peers again after the device changed networks, e.g. from
+    /// Wi-Fi to cellular, or after [crate::GameRoom::reconnect].
+    Reconnected,
+    /// The receiver fell so far behind that the room dropped its `missed` oldest waiting
+    /// events, see [crate::RoomConfig::queue_limit]. Read the room's state again to catch up,
+    /// e.g. with [crate::GameRoom::snapshot].
+    Lagged {
+        missed: u64,
+    },
     Error(UiError),
 }
 
+// Written out by hand, as deriving it would require the game logic itself to be `Clone`.
+impl<G: GameLogic> Clone for UiEvent<G> {
+    fn clone(&self) -> Self {
+        match self {
+            UiEvent::Peer(peers) => UiEvent::Peer(peers.clone()),
+            UiEvent::PeerJoined(peer) => UiEvent::PeerJoined(peer.clone()),
+            UiEvent::PeerLeft { id, reason } => UiEvent::PeerLeft {
+                id: *id,
+                reason: reason.clone(),
+            },
+            UiEvent::PeerUpdated(peer) => UiEvent::PeerUpdated(peer.clone()),
+            UiEvent::GameState(state) => UiEvent::GameState(state.clone()),
+            UiEvent::AppState(state) => UiEvent::AppState(*state),
+            UiEvent::GameStarted { state } => UiEvent::GameStarted {
+                state: state.clone(),
+            },
+            UiEvent::GameOver(result) => UiEvent::GameOver(result.clone()),
+            UiEvent::PrivateState { to, payload } => UiEvent::PrivateState {
+                to: *to,
+                payload: payload.clone(),
+            },
+            UiEvent::AssetAvailable(asset) => UiEvent::AssetAvailable(asset.clone()),
+            UiEvent::Chat { sender, msg } => UiEvent::Chat {
+                sender: sender.clone(),
+                msg: msg.clone(),
+            },
+            UiEvent::ChatUpdated(update) => UiEvent::ChatUpdated(update.clone()),
+            UiEvent::ActionCommitted { player, commitment } => UiEvent::ActionCommitted {
+                player: *player,
+                commitment: *commitment,
+            },
+            UiEvent::ActionAccepted {
+                player,
+                seq,
+                action_id,
+            } => UiEvent::ActionAccepted {
+                player: *player,
+                seq: *seq,
+                action_id: action_id.clone(),
+            },
+            UiEvent::ActionRejected {
+                player,
+                seq,
+                action_id,
+                error,
+            } => UiEvent::ActionRejected {
+                player: *player,
+                seq: *seq,
+                action_id: action_id.clone(),
+                error: error.clone(),
+            },
+            UiEvent::Annotation(annotation) => UiEvent::Annotation(annotation.clone()),
+            UiEvent::TeamChanged { peer, team } => UiEvent::TeamChanged {
+                peer: *peer,
+                team: *team,
+            },
+            UiEvent::Host(event) => UiEvent::Host(event.clone()),
+            UiEvent::PlayerForfeited { id } => UiEvent::PlayerForfeited { id: *id },
+            UiEvent::SeatRequested { peer } => UiEvent::SeatRequested { peer: *peer },
+            UiEvent::Rehosted { ticket } => UiEvent::Rehosted {
+                ticket: ticket.clone(),
+            },
+            UiEvent::RoomClosed(reason) => UiEvent::RoomClosed(reason.clone()),
+            UiEvent::FriendOnline(friend) => UiEvent::FriendOnline(friend.clone()),
+            UiEvent::FriendOffline(friend) => UiEvent::FriendOffline(friend.clone()),
+            UiEvent::InactivityWarning { finishes_in } => UiEvent::InactivityWarning {
+                finishes_in: *finishes_in,
+            },
+            UiEvent::TurnChanged { player, round } => UiEvent::TurnChanged {
+                player: *player,
+                round: *round,
+            },
+            UiEvent::YourTurn {
+                player,
+                round,
+                deadline,
+            } => UiEvent::YourTurn {
+                player: *player,
+                round: *round,
+                deadline: *deadline,
+            },
+            UiEvent::ProposalMade(request) => UiEvent::ProposalMade(request.clone()),
+            UiEvent::ProposalVoted { id, from, accept } => UiEvent::ProposalVoted {
+                id: id.clone(),
+                from: *from,
+                accept: *accept,
+            },
+            UiEvent::ProposalResolved(outcome) => UiEvent::ProposalResolved(outcome.clone()),
+            UiEvent::VoteCalled(call) => UiEvent::VoteCalled(call.clone()),
+            UiEvent::VoteResult(result) => UiEvent::VoteResult(result.clone()),
+            UiEvent::RolledBack { label } => UiEvent::RolledBack {
+                label: label.clone(),
+            },
+            UiEvent::PredictionCorrected(state) => UiEvent::PredictionCorrected(state.clone()),
+            UiEvent::LockstepDiverged { step, peer } => UiEvent::LockstepDiverged {
+                step: *step,
+                peer: *peer,
+            },
+            UiEvent::GameMessage { from, msg } => UiEvent::GameMessage {
+                from: *from,
+                msg: msg.clone(),
+            },
+            UiEvent::PeerTyping { id, typing } => UiEvent::PeerTyping {
+                id: *id,
+                typing: *typing,
+            },
+            UiEvent::Realtime { from, bytes } => UiEvent::Realtime {
+                from: *from,
+                bytes: bytes.clone(),
+            },
+            UiEvent::RateLimited { peer } => UiEvent::RateLimited { peer: *peer },
+            UiEvent::ForgedEntry { claimed, author } => UiEvent::ForgedEntry {
+                claimed: *claimed,
+                author: author.clone(),
+            },
+            UiEvent::JoinDenied(reason) => UiEvent::JoinDenied(*reason),
+            UiEvent::MetaUpdated(meta) => UiEvent::MetaUpdated(meta.clone()),
+            UiEvent::Syncing {
+                entries_done,
+                entries_total,
+            } => UiEvent::Syncing {
+                entries_done: *entries_done,
+                entries_total: *entries_total,
+            },
+            UiEvent::InitialSnapshot {
+                app_state,
+                peers,
+                game_state,
+            } => UiEvent::InitialSnapshot {
+                app_state: *app_state,
+                peers: peers.clone(),
+                game_state: game_state.clone(),
+            },
+            UiEvent::NetworkStats(stats) => UiEvent::NetworkStats(stats.clone()),
+            UiEvent::ConnectionPathChanged { peer, path } => UiEvent::ConnectionPathChanged {
+                peer: *peer,
+                path: path.clone(),
+            },
+            UiEvent::Reconnected => UiEvent::Reconnected,
+            UiEvent::Lagged { missed } => UiEvent::Lagged { missed: *missed },
+            UiEvent::Error(error) => UiEvent::Error(error.clone()),
+        }
+    }
+}
+
 impl<G: GameLogic> Display for UiEvent<G> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             UiEvent::Peer(peers) => write!(f, "PeerUpdated({peers})"),
+            UiEvent::PeerJoined(peer) => write!(f, "PeerJoined({})", peer.id),
+            UiEvent::PeerLeft { id, reason } => match reason {
+                Some(reason) => write!(f, "PeerLeft({id}, {reason:?})"),
+                None => write!(f, "PeerLeft({id})"),
+            },
+            UiEvent::PeerUpdated(peer) => write!(f, "PeerInfoUpdated({})", peer.id),
             UiEvent::GameState(state) => write!(f, "GameStateUpdated({state:?})"),
             UiEvent::AppState(state) => write!(f, "AppStateChanged({state:?})"),
+            UiEvent::GameStarted { state } => write!(f, "GameStarted({state:?})"),
+            UiEvent::GameOver(result) => write!(f, "GameOver({result:?})"),
+            UiEvent::PrivateState { to, payload } => {
+                write!(f, "PrivateState({to}, {} bytes)", payload.len())
+            }
+            UiEvent::AssetAvailable(asset) => {
+                write!(f, "AssetAvailable({}, {} bytes)", asset.name, asset.size)
+            }
             UiEvent::Chat { sender: _, msg } => write!(f, "Chat({msg:?})"),
-            UiEvent::ActionResult(result) => write!(f, "ActionResult({result:?})"),
+            UiEvent::ChatUpdated(update) => write!(f, "ChatUpdated({update:?})"),
+            UiEvent::ActionAccepted { seq, .. } => write!(f, "ActionAccepted({seq})"),
+            UiEvent::ActionCommitted { player, commitment } => {
+                write!(f, "ActionCommitted({player}, {commitment})")
+            }
+            UiEvent::ActionRejected { seq, error, .. } => {
+                write!(f, "ActionRejected({seq}, {error})")
+            }
+            UiEvent::Annotation(annotation) => write!(f, "Annotation({annotation})"),
+            UiEvent::TeamChanged { peer, team } => match team {
+                Some(team) => write!(f, "TeamChanged({peer}, {team})"),
+                None => write!(f, "TeamChanged({peer}, none)"),
+            },
             UiEvent::Host(HostEvent::Changed { to }) => write!(f, "HostSet({to})"),
             UiEvent::Host(HostEvent::Offline) => write!(f, "HostOffline"),
             UiEvent::Host(HostEvent::Online) => write!(f, "HostOnline"),
+            UiEvent::PlayerForfeited { id } => write!(f, "PlayerForfeited({id})"),
+            UiEvent::SeatRequested { peer } => write!(f, "SeatRequested({peer})"),
+            UiEvent::Rehosted { .. } => write!(f, "Rehosted"),
+            UiEvent::RoomClosed(reason) => write!(f, "RoomClosed({reason})"),
+            UiEvent::FriendOnline(friend) => write!(f, "FriendOnline({})", friend.name),
+            UiEvent::FriendOffline(friend) => write!(f, "FriendOffline({})", friend.name),
+            UiEvent::InactivityWarning { finishes_in } => {
+                write!(f, "InactivityWarning({finishes_in:?})")
+            }
+            UiEvent::PredictionCorrected(state) => write!(f, "PredictionCorrected({state:?})"),
+            UiEvent::LockstepDiverged { step, peer } => {
+                write!(f, "LockstepDiverged({step}, {peer})")
+            }
+            UiEvent::GameMessage { from, msg } => write!(f, "GameMessage({from}, {msg:?})"),
+            UiEvent::PeerTyping { id, typing } => write!(f, "PeerTyping({id}, {typing})"),
+            UiEvent::Realtime { from, bytes } => {
+                write!(f, "Realtime({from}, {} bytes)", bytes.len())
+            }
+            UiEvent::TurnChanged { player, round } => {
+                write!(f, "TurnChanged({player}, round {round})")
+            }
+            UiEvent::YourTurn { player, round, .. } => {
+                write!(f, "YourTurn({player}, round {round})")
+            }
+            UiEvent::ProposalMade(request) => {
+                write!(f, "ProposalMade({}, {})", request.id, request.proposal)
+            }
+            UiEvent::ProposalVoted { id, from, accept } => {
+                write!(f, "ProposalVoted({id}, {from}, {accept})")
+            }
+            UiEvent::ProposalResolved(outcome) => {
+                write!(f, "ProposalResolved({}, {})", outcome.id, outcome.accepted)
+            }
+            UiEvent::VoteCalled(call) => write!(f, "VoteCalled({}, {})", call.id, call.kind),
+            UiEvent::VoteResult(result) => {
+                write!(f, "VoteResult({}, {})", result.id, result.passed)
+            }
+            UiEvent::RolledBack { label } => write!(f, "RolledBack({label})"),
+            UiEvent::RateLimited { peer } => write!(f, "RateLimited({peer})"),
+            UiEvent::ForgedEntry { claimed, author } => {
+                write!(f, "ForgedEntry({claimed}, {author})")
+            }
+            UiEvent::JoinDenied(reason) => write!(f, "JoinDenied({reason:?})"),
+            UiEvent::MetaUpdated(meta) => write!(f, "MetaUpdated({})", meta.name),
+            UiEvent::Syncing {
+                entries_done,
+                entries_total,
+            } => write!(f, "Syncing({entries_done}/{entries_total})"),
+            UiEvent::InitialSnapshot {
+                app_state, peers, ..
+            } => write!(f, "InitialSnapshot({app_state:?}, {peers})"),
+            UiEvent::NetworkStats(stats) => write!(f, "NetworkStats({} peers)", stats.len()),
+            UiEvent::ConnectionPathChanged { peer, path } => {
+                write!(f, "ConnectionPathChanged({peer}, {path:?})")
+            }
+            UiEvent::Reconnected => write!(f, "Reconnected"),
+            UiEvent::Lagged { missed } => write!(f, "Lagged behind, missed {missed} events"),
             UiEvent::Error(error) => write!(f, "Error({error:?})"),
         }
     }
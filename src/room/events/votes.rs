@@ -0,0 +1,92 @@
+//! Host tallying of votes.
+
+use super::{
+    connections::{process_forfeit, with_seats},
+    proposals::restart_game,
+};
+use crate::{GameLogic, VoteKind, VoteResult, room::state::StateData};
+use anyhow::Result;
+use std::sync::Arc;
+
+/// Decide a vote once a majority of the room has voted either way, carrying it out if it
+/// passed.
+///
+/// Ballots can sync before the call they belong to, so this is run for both.
+pub(super) async fn tally_vote<G: GameLogic>(
+    data: &StateData<G>,
+    logic: &Arc<G>,
+    id: &str,
+) -> Result<()> {
+    if data.get_vote_result(id).await?.is_some() {
+        return Ok(());
+    }
+    let Some(call) = data.get_vote_call(id).await? else {
+        return Ok(());
+    };
+
+    let voters = data.voters(&call.kind).await?;
+    let ballots = data.get_ballots(id).await?;
+    let count = |yes: bool| {
+        voters
+            .iter()
+            .filter(|voter| ballots.get(*voter) == Some(&yes))
+            .count()
+    };
+    let (yes, no) = (count(true), count(false));
+    let passed = if !can_carry_out(data, &call.kind).await? {
+        false
+    } else if yes * 2 > voters.len() {
+        true
+    } else if no * 2 >= voters.len() {
+        false
+    } else {
+        // Still waiting for a majority either way.
+        return Ok(());
+    };
+
+    if passed {
+        carry_out(data, logic, &call.kind).await?;
+    }
+    data.set_vote_result(&VoteResult {
+        id: call.id,
+        kind: call.kind,
+        passed,
+        yes,
+        no,
+    })
+    .await
+}
+
+/// Check whether the room is still in a state where the decision makes sense.
+async fn can_carry_out<G: GameLogic>(data: &StateData<G>, kind: &VoteKind) -> Result<bool> {
+    Ok(match kind {
+        VoteKind::Kick(peer) => {
+            data.get_peer_info(peer).await?.is_some() && !data.is_peer_host(peer).await?
+        }
+        VoteKind::Restart => data.get_app_state().await?.is_in_game() && !data.is_lockstep(),
+        VoteKind::Custom(_) => true,
+    })
+}
+
+/// Carry out the decision the room voted for.
+async fn carry_out<G: GameLogic>(
+    data: &StateData<G>,
+    logic: &Arc<G>,
+    kind: &VoteKind,
+) -> Result<()> {
+    match kind {
+        VoteKind::Kick(peer) => {
+            let in_game = data.get_app_state().await?.is_in_game();
+            for peer in with_seats(data, *peer).await {
+                match in_game {
+                    true => process_forfeit(data, logic, &peer).await?,
+                    false => data.set_peer_observer(&peer, true).await?,
+                }
+            }
+            Ok(())
+        }
+        VoteKind::Restart => restart_game(data, logic).await,
+        // The game acts on its own questions when it receives the result.
+        VoteKind::Custom(_) => Ok(()),
+    }
+}
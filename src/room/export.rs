@@ -0,0 +1,112 @@
+//! Exporting finished games to a portable file.
+//!
+//! [GameRoom::export_match] writes everything needed to review a game without the room's
+//! documents or a network connection, and [Match::import] reads it back, e.g. for an offline
+//! analysis tool.
+
+use crate::{AppliedAction, GameLogic, GameResult, GameRoom, PeerMap};
+use anyhow::{Context as _, Result, bail};
+use iroh::EndpointId;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, path::Path};
+
+/// The version of the match file format written by [GameRoom::export_match].
+const MATCH_FORMAT_VERSION: u32 = 1;
+
+/// A game exported from a room, with everything needed to review it offline.
+///
+/// The file is a single UTF-8 JSON object:
+///
+/// - `version`: The format version, currently `1`.
+/// - `game`: The [GameLogic::game_name] of the game that was played.
+/// - `room_id`: The ID of the room's document, to tell matches apart.
+/// - `room_name`: The name the host gave the room.
+/// - `players`: Every peer in the room, keyed by endpoint ID.
+/// - `roles`: The role each player was given when the game started.
+/// - `actions`: The actions the host applied, in order, as in [GameRoom::get_action_log].
+/// - `final_state`: The last game state the host wrote.
+/// - `result`: The [GameResult], or `null` if the game was exported before it finished.
+///
+/// Endpoint IDs are written as strings, and game types use their own `serde` representation.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(bound = "")]
+pub struct Match<G: GameLogic> {
+    /// The format version the file was written with.
+    pub version: u32,
+    /// The name of the game that was played.
+    pub game: String,
+    /// The ID of the room's document.
+    pub room_id: String,
+    /// The name the host gave the room.
+    pub room_name: String,
+    /// Every peer in the room when the match was exported.
+    pub players: PeerMap,
+    /// The role each player was given when the game started.
+    pub roles: HashMap<EndpointId, G::PlayerRole>,
+    /// The actions the host applied, in the order it applied them.
+    pub actions: Vec<AppliedAction<G::GameAction>>,
+    /// The last game state the host wrote.
+    pub final_state: G::GameState,
+    /// The final result, if the game had finished.
+    pub result: Option<GameResult>,
+}
+
+impl<G: GameLogic> Match<G> {
+    /// Read a match of the game `logic` plays, written by [GameRoom::export_match].
+    ///
+    /// Fails if the file is from a newer format version or a different game.
+    pub fn import(logic: &G, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = fs::read(path)
+            .with_context(|| format!("Failed to read match file {}", path.display()))?;
+        let exported: Self = serde_json::from_slice(&contents)
+            .with_context(|| format!("Invalid match file {}", path.display()))?;
+        if exported.version > MATCH_FORMAT_VERSION {
+            bail!(
+                "Match file version {} is newer than the supported version {MATCH_FORMAT_VERSION}",
+                exported.version
+            );
+        }
+        if exported.game != logic.game_name() {
+            bail!(
+                "Match file is for game '{}', expected '{}'",
+                exported.game,
+                logic.game_name()
+            );
+        }
+        Ok(exported)
+    }
+}
+
+impl<G: GameLogic> GameRoom<G> {
+    /// Gather the current game into a [Match], as [GameRoom::export_match] would write it.
+    pub async fn to_match(&self) -> Result<Match<G>> {
+        let final_state = self
+            .get_game_state()
+            .await
+            .context("No game state to export")?;
+        Ok(Match {
+            version: MATCH_FORMAT_VERSION,
+            game: self.logic.game_name().to_string(),
+            room_id: self.state.doc.id().to_string(),
+            room_name: self.name.clone(),
+            players: self.get_peer_list().await?,
+            roles: self.state.get_roles().await?,
+            actions: self.get_action_log(..).await?,
+            final_state,
+            result: self.get_result().await?,
+        })
+    }
+
+    /// Write the game to `path` as a self-contained match file, overwriting any existing file.
+    ///
+    /// Meant for finished games, but a game in progress can be exported too, with no result.
+    /// See [Match] for the format.
+    pub async fn export_match(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let exported = self.to_match().await?;
+        let contents = serde_json::to_vec_pretty(&exported)?;
+        fs::write(path, contents)
+            .with_context(|| format!("Failed to write match file {}", path.display()))
+    }
+}
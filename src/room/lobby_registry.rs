@@ -0,0 +1,225 @@
+//! Public lobby browser
+//!
+//! Hosts that opt in with [GameRoom::advertise] announce their open public rooms on a gossip
+//! topic shared by everyone playing the same game, and players find them with
+//! [LobbyRegistry::browse_rooms] instead of being handed a ticket.
+//!
+//! Gossip needs a first peer to meet the others through, so both sides are given the address
+//! of a tracker: any long-running [LobbyRegistry] that is browsing the game's rooms, e.g. one
+//! run on a server.
+
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use bytes::Bytes;
+use iroh::{EndpointAddr, EndpointId};
+use iroh_blobs::Hash;
+use iroh_gossip::{
+    TopicId,
+    api::{Event, GossipTopic},
+};
+use n0_future::{Stream, StreamExt as _};
+use serde::{Deserialize, Serialize};
+use tokio::{sync::mpsc, task::JoinHandle};
+
+use super::{
+    RoomMeta, RoomTicket, RoomVisibility, UiEvent,
+    realtime::{RealtimeMessage, RealtimePayload},
+    state::StateData,
+};
+use crate::{AppState, GameLogic, GameRoom, IrohConfig, networking::Iroh};
+
+/// How often a host repeats its advertisement, so browsers who arrive later still see it.
+pub const ADVERTISE_INTERVAL: Duration = Duration::from_secs(2);
+/// How long to wait for a registry's direct addresses before handing out its address anyway.
+const DIRECT_ADDR_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// An open room, as advertised by its host.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RoomListing {
+    /// The room's details.
+    pub meta: RoomMeta,
+    /// The ticket to join the room with.
+    pub ticket: RoomTicket,
+    /// The host advertising the room.
+    pub host: EndpointId,
+    /// How many players are in the room.
+    pub players: usize,
+}
+
+/// A node for browsing the public rooms of a game, or for acting as a tracker.
+///
+/// Listings are repeated every [ADVERTISE_INTERVAL] for as long as a room is open, so treat
+/// a room whose listing has not been seen for a few intervals as gone.
+#[derive(Clone)]
+pub struct LobbyRegistry {
+    node: Arc<Iroh>,
+    bootstrap: Vec<EndpointAddr>,
+}
+
+impl LobbyRegistry {
+    /// Start a registry node of its own, meeting other peers through the `bootstrap` trackers.
+    ///
+    /// A tracker is started with no bootstrap peers of its own.
+    pub async fn new(bootstrap: Vec<EndpointAddr>, network: &IrohConfig) -> Result<Self> {
+        let node = Iroh::memory(network).await?;
+        Ok(Self {
+            node: Arc::new(node),
+            bootstrap,
+        })
+    }
+
+    /// The address to give hosts and players who should use this registry as a tracker.
+    pub async fn addr(&self) -> EndpointAddr {
+        // Without a direct address the relay may still get peers through.
+        self.node
+            .wait_for_direct_addrs(DIRECT_ADDR_TIMEOUT)
+            .await
+            .ok();
+        self.node.endpoint().addr()
+    }
+
+    /// Listen for open public rooms of the game with [GameLogic::game_name] `game_id`.
+    ///
+    /// Each listing is yielded every time its host repeats it. Listings with a bad signature,
+    /// or that claim to come from someone other than their sender, are dropped.
+    pub async fn browse_rooms(
+        &self,
+        game_id: &str,
+    ) -> Result<impl Stream<Item = RoomListing> + Send + Unpin + 'static> {
+        let (sender, receiver) = subscribe(&self.node, game_id, &self.bootstrap)
+            .await?
+            .split();
+        let game_id = game_id.to_string();
+        let listings = receiver.filter_map(move |event| {
+            // Leaving the topic when the sender is dropped would end the stream early.
+            let _sender = &sender;
+            let Ok(Event::Received(message)) = event else {
+                return None;
+            };
+            decode_listing(&message.content).filter(|listing| listing.meta.game_id == game_id)
+        });
+        Ok(Box::pin(listings))
+    }
+}
+
+/// A running advertisement for a room, see [GameRoom::advertise]. Dropping it stops advertising.
+pub struct LobbyAdvert {
+    task: JoinHandle<()>,
+}
+
+impl Drop for LobbyAdvert {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl<G: GameLogic> GameRoom<G> {
+    /// Advertise this room to [LobbyRegistry] browsers, meeting them through the `bootstrap`
+    /// trackers.
+    ///
+    /// The room is only listed while this peer is the host, the game is still in the lobby,
+    /// the room's [RoomMeta] is public, and it has space for more players. Keep the returned
+    /// [LobbyAdvert] for as long as the room should be advertised.
+    pub async fn advertise(&self, bootstrap: Vec<EndpointAddr>) -> Result<LobbyAdvert> {
+        let state = self.state.clone();
+        let logic = self.logic.clone();
+        let node = state.node()?;
+        let (sender, mut receiver) = subscribe(&node, logic.game_name(), &bootstrap)
+            .await?
+            .split();
+        let task = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(ADVERTISE_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        let Ok(Some(advert)) = advertisement(&state, &logic).await else {
+                            continue;
+                        };
+                        sender.broadcast(advert).await.ok();
+                    }
+                    // Other hosts' listings are not ours to act on, but must not pile up.
+                    next = receiver.next() => {
+                        if next.is_none() {
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+        Ok(LobbyAdvert { task })
+    }
+
+    /// Join a room found with [LobbyRegistry::browse_rooms].
+    pub async fn join_listing(
+        logic: G,
+        listing: &RoomListing,
+        store_path: Option<PathBuf>,
+    ) -> Result<(Self, mpsc::Receiver<UiEvent<G>>)> {
+        Self::join(logic, &listing.ticket.to_string(), store_path).await
+    }
+}
+
+/// The gossip topic a game's rooms are advertised on.
+fn lobby_topic(game_id: &str) -> TopicId {
+    let hash = Hash::new([b"lobby".as_slice(), game_id.as_bytes()].concat());
+    TopicId::from_bytes(*hash.as_bytes())
+}
+
+/// Join a game's lobby topic, meeting the other peers through the trackers.
+async fn subscribe(node: &Iroh, game_id: &str, bootstrap: &[EndpointAddr]) -> Result<GossipTopic> {
+    node.add_known_addrs(bootstrap.iter().cloned());
+    let own_id = node.endpoint().id();
+    let bootstrap = bootstrap
+        .iter()
+        .map(|addr| addr.id)
+        .filter(|id| *id != own_id)
+        .collect();
+    node.subscribe_realtime(lobby_topic(game_id), bootstrap)
+        .await
+}
+
+/// Build the signed listing for a room, if it should be listed right now.
+async fn advertisement<G: GameLogic>(state: &StateData<G>, logic: &G) -> Result<Option<Bytes>> {
+    let Some(meta) = state.get_meta().await? else {
+        return Ok(None);
+    };
+    let peers = state.get_peer_list().await?;
+    let players = peers.values().filter(|peer| !peer.is_observer).count();
+    if meta.visibility != RoomVisibility::Public
+        || meta
+            .max_players
+            .is_some_and(|max_players| players >= max_players)
+        || !state.is_host().await?
+        || state.get_app_state().await? != AppState::Lobby
+    {
+        return Ok(None);
+    }
+    let ticket = RoomTicket::new(
+        logic,
+        state.ticket().await?,
+        state.chat_ticket().await?,
+        &meta.name,
+        meta.visibility,
+    );
+    let listing = RoomListing {
+        meta,
+        ticket,
+        host: state.endpoint_id,
+        players,
+    };
+    let listing = RealtimePayload::Listing(postcard::to_stdvec(&listing)?.into());
+    let message = state.realtime_message(listing)?;
+    Ok(Some(postcard::to_stdvec(&message)?.into()))
+}
+
+/// Decode a listing, checking it was signed by the host it names.
+fn decode_listing(bytes: &[u8]) -> Option<RoomListing> {
+    let message: RealtimeMessage = postcard::from_bytes(bytes).ok()?;
+    message.verify().ok()?;
+    let RealtimePayload::Listing(bytes) = &message.payload else {
+        return None;
+    };
+    let listing: RoomListing = postcard::from_bytes(bytes).ok()?;
+    (listing.host == message.from).then_some(listing)
+}
@@ -0,0 +1,53 @@
+//! Room details for lobby UIs
+//!
+//! This module contains the `RoomMeta` struct, which the host publishes so lobby screens can
+//! show a room's name, description and capacity rather than a raw ticket.
+
+use serde::{Deserialize, Serialize};
+
+use super::RoomVisibility;
+use crate::GameLogic;
+
+/// How the host describes its room, see [crate::GameRoom::set_meta].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RoomMeta {
+    /// The room's display name.
+    pub name: String,
+    /// A longer description, e.g. house rules or who the room is for.
+    pub description: String,
+    /// The [GameLogic::game_name] of the game the room is for.
+    pub game_id: String,
+    /// How many players the room takes, or `None` for no limit.
+    pub max_players: Option<usize>,
+    /// Who the room is advertised to.
+    pub visibility: RoomVisibility,
+}
+
+impl RoomMeta {
+    /// Describe a room running `logic`, with no description or player limit.
+    pub fn new<G: GameLogic>(
+        logic: &G,
+        name: impl Into<String>,
+        visibility: RoomVisibility,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            description: String::new(),
+            game_id: logic.game_name().to_string(),
+            max_players: None,
+            visibility,
+        }
+    }
+
+    /// Set the room's description.
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    /// Limit the room to `max_players` players.
+    pub fn max_players(mut self, max_players: usize) -> Self {
+        self.max_players = Some(max_players);
+        self
+    }
+}
@@ -0,0 +1,31 @@
+//! Private payloads
+//!
+//! This module seals the payloads a host sends to a single player, such as a hand of cards or
+//! a secret role. They are written to the room document like everything else, so every peer
+//! syncs them, but only the endpoint the player is on can open them.
+//!
+//! Payloads are libsodium sealed boxes addressed to the X25519 form of the endpoint's Ed25519
+//! key, so no keys need to be exchanged beyond the endpoint IDs peers already know.
+
+use anyhow::{Result, anyhow};
+use bytes::Bytes;
+use crypto_box::aead::OsRng;
+use iroh::{EndpointId, SecretKey};
+
+/// Seal a payload so that only the endpoint `to` can open it.
+pub(crate) fn seal(to: &EndpointId, payload: &[u8]) -> Result<Vec<u8>> {
+    let key = ed25519_dalek::VerifyingKey::from_bytes(to.as_bytes())?;
+    let key = crypto_box::PublicKey::from(key.to_montgomery().to_bytes());
+    key.seal(&mut OsRng, payload)
+        .map_err(|_| anyhow!("Could not seal a private payload for {to}"))
+}
+
+/// Open a payload sealed to the endpoint with `secret_key`.
+pub(crate) fn open(secret_key: &SecretKey, sealed: &[u8]) -> Result<Bytes> {
+    let key = ed25519_dalek::SigningKey::from_bytes(&secret_key.to_bytes());
+    let key = crypto_box::SecretKey::from(key.to_scalar_bytes());
+    let payload = key
+        .unseal(sealed)
+        .map_err(|_| anyhow!("Private payload was not sealed for this endpoint"))?;
+    Ok(payload.into())
+}
@@ -0,0 +1,66 @@
+//! Proposals
+//!
+//! This module contains the `Proposal` enum, the changes to a running game that a player can
+//! propose and every other player must agree to, e.g. taking back a move or agreeing a draw.
+//! It also contains the `ProposalRequest` and `ProposalOutcome` structs recorded in the room
+//! document while a proposal is open and once the host has resolved it.
+
+use std::fmt::Display;
+
+use anyhow::Result;
+use iroh::EndpointId;
+use serde::{Deserialize, Serialize};
+
+/// A change to the running game that needs every other player's approval.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Proposal {
+    /// Undo the last applied action.
+    Takeback,
+    /// Finish the game as a draw.
+    Draw,
+    /// Start the game again from its initial state, with the same players.
+    Restart,
+}
+
+impl Display for Proposal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Proposal::Takeback => write!(f, "takeback"),
+            Proposal::Draw => write!(f, "draw"),
+            Proposal::Restart => write!(f, "restart"),
+        }
+    }
+}
+
+/// An open proposal, as made by a player.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ProposalRequest {
+    /// The ID used to respond to this proposal.
+    pub id: String,
+    /// The ID of the peer who made the proposal.
+    pub from: EndpointId,
+    /// What was proposed.
+    pub proposal: Proposal,
+}
+
+impl ProposalRequest {
+    /// Create a new proposal with a fresh ID.
+    pub(crate) fn new(from: EndpointId, proposal: Proposal) -> Result<Self> {
+        let id = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos()
+            .to_string();
+        Ok(Self { id, from, proposal })
+    }
+}
+
+/// How the host resolved a proposal.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ProposalOutcome {
+    /// The ID of the resolved proposal.
+    pub id: String,
+    /// What was proposed.
+    pub proposal: Proposal,
+    /// Whether every other player agreed, in which case the host has applied the proposal.
+    pub accepted: bool,
+}
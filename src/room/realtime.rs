@@ -0,0 +1,100 @@
+//! Real-time messages
+//!
+//! This module contains the `RealtimeMessage` struct, which carries high-frequency, lossy data
+//! such as drag previews between the peers in a room over gossip. Unlike everything else in
+//! the room it never touches the documents, so it is neither persisted nor ordered, and peers
+//! who are not connected when a message is sent never see it. The same channel carries the
+//! engine's own ephemeral signals, such as typing indicators.
+
+use anyhow::{Result, anyhow};
+use bytes::Bytes;
+use iroh::{EndpointId, SecretKey, Signature};
+use iroh_blobs::Hash;
+use iroh_docs::NamespaceId;
+use iroh_gossip::TopicId;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// What a packed game message is bound to in a room with a secret, as a value is to the entry
+/// key it is written under.
+pub(crate) const GAME_MESSAGE_KEY: &[u8] = b"realtime.game_message";
+
+/// What a real-time message carries.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) enum RealtimePayload {
+    /// Bytes from the game, see [crate::GameRoom::send_realtime].
+    Game(Bytes),
+    /// A packed [crate::GameLogic::GameMessage], see [crate::GameRoom::broadcast_game_message].
+    GameMessage(Bytes),
+    /// Whether the sender is typing a chat message, see [crate::GameRoom::set_typing].
+    Typing(bool),
+    /// A room advertised to a lobby, see [crate::LobbyRegistry].
+    Listing(Bytes),
+    /// A player announcing they are around, see [crate::Presence].
+    Presence(Bytes),
+}
+
+/// A signed message on the room's real-time channel.
+///
+/// Gossip only reports which neighbour relayed a message, so the sender signs it to prove
+/// where it came from.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct RealtimeMessage {
+    pub(crate) from: EndpointId,
+    /// When the message was sent, in nanoseconds since the Unix epoch. Gossip drops repeated
+    /// messages, so this keeps identical payloads sent twice apart.
+    sent_at: u128,
+    pub(crate) payload: RealtimePayload,
+    signature: Signature,
+}
+
+impl RealtimeMessage {
+    /// Sign a payload as the endpoint with `secret_key`.
+    pub(crate) fn sign(secret_key: &SecretKey, payload: RealtimePayload) -> Result<Self> {
+        Self::sign_at(secret_key, payload, SystemTime::now())
+    }
+
+    /// Sign a payload as the endpoint with `secret_key`, dated `sent_at`.
+    pub(crate) fn sign_at(
+        secret_key: &SecretKey,
+        payload: RealtimePayload,
+        sent_at: SystemTime,
+    ) -> Result<Self> {
+        let from = secret_key.public();
+        let sent_at = sent_at.duration_since(UNIX_EPOCH)?.as_nanos();
+        let signature = secret_key.sign(&signed_payload(&from, sent_at, &payload)?);
+        Ok(Self {
+            from,
+            sent_at,
+            payload,
+            signature,
+        })
+    }
+
+    /// When the sender says the message was sent.
+    pub(crate) fn sent_at(&self) -> Option<SystemTime> {
+        let nanos = u64::try_from(self.sent_at).ok()?;
+        UNIX_EPOCH.checked_add(Duration::from_nanos(nanos))
+    }
+
+    /// Check that the message was signed by the endpoint it claims to be from.
+    pub(crate) fn verify(&self) -> Result<()> {
+        self.from
+            .verify(
+                &signed_payload(&self.from, self.sent_at, &self.payload)?,
+                &self.signature,
+            )
+            .map_err(|_| anyhow!("Real-time message from {} has a bad signature", self.from))
+    }
+}
+
+/// The gossip topic a room's real-time messages are sent on.
+pub(crate) fn realtime_topic(namespace: NamespaceId) -> TopicId {
+    let hash = Hash::new([b"realtime".as_slice(), namespace.as_bytes()].concat());
+    TopicId::from_bytes(*hash.as_bytes())
+}
+
+fn signed_payload(from: &EndpointId, sent_at: u128, payload: &RealtimePayload) -> Result<Vec<u8>> {
+    let payload = postcard::to_stdvec(payload)?;
+    Ok([from.as_bytes().as_slice(), &sent_at.to_le_bytes(), &payload].concat())
+}
@@ -0,0 +1,87 @@
+//! Game results
+//!
+//! This module contains the `GameOutcome` struct, which game logic returns once a game has been
+//! decided, and the `GameResult` struct, which the host records when it finishes the game so
+//! post-game screens can show winners and scores without parsing the final game state.
+
+use std::{collections::HashMap, time::Duration};
+
+use iroh::EndpointId;
+use serde::{Deserialize, Serialize};
+
+use super::MatchStats;
+
+/// Why a game finished.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum FinishReason {
+    /// The game reached one of its own end conditions, e.g. a win or a draw.
+    Completed,
+    /// The host finished the game because nobody acted for too long.
+    Inactivity,
+    /// The players agreed to end the game, e.g. by accepting a draw offer.
+    Agreed,
+    /// A game specific reason, e.g. "checkmate" or "resignation".
+    Custom(String),
+}
+
+/// The decided outcome of a game, as reported by [crate::GameLogic::game_outcome].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GameOutcome {
+    /// The peers who won. Empty for a draw or an abandoned game.
+    pub winners: Vec<EndpointId>,
+    /// Final scores, for games that keep score.
+    pub scores: HashMap<EndpointId, i64>,
+    /// Why the game finished.
+    pub reason: FinishReason,
+}
+
+impl GameOutcome {
+    /// Create an outcome with no winners or scores.
+    pub fn new(reason: FinishReason) -> Self {
+        Self {
+            winners: Vec::new(),
+            scores: HashMap::new(),
+            reason,
+        }
+    }
+
+    /// Add a winner to the outcome.
+    pub fn winner(mut self, peer_id: EndpointId) -> Self {
+        self.winners.push(peer_id);
+        self
+    }
+
+    /// Set a peer's final score.
+    pub fn score(mut self, peer_id: EndpointId, score: i64) -> Self {
+        self.scores.insert(peer_id, score);
+        self
+    }
+}
+
+/// The final result of a game, recorded by the host when it finishes the game.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GameResult {
+    /// The peers who won. Empty for a draw or an abandoned game.
+    pub winners: Vec<EndpointId>,
+    /// Final scores, for games that keep score.
+    pub scores: HashMap<EndpointId, i64>,
+    /// Why the game finished.
+    pub reason: FinishReason,
+    /// How long the game ran, from the start of the game until it finished.
+    pub duration: Duration,
+    /// How each player took part in the game.
+    pub stats: MatchStats,
+}
+
+impl GameResult {
+    /// Record an outcome along with how long the game ran and how the players took part.
+    pub(crate) fn new(outcome: GameOutcome, duration: Duration, stats: MatchStats) -> Self {
+        Self {
+            winners: outcome.winners,
+            scores: outcome.scores,
+            reason: outcome.reason,
+            duration,
+            stats,
+        }
+    }
+}
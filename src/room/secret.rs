@@ -0,0 +1,141 @@
+//! Room secrets
+//!
+//! This module encrypts the values a room writes to its documents with a key derived from a
+//! secret its players share, so a relay, or anyone who gets hold of the ticket, can sync the
+//! room without reading its game state or chat.
+//!
+//! The key is stretched from the secret with Argon2id, salted with the room's document ID,
+//! which is random for every room, so the same secret gives every room a different key and
+//! guessing a passphrase costs an attacker a memory-hard hash per guess, per room. Values are
+//! encrypted with XSalsa20-Poly1305 after compression, each under a random nonce.
+//! XSalsa20-Poly1305 takes no associated data, so each value is sealed under a key derived from
+//! the room key and the entry key it is written under instead, and a value copied to another
+//! entry fails to decrypt rather than being read as that entry. Entry keys, authors and
+//! timestamps stay readable, as does the room metadata, which records a MAC of a fixed label
+//! under the key so joiners with the wrong secret are turned away straight away, without it
+//! being a hash of the key itself.
+
+use std::fmt;
+
+use anyhow::{Result, anyhow};
+use argon2::{Algorithm, Argon2, Params, Version};
+use bytes::Bytes;
+use crypto_secretbox::{
+    KeyInit, XSalsa20Poly1305,
+    aead::{Aead, AeadCore, OsRng},
+};
+use iroh_blobs::Hash;
+use iroh_docs::NamespaceId;
+use serde::{Deserialize, Serialize};
+
+/// Length of the nonce written ahead of each encrypted value.
+const NONCE_LEN: usize = 24;
+/// The label the key check is a MAC of.
+const KEY_CHECK_LABEL: &[u8] = b"p2p-game-engine room key check";
+/// Argon2id memory cost in KiB, time cost and lanes, as OWASP recommends for passwords.
+const ARGON2_PARAMS: (u32, u32, u32) = (19 * 1024, 2, 1);
+
+/// A secret shared by a room's players, out-of-band or in an invite, see
+/// [crate::RoomConfig::secret].
+///
+/// Anything can serve as a secret, but it is only as strong as it is hard to guess, so prefer
+/// a [RoomSecret::generate]d one to a short passphrase.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct RoomSecret(String);
+
+impl RoomSecret {
+    /// Use `secret`, e.g. a passphrase the players agreed on, as a room secret.
+    pub fn new(secret: impl Into<String>) -> Self {
+        Self(secret.into())
+    }
+
+    /// Generate a random secret.
+    pub fn generate() -> Self {
+        let key = XSalsa20Poly1305::generate_key(&mut OsRng);
+        Self(key.iter().map(|byte| format!("{byte:02x}")).collect())
+    }
+
+    /// The secret, for handing to the other players.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for RoomSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RoomSecret(..)")
+    }
+}
+
+/// The key a room's values are encrypted with.
+pub(crate) struct RoomKey {
+    /// The key each value's own key is derived from, see [RoomKey::cipher].
+    key: [u8; 32],
+    check: Hash,
+}
+
+impl RoomKey {
+    /// Derive the key for the room document `namespace` from the players' secret.
+    ///
+    /// This takes a noticeable moment on purpose, and is done once per room opened, on a
+    /// blocking thread so it holds up nothing else.
+    pub(crate) async fn derive(secret: &RoomSecret, namespace: &NamespaceId) -> Result<Self> {
+        let (secret, namespace) = (secret.clone(), *namespace);
+        tokio::task::spawn_blocking(move || Self::derive_blocking(&secret, &namespace)).await?
+    }
+
+    fn derive_blocking(secret: &RoomSecret, namespace: &NamespaceId) -> Result<Self> {
+        let (memory, time, lanes) = ARGON2_PARAMS;
+        let params = Params::new(memory, time, lanes, Some(64))
+            .map_err(|e| anyhow!("Invalid key derivation parameters: {e}"))?;
+        let mut derived = [0u8; 64];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(secret.0.as_bytes(), namespace.as_bytes(), &mut derived)
+            .map_err(|e| anyhow!("Could not derive the room key: {e}"))?;
+        // One half encrypts values, the other proves the key without revealing anything of it.
+        let (key, mac_key) = derived.split_at(32);
+        let mac_key: &[u8; 32] = mac_key.try_into()?;
+        let check = blake3::keyed_hash(mac_key, KEY_CHECK_LABEL);
+        Ok(Self {
+            key: key.try_into()?,
+            check: Hash::from_bytes(*check.as_bytes()),
+        })
+    }
+
+    /// The cipher for values written under `entry_key`.
+    fn cipher(&self, entry_key: &[u8]) -> XSalsa20Poly1305 {
+        let key = blake3::keyed_hash(&self.key, entry_key);
+        XSalsa20Poly1305::new(key.as_bytes().into())
+    }
+
+    /// A MAC of a fixed label under the key, recorded in the room metadata.
+    pub(crate) fn check(&self) -> Hash {
+        self.check
+    }
+
+    /// Encrypt a value before it is written under `entry_key`.
+    pub(crate) fn encrypt(&self, entry_key: &[u8], value: &[u8]) -> Result<Vec<u8>> {
+        let nonce = XSalsa20Poly1305::generate_nonce(&mut OsRng);
+        let mut sealed = nonce.to_vec();
+        sealed.extend(
+            self.cipher(entry_key)
+                .encrypt(&nonce, value)
+                .map_err(|_| anyhow!("Could not encrypt a room value"))?,
+        );
+        Ok(sealed)
+    }
+
+    /// Decrypt a value that was written under `entry_key` with [RoomKey::encrypt].
+    pub(crate) fn decrypt(&self, entry_key: &[u8], sealed: &[u8]) -> Result<Bytes> {
+        if sealed.len() < NONCE_LEN {
+            return Err(anyhow!("Encrypted room value is truncated"));
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let value = self
+            .cipher(entry_key)
+            .decrypt(nonce.into(), ciphertext)
+            .map_err(|_| anyhow!("Room value was not encrypted with this room's secret"))?;
+        Ok(value.into())
+    }
+}
@@ -0,0 +1,223 @@
+//! Resuming the last room from a persistent store.
+//!
+//! Whenever a room is opened on a persistent node, the node's data directory remembers it in
+//! `last_room.json`, so [GameRoom::resume] can open it again after a restart without asking
+//! the player for the ticket. The room's secret, if it has one, is never written there, but
+//! handed to the node's [crate::KeyStore], which only keeps it if it is somewhere safe. It is
+//! also added to the node's list of rooms, see [crate::StoredRoom].
+
+use crate::{
+    GameLogic, GameRoom, PeerStatus, RoomConfig, RoomTicket, StorageBackend, StoredRoom, UiEvent,
+    networking::Iroh, room::state::StateData,
+};
+use anyhow::{Context as _, Result, anyhow};
+use iroh::EndpointAddr;
+use iroh_docs::AuthorId;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::Path,
+    path::PathBuf,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::mpsc;
+
+/// File name of the last opened room within a persistent data directory.
+const LAST_ROOM_FILE: &str = "last_room.json";
+
+/// The saved details of the last room opened on a persistent node.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct LastRoom {
+    /// The room's ticket, carrying its documents and the peers to sync with.
+    ticket: RoomTicket,
+    /// The author this node wrote to the room as.
+    author: AuthorId,
+}
+
+impl LastRoom {
+    async fn load(data_dir: &Path) -> Result<Self> {
+        let path = data_dir.join(LAST_ROOM_FILE);
+        let bytes = match tokio::fs::read(&path).await {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(anyhow!("No room to resume in {}", data_dir.display()));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        serde_json::from_slice(&bytes)
+            .with_context(|| format!("Invalid saved room {}", path.display()))
+    }
+
+    async fn save(&self, data_dir: &Path) -> Result<()> {
+        let bytes = serde_json::to_vec_pretty(self)?;
+        tokio::fs::write(data_dir.join(LAST_ROOM_FILE), bytes).await?;
+        Ok(())
+    }
+}
+
+impl<G: GameLogic> GameRoom<G> {
+    /// Re-open the last room used with the persistent store at `store_path`.
+    ///
+    /// See [GameRoom::resume_with_config].
+    pub async fn resume(
+        logic: G,
+        store_path: PathBuf,
+    ) -> Result<(Self, mpsc::Receiver<UiEvent<G>>)> {
+        Self::resume_with_config(logic, store_path, RoomConfig::default()).await
+    }
+
+    /// Re-open the last room used with the persistent store at `store_path`, with custom
+    /// room settings.
+    ///
+    /// The room is read back from the store, so no ticket is needed, and this peer writes
+    /// as the same author as before. If it was the host and nobody has taken over since, it
+    /// carries on hosting. The room's current [crate::AppState], game state and [crate::PeerMap] are
+    /// reported as the first events, before anything that has changed while it was away.
+    ///
+    /// A room written by an older build can be resumed, and its host records this build's
+    /// protocol in it, while game states an older build wrote are read with
+    /// [GameLogic::migrate_state].
+    ///
+    /// An encrypted room needs its secret in `config`, unless the node's [crate::KeyStore]
+    /// kept it, see [crate::KeyStore::save_room_secret].
+    pub async fn resume_with_config(
+        logic: G,
+        store_path: PathBuf,
+        mut config: RoomConfig,
+    ) -> Result<(Self, mpsc::Receiver<UiEvent<G>>)> {
+        let last_room = LastRoom::load(&store_path).await?;
+        let mut ticket = last_room.ticket;
+        if config.secret.is_none()
+            && let Some(key_store) = &config.network.key_store
+        {
+            config.secret = key_store.load_room_secret(&ticket.doc_ticket.capability.id())?;
+        }
+        // Rooms saved before secrets were kept out of the file still carry theirs.
+        if config.secret.is_none() {
+            config.secret = ticket.secret.take();
+        }
+        ticket.validate_stored_for(&logic)?;
+        let storage = match &config.network.storage {
+            Some(storage) => storage.clone(),
+            None => StorageBackend::Fs(store_path),
+        };
+        let iroh = Iroh::spawn(storage, &config.network).await?;
+        let state = StateData::reopen(iroh, &ticket, last_room.author, &config).await?;
+        // Stored rooms already hold their metadata, so this only checks it, and a room that
+        // can't be read releases the store again.
+        if let Err(err) = state
+            .wait_for_valid_room_metadata(&logic, super::JOIN_TIMEOUT, &[], true)
+            .await
+        {
+            state.shutdown().await.ok();
+            return Err(err);
+        }
+
+        // Catch up with whoever is still around, as well as the peers in the ticket.
+        let mut peers: Vec<EndpointAddr> = ticket
+            .doc_ticket
+            .nodes
+            .iter()
+            .filter(|addr| addr.id != state.endpoint_id)
+            .cloned()
+            .collect();
+        for (id, peer) in state.get_peer_list().await?.iter() {
+            if *id != state.endpoint_id
+                && !peer.is_seat()
+                && !peers.iter().any(|addr| addr.id == *id)
+            {
+                peers.push(EndpointAddr::from(*id));
+            }
+        }
+        state.doc.start_sync(peers.clone()).await?;
+        if let Some(chat_doc) = &state.chat_doc {
+            chat_doc.start_sync(peers).await?;
+        }
+
+        if state.is_host().await? {
+            state.upgrade_protocol().await?;
+            ticket.protocol_version = state.get_room_metadata().await?.protocol_version;
+            state.set_host(&state.endpoint_id).await?;
+            state
+                .set_peer_status(&state.endpoint_id, PeerStatus::Online)
+                .await?;
+        }
+
+        let mut initial = vec![UiEvent::AppState(state.get_app_state().await?)];
+        if let Ok(game_state) = state.get_game_state().await {
+            initial.push(UiEvent::GameState(game_state));
+        }
+        initial.push(UiEvent::Peer(state.get_peer_list().await?));
+
+        let mut room = Self::new(state, Arc::new(logic), &ticket.room_name, config);
+        let (event_inbox, event_handle) = room.start_event_loop(initial).await?;
+        room.event_handle = Some(event_handle);
+        room.remember(Some(ticket)).await?;
+        Ok((room, event_inbox))
+    }
+
+    /// The games this room's node saw finish, in this room or any other, most recent first.
+    ///
+    /// Only persistent rooms keep a history, see [crate::MatchRecord].
+    pub async fn match_history(&self) -> Result<Vec<crate::MatchRecord>> {
+        self.state.iroh()?.match_history().await
+    }
+
+    /// The rooms this room's node keeps, most recently opened first, this one included.
+    ///
+    /// Only persistent rooms keep a list, see [crate::StoredRoom].
+    pub async fn list_rooms(&self) -> Result<Vec<crate::StoredRoom>> {
+        self.state.iroh()?.list_rooms().await
+    }
+
+    /// Delete another room kept on this room's node, e.g. to clear out old games.
+    ///
+    /// `room_id` is the room's [crate::StoredRoom::room_id]. This room cannot delete itself.
+    pub async fn delete_room(&self, room_id: &str) -> Result<()> {
+        if room_id == self.state.doc.id().to_string() {
+            return Err(anyhow!("Leave the room before deleting it"));
+        }
+        self.state.iroh()?.delete_room(room_id).await
+    }
+
+    /// How many bytes this room's node takes on disk, for every room it keeps, or zero if it
+    /// runs in memory.
+    pub async fn storage_usage(&self) -> Result<u64> {
+        self.state.iroh()?.storage_usage().await
+    }
+
+    /// Remember this room as the one to resume, and in the node's list of rooms, if it runs
+    /// on a persistent node.
+    ///
+    /// `ticket` is the one to reach the room with later, or `None` for this room's own ticket.
+    pub(super) async fn remember(&self, ticket: Option<RoomTicket>) -> Result<()> {
+        let iroh = self.state.iroh()?;
+        let Some(data_dir) = iroh.data_dir() else {
+            return Ok(());
+        };
+        iroh.remember_room(StoredRoom {
+            room_id: self.state.doc.id().to_string(),
+            chat_id: self.state.chat_doc.as_ref().map(|doc| doc.id().to_string()),
+            room_name: self.name.clone(),
+            game: self.logic.game_name().to_string(),
+            last_opened: SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64,
+            size: 0,
+            author: Some(self.state.author_id),
+        })
+        .await?;
+        let mut ticket = match ticket {
+            Some(ticket) => ticket,
+            None => self.ticket().await?,
+        };
+        // The secret is the one thing the ticket shouldn't take to disk.
+        let secret = ticket.secret.take().or_else(|| self.config.secret.clone());
+        if let (Some(secret), Some(key_store)) = (secret, &self.config.network.key_store) {
+            key_store.save_room_secret(&self.state.doc.id(), &secret)?;
+        }
+        let last_room = LastRoom {
+            ticket,
+            author: self.state.author_id,
+        };
+        last_room.save(data_dir).await
+    }
+}
@@ -21,7 +21,7 @@ pub struct RoomSnapshot<G: GameLogic> {
     /// Whether the current host is known to be offline by this room instance.
     pub host_disconnected: bool,
     /// The current application lifecycle state, including synthetic pause.
-    pub app_state: AppState,
+    pub app_state: AppState<G>,
     /// The latest known peer map, including synthetic host offline status.
     pub peers: PeerMap,
     /// The latest host-authored game state, if one has been published.
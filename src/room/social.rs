@@ -0,0 +1,321 @@
+//! Friends and presence
+//!
+//! A player's [Friends] are the peers they chose to remember, saved in their identity's data
+//! directory. While [Presence] runs, the player announces themselves on a gossip topic shared
+//! by everyone playing the same game, and hears which friends are around, so a client can
+//! show who is available to invite before any room exists.
+//!
+//! Announcements are signed with the identity's key, so they name the same EndpointId the
+//! player has in every room, even though presence runs on a node of its own. Like the lobby
+//! browser, peers meet through the address of a tracker: any long-running [Presence].
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Result, anyhow};
+use bytes::Bytes;
+use iroh::{EndpointAddr, EndpointId, SecretKey};
+use iroh_blobs::Hash;
+use iroh_gossip::{TopicId, api::Event};
+use n0_future::StreamExt as _;
+use serde::{Deserialize, Serialize};
+use tokio::{sync::mpsc, task::JoinHandle, time::Instant};
+
+use super::{
+    UiEvent,
+    realtime::{RealtimeMessage, RealtimePayload},
+};
+use crate::{GameLogic, Identity, IrohConfig, networking::Iroh};
+
+/// How often a player repeats their presence announcement.
+pub const PRESENCE_INTERVAL: Duration = Duration::from_secs(2);
+/// How long after their last announcement a friend still counts as online.
+const PRESENCE_TIMEOUT: Duration = Duration::from_secs(6);
+/// How far ahead of this device's clock an announcement may be dated, for clocks that drift.
+const MAX_CLOCK_SKEW: Duration = Duration::from_secs(2);
+/// How long to wait for the presence node's direct addresses before handing out its address.
+const DIRECT_ADDR_TIMEOUT: Duration = Duration::from_secs(5);
+/// File name of the saved friends within an identity's data directory.
+const FRIENDS_FILE: &str = "friends.json";
+
+/// A peer a player chose to remember.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Friend {
+    /// The friend's EndpointId, the same in every room they enter with their identity.
+    pub id: EndpointId,
+    /// The friend's nickname, as last announced.
+    pub name: String,
+}
+
+/// The friends of an identity, saved in its data directory.
+#[derive(Debug, Clone)]
+pub struct Friends {
+    data_dir: PathBuf,
+    friends: BTreeMap<EndpointId, Friend>,
+}
+
+impl Friends {
+    /// Load the friends saved in an identity's data directory.
+    pub async fn load(identity: &Identity) -> Result<Self> {
+        let data_dir = identity.data_dir().to_path_buf();
+        let friends: Vec<Friend> = match tokio::fs::read(data_dir.join(FRIENDS_FILE)).await {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            data_dir,
+            friends: friends
+                .into_iter()
+                .map(|friend| (friend.id, friend))
+                .collect(),
+        })
+    }
+
+    /// Remember a peer as a friend, or update their name. Call [Friends::save] to keep it.
+    pub fn add(&mut self, id: EndpointId, name: impl Into<String>) {
+        let name = name.into();
+        self.friends.insert(id, Friend { id, name });
+    }
+
+    /// Forget a friend. Call [Friends::save] to keep the change.
+    pub fn remove(&mut self, id: &EndpointId) -> Option<Friend> {
+        self.friends.remove(id)
+    }
+
+    /// Get a friend by their EndpointId.
+    pub fn get(&self, id: &EndpointId) -> Option<&Friend> {
+        self.friends.get(id)
+    }
+
+    /// Every friend, in EndpointId order.
+    pub fn list(&self) -> Vec<Friend> {
+        self.friends.values().cloned().collect()
+    }
+
+    /// Write the friends back to the data directory.
+    pub async fn save(&self) -> Result<()> {
+        let friends: Vec<_> = self.friends.values().collect();
+        let bytes = serde_json::to_vec_pretty(&friends)?;
+        tokio::fs::write(self.data_dir.join(FRIENDS_FILE), bytes).await?;
+        Ok(())
+    }
+}
+
+/// What a player announces about themselves.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Announcement {
+    name: String,
+}
+
+/// The friends a running [Presence] knows about, and when it last heard from each of them.
+#[derive(Debug)]
+struct Seen {
+    friends: Friends,
+    last_seen: HashMap<EndpointId, Instant>,
+    /// When each friend's newest announcement was sent. Older ones are replays.
+    newest: HashMap<EndpointId, SystemTime>,
+}
+
+impl Seen {
+    /// Note an announcement, returning the friend who sent it if they just came online.
+    fn heard(
+        &mut self,
+        from: EndpointId,
+        sent_at: SystemTime,
+        announcement: Announcement,
+    ) -> Option<Friend> {
+        let friend = self.friends.friends.get_mut(&from)?;
+        if self
+            .newest
+            .get(&from)
+            .is_some_and(|newest| sent_at <= *newest)
+        {
+            return None;
+        }
+        self.newest.insert(from, sent_at);
+        friend.name = announcement.name;
+        let was_online = self.last_seen.insert(from, Instant::now()).is_some();
+        (!was_online).then(|| friend.clone())
+    }
+
+    /// Forget the friends not heard from for a while, returning them.
+    fn expire(&mut self) -> Vec<Friend> {
+        let gone: Vec<_> = self
+            .last_seen
+            .iter()
+            .filter(|(_, at)| at.elapsed() >= PRESENCE_TIMEOUT)
+            .map(|(id, _)| *id)
+            .collect();
+        gone.iter()
+            .filter_map(|id| {
+                self.last_seen.remove(id);
+                self.friends.get(id).cloned()
+            })
+            .collect()
+    }
+}
+
+/// A player's presence, announced to everyone playing the same game. Dropping it goes offline.
+///
+/// Friends who come and go are reported as [UiEvent::FriendOnline] and
+/// [UiEvent::FriendOffline] events, and [Presence::friends_online] lists who is around now.
+pub struct Presence {
+    node: Arc<Iroh>,
+    seen: Arc<Mutex<Seen>>,
+    task: JoinHandle<()>,
+}
+
+impl Drop for Presence {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl Presence {
+    /// Go online as `identity` for the game with `logic`'s [GameLogic::game_name], looking out
+    /// for `friends`, and meeting other players through the `bootstrap` trackers.
+    ///
+    /// Pass no trackers to be one.
+    pub async fn start<G: GameLogic>(
+        logic: &G,
+        identity: &Identity,
+        friends: Friends,
+        bootstrap: Vec<EndpointAddr>,
+        network: &IrohConfig,
+    ) -> Result<(Self, mpsc::Receiver<UiEvent<G>>)> {
+        let key = identity.secret_key()?;
+        let node = Arc::new(Iroh::memory(network).await?);
+        node.add_known_addrs(bootstrap.iter().cloned());
+        let bootstrap = bootstrap.iter().map(|addr| addr.id).collect();
+        let (sender, mut receiver) = node
+            .subscribe_realtime(presence_topic(logic.game_name()), bootstrap)
+            .await?
+            .split();
+        let seen = Arc::new(Mutex::new(Seen {
+            friends,
+            last_seen: HashMap::new(),
+            newest: HashMap::new(),
+        }));
+        let name = identity.profile.nickname.clone();
+        let (events, inbox) = mpsc::channel(32);
+
+        let shared = seen.clone();
+        let task = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PRESENCE_INTERVAL);
+            loop {
+                let changes = tokio::select! {
+                    _ = interval.tick() => {
+                        if let Ok(announcement) = announcement(&key, &name, SystemTime::now()) {
+                            sender.broadcast(announcement).await.ok();
+                        }
+                        let gone = match shared.lock() {
+                            Ok(mut seen) => seen.expire(),
+                            Err(_) => Vec::new(),
+                        };
+                        gone.into_iter().map(UiEvent::FriendOffline).collect()
+                    }
+                    next = receiver.next() => {
+                        let Some(event) = next else {
+                            break;
+                        };
+                        let Ok(Event::Received(message)) = event else {
+                            continue;
+                        };
+                        let Some((from, sent_at, announcement)) =
+                            decode_announcement(&message.content)
+                        else {
+                            continue;
+                        };
+                        let came_online = match shared.lock() {
+                            Ok(mut seen) => seen.heard(from, sent_at, announcement),
+                            Err(_) => None,
+                        };
+                        came_online.into_iter().map(UiEvent::FriendOnline).collect::<Vec<_>>()
+                    }
+                };
+                // Nobody may be listening for the events, but presence carries on regardless.
+                for change in changes {
+                    events.send(change).await.ok();
+                }
+            }
+        });
+        Ok((Self { node, seen, task }, inbox))
+    }
+
+    /// The address to give players who should use this presence as a tracker.
+    pub async fn addr(&self) -> EndpointAddr {
+        // Fall back to the relay if no direct address turns up.
+        self.node
+            .wait_for_direct_addrs(DIRECT_ADDR_TIMEOUT)
+            .await
+            .ok();
+        self.node.endpoint().addr()
+    }
+
+    /// The friends who have announced themselves recently.
+    pub fn friends_online(&self) -> Result<Vec<Friend>> {
+        let seen = self
+            .seen
+            .lock()
+            .map_err(|_| anyhow!("Presence state is poisoned"))?;
+        Ok(seen
+            .last_seen
+            .keys()
+            .filter_map(|id| seen.friends.get(id).cloned())
+            .collect())
+    }
+
+    /// Look out for a different set of friends from now on, e.g. after adding one.
+    pub fn set_friends(&self, friends: Friends) -> Result<()> {
+        let mut seen = self
+            .seen
+            .lock()
+            .map_err(|_| anyhow!("Presence state is poisoned"))?;
+        seen.last_seen.retain(|id, _| friends.get(id).is_some());
+        seen.newest.retain(|id, _| friends.get(id).is_some());
+        seen.friends = friends;
+        Ok(())
+    }
+}
+
+/// The gossip topic the players of a game announce their presence on.
+pub(crate) fn presence_topic(game_id: &str) -> TopicId {
+    let hash = Hash::new([b"presence".as_slice(), game_id.as_bytes()].concat());
+    TopicId::from_bytes(*hash.as_bytes())
+}
+
+/// Build a signed announcement of the player with `key`, dated `sent_at`.
+pub(crate) fn announcement(key: &SecretKey, name: &str, sent_at: SystemTime) -> Result<Bytes> {
+    let announcement = Announcement {
+        name: name.to_string(),
+    };
+    let payload = RealtimePayload::Presence(postcard::to_stdvec(&announcement)?.into());
+    let message = RealtimeMessage::sign_at(key, payload, sent_at)?;
+    Ok(postcard::to_stdvec(&message)?.into())
+}
+
+/// Decode an announcement, checking it was signed by the player it comes from, and that it
+/// was sent recently enough to mean they are still around.
+fn decode_announcement(bytes: &[u8]) -> Option<(EndpointId, SystemTime, Announcement)> {
+    let message: RealtimeMessage = postcard::from_bytes(bytes).ok()?;
+    message.verify().ok()?;
+    let RealtimePayload::Presence(bytes) = &message.payload else {
+        return None;
+    };
+    // Anyone who heard an announcement can send it again, long after the player left.
+    let sent_at = message.sent_at()?;
+    let now = SystemTime::now();
+    if sent_at > now + MAX_CLOCK_SKEW
+        || now
+            .duration_since(sent_at)
+            .is_ok_and(|age| age >= PRESENCE_TIMEOUT)
+    {
+        return None;
+    }
+    Some((message.from, sent_at, postcard::from_bytes(bytes).ok()?))
+}
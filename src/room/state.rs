@@ -9,48 +9,209 @@
 //! certain events have occurred, such as if a peer has joined or quit, if an action has been requested or processed,
 //! and if a chat message has been sent.
 
+mod action_log;
 mod actions;
+mod assets;
+mod cadence;
+mod checkpoint;
+mod clock;
+mod codec;
+mod commitments;
+mod compaction;
 mod game_key;
+mod handover;
+mod history;
 mod lifecycle;
+mod lobby;
+mod lockstep;
 mod metadata;
+mod migration;
+mod peers;
+mod prediction;
+mod proposals;
 mod queries;
+mod rate_limit;
+mod rounds;
+mod stats;
+mod time;
+mod votes;
+mod watches;
 
-use crate::{GameLogic, Iroh};
+use crate::{GameLogic, Iroh, room::secret::RoomKey};
 use anyhow::{Result, anyhow};
 use bytes::Bytes;
 use iroh::EndpointId;
+use iroh_blobs::Hash;
 use iroh_docs::api::{
     Doc,
     protocol::{AddrInfoOptions, ShareMode},
 };
 use iroh_docs::store::Query;
 use iroh_docs::{AuthorId, DocTicket, Entry};
+use lru::LruCache;
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use std::{
+    collections::HashMap,
     marker::PhantomData,
+    num::NonZeroUsize,
     path::PathBuf,
     str::FromStr as _,
-    sync::{Arc, atomic::AtomicBool},
+    sync::{Arc, Mutex, OnceLock},
+    time::Instant,
 };
 
-pub use actions::{ActionRequest, ActionResult};
+pub use action_log::AppliedAction;
+pub use actions::{ActionError, ActionRequest, ActionResponse, SeatRequest};
+pub use assets::Asset;
+pub(crate) use checkpoint::RollbackNotice;
+use clock::LogicalClock;
+pub use codec::{Compression, MAX_DECOMPRESSED_LEN, WireFormat};
+use commitments::PendingReveal;
+pub use commitments::Reveal;
+pub use compaction::{CompactionMark, DocStats};
 pub use game_key::GameKey;
-pub use lifecycle::{AppState, LeaveReason};
+pub(crate) use handover::HostHandover;
+pub use lifecycle::{AppState, JoinDeniedReason, LeaveReason};
+use lockstep::Lockstep;
+pub(crate) use lockstep::LockstepUpdate;
+pub(crate) use metadata::PROTOCOL_VERSION;
 pub use metadata::RoomMetadata;
+use migration::VersionedState;
+use prediction::Predictions;
+pub(crate) use prediction::{PredictionUpdate, Reconciled};
+use rate_limit::TokenBucket;
+pub(crate) use rate_limit::WriteKind;
+pub(crate) use rounds::Round;
+use rounds::Rounds;
+use time::RoomTime;
+pub use watches::Activity;
+use watches::RoomWatches;
 
 /// Wrapper for the Iroh Document
 #[derive(Clone)]
 pub struct StateData<G: GameLogic> {
-    /// If we are not the host, and the host is offline, we pause.
-    host_disconnected: Arc<AtomicBool>,
+    /// The app state and host connection, as [crate::GameRoom::watch_app_state] and
+    /// [crate::GameRoom::watch_host_online] report them. If we are not the host, and the host
+    /// is offline, we pause.
+    watches: Arc<RoomWatches<G>>,
+    /// The room's monotonic time, which tests can move forward.
+    time: Arc<RoomTime>,
+    /// When this peer last saw game activity, used by the host's inactivity policy.
+    last_activity: Arc<Mutex<Instant>>,
+    /// When each peer's latest heartbeat arrived, used by the host to spot silent peers.
+    heartbeats: Arc<Mutex<HashMap<EndpointId, Instant>>>,
+    /// How many entries each peer may write, see [crate::RoomConfig::rate_limits].
+    rate_limits: crate::RateLimits,
+    /// What each peer may still write of each rate-limited kind, used by the host.
+    rate_buckets: Arc<Mutex<HashMap<(WriteKind, EndpointId), TokenBucket>>>,
+    /// The sequence number for each local player's next action, loaded from the doc on first use.
+    next_action_seq: Arc<tokio::sync::Mutex<HashMap<EndpointId, u64>>>,
+    /// Actions this endpoint's players committed to and have not revealed yet.
+    pending_reveals: Arc<Mutex<HashMap<EndpointId, PendingReveal<G::GameAction>>>>,
+    /// The room's hybrid logical clock, which orders chat messages and action requests.
+    clock: Arc<LogicalClock>,
+    /// Avatar images already fetched from the blob store, keyed by blob hash.
+    avatar_cache: Arc<Mutex<LruCache<Hash, Bytes>>>,
+    /// Recently read entry values, decompressed and keyed by entry key and content hash.
+    content_cache: Arc<Mutex<LruCache<ContentKey, Bytes>>>,
+    /// The room's peer list, once it has been read, see [StateData::get_peer_list].
+    peers: Arc<tokio::sync::Mutex<Option<crate::PeerMap>>>,
+    /// Encoded game states larger than this many bytes are stored as blobs.
+    state_blob_threshold: usize,
+    /// How the host collects simultaneous actions, see [crate::RoomConfig::rounds].
+    round_policy: Option<crate::RoundPolicy>,
+    /// The rounds the host has closed and is collecting.
+    rounds: Arc<Mutex<Rounds<G::GameAction>>>,
+    /// Download assets as soon as the host announces them, see
+    /// [crate::RoomConfig::fetch_assets].
+    fetch_assets: bool,
+    /// How the room's values are compressed, chosen by the host and read from the metadata.
+    compression: Arc<OnceLock<Compression>>,
+    /// How the room's values are serialized, chosen by the host and read from the metadata.
+    wire_format: Arc<OnceLock<WireFormat>>,
+    /// The key the room's values are encrypted with, if it has a secret, see
+    /// [crate::RoomConfig::secret].
+    room_key: Option<Arc<RoomKey>>,
+    /// Local actions applied ahead of the host, see [crate::RoomConfig::predict].
+    predictions: Arc<Mutex<Predictions<G>>>,
+    /// Signalled whenever the predicted view changes.
+    prediction_updated: Arc<tokio::sync::Notify>,
+    /// Whether players apply each other's actions without the host, chosen by the host and
+    /// read from the metadata.
+    lockstep: Arc<OnceLock<bool>>,
+    /// This peer's copy of a lock-step game, see [crate::RoomConfig::lockstep].
+    lockstep_game: Arc<tokio::sync::Mutex<Lockstep<G>>>,
+    /// Signalled whenever this peer's copy of a lock-step game changes.
+    lockstep_updated: Arc<tokio::sync::Notify>,
+    /// The host's latest ticked state, while it is only sent straight to the peers, see
+    /// [crate::RoomConfig::state_cadence].
+    ticked_state: Arc<Mutex<Option<G::GameState>>>,
+    /// Signalled when the app has rebound the node, for the event loop to sync the room again.
+    reconnected: Arc<tokio::sync::Notify>,
     phantom: PhantomData<G>,
     pub(crate) endpoint_id: EndpointId,
     pub(crate) author_id: AuthorId,
     // ticket: DocTicket,
-    iroh: Option<Iroh>,
+    iroh: Option<Arc<Iroh>>,
+    /// Game-critical keys: lifecycle, peers, actions and game state.
     pub(crate) doc: Doc,
+    /// High-volume chat traffic, kept apart so it never delays game-state sync.
+    ///
+    /// `None` when the room was created or joined with chat disabled.
+    pub(crate) chat_doc: Option<Doc>,
 }
 
+/// The host-authored game state entry.
+///
+/// A state written while applying an action records which action it was, so a restarted or
+/// newly elected host can tell whether an action still waiting in the doc already made it into
+/// the state before the previous host went down.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct GameStateEntry<S> {
+    pub(crate) state: S,
+    /// The requestor and sequence number of the action that produced this state, if any.
+    pub(crate) applied_action: Option<(EndpointId, u64)>,
+}
+
+/// What the host writes under `game_state`.
+///
+/// Large states are kept out of the doc: the entry only references a blob holding the encoded
+/// [GameStateEntry], which peers fetch from the host.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) enum StoredGameState {
+    /// The encoded [GameStateEntry] itself.
+    Inline(Vec<u8>),
+    /// A blob holding the encoded [GameStateEntry], compressed like the room's values.
+    /// `len` is the size of the encoded entry before compression.
+    Blob { hash: Hash, len: u64 },
+}
+
+/// What the host writes under `game_start`.
+///
+/// Starting a game sets the roles, the initial state, the start time and the app state all at
+/// once. Written as separate entries, peers could see them in any order, so they are written as
+/// one, and each of them is read from whichever the host wrote last: this or its own key.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct GameStart<R> {
+    pub(crate) state: StoredGameState,
+    pub(crate) roles: HashMap<EndpointId, R>,
+    /// When the game started, in milliseconds since the Unix epoch.
+    pub(crate) started_at: u64,
+}
+
+/// How many entry values [StateData::content_bytes] keeps, so entries that have not changed,
+/// e.g. the peer list during a burst of joins, are read from the blob store once.
+const CONTENT_CACHE_CAPACITY: NonZeroUsize = NonZeroUsize::new(1024).unwrap();
+
+/// An entry's key and content hash, which its cached value is looked up by.
+type ContentKey = (Vec<u8>, Hash);
+
+/// How many avatar images [StateData::fetch_avatar] keeps, least recently used first out.
+const AVATAR_CACHE_CAPACITY: NonZeroUsize = NonZeroUsize::new(64).unwrap();
+
+/// The largest avatar image, in bytes, a peer may share or is fetched from another peer.
+pub const MAX_AVATAR_BYTES: usize = 256 * 1024;
+
 /// Convert a string to an EndpointId, returning an error if the string is not a valid EndpointId.
 pub fn endpoint_id_from_str(id: &str) -> Result<EndpointId> {
     EndpointId::from_str(id).map_err(|err| anyhow!("Invalid EndpointId from key {}: {}", id, err))
@@ -61,21 +222,149 @@ pub fn endpoint_id_from_str(id: &str) -> Result<EndpointId> {
 const KEY_APP_STATE: &[u8] = b"app_state";
 /// Key for the current GameState, set by the host.
 const KEY_HOST_ID: &[u8] = b"host_id";
+/// Key for the host's offer to hand its authority to another peer, set by the host.
+const KEY_HOST_HANDOVER: &[u8] = b"host_handover";
 /// Key for the current GameState, set by the host.
 const KEY_GAME_STATE: &[u8] = b"game_state";
+/// Key for everything a game starts with, written in one go, set by the host.
+const KEY_GAME_START: &[u8] = b"game_start";
+/// Key for when the current game started, set by the host.
+const KEY_GAME_STARTED_AT: &[u8] = b"game_started_at";
+/// Key for the final GameResult, set by the host when the game finishes. Like `game_start`, it
+/// also moves the room on, to [AppState::Finished], when written after the app state.
+const KEY_GAME_RESULT: &[u8] = b"game_result";
+/// Key for the ticket of the room the host created for the next game, set by the host.
+const KEY_NEXT_ROOM: &[u8] = b"next_room";
+/// Key for why the host closed the room for good, set by the host.
+const KEY_ROOM_CLOSED: &[u8] = b"room_closed";
+/// Key for the latest inactivity warning, set by the host.
+const KEY_INACTIVITY_WARNING: &[u8] = b"inactivity_warning";
 /// Key for the room metadata, set by the host.
 const KEY_ROOM_METADATA: &[u8] = b"room_metadata";
+/// Key for the point before which history may be compacted, set by the host.
+const KEY_COMPACTION: &[u8] = b"compaction";
+/// Key for the latest rollback to a checkpoint, set by the host.
+const KEY_ROLLBACK: &[u8] = b"rollback";
+/// Key for the game state from before the last applied action, set by the host.
+const KEY_UNDO: &[u8] = b"undo";
+/// Key for the game's turn order, set by the host.
+const KEY_TURNS: &[u8] = b"turns";
+/// Key for the room's details shown in lobbies, set by the host.
+const KEY_ROOM_META: &[u8] = b"meta";
+/// Key for the roles the players were given when the game started, set by the host.
+const KEY_ROLES: &[u8] = b"roles";
 /// Prefix for a peer entry, which contains information about a peer in the room.
 const PREFIX_JOIN: &[u8] = b"join_request.";
+/// Prefix for the host's refusal of a join request.
+const PREFIX_JOIN_DENIED: &[u8] = b"join_denied.";
+/// Prefix for a request to seat another local player on an endpoint.
+const PREFIX_SEAT: &[u8] = b"seat_request.";
+/// Prefix for an observer's request to become a player.
+const PREFIX_PLAY_REQUEST: &[u8] = b"play_request.";
 /// Prefix for a peer quit announcement.
 const PREFIX_QUIT: &[u8] = b"quit_request.";
 /// Prefix for an action request entry.
 const PREFIX_ACTION: &[u8] = b"action.";
 /// Prefix for an action result entry, which contains the result of an action request.
 const PREFIX_ACTION_RESULT: &[u8] = b"action_result.";
+/// Prefix for a player's commitment to an action they will reveal later.
+const PREFIX_COMMITMENT: &[u8] = b"commitment.";
+/// Prefix for the number of the last commitment each player revealed, set by the host.
+const PREFIX_REVEALED: &[u8] = b"revealed.";
 /// Prefix for a processed action entry, which contains the result of an action request after it has been processed by the host.
 const PREFIX_PROCESSED_ACTION: &[u8] = b"processed_action.";
-/// Prefix for a chat message entry.
+/// Prefix for an entry in the log of applied actions, set by the host.
+const PREFIX_APPLIED_ACTION: &[u8] = b"applied_action.";
+/// Prefix for a player's disconnect during a game, set by the host.
+const PREFIX_DISCONNECT: &[u8] = b"disconnect.";
+/// Prefix for a chat message entry, stored in the chat document.
 const PREFIX_CHAT: &[u8] = b"chat.";
+/// Prefix for a chat reaction entry, stored in the chat document.
+const PREFIX_CHAT_REACTION: &[u8] = b"chat_reaction.";
 /// Prefix for a peer entry, which contains information about a peer in the room.
 const PREFIX_PEER: &[u8] = b"peer.";
+/// Prefix for a team change announcement, set by the host.
+const PREFIX_TEAM: &[u8] = b"team.";
+/// Prefix for an action annotation entry.
+const PREFIX_ANNOTATION: &[u8] = b"annotation.";
+/// Prefix for a peer's latest heartbeat.
+const PREFIX_HEARTBEAT: &[u8] = b"heartbeat.";
+/// Prefix for a saved checkpoint of the game state, set by the host.
+const PREFIX_CHECKPOINT: &[u8] = b"checkpoint.";
+/// Prefix for a proposal that needs the other players' approval.
+const PREFIX_PROPOSAL: &[u8] = b"proposal.";
+/// Prefix for a player's vote on a proposal.
+const PREFIX_PROPOSAL_VOTE: &[u8] = b"proposal_vote.";
+/// Prefix for how the host resolved a proposal.
+const PREFIX_PROPOSAL_RESULT: &[u8] = b"proposal_result.";
+/// Prefix for a vote called on a decision about the room.
+const PREFIX_VOTE: &[u8] = b"vote.";
+/// Prefix for a peer's ballot in a vote.
+const PREFIX_BALLOT: &[u8] = b"ballot.";
+/// Prefix for how the host tallied a vote.
+const PREFIX_VOTE_RESULT: &[u8] = b"vote_result.";
+/// Prefix for a player's step in a lock-step game.
+const PREFIX_LOCKSTEP: &[u8] = b"lockstep.";
+/// Prefix for a payload only one player may read, sealed to their endpoint, set by the host.
+const PREFIX_PRIVATE: &[u8] = b"private.";
+/// Prefix for an entry in the manifest of assets distributed with the room, set by the host.
+const PREFIX_ASSET: &[u8] = b"asset.";
+
+/// Every key and key prefix the engine writes to a room's documents.
+///
+/// Exact keys are reserved as prefixes too, so a custom key can never be mistaken for one
+/// of them by a prefix query.
+pub const ENGINE_KEY_PREFIXES: &[&[u8]] = &[
+    KEY_APP_STATE,
+    KEY_HOST_ID,
+    KEY_HOST_HANDOVER,
+    KEY_GAME_STATE,
+    KEY_GAME_START,
+    KEY_GAME_STARTED_AT,
+    KEY_GAME_RESULT,
+    KEY_NEXT_ROOM,
+    KEY_ROOM_CLOSED,
+    KEY_INACTIVITY_WARNING,
+    KEY_ROOM_METADATA,
+    KEY_ROOM_META,
+    KEY_COMPACTION,
+    KEY_ROLLBACK,
+    KEY_UNDO,
+    KEY_TURNS,
+    KEY_ROLES,
+    PREFIX_JOIN,
+    PREFIX_JOIN_DENIED,
+    PREFIX_SEAT,
+    PREFIX_PLAY_REQUEST,
+    PREFIX_QUIT,
+    PREFIX_ACTION,
+    PREFIX_ACTION_RESULT,
+    PREFIX_COMMITMENT,
+    PREFIX_REVEALED,
+    PREFIX_PROCESSED_ACTION,
+    PREFIX_APPLIED_ACTION,
+    PREFIX_DISCONNECT,
+    PREFIX_CHAT,
+    PREFIX_CHAT_REACTION,
+    PREFIX_PEER,
+    PREFIX_TEAM,
+    PREFIX_ANNOTATION,
+    PREFIX_HEARTBEAT,
+    PREFIX_CHECKPOINT,
+    PREFIX_PROPOSAL,
+    PREFIX_PROPOSAL_VOTE,
+    PREFIX_PROPOSAL_RESULT,
+    PREFIX_VOTE,
+    PREFIX_BALLOT,
+    PREFIX_VOTE_RESULT,
+    PREFIX_LOCKSTEP,
+    PREFIX_PRIVATE,
+    PREFIX_ASSET,
+];
+
+/// Check whether a document key belongs to the engine rather than to the game.
+pub fn is_engine_key(key: &[u8]) -> bool {
+    ENGINE_KEY_PREFIXES
+        .iter()
+        .any(|prefix| key.starts_with(prefix))
+}
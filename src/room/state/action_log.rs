@@ -0,0 +1,127 @@
+//! The room's log of applied actions.
+//!
+//! Every action the host applies is recorded under `applied_action.<seq>`, numbered across the
+//! whole room rather than per peer, so the log reads back in the order the actions changed the
+//! game. Entries are never rewritten: a newly elected host carries on numbering after the last
+//! entry it finds.
+
+use super::*;
+use iroh_docs::store::SortDirection;
+use n0_future::StreamExt as _;
+use std::{
+    collections::BTreeMap,
+    ops::RangeBounds,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// An action the host applied, as recorded in the room's action log.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AppliedAction<A> {
+    /// The action's position in the log, starting at 1.
+    pub seq: u64,
+    /// The player the action was applied for.
+    pub player: EndpointId,
+    /// The player's own sequence number for the action.
+    pub action_seq: u64,
+    /// The action itself.
+    pub action: A,
+    /// When the host applied it, in milliseconds since the Unix epoch.
+    pub applied_at: u64,
+    /// The round the action was applied in, if the host collected it into a round, see
+    /// [crate::RoomConfig::rounds].
+    pub round: Option<u64>,
+}
+
+impl<G: GameLogic> StateData<G> {
+    /// Append an action the host just applied to the log.
+    pub(crate) async fn log_applied_action(
+        &self,
+        player: &EndpointId,
+        action_seq: u64,
+        action: &G::GameAction,
+        round: Option<u64>,
+    ) -> Result<()> {
+        let seq = self.last_logged_action_seq().await? + 1;
+        let record = AppliedAction {
+            seq,
+            player: *player,
+            action_seq,
+            action,
+            applied_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64,
+            round,
+        };
+        self.set_bytes(&applied_action_key(seq), &self.encode(&record)?)
+            .await
+    }
+
+    /// The logged actions whose sequence numbers fall in `range`, in order.
+    ///
+    /// Where more than one author wrote the same entry, the current host's copy is used, or
+    /// failing that the first one written.
+    pub(crate) async fn get_action_log(
+        &self,
+        range: impl RangeBounds<u64>,
+    ) -> Result<Vec<AppliedAction<G::GameAction>>> {
+        let host_author = self.get_host_author_id().await?;
+        let query = self.doc.get_many(Query::key_prefix(PREFIX_APPLIED_ACTION));
+        let mut entries = Box::pin(query.await?);
+        let mut chosen: BTreeMap<u64, Entry> = BTreeMap::new();
+        while let Some(entry_result) = entries.next().await {
+            let entry = entry_result?;
+            let Some(seq) = applied_action_seq(entry.key()) else {
+                continue;
+            };
+            if !range.contains(&seq) {
+                continue;
+            }
+            let replace = chosen.get(&seq).is_none_or(|current| {
+                let by_host = |entry: &Entry| Some(entry.author()) == host_author;
+                match (by_host(current), by_host(&entry)) {
+                    (true, _) => false,
+                    (false, true) => true,
+                    (false, false) => entry.timestamp() < current.timestamp(),
+                }
+            });
+            if replace {
+                chosen.insert(seq, entry);
+            }
+        }
+        let mut log = Vec::with_capacity(chosen.len());
+        for entry in chosen.values() {
+            log.push(self.parse(entry).await?);
+        }
+        Ok(log)
+    }
+
+    /// The round of the last logged action, or 0 if it was not applied in a round.
+    pub(crate) async fn last_logged_round(&self) -> Result<u64> {
+        let seq = self.last_logged_action_seq().await?;
+        let log = self.get_action_log(seq..=seq).await?;
+        Ok(log.first().and_then(|applied| applied.round).unwrap_or(0))
+    }
+
+    /// The sequence number of the last logged action, or 0 if nothing has been logged.
+    async fn last_logged_action_seq(&self) -> Result<u64> {
+        // Keys are zero-padded, so the greatest key holds the latest entry.
+        let query = Query::single_latest_per_key()
+            .key_prefix(PREFIX_APPLIED_ACTION)
+            .sort_direction(SortDirection::Desc)
+            .limit(1);
+        let mut entries = Box::pin(self.doc.get_many(query).await?);
+        Ok(match entries.next().await {
+            Some(entry) => applied_action_seq(entry?.key()).unwrap_or(0),
+            None => 0,
+        })
+    }
+}
+
+/// Build the key an applied action is logged under.
+fn applied_action_key(seq: u64) -> Vec<u8> {
+    [PREFIX_APPLIED_ACTION, format!("{seq:020}").as_bytes()].concat()
+}
+
+/// Read the sequence number from an action log key.
+fn applied_action_seq(key: &[u8]) -> Option<u64> {
+    let seq = key.strip_prefix(PREFIX_APPLIED_ACTION)?;
+    std::str::from_utf8(seq).ok()?.parse().ok()
+}
@@ -3,8 +3,8 @@
 //! This module contains methods for performing these mutations, which typically involve writing new entries
 //! to the document with specific key formats that other peers can recognize and respond to.
 //! For example, submitting a game action involves writing an entry with a key that starts with "action."
-//! followed by the peer's endpoint ID and a unique action ID, and a value that contains the serialized action
-//! request. Other peers will recognize this key format as a game action request and can respond accordingly.
+//! followed by the peer's endpoint ID and the action's sequence number, and a value that contains the serialized
+//! action request. Other peers will recognize this key format as a game action request and can respond accordingly.
 //!
 //! Each method in this module corresponds to a specific type of state mutation, such as `submit_action` for
 //! submitting a game action, `send_chat` for sending a chat message, and `update_peer` for updating a peer's information.
@@ -15,35 +15,89 @@
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use super::*;
-use crate::{ChatMessage, GameLogic, PeerInfo, PeerMap, PeerProfile, PeerStatus};
+use crate::{
+    Annotation, ChatMessage, ChatReaction, GameLogic, GameOutcome, GameResult, PeerInfo, PeerMap,
+    PeerProfile, PeerStatus, RoomMeta, RoomTicket, TeamId, TurnManager, room::private, trace,
+};
 use anyhow::{Result, anyhow};
-use tokio::time::sleep;
+
+/// How long to wait for a leave announcement to sync before giving up.
+const LEAVE_SYNC_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// A request from a peer to perform an action, containing the action and a unique ID for this request.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct ActionRequest<A> {
     /// A unique ID for this action request, generated by the requestor.
     pub id: String,
+    /// The requestor's sequence number for this action, starting at 1.
+    ///
+    /// The host applies each peer's actions strictly in sequence order.
+    pub seq: u64,
+    /// The room's logical clock when the action was requested.
+    ///
+    /// Requests from different peers are ordered by this reading, then by requestor.
+    pub clock: u64,
     /// The action being requested.
     pub action: A,
+    /// What opens the commitment this action was made under, if it was committed to first,
+    /// see [crate::GameRoom::commit_action].
+    pub reveal: Option<Reveal>,
+}
+
+/// A request from an endpoint to seat another local player, e.g. a second player at the same screen.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SeatRequest {
+    /// The endpoint the seat shares, which submits actions on the seat's behalf.
+    pub owner: EndpointId,
+    /// The seat's player profile.
+    pub profile: PeerProfile,
+}
+
+/// Why the host rejected an action request.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum ActionError<E> {
+    /// The game's rules rejected the action.
+    Game(E),
+    /// The engine refused the action before the game saw it, e.g. it came from an observer.
+    Engine(String),
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for ActionError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ActionError::Game(error) => write!(f, "{error}"),
+            ActionError::Engine(reason) => write!(f, "{reason}"),
+        }
+    }
 }
 
-/// The result of an action request, containing the ID of the original request,
-/// whether it was accepted, and an optional error message if it was rejected.
+/// The host's response to an action request, written under the request's sequence number.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
-pub struct ActionResult {
+pub struct ActionResponse<E> {
     /// The ID of the original action request.
     pub action_id: String,
-    /// Whether the action request was accepted by the host.
-    pub accepted: bool,
-    /// An optional error message if the action request was rejected.
-    pub error: Option<String>,
+    /// The requestor's sequence number for the original action request.
+    pub seq: u64,
+    /// Why the action was rejected, or `None` if it was applied.
+    pub error: Option<ActionError<E>>,
+}
+
+impl<E> ActionResponse<E> {
+    /// Build a rejection raised by the engine rather than the game's rules.
+    pub(crate) fn engine_rejection(action_id: String, seq: u64, reason: impl Into<String>) -> Self {
+        Self {
+            action_id,
+            seq,
+            error: Some(ActionError::Engine(reason.into())),
+        }
+    }
 }
 
 impl<G: GameLogic> StateData<G> {
     /// Set the AppState.
-    pub async fn set_app_state(&self, state: &AppState) -> Result<()> {
-        let state = postcard::to_stdvec(&state)?;
+    pub async fn set_app_state(&self, state: &AppState<G>) -> Result<()> {
+        self.record_activity();
+        let state = self.encode(&state)?;
         self.set_bytes(KEY_APP_STATE, &state).await
     }
 
@@ -53,10 +107,145 @@ impl<G: GameLogic> StateData<G> {
         self.set_bytes(KEY_ROOM_METADATA, &metadata).await
     }
 
+    /// Record this build's protocol in a room written with an older one, as its host reopens
+    /// it, so peers on this build can join.
+    pub(crate) async fn upgrade_protocol(&self) -> Result<()> {
+        let metadata = self.get_room_metadata().await?;
+        if metadata.protocol_version < PROTOCOL_VERSION {
+            self.set_room_metadata(&RoomMetadata {
+                protocol_version: PROTOCOL_VERSION,
+                ..metadata
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
     /// Set Game State.
     pub async fn set_game_state(&self, state: &G::GameState) -> Result<()> {
-        let state = postcard::to_stdvec(state)?;
-        self.set_bytes(KEY_GAME_STATE, &state).await
+        self.put_game_state(state, None).await
+    }
+
+    /// Set the Game State produced by applying a peer's action, recording the action with it.
+    pub(crate) async fn set_action_game_state(
+        &self,
+        state: &G::GameState,
+        peer_id: &EndpointId,
+        seq: u64,
+    ) -> Result<()> {
+        self.put_game_state(state, Some((*peer_id, seq))).await
+    }
+
+    pub(super) async fn put_game_state(
+        &self,
+        state: &G::GameState,
+        applied_action: Option<(EndpointId, u64)>,
+    ) -> Result<()> {
+        let stored = self.store_game_state(state, applied_action).await?;
+        let value = self.encode(&stored)?;
+        self.set_bytes(KEY_GAME_STATE, &value).await?;
+        self.clear_ticked_state();
+        Ok(())
+    }
+
+    /// Encode a game state for writing, moving it into a blob if it is too large for the doc.
+    async fn store_game_state(
+        &self,
+        state: &G::GameState,
+        applied_action: Option<(EndpointId, u64)>,
+    ) -> Result<StoredGameState> {
+        let entry = self.encode(&self.versioned(state, applied_action)?)?;
+        Ok(match entry.len() > self.state_blob_threshold {
+            true => {
+                let len = entry.len() as u64;
+                let blob = self.pack(KEY_GAME_STATE, &entry)?;
+                let hash = self
+                    .iroh()?
+                    .set_named_blob(&self.game_state_tag(), blob)
+                    .await?;
+                StoredGameState::Blob { hash, len }
+            }
+            false => StoredGameState::Inline(entry),
+        })
+    }
+
+    /// Start a game with the given roles and initial state, moving the room to
+    /// [AppState::InGame], in a single entry so peers see it all change at once.
+    pub(crate) async fn set_game_start(
+        &self,
+        roles: HashMap<EndpointId, G::PlayerRole>,
+        initial_state: &G::GameState,
+    ) -> Result<()> {
+        self.record_activity();
+        let start = GameStart {
+            state: self.store_game_state(initial_state, None).await?,
+            roles,
+            started_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64,
+        };
+        let value = self.encode(&start)?;
+        self.set_bytes(KEY_GAME_START, &value).await?;
+        self.clear_ticked_state();
+        Ok(())
+    }
+
+    /// Record the game's result, which moves the room to [AppState::Finished] with it.
+    pub(crate) async fn finish_game(&self, outcome: GameOutcome) -> Result<()> {
+        let duration = match self.get_game_started_at().await? {
+            Some(started_at) => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+                Duration::from_millis(now.saturating_sub(started_at))
+            }
+            None => Duration::ZERO,
+        };
+        let stats = self.get_match_stats().await?;
+        let result = self.encode(&GameResult::new(outcome, duration, stats))?;
+        self.record_activity();
+        self.set_bytes(KEY_GAME_RESULT, &result).await
+    }
+
+    /// Seal a payload to the endpoint a player is on and write it for them.
+    pub(crate) async fn set_private_state(&self, to: &EndpointId, payload: &[u8]) -> Result<()> {
+        let Some(peer) = self.get_peer_info(to).await? else {
+            return Err(anyhow!("Peer {to} is not in the room"));
+        };
+        let sealed = private::seal(&peer.seat_of.unwrap_or(*to), payload)?;
+        let key = format!("{}{}", std::str::from_utf8(PREFIX_PRIVATE)?, to);
+        self.set_bytes(key.as_bytes(), &sealed).await
+    }
+
+    /// Invite every peer in this room to the room the host created for the next game.
+    pub(crate) async fn set_next_room(&self, ticket: &RoomTicket) -> Result<()> {
+        self.set_bytes(KEY_NEXT_ROOM, ticket.to_string().as_bytes())
+            .await
+    }
+
+    /// Close the room for good, telling every peer why, and finish whatever it was doing.
+    pub(crate) async fn close_room(&self, reason: &str) -> Result<()> {
+        self.set_bytes(KEY_ROOM_CLOSED, reason.as_bytes()).await?;
+        self.set_app_state(&AppState::Finished).await
+    }
+
+    /// Publish the room's details for lobby screens.
+    pub(crate) async fn set_meta(&self, meta: &RoomMeta) -> Result<()> {
+        self.set_bytes(KEY_ROOM_META, &self.encode(meta)?).await
+    }
+
+    /// Warn every peer that the game will be finished for inactivity.
+    pub(crate) async fn set_inactivity_warning(&self, finishes_in: Duration) -> Result<()> {
+        let value = self.encode(&finishes_in)?;
+        self.set_bytes(KEY_INACTIVITY_WARNING, &value).await
+    }
+
+    /// Publish the game's turn order.
+    pub(crate) async fn set_turns(&self, turns: Option<&TurnManager>) -> Result<()> {
+        let value = self.encode(&turns)?;
+        self.set_bytes(KEY_TURNS, &value).await
+    }
+
+    /// Tell every peer which history it may now remove.
+    pub(crate) async fn set_compaction_mark(&self, mark: &CompactionMark) -> Result<()> {
+        let value = self.encode(mark)?;
+        self.set_bytes(KEY_COMPACTION, &value).await
     }
 
     /// Elect a new host when no known online host currently has authority.
@@ -86,23 +275,102 @@ impl<G: GameLogic> StateData<G> {
 
     /// Declare that a peer now has hosting authority.
     pub(crate) async fn set_host(&self, peer_id: &EndpointId) -> Result<()> {
+        trace::debug!(host = %peer_id, "Writing host");
         self.set_bytes(KEY_HOST_ID, peer_id.to_string().as_bytes())
             .await
     }
 
-    /// Send a chat message.
-    pub async fn send_chat(&self, message: &str) -> Result<()> {
-        let message = ChatMessage::new(self.endpoint_id, message)?;
+    /// Send a chat message, unless the game's chat filter blocks it.
+    pub async fn send_chat(&self, logic: &G, message: &str) -> Result<()> {
+        let mut message = ChatMessage::new(self.endpoint_id, message)?;
+        message.clock = self.tick_clock();
+        let Some(message) = self.filter_chat(logic, message).await? else {
+            return Err(anyhow!("Chat is not allowed right now"));
+        };
+        self.put_chat(&message).await
+    }
+
+    /// Replace the text of one of our own chat messages.
+    pub async fn edit_chat(&self, logic: &G, message_id: &str, text: &str) -> Result<()> {
+        let mut message = self.get_own_chat(message_id).await?;
+        message.message = text.to_string();
+        message.edited = true;
+        let Some(message) = self.filter_chat(logic, message).await? else {
+            return Err(anyhow!("Chat is not allowed right now"));
+        };
+        self.put_chat(&message).await
+    }
+
+    /// Delete one of our own chat messages.
+    pub async fn delete_chat(&self, message_id: &str) -> Result<()> {
+        let mut message = self.get_own_chat(message_id).await?;
+        message.message.clear();
+        message.deleted = true;
+        self.put_chat(&message).await
+    }
+
+    /// React to a chat message.
+    pub async fn react_to_chat(&self, message_id: &str, emoji: &str) -> Result<()> {
+        if !self.has_chat_message(message_id).await? {
+            return Err(anyhow!("Chat message {message_id} not found"));
+        }
+        let reaction = ChatReaction::new(self.endpoint_id, message_id, emoji)?;
+        self.put_reaction(&reaction).await
+    }
+
+    /// Write a chat reaction under its reacting peer's key.
+    pub(crate) async fn put_reaction(&self, reaction: &ChatReaction) -> Result<()> {
+        // e.g., "chat_reaction.<message_id>.<id>.<emoji>"
+        let key = format!(
+            "{}{}.{}.{}",
+            str::from_utf8(PREFIX_CHAT_REACTION)?,
+            reaction.message_id,
+            reaction.from,
+            reaction.emoji
+        );
+        let value = self.encode(reaction)?;
+        self.set_chat_bytes(key.as_bytes(), &value).await
+    }
+
+    /// Write a chat message under its stable key.
+    pub(crate) async fn put_chat(&self, message: &ChatMessage) -> Result<()> {
         // Key ensures uniqueness for last-write-wins conflict resolution
-        // e.g., "chat.123456789.id"
+        // e.g., "chat.<message_id>.<id>"
         let chat_key = format!(
             "{}{}.{}",
             std::str::from_utf8(PREFIX_CHAT)?,
-            message.timestamp,
-            self.endpoint_id
+            message.id,
+            message.from
         );
-        let value = postcard::to_stdvec(&message)?;
-        self.set_bytes(&chat_key.into_bytes(), &value).await
+        let value = self.encode(message)?;
+        self.set_chat_bytes(&chat_key.into_bytes(), &value).await
+    }
+
+    /// Find one of our own chat messages that can still be changed.
+    async fn get_own_chat(&self, message_id: &str) -> Result<ChatMessage> {
+        match self.get_chat_message(message_id).await? {
+            Some(message) if !message.is_from(&self.endpoint_id) => {
+                Err(anyhow!("Only the sender can change a chat message"))
+            }
+            Some(message) if message.deleted => Err(anyhow!("Chat message has been deleted")),
+            Some(message) => Ok(message),
+            None => Err(anyhow!("Chat message {message_id} not found")),
+        }
+    }
+
+    /// Attach an annotation to an action.
+    pub async fn annotate_action(&self, action_id: &str, text: &str) -> Result<()> {
+        let annotation = Annotation::new(self.endpoint_id, action_id, text)?;
+        // e.g., "annotation.<action_id>.<id>.<timestamp>"
+        let key = format!(
+            "{}{}.{}.{}",
+            str::from_utf8(PREFIX_ANNOTATION)?,
+            annotation.action_id,
+            self.endpoint_id,
+            annotation.timestamp
+        );
+        let value = self.encode(&annotation)?;
+        self.set_bytes(key.as_bytes(), &value).await
     }
 
     /// Add a peer to the peers list
@@ -113,17 +381,58 @@ impl<G: GameLogic> StateData<G> {
         profile: PeerProfile,
     ) -> Result<()> {
         let peer_info = match self.get_peer_info(peer_id).await? {
-            Some(existing) => existing.reintroduced(author_id, profile),
-            None => PeerInfo::new(*peer_id, author_id, profile),
+            // Anyone can write a join request in another peer's name, so only the author the
+            // peer first joined with may reintroduce them.
+            Some(existing) if existing.author_id != author_id => return Ok(()),
+            Some(existing) => existing.reintroduced(profile),
+            None => PeerInfo {
+                is_observer: self.joins_as_observer().await,
+                ..PeerInfo::new(*peer_id, author_id, profile)
+            },
         };
         self.update_peer(peer_id, peer_info).await
     }
 
+    /// Check whether adding a peer now would take a player's place the room doesn't have.
+    ///
+    /// Peers already in the room may always rejoin, and late arrivals who only watch are
+    /// not limited.
+    pub(crate) async fn is_full_for(&self, peer_id: &EndpointId) -> Result<bool> {
+        let Some(max_players) = self.get_meta().await?.and_then(|meta| meta.max_players) else {
+            return Ok(false);
+        };
+        if self.get_peer_info(peer_id).await?.is_some() || self.joins_as_observer().await {
+            return Ok(false);
+        }
+        let peers = self.get_peer_list().await?;
+        let players = peers.values().filter(|peer| !peer.is_observer).count();
+        Ok(players >= max_players)
+    }
+
+    /// Tell a peer why they were not added to the room.
+    pub(crate) async fn deny_join(
+        &self,
+        peer_id: &EndpointId,
+        reason: JoinDeniedReason,
+    ) -> Result<()> {
+        let key = format!("{}{}", str::from_utf8(PREFIX_JOIN_DENIED)?, peer_id);
+        self.set_bytes(key.as_bytes(), &self.encode(&reason)?).await
+    }
+
+    /// Peers who join in the lobby get a place as a player, while later arrivals watch.
+    async fn joins_as_observer(&self) -> bool {
+        self.get_app_state()
+            .await
+            .is_ok_and(|state| state != AppState::Lobby)
+    }
+
     /// Update a peer's info, or add them if they don't exist.
     pub async fn update_peer(&self, peer_id: &EndpointId, peer_info: PeerInfo) -> Result<()> {
         let key = format!("{}{}", std::str::from_utf8(PREFIX_PEER)?, peer_id);
-        let value = postcard::to_stdvec(&peer_info)?;
-        self.set_bytes(key.as_bytes(), &value).await
+        let value = self.encode(&peer_info)?;
+        self.set_bytes(key.as_bytes(), &value).await?;
+        self.refresh_peer(key.as_bytes()).await;
+        Ok(())
     }
 
     /// Set a peer's online/offline status, if they are in our peer list
@@ -158,68 +467,223 @@ impl<G: GameLogic> StateData<G> {
         Ok(())
     }
 
-    /// Announce that we have left the room, and why.
-    pub async fn announce_leave(&self, reason: &LeaveReason<G>) -> Result<()> {
+    /// Place a peer on a team, or take them off their team, and announce the change.
+    pub(crate) async fn set_peer_team(
+        &self,
+        peer_id: &EndpointId,
+        team: Option<TeamId>,
+    ) -> Result<()> {
+        let Some(mut peer_info) = self.get_peer_info(peer_id).await? else {
+            return Err(anyhow!("Cannot set the team of a peer who has not joined"));
+        };
+        peer_info.team = team;
+        self.update_peer(peer_id, peer_info).await?;
+        let key = format!("{}{}", str::from_utf8(PREFIX_TEAM)?, peer_id);
+        let value = self.encode(&team)?;
+        self.set_bytes(key.as_bytes(), &value).await
+    }
+
+    /// Announce that this peer is still in the room.
+    pub(crate) async fn send_heartbeat(&self) -> Result<()> {
+        let key = format!("{}{}", str::from_utf8(PREFIX_HEARTBEAT)?, self.endpoint_id);
+        let sent_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+        let value = self.encode(&sent_at)?;
+        self.set_bytes(key.as_bytes(), &value).await
+    }
+
+    /// Announce that we have left the room, and why, returning whether another peer synced
+    /// the announcement before `LEAVE_SYNC_TIMEOUT`.
+    pub async fn announce_leave(&self, reason: &LeaveReason<G>) -> Result<bool> {
         let quit_key = format!("{}{}", str::from_utf8(PREFIX_QUIT)?, self.endpoint_id);
-        let value = postcard::to_stdvec(reason)?;
+        let value = self.encode(reason)?;
         self.set_bytes(&quit_key.into_bytes(), &value).await?;
-        // allow a short delay for this message to sync
-        sleep(Duration::from_secs(1)).await;
-        Ok(())
+        // Give the announcement a chance to reach the other peers before we go.
+        let synced = self.wait_for_sync(LEAVE_SYNC_TIMEOUT).await?;
+        if !synced {
+            trace::warning!("Leave announcement was not synced with any peer in time");
+        }
+        Ok(synced)
+    }
+
+    /// Ask the host to let this peer play rather than observe.
+    pub(crate) async fn request_to_play(&self) -> Result<()> {
+        let key = format!(
+            "{}{}",
+            str::from_utf8(PREFIX_PLAY_REQUEST)?,
+            self.endpoint_id
+        );
+        let requested_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+        let value = self.encode(&requested_at)?;
+        self.set_bytes(key.as_bytes(), &value).await
     }
 
     /// Announce that this peer has forfeited active play.
     pub async fn announce_forfeit(&self) -> Result<()> {
         let reason = LeaveReason::<G>::Forfeit;
-        self.announce_leave(&reason).await
+        self.announce_leave(&reason).await.map(|_| ())
     }
 
     /// Announce that we have joined the room.
     pub async fn announce_presence(&self, introduction: impl Into<PeerProfile>) -> Result<()> {
-        let join_key = format!("{}{}", str::from_utf8(PREFIX_JOIN)?, self.endpoint_id);
-        let value = postcard::to_stdvec(&introduction.into())?;
+        self.put_join_request(&self.endpoint_id, introduction.into())
+            .await
+    }
+
+    /// Write a join request for `peer_id`, whoever that is.
+    pub(crate) async fn put_join_request(
+        &self,
+        peer_id: &EndpointId,
+        profile: PeerProfile,
+    ) -> Result<()> {
+        let join_key = format!("{}{}", str::from_utf8(PREFIX_JOIN)?, peer_id);
+        let value = self.encode(&profile)?;
         self.set_bytes(&join_key.into_bytes(), &value).await
     }
 
-    /// Submit a game action.
-    pub async fn submit_action(&self, action: G::GameAction) -> Result<()> {
-        let action_id = unique_id()?;
-        let action_key = format!(
-            "{}{}.{}",
-            str::from_utf8(PREFIX_ACTION)?,
-            self.endpoint_id,
-            action_id
-        );
-        let value = postcard::to_stdvec(&ActionRequest {
-            id: action_id,
+    /// Ask the host to seat another local player on this endpoint, returning the seat's ID.
+    ///
+    /// Seat IDs are derived from this endpoint's secret key and the seat's index, starting at 1,
+    /// so the same endpoint gets the same seats back when it rejoins.
+    pub async fn announce_seat(
+        &self,
+        index: u32,
+        introduction: impl Into<PeerProfile>,
+    ) -> Result<EndpointId> {
+        let seat_id = self.seat_id(index)?;
+        let seat_key = format!("{}{}", str::from_utf8(PREFIX_SEAT)?, seat_id);
+        let value = self.encode(&SeatRequest {
+            owner: self.endpoint_id,
+            profile: introduction.into(),
+        })?;
+        self.set_bytes(&seat_key.into_bytes(), &value).await?;
+        Ok(seat_id)
+    }
+
+    /// Derive the ID of one of this endpoint's local seats.
+    pub(crate) fn seat_id(&self, index: u32) -> Result<EndpointId> {
+        let mut seed = self.iroh()?.endpoint().secret_key().to_bytes().to_vec();
+        seed.extend_from_slice(b"seat");
+        seed.extend_from_slice(&index.to_le_bytes());
+        let secret = iroh::SecretKey::from_bytes(Hash::new(seed).as_bytes());
+        Ok(secret.public())
+    }
+
+    /// Add a local seat to the peers list, unless its ID already belongs to someone else.
+    pub(crate) async fn insert_seat(
+        &self,
+        seat_id: &EndpointId,
+        author_id: AuthorId,
+        request: SeatRequest,
+    ) -> Result<()> {
+        let seat_info = match self.get_peer_info(seat_id).await? {
+            // Never let a seat request take over another player's entry.
+            Some(existing) if existing.seat_of != Some(request.owner) => return Ok(()),
+            Some(existing) if existing.profile == request.profile => return Ok(()),
+            Some(existing) => existing.reintroduced(request.profile),
+            None => PeerInfo {
+                is_observer: self.joins_as_observer().await,
+                ..PeerInfo::seat(*seat_id, request.owner, author_id, request.profile)
+            },
+        };
+        self.update_peer(seat_id, seat_info).await
+    }
+
+    /// Share an avatar image through the blob store and re-announce our profile with its hash.
+    pub async fn set_avatar(&self, image: impl Into<Bytes>) -> Result<()> {
+        let image: Bytes = image.into();
+        if image.len() > MAX_AVATAR_BYTES {
+            return Err(anyhow!(
+                "Avatar of {} bytes is larger than the {MAX_AVATAR_BYTES} allowed",
+                image.len()
+            ));
+        }
+        self.share_avatar(image).await
+    }
+
+    /// Share an avatar image of any size, see [StateData::set_avatar].
+    pub(crate) async fn share_avatar(&self, image: Bytes) -> Result<()> {
+        let Some(peer_info) = self.get_peer_info(&self.endpoint_id).await? else {
+            return Err(anyhow!("Cannot set an avatar before peer has joined"));
+        };
+        let hash = self
+            .iroh()?
+            .set_named_blob(&self.room_tag("avatar"), image.clone())
+            .await?;
+        if let Ok(mut cache) = self.avatar_cache.lock() {
+            cache.put(hash, image);
+        }
+        let mut profile = peer_info.profile;
+        profile.avatar_blob = Some(hash);
+        self.announce_presence(profile).await
+    }
+
+    /// Submit a game action for one of this endpoint's players, returning its sequence number.
+    pub async fn submit_action(
+        &self,
+        player_id: &EndpointId,
+        action: G::GameAction,
+    ) -> Result<u64> {
+        self.put_action_request(player_id, action, None).await
+    }
+
+    /// Write an action request for one of this endpoint's players under their next sequence
+    /// number.
+    pub(crate) async fn put_action_request(
+        &self,
+        player_id: &EndpointId,
+        action: G::GameAction,
+        reveal: Option<Reveal>,
+    ) -> Result<u64> {
+        let mut next_seqs = self.next_action_seq.lock().await;
+        let seq = match next_seqs.get(player_id) {
+            Some(seq) => *seq,
+            // Carry on from any actions this player submitted in an earlier session.
+            None => self.last_submitted_action_seq(player_id).await? + 1,
+        };
+        // e.g., "action.<id>.<seq>"
+        let action_key = format!("{}{}.{}", str::from_utf8(PREFIX_ACTION)?, player_id, seq);
+        let value = self.encode(&ActionRequest {
+            id: unique_id()?,
+            seq,
+            clock: self.tick_clock(),
             action,
+            reveal,
         })?;
-        self.set_bytes(&action_key.into_bytes(), &value).await
+        self.set_bytes(&action_key.into_bytes(), &value).await?;
+        next_seqs.insert(*player_id, seq + 1);
+        Ok(seq)
     }
 
-    /// Publish the host's accept/reject result for an action request.
-    pub(crate) async fn set_action_result(
+    /// Write a game-defined entry to the room document.
+    pub async fn set_custom(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        if is_engine_key(key) {
+            return Err(anyhow!(
+                "Key '{}' is reserved by the engine",
+                String::from_utf8_lossy(key)
+            ));
+        }
+        self.set_bytes(key, value).await
+    }
+
+    /// Publish the host's accept/reject response for an action request.
+    pub(crate) async fn set_action_response(
         &self,
         peer_id: &EndpointId,
-        result: &ActionResult,
+        response: &ActionResponse<G::GameError>,
     ) -> Result<()> {
         let key = format!(
             "{}{}.{}",
             str::from_utf8(PREFIX_ACTION_RESULT)?,
             peer_id,
-            result.action_id
+            response.seq
         );
-        let value = postcard::to_stdvec(result)?;
+        let value = self.encode(response)?;
         self.set_bytes(key.as_bytes(), &value).await
     }
 
     /// Mark an action request as already handled by the host.
-    pub(crate) async fn mark_action_processed(
-        &self,
-        peer_id: &EndpointId,
-        action_id: &str,
-    ) -> Result<()> {
-        let key = processed_action_key(peer_id, action_id)?;
+    pub(crate) async fn mark_action_processed(&self, peer_id: &EndpointId, seq: u64) -> Result<()> {
+        let key = processed_action_key(peer_id, seq)?;
         self.set_bytes(&key, &[1]).await
     }
 
@@ -234,27 +698,55 @@ impl<G: GameLogic> StateData<G> {
 
 impl<G: GameLogic> StateData<G> {
     /// Set the state data for a particular key.
-    async fn set_bytes(&self, key: &[u8], value: &[u8]) -> Result<()> {
+    pub(super) async fn set_bytes(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        let value = match key == KEY_ROOM_METADATA {
+            true => value.to_vec(),
+            false => self.pack(key, value)?,
+        };
         self.doc
-            .set_bytes(self.author_id, key.to_vec(), value.to_vec())
+            .set_bytes(self.author_id, key.to_vec(), value)
+            .await?;
+        Ok(())
+    }
+
+    /// Write the latest value under `from` to `to` as it is stored, sealed if the room has a
+    /// secret, as a peer without the secret could.
+    #[cfg(feature = "testing")]
+    pub(crate) async fn copy_stored_bytes(&self, from: &[u8], to: &[u8]) -> Result<()> {
+        let entry = self
+            .doc
+            .get_one(Query::single_latest_per_key().key_exact(from))
+            .await?
+            .ok_or_else(|| anyhow!("Nothing is stored under the key"))?;
+        let stored = self.iroh()?.get_content_bytes(&entry).await?;
+        self.doc
+            .set_bytes(self.author_id, to.to_vec(), stored)
+            .await?;
+        Ok(())
+    }
+
+    /// Set the chat data for a particular key.
+    async fn set_chat_bytes(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.chat_doc()?
+            .set_bytes(self.author_id, key.to_vec(), self.pack(key, value)?)
             .await?;
         Ok(())
     }
 }
 
 /// Build the document key used to record a processed action.
-pub(crate) fn processed_action_key(peer_id: &EndpointId, action_id: &str) -> Result<Vec<u8>> {
+pub(crate) fn processed_action_key(peer_id: &EndpointId, seq: u64) -> Result<Vec<u8>> {
     Ok(format!(
         "{}{}.{}",
         str::from_utf8(PREFIX_PROCESSED_ACTION)?,
         peer_id,
-        action_id
+        seq
     )
     .into_bytes())
 }
 
 /// Generate a locally unique action identifier.
-fn unique_id() -> Result<String> {
+pub(super) fn unique_id() -> Result<String> {
     let nanos = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
     Ok(format!("{nanos}"))
 }
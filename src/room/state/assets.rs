@@ -0,0 +1,117 @@
+//! Game assets the host distributes with the room.
+//!
+//! An asset is a named blob, e.g. a custom map or a mod, so players get it from the host rather
+//! than needing the same files installed. The blob is packed like the room's values, so it is
+//! compressed and, in a room with a secret, encrypted. The host writes an `Asset` entry for it
+//! to the game document, and the entries together make up the room's manifest. Peers download
+//! the blob itself when they first ask for it, or as soon as it is announced with
+//! [crate::RoomConfig::fetch_assets].
+
+use super::*;
+use n0_future::StreamExt as _;
+use std::collections::BTreeMap;
+
+/// Longest asset name accepted, in bytes.
+const MAX_ASSET_NAME_LEN: usize = 128;
+
+/// An entry in the room's asset manifest, see [crate::GameRoom::publish_asset].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Asset {
+    /// The name the host published the asset under, e.g. "map_01".
+    pub name: String,
+    /// The blob holding the packed asset.
+    pub hash: Hash,
+    /// The size of the asset in bytes, before it was packed.
+    pub size: u64,
+}
+
+impl<G: GameLogic> StateData<G> {
+    /// Store an asset as a blob and add it to the manifest, replacing any asset of that name.
+    pub(crate) async fn set_asset(&self, name: &str, bytes: &[u8]) -> Result<Asset> {
+        let key = asset_key(name)?;
+        let blob = self.pack(&key, bytes)?;
+        let hash = self
+            .iroh()?
+            .set_named_blob(&self.asset_tag(name), blob)
+            .await?;
+        let asset = Asset {
+            name: name.to_string(),
+            hash,
+            size: bytes.len() as u64,
+        };
+        self.set_bytes(&key, &self.encode(&asset)?).await?;
+        Ok(asset)
+    }
+
+    /// Get the manifest entry the host wrote for an asset, if there is one.
+    pub async fn get_asset(&self, name: &str) -> Result<Option<Asset>> {
+        match self.get_host_authored_bytes(&asset_key(name)?).await? {
+            Some(bytes) => Ok(Some(self.decode(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the room's asset manifest, ordered by name.
+    pub async fn get_assets(&self) -> Result<Vec<Asset>> {
+        let host_author = self.get_host_author_id().await?;
+        let query = Query::single_latest_per_key().key_prefix(PREFIX_ASSET);
+        let mut entries = Box::pin(self.doc.get_many(query).await?);
+        let mut assets = BTreeMap::new();
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            if host_author.is_some_and(|host| host != entry.author()) {
+                continue;
+            }
+            let asset: Asset = self.parse(&entry).await?;
+            assets.insert(asset.name.clone(), asset);
+        }
+        Ok(assets.into_values().collect())
+    }
+
+    /// Get an asset's bytes, downloading it from the host, or failing that from another
+    /// peer, if it is not stored locally yet.
+    pub async fn fetch_asset(&self, asset: &Asset) -> Result<Bytes> {
+        let host_id = self.get_host_id().await?;
+        let mut providers = vec![host_id];
+        for (id, peer) in self.get_peer_list().await?.iter() {
+            if !peer.is_seat() && *id != self.endpoint_id && *id != host_id {
+                providers.push(*id);
+            }
+        }
+        let iroh = self.iroh()?;
+        iroh.download_blob(asset.hash, providers).await?;
+        let blob = iroh
+            .fetch_named_blob(&self.asset_tag(&asset.name), asset.hash, host_id)
+            .await?;
+        let bytes = self.unpack(&asset_key(&asset.name)?, blob)?;
+        if bytes.len() as u64 != asset.size {
+            return Err(anyhow!(
+                "Asset '{}' is {} bytes, expected {}",
+                asset.name,
+                bytes.len(),
+                asset.size
+            ));
+        }
+        Ok(bytes)
+    }
+
+    /// Whether to download assets as soon as they are announced.
+    pub(crate) fn fetches_assets(&self) -> bool {
+        self.fetch_assets
+    }
+
+    /// The blob tag that keeps this room's copy of an asset stored.
+    fn asset_tag(&self, name: &str) -> String {
+        self.room_tag(&format!("asset.{name}"))
+    }
+}
+
+/// Build the document key an asset's manifest entry is written under.
+fn asset_key(name: &str) -> Result<Vec<u8>> {
+    if name.is_empty() || name.len() > MAX_ASSET_NAME_LEN {
+        return Err(anyhow!(
+            "Asset names must be between 1 and {MAX_ASSET_NAME_LEN} bytes long"
+        ));
+    }
+    Ok([PREFIX_ASSET, name.as_bytes()].concat())
+}
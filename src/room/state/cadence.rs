@@ -0,0 +1,67 @@
+//! Ticked states held back from the document.
+//!
+//! With [crate::RoomConfig::state_cadence], the host only writes some of the states its tick
+//! produces. The latest of the others is held here, and sent straight to each peer as it falls
+//! due, until a newer state is written.
+
+use super::*;
+use crate::networking::StateFrame;
+use std::sync::MutexGuard;
+use tokio::sync::mpsc;
+
+impl<G: GameLogic> StateData<G> {
+    fn ticked(&self) -> MutexGuard<'_, Option<G::GameState>> {
+        // The state is only ever replaced whole, so a poisoned lock is still usable.
+        self.ticked_state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// The latest ticked state the host has not written yet, if any.
+    pub(crate) fn ticked_state(&self) -> Option<G::GameState> {
+        self.ticked().clone()
+    }
+
+    /// Hold a ticked state in place of the one in the document, until a newer one is written.
+    pub(crate) fn hold_ticked_state(&self, state: G::GameState) {
+        *self.ticked() = Some(state);
+    }
+
+    /// Forget the held ticked state, once it is outdated.
+    pub(crate) fn clear_ticked_state(&self) {
+        self.ticked().take();
+    }
+
+    /// Send a frame written with [StateData::encode_state_frame] to each of `peers` alone.
+    ///
+    /// Frames are lossy, and a slow peer must not hold up the others, so each is sent in the
+    /// background and one that fails is simply missed.
+    pub(crate) fn send_state_frames(&self, peers: Vec<EndpointId>, frame: Bytes) -> Result<()> {
+        let node = self.node()?;
+        let room = self.doc.id();
+        for peer in peers {
+            let node = node.clone();
+            let frame = frame.clone();
+            tokio::spawn(async move { node.send_state_frame(peer, room, frame).await });
+        }
+        Ok(())
+    }
+
+    /// Receive the frames the host sends this peer between writes.
+    pub(crate) fn accept_state_frames(&self) -> Result<mpsc::Receiver<StateFrame>> {
+        Ok(self.iroh()?.accept_state_frames(self.doc.id()))
+    }
+
+    /// Encode a game state for a frame, packed like the room's game state entry.
+    pub(crate) fn encode_state_frame(&self, state: &G::GameState) -> Result<Bytes> {
+        let entry = self.encode(&self.versioned(state, None)?)?;
+        Ok(self.pack(KEY_GAME_STATE, &entry)?.into())
+    }
+
+    /// Decode a frame written with [StateData::encode_state_frame].
+    pub(crate) fn decode_state_frame(&self, frame: Bytes) -> Result<G::GameState> {
+        Ok(self
+            .decode_state_entry(&self.unpack(KEY_GAME_STATE, frame)?)?
+            .state)
+    }
+}
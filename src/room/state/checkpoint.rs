@@ -0,0 +1,61 @@
+//! Checkpoints of the game state that the host can roll back to.
+//!
+//! A checkpoint is written to the game document under its label, so a newly elected host
+//! can still roll back to checkpoints its predecessor saved. Rolling back publishes the saved
+//! state as the current one and tells every peer with a `RollbackNotice`.
+
+use super::*;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Announcement that the host restored a checkpoint.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RollbackNotice {
+    /// The label of the restored checkpoint.
+    pub(crate) label: String,
+    /// When the rollback happened, in milliseconds since the Unix epoch, so rolling back to
+    /// the same checkpoint twice is still announced twice.
+    pub(crate) at: u64,
+}
+
+impl<G: GameLogic> StateData<G> {
+    /// Save the current game state, along with the action that produced it, under `label`.
+    ///
+    /// Saving under a label that is already in use replaces that checkpoint.
+    pub(crate) async fn save_checkpoint(&self, label: &str) -> Result<()> {
+        let entry = self.get_game_state_entry().await?;
+        let value = self.encode_state_entry(&entry)?;
+        self.set_bytes(&checkpoint_key(label)?, &value).await
+    }
+
+    /// Publish the game state saved under `label` as the current one and announce it.
+    ///
+    /// Action sequence numbers carry on from where they were, so requests made after the
+    /// checkpoint are never mistaken for new ones.
+    pub(crate) async fn restore_checkpoint(&self, label: &str) -> Result<()> {
+        let Some(bytes) = self
+            .get_host_authored_bytes(&checkpoint_key(label)?)
+            .await?
+        else {
+            return Err(anyhow!("No checkpoint named '{label}'"));
+        };
+        let entry = self.decode_state_entry(&bytes)?;
+        self.record_activity();
+        self.put_game_state(&entry.state, entry.applied_action)
+            .await?;
+        // The last action is no longer the one that produced the restored state.
+        self.set_undo_point(None).await?;
+        let notice = RollbackNotice {
+            label: label.to_string(),
+            at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64,
+        };
+        self.set_bytes(KEY_ROLLBACK, &self.encode(&notice)?).await
+    }
+}
+
+/// Build the document key a checkpoint is saved under.
+fn checkpoint_key(label: &str) -> Result<Vec<u8>> {
+    if label.is_empty() {
+        return Err(anyhow!("Checkpoint labels cannot be empty"));
+    }
+    Ok([PREFIX_CHECKPOINT, label.as_bytes()].concat())
+}
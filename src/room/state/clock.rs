@@ -0,0 +1,53 @@
+//! The room's hybrid logical clock.
+//!
+//! Entry timestamps come from each peer's wall clock, so two peers can disagree on which of two
+//! messages came first, and a reply can even appear to predate what it answers. Every peer
+//! instead keeps a hybrid logical clock: a reading is the wall clock in milliseconds, but never
+//! less than one past the latest reading this peer made or saw from another peer. Chat messages
+//! and action requests carry the reading they were made at, so every peer puts them in the same
+//! order, and anything written after seeing an entry sorts after it.
+
+use super::*;
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A hybrid logical clock, shared by everything that writes to one room.
+#[derive(Debug, Default)]
+pub(crate) struct LogicalClock {
+    latest: AtomicU64,
+}
+
+impl LogicalClock {
+    /// Take a new reading, later than every reading made or seen so far.
+    pub(crate) fn tick(&self) -> u64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_millis() as u64);
+        let previous = self
+            .latest
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |latest| {
+                Some(now.max(latest + 1))
+            })
+            .unwrap_or_default();
+        now.max(previous + 1)
+    }
+
+    /// Catch up with a reading seen on another peer's entry.
+    pub(crate) fn observe(&self, reading: u64) {
+        self.latest.fetch_max(reading, Ordering::SeqCst);
+    }
+}
+
+impl<G: GameLogic> StateData<G> {
+    /// Take a reading of the room's logical clock for an entry about to be written.
+    pub(crate) fn tick_clock(&self) -> u64 {
+        self.clock.tick()
+    }
+
+    /// Catch up the room's logical clock with a reading from another peer's entry.
+    pub(crate) fn observe_clock(&self, reading: u64) {
+        self.clock.observe(reading);
+    }
+}
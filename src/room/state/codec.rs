@@ -0,0 +1,112 @@
+//! Serialization and compression of the values written to the room's documents.
+//!
+//! The host picks a [WireFormat] and a [Compression] when it creates the room and records
+//! them in the room metadata. Joiners read them from there before touching any other entry,
+//! so every peer encodes and decodes values the same way. The metadata entry itself is always
+//! postcard and never compressed.
+
+use anyhow::{Result, anyhow};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+/// How values are serialized before they are written to the room's documents.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WireFormat {
+    /// Compact binary, readable by any serde implementation of postcard.
+    #[default]
+    Postcard,
+    /// Plain JSON text, for peers written in other languages and for debugging.
+    Json,
+    /// CBOR, a self-describing binary format with implementations in most languages.
+    Cbor,
+}
+
+impl WireFormat {
+    /// Serialize a value before it is written.
+    pub fn encode<T: Serialize + ?Sized>(self, value: &T) -> Result<Vec<u8>> {
+        Ok(match self {
+            WireFormat::Postcard => postcard::to_stdvec(value)?,
+            WireFormat::Json => serde_json::to_vec(value)?,
+            WireFormat::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes)?;
+                bytes
+            }
+        })
+    }
+
+    /// Deserialize a value that was written with [WireFormat::encode].
+    pub fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T> {
+        Ok(match self {
+            WireFormat::Postcard => postcard::from_bytes(bytes)?,
+            WireFormat::Json => serde_json::from_slice(bytes)?,
+            WireFormat::Cbor => ciborium::from_reader(bytes)?,
+        })
+    }
+}
+
+/// The most a compressed value may expand to, in bytes. Anything claiming to be larger is
+/// refused before it is decompressed, so a small entry can't exhaust a peer's memory.
+pub const MAX_DECOMPRESSED_LEN: usize = 64 * 1024 * 1024;
+
+/// How values are compressed before they are written to the room's documents.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Store values as they are.
+    #[default]
+    None,
+    /// LZ4 block compression, prefixed with the uncompressed length. Fast, for entries that
+    /// change often.
+    Lz4,
+    /// A Zstandard frame. Slower than [Compression::Lz4], but smaller, for large states.
+    Zstd,
+}
+
+impl Compression {
+    /// Compress a value before it is written.
+    pub fn encode(self, value: &[u8]) -> Result<Vec<u8>> {
+        Ok(match self {
+            Compression::None => value.to_vec(),
+            Compression::Lz4 => lz4_flex::compress_prepend_size(value),
+            Compression::Zstd => zstd::bulk::compress(value, zstd::DEFAULT_COMPRESSION_LEVEL)?,
+        })
+    }
+
+    /// Decompress a value that was written with [Compression::encode], refusing anything
+    /// that would expand past [MAX_DECOMPRESSED_LEN].
+    pub fn decode(self, value: Bytes) -> Result<Bytes> {
+        let truncated = || anyhow!("Compressed value is truncated");
+        match self {
+            Compression::None => Ok(value),
+            Compression::Lz4 => {
+                let (size, block) = value.split_first_chunk::<4>().ok_or_else(truncated)?;
+                let size = within_limit(u32::from_le_bytes(*size) as u64)?;
+                let value = lz4_flex::block::decompress(block, size)?;
+                if value.len() != size {
+                    return Err(anyhow!(
+                        "Compressed value is {} bytes, expected {size}",
+                        value.len()
+                    ));
+                }
+                Ok(value.into())
+            }
+            Compression::Zstd => {
+                let size = zstd::zstd_safe::get_frame_content_size(&value)
+                    .map_err(|_| truncated())?
+                    .ok_or_else(|| anyhow!("Compressed value does not state its size"))?;
+                let size = within_limit(size)?;
+                Ok(zstd::bulk::decompress(&value, size)?.into())
+            }
+        }
+    }
+}
+
+/// Check the size a compressed value says it expands to against [MAX_DECOMPRESSED_LEN].
+fn within_limit(size: u64) -> Result<usize> {
+    match usize::try_from(size) {
+        Ok(size) if size <= MAX_DECOMPRESSED_LEN => Ok(size),
+        _ => Err(anyhow!(
+            "Compressed value expands to {size} bytes, over the {MAX_DECOMPRESSED_LEN} allowed"
+        )),
+    }
+}
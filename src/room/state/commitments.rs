@@ -0,0 +1,226 @@
+//! Commit-then-reveal for simultaneous secret choices.
+//!
+//! Action requests sit in the document in the clear, so in a simultaneous round a player could
+//! read the others' choices before making their own. With [crate::GameRoom::commit_action] a
+//! player first writes only a salted hash of their action, under
+//! `commitment.<player>.<number>`. Once every player in the game has made a commitment with
+//! that number, each committing peer submits the action itself along with the salt, and the
+//! host refuses any action that does not match the commitment it reveals. Until it sees that
+//! reveal, the host also refuses any other action from the player, so nobody can commit to
+//! something meaningless and then act on what the others revealed.
+//!
+//! The salt is only kept in memory, so a peer that restarts before the reveal has to commit
+//! again.
+
+use super::*;
+use crypto_secretbox::aead::{OsRng, rand_core::RngCore as _};
+use n0_future::StreamExt as _;
+use std::sync::MutexGuard;
+
+/// The salt and number that open a player's commitment, sent with the action it was made for.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Reveal {
+    /// The number of the commitment this action opens.
+    pub commitment: u64,
+    /// The random salt the action was hashed with.
+    pub salt: [u8; 32],
+}
+
+/// An action committed to on this endpoint and not yet revealed.
+#[derive(Debug)]
+pub(crate) struct PendingReveal<A> {
+    commitment: u64,
+    salt: [u8; 32],
+    action: A,
+}
+
+impl<G: GameLogic> StateData<G> {
+    fn pending_reveals(&self) -> MutexGuard<'_, HashMap<EndpointId, PendingReveal<G::GameAction>>> {
+        // Reveals are only ever added or removed whole, so a poisoned lock is still usable.
+        self.pending_reveals
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Write a commitment to one of this endpoint's players' next action, returning its number.
+    ///
+    /// The action is submitted by itself once every player has made the same commitment.
+    pub(crate) async fn commit_action(
+        &self,
+        player: &EndpointId,
+        action: G::GameAction,
+    ) -> Result<u64> {
+        if self.pending_reveal(player).is_some() {
+            return Err(anyhow!(
+                "Player {player} has a commitment waiting to be revealed"
+            ));
+        }
+        let commitment = self.last_commitment(player).await? + 1;
+        let mut salt = [0; 32];
+        OsRng.fill_bytes(&mut salt);
+        let hash = commitment_hash(&salt, &action)?;
+        self.pending_reveals().insert(
+            *player,
+            PendingReveal {
+                commitment,
+                salt,
+                action,
+            },
+        );
+        let written = self
+            .set_bytes(&commitment_key(player, commitment), &self.encode(&hash)?)
+            .await;
+        if written.is_err() {
+            self.pending_reveals().remove(player);
+        }
+        written.map(|_| commitment)
+    }
+
+    /// Submit every action committed to on this endpoint whose commitment every player has
+    /// now matched.
+    pub(crate) async fn reveal_ready_actions(&self) -> Result<()> {
+        let pending: Vec<_> = self
+            .pending_reveals()
+            .iter()
+            .map(|(player, reveal)| (*player, reveal.commitment))
+            .collect();
+        for (player, commitment) in pending {
+            if !self.all_committed(commitment).await? {
+                continue;
+            }
+            let Some(pending) = self.pending_reveals().remove(&player) else {
+                continue;
+            };
+            let reveal = Reveal {
+                commitment,
+                salt: pending.salt,
+            };
+            self.put_action_request(&player, pending.action, Some(reveal))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Why an action request must be refused for the player's commitments, if it must.
+    ///
+    /// While the player has a commitment the host has not seen revealed, only the action that
+    /// opens it is accepted; otherwise only requests without a reveal are.
+    pub(crate) async fn reveal_error(
+        &self,
+        player: &EndpointId,
+        request: &ActionRequest<G::GameAction>,
+    ) -> Result<Option<String>> {
+        let last = self.last_commitment(player).await?;
+        let open = (last > self.last_revealed(player).await?).then_some(last);
+        let reveal = match (&request.reveal, open) {
+            (None, None) => return Ok(None),
+            (None, Some(_)) => {
+                return Ok(Some("Action must reveal its commitment".to_string()));
+            }
+            (Some(reveal), open) if open != Some(reveal.commitment) => {
+                return Ok(Some("Action reveals the wrong commitment".to_string()));
+            }
+            (Some(reveal), _) => reveal,
+        };
+        let committed = self.get_commitment(player, reveal.commitment).await?;
+        if committed != Some(commitment_hash(&reveal.salt, &request.action)?) {
+            return Ok(Some("Action does not match its commitment".to_string()));
+        }
+        Ok(None)
+    }
+
+    /// Record that the host has seen a player reveal the commitment with this number.
+    pub(crate) async fn mark_revealed(&self, player: &EndpointId, commitment: u64) -> Result<()> {
+        self.set_bytes(&revealed_key(player), &self.encode(&commitment)?)
+            .await
+    }
+
+    /// The number of the last commitment the host saw a player reveal, or 0 if none.
+    async fn last_revealed(&self, player: &EndpointId) -> Result<u64> {
+        match self.get_host_authored_bytes(&revealed_key(player)).await? {
+            Some(bytes) => self.decode(&bytes),
+            None => Ok(0),
+        }
+    }
+
+    /// The commitment number of one of this endpoint's players' action still to be revealed.
+    fn pending_reveal(&self, player: &EndpointId) -> Option<u64> {
+        self.pending_reveals()
+            .get(player)
+            .map(|reveal| reveal.commitment)
+    }
+
+    /// Whether every online player has made the commitment with this number.
+    async fn all_committed(&self, commitment: u64) -> Result<bool> {
+        for (id, peer) in self.get_peer_list().await?.iter() {
+            if peer.is_observer || !peer.status.is_online() {
+                continue;
+            }
+            if self.get_commitment(id, commitment).await?.is_none() {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Get the hash a player committed to, written by the player's registered author.
+    async fn get_commitment(&self, player: &EndpointId, commitment: u64) -> Result<Option<Hash>> {
+        let Some(author_id) = self.get_peer_info(player).await?.map(|peer| peer.author_id) else {
+            return Ok(None);
+        };
+        let key = commitment_key(player, commitment);
+        let query = self.doc.get_one(Query::key_exact(&key).author(author_id));
+        match query.await? {
+            Some(entry) => Ok(Some(self.parse(&entry).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The number of the last commitment a player made, or 0 if they have made none.
+    async fn last_commitment(&self, player: &EndpointId) -> Result<u64> {
+        let Some(author_id) = self.get_peer_info(player).await?.map(|peer| peer.author_id) else {
+            return Ok(0);
+        };
+        let prefix = commitment_key_prefix(player);
+        let query = Query::single_latest_per_key()
+            .key_prefix(&prefix)
+            .author(author_id);
+        let mut entries = Box::pin(self.doc.get_many(query).await?);
+        let mut last = 0;
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            let number = std::str::from_utf8(&entry.key()[prefix.len()..])
+                .ok()
+                .and_then(|number| number.parse().ok());
+            last = last.max(number.unwrap_or_default());
+        }
+        Ok(last)
+    }
+}
+
+/// The hash a player commits to: the action, salted so it cannot be guessed from a list of
+/// the moves the game allows.
+fn commitment_hash<A: Serialize>(salt: &[u8; 32], action: &A) -> Result<Hash> {
+    Ok(Hash::new(
+        [salt.as_slice(), &postcard::to_stdvec(action)?].concat(),
+    ))
+}
+
+/// Build the key prefix a player's commitments are written under.
+fn commitment_key_prefix(player: &EndpointId) -> Vec<u8> {
+    [PREFIX_COMMITMENT, format!("{player}.").as_bytes()].concat()
+}
+
+/// Build the key the last commitment a player revealed is recorded under.
+fn revealed_key(player: &EndpointId) -> Vec<u8> {
+    [PREFIX_REVEALED, player.to_string().as_bytes()].concat()
+}
+
+/// Build the key a player's commitment is written under.
+fn commitment_key(player: &EndpointId, commitment: u64) -> Vec<u8> {
+    [
+        commitment_key_prefix(player),
+        commitment.to_string().into_bytes(),
+    ]
+    .concat()
+}
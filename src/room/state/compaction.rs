@@ -0,0 +1,218 @@
+//! Compaction of chat and action history.
+//!
+//! Long sessions keep adding chat messages and action journal entries. The host decides how
+//! much of that history to keep and publishes a `CompactionMark`; since a peer can only delete
+//! entries it authored, every peer then removes its own entries from before the mark.
+
+use super::*;
+use crate::CompactionPolicy;
+use n0_future::StreamExt as _;
+use std::{
+    collections::HashSet,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Key prefixes of the action journal in the game document.
+const ACTION_PREFIXES: [&[u8]; 3] = [PREFIX_ACTION, PREFIX_ACTION_RESULT, PREFIX_PROCESSED_ACTION];
+/// Key prefixes of chat history in the chat document.
+const CHAT_PREFIXES: [&[u8]; 2] = [PREFIX_CHAT, PREFIX_CHAT_REACTION];
+
+/// The point before which history may be removed, published by the host.
+///
+/// Both fields are entry timestamps, in microseconds since the Unix epoch.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CompactionMark {
+    /// Chat messages and reactions written before this time may be removed.
+    pub chat_before: u64,
+    /// Action requests, results and processed markers written before this time may be removed.
+    pub actions_before: u64,
+}
+
+/// How much a room is storing, as seen by this peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DocStats {
+    /// Entries in the game document.
+    pub game_entries: usize,
+    /// Entries in the game document that belong to the action journal.
+    pub action_entries: usize,
+    /// Entries in the chat document.
+    pub chat_entries: usize,
+    /// Total size of the blobs the entries in both documents point to.
+    pub blob_bytes: u64,
+}
+
+impl<G: GameLogic> StateData<G> {
+    /// Work out which history the policy lets go of, as of now.
+    pub(crate) async fn compaction_mark(
+        &self,
+        policy: &CompactionPolicy,
+    ) -> Result<CompactionMark> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_micros() as u64;
+        let by_age = now.saturating_sub(policy.max_age.as_micros() as u64);
+        let chat_before = match &self.chat_doc {
+            Some(chat_doc) => now
+                .saturating_sub(policy.chat_max_age.as_micros() as u64)
+                .max(count_cutoff(chat_doc, PREFIX_CHAT, policy.chat_max_entries).await?),
+            None => 0,
+        };
+        let actions_before =
+            by_age.max(count_cutoff(&self.doc, PREFIX_ACTION, policy.max_entries).await?);
+        Ok(CompactionMark {
+            chat_before,
+            actions_before,
+        })
+    }
+
+    /// Delete this peer's own chat and action entries from before the mark, returning how many
+    /// entries were removed.
+    ///
+    /// Each player's latest journal entries are kept so sequence numbers carry on where they
+    /// left off, along with any action request the host has not processed yet.
+    pub(crate) async fn compact_own_entries(&self, mark: &CompactionMark) -> Result<usize> {
+        let mut removed = 0;
+        if let Some(chat_doc) = &self.chat_doc {
+            for prefix in CHAT_PREFIXES {
+                let entries = own_entries(chat_doc, self.author_id, prefix).await?;
+                let keep = HashSet::new();
+                removed +=
+                    delete_before(chat_doc, self.author_id, entries, mark.chat_before, &keep)
+                        .await?;
+            }
+        }
+        for prefix in ACTION_PREFIXES {
+            let entries = own_entries(&self.doc, self.author_id, prefix).await?;
+            let keep = self.journal_entries_to_keep(prefix, &entries).await?;
+            removed += delete_before(
+                &self.doc,
+                self.author_id,
+                entries,
+                mark.actions_before,
+                &keep,
+            )
+            .await?;
+        }
+        Ok(removed)
+    }
+
+    /// The keys of journal entries that must survive compaction.
+    async fn journal_entries_to_keep(
+        &self,
+        prefix: &[u8],
+        entries: &[Entry],
+    ) -> Result<HashSet<Vec<u8>>> {
+        // e.g., "action.<id>.<seq>"
+        let mut latest: HashMap<EndpointId, (u64, Vec<u8>)> = HashMap::new();
+        let mut keep = HashSet::new();
+        for entry in entries {
+            let Some((player_id, seq)) = parse_journal_key(&entry.key()[prefix.len()..]) else {
+                continue;
+            };
+            if prefix == PREFIX_ACTION && seq > self.last_processed_action_seq(&player_id).await? {
+                keep.insert(entry.key().to_vec());
+            }
+            let newest = latest
+                .entry(player_id)
+                .or_insert((seq, entry.key().to_vec()));
+            if seq > newest.0 {
+                *newest = (seq, entry.key().to_vec());
+            }
+        }
+        keep.extend(latest.into_values().map(|(_, key)| key));
+        Ok(keep)
+    }
+
+    /// Count the entries in the room's documents, and the bytes they point to.
+    pub async fn doc_stats(&self) -> Result<DocStats> {
+        let mut stats = DocStats::default();
+        let mut entries = Box::pin(self.doc.get_many(Query::single_latest_per_key()).await?);
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            stats.game_entries += 1;
+            stats.blob_bytes += entry.content_len();
+            if ACTION_PREFIXES
+                .iter()
+                .any(|prefix| entry.key().starts_with(prefix))
+            {
+                stats.action_entries += 1;
+            }
+        }
+        if let Some(chat_doc) = &self.chat_doc {
+            let mut entries = Box::pin(chat_doc.get_many(Query::single_latest_per_key()).await?);
+            while let Some(entry) = entries.next().await {
+                stats.chat_entries += 1;
+                stats.blob_bytes += entry?.content_len();
+            }
+        }
+        Ok(stats)
+    }
+}
+
+/// The timestamp before which entries under `prefix` are beyond the newest `max_entries`.
+async fn count_cutoff(doc: &Doc, prefix: &[u8], max_entries: usize) -> Result<u64> {
+    let mut timestamps = Vec::new();
+    let mut entries = Box::pin(
+        doc.get_many(Query::single_latest_per_key().key_prefix(prefix))
+            .await?,
+    );
+    while let Some(entry) = entries.next().await {
+        timestamps.push(entry?.timestamp());
+    }
+    if timestamps.len() <= max_entries {
+        return Ok(0);
+    }
+    timestamps.sort_unstable_by(|a, b| b.cmp(a));
+    Ok(match max_entries {
+        0 => timestamps[0] + 1,
+        n => timestamps[n - 1],
+    })
+}
+
+/// Every entry under `prefix` written by `author`.
+async fn own_entries(doc: &Doc, author: AuthorId, prefix: &[u8]) -> Result<Vec<Entry>> {
+    let mut own = Vec::new();
+    let mut entries = Box::pin(
+        doc.get_many(Query::author(author).key_prefix(prefix))
+            .await?,
+    );
+    while let Some(entry) = entries.next().await {
+        own.push(entry?);
+    }
+    Ok(own)
+}
+
+/// Delete the entries written before `before`, apart from those in `keep`.
+///
+/// Deleting a key also deletes every key it is a prefix of, e.g. `action.<id>.1` takes
+/// `action.<id>.12` with it, so a key is left alone while a longer one it prefixes must stay.
+async fn delete_before(
+    doc: &Doc,
+    author: AuthorId,
+    entries: Vec<Entry>,
+    before: u64,
+    keep: &HashSet<Vec<u8>>,
+) -> Result<usize> {
+    let (mut old, kept): (Vec<Entry>, Vec<Entry>) = entries
+        .into_iter()
+        .partition(|entry| entry.timestamp() < before && !keep.contains(entry.key()));
+    old.sort_by(|a, b| a.key().cmp(b.key()));
+
+    let mut removed = 0;
+    let mut deleted: Vec<&[u8]> = Vec::new();
+    for entry in &old {
+        let key = entry.key();
+        if deleted.iter().any(|prefix| key.starts_with(prefix))
+            || kept.iter().any(|kept| kept.key().starts_with(key))
+        {
+            continue;
+        }
+        removed += doc.del(author, key.to_vec()).await?;
+        deleted.push(key);
+    }
+    Ok(removed)
+}
+
+/// Split a journal key suffix, e.g. `<id>.<seq>`, into the player and sequence number.
+fn parse_journal_key(suffix: &[u8]) -> Option<(EndpointId, u64)> {
+    let (player_id, seq) = std::str::from_utf8(suffix).ok()?.rsplit_once('.')?;
+    Some((endpoint_id_from_str(player_id).ok()?, seq.parse().ok()?))
+}
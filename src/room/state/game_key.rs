@@ -14,22 +14,76 @@ use iroh_docs::Entry;
 pub trait GameKey {
     /// This entry is an arrival announcement, return the ID of the new arrival.
     fn is_join(&self) -> Option<Result<EndpointId>>;
-    /// This entry is a request to perform an action, return the requestor and action id.
-    fn is_action_request(&self) -> Option<Result<(EndpointId, String)>>;
-    /// This entry is the result of a requested action, return the requestor and action id.
-    fn is_action_result(&self) -> Option<Result<(EndpointId, String)>>;
+    /// This entry is the host refusing a join request, return the ID of the refused peer.
+    fn is_join_denial(&self) -> Option<Result<EndpointId>>;
+    /// This entry asks to seat another local player, return the ID of the new seat.
+    fn is_seat_request(&self) -> Option<Result<EndpointId>>;
+    /// This entry is a request to perform an action, return the requestor and sequence number.
+    fn is_action_request(&self) -> Option<Result<(EndpointId, u64)>>;
+    /// This entry is a commitment to an action, return the player and the commitment's number.
+    fn is_commitment(&self) -> Option<Result<(EndpointId, u64)>>;
+    /// This entry is the result of a requested action, return the requestor and sequence number.
+    fn is_action_result(&self) -> Option<Result<(EndpointId, u64)>>;
     /// This entry is a chat message, return the ID of the sender.
     fn is_chat_message(&self) -> Option<Result<EndpointId>>;
+    /// This entry is a chat reaction, return the ID of the reacting peer.
+    fn is_chat_reaction(&self) -> Option<Result<EndpointId>>;
+    /// This entry asks for a place as a player, return the ID of the requesting peer.
+    fn is_play_request(&self) -> Option<Result<EndpointId>>;
     /// This entry is a quit announcement, return the ID of the quitter.
     fn is_quit_request(&self) -> Option<Result<EndpointId>>;
+    /// This entry announces a team change, return the ID of the peer who changed team.
+    fn is_team_update(&self) -> Option<Result<EndpointId>>;
+    /// This entry is an action annotation, return the ID of the annotating peer.
+    fn is_annotation(&self) -> Option<Result<EndpointId>>;
+    /// This entry is a heartbeat, return the ID of the peer it came from.
+    fn is_heartbeat(&self) -> Option<Result<EndpointId>>;
+    /// This entry is a private payload from the host, return the ID of the player it is for.
+    fn is_private_state(&self) -> Option<Result<EndpointId>>;
+    /// This entry is in the asset manifest, return the asset's name.
+    fn is_asset(&self) -> Option<String>;
+    /// This entry is a proposal, return the proposal's ID.
+    fn is_proposal(&self) -> Option<String>;
+    /// This entry is a vote on a proposal, return the proposal's ID and the voter.
+    fn is_proposal_vote(&self) -> Option<Result<(String, EndpointId)>>;
+    /// This entry is the host's resolution of a proposal, return the proposal's ID.
+    fn is_proposal_result(&self) -> Option<String>;
+    /// This entry calls a vote, return the vote's ID.
+    fn is_vote_call(&self) -> Option<String>;
+    /// This entry is a ballot in a vote, return the vote's ID and the voter.
+    fn is_ballot(&self) -> Option<Result<(String, EndpointId)>>;
+    /// This entry is the host's tally of a vote, return the vote's ID.
+    fn is_vote_result(&self) -> Option<String>;
+    /// This entry is a step in a lock-step game, return the step's number.
+    fn is_lockstep_step(&self) -> Option<Result<u64>>;
     /// A peer entry has been updated
     fn is_peer_entry(&self) -> bool;
     /// Game State has updated
     fn is_game_state_update(&self) -> bool;
+    /// Host has started the game
+    fn is_game_start(&self) -> bool;
+    /// Host has finished the game with a result
+    fn is_game_result(&self) -> bool;
     /// App State has updated
     fn is_app_state_update(&self) -> bool;
     /// Host has updated
     fn is_host_update(&self) -> bool;
+    /// Host has offered its authority to another peer
+    fn is_host_handover(&self) -> bool;
+    /// Host has warned that the game is about to be finished for inactivity
+    fn is_inactivity_warning(&self) -> bool;
+    /// Host has invited everyone to a new room for the next game
+    fn is_next_room(&self) -> bool;
+    /// Host has closed the room for good
+    fn is_room_closed(&self) -> bool;
+    /// Host has moved the point before which history may be compacted
+    fn is_compaction_mark(&self) -> bool;
+    /// Host has rolled the game back to a checkpoint
+    fn is_rollback(&self) -> bool;
+    /// Host has published a change to the turn order
+    fn is_turn_update(&self) -> bool;
+    /// Host has changed the room's details
+    fn is_meta_update(&self) -> bool;
 }
 
 impl GameKey for Entry {
@@ -40,19 +94,41 @@ impl GameKey for Entry {
         let id = String::from_utf8_lossy(&self.key()[PREFIX_JOIN.len()..]);
         Some(endpoint_id_from_str(&id))
     }
-    fn is_action_request(&self) -> Option<Result<(EndpointId, String)>> {
+    fn is_join_denial(&self) -> Option<Result<EndpointId>> {
+        if !self.key().starts_with(PREFIX_JOIN_DENIED) {
+            return None;
+        }
+        let id = String::from_utf8_lossy(&self.key()[PREFIX_JOIN_DENIED.len()..]);
+        Some(endpoint_id_from_str(&id))
+    }
+    fn is_seat_request(&self) -> Option<Result<EndpointId>> {
+        if !self.key().starts_with(PREFIX_SEAT) {
+            return None;
+        }
+        let id = String::from_utf8_lossy(&self.key()[PREFIX_SEAT.len()..]);
+        Some(endpoint_id_from_str(&id))
+    }
+    fn is_action_request(&self) -> Option<Result<(EndpointId, u64)>> {
         if !self.key().starts_with(PREFIX_ACTION) {
             return None;
         }
-        Some(parse_endpoint_and_suffix(&String::from_utf8_lossy(
+        Some(parse_endpoint_and_seq(&String::from_utf8_lossy(
             &self.key()[PREFIX_ACTION.len()..],
         )))
     }
-    fn is_action_result(&self) -> Option<Result<(EndpointId, String)>> {
+    fn is_commitment(&self) -> Option<Result<(EndpointId, u64)>> {
+        if !self.key().starts_with(PREFIX_COMMITMENT) {
+            return None;
+        }
+        Some(parse_endpoint_and_seq(&String::from_utf8_lossy(
+            &self.key()[PREFIX_COMMITMENT.len()..],
+        )))
+    }
+    fn is_action_result(&self) -> Option<Result<(EndpointId, u64)>> {
         if !self.key().starts_with(PREFIX_ACTION_RESULT) {
             return None;
         }
-        Some(parse_endpoint_and_suffix(&String::from_utf8_lossy(
+        Some(parse_endpoint_and_seq(&String::from_utf8_lossy(
             &self.key()[PREFIX_ACTION_RESULT.len()..],
         )))
     }
@@ -60,10 +136,30 @@ impl GameKey for Entry {
         if !self.key().starts_with(PREFIX_CHAT) {
             return None;
         }
-        // The key is "chat.<timestamp>.<id>", so we split and take the last part.
+        // The key is "chat.<message_id>.<from>", so we split and take the last part.
         let key_str = String::from_utf8_lossy(self.key());
         key_str.split('.').next_back().map(endpoint_id_from_str)
     }
+    fn is_chat_reaction(&self) -> Option<Result<EndpointId>> {
+        if !self.key().starts_with(PREFIX_CHAT_REACTION) {
+            return None;
+        }
+        // The key is "chat_reaction.<message_id>.<id>.<emoji>".
+        let key_str = String::from_utf8_lossy(&self.key()[PREFIX_CHAT_REACTION.len()..]);
+        Some(match key_str.split('.').nth(1) {
+            Some(id) => endpoint_id_from_str(id),
+            None => Err(anyhow!(
+                "Expected '<message>.<endpoint>.<emoji>', got '{key_str}'"
+            )),
+        })
+    }
+    fn is_play_request(&self) -> Option<Result<EndpointId>> {
+        if !self.key().starts_with(PREFIX_PLAY_REQUEST) {
+            return None;
+        }
+        let id = String::from_utf8_lossy(&self.key()[PREFIX_PLAY_REQUEST.len()..]);
+        Some(endpoint_id_from_str(&id))
+    }
     fn is_quit_request(&self) -> Option<Result<EndpointId>> {
         if !self.key().starts_with(PREFIX_QUIT) {
             return None;
@@ -71,24 +167,155 @@ impl GameKey for Entry {
         let id = String::from_utf8_lossy(&self.key()[PREFIX_QUIT.len()..]);
         Some(endpoint_id_from_str(&id))
     }
+    fn is_team_update(&self) -> Option<Result<EndpointId>> {
+        if !self.key().starts_with(PREFIX_TEAM) {
+            return None;
+        }
+        let id = String::from_utf8_lossy(&self.key()[PREFIX_TEAM.len()..]);
+        Some(endpoint_id_from_str(&id))
+    }
+    fn is_annotation(&self) -> Option<Result<EndpointId>> {
+        if !self.key().starts_with(PREFIX_ANNOTATION) {
+            return None;
+        }
+        // The key is "annotation.<action_id>.<id>.<timestamp>".
+        let key_str = String::from_utf8_lossy(&self.key()[PREFIX_ANNOTATION.len()..]);
+        Some(match key_str.split('.').nth(1) {
+            Some(id) => endpoint_id_from_str(id),
+            None => Err(anyhow!(
+                "Expected '<action>.<endpoint>.<timestamp>', got '{key_str}'"
+            )),
+        })
+    }
+    fn is_heartbeat(&self) -> Option<Result<EndpointId>> {
+        if !self.key().starts_with(PREFIX_HEARTBEAT) {
+            return None;
+        }
+        let id = String::from_utf8_lossy(&self.key()[PREFIX_HEARTBEAT.len()..]);
+        Some(endpoint_id_from_str(&id))
+    }
+    fn is_private_state(&self) -> Option<Result<EndpointId>> {
+        if !self.key().starts_with(PREFIX_PRIVATE) {
+            return None;
+        }
+        let id = String::from_utf8_lossy(&self.key()[PREFIX_PRIVATE.len()..]);
+        Some(endpoint_id_from_str(&id))
+    }
+    fn is_asset(&self) -> Option<String> {
+        if !self.key().starts_with(PREFIX_ASSET) {
+            return None;
+        }
+        Some(String::from_utf8_lossy(&self.key()[PREFIX_ASSET.len()..]).to_string())
+    }
+    fn is_proposal(&self) -> Option<String> {
+        if !self.key().starts_with(PREFIX_PROPOSAL) {
+            return None;
+        }
+        Some(String::from_utf8_lossy(&self.key()[PREFIX_PROPOSAL.len()..]).to_string())
+    }
+    fn is_proposal_vote(&self) -> Option<Result<(String, EndpointId)>> {
+        if !self.key().starts_with(PREFIX_PROPOSAL_VOTE) {
+            return None;
+        }
+        let value = String::from_utf8_lossy(&self.key()[PREFIX_PROPOSAL_VOTE.len()..]);
+        let Some((id, voter)) = value.split_once('.') else {
+            return Some(Err(anyhow!(
+                "Expected '<proposal>.<endpoint>', got '{value}'"
+            )));
+        };
+        Some(endpoint_id_from_str(voter).map(|voter| (id.to_string(), voter)))
+    }
+    fn is_proposal_result(&self) -> Option<String> {
+        if !self.key().starts_with(PREFIX_PROPOSAL_RESULT) {
+            return None;
+        }
+        Some(String::from_utf8_lossy(&self.key()[PREFIX_PROPOSAL_RESULT.len()..]).to_string())
+    }
+    fn is_vote_call(&self) -> Option<String> {
+        if !self.key().starts_with(PREFIX_VOTE) {
+            return None;
+        }
+        Some(String::from_utf8_lossy(&self.key()[PREFIX_VOTE.len()..]).to_string())
+    }
+    fn is_ballot(&self) -> Option<Result<(String, EndpointId)>> {
+        if !self.key().starts_with(PREFIX_BALLOT) {
+            return None;
+        }
+        let value = String::from_utf8_lossy(&self.key()[PREFIX_BALLOT.len()..]);
+        let Some((id, voter)) = value.split_once('.') else {
+            return Some(Err(anyhow!("Expected '<vote>.<endpoint>', got '{value}'")));
+        };
+        Some(endpoint_id_from_str(voter).map(|voter| (id.to_string(), voter)))
+    }
+    fn is_vote_result(&self) -> Option<String> {
+        if !self.key().starts_with(PREFIX_VOTE_RESULT) {
+            return None;
+        }
+        Some(String::from_utf8_lossy(&self.key()[PREFIX_VOTE_RESULT.len()..]).to_string())
+    }
+    fn is_lockstep_step(&self) -> Option<Result<u64>> {
+        if !self.key().starts_with(PREFIX_LOCKSTEP) {
+            return None;
+        }
+        // The key is "lockstep.<step>.<id>".
+        let key_str = String::from_utf8_lossy(&self.key()[PREFIX_LOCKSTEP.len()..]);
+        let step = key_str.split('.').next().unwrap_or_default();
+        Some(
+            step.parse()
+                .map_err(|e| anyhow!("Invalid lock-step step '{step}': {e}")),
+        )
+    }
     fn is_peer_entry(&self) -> bool {
         self.key().starts_with(PREFIX_PEER)
     }
     fn is_game_state_update(&self) -> bool {
         self.key() == KEY_GAME_STATE
     }
+    fn is_game_start(&self) -> bool {
+        self.key() == KEY_GAME_START
+    }
+    fn is_game_result(&self) -> bool {
+        self.key() == KEY_GAME_RESULT
+    }
     fn is_app_state_update(&self) -> bool {
         self.key() == KEY_APP_STATE
     }
     fn is_host_update(&self) -> bool {
         self.key() == KEY_HOST_ID
     }
+    fn is_host_handover(&self) -> bool {
+        self.key() == KEY_HOST_HANDOVER
+    }
+    fn is_inactivity_warning(&self) -> bool {
+        self.key() == KEY_INACTIVITY_WARNING
+    }
+    fn is_compaction_mark(&self) -> bool {
+        self.key() == KEY_COMPACTION
+    }
+    fn is_next_room(&self) -> bool {
+        self.key() == KEY_NEXT_ROOM
+    }
+    fn is_room_closed(&self) -> bool {
+        self.key() == KEY_ROOM_CLOSED
+    }
+    fn is_rollback(&self) -> bool {
+        self.key() == KEY_ROLLBACK
+    }
+    fn is_turn_update(&self) -> bool {
+        self.key() == KEY_TURNS
+    }
+    fn is_meta_update(&self) -> bool {
+        self.key() == KEY_ROOM_META
+    }
 }
 
 /// Parse keys shaped as `<endpoint>.<suffix>`.
-fn parse_endpoint_and_suffix(value: &str) -> Result<(EndpointId, String)> {
-    let Some((id, suffix)) = value.split_once('.') else {
-        return Err(anyhow!("Expected '<endpoint>.<id>', got '{value}'"));
+fn parse_endpoint_and_seq(value: &str) -> Result<(EndpointId, u64)> {
+    let Some((id, seq)) = value.split_once('.') else {
+        return Err(anyhow!("Expected '<endpoint>.<seq>', got '{value}'"));
     };
-    Ok((endpoint_id_from_str(id)?, suffix.to_string()))
+    let seq = seq
+        .parse()
+        .map_err(|e| anyhow!("Invalid action sequence '{seq}': {e}"))?;
+    Ok((endpoint_id_from_str(id)?, seq))
 }
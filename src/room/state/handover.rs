@@ -0,0 +1,78 @@
+//! Voluntary handover of hosting authority.
+//!
+//! The host writes a signed `HostHandover` naming the peer that should take over. The target
+//! claims host when the handover reaches it, so every peer sees an ordinary host change
+//! rather than the host going offline.
+
+use super::*;
+use iroh::{SecretKey, Signature};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The host's offer to hand its authority to another peer.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct HostHandover {
+    pub(crate) from: EndpointId,
+    pub(crate) to: EndpointId,
+    /// When the handover was offered, in milliseconds since the Unix epoch, so handing over
+    /// to the same peer twice is still announced twice.
+    at: u64,
+    signature: Signature,
+}
+
+impl HostHandover {
+    /// Offer hosting to `to`, signed as the endpoint with `secret_key`.
+    fn sign(secret_key: &SecretKey, to: EndpointId) -> Result<Self> {
+        let from = secret_key.public();
+        let at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+        let signature = secret_key.sign(&signed_payload(&from, &to, at));
+        Ok(Self {
+            from,
+            to,
+            at,
+            signature,
+        })
+    }
+
+    /// Check that the handover was signed by the endpoint it claims to be from.
+    fn verify(&self) -> Result<()> {
+        self.from
+            .verify(
+                &signed_payload(&self.from, &self.to, self.at),
+                &self.signature,
+            )
+            .map_err(|_| anyhow!("Host handover from {} has a bad signature", self.from))
+    }
+}
+
+impl<G: GameLogic> StateData<G> {
+    /// Offer hosting to another peer, who takes over once the offer reaches them.
+    pub(crate) async fn hand_over_host(&self, to: &EndpointId) -> Result<()> {
+        let handover = HostHandover::sign(self.iroh()?.endpoint().secret_key(), *to)?;
+        self.set_bytes(KEY_HOST_HANDOVER, &self.encode(&handover)?)
+            .await
+    }
+
+    /// Take over hosting if `handover` is addressed to us by the current host.
+    ///
+    /// Returns whether we claimed host. Offers from anyone but the current host are stale or
+    /// forged, and are ignored.
+    pub(crate) async fn accept_handover(
+        &self,
+        handover: &HostHandover,
+        author_id: &AuthorId,
+    ) -> Result<bool> {
+        if handover.to != self.endpoint_id
+            || !self.is_peer_host(&handover.from).await?
+            || !self.host_author_matches(author_id).await?
+        {
+            return Ok(false);
+        }
+        handover.verify()?;
+        self.set_host(&self.endpoint_id).await?;
+        Ok(true)
+    }
+}
+
+fn signed_payload(from: &EndpointId, to: &EndpointId, at: u64) -> Vec<u8> {
+    [from.as_bytes().as_slice(), to.as_bytes(), &at.to_le_bytes()].concat()
+}
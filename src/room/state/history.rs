@@ -0,0 +1,29 @@
+//! Adding finished games to the node's match history, see [crate::Iroh::match_history].
+
+use super::*;
+use crate::{GameResult, MatchRecord};
+
+impl<G: GameLogic> StateData<G> {
+    /// Remember the game that just finished in the node's match history, if it keeps one.
+    pub(crate) async fn record_match(&self, logic: &G, result: &GameResult) -> Result<()> {
+        let iroh = self.iroh()?;
+        if iroh.data_dir().is_none() {
+            return Ok(());
+        }
+        let actions = self.get_action_log(..).await?;
+        let record = MatchRecord {
+            game: logic.game_name().to_string(),
+            room_id: self.doc.id().to_string(),
+            room_name: self
+                .get_meta()
+                .await?
+                .map(|meta| meta.name)
+                .unwrap_or_default(),
+            started_at: self.get_game_started_at().await?.unwrap_or_default(),
+            players: self.get_peer_list().await?,
+            result: result.clone(),
+            action_log_hash: Hash::new(postcard::to_stdvec(&actions)?),
+        };
+        iroh.record_match(&record).await
+    }
+}
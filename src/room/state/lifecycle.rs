@@ -2,11 +2,25 @@
 //! including player actions, game state, and lifecycle events.
 
 use super::*;
-use crate::{GameLogic, GameTicket};
+use crate::{
+    ConnectionPath, GameLogic, PeerConnectionInfo, PeerNetworkStats, PeerStatus, PeerStream,
+    RoomConfig, RoomTicket, StorageBackend,
+    room::realtime::{RealtimeMessage, RealtimePayload, realtime_topic},
+    trace,
+};
 use anyhow::Result;
+use iroh::EndpointAddr;
+use iroh_docs::engine::{LiveEvent, SyncEvent};
+use iroh_gossip::api::GossipTopic;
+use n0_future::{Stream, StreamExt as _};
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// How long to wait for the endpoint's direct addresses before handing out a LAN-only ticket.
+const DIRECT_ADDR_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// Report a reason for this endpoint leaving a GameRoom
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug)]
 pub enum LeaveReason<G: GameLogic> {
     /// Peer has closed the application.
     ApplicationClosed,
@@ -22,9 +36,45 @@ pub enum LeaveReason<G: GameLogic> {
     Unknown,
 }
 
+// Written out by hand, as deriving them would require the game logic itself to be `Clone`
+// and `PartialEq`.
+impl<G: GameLogic> Clone for LeaveReason<G> {
+    fn clone(&self) -> Self {
+        match self {
+            LeaveReason::ApplicationClosed => LeaveReason::ApplicationClosed,
+            LeaveReason::Timeout => LeaveReason::Timeout,
+            LeaveReason::Forfeit => LeaveReason::Forfeit,
+            LeaveReason::Error(message) => LeaveReason::Error(message.clone()),
+            LeaveReason::Custom(reason) => LeaveReason::Custom(reason.clone()),
+            LeaveReason::Unknown => LeaveReason::Unknown,
+        }
+    }
+}
+
+impl<G: GameLogic> PartialEq for LeaveReason<G> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (LeaveReason::Error(a), LeaveReason::Error(b)) => a == b,
+            (LeaveReason::Custom(a), LeaveReason::Custom(b)) => a == b,
+            (a, b) => std::mem::discriminant(a) == std::mem::discriminant(b),
+        }
+    }
+}
+
+impl<G: GameLogic> Eq for LeaveReason<G> {}
+
+/// Why the host refused to add a peer to the room.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinDeniedReason {
+    /// The room already has as many players as its [crate::RoomMeta::max_players] allows.
+    RoomFull,
+    /// The host closed the room, see [crate::GameRoom::close].
+    RoomClosed,
+}
+
 /// The current state of the game, used to determine what actions are available and how the UI should be presented.
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Copy)]
-pub enum AppState {
+#[derive(Serialize, Deserialize, Debug)]
+pub enum AppState<G: GameLogic + ?Sized> {
     /// The game is in the lobby, waiting for players to join and the host to start the game.
     /// In this state, players can chat and see who else is in the room, but cannot see the game state or perform actions.
     Lobby,
@@ -37,11 +87,43 @@ pub enum AppState {
     /// The game has ended, either because a win condition has been met or because the host has ended the game.
     /// In this state, players cannot perform actions, but can still chat and see the final game state.
     Finished,
+    /// The game is in progress, in one of the game's own phases, see [GameLogic::Phase].
+    /// Players can perform actions just as in [AppState::InGame].
+    Custom(G::Phase),
+}
+
+impl<G: GameLogic + ?Sized> AppState<G> {
+    /// Check whether the game is being played, in [AppState::InGame] or one of the game's own
+    /// phases.
+    pub fn is_in_game(&self) -> bool {
+        matches!(self, AppState::InGame | AppState::Custom(_))
+    }
 }
 
+// Written out by hand for the same reason as those of [LeaveReason].
+impl<G: GameLogic + ?Sized> Clone for AppState<G> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<G: GameLogic + ?Sized> Copy for AppState<G> {}
+
+impl<G: GameLogic + ?Sized> PartialEq for AppState<G> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (AppState::Custom(a), AppState::Custom(b)) => a == b,
+            (a, b) => std::mem::discriminant(a) == std::mem::discriminant(b),
+        }
+    }
+}
+
+impl<G: GameLogic + ?Sized> Eq for AppState<G> {}
+
 impl<G: GameLogic> Drop for StateData<G> {
     fn drop(&mut self) {
-        if let Some(iroh) = self.iroh.take() {
+        // Rooms can share a node, so only the last one to go shuts it down.
+        if let Some(iroh) = self.iroh.take().and_then(Arc::into_inner) {
             tokio::spawn(async move {
                 iroh.shutdown().await.ok();
             });
@@ -54,56 +136,519 @@ impl<G: GameLogic> StateData<G> {
     const ADDR_OPTIONS: AddrInfoOptions = AddrInfoOptions::RelayAndAddresses;
 
     /// Create a new StateData instance
-    pub async fn new(store_path: Option<PathBuf>, ticket: Option<GameTicket>) -> Result<Self> {
-        let iroh = match store_path {
-            None => Iroh::memory().await?,
-            Some(store_path) => Iroh::persistent(store_path).await?,
+    pub async fn new(
+        store_path: Option<PathBuf>,
+        ticket: Option<RoomTicket>,
+        config: &RoomConfig,
+    ) -> Result<Self> {
+        let storage = match &config.network.storage {
+            Some(storage) => storage.clone(),
+            None => StorageBackend::for_path(store_path),
+        };
+        let iroh = Iroh::spawn(storage, &config.network).await?;
+        Self::with_node(Arc::new(iroh), ticket, config).await
+    }
+
+    /// Create a new StateData instance on a node that is already running, e.g. one shared
+    /// with another room.
+    pub(crate) async fn with_node(
+        iroh: Arc<Iroh>,
+        ticket: Option<RoomTicket>,
+        config: &RoomConfig,
+    ) -> Result<Self> {
+        let author_id = match config.reuse_author {
+            true => iroh.get_default_author().await?,
+            false => iroh.create_author().await?,
         };
-        let author_id = iroh.docs().author_default().await?;
         let endpoint_id = iroh.endpoint().id();
 
-        let doc = match ticket {
-            None => iroh.docs().create().await?,
-            Some(game_ticket) => iroh.docs().import(game_ticket.doc_ticket).await?,
+        let (doc, chat_doc) = match ticket {
+            None => {
+                let doc = iroh.docs().create().await?;
+                let chat_doc = match config.chat {
+                    true => Some(iroh.docs().create().await?),
+                    false => None,
+                };
+                (doc, chat_doc)
+            }
+            Some(game_ticket) => {
+                let doc = iroh.docs().import(game_ticket.doc_ticket).await?;
+                let chat_doc = match game_ticket.chat_ticket {
+                    Some(chat_ticket) if config.chat => {
+                        Some(iroh.docs().import(chat_ticket).await?)
+                    }
+                    _ => None,
+                };
+                (doc, chat_doc)
+            }
+        };
+        Self::assemble(iroh, endpoint_id, author_id, doc, chat_doc, config).await
+    }
+
+    /// Re-open a room this persistent node has been in before, writing as `author_id` again.
+    ///
+    /// The room's documents are read from the node's store rather than synced from a ticket,
+    /// so this fails if they are no longer there.
+    pub(crate) async fn reopen(
+        iroh: Iroh,
+        ticket: &RoomTicket,
+        author_id: AuthorId,
+        config: &RoomConfig,
+    ) -> Result<Self> {
+        if iroh.docs().author_export(author_id).await?.is_none() {
+            return Err(anyhow!("The room's author is missing from the store"));
+        }
+        let missing = || anyhow!("The room is missing from the store");
+        let doc = iroh
+            .docs()
+            .open(ticket.doc_ticket.capability.id())
+            .await?
+            .ok_or_else(missing)?;
+        let chat_doc = match &ticket.chat_ticket {
+            Some(chat_ticket) if config.chat => Some(
+                iroh.docs()
+                    .open(chat_ticket.capability.id())
+                    .await?
+                    .ok_or_else(missing)?,
+            ),
+            _ => None,
         };
+        let endpoint_id = iroh.endpoint().id();
+        Self::assemble(
+            Arc::new(iroh),
+            endpoint_id,
+            author_id,
+            doc,
+            chat_doc,
+            config,
+        )
+        .await
+    }
 
+    /// Build the room's state around its open documents.
+    async fn assemble(
+        iroh: Arc<Iroh>,
+        endpoint_id: EndpointId,
+        author_id: AuthorId,
+        doc: Doc,
+        chat_doc: Option<Doc>,
+        config: &RoomConfig,
+    ) -> Result<Self> {
+        let room_key = match &config.secret {
+            Some(secret) => Some(Arc::new(RoomKey::derive(secret, &doc.id()).await?)),
+            None => None,
+        };
         Ok(Self {
-            host_disconnected: Arc::new(AtomicBool::new(false)),
+            watches: Arc::new(RoomWatches::default()),
+            time: Arc::new(RoomTime::default()),
+            last_activity: Arc::new(Mutex::new(Instant::now())),
+            heartbeats: Arc::new(Mutex::new(HashMap::new())),
+            rate_limits: config.rate_limits,
+            rate_buckets: Arc::new(Mutex::new(HashMap::new())),
+            next_action_seq: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+            pending_reveals: Arc::new(Mutex::new(HashMap::new())),
+            clock: Arc::new(LogicalClock::default()),
+            avatar_cache: Arc::new(Mutex::new(LruCache::new(AVATAR_CACHE_CAPACITY))),
+            content_cache: Arc::new(Mutex::new(LruCache::new(CONTENT_CACHE_CAPACITY))),
+            peers: Arc::new(tokio::sync::Mutex::new(None)),
+            state_blob_threshold: config.state_blob_threshold,
+            round_policy: config.rounds,
+            rounds: Arc::new(Mutex::new(Rounds::default())),
+            fetch_assets: config.fetch_assets,
+            compression: Arc::new(OnceLock::new()),
+            wire_format: Arc::new(OnceLock::new()),
+            room_key,
+            predictions: Arc::new(Mutex::new(Predictions::default())),
+            prediction_updated: Arc::new(tokio::sync::Notify::new()),
+            lockstep: Arc::new(OnceLock::new()),
+            lockstep_game: Arc::new(tokio::sync::Mutex::new(Lockstep::default())),
+            lockstep_updated: Arc::new(tokio::sync::Notify::new()),
+            ticked_state: Arc::new(Mutex::new(None)),
+            reconnected: Arc::new(tokio::sync::Notify::new()),
             phantom: PhantomData,
             endpoint_id,
             author_id,
             iroh: Some(iroh),
             doc,
+            chat_doc,
         })
     }
 
     pub(crate) fn iroh(&self) -> Result<&Iroh> {
-        self.iroh.as_ref().ok_or(anyhow!("Network layer missing"))
+        self.iroh.as_deref().ok_or(anyhow!("Network layer missing"))
+    }
+
+    /// Connection quality to the room's peers, leaving out the node's other connections.
+    pub async fn network_stats(&self) -> Result<Vec<PeerNetworkStats>> {
+        let peers = self.get_peer_list().await?;
+        let mut stats = self.iroh()?.network_stats();
+        stats.retain(|stats| peers.contains_key(&stats.peer));
+        Ok(stats)
+    }
+
+    /// How this endpoint reaches one of the room's peers.
+    pub async fn connection_info(&self, peer: &EndpointId) -> Result<PeerConnectionInfo> {
+        if !self.get_peer_list().await?.contains_key(peer) {
+            return Err(anyhow!("Peer has not joined the room"));
+        }
+        Ok(self.iroh()?.connection_info(*peer).await)
+    }
+
+    /// Open a stream on the game's own protocol `suffix` to the endpoint a peer is on.
+    pub async fn open_stream(&self, peer: &EndpointId, suffix: &str) -> Result<PeerStream> {
+        let Some(info) = self.get_peer_info(peer).await? else {
+            return Err(anyhow!("Peer has not joined the room"));
+        };
+        let endpoint = info.seat_of.unwrap_or(*peer);
+        if endpoint == self.endpoint_id {
+            return Err(anyhow!("Cannot open a stream to this endpoint"));
+        }
+        self.iroh()?.open_stream(endpoint, suffix).await
+    }
+
+    /// The path traffic takes to each of the room's peers with an open connection.
+    pub(crate) async fn connection_paths(&self) -> Result<HashMap<EndpointId, ConnectionPath>> {
+        let peers = self.get_peer_list().await?;
+        let mut paths = self.iroh()?.connection_paths();
+        paths.retain(|peer, _| peers.contains_key(peer));
+        Ok(paths)
+    }
+
+    /// Join the room's real-time channel, starting from the peers we already know about.
+    pub(crate) async fn join_realtime(&self) -> Result<GossipTopic> {
+        let mut bootstrap: Vec<EndpointId> = self
+            .get_peer_list()
+            .await?
+            .iter()
+            .filter(|(id, peer)| **id != self.endpoint_id && !peer.is_seat())
+            .map(|(id, _)| *id)
+            .collect();
+        if let Ok(host_id) = self.get_host_id().await
+            && host_id != self.endpoint_id
+            && !bootstrap.contains(&host_id)
+        {
+            bootstrap.push(host_id);
+        }
+        let topic = realtime_topic(self.doc.id());
+        self.iroh()?.subscribe_realtime(topic, bootstrap).await
+    }
+
+    /// Sign a real-time message as this endpoint.
+    pub(crate) fn realtime_message(&self, payload: RealtimePayload) -> Result<RealtimeMessage> {
+        RealtimeMessage::sign(self.iroh()?.endpoint().secret_key(), payload)
+    }
+
+    /// Get a shared handle to the node this room runs on.
+    pub(crate) fn node(&self) -> Result<Arc<Iroh>> {
+        self.iroh.clone().ok_or(anyhow!("Network layer missing"))
     }
 
     /// Convert entry to known data type
     pub async fn parse<T: DeserializeOwned>(&self, entry: &Entry) -> Result<T> {
-        self.iroh()?.get_content_as(entry).await
+        let bytes = self.content_bytes(entry).await?;
+        match entry.key() == KEY_ROOM_METADATA {
+            true => Ok(postcard::from_bytes(&bytes)?),
+            false => self.decode(&bytes),
+        }
+    }
+
+    /// Serialize a value in the room's [WireFormat].
+    pub(crate) fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>> {
+        self.wire_format().encode(value)
+    }
+
+    /// Deserialize a value that was written with [StateData::encode].
+    pub(crate) fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T> {
+        self.wire_format().decode(bytes)
     }
+
+    /// Compress a value with the room's [Compression], then encrypt it if the room has a
+    /// secret, before it is written under `key`.
+    pub(crate) fn pack(&self, key: &[u8], value: &[u8]) -> Result<Vec<u8>> {
+        let value = self.compression().encode(value)?;
+        match &self.room_key {
+            Some(room_key) => room_key.encrypt(key, &value),
+            None => Ok(value),
+        }
+    }
+
+    /// Decrypt and decompress a value that was written under `key` with [StateData::pack].
+    pub(crate) fn unpack(&self, key: &[u8], value: Bytes) -> Result<Bytes> {
+        let value = match &self.room_key {
+            Some(room_key) => room_key.decrypt(key, &value)?,
+            None => value,
+        };
+        self.compression().decode(value)
+    }
+
+    /// The fingerprint of the key the room's values are encrypted with, if it has a secret.
+    pub(crate) fn key_check(&self) -> Option<Hash> {
+        self.room_key.as_ref().map(|key| key.check())
+    }
+
+    /// Get an entry's value, decrypted and decompressed, see [StateData::unpack].
+    ///
+    /// Values are cached by entry key and content hash, once the room's compression is known.
+    pub(crate) async fn content_bytes(&self, entry: &Entry) -> Result<Bytes> {
+        if entry.key() == KEY_ROOM_METADATA {
+            return self.iroh()?.get_content_bytes(entry).await;
+        }
+        // The same content under another key is decrypted afresh, and fails unless it was
+        // written there.
+        let cache_key = (entry.key().to_vec(), entry.content_hash());
+        let cached = self
+            .content_cache
+            .lock()
+            .ok()
+            .and_then(|mut cache| cache.get(&cache_key).cloned());
+        if let Some(bytes) = cached {
+            return Ok(bytes);
+        }
+        let bytes = self.unpack(entry.key(), self.iroh()?.get_content_bytes(entry).await?)?;
+        if self.compression.get().is_some()
+            && let Ok(mut cache) = self.content_cache.lock()
+        {
+            cache.put(cache_key, bytes.clone());
+        }
+        Ok(bytes)
+    }
+
+    /// The compression used for the room's values.
+    pub(crate) fn compression(&self) -> Compression {
+        self.compression.get().copied().unwrap_or_default()
+    }
+
+    /// Use the room's compression, once the host has chosen it or we have read it from the
+    /// metadata. The first choice sticks for the lifetime of the room.
+    pub(crate) fn adopt_compression(&self, compression: Compression) {
+        let _ = self.compression.set(compression);
+    }
+    /// The format the room's values are serialized in.
+    pub(crate) fn wire_format(&self) -> WireFormat {
+        self.wire_format.get().copied().unwrap_or_default()
+    }
+
+    /// Use the room's wire format, as with [StateData::adopt_compression].
+    pub(crate) fn adopt_wire_format(&self, wire_format: WireFormat) {
+        let _ = self.wire_format.set(wire_format);
+    }
+
     /// Set the data into a paused state
     pub fn host_offline(&self) {
-        self.host_disconnected
-            .store(true, std::sync::atomic::Ordering::Relaxed);
+        trace::info!("Host went offline");
+        self.watches.set_host_online(false);
     }
     /// Set the data into a resumed state
     pub fn host_online(&self) {
-        self.host_disconnected
-            .store(false, std::sync::atomic::Ordering::Relaxed);
+        trace::debug!("Host is online");
+        self.watches.set_host_online(true);
     }
     /// Check if the data is in a paused state
     pub fn is_host_disconnected(&self) -> bool {
-        self.host_disconnected
-            .load(std::sync::atomic::Ordering::Relaxed)
+        !self.watches.is_host_online()
+    }
+    /// Record the latest app state the host wrote, for app state watchers.
+    pub(crate) fn app_state_synced(&self, app_state: AppState<G>) {
+        self.watches.set_app_state(app_state);
+    }
+    /// Watch the app state, which is paused while the host is offline.
+    pub(crate) fn watch_app_state(&self) -> watch::Receiver<AppState<G>> {
+        self.watches.subscribe_app_state()
+    }
+    /// Watch whether the host is connected.
+    pub(crate) fn watch_host_online(&self) -> watch::Receiver<bool> {
+        self.watches.subscribe_host_online()
+    }
+    /// Record whether the app is in use, returning what it was before.
+    pub(crate) fn set_activity(&self, activity: Activity) -> Activity {
+        self.watches.set_activity(activity)
+    }
+    /// Check whether the app is in use.
+    pub(crate) fn activity(&self) -> Activity {
+        self.watches.activity()
+    }
+    /// Watch whether the app is in use, for the event loop to follow.
+    pub(crate) fn watch_activity(&self) -> watch::Receiver<Activity> {
+        self.watches.subscribe_activity()
+    }
+    /// Sync the document with the online peers and wait for one of them to confirm it.
+    ///
+    /// Returns `true` once a peer finishes a successful sync, or `false` if the timeout
+    /// elapses first. Returns `true` straight away when there is no one else to sync with.
+    pub async fn wait_for_sync(&self, timeout: Duration) -> Result<bool> {
+        let peers: Vec<EndpointAddr> = self
+            .get_peer_list()
+            .await?
+            .values()
+            .filter(|peer| peer.id != self.endpoint_id && peer.status.is_online())
+            .map(|peer| EndpointAddr::from(peer.id))
+            .collect();
+        if peers.is_empty() {
+            return Ok(true);
+        }
+        let targets: Vec<EndpointId> = peers.iter().map(|addr| addr.id).collect();
+        let mut events = self.doc.subscribe().await?;
+        self.doc.start_sync(peers).await?;
+        let confirmed = tokio::time::timeout(timeout, async {
+            while let Some(event) = events.next().await {
+                if let Ok(LiveEvent::SyncFinished(SyncEvent {
+                    peer,
+                    result: Ok(_),
+                    ..
+                })) = event
+                    && targets.contains(&peer)
+                {
+                    return true;
+                }
+            }
+            false
+        })
+        .await;
+        Ok(confirmed.unwrap_or(false))
+    }
+
+    /// Stop syncing the room's documents with other peers.
+    pub async fn stop_sync(&self) -> Result<()> {
+        if let Some(chat_doc) = &self.chat_doc {
+            chat_doc.leave().await?;
+        }
+        self.doc.leave().await?;
+        Ok(())
+    }
+
+    /// Sync the documents with every other peer again, e.g. after [StateData::stop_sync] or
+    /// once the app comes back to the foreground.
+    pub(crate) async fn restart_sync(&self) -> Result<()> {
+        let peers: Vec<EndpointAddr> = self
+            .get_peer_list()
+            .await?
+            .values()
+            .filter(|peer| peer.id != self.endpoint_id && !peer.is_seat())
+            .map(|peer| EndpointAddr::from(peer.id))
+            .collect();
+        if let Some(chat_doc) = &self.chat_doc {
+            chat_doc.start_sync(peers.clone()).await?;
+        }
+        self.doc.start_sync(peers).await?;
+        Ok(())
+    }
+
+    /// Rebind the node after a network change, waiting up to `timeout` for its relay, and
+    /// have the event loop sync the room again.
+    pub(crate) async fn reconnect(&self, timeout: Duration) -> Result<()> {
+        self.iroh()?.reconnect(timeout).await;
+        self.reconnected.notify_one();
+        Ok(())
+    }
+
+    /// Wait until the room has to sync again: the app rebound the node, or one of its
+    /// addresses went away.
+    pub(crate) async fn network_changed(&self, changes: &mut (impl Stream<Item = ()> + Unpin)) {
+        tokio::select! {
+            _ = self.reconnected.notified() => {}
+            Some(()) = changes.next() => {}
+        }
+    }
+
+    /// Stop syncing the documents and shut down the Iroh node, unless another room shares it.
+    pub async fn shutdown(&self) -> Result<()> {
+        self.stop_sync().await?;
+        if let Some(chat_doc) = &self.chat_doc {
+            chat_doc.close().await?;
+        }
+        self.doc.close().await?;
+        match &self.iroh {
+            Some(iroh) if Arc::strong_count(iroh) > 1 => Ok(()),
+            Some(iroh) => iroh.as_ref().clone().shutdown().await,
+            None => Err(anyhow!("Network layer missing")),
+        }
+    }
+
+    /// Record that the game has just seen activity.
+    pub fn record_activity(&self) {
+        if let Ok(mut last_activity) = self.last_activity.lock() {
+            *last_activity = self.now();
+        }
+    }
+    /// Record that a peer's heartbeat has just arrived.
+    pub fn record_heartbeat(&self, peer_id: &EndpointId) {
+        if let Ok(mut heartbeats) = self.heartbeats.lock() {
+            heartbeats.insert(*peer_id, self.now());
+        }
+    }
+    /// Peers whose latest heartbeat arrived longer ago than `timeout`.
+    pub fn silent_peers(&self, timeout: Duration) -> Vec<EndpointId> {
+        self.heartbeats.lock().map_or_else(
+            |_| Vec::new(),
+            |heartbeats| {
+                heartbeats
+                    .iter()
+                    .filter(|(_, last_beat)| self.time_since(**last_beat) > timeout)
+                    .map(|(peer_id, _)| *peer_id)
+                    .collect()
+            },
+        )
+    }
+    /// How long it has been since the game last saw activity.
+    pub fn idle_for(&self) -> Duration {
+        self.last_activity
+            .lock()
+            .map_or(Duration::ZERO, |last_activity| {
+                self.time_since(*last_activity)
+            })
     }
     /// Regenerate the ticket with the latest node information
     pub async fn ticket(&self) -> Result<DocTicket> {
+        self.wait_until_dialable().await?;
         // Regenerate the ticket to include all current peer addresses.
-        let ticket = self.doc.share(ShareMode::Write, Self::ADDR_OPTIONS).await?;
+        let mut ticket = self.doc.share(ShareMode::Write, Self::ADDR_OPTIONS).await?;
+        ticket.nodes.extend(self.online_peer_addrs().await?);
         Ok(ticket)
     }
+    /// Regenerate the chat document ticket, if this room has chat enabled.
+    pub async fn chat_ticket(&self) -> Result<Option<DocTicket>> {
+        let Some(chat_doc) = &self.chat_doc else {
+            return Ok(None);
+        };
+        self.wait_until_dialable().await?;
+        let mut ticket = chat_doc.share(ShareMode::Write, Self::ADDR_OPTIONS).await?;
+        ticket.nodes.extend(self.online_peer_addrs().await?);
+        Ok(Some(ticket))
+    }
+    /// The addresses we know for every other online peer, so a joiner can sync from any of
+    /// them, e.g. while the host is away.
+    async fn online_peer_addrs(&self) -> Result<Vec<EndpointAddr>> {
+        let endpoint = self.iroh()?.endpoint();
+        let mut addrs = Vec::new();
+        for (id, peer) in self.get_peer_list().await?.iter() {
+            if *id == self.endpoint_id || peer.is_seat() || peer.status != PeerStatus::Online {
+                continue;
+            }
+            let Some(info) = endpoint.remote_info(*id).await else {
+                continue; // Not connected to them, so we know no better than the joiner
+            };
+            let addr =
+                EndpointAddr::from_parts(*id, info.into_addrs().map(|addr| addr.into_addr()));
+            if !addr.is_empty() {
+                addrs.push(addr);
+            }
+        }
+        Ok(addrs)
+    }
+    /// Make sure tickets will carry an address that peers can dial.
+    ///
+    /// Only LAN-only nodes need to wait; otherwise peers can always fall back to a relay.
+    async fn wait_until_dialable(&self) -> Result<()> {
+        let iroh = self.iroh()?;
+        if iroh.config().is_lan_only() {
+            iroh.wait_for_direct_addrs(DIRECT_ADDR_TIMEOUT).await?;
+        }
+        Ok(())
+    }
+    /// Get the chat document, or an error if chat is disabled for this room.
+    pub(crate) fn chat_doc(&self) -> Result<&Doc> {
+        self.chat_doc
+            .as_ref()
+            .ok_or(anyhow!("Chat is disabled for this room"))
+    }
 }
@@ -0,0 +1,70 @@
+//! Starting games from the lobby, and reopening the lobby once a game has finished.
+
+use super::*;
+use crate::{PeerMap, TeamId};
+
+impl<G: GameLogic> StateData<G> {
+    /// Assign roles and teams to the peers in the lobby and start the game, as the host.
+    ///
+    /// Fails if a player is not ready or the game refuses to start with these players.
+    pub(crate) async fn start_game(&self, logic: &G) -> Result<()> {
+        let mut players: PeerMap = self.get_peer_list().await?;
+        let roles: HashMap<EndpointId, G::PlayerRole> = logic.assign_roles(&players)?;
+        let teams: HashMap<EndpointId, TeamId> = logic.assign_teams(&players, &roles)?;
+        for (peer_id, peer) in players.iter_mut() {
+            peer.team = teams.get(peer_id).copied();
+            // Peers demoted in the lobby stay observers whatever role they were given.
+            peer.is_observer |= roles
+                .get(peer_id)
+                .is_some_and(|role| logic.is_observer_role(role));
+        }
+        if let Some(peer) = players.iter().find_map(|(peer_id, peer)| {
+            roles
+                .get(peer_id)
+                .filter(|_| !peer.is_observer && !peer.ready)
+                .map(|_| peer)
+        }) {
+            return Err(anyhow!("Peer {peer} is not ready"));
+        }
+        logic.validate_start(&players, &roles)?;
+        if self.is_lockstep()
+            && roles
+                .keys()
+                .filter(|id| players.get(*id).is_some_and(|peer| !peer.is_observer))
+                .count()
+                != 2
+        {
+            return Err(anyhow!("Lock-step games need exactly two players"));
+        }
+        let initial_state: G::GameState = logic.initial_state(&players, &roles)?;
+        if self.is_lockstep() && logic.canonical_state(&initial_state).is_none() {
+            return Err(anyhow!(
+                "Lock-step games need a canonical state, see GameLogic::canonical_state"
+            ));
+        }
+
+        for peer_id in roles.keys() {
+            let is_observer = players.get(peer_id).is_some_and(|peer| peer.is_observer);
+            self.set_peer_observer(peer_id, is_observer).await?;
+        }
+        for (peer_id, peer) in players.iter() {
+            let current = self.get_peer_info(peer_id).await?;
+            if current.is_some_and(|current| current.team != peer.team) {
+                self.set_peer_team(peer_id, peer.team).await?;
+            }
+        }
+
+        self.set_game_start(roles, &initial_state).await
+    }
+
+    /// Move a finished game's room back to the lobby, as the host, with every peer not ready,
+    /// so the next game starts once the players ready up again.
+    pub(crate) async fn reopen_lobby(&self) -> Result<()> {
+        for (peer_id, peer) in self.get_peer_list().await?.iter() {
+            if peer.ready {
+                self.set_peer_ready(peer_id, false).await?;
+            }
+        }
+        self.set_app_state(&AppState::Lobby).await
+    }
+}
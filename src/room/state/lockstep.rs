@@ -0,0 +1,317 @@
+//! Lock-step play without the host in the action path.
+//!
+//! In a lock-step room each player writes its actions to the game document as numbered steps,
+//! and every peer applies them to its own copy of the game, so a move reaches the other player
+//! in a single hop. Each step records digests of the state it was applied to and of the state
+//! it produced: a step written on top of a different history is ignored, and a step that
+//! produces a different state for us than for its sender means the game is not deterministic,
+//! which is reported as a divergence. When both players take the same step at once, the one
+//! with the lower ID keeps it and the other's action is rejected.
+
+use std::collections::VecDeque;
+
+use super::*;
+use actions::unique_id;
+use n0_future::StreamExt as _;
+
+/// A player's action, taken as the next step of a lock-step game.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct LockstepAction<A> {
+    id: String,
+    action: A,
+    /// Digest of the state the action was applied to.
+    base: Hash,
+    /// Digest of the state the action produced, as its sender computed it.
+    digest: Hash,
+}
+
+/// A change to this peer's copy of a lock-step game, waiting for the event loop to report it.
+pub(crate) enum LockstepUpdate<S> {
+    /// The game moved on to a new state.
+    Advanced(S),
+    /// The other player took the step one of our actions was written for first.
+    Overtaken {
+        player: EndpointId,
+        step: u64,
+        action_id: String,
+    },
+    /// A player's step produced a different state for us than for them.
+    Diverged { step: u64, peer: EndpointId },
+}
+
+/// One step of the game and the state it produced.
+struct Step<S> {
+    /// The player who took the step, `None` for the state the game started from.
+    player: Option<EndpointId>,
+    action_id: String,
+    state: S,
+    digest: Hash,
+}
+
+/// This peer's copy of a lock-step game.
+pub(crate) struct Lockstep<G: GameLogic> {
+    /// The number of the first step in `steps`.
+    start: u64,
+    /// Every step applied so far, starting with the state the host started the game from.
+    steps: Vec<Step<G::GameState>>,
+    /// Set once a step produced a different state for us, after which the game cannot go on.
+    diverged: bool,
+    /// Changes that have not been reported yet.
+    updates: VecDeque<LockstepUpdate<G::GameState>>,
+}
+
+impl<G: GameLogic> Default for Lockstep<G> {
+    fn default() -> Self {
+        Self {
+            start: 0,
+            steps: Vec::new(),
+            diverged: false,
+            updates: VecDeque::new(),
+        }
+    }
+}
+
+impl<G: GameLogic> Lockstep<G> {
+    /// The latest step, once the game has been started.
+    fn tip(&self) -> Option<&Step<G::GameState>> {
+        self.steps.last()
+    }
+
+    /// The number of the step after the latest one.
+    fn next_step(&self) -> u64 {
+        self.start + self.steps.len() as u64
+    }
+}
+
+impl<G: GameLogic> StateData<G> {
+    /// Whether players in this room apply each other's actions themselves, see
+    /// [crate::RoomConfig::lockstep].
+    pub(crate) fn is_lockstep(&self) -> bool {
+        self.lockstep.get().copied().unwrap_or_default()
+    }
+
+    /// Use lock-step play, once the host has chosen it or we have read it from the metadata.
+    pub(crate) fn adopt_lockstep(&self, lockstep: bool) {
+        let _ = self.lockstep.set(lockstep);
+    }
+
+    /// Take the next step of a lock-step game with one of this endpoint's players' actions.
+    ///
+    /// The step is applied straight away, so actions the game rejects are refused here
+    /// rather than sent. Returns the step's number.
+    pub(crate) async fn take_step(
+        &self,
+        logic: &G,
+        player: &EndpointId,
+        action: G::GameAction,
+    ) -> Result<u64> {
+        let mut game = self.lockstep_game.lock().await;
+        if !self.start_lockstep(logic, &mut game).await? {
+            return Err(anyhow!("No GameState found"));
+        }
+        if game.diverged {
+            return Err(anyhow!("The game has diverged between the players"));
+        }
+        let Some(tip) = game.tip() else {
+            return Err(anyhow!("No GameState found"));
+        };
+        let mut state = tip.state.clone();
+        logic
+            .apply_action(&mut state, player, &action)
+            .map_err(|e| anyhow!("Action rejected: {e}"))?;
+        let step = game.next_step();
+        let request = LockstepAction {
+            id: unique_id()?,
+            action,
+            base: tip.digest,
+            digest: digest_of(logic, &state)?,
+        };
+        self.set_bytes(&lockstep_key(step, player)?, &self.encode(&request)?)
+            .await?;
+        self.record_activity();
+        game.steps.push(Step {
+            player: Some(*player),
+            action_id: request.id,
+            state: state.clone(),
+            digest: request.digest,
+        });
+        game.updates.push_back(LockstepUpdate::Advanced(state));
+        self.lockstep_updated.notify_one();
+        self.finish_lockstep_game(logic, &game).await?;
+        Ok(step)
+    }
+
+    /// Apply every step that has synced since the last one we applied.
+    ///
+    /// With `arrived`, the number of a step that has just synced, a step we already applied is
+    /// first checked against it, in case the other player took that step first.
+    pub(crate) async fn advance_lockstep(&self, logic: &G, arrived: Option<u64>) -> Result<()> {
+        let mut game = self.lockstep_game.lock().await;
+        if !self.start_lockstep(logic, &mut game).await? || game.diverged {
+            return Ok(());
+        }
+        let mut changed = false;
+        if let Some(step) = arrived
+            && step > game.start
+            && step < game.next_step()
+        {
+            let index = (step - game.start) as usize;
+            let winner = self
+                .step_winner(step, &game.steps[index - 1].digest)
+                .await?;
+            let current = &game.steps[index];
+            if winner.is_some_and(|(player, request)| {
+                current.player != Some(player) || current.action_id != request.id
+            }) {
+                for (overtaken_step, overtaken) in (step..).zip(game.steps.split_off(index)) {
+                    let Some(player) = overtaken.player else {
+                        continue;
+                    };
+                    if self.is_local_player(&player).await? {
+                        game.updates.push_back(LockstepUpdate::Overtaken {
+                            player,
+                            step: overtaken_step,
+                            action_id: overtaken.action_id,
+                        });
+                    }
+                }
+                changed = true;
+            }
+        }
+
+        while let Some(tip) = game.tip() {
+            let step = game.next_step();
+            let Some((player, request)) = self.step_winner(step, &tip.digest).await? else {
+                break;
+            };
+            let mut state = tip.state.clone();
+            let applied = logic.apply_action(&mut state, &player, &request.action);
+            let digest = digest_of(logic, &state)?;
+            if applied.is_err() || digest != request.digest {
+                game.diverged = true;
+                game.updates
+                    .push_back(LockstepUpdate::Diverged { step, peer: player });
+                break;
+            }
+            game.steps.push(Step {
+                player: Some(player),
+                action_id: request.id,
+                state,
+                digest,
+            });
+            changed = true;
+        }
+
+        if changed && let Some(state) = game.tip().map(|tip| tip.state.clone()) {
+            self.record_activity();
+            game.updates.push_back(LockstepUpdate::Advanced(state));
+        }
+        if !game.updates.is_empty() {
+            self.lockstep_updated.notify_one();
+        }
+        self.finish_lockstep_game(logic, &game).await
+    }
+
+    /// Wait until there are changes to this peer's copy of a lock-step game to report.
+    pub(crate) async fn lockstep_updates(&self) -> Vec<LockstepUpdate<G::GameState>> {
+        self.lockstep_updated.notified().await;
+        self.lockstep_game.lock().await.updates.drain(..).collect()
+    }
+
+    /// The latest state of a lock-step game, once this peer has started stepping through it.
+    pub(crate) async fn lockstep_game_state(&self) -> Option<G::GameState> {
+        let game = self.lockstep_game.lock().await;
+        game.tip().map(|tip| tip.state.clone())
+    }
+
+    /// Start from the state the host last wrote, returning whether there is a game yet.
+    ///
+    /// The host only writes the state the game started from, and the final state, which
+    /// records the step that produced it.
+    async fn start_lockstep(&self, logic: &G, game: &mut Lockstep<G>) -> Result<bool> {
+        if !game.steps.is_empty() {
+            return Ok(true);
+        }
+        let Ok(entry) = self.get_game_state_entry().await else {
+            return Ok(false);
+        };
+        game.start = entry.applied_action.map_or(0, |(_, step)| step);
+        game.steps.push(Step {
+            player: entry.applied_action.map(|(player, _)| player),
+            action_id: String::new(),
+            digest: digest_of(logic, &entry.state)?,
+            state: entry.state,
+        });
+        Ok(true)
+    }
+
+    /// Find the action that takes `step` on top of the state with digest `base`.
+    ///
+    /// Only players' own actions count, and when both players took the step, the one with
+    /// the lower ID keeps it.
+    async fn step_winner(
+        &self,
+        step: u64,
+        base: &Hash,
+    ) -> Result<Option<(EndpointId, LockstepAction<G::GameAction>)>> {
+        let prefix = format!("{}{step}.", str::from_utf8(PREFIX_LOCKSTEP)?);
+        let query = self
+            .doc
+            .get_many(Query::single_latest_per_key().key_prefix(prefix.as_bytes()));
+        let mut entries = Box::pin(query.await?);
+        let mut winner: Option<(EndpointId, LockstepAction<G::GameAction>)> = None;
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            let player =
+                endpoint_id_from_str(&String::from_utf8_lossy(&entry.key()[prefix.len()..]))?;
+            if winner
+                .as_ref()
+                .is_some_and(|(current, _)| *current < player)
+                || !self.peer_author_matches(&player, &entry.author()).await?
+                || !self.has_player_on(&player).await?
+            {
+                continue;
+            }
+            let Ok(request) = self.parse::<LockstepAction<G::GameAction>>(&entry).await else {
+                continue; // Its content has not synced yet
+            };
+            if request.base == *base {
+                winner = Some((player, request));
+            }
+        }
+        Ok(winner)
+    }
+
+    /// Finish the game once the latest step decides it, if this peer is the host.
+    ///
+    /// The final state is written with the step that produced it, so a peer that opens the
+    /// room later carries on from there.
+    async fn finish_lockstep_game(&self, logic: &G, game: &Lockstep<G>) -> Result<()> {
+        let Some(tip) = game.tip() else {
+            return Ok(());
+        };
+        let Some(outcome) = logic.game_outcome(&tip.state) else {
+            return Ok(());
+        };
+        if !self.is_host().await? || !self.get_app_state().await?.is_in_game() {
+            return Ok(());
+        }
+        let step = game.next_step() - 1;
+        let applied_action = tip.player.map(|player| (player, step));
+        self.put_game_state(&tip.state, applied_action).await?;
+        self.finish_game(outcome).await
+    }
+}
+
+/// Build the document key a player's step is written under, e.g. "lockstep.<step>.<id>".
+fn lockstep_key(step: u64, player: &EndpointId) -> Result<Vec<u8>> {
+    Ok(format!("{}{step}.{player}", str::from_utf8(PREFIX_LOCKSTEP)?).into_bytes())
+}
+
+/// Digest a game state by its canonical encoding, see [GameLogic::canonical_state].
+fn digest_of<G: GameLogic>(logic: &G, state: &G::GameState) -> Result<Hash> {
+    logic
+        .canonical_state(state)
+        .map(Hash::new)
+        .ok_or_else(|| anyhow!("Lock-step games need a canonical state"))
+}
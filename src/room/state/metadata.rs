@@ -1,27 +1,81 @@
 //! Metadata describing the room's protocol and game type, used to detect incompatible clients.
 
+use iroh_blobs::Hash;
 use serde::{Deserialize, Serialize};
 
+use super::{Compression, WireFormat};
 use crate::GameLogic;
 
-/// Current protocol version. This should be incremented whenever a breaking change is made to the protocol.
-const PROTOCOL_VERSION: u32 = 1;
+/// Current protocol version, which every peer in a room speaks.
+///
+/// Increment it only when peers on the previous version can no longer read what this one
+/// writes. Changes to the layout of a game's state are versioned separately, by
+/// [GameLogic::STATE_VERSION], and rooms written with an older protocol can still be resumed.
+/// Version 2 covers every change to the entries since version 1 was released.
+pub(crate) const PROTOCOL_VERSION: u32 = 2;
 
 /// Metadata describing the room's protocol and game type.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct RoomMetadata {
     /// Protocol version, used to detect incompatible clients.
     pub protocol_version: u32,
-    /// The Rust type name of the game logic, used to detect incompatible clients.
+    /// The [GameLogic::game_type] of the game logic, used to detect incompatible clients.
     pub game_type: String,
+    /// How the room's other values are compressed, chosen by the host.
+    pub compression: Compression,
+    /// How the room's other values are serialized, chosen by the host.
+    pub wire_format: WireFormat,
+    /// Whether players apply each other's actions without the host, chosen by the host.
+    pub lockstep: bool,
+    /// A fingerprint of the key the room's other values are encrypted with, if they are.
+    pub key_check: Option<Hash>,
 }
 
 impl RoomMetadata {
-    /// Build metadata for the current game logic type.
-    pub fn for_game<G: GameLogic>() -> Self {
+    /// Build metadata for a room running `logic`.
+    pub fn for_game<G: GameLogic>(logic: &G) -> Self {
         Self {
             protocol_version: PROTOCOL_VERSION,
-            game_type: std::any::type_name::<G>().to_string(),
+            game_type: logic.game_type(),
+            compression: Compression::None,
+            wire_format: WireFormat::Postcard,
+            lockstep: false,
+            key_check: None,
         }
     }
+
+    /// Check whether peers on this build can join a room written with `protocol_version`.
+    ///
+    /// A peer reopening a room from its own store also accepts rooms written with an older
+    /// protocol, which its host brings up to date.
+    pub fn supports_protocol(&self, protocol_version: u32, resuming: bool) -> bool {
+        match resuming {
+            true => protocol_version <= self.protocol_version,
+            false => protocol_version == self.protocol_version,
+        }
+    }
+
+    /// Record the compression the room's values are written with.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Record the format the room's values are serialized in.
+    pub fn with_wire_format(mut self, wire_format: WireFormat) -> Self {
+        self.wire_format = wire_format;
+        self
+    }
+
+    /// Record whether the room is played in lock-step.
+    pub fn with_lockstep(mut self, lockstep: bool) -> Self {
+        self.lockstep = lockstep;
+        self
+    }
+
+    /// Record the fingerprint of the key the room's values are encrypted with.
+    pub fn with_key_check(mut self, key_check: Option<Hash>) -> Self {
+        self.key_check = key_check;
+        self
+    }
 }
@@ -0,0 +1,84 @@
+//! Versioned game states.
+//!
+//! Every stored game state records the [GameLogic::STATE_VERSION] of the build that wrote it,
+//! so a newer build can migrate it with [GameLogic::migrate_state] and an older build refuses
+//! it rather than misreading it.
+
+use super::*;
+use std::cmp::Ordering;
+
+/// A game state, serialized by a build with [GameLogic::STATE_VERSION] `version`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct VersionedState {
+    version: u32,
+    bytes: Vec<u8>,
+}
+
+impl<G: GameLogic> StateData<G> {
+    /// Tag a game state entry with this build's state version, ready to be encoded.
+    pub(crate) fn versioned(
+        &self,
+        state: &G::GameState,
+        applied_action: Option<(EndpointId, u64)>,
+    ) -> Result<GameStateEntry<VersionedState>> {
+        Ok(GameStateEntry {
+            state: VersionedState {
+                version: G::STATE_VERSION,
+                bytes: self.encode(state)?,
+            },
+            applied_action,
+        })
+    }
+
+    /// Read a decoded game state entry, migrating its state if an older build wrote it.
+    pub(crate) fn unversioned(
+        &self,
+        entry: GameStateEntry<VersionedState>,
+    ) -> Result<GameStateEntry<G::GameState>> {
+        let VersionedState { version, bytes } = entry.state;
+        let state = match version.cmp(&G::STATE_VERSION) {
+            Ordering::Equal => self.decode(&bytes)?,
+            Ordering::Less => G::migrate_state(version, &bytes).ok_or_else(|| {
+                anyhow!(
+                    "Game state version {version} cannot be migrated to version {}",
+                    G::STATE_VERSION
+                )
+            })?,
+            Ordering::Greater => {
+                return Err(anyhow!(
+                    "Game state version {version} is newer than this build's version {}",
+                    G::STATE_VERSION
+                ));
+            }
+        };
+        Ok(GameStateEntry {
+            state,
+            applied_action: entry.applied_action,
+        })
+    }
+
+    /// Write `bytes` as the game state, tagged with state `version`, as a build of the game
+    /// with that [GameLogic::STATE_VERSION] would have written it.
+    #[cfg(feature = "testing")]
+    pub(crate) async fn put_versioned_state(&self, version: u32, bytes: Vec<u8>) -> Result<()> {
+        let entry = GameStateEntry {
+            state: VersionedState { version, bytes },
+            applied_action: None,
+        };
+        let stored = StoredGameState::Inline(self.encode(&entry)?);
+        self.set_bytes(KEY_GAME_STATE, &self.encode(&stored)?).await
+    }
+
+    /// Encode a game state entry, tagged with this build's state version.
+    pub(crate) fn encode_state_entry(
+        &self,
+        entry: &GameStateEntry<G::GameState>,
+    ) -> Result<Vec<u8>> {
+        self.encode(&self.versioned(&entry.state, entry.applied_action)?)
+    }
+
+    /// Decode a game state entry written with [StateData::encode_state_entry].
+    pub(crate) fn decode_state_entry(&self, bytes: &[u8]) -> Result<GameStateEntry<G::GameState>> {
+        self.unversioned(self.decode(bytes)?)
+    }
+}
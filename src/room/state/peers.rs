@@ -0,0 +1,134 @@
+//! The room's peer list, mirrored in memory.
+//!
+//! Every peer entry event used to read the whole peer list back from the document, which adds
+//! up to a lot of reads while many peers join at once. Instead, the list is read in full once,
+//! then kept in step one peer at a time as this endpoint writes peer entries and the event
+//! loop receives them.
+
+use super::*;
+use crate::{LeaveReason, PeerInfo, PeerMap, PeerStatus};
+use n0_future::StreamExt as _;
+
+impl<G: GameLogic> StateData<G> {
+    /// Get list of peers in this Game Room.
+    pub async fn get_peer_list(&self) -> Result<PeerMap> {
+        let mut peers = self.mirrored_peer_list().await?;
+        if self.is_host_disconnected() {
+            // modify the host's status to indicate that they are offline
+            if let Ok(host_id) = self.get_host_id().await
+                && let Some(host) = peers.get_mut(&host_id)
+            {
+                host.status = PeerStatus::Offline;
+            }
+        }
+        Ok(peers)
+    }
+
+    /// The peer list as the peer entries have it, without marking a lost host offline.
+    pub(crate) async fn mirrored_peer_list(&self) -> Result<PeerMap> {
+        self.with_mirror(|peers| peers.clone()).await
+    }
+
+    /// A peer as the peer entries have it, if they are in the room.
+    pub(crate) async fn mirrored_peer(&self, peer_id: &EndpointId) -> Result<Option<PeerInfo>> {
+        self.with_mirror(|peers| peers.get(peer_id).cloned()).await
+    }
+
+    /// Bring the mirrored peer list up to date with a peer entry that has changed, returning
+    /// the peer the entry is for.
+    ///
+    /// The peer's latest entry is read back rather than taken from `key`'s event, as an entry
+    /// can arrive after a newer one for the same peer. If that fails, the list is read in full
+    /// next time.
+    pub(crate) async fn refresh_peer(&self, key: &[u8]) -> Option<EndpointId> {
+        let peer_id = peer_entry_id(key)?;
+        let mut mirror = self.peers.lock().await;
+        let Some(peers) = mirror.as_mut() else {
+            return Some(peer_id); // Nothing mirrored yet
+        };
+        match self.get_peer_info(&peer_id).await {
+            Ok(Some(peer_info)) => {
+                peers.insert(peer_id, peer_info);
+            }
+            Ok(None) => {
+                peers.remove(&peer_id);
+            }
+            Err(_) => *mirror = None,
+        }
+        Some(peer_id)
+    }
+
+    /// Why a peer left the room, if they said so when they last left and have not joined
+    /// again since.
+    pub(crate) async fn get_leave_reason(
+        &self,
+        peer_id: &EndpointId,
+    ) -> Result<Option<LeaveReason<G>>> {
+        let Some(quit) = self.latest_peer_entry(PREFIX_QUIT, peer_id).await? else {
+            return Ok(None);
+        };
+        if !self.peer_author_matches(peer_id, &quit.author()).await? {
+            return Ok(None);
+        }
+        if let Some(join) = self.latest_peer_entry(PREFIX_JOIN, peer_id).await?
+            && join.timestamp() > quit.timestamp()
+        {
+            return Ok(None);
+        }
+        Ok(Some(self.parse(&quit).await?))
+    }
+
+    /// The latest entry a peer wrote under one of the per-peer prefixes.
+    async fn latest_peer_entry(
+        &self,
+        prefix: &[u8],
+        peer_id: &EndpointId,
+    ) -> Result<Option<Entry>> {
+        let key = [prefix, peer_id.to_string().as_bytes()].concat();
+        let query = Query::single_latest_per_key().key_exact(key);
+        self.doc.get_one(query).await
+    }
+
+    /// Drop the mirrored peer list, so it is read in full next time, e.g. when entries may have
+    /// synced without an event.
+    pub(crate) async fn forget_peer_list(&self) {
+        *self.peers.lock().await = None;
+    }
+
+    /// Look at the mirrored peer list, reading it in full first if need be.
+    async fn with_mirror<T>(&self, look: impl FnOnce(&PeerMap) -> T) -> Result<T> {
+        let mut mirror = self.peers.lock().await;
+        let peers = match mirror.take() {
+            Some(peers) => peers,
+            None => self.load_peer_list().await?,
+        };
+        Ok(look(mirror.insert(peers)))
+    }
+
+    /// Read every peer entry in the document.
+    async fn load_peer_list(&self) -> Result<PeerMap> {
+        let query = self
+            .doc
+            .get_many(Query::single_latest_per_key().key_prefix(PREFIX_PEER));
+        let mut entries = Box::pin(query.await?);
+        let mut peers = PeerMap::default();
+        while let Some(entry_result) = entries.next().await {
+            let entry = entry_result?;
+            let peer_info: PeerInfo = match self.parse(&entry).await {
+                Ok(info) => info,
+                Err(_) => continue,
+            };
+            let Some(peer_id) = peer_entry_id(entry.key()) else {
+                continue;
+            };
+            peers.insert(peer_id, peer_info);
+        }
+        Ok(peers)
+    }
+}
+
+/// Read the peer ID from a peer entry's key.
+fn peer_entry_id(key: &[u8]) -> Option<EndpointId> {
+    let id = key.strip_prefix(PREFIX_PEER)?;
+    EndpointId::from_str(std::str::from_utf8(id).ok()?).ok()
+}
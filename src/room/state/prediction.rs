@@ -0,0 +1,201 @@
+//! Client-side prediction of the game state.
+//!
+//! With [crate::RoomConfig::predict] enabled, a peer applies its own actions to its view of the
+//! game as soon as it submits them, rather than waiting a round trip for the host. Each action
+//! stays pending until the host's state includes it or the host rejects it. Every state the
+//! host writes is replayed under the actions still pending, and if the result differs from what
+//! the peer was shown, the new view is reported as a correction.
+
+use std::{collections::VecDeque, sync::MutexGuard};
+
+use super::*;
+
+/// A change to this peer's predicted view, waiting for the event loop to report it.
+#[derive(Debug, Clone)]
+pub(crate) enum PredictionUpdate<S> {
+    /// A local action was applied ahead of the host.
+    Predicted(S),
+    /// The host's state disagreed with the view this peer was shown.
+    Corrected(S),
+}
+
+/// What to show after the host wrote a new game state.
+pub(crate) enum Reconciled<S> {
+    /// No actions were pending, so the host's state is shown as it is.
+    NotPredicting,
+    /// The host's state, with the actions still pending replayed on it, is what was shown.
+    Confirmed,
+    /// The view changed and should be replaced with this state.
+    Corrected(S),
+}
+
+/// An action this peer applied locally that the host has not settled yet.
+#[derive(Debug, Clone)]
+struct PendingAction<A> {
+    seat: EndpointId,
+    seq: u64,
+    action: A,
+}
+
+/// This peer's predicted view of the game and the actions it is built from.
+pub(crate) struct Predictions<G: GameLogic> {
+    /// Local actions the host has neither applied nor rejected yet, in submission order.
+    pending: Vec<PendingAction<G::GameAction>>,
+    /// The state last shown, with every pending action applied. `None` when nothing is pending.
+    view: Option<G::GameState>,
+    /// Changes to the view that have not been reported yet.
+    updates: VecDeque<PredictionUpdate<G::GameState>>,
+}
+
+impl<G: GameLogic> Default for Predictions<G> {
+    fn default() -> Self {
+        Self {
+            pending: Vec::new(),
+            view: None,
+            updates: VecDeque::new(),
+        }
+    }
+}
+
+impl<G: GameLogic> Predictions<G> {
+    /// Drop the pending actions the host has applied, given the action its latest state holds.
+    fn settle_applied(&mut self, applied: Option<(EndpointId, u64)>) {
+        if let Some((seat, seq)) = applied {
+            self.pending
+                .retain(|pending| pending.seat != seat || pending.seq > seq);
+        }
+    }
+
+    /// Replay the pending actions on a state from the host, dropping any that no longer apply.
+    ///
+    /// Returns the corrected view if it differs from the one last shown.
+    fn rebase(&mut self, logic: &G, authoritative: G::GameState) -> Option<G::GameState> {
+        let mut view = authoritative;
+        self.pending.retain(|pending| {
+            logic
+                .apply_action(&mut view, &pending.seat, &pending.action)
+                .is_ok()
+        });
+        let shown = self.view.take();
+        if !self.pending.is_empty() {
+            self.view = Some(view.clone());
+        }
+        let unchanged = shown.is_some_and(|shown| same_state::<G>(&shown, &view));
+        (!unchanged).then_some(view)
+    }
+}
+
+/// Compare two states by their encoding, as game states need not implement `PartialEq`.
+fn same_state<G: GameLogic>(a: &G::GameState, b: &G::GameState) -> bool {
+    match (postcard::to_stdvec(a), postcard::to_stdvec(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+impl<G: GameLogic> StateData<G> {
+    fn predictions(&self) -> MutexGuard<'_, Predictions<G>> {
+        // The predictions are never left half-updated, so a poisoned lock is still usable.
+        self.predictions
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Apply a submitted action to this peer's view of the game ahead of the host.
+    ///
+    /// Actions the game rejects locally are left for the host to reject.
+    pub(crate) async fn predict(
+        &self,
+        logic: &G,
+        seat: &EndpointId,
+        seq: u64,
+        action: G::GameAction,
+    ) -> Result<()> {
+        let authoritative = self.get_game_state_entry().await?;
+        let processed = self.last_processed_action_seq(seat).await?;
+        let mut predictions = self.predictions();
+        // The host may already have answered, if it was quicker than us.
+        if seq <= processed
+            || authoritative
+                .applied_action
+                .is_some_and(|(applied, applied_seq)| applied == *seat && seq <= applied_seq)
+        {
+            return Ok(());
+        }
+        let mut view = predictions.view.clone().unwrap_or(authoritative.state);
+        if logic.apply_action(&mut view, seat, &action).is_err() {
+            return Ok(());
+        }
+        predictions.pending.push(PendingAction {
+            seat: *seat,
+            seq,
+            action,
+        });
+        predictions.view = Some(view.clone());
+        predictions
+            .updates
+            .push_back(PredictionUpdate::Predicted(view));
+        self.prediction_updated.notify_one();
+        Ok(())
+    }
+
+    /// Reconcile this peer's predicted view with a game state the host wrote.
+    pub(crate) fn reconcile(
+        &self,
+        logic: &G,
+        entry: &GameStateEntry<G::GameState>,
+    ) -> Reconciled<G::GameState> {
+        let mut predictions = self.predictions();
+        if predictions.pending.is_empty() && predictions.view.is_none() {
+            return Reconciled::NotPredicting;
+        }
+        predictions.settle_applied(entry.applied_action);
+        match predictions.rebase(logic, entry.state.clone()) {
+            Some(view) => Reconciled::Corrected(view),
+            None => Reconciled::Confirmed,
+        }
+    }
+
+    /// Drop a pending action once the host has answered it, correcting the view if needed.
+    ///
+    /// Accepted actions are normally settled by the state that includes them, but that state may
+    /// have been overwritten before it synced, so the host's answer settles them too.
+    pub(crate) async fn settle_prediction(
+        &self,
+        logic: &G,
+        seat: &EndpointId,
+        seq: u64,
+    ) -> Result<()> {
+        {
+            let predictions = self.predictions();
+            if !predictions
+                .pending
+                .iter()
+                .any(|pending| pending.seat == *seat && pending.seq <= seq)
+            {
+                return Ok(());
+            }
+        }
+        let authoritative = self.get_game_state().await?;
+        let mut predictions = self.predictions();
+        predictions.settle_applied(Some((*seat, seq)));
+        if let Some(view) = predictions.rebase(logic, authoritative) {
+            predictions
+                .updates
+                .push_back(PredictionUpdate::Corrected(view));
+            self.prediction_updated.notify_one();
+        }
+        Ok(())
+    }
+
+    /// Wait until there are changes to this peer's predicted view to report.
+    pub(crate) async fn prediction_updates(&self) -> Vec<PredictionUpdate<G::GameState>> {
+        self.prediction_updated.notified().await;
+        self.predictions().updates.drain(..).collect()
+    }
+
+    /// This peer's view of the game with its pending actions applied, if any are pending.
+    pub(crate) fn predicted_game_state(&self) -> Option<G::GameState> {
+        self.predictions().view.clone()
+    }
+}
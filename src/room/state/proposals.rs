@@ -0,0 +1,151 @@
+//! Proposals that need the other players' approval.
+//!
+//! A player writes a `ProposalRequest` and the other players write their votes on it. The
+//! host counts the votes and writes a `ProposalOutcome` once everyone has agreed or anyone
+//! has refused. For takebacks, the host keeps the state from before the last applied action
+//! as an undo point.
+
+use super::*;
+use crate::{ProposalOutcome, ProposalRequest};
+use n0_future::StreamExt as _;
+use std::collections::HashSet;
+
+impl<G: GameLogic> StateData<G> {
+    /// Write a new proposal for the other players to vote on.
+    pub(crate) async fn write_proposal(&self, request: &ProposalRequest) -> Result<()> {
+        let key = [PREFIX_PROPOSAL, request.id.as_bytes()].concat();
+        self.set_bytes(&key, &self.encode(request)?).await
+    }
+
+    /// Vote on an open proposal.
+    pub(crate) async fn vote_on_proposal(&self, id: &str, accept: bool) -> Result<()> {
+        if self.get_proposal(id).await?.is_none() {
+            return Err(anyhow!("No proposal with id '{id}'"));
+        }
+        if self.get_proposal_outcome(id).await?.is_some() {
+            return Err(anyhow!("Proposal '{id}' has already been resolved"));
+        }
+        // e.g., "proposal_vote.<proposal id>.<voter id>"
+        let key = format!(
+            "{}{id}.{}",
+            str::from_utf8(PREFIX_PROPOSAL_VOTE)?,
+            self.endpoint_id
+        );
+        self.set_bytes(key.as_bytes(), &self.encode(&accept)?).await
+    }
+
+    /// Get an open or resolved proposal, if its author is the peer it claims to be from.
+    pub(crate) async fn get_proposal(&self, id: &str) -> Result<Option<ProposalRequest>> {
+        let key = [PREFIX_PROPOSAL, id.as_bytes()].concat();
+        let query = self
+            .doc
+            .get_one(Query::single_latest_per_key().key_exact(key));
+        let Some(entry) = query.await? else {
+            return Ok(None);
+        };
+        let request: ProposalRequest = self.parse(&entry).await?;
+        match self
+            .peer_author_matches(&request.from, &entry.author())
+            .await?
+        {
+            true => Ok(Some(request)),
+            false => Ok(None),
+        }
+    }
+
+    /// Get each vote cast on a proposal, keyed by voter.
+    pub(crate) async fn get_proposal_votes(&self, id: &str) -> Result<HashMap<EndpointId, bool>> {
+        let prefix = format!("{}{id}.", str::from_utf8(PREFIX_PROPOSAL_VOTE)?);
+        let query = Query::single_latest_per_key().key_prefix(prefix.as_bytes());
+        let mut entries = Box::pin(self.doc.get_many(query).await?);
+        let mut votes = HashMap::new();
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            let voter =
+                endpoint_id_from_str(&String::from_utf8_lossy(&entry.key()[prefix.len()..]))?;
+            if !self.peer_author_matches(&voter, &entry.author()).await? {
+                continue;
+            }
+            votes.insert(voter, self.parse::<bool>(&entry).await?);
+        }
+        Ok(votes)
+    }
+
+    /// Get how the host resolved a proposal, if it has.
+    pub(crate) async fn get_proposal_outcome(&self, id: &str) -> Result<Option<ProposalOutcome>> {
+        let key = [PREFIX_PROPOSAL_RESULT, id.as_bytes()].concat();
+        match self.get_host_authored_bytes(&key).await? {
+            Some(bytes) => Ok(Some(self.decode(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Record how a proposal was resolved.
+    pub(crate) async fn set_proposal_outcome(&self, outcome: &ProposalOutcome) -> Result<()> {
+        let key = [PREFIX_PROPOSAL_RESULT, outcome.id.as_bytes()].concat();
+        self.set_bytes(&key, &self.encode(outcome)?).await
+    }
+
+    /// The endpoints whose approval a proposal from `proposer` needs.
+    ///
+    /// Local seats are represented by the endpoint they sit on, and observers have no say.
+    pub(crate) async fn proposal_voters(
+        &self,
+        proposer: &EndpointId,
+    ) -> Result<HashSet<EndpointId>> {
+        Ok(self
+            .get_peer_list()
+            .await?
+            .iter()
+            .filter(|(_, peer)| !peer.is_observer)
+            .map(|(id, peer)| peer.seat_of.unwrap_or(*id))
+            .filter(|owner| owner != proposer)
+            .collect())
+    }
+
+    /// Check whether an endpoint has a player, of its own or seated on it, in the game.
+    pub(crate) async fn has_player_on(&self, endpoint: &EndpointId) -> Result<bool> {
+        Ok(self
+            .get_peer_list()
+            .await?
+            .iter()
+            .any(|(id, peer)| !peer.is_observer && peer.seat_of.unwrap_or(*id) == *endpoint))
+    }
+
+    /// Remember the game state from before an action, so a takeback can restore it.
+    pub(crate) async fn set_undo_point(
+        &self,
+        entry: Option<&GameStateEntry<G::GameState>>,
+    ) -> Result<()> {
+        let entry = entry
+            .map(|entry| self.versioned(&entry.state, entry.applied_action))
+            .transpose()?;
+        self.set_bytes(KEY_UNDO, &self.encode(&entry)?).await
+    }
+
+    /// Check whether there is an action that a takeback could undo.
+    pub(crate) async fn has_undo_point(&self) -> Result<bool> {
+        Ok(self.get_undo_point().await?.is_some())
+    }
+
+    /// Publish the game state from before the last applied action as the current one.
+    pub(crate) async fn restore_undo_point(&self) -> Result<()> {
+        let Some(entry) = self.get_undo_point().await? else {
+            return Err(anyhow!("There is no action to take back"));
+        };
+        self.record_activity();
+        self.put_game_state(&entry.state, entry.applied_action)
+            .await?;
+        self.set_undo_point(None).await
+    }
+
+    async fn get_undo_point(&self) -> Result<Option<GameStateEntry<G::GameState>>> {
+        match self.get_host_authored_bytes(KEY_UNDO).await? {
+            Some(bytes) => self
+                .decode::<Option<GameStateEntry<VersionedState>>>(&bytes)?
+                .map(|entry| self.unversioned(entry))
+                .transpose(),
+            None => Ok(None),
+        }
+    }
+}
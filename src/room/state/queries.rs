@@ -12,12 +12,20 @@
 //! of peers in the room.
 
 use super::*;
-use crate::{ChatMessage, GameLogic, PeerInfo, PeerMap, PeerStatus};
+use crate::{
+    Annotation, ChatFilter, ChatMessage, ChatReaction, GameLogic, GameResult, PeerInfo, RoomMeta,
+    RoomTicket, TurnManager, networking::room_tag_prefix, room::private,
+};
 use anyhow::Result;
+use iroh::EndpointAddr;
+use iroh_blobs::api::blobs::BlobStatus;
 use n0_future::StreamExt;
-use std::time::Duration;
+use std::{collections::HashSet, time::Duration};
 use tokio::time::{Instant, sleep};
 
+/// How often a joiner dials the ticket's peers again while waiting for the room to sync.
+const REDIAL_INTERVAL: Duration = Duration::from_secs(2);
+
 impl<G: GameLogic> StateData<G> {
     /// Check the document to see if we are the host
     pub async fn is_host(&self) -> Result<bool> {
@@ -45,14 +53,25 @@ impl<G: GameLogic> StateData<G> {
     }
 
     /// Get the AppState.
-    pub async fn get_app_state(&self) -> Result<AppState> {
+    pub async fn get_app_state(&self) -> Result<AppState<G>> {
         if self.is_host_disconnected() {
             return Ok(AppState::Paused);
         };
-        if let Some(bytes) = self.get_host_authored_bytes(KEY_APP_STATE).await? {
-            Ok(postcard::from_bytes(&bytes)?)
-        } else {
-            Err(anyhow::anyhow!("No AppState found"))
+        let mut latest: Option<Entry> = None;
+        for key in [KEY_APP_STATE, KEY_GAME_START, KEY_GAME_RESULT] {
+            if let Some(entry) = self.get_host_authored_entry(key).await?
+                && latest
+                    .as_ref()
+                    .is_none_or(|l| entry.timestamp() > l.timestamp())
+            {
+                latest = Some(entry);
+            }
+        }
+        match latest {
+            None => Err(anyhow::anyhow!("No AppState found")),
+            Some(entry) if entry.key() == KEY_GAME_START => Ok(AppState::InGame),
+            Some(entry) if entry.key() == KEY_GAME_RESULT => Ok(AppState::Finished),
+            Some(entry) => self.decode(&self.content_bytes(&entry).await?),
         }
     }
 
@@ -66,12 +85,43 @@ impl<G: GameLogic> StateData<G> {
     }
 
     /// Wait briefly for room metadata to sync, then validate it.
-    pub async fn wait_for_valid_room_metadata(&self, timeout: Duration) -> Result<()> {
-        let expected = RoomMetadata::for_game::<G>();
+    ///
+    /// A room being resumed from this node's store may have been written with an older
+    /// protocol, see [RoomMetadata::supports_protocol].
+    pub async fn wait_for_valid_room_metadata(
+        &self,
+        logic: &G,
+        timeout: Duration,
+        peers: &[EndpointAddr],
+        resuming: bool,
+    ) -> Result<()> {
+        let expected = RoomMetadata::for_game(logic);
         let deadline = Instant::now() + timeout;
+        let mut last_dial = Instant::now();
         loop {
             match self.get_room_metadata().await {
-                Ok(actual) if actual == expected => return Ok(()),
+                Ok(actual)
+                    if expected.supports_protocol(actual.protocol_version, resuming)
+                        && actual.game_type == expected.game_type =>
+                {
+                    match (actual.key_check, self.key_check()) {
+                        (None, None) => {}
+                        (Some(_), None) => {
+                            return Err(anyhow::anyhow!("Room is encrypted, a secret is needed"));
+                        }
+                        (None, Some(_)) => {
+                            return Err(anyhow::anyhow!("Room is not encrypted with a secret"));
+                        }
+                        (Some(expected), Some(check)) if expected != check => {
+                            return Err(anyhow::anyhow!("Wrong secret for this room"));
+                        }
+                        (Some(_), Some(_)) => {}
+                    }
+                    self.adopt_compression(actual.compression);
+                    self.adopt_wire_format(actual.wire_format);
+                    self.adopt_lockstep(actual.lockstep);
+                    return Ok(());
+                }
                 Ok(actual) => {
                     return Err(anyhow::anyhow!(
                         "Room metadata mismatch: expected protocol {} game '{}', got protocol {} game '{}'",
@@ -85,97 +135,551 @@ impl<G: GameLogic> StateData<G> {
                     if Instant::now() >= deadline {
                         return Err(err);
                     }
+                    // The first dial may have happened before the host was reachable.
+                    if last_dial.elapsed() >= REDIAL_INTERVAL {
+                        self.doc.start_sync(peers.to_vec()).await.ok();
+                        last_dial = Instant::now();
+                    }
                     sleep(Duration::from_millis(100)).await;
                 }
             }
         }
     }
 
+    /// Wait until the room's app state and host, and the game state once a game has started,
+    /// can be read locally, dialling `peers` again while waiting.
+    pub async fn wait_for_initial_sync(
+        &self,
+        timeout: Duration,
+        peers: &[EndpointAddr],
+    ) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+        let mut last_dial = Instant::now();
+        loop {
+            if self.has_initial_state().await {
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                return Err(anyhow::anyhow!("Timed out waiting for the room to sync"));
+            }
+            if last_dial.elapsed() >= REDIAL_INTERVAL {
+                self.doc.start_sync(peers.to_vec()).await.ok();
+                last_dial = Instant::now();
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    /// Check whether the room's app state and host, and the game state once a game has
+    /// started, can be read locally.
+    pub(crate) async fn has_initial_state(&self) -> bool {
+        match self.get_app_state().await {
+            Ok(AppState::Lobby) => self.get_host_id().await.is_ok(),
+            // A room closed from the lobby finishes without ever having a game state.
+            Ok(_) => {
+                self.get_host_id().await.is_ok()
+                    && (self.get_game_state().await.is_ok()
+                        || matches!(self.get_close_reason().await, Ok(Some(_))))
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Count the latest entries in the room's documents, and how many of them already have
+    /// their content stored locally, as `(done, total)`.
+    pub(crate) async fn sync_progress(&self) -> Result<(usize, usize)> {
+        let blobs = self.iroh()?.blobs();
+        let (mut done, mut total) = (0, 0);
+        for doc in std::iter::once(&self.doc).chain(&self.chat_doc) {
+            let mut entries = Box::pin(doc.get_many(Query::single_latest_per_key()).await?);
+            while let Some(entry) = entries.next().await {
+                let entry = entry?;
+                // Deletions leave an empty entry behind, with no content to fetch.
+                if entry.content_len() == 0 {
+                    continue;
+                }
+                total += 1;
+                if blobs.has(entry.content_hash()).await? {
+                    done += 1;
+                }
+            }
+        }
+        Ok((done, total))
+    }
+
     /// Get Game State.
     pub async fn get_game_state(&self) -> Result<G::GameState> {
-        if let Some(bytes) = self.get_host_authored_bytes(KEY_GAME_STATE).await? {
-            Ok(postcard::from_bytes(&bytes)?)
+        // Lock-step games move on without the host writing their state.
+        if self.is_lockstep()
+            && let Some(state) = self.lockstep_game_state().await
+        {
+            return Ok(state);
+        }
+        // So do the ticked states a host with a cadence only sends straight to the peers.
+        if let Some(state) = self.ticked_state() {
+            return Ok(state);
+        }
+        Ok(self.get_game_state_entry().await?.state)
+    }
+
+    /// Get the Game State along with the action that produced it.
+    pub(crate) async fn get_game_state_entry(&self) -> Result<GameStateEntry<G::GameState>> {
+        if let Some(start) = self.game_start_since(KEY_GAME_STATE).await? {
+            self.load_game_state(start.state).await
+        } else if let Some(bytes) = self.get_host_authored_bytes(KEY_GAME_STATE).await? {
+            self.load_game_state(self.decode(&bytes)?).await
         } else {
             Err(anyhow::anyhow!("No GameState found"))
         }
     }
 
-    /// Get list of peers in this Game Room.
-    pub async fn get_peer_list(&self) -> Result<PeerMap> {
-        let query = self
-            .doc
-            .get_many(Query::single_latest_per_key().key_prefix(PREFIX_PEER));
-        let mut entries = Box::pin(query.await?);
-        let mut peers = PeerMap::default();
-        while let Some(entry_result) = entries.next().await {
-            let entry = entry_result?;
-            let peer_info: PeerInfo = match self.iroh()?.get_content_as(&entry).await {
-                Ok(info) => info,
-                Err(_) => continue,
-            };
-            let key_str = String::from_utf8_lossy(entry.key());
-            let Some(id_str) = key_str.strip_prefix(std::str::from_utf8(PREFIX_PEER)?) else {
-                continue;
-            };
-            let Ok(peer_id) = EndpointId::from_str(id_str) else {
-                continue;
-            };
-            peers.insert(peer_id, peer_info);
-        }
-        if self.is_host_disconnected() {
-            // modify the host's status to indicate that they are offline
-            if let Ok(host_id) = self.get_host_id().await
-                && let Some(host) = peers.get_mut(&host_id)
-            {
-                host.status = PeerStatus::Offline;
+    /// Decode a stored game state, fetching it from the host first if it lives in a blob.
+    pub(crate) async fn load_game_state(
+        &self,
+        stored: StoredGameState,
+    ) -> Result<GameStateEntry<G::GameState>> {
+        let bytes = match stored {
+            StoredGameState::Inline(bytes) => Bytes::from(bytes),
+            StoredGameState::Blob { hash, len } => {
+                let host_id = self.get_host_id().await?;
+                let tag = self.game_state_tag();
+                let blob = self.iroh()?.fetch_named_blob(&tag, hash, host_id).await?;
+                let bytes = self.unpack(KEY_GAME_STATE, blob)?;
+                if bytes.len() as u64 != len {
+                    return Err(anyhow::anyhow!(
+                        "Game state blob is {} bytes, expected {len}",
+                        bytes.len()
+                    ));
+                }
+                bytes
             }
+        };
+        self.decode_state_entry(&bytes)
+    }
+
+    /// The name of one of the blob tags this room keeps, see [crate::StoredRoom].
+    pub(crate) fn room_tag(&self, name: &str) -> String {
+        format!("{}{name}", room_tag_prefix(&self.doc.id()))
+    }
+
+    /// The blob tag that keeps this room's latest game state, and only that one, stored.
+    pub(crate) fn game_state_tag(&self) -> String {
+        self.room_tag("game_state")
+    }
+
+    /// Get the final result recorded by the host, if the game has finished.
+    pub async fn get_game_result(&self) -> Result<Option<GameResult>> {
+        match self.get_host_authored_bytes(KEY_GAME_RESULT).await? {
+            Some(bytes) => Ok(Some(self.decode(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the roles the players were given for the current game, empty before it starts.
+    pub async fn get_roles(&self) -> Result<HashMap<EndpointId, G::PlayerRole>> {
+        if let Some(start) = self.game_start_since(KEY_ROLES).await? {
+            return Ok(start.roles);
+        }
+        match self.get_host_authored_bytes(KEY_ROLES).await? {
+            Some(bytes) => self.decode(&bytes),
+            None => Ok(HashMap::new()),
+        }
+    }
+
+    /// Get the turn order the host last published, if the game keeps one.
+    pub async fn get_turns(&self) -> Result<Option<TurnManager>> {
+        match self.get_host_authored_bytes(KEY_TURNS).await? {
+            Some(bytes) => self.decode(&bytes),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the ticket for the room the host created for the next game, if there is one.
+    pub async fn get_next_room(&self) -> Result<Option<RoomTicket>> {
+        match self.get_host_authored_bytes(KEY_NEXT_ROOM).await? {
+            Some(bytes) => Ok(Some(RoomTicket::parse(&String::from_utf8_lossy(&bytes))?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get why the host closed the room, if it has.
+    pub async fn get_close_reason(&self) -> Result<Option<String>> {
+        Ok(self
+            .get_host_authored_bytes(KEY_ROOM_CLOSED)
+            .await?
+            .map(|bytes| String::from_utf8_lossy(&bytes).to_string()))
+    }
+
+    /// Get the room's details as the host last published them.
+    pub async fn get_meta(&self) -> Result<Option<RoomMeta>> {
+        match self.get_host_authored_bytes(KEY_ROOM_META).await? {
+            Some(bytes) => Ok(Some(self.decode(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get when the current game started, as milliseconds since the Unix epoch.
+    pub(crate) async fn get_game_started_at(&self) -> Result<Option<u64>> {
+        if let Some(start) = self.game_start_since(KEY_GAME_STARTED_AT).await? {
+            return Ok(Some(start.started_at));
+        }
+        match self.get_host_authored_bytes(KEY_GAME_STARTED_AT).await? {
+            Some(bytes) => Ok(Some(self.decode(&bytes)?)),
+            None => Ok(None),
         }
-        Ok(peers)
     }
 
     /// Get a peer's Information from their endpointId, if they exist.
     pub async fn get_peer_info(&self, peer_id: &EndpointId) -> Result<Option<PeerInfo>> {
         let key = format!("{}{}", std::str::from_utf8(PREFIX_PEER)?, peer_id);
         if let Some(bytes) = self.get_bytes(key.as_bytes()).await? {
-            return Ok(Some(postcard::from_bytes(&bytes)?));
+            return Ok(Some(self.decode(&bytes)?));
         }
         Ok(None)
     }
+
+    /// Get the latest private payload the host sent one of this endpoint's players, if any.
+    pub async fn get_private_state(&self, player_id: &EndpointId) -> Result<Option<Bytes>> {
+        if !self.is_local_player(player_id).await? {
+            return Err(anyhow::anyhow!(
+                "Private payloads for {player_id} can only be read on its endpoint"
+            ));
+        }
+        let key = format!("{}{}", std::str::from_utf8(PREFIX_PRIVATE)?, player_id);
+        match self.get_host_authored_bytes(key.as_bytes()).await? {
+            Some(sealed) => Ok(Some(self.open_private(&sealed)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Open a private payload sealed to this endpoint.
+    pub(crate) fn open_private(&self, sealed: &[u8]) -> Result<Bytes> {
+        private::open(self.iroh()?.endpoint().secret_key(), sealed)
+    }
+
+    /// Get the players seated on an endpoint, not including the endpoint's own player.
+    pub async fn get_seats_of(&self, owner: &EndpointId) -> Result<Vec<EndpointId>> {
+        let peers = self.get_peer_list().await?;
+        Ok(peers
+            .values()
+            .filter(|peer| peer.seat_of.as_ref() == Some(owner))
+            .map(|peer| peer.id)
+            .collect())
+    }
+
+    /// Check whether a player is this endpoint's own player or one of its seats.
+    pub async fn is_local_player(&self, player_id: &EndpointId) -> Result<bool> {
+        if *player_id == self.endpoint_id {
+            return Ok(true);
+        }
+        Ok(self
+            .get_peer_info(player_id)
+            .await?
+            .is_some_and(|peer| peer.seat_of == Some(self.endpoint_id)))
+    }
+
+    /// Get the fully synced seat requests written by an endpoint's registered author.
+    pub(crate) async fn get_seat_requests(
+        &self,
+        owner: &EndpointId,
+    ) -> Result<Vec<(EndpointId, Entry)>> {
+        let Some(author_id) = self.get_peer_info(owner).await?.map(|peer| peer.author_id) else {
+            return Ok(Vec::new());
+        };
+        let query = self.doc.get_many(
+            Query::single_latest_per_key()
+                .key_prefix(PREFIX_SEAT)
+                .author(author_id),
+        );
+        let mut entries = Box::pin(query.await?);
+        let mut requests = Vec::new();
+        while let Some(entry_result) = entries.next().await {
+            let entry = entry_result?;
+            let Some(Ok(seat_id)) = entry.is_seat_request() else {
+                continue;
+            };
+            if self.iroh()?.blobs().has(entry.content_hash()).await? {
+                requests.push((seat_id, entry));
+            }
+        }
+        Ok(requests)
+    }
+
+    /// Get a peer's avatar image, if they have shared one.
+    ///
+    /// Images are downloaded from the peer's blob store on first use and cached afterwards.
+    /// An image larger than [MAX_AVATAR_BYTES] is an error, and is never read into memory.
+    pub async fn fetch_avatar(&self, peer_id: &EndpointId) -> Result<Option<Bytes>> {
+        let Some(hash) = self
+            .get_peer_info(peer_id)
+            .await?
+            .and_then(|peer| peer.profile.avatar_blob)
+        else {
+            return Ok(None);
+        };
+        if let Some(image) = self
+            .avatar_cache
+            .lock()
+            .ok()
+            .and_then(|mut cache| cache.get(&hash).cloned())
+        {
+            return Ok(Some(image));
+        }
+        let iroh = self.iroh()?;
+        iroh.download_blob(hash, vec![*peer_id]).await?;
+        if let BlobStatus::Complete { size } = iroh.blobs().status(hash).await?
+            && size > MAX_AVATAR_BYTES as u64
+        {
+            return Err(anyhow!(
+                "Avatar of {size} bytes is larger than the {MAX_AVATAR_BYTES} allowed"
+            ));
+        }
+        let image = iroh.blobs().get_bytes(hash).await?;
+        if let Ok(mut cache) = self.avatar_cache.lock() {
+            cache.put(hash, image.clone());
+        }
+        Ok(Some(image))
+    }
     /// Get a peer's name from their endpointId, if they exist.
     pub async fn get_peer_name(&self, peer_id: &EndpointId) -> Result<String> {
         let peer_info = self.get_peer_info(peer_id).await?;
         Ok(peer_info.map_or("unknown".to_string(), |peer| peer.profile.nickname))
     }
 
-    /// Get persisted chat messages for this room, ordered oldest to newest.
-    pub async fn get_chat_history(&self) -> Result<Vec<ChatMessage>> {
-        let query = self
-            .doc
-            .get_many(Query::single_latest_per_key().key_prefix(PREFIX_CHAT));
+    /// Apply the game's chat filter to a message, returning `None` if it is blocked.
+    pub(crate) async fn filter_chat(
+        &self,
+        logic: &G,
+        mut message: ChatMessage,
+    ) -> Result<Option<ChatMessage>> {
+        let Some(sender) = self.get_peer_info(&message.from).await? else {
+            return Ok(Some(message));
+        };
+        let phase = self.get_app_state().await.unwrap_or(AppState::Lobby);
+        Ok(match logic.filter_chat(&sender, &phase, &message) {
+            ChatFilter::Allow => Some(message),
+            ChatFilter::Block => None,
+            ChatFilter::Replace(text) => {
+                message.message = text;
+                Some(message)
+            }
+        })
+    }
+
+    /// Get persisted chat messages for this room that pass the game's chat filter,
+    /// ordered by the room's logical clock, oldest to newest.
+    pub async fn get_chat_history(&self, logic: &G) -> Result<Vec<ChatMessage>> {
+        let Some(chat_doc) = &self.chat_doc else {
+            return Ok(Vec::new());
+        };
+        // Every author's version of each key, so one written under a forged sender can't hide
+        // the real one.
+        let query = chat_doc.get_many(Query::all().key_prefix(PREFIX_CHAT));
         let mut entries = Box::pin(query.await?);
         let mut messages = Vec::new();
         while let Some(entry_result) = entries.next().await {
             let entry = entry_result?;
-            let message = match self.iroh()?.get_content_as::<ChatMessage>(&entry).await {
+            let message = match self.parse::<ChatMessage>(&entry).await {
                 Ok(message) => message,
                 Err(_) => continue,
             };
-            messages.push(message);
+            if !self.sender_wrote(&entry, message.from).await? || message.deleted {
+                continue;
+            }
+            if let Some(message) = self.filter_chat(logic, message).await? {
+                messages.push(message);
+            }
         }
-        messages.sort_by_key(|message| message.timestamp);
+        // Every peer sees the same order, whatever their wall clocks say.
+        messages.sort_by(|a, b| (a.clock, a.from, &a.id).cmp(&(b.clock, b.from, &b.id)));
         Ok(messages)
     }
 
-    /// Check whether an action request has already been processed.
-    pub(crate) async fn has_processed_action(
+    /// Get the latest version of a chat message by its ID.
+    pub async fn get_chat_message(&self, message_id: &str) -> Result<Option<ChatMessage>> {
+        let prefix = format!("{}{message_id}.", std::str::from_utf8(PREFIX_CHAT)?);
+        let query = self
+            .chat_doc()?
+            .get_one(Query::single_latest_per_key().key_prefix(prefix.as_bytes()));
+        Ok(match query.await? {
+            None => None,
+            Some(entry) => Some(self.parse(&entry).await?),
+        })
+    }
+
+    /// Check whether a chat message has been sent, without waiting for its content to sync.
+    pub async fn has_chat_message(&self, message_id: &str) -> Result<bool> {
+        let prefix = format!("{}{message_id}.", std::str::from_utf8(PREFIX_CHAT)?);
+        let query = self
+            .chat_doc()?
+            .get_one(Query::single_latest_per_key().key_prefix(prefix.as_bytes()));
+        Ok(query.await?.is_some())
+    }
+
+    /// Get every reaction left on a chat message.
+    pub async fn get_chat_reactions(&self, message_id: &str) -> Result<Vec<ChatReaction>> {
+        let prefix = format!(
+            "{}{message_id}.",
+            std::str::from_utf8(PREFIX_CHAT_REACTION)?
+        );
+        let query = self
+            .chat_doc()?
+            .get_many(Query::all().key_prefix(prefix.as_bytes()));
+        let mut entries = Box::pin(query.await?);
+        let mut reactions = Vec::new();
+        while let Some(entry_result) = entries.next().await {
+            let entry = entry_result?;
+            if let Ok(reaction) = self.parse::<ChatReaction>(&entry).await
+                && self.sender_wrote(&entry, reaction.from).await?
+            {
+                reactions.push(reaction);
+            }
+        }
+        Ok(reactions)
+    }
+
+    /// Check that a chat entry is keyed by the peer it claims to be from, and written by them.
+    async fn sender_wrote(&self, entry: &Entry, from: EndpointId) -> Result<bool> {
+        let keyed = match entry.is_chat_message().or_else(|| entry.is_chat_reaction()) {
+            Some(Ok(node_id)) => node_id == from,
+            _ => false,
+        };
+        Ok(keyed && self.peer_author_matches(&from, &entry.author()).await?)
+    }
+
+    /// Get persisted annotations, optionally only those for one action, ordered oldest to newest.
+    pub async fn get_annotations(&self, action_id: Option<&str>) -> Result<Vec<Annotation>> {
+        let prefix = match action_id {
+            Some(action_id) => format!("{}{action_id}.", std::str::from_utf8(PREFIX_ANNOTATION)?),
+            None => std::str::from_utf8(PREFIX_ANNOTATION)?.to_string(),
+        };
+        let query = self
+            .doc
+            .get_many(Query::single_latest_per_key().key_prefix(prefix.as_bytes()));
+        let mut entries = Box::pin(query.await?);
+        let mut annotations = Vec::new();
+        while let Some(entry_result) = entries.next().await {
+            let entry = entry_result?;
+            let annotation = match self.parse::<Annotation>(&entry).await {
+                Ok(annotation) => annotation,
+                Err(_) => continue,
+            };
+            // Only trust annotations written by the peer they claim to be from.
+            if !self
+                .peer_author_matches(&annotation.from, &entry.author())
+                .await?
+            {
+                continue;
+            }
+            annotations.push(annotation);
+        }
+        annotations.sort_by_key(|annotation| annotation.timestamp);
+        Ok(annotations)
+    }
+
+    /// Get the latest value of a game-defined entry, once it has fully synced.
+    pub async fn get_custom(&self, key: &[u8]) -> Result<Option<Bytes>> {
+        if is_engine_key(key) {
+            return Err(anyhow::anyhow!(
+                "Key '{}' is reserved by the engine",
+                String::from_utf8_lossy(key)
+            ));
+        }
+        let query = self
+            .doc
+            .get_one(Query::single_latest_per_key().key_exact(key));
+        let Some(entry) = query.await? else {
+            return Ok(None);
+        };
+        if !self.iroh()?.blobs().has(entry.content_hash()).await? {
+            return Ok(None);
+        }
+        Ok(Some(self.content_bytes(&entry).await?))
+    }
+
+    /// The highest action sequence number the host has processed for a peer, or 0 if none.
+    pub(crate) async fn last_processed_action_seq(&self, peer_id: &EndpointId) -> Result<u64> {
+        self.max_action_seq(PREFIX_PROCESSED_ACTION, peer_id, None)
+            .await
+    }
+
+    /// The highest action sequence number we have submitted for a peer, or 0 if none.
+    ///
+    /// Only our own entries are considered, as anyone could write a request under a higher one.
+    pub(crate) async fn last_submitted_action_seq(&self, peer_id: &EndpointId) -> Result<u64> {
+        self.max_action_seq(PREFIX_ACTION, peer_id, Some(self.author_id))
+            .await
+    }
+
+    /// Every peer that has submitted at least one action request.
+    pub(crate) async fn get_action_requesters(&self) -> Result<HashSet<EndpointId>> {
+        let query = self
+            .doc
+            .get_many(Query::single_latest_per_key().key_prefix(PREFIX_ACTION));
+        let mut entries = Box::pin(query.await?);
+        let mut requesters = HashSet::new();
+        while let Some(entry_result) = entries.next().await {
+            if let Some(Ok((peer_id, _seq))) = entry_result?.is_action_request() {
+                requesters.insert(peer_id);
+            }
+        }
+        Ok(requesters)
+    }
+
+    /// Get a peer's action request with a given sequence number, if it has fully synced.
+    ///
+    /// Only entries written by the peer's registered author are considered.
+    pub(crate) async fn get_action_request(
         &self,
         peer_id: &EndpointId,
-        action_id: &str,
-    ) -> Result<bool> {
-        Ok(self
-            .get_bytes(&actions::processed_action_key(peer_id, action_id)?)
+        seq: u64,
+    ) -> Result<Option<Entry>> {
+        let Some(author_id) = self
+            .get_peer_info(peer_id)
             .await?
-            .is_some())
+            .map(|peer| peer.author_id)
+        else {
+            return Ok(None);
+        };
+        let key = format!("{}{peer_id}.{seq}", std::str::from_utf8(PREFIX_ACTION)?);
+        let query = self
+            .doc
+            .get_one(Query::key_exact(key.as_bytes()).author(author_id));
+        let Some(entry) = query.await? else {
+            return Ok(None);
+        };
+        if !self.iroh()?.blobs().has(entry.content_hash()).await? {
+            return Ok(None);
+        }
+        Ok(Some(entry))
+    }
+
+    /// Find the highest sequence number among a peer's entries under an action prefix,
+    /// optionally only those written by `author`.
+    async fn max_action_seq(
+        &self,
+        prefix: &[u8],
+        peer_id: &EndpointId,
+        author: Option<AuthorId>,
+    ) -> Result<u64> {
+        let prefix = format!("{}{peer_id}.", std::str::from_utf8(prefix)?);
+        let query = Query::single_latest_per_key().key_prefix(prefix.as_bytes());
+        let query = match author {
+            Some(author) => query.author(author),
+            None => query,
+        };
+        let query = self.doc.get_many(query);
+        let mut entries = Box::pin(query.await?);
+        let mut max_seq = 0;
+        while let Some(entry_result) = entries.next().await {
+            let entry = entry_result?;
+            let seq = std::str::from_utf8(&entry.key()[prefix.len()..])
+                .ok()
+                .and_then(|seq| seq.parse().ok())
+                .unwrap_or(0);
+            max_seq = max_seq.max(seq);
+        }
+        Ok(max_seq)
     }
 
     /// Check whether an entry author matches the registered peer author.
@@ -211,7 +715,9 @@ impl<G: GameLogic> StateData<G> {
         let mut candidates: Vec<_> = peers
             .iter()
             .filter(|(id, peer)| {
-                excluding.is_none_or(|excluded| *id != excluded) && logic.can_host(peer)
+                excluding.is_none_or(|excluded| *id != excluded)
+                    && !peer.is_seat()
+                    && logic.can_host(peer)
             })
             .map(|(id, _)| *id)
             .collect();
@@ -228,14 +734,26 @@ impl<G: GameLogic> StateData<G> {
             .get_one(Query::single_latest_per_key().key_exact(key));
         Ok(match query.await? {
             None => None,
-            Some(entry) => Some(self.iroh()?.get_content_bytes(&entry).await?),
+            Some(entry) => Some(self.content_bytes(&entry).await?),
         })
     }
 
     /// Get the latest bytes for a key written by the current host.
-    async fn get_host_authored_bytes(&self, key: &[u8]) -> Result<Option<Bytes>> {
+    pub(super) async fn get_host_authored_bytes(&self, key: &[u8]) -> Result<Option<Bytes>> {
+        match self.get_host_authored_entry(key).await? {
+            Some(entry) => Ok(Some(self.content_bytes(&entry).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the latest entry for a key written by the current host.
+    async fn get_host_authored_entry(&self, key: &[u8]) -> Result<Option<Entry>> {
+        let latest_any = async || {
+            let query = Query::single_latest_per_key().key_exact(key);
+            self.doc.get_one(query).await
+        };
         let Some(host_author) = self.get_host_author_id().await? else {
-            return self.get_bytes(key).await;
+            return latest_any().await;
         };
         let query = self.doc.get_many(Query::key_exact(key));
         let mut entries = Box::pin(query.await?);
@@ -252,14 +770,29 @@ impl<G: GameLogic> StateData<G> {
                 latest = Some(entry);
             }
         }
-        Ok(match latest {
-            Some(entry) => Some(self.iroh()?.get_content_bytes(&entry).await?),
-            None => self.get_bytes(key).await?,
-        })
+        match latest {
+            Some(entry) => Ok(Some(entry)),
+            None => latest_any().await,
+        }
+    }
+
+    /// Get the host's game start entry, if the host wrote it after its latest entry for `key`.
+    ///
+    /// See [GameStart] for why the keys it covers are read this way.
+    async fn game_start_since(&self, key: &[u8]) -> Result<Option<GameStart<G::PlayerRole>>> {
+        let Some(start) = self.get_host_authored_entry(KEY_GAME_START).await? else {
+            return Ok(None);
+        };
+        if let Some(latest) = self.get_host_authored_entry(key).await?
+            && latest.timestamp() > start.timestamp()
+        {
+            return Ok(None);
+        }
+        Ok(Some(self.decode(&self.content_bytes(&start).await?)?))
     }
 
     /// Get the registered document author for the current host, if known.
-    async fn get_host_author_id(&self) -> Result<Option<AuthorId>> {
+    pub(super) async fn get_host_author_id(&self) -> Result<Option<AuthorId>> {
         let Ok(host_id) = self.get_host_id().await else {
             return Ok(None);
         };
@@ -0,0 +1,106 @@
+//! Host-side rate limits on what peers write.
+//!
+//! Every entry a peer writes lands in the document whether the host likes it or not, so the
+//! host keeps a token bucket per peer and kind of entry, and drops whatever goes over.
+
+use super::*;
+use crate::{RateLimit, RateLimits};
+use std::sync::PoisonError;
+
+/// The kinds of entries that are rate limited, see [RateLimits].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum WriteKind {
+    Action,
+    Chat,
+}
+
+impl WriteKind {
+    fn limit(&self, limits: &RateLimits) -> Option<RateLimit> {
+        match self {
+            WriteKind::Action => limits.actions,
+            WriteKind::Chat => limits.chat,
+        }
+    }
+}
+
+/// The entries a peer may still write of one kind.
+#[derive(Debug)]
+pub(crate) struct TokenBucket {
+    tokens: f64,
+    filled_at: Instant,
+    /// Set while the peer's writes are being dropped.
+    limited: bool,
+    /// Set when the peer went over the limit, until the host has reported it.
+    unreported: bool,
+}
+
+impl TokenBucket {
+    fn full(limit: &RateLimit, now: Instant) -> Self {
+        Self {
+            tokens: limit.burst as f64,
+            filled_at: now,
+            limited: false,
+            unreported: false,
+        }
+    }
+
+    /// Take a token if there is one, refilling for the time since the last write.
+    fn take(&mut self, limit: &RateLimit, now: Instant) -> bool {
+        let earned = match limit.refill.is_zero() {
+            true => limit.burst as f64,
+            false => {
+                now.saturating_duration_since(self.filled_at).as_secs_f64()
+                    / limit.refill.as_secs_f64()
+            }
+        };
+        self.tokens = (self.tokens + earned).min(limit.burst as f64);
+        self.filled_at = now;
+        if self.tokens < 1.0 {
+            self.unreported |= !self.limited;
+            self.limited = true;
+            return false;
+        }
+        self.tokens -= 1.0;
+        self.limited = false;
+        true
+    }
+}
+
+impl<G: GameLogic> StateData<G> {
+    /// Check whether a peer may write another entry of this kind, using up one of its tokens.
+    ///
+    /// Only the host limits writes, and never those of its own players.
+    pub(crate) async fn within_rate_limit(
+        &self,
+        kind: WriteKind,
+        peer_id: &EndpointId,
+    ) -> Result<bool> {
+        let Some(limit) = kind.limit(&self.rate_limits) else {
+            return Ok(true);
+        };
+        if !self.is_host().await? || self.is_local_player(peer_id).await? {
+            return Ok(true);
+        }
+        let now = self.now();
+        // A bucket is only ever left a little over- or under-filled, so a poisoned lock is
+        // still usable.
+        let mut buckets = self
+            .rate_buckets
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        Ok(buckets
+            .entry((kind, *peer_id))
+            .or_insert_with(|| TokenBucket::full(&limit, now))
+            .take(&limit, now))
+    }
+
+    /// Check whether a peer has gone over a rate limit since this was last asked, so the
+    /// host reports each run of dropped writes once.
+    pub(crate) fn take_rate_limit_report(&self, kind: WriteKind, peer_id: &EndpointId) -> bool {
+        self.rate_buckets
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_mut(&(kind, *peer_id))
+            .is_some_and(|bucket| std::mem::take(&mut bucket.unreported))
+    }
+}
@@ -0,0 +1,129 @@
+//! Simultaneous rounds, collected by the host.
+//!
+//! With [crate::RoomConfig::rounds], the host holds each player's next action rather than
+//! applying it. Held requests stay in the document unprocessed until their round closes, so a
+//! host that restarts, or a peer that is elected host, collects them again from the journal.
+
+use super::*;
+use std::{collections::BTreeMap, sync::MutexGuard, time::Duration};
+
+/// An action held for the round being collected.
+#[derive(Debug)]
+pub(crate) struct HeldAction<A> {
+    pub(crate) id: String,
+    pub(crate) seq: u64,
+    pub(crate) action: A,
+}
+
+/// The round the host is collecting.
+#[derive(Debug)]
+pub(crate) struct Round<A> {
+    pub(crate) id: u64,
+    opened_at: Instant,
+    pub(crate) actions: BTreeMap<EndpointId, HeldAction<A>>,
+}
+
+/// The host's rounds: the last one closed, once known, and the one being collected.
+#[derive(Debug)]
+pub(crate) struct Rounds<A> {
+    last: Option<u64>,
+    open: Option<Round<A>>,
+}
+
+impl<A> Default for Rounds<A> {
+    fn default() -> Self {
+        Self {
+            last: None,
+            open: None,
+        }
+    }
+}
+
+impl<G: GameLogic> StateData<G> {
+    fn rounds(&self) -> MutexGuard<'_, Rounds<G::GameAction>> {
+        // Rounds are never left half-updated, so a poisoned lock is still usable.
+        self.rounds
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Whether the host collects actions into rounds rather than applying them as they come.
+    pub(crate) async fn collects_rounds(&self) -> bool {
+        self.round_policy.is_some()
+            && !self.is_lockstep()
+            && self
+                .get_app_state()
+                .await
+                .is_ok_and(|state| state.is_in_game())
+    }
+
+    /// Hold a player's action for the round being collected, returning whether it was held.
+    ///
+    /// A player with an action in the round already waits for the next one.
+    pub(crate) async fn hold_round_action(
+        &self,
+        player: &EndpointId,
+        request: ActionRequest<G::GameAction>,
+    ) -> Result<bool> {
+        // A new host carries on numbering after the last round in the action log.
+        let known = self.rounds().last.is_some();
+        let logged = match known {
+            true => 0,
+            false => self.last_logged_round().await?,
+        };
+        let mut rounds = self.rounds();
+        let last = *rounds.last.get_or_insert(logged);
+        let now = self.now();
+        let round = rounds.open.get_or_insert_with(|| Round {
+            id: last + 1,
+            opened_at: now,
+            actions: BTreeMap::new(),
+        });
+        if round.actions.contains_key(player) {
+            return Ok(false);
+        }
+        let held = HeldAction {
+            id: request.id,
+            seq: request.seq,
+            action: request.action,
+        };
+        round.actions.insert(*player, held);
+        Ok(true)
+    }
+
+    /// Whether the round being collected has an action from every online player, or has
+    /// passed its deadline.
+    pub(crate) async fn round_ready(&self) -> Result<bool> {
+        let opened_at = match &self.rounds().open {
+            Some(round) => round.opened_at,
+            None => return Ok(false),
+        };
+        if self
+            .round_deadline()
+            .is_some_and(|deadline| self.time_since(opened_at) >= deadline)
+        {
+            return Ok(true);
+        }
+        let players = self.get_peer_list().await?;
+        let rounds = self.rounds();
+        let Some(round) = &rounds.open else {
+            return Ok(false);
+        };
+        Ok(players.iter().all(|(id, peer)| {
+            peer.is_observer || !peer.status.is_online() || round.actions.contains_key(id)
+        }))
+    }
+
+    /// Close the round being collected, taking its actions.
+    pub(crate) fn close_round(&self) -> Option<Round<G::GameAction>> {
+        let mut rounds = self.rounds();
+        let round = rounds.open.take()?;
+        rounds.last = Some(round.id);
+        Some(round)
+    }
+
+    /// The deadline rounds close at, if the host collects rounds with one.
+    pub(crate) fn round_deadline(&self) -> Option<Duration> {
+        self.round_policy.and_then(|policy| policy.deadline)
+    }
+}
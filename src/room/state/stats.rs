@@ -0,0 +1,86 @@
+//! Per-player statistics for the current game.
+//!
+//! Stats are not counted as the game goes, but worked out when asked for: actions and think
+//! time from the action log, chat counts from the chat document, and disconnects from the
+//! `disconnect.<player>.<millis>` entries the host writes when a player drops out mid-game.
+//! Anything from before the game started is left out.
+
+use super::*;
+use crate::{ChatMessage, MatchPlayerStats, MatchStats};
+use n0_future::StreamExt as _;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+impl<G: GameLogic> StateData<G> {
+    /// Record that a player dropped out of the game being played, as the host.
+    pub(crate) async fn record_disconnect(&self, player: &EndpointId) -> Result<()> {
+        if !self.get_app_state().await?.is_in_game() {
+            return Ok(());
+        }
+        let millis = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+        let key = format!(
+            "{}{player}.{millis}",
+            std::str::from_utf8(PREFIX_DISCONNECT)?
+        );
+        self.set_bytes(key.as_bytes(), &self.encode(&millis)?).await
+    }
+
+    /// Work out how each player has taken part in the current game, or the last one played.
+    pub(crate) async fn get_match_stats(&self) -> Result<MatchStats> {
+        let started_at = self.get_game_started_at().await?.unwrap_or_default();
+        let mut stats = MatchStats::default();
+        for (id, peer) in self.get_peer_list().await?.iter() {
+            if !peer.is_observer {
+                stats.players.insert(*id, MatchPlayerStats::default());
+            }
+        }
+
+        let mut previous = started_at;
+        for applied in self.get_action_log(..).await? {
+            if applied.applied_at < started_at {
+                continue;
+            }
+            let player = stats.players.entry(applied.player).or_default();
+            player.actions += 1;
+            player.think_time += Duration::from_millis(applied.applied_at.saturating_sub(previous));
+            stats.actions += 1;
+            previous = applied.applied_at;
+        }
+
+        if let Some(chat_doc) = &self.chat_doc {
+            let query = chat_doc.get_many(Query::single_latest_per_key().key_prefix(PREFIX_CHAT));
+            let mut entries = Box::pin(query.await?);
+            while let Some(entry_result) = entries.next().await {
+                let Ok(message) = self.parse::<ChatMessage>(&entry_result?).await else {
+                    continue;
+                };
+                if message.deleted || message.timestamp < started_at {
+                    continue;
+                }
+                stats.players.entry(message.from).or_default().chat_messages += 1;
+            }
+        }
+
+        if let Some(host_author) = self.get_host_author_id().await? {
+            let query = Query::key_prefix(PREFIX_DISCONNECT).author(host_author);
+            let mut entries = Box::pin(self.doc.get_many(query).await?);
+            while let Some(entry_result) = entries.next().await {
+                let entry = entry_result?;
+                let Some(player) = disconnected_player(entry.key()) else {
+                    continue;
+                };
+                let millis: u64 = self.parse(&entry).await?;
+                if millis >= started_at {
+                    stats.players.entry(player).or_default().disconnects += 1;
+                }
+            }
+        }
+        Ok(stats)
+    }
+}
+
+/// Read the player from a disconnect key.
+fn disconnected_player(key: &[u8]) -> Option<EndpointId> {
+    let rest = std::str::from_utf8(key.strip_prefix(PREFIX_DISCONNECT)?).ok()?;
+    let (player, _) = rest.rsplit_once('.')?;
+    endpoint_id_from_str(player).ok()
+}
@@ -0,0 +1,56 @@
+//! The room's monotonic time.
+//!
+//! The host's timed policies, heartbeats, inactivity, rate limits and round deadlines, and the
+//! clock of a timed game, read the time from here rather than straight from [Instant::now]. It runs with
+//! the system's clock, but tests can move it forward, see [crate::GameRoom::advance_clock], so
+//! they can check a timeout without waiting for it.
+
+use super::*;
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// A monotonic clock, shared by everything that times something in one room.
+#[derive(Debug, Default)]
+pub(crate) struct RoomTime {
+    /// How far the clock has been moved ahead of the system's, in milliseconds.
+    skipped: AtomicU64,
+}
+
+impl RoomTime {
+    /// The current time.
+    pub(crate) fn now(&self) -> Instant {
+        Instant::now() + Duration::from_millis(self.skipped.load(Ordering::SeqCst))
+    }
+
+    /// How long it has been since `earlier`.
+    pub(crate) fn since(&self, earlier: Instant) -> Duration {
+        self.now().saturating_duration_since(earlier)
+    }
+
+    /// Move the clock forward by `by`, as if that much time had passed.
+    #[cfg(feature = "testing")]
+    pub(crate) fn advance(&self, by: Duration) {
+        self.skipped
+            .fetch_add(by.as_millis() as u64, Ordering::SeqCst);
+    }
+}
+
+impl<G: GameLogic> StateData<G> {
+    /// The room's current time, see [RoomTime].
+    pub(crate) fn now(&self) -> Instant {
+        self.time.now()
+    }
+
+    /// How long it has been since `earlier` by the room's time.
+    pub(crate) fn time_since(&self, earlier: Instant) -> Duration {
+        self.time.since(earlier)
+    }
+
+    /// Move the room's time forward by `by`.
+    #[cfg(feature = "testing")]
+    pub(crate) fn advance_time(&self, by: Duration) {
+        self.time.advance(by);
+    }
+}
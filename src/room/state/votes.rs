@@ -0,0 +1,102 @@
+//! Votes settled by a majority of the room.
+//!
+//! A peer writes a `VoteCall` and every peer in the room, the caller included, writes a
+//! ballot on it. The host counts the ballots of the peers who are online and writes a
+//! `VoteResult` once a majority has voted either way.
+
+use super::*;
+use crate::{PeerStatus, VoteCall, VoteKind, VoteResult};
+use n0_future::StreamExt as _;
+use std::collections::HashSet;
+
+impl<G: GameLogic> StateData<G> {
+    /// Write a new vote for the room to decide.
+    pub(crate) async fn write_vote_call(&self, call: &VoteCall) -> Result<()> {
+        let key = [PREFIX_VOTE, call.id.as_bytes()].concat();
+        self.set_bytes(&key, &self.encode(call)?).await
+    }
+
+    /// Cast this endpoint's ballot in an open vote.
+    pub(crate) async fn cast_ballot(&self, id: &str, yes: bool) -> Result<()> {
+        if self.get_vote_call(id).await?.is_none() {
+            return Err(anyhow!("No vote with id '{id}'"));
+        }
+        if self.get_vote_result(id).await?.is_some() {
+            return Err(anyhow!("Vote '{id}' has already been decided"));
+        }
+        // e.g., "ballot.<vote id>.<voter id>"
+        let key = format!(
+            "{}{id}.{}",
+            str::from_utf8(PREFIX_BALLOT)?,
+            self.endpoint_id
+        );
+        self.set_bytes(key.as_bytes(), &self.encode(&yes)?).await
+    }
+
+    /// Get an open or decided vote, if its author is the peer it claims to be from.
+    pub(crate) async fn get_vote_call(&self, id: &str) -> Result<Option<VoteCall>> {
+        let key = [PREFIX_VOTE, id.as_bytes()].concat();
+        let query = self
+            .doc
+            .get_one(Query::single_latest_per_key().key_exact(key));
+        let Some(entry) = query.await? else {
+            return Ok(None);
+        };
+        let call: VoteCall = self.parse(&entry).await?;
+        match self
+            .peer_author_matches(&call.from, &entry.author())
+            .await?
+        {
+            true => Ok(Some(call)),
+            false => Ok(None),
+        }
+    }
+
+    /// Get each ballot cast in a vote, keyed by voter.
+    pub(crate) async fn get_ballots(&self, id: &str) -> Result<HashMap<EndpointId, bool>> {
+        let prefix = format!("{}{id}.", str::from_utf8(PREFIX_BALLOT)?);
+        let query = Query::single_latest_per_key().key_prefix(prefix.as_bytes());
+        let mut entries = Box::pin(self.doc.get_many(query).await?);
+        let mut ballots = HashMap::new();
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            let voter =
+                endpoint_id_from_str(&String::from_utf8_lossy(&entry.key()[prefix.len()..]))?;
+            if !self.peer_author_matches(&voter, &entry.author()).await? {
+                continue;
+            }
+            ballots.insert(voter, self.parse::<bool>(&entry).await?);
+        }
+        Ok(ballots)
+    }
+
+    /// Get how the host tallied a vote, if it has.
+    pub(crate) async fn get_vote_result(&self, id: &str) -> Result<Option<VoteResult>> {
+        let key = [PREFIX_VOTE_RESULT, id.as_bytes()].concat();
+        match self.get_host_authored_bytes(&key).await? {
+            Some(bytes) => Ok(Some(self.decode(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Record how a vote was tallied.
+    pub(crate) async fn set_vote_result(&self, result: &VoteResult) -> Result<()> {
+        let key = [PREFIX_VOTE_RESULT, result.id.as_bytes()].concat();
+        self.set_bytes(&key, &self.encode(result)?).await
+    }
+
+    /// The endpoints whose ballots count in a vote on `kind`.
+    ///
+    /// Every endpoint that is online has a say, players and observers alike, except a peer
+    /// being voted out. Local seats are represented by the endpoint they sit on.
+    pub(crate) async fn voters(&self, kind: &VoteKind) -> Result<HashSet<EndpointId>> {
+        Ok(self
+            .get_peer_list()
+            .await?
+            .iter()
+            .filter(|(_, peer)| peer.status == PeerStatus::Online)
+            .map(|(id, peer)| peer.seat_of.unwrap_or(*id))
+            .filter(|voter| *kind != VoteKind::Kick(*voter))
+            .collect())
+    }
+}
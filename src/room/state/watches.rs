@@ -0,0 +1,103 @@
+//! Watchable copies of the room's app state and host connection, and of whether the app is
+//! in the foreground.
+//!
+//! The event loop keeps these up to date as it processes entries and connection changes, so
+//! applications can bind to them with `tokio::sync::watch` instead of folding the event stream.
+//! The activity goes the other way: the application sets it, and the event loop follows it.
+
+use super::AppState;
+use crate::GameLogic;
+use std::sync::Mutex;
+use tokio::sync::watch;
+
+/// Whether the app is in use, see [crate::GameRoom::set_activity].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Activity {
+    /// The app is in the foreground, and the room runs at full speed.
+    #[default]
+    Active,
+    /// The app is in the background, e.g. on a phone between turns of a long game, and the
+    /// room does as little as it can while staying in the game.
+    Background,
+}
+
+/// The room's app state and host connection, as this peer currently sees them.
+pub(crate) struct RoomWatches<G: GameLogic> {
+    /// The latest app state the host wrote, as far as this peer knows.
+    synced_app_state: Mutex<AppState<G>>,
+    /// The app state to show, which is paused while the host is offline.
+    app_state: watch::Sender<AppState<G>>,
+    host_online: watch::Sender<bool>,
+    activity: watch::Sender<Activity>,
+}
+
+impl<G: GameLogic> Default for RoomWatches<G> {
+    fn default() -> Self {
+        Self {
+            synced_app_state: Mutex::new(AppState::Lobby),
+            app_state: watch::Sender::new(AppState::Lobby),
+            host_online: watch::Sender::new(true),
+            activity: watch::Sender::new(Activity::Active),
+        }
+    }
+}
+
+impl<G: GameLogic> RoomWatches<G> {
+    /// Record the latest app state the host wrote.
+    pub(crate) fn set_app_state(&self, app_state: AppState<G>) {
+        if let Ok(mut synced) = self.synced_app_state.lock() {
+            *synced = app_state;
+        }
+        self.publish_app_state();
+    }
+
+    /// Record whether the host is connected.
+    pub(crate) fn set_host_online(&self, online: bool) {
+        self.host_online.send_if_modified(|current| {
+            let changed = *current != online;
+            *current = online;
+            changed
+        });
+        self.publish_app_state();
+    }
+
+    pub(crate) fn is_host_online(&self) -> bool {
+        *self.host_online.borrow()
+    }
+
+    pub(crate) fn subscribe_app_state(&self) -> watch::Receiver<AppState<G>> {
+        self.app_state.subscribe()
+    }
+
+    pub(crate) fn subscribe_host_online(&self) -> watch::Receiver<bool> {
+        self.host_online.subscribe()
+    }
+
+    pub(crate) fn set_activity(&self, activity: Activity) -> Activity {
+        self.activity.send_replace(activity)
+    }
+
+    pub(crate) fn activity(&self) -> Activity {
+        *self.activity.borrow()
+    }
+
+    pub(crate) fn subscribe_activity(&self) -> watch::Receiver<Activity> {
+        self.activity.subscribe()
+    }
+
+    /// Tell app state watchers what to show, now that the state or the host has changed.
+    fn publish_app_state(&self) {
+        let Ok(synced) = self.synced_app_state.lock() else {
+            return;
+        };
+        let app_state = match self.is_host_online() {
+            true => *synced,
+            false => AppState::Paused,
+        };
+        self.app_state.send_if_modified(|current| {
+            let changed = *current != app_state;
+            *current = app_state;
+            changed
+        });
+    }
+}
@@ -0,0 +1,42 @@
+//! Match statistics
+//!
+//! This module contains the `MatchStats` and `MatchPlayerStats` structs, which sum up how each player
+//! took part in a game. They are worked out from the room's action log, its chat and the
+//! host's record of disconnects, so post-game screens can show them without the game counting
+//! anything itself.
+
+use std::{collections::HashMap, time::Duration};
+
+use iroh::EndpointId;
+use serde::{Deserialize, Serialize};
+
+/// How the players took part in a game, see [crate::GameRoom::stats].
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct MatchStats {
+    /// Actions the host applied, across every player.
+    pub actions: u64,
+    /// Each player's statistics.
+    pub players: HashMap<EndpointId, MatchPlayerStats>,
+}
+
+/// How one player took part in a game.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct MatchPlayerStats {
+    /// Actions the host applied for the player.
+    pub actions: u64,
+    /// The time the player took over those actions, each counted from the action applied
+    /// before it, or from the start of the game.
+    pub think_time: Duration,
+    /// Chat messages the player sent during the game.
+    pub chat_messages: u64,
+    /// Times the host saw the player drop out during the game.
+    pub disconnects: u64,
+}
+
+impl MatchPlayerStats {
+    /// The time the player took over an action on average, once they have taken one.
+    pub fn average_think_time(&self) -> Option<Duration> {
+        let actions = u32::try_from(self.actions).ok().filter(|n| *n > 0)?;
+        Some(self.think_time / actions)
+    }
+}
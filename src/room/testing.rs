@@ -0,0 +1,545 @@
+//! Simulation test harness
+//!
+//! This module, behind the `testing` feature, helps game authors write reliable integration
+//! tests. A [SimNetwork] spins up any number of in-memory rooms that only listen on the
+//! loopback interface, with relays and public address lookup turned off, so peers find each
+//! other through the direct addresses in room tickets and nothing leaves the machine.
+//!
+//! Tests wait for conditions instead of sleeping: [SimPeer::expect_event] waits for a
+//! matching event, and [wait_until] re-checks a condition on a room whenever it reports
+//! something. Faults are injected per peer, with [SimPeer::delay_sync] and
+//! [SimPeer::resume_sync] holding back its document sync, and [SimPeer::crash] dropping it
+//! off the network without a word.
+//!
+//! For finer control, each room's [Faults] sit between its document subscription and its
+//! event loop, and can drop, duplicate or delay the events that match a rule, panic on them,
+//! or make up events of their own, e.g. a failed sync or a lost neighbour, so host migration,
+//! pauses, reconnection and event loop restarts can be tested without killing processes.
+
+use std::{
+    net::{Ipv4Addr, SocketAddrV4},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Result, anyhow};
+use bytes::Bytes;
+use iroh::{EndpointAddr, EndpointId};
+use iroh_docs::engine::{LiveEvent, Origin, SyncEvent, SyncReason};
+use iroh_gossip::api::GossipSender;
+use n0_future::{Stream, StreamExt as _, stream};
+use tokio::sync::mpsc;
+
+use super::{
+    GameRoom, RoomConfig, UiEvent,
+    social::{announcement, presence_topic},
+    state::{ActionResponse, RoomMetadata},
+};
+use crate::{
+    ChatMessage, ChatReaction, EventFilter, GameLogic, Identity, Iroh, IrohConfig, PeerProfile,
+};
+
+/// How long the harness waits for anything before failing the test.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+/// How often [wait_until] re-checks a condition while the room is quiet, for state that
+/// changes without an event of its own.
+const RECHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A set of rooms on a simulated, loopback-only network.
+#[derive(Debug, Clone)]
+pub struct SimNetwork {
+    config: RoomConfig,
+    timeout: Duration,
+}
+
+impl Default for SimNetwork {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimNetwork {
+    /// A network whose peers only listen on `127.0.0.1`.
+    pub fn new() -> Self {
+        let network = IrohConfig::lan()
+            .bind_v4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0))
+            .bind_v6(None);
+        Self {
+            config: RoomConfig::default().network(network),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Use these room settings for every peer. The network settings are kept on loopback.
+    pub fn room_config(mut self, config: RoomConfig) -> Self {
+        let network = self.config.network.clone();
+        self.config = config.network(network);
+        self
+    }
+
+    /// Fail waits that take longer than `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Create a room hosted by a new peer called `name`, once it is in the lobby.
+    pub async fn host<G: GameLogic>(&self, logic: G, name: &str) -> Result<SimPeer<G>> {
+        let (room, events) =
+            GameRoom::create_with_config(logic, None, None, self.config.clone()).await?;
+        self.enter(room, events, name).await
+    }
+
+    /// Join `host`'s room as a new peer called `name`, once it is in the lobby.
+    pub async fn join<G: GameLogic>(
+        &self,
+        logic: G,
+        host: &SimPeer<G>,
+        name: &str,
+    ) -> Result<SimPeer<G>> {
+        let ticket = host.room.ticket().await?.to_string();
+        let (room, events) =
+            GameRoom::join_with_config(logic, &ticket, None, self.config.clone()).await?;
+        self.enter(room, events, name).await
+    }
+
+    /// Start a room with `count` peers, the first of them hosting, named `peer0`, `peer1`
+    /// and so on, once each has seen everyone in the lobby.
+    pub async fn spawn<G: GameLogic + Clone>(
+        &self,
+        logic: G,
+        count: usize,
+    ) -> Result<Vec<SimPeer<G>>> {
+        let mut peers = Vec::with_capacity(count);
+        if count == 0 {
+            return Ok(peers);
+        }
+        peers.push(self.host(logic.clone(), "peer0").await?);
+        for index in 1..count {
+            let peer = self
+                .join(logic.clone(), &peers[0], &format!("peer{index}"))
+                .await?;
+            peers.push(peer);
+        }
+        for peer in &peers {
+            wait_until(&peer.room, peer.timeout, async |room| {
+                Ok((room.get_peer_list().await?.len() == count).then_some(()))
+            })
+            .await?;
+        }
+        Ok(peers)
+    }
+
+    async fn enter<G: GameLogic>(
+        &self,
+        room: GameRoom<G>,
+        events: mpsc::Receiver<UiEvent<G>>,
+        name: &str,
+    ) -> Result<SimPeer<G>> {
+        let peer = SimPeer {
+            room,
+            events,
+            timeout: self.timeout,
+        };
+        peer.room.announce_presence(name).await?;
+        // Polled rather than awaited as an event, which a room with no room to queue events
+        // may drop.
+        let id = peer.id();
+        peer.wait_until(async |room| {
+            Ok(room.get_peer_list().await?.contains_key(&id).then_some(()))
+        })
+        .await?;
+        Ok(peer)
+    }
+}
+
+/// A peer on a [SimNetwork], with its room and the room's events.
+pub struct SimPeer<G: GameLogic> {
+    /// The peer's room.
+    pub room: GameRoom<G>,
+    /// The room's events, as returned when it was created or joined.
+    pub events: mpsc::Receiver<UiEvent<G>>,
+    timeout: Duration,
+}
+
+impl<G: GameLogic> SimPeer<G> {
+    /// The peer's ID.
+    pub fn id(&self) -> EndpointId {
+        self.room.id()
+    }
+
+    /// Wait for the room's next event.
+    pub async fn next_event(&mut self) -> Result<UiEvent<G>> {
+        tokio::time::timeout(self.timeout, self.events.recv())
+            .await
+            .map_err(|_| anyhow!("Timed out waiting for an event"))?
+            .ok_or_else(|| anyhow!("The room's event loop has stopped"))
+    }
+
+    /// Wait for an event that `matches` picks out, skipping any before it.
+    pub async fn expect_event<T>(
+        &mut self,
+        mut matches: impl FnMut(UiEvent<G>) -> Option<T>,
+    ) -> Result<T> {
+        tokio::time::timeout(self.timeout, async {
+            loop {
+                let event = self
+                    .events
+                    .recv()
+                    .await
+                    .ok_or_else(|| anyhow!("The room's event loop has stopped"))?;
+                if let Some(found) = matches(event) {
+                    return Ok(found);
+                }
+            }
+        })
+        .await
+        .map_err(|_| anyhow!("Timed out waiting for a matching event"))?
+    }
+
+    /// Wait until `check` finds what it is looking for in the room, see [wait_until].
+    pub async fn wait_until<T>(
+        &self,
+        check: impl AsyncFnMut(&GameRoom<G>) -> Result<Option<T>>,
+    ) -> Result<T> {
+        wait_until(&self.room, self.timeout, check).await
+    }
+
+    /// Stop syncing the room with the other peers, so nothing this peer writes reaches them
+    /// and nothing they write reaches it, until [SimPeer::resume_sync].
+    pub async fn delay_sync(&self) -> Result<()> {
+        self.room.state.stop_sync().await
+    }
+
+    /// Sync the room with every other online peer again, catching up on what was missed.
+    pub async fn resume_sync(&self) -> Result<()> {
+        self.room.state.restart_sync().await
+    }
+
+    /// Write a chat message exactly as given, whoever it claims to be from, as a
+    /// misbehaving peer could.
+    pub async fn forge_chat(&self, message: &ChatMessage) -> Result<()> {
+        self.room.state.put_chat(message).await
+    }
+
+    /// Share an avatar image whatever its size, as a misbehaving peer could.
+    pub async fn forge_avatar(&self, image: impl Into<Bytes>) -> Result<()> {
+        self.room.state.share_avatar(image.into()).await
+    }
+
+    /// Write an action request for `player`, whoever they are, under their next sequence
+    /// number, as a misbehaving peer could.
+    pub async fn forge_action(&self, player: &EndpointId, action: G::GameAction) -> Result<u64> {
+        self.room
+            .state
+            .put_action_request(player, action, None)
+            .await
+    }
+
+    /// Reject `player`'s action with sequence number `seq`, as if this peer were the host.
+    pub async fn forge_rejection(&self, player: &EndpointId, seq: u64, reason: &str) -> Result<()> {
+        let response = ActionResponse::engine_rejection(String::new(), seq, reason);
+        self.room.state.set_action_response(player, &response).await
+    }
+
+    /// Write a join request for `player`, whoever they are, as a misbehaving peer could.
+    pub async fn forge_join(
+        &self,
+        player: &EndpointId,
+        profile: impl Into<PeerProfile>,
+    ) -> Result<()> {
+        self.room
+            .state
+            .put_join_request(player, profile.into())
+            .await
+    }
+
+    /// Copy the value stored under one key to another exactly as stored, as a peer who can
+    /// write to the room but not read it could.
+    pub async fn forge_copy(&self, from: &[u8], to: &[u8]) -> Result<()> {
+        self.room.state.copy_stored_bytes(from, to).await
+    }
+
+    /// Write a chat reaction exactly as given, whoever it claims to be from.
+    pub async fn forge_reaction(&self, reaction: &ChatReaction) -> Result<()> {
+        self.room.state.put_reaction(reaction).await
+    }
+
+    /// Drop off the network without announcing it, as if the process had crashed.
+    pub async fn crash(mut self) -> Result<()> {
+        if let Some(handle) = self.room.event_handle.take() {
+            handle.abort();
+        }
+        self.room.state.node()?.endpoint().close().await;
+        Ok(())
+    }
+}
+
+/// Wait until `check` returns `Some` for `room`, failing after `timeout`.
+///
+/// The condition is checked straight away, then again whenever the room reports an event,
+/// so tests neither sleep for a guessed time nor poll in a tight loop.
+pub async fn wait_until<G: GameLogic, T>(
+    room: &GameRoom<G>,
+    timeout: Duration,
+    mut check: impl AsyncFnMut(&GameRoom<G>) -> Result<Option<T>>,
+) -> Result<T> {
+    let mut events = room.subscribe(EventFilter::default());
+    tokio::time::timeout(timeout, async {
+        loop {
+            if let Some(found) = check(room).await? {
+                return Ok(found);
+            }
+            // Also re-check now and then, in case the change came without an event.
+            tokio::time::timeout(RECHECK_INTERVAL, events.recv())
+                .await
+                .ok();
+        }
+    })
+    .await
+    .map_err(|_| anyhow!("Timed out waiting for the room"))?
+}
+
+impl<G: GameLogic> GameRoom<G> {
+    /// The faults injected into this room's event loop.
+    pub fn faults(&self) -> &Faults {
+        &self.faults
+    }
+
+    /// Write `state` as the room's game state, tagged with state `version`, as an older build
+    /// of the game would have written it, to test [GameLogic::migrate_state].
+    pub async fn set_versioned_game_state(
+        &self,
+        version: u32,
+        state: &impl serde::Serialize,
+    ) -> Result<()> {
+        let bytes = self.state.encode(state)?;
+        self.state.put_versioned_state(version, bytes).await
+    }
+
+    /// Move the room's clock forward by `by`, as if that much time had passed, so the host's
+    /// heartbeat, inactivity and round deadlines, and a timed game, can be tested without
+    /// waiting for them.
+    pub fn advance_clock(&self, by: Duration) {
+        self.state.advance_time(by);
+    }
+
+    /// Report `rtt` as the round trip to `peer`, as if the link to it were that slow, to test
+    /// what the host does for peers on poor connections, e.g. [crate::StateCadence].
+    pub fn simulate_rtt(&self, peer: &EndpointId, rtt: Duration) -> Result<()> {
+        self.state.iroh()?.simulate_rtt(*peer, rtt);
+        Ok(())
+    }
+
+    /// Stop the room without a word to its peers, as if the process had exited, returning
+    /// once its node has released the persistent store, so it can be opened again.
+    pub async fn stop(mut self) -> Result<()> {
+        if let Some(handle) = self.event_handle.take() {
+            handle.abort();
+            handle.await.ok();
+        }
+        self.state.shutdown().await
+    }
+
+    /// Record `version` as the room's protocol, as an older build would have written it, to
+    /// test resuming rooms from before an upgrade.
+    pub async fn set_protocol_version(&self, version: u32) -> Result<()> {
+        let metadata = self.state.get_room_metadata().await?;
+        self.state
+            .set_room_metadata(&RoomMetadata {
+                protocol_version: version,
+                ..metadata
+            })
+            .await
+    }
+}
+
+/// A node on a game's presence topic that sends whatever announcements a test makes up, as a
+/// misbehaving player could, e.g. replaying another player's old announcements.
+pub struct PresenceForger {
+    _node: Iroh,
+    sender: GossipSender,
+}
+
+impl PresenceForger {
+    /// Join the presence topic of `logic`'s game through `tracker`, once connected to it.
+    pub async fn join<G: GameLogic>(logic: &G, tracker: EndpointAddr) -> Result<Self> {
+        let node = Iroh::memory(&IrohConfig::default()).await?;
+        node.add_known_addrs([tracker.clone()]);
+        let mut topic = node
+            .subscribe_realtime(presence_topic(logic.game_name()), vec![tracker.id])
+            .await?;
+        topic.joined().await?;
+        let (sender, _) = topic.split();
+        Ok(Self {
+            _node: node,
+            sender,
+        })
+    }
+
+    /// Announce the player with `identity`, dated `sent_at`.
+    pub async fn announce(&self, identity: &Identity, sent_at: SystemTime) -> Result<()> {
+        let announcement =
+            announcement(&identity.secret_key()?, &identity.profile.nickname, sent_at)?;
+        self.sender.broadcast(announcement).await?;
+        Ok(())
+    }
+}
+
+/// What happens to an event that matches a fault rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// The event loop never sees the event.
+    Drop,
+    /// The event loop sees the event twice.
+    Duplicate,
+    /// The event loop sees the event this much later, after events that came behind it.
+    Delay(Duration),
+    /// The event loop panics instead of seeing the event, as a bug in it or in the game
+    /// logic would make it, and is started again.
+    Panic,
+}
+
+type Matcher = Box<dyn Fn(&LiveEvent) -> bool + Send + Sync>;
+
+struct FaultRule {
+    fault: Fault,
+    matches: Matcher,
+    /// How many more events the rule applies to.
+    remaining: usize,
+}
+
+/// Faults injected into a room's event loop, see [GameRoom::faults].
+///
+/// They apply to the game document's events, which carry its entries, neighbours and sync
+/// results. Rules are tried in the order they were added, and the first that matches an
+/// event decides what happens to it.
+#[derive(Clone, Default)]
+pub struct Faults {
+    rules: Arc<Mutex<Vec<FaultRule>>>,
+    /// Feeds made-up events to the event loop, while it runs.
+    injector: Arc<Mutex<Option<mpsc::WeakUnboundedSender<Feed>>>>,
+}
+
+impl std::fmt::Debug for Faults {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rules = self.rules.lock().map_or(0, |rules| rules.len());
+        f.debug_struct("Faults").field("rules", &rules).finish()
+    }
+}
+
+impl Faults {
+    /// Apply `fault` to the next `times` events that `matches` picks out.
+    pub fn add(
+        &self,
+        fault: Fault,
+        times: usize,
+        matches: impl Fn(&LiveEvent) -> bool + Send + Sync + 'static,
+    ) {
+        if let Ok(mut rules) = self.rules.lock() {
+            rules.push(FaultRule {
+                fault,
+                matches: Box::new(matches),
+                remaining: times,
+            });
+        }
+    }
+
+    /// Drop every rule that has not been used up yet.
+    pub fn clear(&self) {
+        if let Ok(mut rules) = self.rules.lock() {
+            rules.clear();
+        }
+    }
+
+    /// Hand the event loop an event the network never sent.
+    pub fn inject(&self, event: LiveEvent) -> Result<()> {
+        let injector = self
+            .injector
+            .lock()
+            .map_err(|_| anyhow!("Fault injector lock poisoned"))?
+            .as_ref()
+            .and_then(|injector| injector.upgrade())
+            .ok_or_else(|| anyhow!("The room's event loop is not running"))?;
+        injector
+            .send(Feed::Event(Ok(event)))
+            .map_err(|_| anyhow!("The room's event loop has stopped"))
+    }
+
+    /// Report a failed sync with `peer`, as if the network had.
+    pub fn fail_sync(&self, peer: EndpointId, reason: &str) -> Result<()> {
+        let now = SystemTime::now();
+        self.inject(LiveEvent::SyncFinished(SyncEvent {
+            peer,
+            origin: Origin::Connect(SyncReason::DirectJoin),
+            started: now,
+            finished: now,
+            result: Err(reason.to_string()),
+        }))
+    }
+
+    /// Take the fault for an event, using up one turn of the rule that matched it.
+    fn take(&self, event: &LiveEvent) -> Option<Fault> {
+        let mut rules = self.rules.lock().ok()?;
+        let index = rules.iter().position(|rule| (rule.matches)(event))?;
+        let fault = rules[index].fault;
+        rules[index].remaining -= 1;
+        if rules[index].remaining == 0 {
+            rules.remove(index);
+        }
+        Some(fault)
+    }
+
+    /// Pass a room's document events through these faults on their way to the event loop.
+    pub(crate) fn apply(
+        &self,
+        mut events: impl Stream<Item = Result<LiveEvent>> + Send + Unpin + 'static,
+    ) -> impl Stream<Item = Result<LiveEvent>> + Send + Unpin + 'static {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        if let Ok(mut injector) = self.injector.lock() {
+            *injector = Some(sender.downgrade());
+        }
+        let faults = self.clone();
+        tokio::spawn(async move {
+            while let Some(event) = events.next().await {
+                let fault = match &event {
+                    Ok(event) => faults.take(event),
+                    Err(_) => None,
+                };
+                let sent = match (fault, event) {
+                    (Some(Fault::Drop), _) => Ok(()),
+                    (Some(Fault::Duplicate), Ok(event)) => sender
+                        .send(Feed::Event(Ok(event.clone())))
+                        .and_then(|_| sender.send(Feed::Event(Ok(event)))),
+                    (Some(Fault::Delay(delay)), event) => {
+                        let sender = sender.clone();
+                        tokio::spawn(async move {
+                            tokio::time::sleep(delay).await;
+                            sender.send(Feed::Event(event)).ok();
+                        });
+                        Ok(())
+                    }
+                    (Some(Fault::Panic), _) => sender.send(Feed::Panic),
+                    (_, event) => sender.send(Feed::Event(event)),
+                };
+                if sent.is_err() {
+                    break; // Event loop finished
+                }
+            }
+        });
+        stream::poll_fn(move |cx| {
+            receiver.poll_recv(cx).map(|feed| {
+                feed.map(|feed| match feed {
+                    Feed::Event(event) => event,
+                    Feed::Panic => panic!("Injected event loop panic"),
+                })
+            })
+        })
+    }
+}
+
+/// What the faults hand on to the event loop.
+enum Feed {
+    Event(Result<LiveEvent>),
+    Panic,
+}
@@ -1,29 +1,129 @@
-//! A ticket for joining a game room, including the Iroh document ticket and game/room identifiers.
+//! A ticket for joining a game room, including the Iroh document tickets and the room's metadata.
 
 use std::{fmt, str::FromStr};
 
 use iroh_docs::DocTicket;
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{RoomSecret, state::RoomMetadata};
+use crate::GameLogic;
+
+/// Who a room is advertised to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum RoomVisibility {
+    /// Anyone who can discover the room may join.
+    Public,
+    /// Only peers who have been handed the ticket may join.
+    #[default]
+    Private,
+}
+
+/// Reasons a room ticket can be rejected before connecting.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TicketError {
+    /// The ticket could not be decoded.
+    #[error("Malformed room ticket: {0}")]
+    Malformed(String),
+    /// The ticket is for a different game.
+    #[error("Room ticket is for game '{found}', expected '{expected}'")]
+    GameMismatch { expected: String, found: String },
+    /// The ticket was issued by an incompatible protocol version.
+    #[error("Room ticket uses protocol {found}, expected protocol {expected}")]
+    ProtocolMismatch { expected: u32, found: u32 },
+}
 
 /// A ticket for joining a game room
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct GameTicket {
+pub struct RoomTicket {
     /// The Iroh network ticket for joining the room, including all known peer addresses.
     pub doc_ticket: DocTicket,
-    /// The room ID, used to identify the specific room to join.
-    pub room_id: String,
+    /// The Iroh ticket for the room's chat document, if the room has chat.
+    #[serde(default)]
+    pub chat_ticket: Option<DocTicket>,
+    /// The room's display name.
+    pub room_name: String,
+    /// The Rust type name of the game logic hosting the room.
+    pub game_type: String,
+    /// The protocol version the host is running.
+    pub protocol_version: u32,
+    /// Who the room is advertised to.
+    pub visibility: RoomVisibility,
+    /// The secret the room's values are encrypted with, if the invite carries it.
+    #[serde(default)]
+    pub secret: Option<RoomSecret>,
+}
+
+impl RoomTicket {
+    /// Build a ticket for a room running `logic`.
+    pub fn new<G: GameLogic>(
+        logic: &G,
+        doc_ticket: DocTicket,
+        chat_ticket: Option<DocTicket>,
+        room_name: impl Into<String>,
+        visibility: RoomVisibility,
+    ) -> Self {
+        let metadata = RoomMetadata::for_game(logic);
+        Self {
+            doc_ticket,
+            chat_ticket,
+            room_name: room_name.into(),
+            game_type: metadata.game_type,
+            protocol_version: metadata.protocol_version,
+            visibility,
+            secret: None,
+        }
+    }
+
+    /// Carry the room's secret in the ticket, so whoever it is handed to can join an
+    /// encrypted room without being told the secret separately.
+    pub fn with_secret(mut self, secret: RoomSecret) -> Self {
+        self.secret = Some(secret);
+        self
+    }
+
+    /// Parse a ticket string, reporting what was wrong with it if it is malformed.
+    pub fn parse(s: &str) -> Result<Self, TicketError> {
+        s.parse()
+    }
+
+    /// Check that this ticket was issued for a room running `logic`, on a compatible protocol.
+    pub fn validate_for<G: GameLogic>(&self, logic: &G) -> Result<(), TicketError> {
+        self.validate(RoomMetadata::for_game(logic), false)
+    }
+
+    /// Check that this ticket, saved when the room was last opened, is for a room running
+    /// `logic`, on a protocol no newer than this build's.
+    pub(crate) fn validate_stored_for<G: GameLogic>(&self, logic: &G) -> Result<(), TicketError> {
+        self.validate(RoomMetadata::for_game(logic), true)
+    }
+
+    fn validate(&self, expected: RoomMetadata, resuming: bool) -> Result<(), TicketError> {
+        if !expected.supports_protocol(self.protocol_version, resuming) {
+            return Err(TicketError::ProtocolMismatch {
+                expected: expected.protocol_version,
+                found: self.protocol_version,
+            });
+        }
+        if self.game_type != expected.game_type {
+            return Err(TicketError::GameMismatch {
+                expected: expected.game_type,
+                found: self.game_type.clone(),
+            });
+        }
+        Ok(())
+    }
 }
 
-impl FromStr for GameTicket {
-    type Err = anyhow::Error;
+impl FromStr for RoomTicket {
+    type Err = TicketError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let game_ticket = serde_json::from_str(s)?;
-        Ok(game_ticket)
+        serde_json::from_str(s.trim()).map_err(|e| TicketError::Malformed(e.to_string()))
     }
 }
 
-impl TryInto<String> for GameTicket {
+impl TryInto<String> for RoomTicket {
     type Error = anyhow::Error;
 
     fn try_into(self) -> Result<String, Self::Error> {
@@ -32,7 +132,7 @@ impl TryInto<String> for GameTicket {
     }
 }
 
-impl fmt::Display for GameTicket {
+impl fmt::Display for RoomTicket {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let s = serde_json::to_string(self).map_err(|_| fmt::Error)?;
         f.write_str(&s)
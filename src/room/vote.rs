@@ -0,0 +1,70 @@
+//! Votes
+//!
+//! This module contains the `VoteKind` enum, the decisions about the room that any peer can
+//! put to a vote and a majority of the room settles, e.g. kicking a peer or restarting the
+//! game. It also contains the `VoteCall` and `VoteResult` structs recorded in the room
+//! document while a vote is open and once the host has tallied it.
+
+use std::fmt::Display;
+
+use anyhow::Result;
+use iroh::EndpointId;
+use serde::{Deserialize, Serialize};
+
+/// A decision about the room that is settled by a majority of its peers.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum VoteKind {
+    /// Take a peer's place in the game away, as if they had forfeited, and leave them watching.
+    Kick(EndpointId),
+    /// Restart the game, as [crate::Proposal::Restart] would.
+    Restart,
+    /// A question of the game's own, e.g. which map to play next. The engine only tallies it.
+    Custom(String),
+}
+
+impl Display for VoteKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VoteKind::Kick(peer) => write!(f, "kick {peer}"),
+            VoteKind::Restart => write!(f, "restart"),
+            VoteKind::Custom(question) => write!(f, "{question}"),
+        }
+    }
+}
+
+/// An open vote, as called by a peer.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct VoteCall {
+    /// The ID used to cast a vote in this call.
+    pub id: String,
+    /// The ID of the peer who called the vote.
+    pub from: EndpointId,
+    /// What is being decided.
+    pub kind: VoteKind,
+}
+
+impl VoteCall {
+    /// Call a new vote with a fresh ID.
+    pub(crate) fn new(from: EndpointId, kind: VoteKind) -> Result<Self> {
+        let id = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos()
+            .to_string();
+        Ok(Self { id, from, kind })
+    }
+}
+
+/// How the host tallied a vote.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct VoteResult {
+    /// The ID of the tallied vote.
+    pub id: String,
+    /// What was decided.
+    pub kind: VoteKind,
+    /// Whether a majority voted for it, in which case the host has carried it out.
+    pub passed: bool,
+    /// How many peers voted for it.
+    pub yes: usize,
+    /// How many peers voted against it.
+    pub no: usize,
+}
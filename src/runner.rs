@@ -0,0 +1,153 @@
+//! Headless host runner
+//!
+//! This module runs a long-lived dedicated room, behind the `runner` feature, so a room can
+//! be deployed on a server in a few lines:
+//!
+//! ```no_run
+//! # use p2p_game_engine::{GameLogic, RoomConfig, runner::HostRunner};
+//! # async fn deploy<G: GameLogic>(logic: G) -> anyhow::Result<()> {
+//! HostRunner::new(logic, RoomConfig::default())
+//!     .on_ticket(|ticket| println!("Join with {ticket}"))
+//!     .run()
+//!     .await
+//! # }
+//! ```
+//!
+//! The room is created with [GameRoom::create_dedicated], so the host never plays. After
+//! each game the lobby reopens for a rematch, and the runner stops once the room has had no
+//! players online for a while, or the process is asked to stop with Ctrl-C or `SIGTERM`.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::time::Instant;
+
+use crate::{
+    DedicatedPolicy, GameLogic, GameRoom, LeaveReason, RoomConfig, RoomTicket, room::timer, trace,
+};
+
+/// How long after a game finishes the lobby reopens, unless the config sets it.
+const DEFAULT_REMATCH_DELAY: Duration = Duration::from_secs(10);
+/// How long the room may go without players before the runner stops, unless set.
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+/// How often the runner checks whether the room has gone idle.
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Receives the room's ticket once it is open, see [HostRunner::on_ticket].
+type TicketHandler = Box<dyn FnOnce(&RoomTicket) + Send>;
+
+/// Why a [HostRunner] stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// Nobody was online in the room for the idle timeout.
+    Idle,
+    /// The process was asked to stop.
+    Signal,
+}
+
+/// Runs a dedicated room until it goes idle or the process is asked to stop.
+pub struct HostRunner<G: GameLogic> {
+    logic: G,
+    config: RoomConfig,
+    idle_timeout: Option<Duration>,
+    on_ticket: Option<TicketHandler>,
+}
+
+impl<G: GameLogic> HostRunner<G> {
+    /// Prepare to run a dedicated room for `logic`.
+    ///
+    /// Unless `config` sets a [DedicatedPolicy], games start once two players are ready and
+    /// the lobby reopens ten seconds after each one.
+    pub fn new(logic: G, mut config: RoomConfig) -> Self {
+        config
+            .dedicated
+            .get_or_insert_with(|| DedicatedPolicy::default().reopen_after(DEFAULT_REMATCH_DELAY));
+        Self {
+            logic,
+            config,
+            idle_timeout: Some(DEFAULT_IDLE_TIMEOUT),
+            on_ticket: None,
+        }
+    }
+
+    /// Stop once the room has had no players online for `timeout`, thirty minutes by default.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Keep running however long the room has no players.
+    pub fn keep_alive(mut self) -> Self {
+        self.idle_timeout = None;
+        self
+    }
+
+    /// Hand the room's ticket to `f` once it is open, e.g. to print or publish it.
+    pub fn on_ticket(mut self, f: impl FnOnce(&RoomTicket) + Send + 'static) -> Self {
+        self.on_ticket = Some(Box::new(f));
+        self
+    }
+
+    /// Run the room until it goes idle or the process is asked to stop, then leave it and
+    /// shut down the node.
+    pub async fn run(self) -> Result<()> {
+        self.run_until_stopped().await.map(|_| ())
+    }
+
+    /// Run the room like [HostRunner::run], returning why it stopped.
+    pub async fn run_until_stopped(self) -> Result<StopReason> {
+        let room = GameRoom::create_dedicated(self.logic, self.config).await?;
+        if let Some(on_ticket) = self.on_ticket {
+            on_ticket(&room.ticket().await?);
+        }
+        let reason = tokio::select! {
+            result = idle(&room, self.idle_timeout) => {
+                result?;
+                StopReason::Idle
+            }
+            result = stop_signal() => {
+                result?;
+                StopReason::Signal
+            }
+        };
+        trace::info!(?reason, "Stopping the dedicated room");
+        if !room.leave(&LeaveReason::ApplicationClosed, true).await? {
+            trace::warning!("No peer confirmed the room's closing before it stopped");
+        }
+        Ok(reason)
+    }
+}
+
+/// Wait until the room has had no players online for `timeout`, or forever without one.
+async fn idle<G: GameLogic>(room: &GameRoom<G>, timeout: Option<Duration>) -> Result<()> {
+    let Some(timeout) = timeout else {
+        return std::future::pending().await;
+    };
+    let mut last_seen = Instant::now();
+    let mut check = timer(IDLE_CHECK_INTERVAL.min(timeout));
+    loop {
+        check.tick().await;
+        let players = room.get_peer_list().await?;
+        if players.values().any(|peer| peer.status.is_online()) {
+            last_seen = Instant::now();
+        } else if last_seen.elapsed() >= timeout {
+            return Ok(());
+        }
+    }
+}
+
+/// Wait for Ctrl-C, or `SIGTERM` on Unix, e.g. from a service manager.
+async fn stop_signal() -> Result<()> {
+    #[cfg(unix)]
+    {
+        let mut terminate =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result?,
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
+    Ok(())
+}
@@ -0,0 +1,179 @@
+//! Blocking room API
+//!
+//! This module wraps a room for apps that do not run an async runtime of their own, behind
+//! the `sync` feature, e.g. immediate-mode GUIs like egui or raylib and plugin hosts.
+//! [BlockingGameRoom] owns a Tokio runtime that keeps the room syncing in the background, and
+//! every call blocks until it is done. Events are polled, once per frame or with a timeout:
+//!
+//! ```no_run
+//! # use p2p_game_engine::{GameLogic, RoomConfig, sync::BlockingGameRoom};
+//! # fn play<G: GameLogic>(logic: G, ticket: &str) -> anyhow::Result<()> {
+//! let mut room = BlockingGameRoom::join(logic, ticket, RoomConfig::default())?;
+//! room.announce_presence("Alice")?;
+//! loop {
+//!     while let Some(event) = room.try_event() {
+//!         println!("{event}");
+//!     }
+//!     // Draw the frame, and act on the player's input...
+//! #   break;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Anything not wrapped here can still be reached through [BlockingGameRoom::room] and
+//! [BlockingGameRoom::block_on].
+
+use std::{future::Future, time::Duration};
+
+use anyhow::{Result, anyhow};
+use iroh::EndpointId;
+use tokio::{runtime::Runtime, sync::mpsc};
+
+use crate::{
+    AppState, GameLogic, GameRoom, LeaveReason, PeerMap, PeerProfile, RoomConfig, RoomTicket,
+    UiEvent,
+};
+
+/// A [GameRoom] with a runtime of its own, driven by blocking calls.
+pub struct BlockingGameRoom<G: GameLogic> {
+    /// Always set until the room is left or dropped.
+    room: Option<GameRoom<G>>,
+    events: mpsc::Receiver<UiEvent<G>>,
+    runtime: Runtime,
+}
+
+impl<G: GameLogic> Drop for BlockingGameRoom<G> {
+    fn drop(&mut self) {
+        // The room's tasks belong to the runtime, so stop them from within it.
+        let _guard = self.runtime.enter();
+        self.room.take();
+    }
+}
+
+impl<G: GameLogic> BlockingGameRoom<G> {
+    /// Create a room in memory and host it, see [GameRoom::create_with_config].
+    pub fn create(logic: G, name: Option<&str>, config: RoomConfig) -> Result<Self> {
+        let runtime = runtime()?;
+        let (room, events) =
+            runtime.block_on(GameRoom::create_with_config(logic, None, name, config))?;
+        Ok(Self {
+            room: Some(room),
+            events,
+            runtime,
+        })
+    }
+
+    /// Join a room in memory with a ticket, see [GameRoom::join_with_config].
+    pub fn join(logic: G, ticket: &str, config: RoomConfig) -> Result<Self> {
+        let runtime = runtime()?;
+        let (room, events) =
+            runtime.block_on(GameRoom::join_with_config(logic, ticket, None, config))?;
+        Ok(Self {
+            room: Some(room),
+            events,
+            runtime,
+        })
+    }
+
+    /// The room itself, for calls this wrapper does not cover, see [BlockingGameRoom::block_on].
+    pub fn room(&self) -> &GameRoom<G> {
+        self.room
+            .as_ref()
+            .expect("the room is only taken when it is left")
+    }
+
+    /// Run a future on the room's runtime until it completes, e.g.
+    /// `room.block_on(room.room().forfeit())`.
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    /// Get this peer's ID.
+    pub fn id(&self) -> EndpointId {
+        self.room().id()
+    }
+
+    /// Get the ticket other players join the room with.
+    pub fn ticket(&self) -> Result<RoomTicket> {
+        self.block_on(self.room().ticket())
+    }
+
+    /// Announce this peer's profile to the room.
+    pub fn announce_presence<I: Into<PeerProfile>>(&self, introduction: I) -> Result<()> {
+        self.block_on(self.room().announce_presence(introduction))
+    }
+
+    /// Update this peer's lobby readiness.
+    pub fn set_ready(&self, ready: bool) -> Result<()> {
+        self.block_on(self.room().set_ready(ready))
+    }
+
+    /// Start the game, if this peer is the host.
+    pub fn start_game(&self) -> Result<()> {
+        self.block_on(self.room().start_game())
+    }
+
+    /// Submit a game action, returning its sequence number, see [GameRoom::submit_action].
+    pub fn submit_action(&self, action: G::GameAction) -> Result<u64> {
+        self.block_on(self.room().submit_action(action))
+    }
+
+    /// Send a chat message to the room.
+    pub fn send_chat(&self, message: &str) -> Result<()> {
+        self.block_on(self.room().send_chat(message))
+    }
+
+    /// Get the current app state.
+    pub fn get_app_state(&self) -> Result<AppState<G>> {
+        self.block_on(self.room().get_app_state())
+    }
+
+    /// Get the latest host-authored game state.
+    pub fn get_game_state(&self) -> Result<G::GameState> {
+        self.block_on(self.room().get_game_state())
+    }
+
+    /// Get the room's peers.
+    pub fn get_peer_list(&self) -> Result<PeerMap> {
+        self.block_on(self.room().get_peer_list())
+    }
+
+    /// Take the next event if one is waiting, without blocking, e.g. once per frame.
+    ///
+    /// Returns `None` once the room has stopped as well.
+    pub fn try_event(&mut self) -> Option<UiEvent<G>> {
+        self.events.try_recv().ok()
+    }
+
+    /// Wait up to `timeout` for the next event.
+    ///
+    /// Returns `None` if nothing arrived in time, or the room has stopped.
+    pub fn poll_event(&mut self, timeout: Duration) -> Option<UiEvent<G>> {
+        let events = &mut self.events;
+        self.runtime
+            .block_on(async { tokio::time::timeout(timeout, events.recv()).await })
+            .ok()
+            .flatten()
+    }
+
+    /// Leave the room, telling the other peers why, and shut down its node.
+    ///
+    /// Returns whether another peer synced the announcement, see [GameRoom::leave].
+    pub fn leave(mut self, reason: &LeaveReason<G>) -> Result<bool> {
+        let room = self
+            .room
+            .take()
+            .ok_or_else(|| anyhow!("The room was already left"))?;
+        self.runtime.block_on(room.leave(reason, true))
+    }
+}
+
+/// Build the runtime a blocking room runs on, with worker threads that keep it syncing
+/// between calls.
+fn runtime() -> Result<Runtime> {
+    Ok(tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .thread_name("p2p-game-engine")
+        .build()?)
+}
@@ -0,0 +1,32 @@
+//! Structured diagnostics
+//!
+//! With the `tracing` feature, the engine reports what it does through the `tracing` crate:
+//! each room's event loop runs in a `room` span carrying the room and peer IDs, and entries,
+//! connections, host changes, actions and errors are reported as events within it.
+//!
+//! The macros here forward to `tracing`'s `debug!`, `info!` and `warn!` when the feature is on
+//! and expand to nothing when it is off, so instrumented code needs no `cfg` attributes of its
+//! own.
+
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        ::tracing::debug!($($arg)*);
+    };
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        ::tracing::info!($($arg)*);
+    };
+}
+
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        ::tracing::warn!($($arg)*);
+    };
+}
+
+pub(crate) use {debug, info, warning};
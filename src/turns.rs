@@ -0,0 +1,150 @@
+//! Turn order
+//!
+//! This module contains the `TurnManager` struct, an optional helper for the turn order
+//! bookkeeping most games need. Keep one in the game state and report it from
+//! [crate::GameLogic::turns], and the host publishes every change to it so peers receive a
+//! [crate::UiEvent::TurnChanged] and can query it with [crate::GameRoom::turns]. Peers whose
+//! own player gets the turn also receive a [crate::UiEvent::YourTurn].
+
+use iroh::EndpointId;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Whose turn it is, in what order players take turns, and how many rounds have been played.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TurnManager {
+    /// The seats, in the order they take turns when play is not reversed.
+    order: Vec<EndpointId>,
+    /// The index in `order` of the player whose turn it is.
+    current: usize,
+    /// Whether play runs backwards through `order`.
+    reversed: bool,
+    /// The current round, starting at 1. A round ends once play wraps around the order.
+    round: u32,
+    /// How long each player has for their turn, if the game enforces a limit.
+    #[serde(default)]
+    time_limit: Option<Duration>,
+}
+
+impl TurnManager {
+    /// Start the first round with the first player in `order` to play.
+    pub fn new(order: impl IntoIterator<Item = EndpointId>) -> Self {
+        Self {
+            order: order.into_iter().collect(),
+            current: 0,
+            reversed: false,
+            round: 1,
+            time_limit: None,
+        }
+    }
+
+    /// Tell players how long they have for each turn.
+    ///
+    /// The engine only reports the limit, as the deadline in [crate::UiEvent::YourTurn];
+    /// the game decides what happens when a turn runs out, e.g. in [crate::GameLogic::tick].
+    pub fn with_time_limit(mut self, limit: Duration) -> Self {
+        self.time_limit = Some(limit);
+        self
+    }
+
+    /// How long each player has for their turn, if the game set a limit.
+    pub fn time_limit(&self) -> Option<Duration> {
+        self.time_limit
+    }
+
+    /// The player whose turn it is, or `None` if nobody is left in the order.
+    pub fn current(&self) -> Option<EndpointId> {
+        self.order.get(self.current).copied()
+    }
+
+    /// Check whether it is a player's turn.
+    pub fn is_turn(&self, player: &EndpointId) -> bool {
+        self.current().as_ref() == Some(player)
+    }
+
+    /// The seats, in the order they take turns when play is not reversed.
+    pub fn order(&self) -> &[EndpointId] {
+        &self.order
+    }
+
+    /// The current round, starting at 1.
+    pub fn round(&self) -> u32 {
+        self.round
+    }
+
+    /// Check whether play runs backwards through the order.
+    pub fn is_reversed(&self) -> bool {
+        self.reversed
+    }
+
+    /// Pass the turn to the next player, starting a new round when play wraps around.
+    pub fn advance(&mut self) -> Option<EndpointId> {
+        if self.order.is_empty() {
+            return None;
+        }
+        let last = self.order.len() - 1;
+        self.current = match (self.reversed, self.current) {
+            (false, current) if current >= last => {
+                self.round += 1;
+                0
+            }
+            (false, current) => current + 1,
+            (true, 0) => {
+                self.round += 1;
+                last
+            }
+            (true, current) => current - 1,
+        };
+        self.current()
+    }
+
+    /// Pass the turn over the next player to the one after them.
+    pub fn skip(&mut self) -> Option<EndpointId> {
+        self.advance();
+        self.advance()
+    }
+
+    /// Reverse the direction of play. The current player keeps the turn.
+    pub fn reverse(&mut self) {
+        self.reversed = !self.reversed;
+    }
+
+    /// Give the turn to a particular player, if they are in the order.
+    pub fn set_current(&mut self, player: &EndpointId) -> bool {
+        match self.order.iter().position(|seat| seat == player) {
+            Some(index) => {
+                self.current = index;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Take a player out of the order, e.g. once they are eliminated.
+    ///
+    /// If it was their turn, the turn passes to whoever would have played next.
+    pub fn remove(&mut self, player: &EndpointId) {
+        let Some(index) = self.order.iter().position(|seat| seat == player) else {
+            return;
+        };
+        let had_turn = index == self.current;
+        self.order.remove(index);
+        if self.order.is_empty() {
+            self.current = 0;
+            return;
+        }
+        if index < self.current || (had_turn && self.reversed) {
+            // Everyone after the removed seat moved down one place.
+            match self.current {
+                0 => {
+                    self.current = self.order.len() - 1;
+                    self.round += 1;
+                }
+                current => self.current = current - 1,
+            }
+        } else if self.current >= self.order.len() {
+            self.current = 0;
+            self.round += 1;
+        }
+    }
+}
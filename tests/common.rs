@@ -1,13 +1,13 @@
-#![allow(dead_code)]
+#![allow(dead_code, clippy::collapsible_if, clippy::needless_borrow)]
 
 pub use p2p_game_engine::iroh::EndpointId;
 pub use p2p_game_engine::*;
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, time::Duration};
 use thiserror::Error;
-use tokio::{sync::mpsc, time::sleep};
+use tokio::sync::mpsc;
 
-#[derive(Debug, Error)]
+#[derive(Debug, Clone, Error, Serialize, Deserialize)]
 pub enum TestGameError {
     #[error("An unknown error occurred")]
     Unknown,
@@ -16,12 +16,23 @@ pub enum TestGameError {
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct TestGameState {
     pub counter: u32,
+    pub winner: Option<EndpointId>,
+    pub turns: Option<TurnManager>,
+    pub clock: Option<Duration>,
+    /// Players who are away from the game, in the order they left.
+    pub away: Vec<EndpointId>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TestGameAction {
     Increment,
     Reject,
+    Win,
+    /// Start taking turns in this order, with [TURN_TIME_LIMIT] for each. Each increment then
+    /// passes the turn on.
+    TakeTurns(Vec<EndpointId>),
+    /// Start a countdown that the host's ticks run down.
+    StartClock(Duration),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,13 +43,54 @@ pub enum TestPlayerRole {
 #[derive(Debug, Clone)]
 pub struct TestGame;
 
+/// TestGame's own phases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TestPhase {
+    Scoring,
+}
+
 impl GameLogic for TestGame {
     const GAME_NAME: &'static str = "TestGame";
+    const STATE_VERSION: u32 = 1;
     type GameState = TestGameState;
     type GameAction = TestGameAction;
     type PlayerRole = TestPlayerRole;
     type GameError = TestGameError;
     type PlayerLeaveReason = ();
+    type Phase = TestPhase;
+    type GameMessage = String;
+
+    fn filter_chat(
+        &self,
+        _sender: &PeerInfo,
+        phase: &AppState<Self>,
+        message: &ChatMessage,
+    ) -> ChatFilter {
+        match message.message.as_str() {
+            "spoiler" if *phase == AppState::InGame => ChatFilter::Block,
+            "darn" => ChatFilter::Replace("****".to_string()),
+            _ => ChatFilter::Allow,
+        }
+    }
+
+    fn canonical_state(&self, state: &Self::GameState) -> Option<Vec<u8>> {
+        postcard::to_stdvec(state).ok()
+    }
+
+    /// Version 0 of the state was just the counter.
+    fn migrate_state(version: u32, bytes: &[u8]) -> Option<Self::GameState> {
+        let counter = match version {
+            0 => WireFormat::Postcard.decode(bytes).ok()?,
+            _ => return None,
+        };
+        Some(TestGameState {
+            counter,
+            winner: None,
+            turns: None,
+            clock: None,
+            away: Vec::new(),
+        })
+    }
 
     fn assign_roles(
         &self,
@@ -63,38 +115,83 @@ impl GameLogic for TestGame {
         _players: &PeerMap,
         _roles: &HashMap<EndpointId, Self::PlayerRole>,
     ) -> Result<Self::GameState, Self::GameError> {
-        Ok(TestGameState { counter: 0 })
+        Ok(TestGameState {
+            counter: 0,
+            winner: None,
+            turns: None,
+            clock: None,
+            away: Vec::new(),
+        })
     }
 
     fn apply_action(
         &self,
         current_state: &mut Self::GameState,
-        _player_id: &EndpointId,
+        player_id: &EndpointId,
         action: &Self::GameAction,
     ) -> Result<(), Self::GameError> {
         match action {
             TestGameAction::Increment => {
                 current_state.counter += 1;
+                if let Some(turns) = current_state.turns.as_mut() {
+                    turns.advance();
+                }
                 Ok(())
             }
             TestGameAction::Reject => Err(TestGameError::Unknown),
+            TestGameAction::Win => {
+                current_state.winner = Some(*player_id);
+                Ok(())
+            }
+            TestGameAction::TakeTurns(order) => {
+                current_state.turns =
+                    Some(TurnManager::new(order.iter().copied()).with_time_limit(TURN_TIME_LIMIT));
+                Ok(())
+            }
+            TestGameAction::StartClock(duration) => {
+                current_state.clock = Some(*duration);
+                Ok(())
+            }
         }
     }
+
+    fn tick(&self, current_state: &Self::GameState, elapsed: Duration) -> Option<Self::GameState> {
+        let remaining = current_state.clock.filter(|clock| !clock.is_zero())?;
+        Some(TestGameState {
+            clock: Some(remaining.saturating_sub(elapsed)),
+            ..current_state.clone()
+        })
+    }
+
+    fn turns(&self, current_state: &Self::GameState) -> Option<TurnManager> {
+        current_state.turns.clone()
+    }
+
+    fn game_outcome(&self, current_state: &Self::GameState) -> Option<GameOutcome> {
+        let winner = current_state.winner?;
+        Some(
+            GameOutcome::new(FinishReason::Completed)
+                .winner(winner)
+                .score(winner, current_state.counter as i64),
+        )
+    }
     fn handle_player_disconnect(
         &self,
         _players: &mut PeerMap,
-        _player_id: &EndpointId,
-        _current_state: &mut Self::GameState,
+        player_id: &EndpointId,
+        current_state: &mut Self::GameState,
     ) -> Result<ConnectionEffect, Self::GameError> {
-        Ok(ConnectionEffect::NoChange)
+        current_state.away.push(*player_id);
+        Ok(ConnectionEffect::StateChanged)
     }
     fn handle_player_reconnect(
         &self,
         _players: &mut PeerMap,
-        _player_id: &EndpointId,
-        _current_state: &mut Self::GameState,
+        player_id: &EndpointId,
+        current_state: &mut Self::GameState,
     ) -> Result<ConnectionEffect, Self::GameError> {
-        Ok(ConnectionEffect::NoChange)
+        current_state.away.retain(|id| id != player_id);
+        Ok(ConnectionEffect::StateChanged)
     }
 
     fn handle_player_forfeit(
@@ -107,16 +204,32 @@ impl GameLogic for TestGame {
     }
 }
 
+/// How long each player has for their turn once the test game takes turns.
+pub const TURN_TIME_LIMIT: Duration = Duration::from_secs(60);
+
+/// Long timeout is to give reconnections time to happen.
+const WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+
 pub async fn await_event(
     event: &mut mpsc::Receiver<UiEvent<TestGame>>,
 ) -> anyhow::Result<UiEvent<TestGame>> {
-    // Long timeout is to give reconnections time to happen.
-    let duration = Duration::from_secs(30);
-    tokio::time::timeout(duration, event.recv())
+    tokio::time::timeout(WAIT_TIMEOUT, event.recv())
         .await?
         .ok_or_else(|| anyhow::anyhow!("Timed out waiting for event"))
 }
 
+/// Wait for the next event, skipping the granular peer events sent ahead of each peer list.
+pub async fn await_event_after_peer_changes(
+    events: &mut mpsc::Receiver<UiEvent<TestGame>>,
+) -> anyhow::Result<UiEvent<TestGame>> {
+    loop {
+        match await_event(events).await? {
+            UiEvent::PeerJoined(_) | UiEvent::PeerUpdated(_) | UiEvent::PeerLeft { .. } => {}
+            event => return Ok(event),
+        }
+    }
+}
+
 pub async fn setup_test_room(
     name: &str,
 ) -> anyhow::Result<(
@@ -132,7 +245,7 @@ pub async fn setup_test_room(
 
     println!("Announcing Host Presence");
     host_room.announce_presence(name).await?;
-    let event = await_event(&mut host_events).await?;
+    let event = await_event_after_peer_changes(&mut host_events).await?;
     println!("Received Host Lobby Update: {event}");
     let host_id = host_room.id();
     match event {
@@ -163,7 +276,7 @@ pub async fn setup_persistent_test_room(
 
     println!("Announcing Host Presence");
     host_room.announce_presence(name).await?;
-    let event = await_event(&mut host_events).await?;
+    let event = await_event_after_peer_changes(&mut host_events).await?;
     println!("Received Host Lobby Update: {event}");
     let host_id = host_room.id();
     match event {
@@ -185,7 +298,6 @@ pub async fn join_test_room(
     println!("Setting up Client Room");
     // Sometimes this fails, so we have a retry mechanic.
     let (client_room, mut client_events) = loop {
-        sleep(Duration::from_secs(1)).await;
         match GameRoom::join(TestGame, &ticket_string, None).await {
             Ok((room, events)) => break (room, events),
             Err(e) => {
@@ -236,17 +348,11 @@ pub async fn await_peer_list_count(
     room: &GameRoom<TestGame>,
     expected_players: usize,
 ) -> anyhow::Result<PeerMap> {
-    let duration = Duration::from_secs(30);
-    tokio::time::timeout(duration, async {
-        loop {
-            let players = room.get_peer_list().await?;
-            if players.len() == expected_players {
-                return Ok(players);
-            }
-            sleep(Duration::from_millis(100)).await;
-        }
+    testing::wait_until(room, WAIT_TIMEOUT, async |room| {
+        let players = room.get_peer_list().await?;
+        Ok((players.len() == expected_players).then_some(players))
     })
-    .await?
+    .await
 }
 
 pub async fn await_peer_ready(
@@ -254,71 +360,44 @@ pub async fn await_peer_ready(
     player_id: &EndpointId,
     expected_ready: bool,
 ) -> anyhow::Result<()> {
-    let duration = Duration::from_secs(30);
-    tokio::time::timeout(duration, async {
-        loop {
-            let players = room.get_peer_list().await?;
-            if players
-                .get(player_id)
-                .is_some_and(|player| player.ready == expected_ready)
-            {
-                return Ok(());
-            }
-            sleep(Duration::from_millis(100)).await;
-        }
+    testing::wait_until(room, WAIT_TIMEOUT, async |room| {
+        let players = room.get_peer_list().await?;
+        Ok(players
+            .get(player_id)
+            .is_some_and(|player| player.ready == expected_ready)
+            .then_some(()))
     })
-    .await?
+    .await
 }
 
 pub async fn await_room_app_state(
     room: &GameRoom<TestGame>,
-    expected_state: AppState,
+    expected_state: AppState<TestGame>,
 ) -> anyhow::Result<()> {
-    let duration = Duration::from_secs(30);
-    tokio::time::timeout(duration, async {
-        loop {
-            if room.get_app_state().await? == expected_state {
-                return anyhow::Ok(());
-            }
-            sleep(Duration::from_millis(100)).await;
-        }
+    testing::wait_until(room, WAIT_TIMEOUT, async |room| {
+        Ok((room.get_app_state().await? == expected_state).then_some(()))
     })
-    .await?
+    .await
 }
 
 pub async fn await_room_counter_state(
     room: &GameRoom<TestGame>,
     expected_counter: u32,
 ) -> anyhow::Result<()> {
-    let duration = Duration::from_secs(30);
-    tokio::time::timeout(duration, async {
-        loop {
-            if room.get_game_state().await?.counter == expected_counter {
-                return anyhow::Ok(());
-            }
-            sleep(Duration::from_millis(100)).await;
-        }
+    testing::wait_until(room, WAIT_TIMEOUT, async |room| {
+        Ok((room.get_game_state().await?.counter == expected_counter).then_some(()))
     })
-    .await?
+    .await
 }
 
-/// When a game starts we see two events (in non-deterministic order)
+/// Wait for the host to start the game, returning the state it started from.
 pub async fn await_game_start(
     events: &mut mpsc::Receiver<UiEvent<TestGame>>,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<TestGameState> {
     println!("Waiting for game to start...");
-    let mut has_seen_app_state_update = false;
-    let mut has_seen_game_state_update = false;
     loop {
-        match await_event(events).await? {
-            UiEvent::AppState(AppState::InGame) => {
-                has_seen_app_state_update = true;
-            }
-            UiEvent::GameState(..) => has_seen_game_state_update = true,
-            _ => {}
-        }
-        if has_seen_app_state_update && has_seen_game_state_update {
-            return Ok(());
+        if let UiEvent::GameStarted { state } = await_event(events).await? {
+            return Ok(state);
         }
     }
 }
@@ -329,7 +408,7 @@ pub async fn await_counter_state(
 ) -> anyhow::Result<()> {
     loop {
         let event = await_event(events).await?;
-        if let UiEvent::GameState(TestGameState { counter }) = event {
+        if let UiEvent::GameState(TestGameState { counter, .. }) = event {
             if counter == expected_counter {
                 return Ok(());
             }
@@ -337,16 +416,26 @@ pub async fn await_counter_state(
     }
 }
 
-pub async fn await_action_result(
+/// Wait for the host to accept one of our actions, returning its sequence number and action id.
+pub async fn await_action_accepted(
     events: &mut mpsc::Receiver<UiEvent<TestGame>>,
-    accepted: bool,
-) -> anyhow::Result<ActionResult> {
+) -> anyhow::Result<(u64, String)> {
     loop {
         let event = await_event(events).await?;
-        if let UiEvent::ActionResult(result) = event
-            && result.accepted == accepted
-        {
-            return Ok(result);
+        if let UiEvent::ActionAccepted { seq, action_id, .. } = event {
+            return Ok((seq, action_id));
+        }
+    }
+}
+
+/// Wait for the host to reject one of our actions, returning its sequence number and error.
+pub async fn await_action_rejected(
+    events: &mut mpsc::Receiver<UiEvent<TestGame>>,
+) -> anyhow::Result<(u64, ActionError<TestGameError>)> {
+    loop {
+        let event = await_event(events).await?;
+        if let UiEvent::ActionRejected { seq, error, .. } = event {
+            return Ok((seq, error));
         }
     }
 }
@@ -415,3 +504,59 @@ pub async fn await_lobby_ready_update(
         }
     }
 }
+
+/// Spawn `N` peers on `network`, the first of them hosting, see [testing::SimNetwork::spawn].
+pub async fn spawn_sim_peers<G: GameLogic + Clone, const N: usize>(
+    network: &testing::SimNetwork,
+    logic: G,
+) -> anyhow::Result<[testing::SimPeer<G>; N]> {
+    let peers = network.spawn(logic, N).await?;
+    peers
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Expected {N} peers"))
+}
+
+/// Ready every peer, and wait until the host, the first of them, has seen it.
+pub async fn ready_sim_peers<G: GameLogic>(peers: &[&testing::SimPeer<G>]) -> anyhow::Result<()> {
+    for peer in peers {
+        peer.room.set_ready(true).await?;
+    }
+    peers[0]
+        .wait_until(async |room| {
+            let peers = room.get_peer_list().await?;
+            Ok(peers.values().all(|peer| peer.ready).then_some(()))
+        })
+        .await
+}
+
+/// Spawn `N` peers on `network` and start a game between them, the first of them hosting.
+pub async fn start_sim_game<G: GameLogic + Clone, const N: usize>(
+    network: &testing::SimNetwork,
+    logic: G,
+) -> anyhow::Result<[testing::SimPeer<G>; N]> {
+    let peers = spawn_sim_peers(network, logic).await?;
+    ready_sim_peers(&peers.each_ref()).await?;
+    peers[0].room.start_game().await?;
+    for peer in &peers {
+        peer.wait_until(async |room| {
+            Ok(matches!(room.get_app_state().await?, AppState::InGame).then_some(()))
+        })
+        .await?;
+    }
+    Ok(peers)
+}
+
+/// Keep sending with `send` until `receive` gets something, within half a second each time,
+/// as messages sent before the gossip swarm has formed are lost.
+pub async fn resend_until<T>(
+    mut send: impl AsyncFnMut() -> anyhow::Result<()>,
+    mut receive: impl AsyncFnMut() -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    loop {
+        send().await?;
+        let wait = Duration::from_millis(500);
+        if let Ok(received) = tokio::time::timeout(wait, receive()).await {
+            return received;
+        }
+    }
+}
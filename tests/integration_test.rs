@@ -1,9 +1,12 @@
 //! This is the basic test for setting up rooms and exchanging basic information between them.
 
+#![allow(clippy::await_holding_lock)]
+
 mod common;
 use common::*;
+use iroh_docs::engine::LiveEvent;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use thiserror::Error;
 
 static PERSISTENT_ROOM_TEST_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
@@ -56,15 +59,35 @@ async fn test_full_game_lifecycle() -> anyhow::Result<()> {
     // Host starts the game
     host_room.start_game().await?;
 
-    // Client should receive a GameStarted and a GameState Updated event.
-    await_game_start(&mut client_events).await?;
+    // Client should receive a single GameStarted event, by which time the room is in game.
+    let started = await_game_start(&mut client_events).await?;
+    assert_eq!(started.counter, 0);
+    assert_eq!(client_room.get_app_state().await?, AppState::InGame);
 
     // Query the state directly
     let initial_state = client_room.get_game_state().await?;
-    assert_eq!(initial_state, TestGameState { counter: 0 });
+    assert_eq!(
+        initial_state,
+        TestGameState {
+            counter: 0,
+            winner: None,
+            turns: None,
+            clock: None,
+            away: Vec::new(),
+        }
+    );
     let game_snapshot = client_room.snapshot().await?;
     assert_eq!(game_snapshot.app_state, AppState::InGame);
-    assert_eq!(game_snapshot.game_state, Some(TestGameState { counter: 0 }));
+    assert_eq!(
+        game_snapshot.game_state,
+        Some(TestGameState {
+            counter: 0,
+            winner: None,
+            turns: None,
+            clock: None,
+            away: Vec::new(),
+        })
+    );
     println!("Client direct query of initial game state successful.");
 
     // --- ACTION PHASE ---
@@ -74,12 +97,20 @@ async fn test_full_game_lifecycle() -> anyhow::Result<()> {
     client_room.submit_action(TestGameAction::Increment).await?;
 
     await_counter_state(&mut client_events, 1).await?;
-    let result = await_action_result(&mut client_events, true).await?;
-    assert!(result.error.is_none());
+    await_action_accepted(&mut client_events).await?;
 
     // Query the final state
     let final_state = client_room.get_game_state().await?;
-    assert_eq!(final_state, TestGameState { counter: 1 });
+    assert_eq!(
+        final_state,
+        TestGameState {
+            counter: 1,
+            winner: None,
+            turns: None,
+            clock: None,
+            away: Vec::new(),
+        }
+    );
     println!("Client direct query of final game state successful.");
 
     Ok(())
@@ -114,9 +145,10 @@ async fn test_invalid_action_returns_action_result() -> anyhow::Result<()> {
     host_room.start_game().await?;
     await_game_start(&mut client_events).await?;
 
-    client_room.submit_action(TestGameAction::Reject).await?;
-    let result = await_action_result(&mut client_events, false).await?;
-    assert!(result.error.is_some());
+    let submitted = client_room.submit_action(TestGameAction::Reject).await?;
+    let (seq, error) = await_action_rejected(&mut client_events).await?;
+    assert_eq!(seq, submitted);
+    assert!(matches!(error, ActionError::Game(TestGameError::Unknown)));
     assert_eq!(client_room.get_game_state().await?.counter, 0);
     Ok(())
 }
@@ -141,7 +173,7 @@ async fn test_enter_lobby_defaults_to_not_ready() -> anyhow::Result<()> {
     let (room, mut events) = GameRoom::create(TestGame, None, None).await?;
 
     room.enter_lobby("host").await?;
-    let event = await_event(&mut events).await?;
+    let event = await_event_after_peer_changes(&mut events).await?;
 
     match event {
         UiEvent::Peer(players) => {
@@ -155,6 +187,35 @@ async fn test_enter_lobby_defaults_to_not_ready() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_rapid_actions_are_applied_in_sequence() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, _host_id, mut host_events) = setup_test_room("host").await?;
+    let (client_room, mut client_events) = join_test_room("client", &ticket_string, 3).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+    host_room.start_game().await?;
+    await_game_start(&mut client_events).await?;
+
+    let mut submitted = Vec::new();
+    for _ in 0..10 {
+        submitted.push(client_room.submit_action(TestGameAction::Increment).await?);
+    }
+    assert_eq!(submitted, (1..=10).collect::<Vec<u64>>());
+    await_room_counter_state(&host_room, 10).await?;
+
+    let mut acknowledged = Vec::new();
+    while acknowledged.len() < submitted.len() {
+        if let UiEvent::ActionAccepted { seq, .. } = await_event(&mut client_events).await?
+            && !acknowledged.contains(&seq)
+        {
+            acknowledged.push(seq);
+        }
+    }
+    acknowledged.sort();
+    assert_eq!(acknowledged, submitted);
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_processed_actions_are_not_replayed_after_host_reconnect() -> anyhow::Result<()> {
     let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
@@ -169,26 +230,140 @@ async fn test_processed_actions_are_not_replayed_after_host_reconnect() -> anyho
 
     host_room.submit_action(TestGameAction::Increment).await?;
     await_room_counter_state(&host_room, 1).await?;
-    let result = await_action_result(&mut host_events, true).await?;
-    assert!(result.error.is_none());
+    await_action_accepted(&mut host_events).await?;
 
-    drop(host_room);
+    host_room.stop().await?;
 
     let (reconnected_host, mut reconnected_host_events) =
         GameRoom::join(TestGame, &ticket_string, Some(host_dir)).await?;
     assert_eq!(reconnected_host.id(), host_id);
     assert_eq!(reconnected_host.get_game_state().await?.counter, 1);
 
-    reconnected_host
+    // The action sequence carries on from the earlier session.
+    let seq = reconnected_host
         .submit_action(TestGameAction::Increment)
         .await?;
+    assert_eq!(seq, 2);
     await_room_counter_state(&reconnected_host, 2).await?;
-    let result = await_action_result(&mut reconnected_host_events, true).await?;
-    assert!(result.error.is_none());
+    let (seq, _) = await_action_accepted(&mut reconnected_host_events).await?;
+    assert_eq!(seq, 2);
     assert_eq!(reconnected_host.get_game_state().await?.counter, 2);
     Ok(())
 }
 
+#[tokio::test]
+async fn test_unapplied_actions_are_replayed_after_host_restart() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let host_temp = tempfile::tempdir()?;
+    let host_dir = host_temp.path().to_path_buf();
+    let (host_room, ticket_string, _host_id, _host_events) =
+        setup_persistent_test_room("host", host_dir.clone()).await?;
+
+    host_room.start_game().await?;
+    await_room_counter_state(&host_room, 0).await?;
+
+    // Go down straight after the request lands, before the host can finish applying it.
+    host_room.submit_action(TestGameAction::Increment).await?;
+    host_room.stop().await?;
+
+    let (restarted_host, mut restarted_events) =
+        GameRoom::join(TestGame, &ticket_string, Some(host_dir)).await?;
+    await_room_counter_state(&restarted_host, 1).await?;
+
+    // The journalled action is applied exactly once, and new actions carry on after it.
+    let seq = restarted_host
+        .submit_action(TestGameAction::Increment)
+        .await?;
+    assert_eq!(seq, 2);
+    loop {
+        let (accepted, _) = await_action_accepted(&mut restarted_events).await?;
+        if accepted == seq {
+            break;
+        }
+    }
+    assert_eq!(restarted_host.get_game_state().await?.counter, 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_old_rooms_can_be_listed_and_deleted() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let host_temp = tempfile::tempdir()?;
+    let host_dir = host_temp.path().to_path_buf();
+    let (old_room, _old_ticket, _host_id, _old_events) =
+        setup_persistent_test_room("host", host_dir.clone()).await?;
+    let old_id = old_room
+        .ticket()
+        .await?
+        .doc_ticket
+        .capability
+        .id()
+        .to_string();
+    old_room.stop().await?;
+
+    let (room, _ticket, _host_id, _events) =
+        setup_persistent_test_room("host", host_dir.clone()).await?;
+    let id = room.ticket().await?.doc_ticket.capability.id().to_string();
+    let rooms = room.list_rooms().await?;
+    let listed: Vec<_> = rooms.iter().map(|stored| stored.room_id.clone()).collect();
+    assert_eq!(listed, vec![id.clone(), old_id.clone()]);
+    assert!(
+        rooms
+            .iter()
+            .all(|stored| stored.game == TestGame::GAME_NAME)
+    );
+    assert!(rooms.iter().all(|stored| stored.size > 0));
+    assert!(room.storage_usage().await? > 0);
+
+    // A room cannot delete itself from under its own feet.
+    assert!(room.delete_room(&id).await.is_err());
+    room.delete_room(&old_id).await?;
+    let listed: Vec<_> = room
+        .list_rooms()
+        .await?
+        .into_iter()
+        .map(|stored| stored.room_id)
+        .collect();
+    assert_eq!(listed, vec![id]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_deleting_a_room_keeps_other_rooms_content() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let host_temp = tempfile::tempdir()?;
+    let host_dir = host_temp.path().to_path_buf();
+    let artwork = vec![7u8; 64 * 1024];
+    let (old_room, _old_ticket, _host_id, _old_events) =
+        setup_persistent_test_room("host", host_dir.clone()).await?;
+    old_room.publish_asset("artwork", artwork.clone()).await?;
+    let old_id = old_room
+        .ticket()
+        .await?
+        .doc_ticket
+        .capability
+        .id()
+        .to_string();
+    old_room.stop().await?;
+
+    // Both rooms keep the same content, each under its own name.
+    let (room, _ticket, _host_id, _events) =
+        setup_persistent_test_room("host", host_dir.clone()).await?;
+    room.publish_asset("artwork", artwork.clone()).await?;
+    let rooms = room.list_rooms().await?;
+    assert_eq!(rooms.len(), 2);
+    assert!(
+        rooms
+            .iter()
+            .all(|stored| stored.size >= artwork.len() as u64)
+    );
+
+    room.delete_room(&old_id).await?;
+    assert_eq!(room.list_rooms().await?.len(), 1);
+    assert_eq!(room.fetch_asset("artwork").await?, artwork);
+    Ok(())
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 struct HostObserverState {
     started: bool,
@@ -203,7 +378,7 @@ enum HostObserverRole {
     Observer,
 }
 
-#[derive(Debug, Error)]
+#[derive(Debug, Clone, Error, Serialize, Deserialize)]
 enum HostObserverError {
     #[error("no player")]
     NoPlayer,
@@ -218,6 +393,8 @@ impl GameLogic for HostObserverGame {
     type GameAction = HostObserverAction;
     type PlayerRole = HostObserverRole;
     type PlayerLeaveReason = ();
+    type Phase = ();
+    type GameMessage = ();
     type GameError = HostObserverError;
 
     fn is_observer_role(&self, role: &Self::PlayerRole) -> bool {
@@ -315,7 +492,11 @@ async fn test_readiness_only_blocks_assigned_players() -> anyhow::Result<()> {
     tokio::time::timeout(std::time::Duration::from_secs(30), player_events.recv()).await?;
     tokio::time::timeout(std::time::Duration::from_secs(30), async {
         loop {
-            if host_room.get_peer_list().await?.len() == 2 {
+            let player_sees_both = player_room
+                .get_peer_list()
+                .await
+                .is_ok_and(|peers| peers.len() == 2);
+            if host_room.get_peer_list().await?.len() == 2 && player_sees_both {
                 return anyhow::Ok(());
             }
             tokio::time::sleep(std::time::Duration::from_millis(100)).await;
@@ -396,7 +577,7 @@ async fn test_online_host_claim_is_rejected() -> anyhow::Result<()> {
     Ok(())
 }
 
-#[derive(Debug, Error)]
+#[derive(Debug, Clone, Error, Serialize, Deserialize)]
 enum StartBlockedError {
     #[error("start blocked")]
     StartBlocked,
@@ -420,6 +601,8 @@ impl GameLogic for StartBlockedGame {
     type GameAction = StartBlockedAction;
     type PlayerRole = StartBlockedRole;
     type PlayerLeaveReason = ();
+    type Phase = ();
+    type GameMessage = ();
     type GameError = StartBlockedError;
 
     fn assign_roles(
@@ -505,3 +688,3927 @@ async fn test_join_rejects_wrong_game_type() -> anyhow::Result<()> {
     assert!(result.is_err());
     Ok(())
 }
+
+#[tokio::test]
+async fn test_leave_notifies_host_and_shuts_down() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (_host_room, ticket_string, _host_id, mut host_events) = setup_test_room("host").await?;
+    let (client_room, _client_events) = join_test_room("client", &ticket_string, 3).await?;
+    let client_id = client_room.id();
+    await_lobby_contains(&mut host_events, &client_id).await?;
+
+    client_room
+        .leave(&LeaveReason::ApplicationClosed, true)
+        .await?;
+    await_lobby_status_update(&mut host_events, &client_id, PeerStatus::Offline).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_leave_reports_that_the_announcement_synced() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (_host_room, ticket_string, _host_id, mut host_events) = setup_test_room("host").await?;
+    let (client_room, _client_events) = join_test_room("client", &ticket_string, 3).await?;
+    await_lobby_contains(&mut host_events, &client_room.id()).await?;
+
+    let synced = client_room
+        .leave(&LeaveReason::ApplicationClosed, true)
+        .await?;
+    assert!(synced, "the host was online to sync the leave with");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_persistent_store_reuses_author_across_rooms() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let temp = tempfile::tempdir()?;
+    let path = temp.path().to_path_buf();
+
+    let (first_room, _ticket, first_id, _events) =
+        setup_persistent_test_room("host", path.clone()).await?;
+    let author_id = first_room.get_peer_list().await?[&first_id].author_id;
+    first_room
+        .leave(&LeaveReason::ApplicationClosed, true)
+        .await?;
+
+    let (second_room, _ticket, second_id, _events) =
+        setup_persistent_test_room("host", path.clone()).await?;
+    assert_eq!(
+        second_room.get_peer_list().await?[&second_id].author_id,
+        author_id
+    );
+    second_room
+        .leave(&LeaveReason::ApplicationClosed, true)
+        .await?;
+
+    let (fresh_room, mut fresh_events) = GameRoom::create_with_config(
+        TestGame,
+        Some(path),
+        None,
+        RoomConfig::default().reuse_author(false),
+    )
+    .await?;
+    fresh_room.announce_presence("host").await?;
+    await_lobby_contains(&mut fresh_events, &fresh_room.id()).await?;
+    assert_ne!(
+        fresh_room.get_peer_list().await?[&fresh_room.id()].author_id,
+        author_id
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_identity_is_reused_across_rooms() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let temp = tempfile::tempdir()?;
+
+    let mut identity = Identity::load_or_create(temp.path(), "alice").await?;
+    let (first_room, mut first_events) =
+        GameRoom::create_with_identity(TestGame, &mut identity, None, RoomConfig::default())
+            .await?;
+    assert_eq!(first_room.id(), identity.id());
+    await_lobby_contains(&mut first_events, &identity.id()).await?;
+    let first_peer = first_room.get_peer_list().await?[&identity.id()].clone();
+    assert_eq!(first_peer.profile.nickname, "alice");
+    first_room
+        .leave(&LeaveReason::ApplicationClosed, true)
+        .await?;
+
+    // The nickname argument only seeds a brand new identity.
+    let mut identity = Identity::load_or_create(temp.path(), "bob").await?;
+    assert_eq!(identity.profile.nickname, "alice");
+    assert_eq!(identity.stats.games_hosted, 1);
+    let (second_room, mut second_events) =
+        GameRoom::create_with_identity(TestGame, &mut identity, None, RoomConfig::default())
+            .await?;
+    await_lobby_contains(&mut second_events, &identity.id()).await?;
+    let second_peer = second_room.get_peer_list().await?[&identity.id()].clone();
+    assert_eq!(second_peer.author_id, first_peer.author_id);
+    assert_eq!(second_peer.profile, first_peer.profile);
+    assert_eq!(identity.stats.games_hosted, 2);
+    Ok(())
+}
+
+/// Keeps the node's key, author and room secrets in memory, standing in for an OS keychain.
+#[derive(Debug, Default)]
+struct MemoryKeyStore {
+    key: std::sync::Mutex<Option<p2p_game_engine::iroh::SecretKey>>,
+    author: std::sync::Mutex<Option<iroh_docs::Author>>,
+    room_secrets: std::sync::Mutex<HashMap<iroh_docs::NamespaceId, RoomSecret>>,
+}
+
+impl KeyStore for MemoryKeyStore {
+    fn load_secret_key(&self) -> anyhow::Result<Option<p2p_game_engine::iroh::SecretKey>> {
+        Ok(self.key.lock().unwrap().clone())
+    }
+
+    fn save_secret_key(&self, key: &p2p_game_engine::iroh::SecretKey) -> anyhow::Result<()> {
+        *self.key.lock().unwrap() = Some(key.clone());
+        Ok(())
+    }
+
+    fn load_author(&self) -> anyhow::Result<Option<iroh_docs::Author>> {
+        Ok(self.author.lock().unwrap().clone())
+    }
+
+    fn save_author(&self, author: &iroh_docs::Author) -> anyhow::Result<()> {
+        *self.author.lock().unwrap() = Some(author.clone());
+        Ok(())
+    }
+
+    fn load_room_secret(
+        &self,
+        room: &iroh_docs::NamespaceId,
+    ) -> anyhow::Result<Option<RoomSecret>> {
+        Ok(self.room_secrets.lock().unwrap().get(room).cloned())
+    }
+
+    fn save_room_secret(
+        &self,
+        room: &iroh_docs::NamespaceId,
+        secret: &RoomSecret,
+    ) -> anyhow::Result<()> {
+        self.room_secrets
+            .lock()
+            .unwrap()
+            .insert(*room, secret.clone());
+        Ok(())
+    }
+}
+
+/// Opens the node's stores under file names of its own, as a sandboxed app might.
+#[derive(Debug)]
+struct SandboxStorage {
+    dir: std::path::PathBuf,
+}
+
+impl CustomStorage for SandboxStorage {
+    fn open(&self) -> n0_future::boxed::BoxFuture<anyhow::Result<OpenStorage>> {
+        let dir = self.dir.clone();
+        Box::pin(async move {
+            let blobs = iroh_blobs::store::fs::FsStore::load(dir.join("blobs")).await?;
+            Ok(OpenStorage {
+                blobs: blobs.into(),
+                docs: iroh_docs::store::Store::persistent(dir.join("rooms.redb"))?,
+            })
+        })
+    }
+
+    fn data_dir(&self) -> Option<&std::path::Path> {
+        Some(&self.dir)
+    }
+}
+
+#[tokio::test]
+async fn test_rooms_can_use_custom_storage() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let temp = tempfile::tempdir()?;
+    let storage = SandboxStorage {
+        dir: temp.path().to_path_buf(),
+    };
+    let network =
+        IrohConfig::default().storage(StorageBackend::Custom(std::sync::Arc::new(storage)));
+    let config = RoomConfig::default().network(network);
+    let (host_room, mut host_events) =
+        GameRoom::create_with_config(TestGame, None, None, config).await?;
+    host_room.announce_presence("host").await?;
+    await_lobby_contains(&mut host_events, &host_room.id()).await?;
+    assert!(temp.path().join("rooms.redb").exists());
+    assert!(temp.path().join("keypair").exists());
+
+    let ticket_string = host_room.ticket().await?.to_string();
+    let (client_room, _client_events) = join_test_room("client", &ticket_string, 3).await?;
+    await_peer_list_count(&host_room, 2).await?;
+    assert_eq!(client_room.get_peer_list().await?.len(), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_identity_keys_can_live_in_a_key_store() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let key_store = std::sync::Arc::new(MemoryKeyStore::default());
+    let temp = tempfile::tempdir()?;
+
+    let mut identity =
+        Identity::load_or_create_with_key_store(temp.path(), "alice", key_store.clone()).await?;
+    let (first_room, mut first_events) =
+        GameRoom::create_with_identity(TestGame, &mut identity, None, RoomConfig::default())
+            .await?;
+    assert_eq!(first_room.id(), identity.id());
+    await_lobby_contains(&mut first_events, &identity.id()).await?;
+    let author_id = first_room.get_peer_list().await?[&identity.id()].author_id;
+    first_room
+        .leave(&LeaveReason::ApplicationClosed, true)
+        .await?;
+    assert!(!temp.path().join("keypair").exists());
+    let saved = key_store.load_secret_key()?.expect("the key was saved");
+    assert_eq!(saved.public(), identity.id());
+
+    // The key store carries the identity over to a fresh data directory.
+    let other_temp = tempfile::tempdir()?;
+    let network = IrohConfig::default().key_store(key_store);
+    let config = RoomConfig::default().network(network);
+    let (second_room, mut second_events) = GameRoom::create_with_config(
+        TestGame,
+        Some(other_temp.path().to_path_buf()),
+        None,
+        config,
+    )
+    .await?;
+    second_room.announce_presence("alice").await?;
+    await_lobby_contains(&mut second_events, &identity.id()).await?;
+    assert_eq!(second_room.id(), identity.id());
+    assert_eq!(
+        second_room.get_peer_list().await?[&identity.id()].author_id,
+        author_id
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_actions_can_be_annotated() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (room, _ticket_string, _host_id, mut events) = setup_test_room("host").await?;
+    room.start_game().await?;
+    await_room_app_state(&room, AppState::InGame).await?;
+
+    room.submit_action(TestGameAction::Increment).await?;
+    let (_, action_id) = await_action_accepted(&mut events).await?;
+
+    room.annotate_action(&action_id, "!?").await?;
+    loop {
+        if let UiEvent::Annotation(annotation) = await_event(&mut events).await? {
+            assert_eq!(annotation.action_id, action_id);
+            assert_eq!(annotation.text, "!?");
+            break;
+        }
+    }
+    let annotations = room.get_annotations(Some(&action_id)).await?;
+    assert_eq!(annotations.len(), 1);
+    assert_eq!(annotations[0].from, room.id());
+    assert!(room.get_annotations(Some("missing")).await?.is_empty());
+
+    let too_long = "x".repeat(MAX_ANNOTATION_LEN + 1);
+    assert!(room.annotate_action(&action_id, &too_long).await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_inactive_game_is_warned_then_finished() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let policy = InactivityPolicy::new(
+        std::time::Duration::from_millis(300),
+        std::time::Duration::from_millis(900),
+    );
+    let (room, mut events) = GameRoom::create_with_config(
+        TestGame,
+        None,
+        None,
+        RoomConfig::default().inactivity(policy),
+    )
+    .await?;
+    room.announce_presence("host").await?;
+    await_lobby_contains(&mut events, &room.id()).await?;
+    room.set_ready(true).await?;
+    room.start_game().await?;
+
+    let mut warned = false;
+    loop {
+        match await_event(&mut events).await? {
+            UiEvent::InactivityWarning { finishes_in } => {
+                assert!(finishes_in <= policy.finish_after);
+                warned = true;
+            }
+            UiEvent::GameOver(result) => {
+                assert_eq!(result.reason, FinishReason::Inactivity);
+                break;
+            }
+            _ => {}
+        }
+    }
+    assert!(warned);
+    assert_eq!(room.get_app_state().await?, AppState::Finished);
+    let result = room
+        .get_result()
+        .await?
+        .expect("finished game has a result");
+    assert_eq!(result.reason, FinishReason::Inactivity);
+    assert!(result.winners.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_finished_game_reports_result() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, host_id, mut host_events) = setup_test_room("host").await?;
+    let (client_room, mut client_events) = join_test_room("client", &ticket_string, 3).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+    host_room.start_game().await?;
+    await_game_start(&mut client_events).await?;
+    assert!(host_room.get_result().await?.is_none());
+
+    host_room.submit_action(TestGameAction::Increment).await?;
+    await_room_counter_state(&host_room, 1).await?;
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    host_room.submit_action(TestGameAction::Win).await?;
+    await_room_app_state(&host_room, AppState::Finished).await?;
+
+    let result = host_room
+        .get_result()
+        .await?
+        .expect("finished game has a result");
+    assert_eq!(result.reason, FinishReason::Completed);
+    assert_eq!(result.winners, vec![host_id]);
+    assert_eq!(result.scores.get(&host_id), Some(&1));
+    assert!(result.duration > std::time::Duration::ZERO);
+
+    // Peers are told the result along with the finish, without reading it back.
+    let reported = loop {
+        match await_event(&mut client_events).await? {
+            UiEvent::GameOver(result) => break result,
+            UiEvent::AppState(AppState::Finished) => panic!("finish reported apart from result"),
+            _ => {}
+        }
+    };
+    assert_eq!(reported, result);
+    assert_eq!(client_room.get_app_state().await?, AppState::Finished);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_finished_game_reports_player_stats() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, host_id, mut host_events) = setup_test_room("host").await?;
+    let (client_room, mut client_events) = join_test_room("client", &ticket_string, 3).await?;
+    let client_id = client_room.id();
+    await_lobby_ready_update(&mut host_events, &client_id, true).await?;
+    // Lobby chat is not part of the game.
+    client_room.send_chat("good luck").await?;
+    host_room.start_game().await?;
+    await_game_start(&mut client_events).await?;
+
+    client_room.submit_action(TestGameAction::Increment).await?;
+    await_room_counter_state(&host_room, 1).await?;
+    let stats = host_room.stats().await?;
+    assert_eq!(stats.actions, 1);
+    assert_eq!(stats.players[&client_id].actions, 1);
+    assert_eq!(stats.players[&host_id].actions, 0);
+    assert_eq!(stats.players[&host_id].average_think_time(), None);
+
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    host_room.submit_action(TestGameAction::Win).await?;
+    let result = loop {
+        if let UiEvent::GameOver(result) = await_event(&mut client_events).await? {
+            break result;
+        }
+    };
+    assert_eq!(result.stats.actions, 2);
+    let host = &result.stats.players[&host_id];
+    assert_eq!(host.actions, 1);
+    assert!(host.average_think_time() >= Some(std::time::Duration::from_millis(10)));
+    let client = &result.stats.players[&client_id];
+    assert_eq!((client.actions, client.chat_messages), (1, 0));
+    assert_eq!(client.disconnects, 0);
+    assert_eq!(client_room.stats().await?, result.stats);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_finished_games_are_kept_in_match_history() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let host_temp = tempfile::tempdir()?;
+    let (host_room, ticket_string, host_id, mut host_events) =
+        setup_persistent_test_room("host", host_temp.path().to_path_buf()).await?;
+    let (client_room, mut client_events) = join_test_room("client", &ticket_string, 3).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+    assert!(host_room.match_history().await?.is_empty());
+    host_room.start_game().await?;
+    await_game_start(&mut client_events).await?;
+
+    host_room.submit_action(TestGameAction::Win).await?;
+    let result = loop {
+        if let UiEvent::GameOver(result) = await_event(&mut host_events).await? {
+            break result;
+        }
+    };
+    let history = host_room.match_history().await?;
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].game, TestGame::GAME_NAME);
+    assert_eq!(history[0].result, result);
+    assert!(history[0].players.contains_key(&client_room.id()));
+    let actions = host_room.get_action_log(..).await?;
+    assert_eq!(
+        history[0].action_log_hash,
+        iroh_blobs::Hash::new(postcard::to_stdvec(&actions)?)
+    );
+    assert_eq!(result.winners, vec![host_id]);
+
+    // The history outlives the room, and reads the same through the identity.
+    host_room
+        .leave(&LeaveReason::ApplicationClosed, true)
+        .await?;
+    let identity = Identity::load_or_create(host_temp.path(), "host").await?;
+    assert_eq!(identity.match_history().await?, history);
+
+    // Rooms in memory keep no history.
+    assert!(client_room.match_history().await?.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_dedicated_host_runs_games_without_playing() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let policy = DedicatedPolicy::new(2).reopen_after(std::time::Duration::from_millis(300));
+    let host_room =
+        GameRoom::create_dedicated(TestGame, RoomConfig::default().dedicated(policy)).await?;
+    let ticket_string = host_room.ticket().await?.to_string();
+
+    // One ready player is not enough to start.
+    let (first_room, mut first_events) = join_test_room("first", &ticket_string, 3).await?;
+    await_peer_ready(&host_room, &first_room.id(), true).await?;
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+    assert_eq!(host_room.get_app_state().await?, AppState::Lobby);
+
+    let (second_room, _second_events) = join_test_room("second", &ticket_string, 3).await?;
+    await_game_start(&mut first_events).await?;
+    let players = host_room.get_peer_list().await?;
+    assert_eq!(players.len(), 2);
+    assert!(!players.contains_key(&host_room.id()));
+
+    first_room.submit_action(TestGameAction::Win).await?;
+    await_room_app_state(&second_room, AppState::Finished).await?;
+    // The room comes back to the lobby for the next game, with nobody ready yet.
+    await_room_app_state(&second_room, AppState::Lobby).await?;
+    await_peer_ready(&host_room, &first_room.id(), false).await?;
+    await_peer_ready(&host_room, &second_room.id(), false).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_chat_filter_blocks_and_rewrites_messages() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, _host_id, mut host_events) = setup_test_room("host").await?;
+    let (client_room, mut client_events) = join_test_room("client", &ticket_string, 3).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+
+    client_room.send_chat("darn").await?;
+    loop {
+        if let UiEvent::Chat { msg, .. } = await_event(&mut host_events).await? {
+            assert_eq!(msg.message, "****");
+            break;
+        }
+    }
+
+    host_room.start_game().await?;
+    await_game_start(&mut client_events).await?;
+    let result = client_room.send_chat("spoiler").await;
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "Chat is not allowed right now"
+    );
+
+    let history = host_room.get_chat_history().await?;
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].message, "****");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_chat_messages_can_be_edited_reacted_and_deleted() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, _host_id, mut host_events) = setup_test_room("host").await?;
+    let (client_room, _client_events) = join_test_room("client", &ticket_string, 3).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+
+    client_room.send_chat("helo").await?;
+    let message = loop {
+        if let UiEvent::Chat { msg, .. } = await_event(&mut host_events).await? {
+            break msg;
+        }
+    };
+    assert!(host_room.edit_chat(&message.id, "hijack").await.is_err());
+
+    client_room.edit_chat(&message.id, "hello").await?;
+    host_room.react(&message.id, "👍").await?;
+    let mut seen_edit = false;
+    let mut seen_reaction = false;
+    while !(seen_edit && seen_reaction) {
+        match await_event(&mut host_events).await? {
+            UiEvent::ChatUpdated(ChatUpdate::Edited(msg)) => {
+                assert_eq!(msg.message, "hello");
+                seen_edit = true;
+            }
+            UiEvent::ChatUpdated(ChatUpdate::Reacted(reaction)) => {
+                assert_eq!(reaction.emoji, "👍");
+                seen_reaction = true;
+            }
+            _ => {}
+        }
+    }
+    assert_eq!(host_room.get_chat_history().await?[0].message, "hello");
+    assert_eq!(host_room.get_chat_reactions(&message.id).await?.len(), 1);
+
+    client_room.delete_chat(&message.id).await?;
+    loop {
+        if let UiEvent::ChatUpdated(ChatUpdate::Deleted { id }) =
+            await_event(&mut host_events).await?
+        {
+            assert_eq!(id, message.id);
+            break;
+        }
+    }
+    assert!(host_room.get_chat_history().await?.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_chat_written_under_another_peers_name_is_ignored() -> anyhow::Result<()> {
+    let network = testing::SimNetwork::new();
+    let [mut host, mallory] = spawn_sim_peers(&network, TestGame).await?;
+
+    host.room.send_chat("hello").await?;
+    let message = host
+        .expect_event(|event| match event {
+            UiEvent::Chat { msg, .. } => Some(msg),
+            _ => None,
+        })
+        .await?;
+    mallory
+        .wait_until(async |room| Ok(room.get_chat_history().await?.pop()))
+        .await?;
+
+    // Mallory edits the host's message, reacts and speaks in their name, with her own author.
+    let mut edit = message.clone();
+    edit.message = "I resign".to_string();
+    edit.edited = true;
+    mallory.forge_chat(&edit).await?;
+    mallory
+        .forge_chat(&ChatMessage::new(host.id(), "gg, you win")?)
+        .await?;
+    mallory
+        .forge_reaction(&ChatReaction::new(host.id(), &message.id, "👎")?)
+        .await?;
+    mallory.room.react(&message.id, "👍").await?;
+
+    let reaction = host
+        .expect_event(|event| match event {
+            UiEvent::Chat { msg, .. } => panic!("Forged message shown: {}", msg.message),
+            UiEvent::ChatUpdated(ChatUpdate::Edited(msg)) => {
+                panic!("Forged edit shown: {}", msg.message)
+            }
+            UiEvent::ChatUpdated(ChatUpdate::Reacted(reaction)) => Some(reaction),
+            _ => None,
+        })
+        .await?;
+    assert_eq!(
+        (reaction.from, reaction.emoji.as_str()),
+        (mallory.id(), "👍")
+    );
+    let history = host.room.get_chat_history().await?;
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].message, "hello");
+    let reactions = host.room.get_chat_reactions(&message.id).await?;
+    assert_eq!(reactions.len(), 1);
+    assert_eq!(reactions[0].from, mallory.id());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_chat_is_ordered_by_logical_clock() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, _host_id, mut host_events) = setup_test_room("host").await?;
+    let (client_room, mut client_events) = join_test_room("client", &ticket_string, 3).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+
+    host_room.send_chat("first").await?;
+    let first = loop {
+        if let UiEvent::Chat { msg, .. } = await_event(&mut client_events).await? {
+            break msg;
+        }
+    };
+    // A reply always reads later on the clock than what it answers.
+    client_room.send_chat("reply").await?;
+    let reply = loop {
+        if let UiEvent::Chat { msg, .. } = await_event(&mut host_events).await?
+            && msg.message == "reply"
+        {
+            break msg;
+        }
+    };
+    assert!(reply.clock > first.clock);
+
+    let host_history = host_room.get_chat_history().await?;
+    let client_history = client_room.get_chat_history().await?;
+    assert_eq!(host_history, client_history);
+    let order: Vec<_> = host_history
+        .iter()
+        .map(|msg| msg.message.as_str())
+        .collect();
+    assert_eq!(order, ["first", "reply"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_avatar_is_shared_via_blobs() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, _host_id, mut host_events) = setup_test_room("host").await?;
+    let (client_room, _client_events) = join_test_room("client", &ticket_string, 3).await?;
+    let client_id = client_room.id();
+    await_lobby_ready_update(&mut host_events, &client_id, true).await?;
+    assert_eq!(host_room.fetch_avatar(&client_id).await?, None);
+
+    let image = b"\x89PNG not really an image".to_vec();
+    client_room.set_avatar(image.clone()).await?;
+
+    let duration = std::time::Duration::from_secs(30);
+    tokio::time::timeout(duration, async {
+        loop {
+            let players = host_room.get_peer_list().await?;
+            if players
+                .get(&client_id)
+                .is_some_and(|player| player.profile.avatar_blob.is_some())
+            {
+                return anyhow::Ok(());
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+    })
+    .await??;
+
+    let fetched = host_room.fetch_avatar(&client_id).await?;
+    assert_eq!(fetched.as_deref(), Some(image.as_slice()));
+    // Served from the cache on repeat lookups.
+    assert_eq!(host_room.fetch_avatar(&client_id).await?, fetched);
+    assert_eq!(
+        client_room.get_peer_list().await?[&client_id]
+            .profile
+            .nickname,
+        "client"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_oversized_avatars_are_refused() -> anyhow::Result<()> {
+    let network = testing::SimNetwork::new();
+    let [host, client] = spawn_sim_peers(&network, TestGame).await?;
+    let image = vec![0u8; MAX_AVATAR_BYTES + 1];
+    assert!(client.room.set_avatar(image.clone()).await.is_err());
+
+    // A peer that shares one anyway is never read.
+    client.forge_avatar(image).await?;
+    let client_id = client.id();
+    host.wait_until(async |room| {
+        let peers = room.get_peer_list().await?;
+        Ok(peers
+            .get(&client_id)
+            .and_then(|peer| peer.profile.avatar_blob))
+    })
+    .await?;
+    assert!(host.room.fetch_avatar(&client_id).await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_chat_can_be_skipped_by_headless_peers() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, _host_id, mut host_events) = setup_test_room("host").await?;
+    assert!(host_room.ticket().await?.chat_ticket.is_some());
+
+    let (bot_room, mut bot_events) = GameRoom::join_with_config(
+        TestGame,
+        &ticket_string,
+        None,
+        RoomConfig::default().chat(false),
+    )
+    .await?;
+    bot_room.announce_presence("bot").await?;
+    await_lobby_contains(&mut bot_events, &bot_room.id()).await?;
+    bot_room.set_ready(true).await?;
+    await_lobby_ready_update(&mut host_events, &bot_room.id(), true).await?;
+    assert!(bot_room.ticket().await?.chat_ticket.is_none());
+
+    let result = bot_room.send_chat("hello").await;
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "Chat is disabled for this room"
+    );
+    host_room.send_chat("anyone there?").await?;
+    assert_eq!(host_room.get_chat_history().await?.len(), 1);
+    assert!(bot_room.get_chat_history().await?.is_empty());
+
+    // Game state still syncs without the chat document.
+    host_room.start_game().await?;
+    await_game_start(&mut bot_events).await?;
+    bot_room.submit_action(TestGameAction::Increment).await?;
+    await_counter_state(&mut host_events, 1).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_room_ticket_is_validated_before_joining() -> anyhow::Result<()> {
+    let (host_room, _host_events) = GameRoom::create(TestGame, None, Some("Friday Night")).await?;
+    let ticket = host_room.ticket().await?;
+    assert_eq!(ticket.room_name, "Friday Night");
+    assert_eq!(ticket.visibility, RoomVisibility::Private);
+
+    let parsed = RoomTicket::parse(&ticket.to_string())?;
+    assert_eq!(parsed.game_type, ticket.game_type);
+    assert!(parsed.validate_for(&TestGame).is_ok());
+
+    let error = GameRoom::join(TestGame, "not a ticket", None)
+        .await
+        .err()
+        .unwrap();
+    assert!(matches!(
+        error.downcast_ref::<TicketError>(),
+        Some(TicketError::Malformed(_))
+    ));
+
+    let mut other_game = parsed.clone();
+    other_game.game_type = "SomeOtherGame".to_string();
+    let error = GameRoom::join(TestGame, &other_game.to_string(), None)
+        .await
+        .err()
+        .unwrap();
+    assert!(matches!(
+        error.downcast_ref::<TicketError>(),
+        Some(TicketError::GameMismatch { .. })
+    ));
+
+    let mut old_protocol = parsed;
+    old_protocol.protocol_version = 0;
+    assert!(matches!(
+        old_protocol.validate_for(&TestGame),
+        Err(TicketError::ProtocolMismatch { found: 0, .. })
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_game_state_bursts_are_coalesced_for_slow_consumers() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (room, _ticket_string, _host_id, mut events) = setup_test_room("host").await?;
+    room.start_game().await?;
+    await_room_app_state(&room, AppState::InGame).await?;
+
+    // Fill the event channel without reading it.
+    let actions = 60;
+    for _ in 0..actions {
+        room.submit_action(TestGameAction::Increment).await?;
+    }
+    await_room_counter_state(&room, actions).await?;
+    // The host is still working through the entries its own writes produced.
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+
+    let mut game_states = Vec::new();
+    let mut action_results = 0;
+    while let Ok(Some(event)) =
+        tokio::time::timeout(std::time::Duration::from_millis(500), events.recv()).await
+    {
+        match event {
+            UiEvent::GameState(state) => game_states.push(state.counter),
+            UiEvent::ActionAccepted { .. } => action_results += 1,
+            _ => {}
+        }
+    }
+    // Every action result is kept, but intermediate states are skipped.
+    assert_eq!(action_results, actions);
+    assert!(game_states.len() < actions as usize);
+    assert_eq!(game_states.last(), Some(&actions));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_hot_seat_players_share_one_endpoint() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, _host_id, mut host_events) = setup_test_room("host").await?;
+    let (client_room, mut client_events) = GameRoom::join(TestGame, &ticket_string, None).await?;
+    let seats = client_room
+        .announce_presence_multi(["Alice", "Bob"])
+        .await?;
+    assert_eq!(seats.len(), 2);
+    assert_eq!(seats[0], client_room.id());
+    let bob = seats[1];
+
+    let players = await_peer_list_count(&client_room, 3).await?;
+    assert_eq!(players[&bob].profile.nickname, "Bob");
+    assert_eq!(players[&bob].seat_of, Some(client_room.id()));
+    assert_eq!(client_room.seats().await?, seats);
+    // Only the endpoint a seat belongs to can act for it.
+    assert!(host_room.set_ready_as(&bob, true).await.is_err());
+
+    for seat in &seats {
+        client_room.set_ready_as(seat, true).await?;
+        await_lobby_ready_update(&mut host_events, seat, true).await?;
+    }
+    host_room.start_game().await?;
+    await_game_start(&mut client_events).await?;
+
+    let seq = client_room
+        .submit_action_as(&bob, TestGameAction::Increment)
+        .await?;
+    loop {
+        if let UiEvent::ActionAccepted {
+            player,
+            seq: accepted,
+            ..
+        } = await_event(&mut client_events).await?
+        {
+            assert_eq!((player, accepted), (bob, seq));
+            break;
+        }
+    }
+    // Each seat keeps its own action sequence.
+    assert_eq!(client_room.submit_action(TestGameAction::Win).await?, 1);
+    await_room_app_state(&host_room, AppState::Finished).await?;
+    let result = host_room.get_result().await?.unwrap();
+    assert_eq!(result.winners, vec![client_room.id()]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rehost_invites_the_group_to_a_new_room() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, host_id, mut host_events) = setup_test_room("host").await?;
+    let (client_room, mut client_events) = join_test_room("client", &ticket_string, 3).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+    assert!(GameRoom::rehost_from(&host_room).await.is_err());
+
+    host_room.start_game().await?;
+    await_game_start(&mut client_events).await?;
+    client_room.submit_action(TestGameAction::Win).await?;
+    await_room_app_state(&host_room, AppState::Finished).await?;
+    assert!(GameRoom::rehost_from(&client_room).await.is_err());
+
+    let (next_room, _next_events) = GameRoom::rehost_from(&host_room).await?;
+    assert_eq!(next_room.id(), host_id);
+    assert_eq!(next_room.name, host_room.name);
+    let ticket = loop {
+        if let UiEvent::Rehosted { ticket } = await_event(&mut client_events).await? {
+            break ticket;
+        }
+    };
+    assert!(client_room.get_next_room().await?.is_some());
+
+    let (next_client, _next_client_events) = join_test_room("client", &ticket, 3).await?;
+    let players = await_peer_list_count(&next_client, 2).await?;
+    assert_eq!(players[&host_id].profile.nickname, "host");
+    assert_eq!(next_client.get_app_state().await?, AppState::Lobby);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_host_places_peers_on_teams() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, host_id, mut host_events) = setup_test_room("host").await?;
+    let (client_room, mut client_events) = join_test_room("client", &ticket_string, 3).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+    let client_id = client_room.id();
+
+    assert!(
+        client_room
+            .set_team(&client_id, Some(TeamId(0)))
+            .await
+            .is_err()
+    );
+    host_room.set_team(&host_id, Some(TeamId(0))).await?;
+    host_room.set_team(&client_id, Some(TeamId(1))).await?;
+    loop {
+        if let UiEvent::TeamChanged { peer, team } = await_event(&mut client_events).await?
+            && peer == client_id
+        {
+            assert_eq!(team, Some(TeamId(1)));
+            break;
+        }
+    }
+
+    // Lobby teams are kept when the game starts.
+    host_room.start_game().await?;
+    await_game_start(&mut client_events).await?;
+    let players = client_room.get_peer_list().await?;
+    assert_eq!(players[&host_id].team, Some(TeamId(0)));
+    assert_eq!(players[&client_id].team, Some(TeamId(1)));
+    assert_eq!(players.team(TeamId(1)).count(), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_host_promotes_and_demotes_players() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, _host_id, mut host_events) = setup_test_room("host").await?;
+    let (client_room, mut client_events) = join_test_room("client", &ticket_string, 3).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+    let client_id = client_room.id();
+
+    // Peers who join in the lobby get a place, until the host takes it away.
+    assert!(!host_room.get_peer_list().await?[&client_id].is_observer);
+    assert!(client_room.demote_to_observer(&client_id).await.is_err());
+    host_room.demote_to_observer(&client_id).await?;
+    await_lobby_observer_update(&mut client_events, &client_id, true).await?;
+
+    host_room.start_game().await?;
+    await_game_start(&mut client_events).await?;
+    let result = client_room.submit_action(TestGameAction::Increment).await;
+    assert_eq!(result.unwrap_err().to_string(), "Peer is an observer");
+
+    client_room.request_seat().await?;
+    loop {
+        if let UiEvent::SeatRequested { peer } = await_event(&mut host_events).await? {
+            assert_eq!(peer, client_id);
+            break;
+        }
+    }
+    host_room.promote_to_player(&client_id).await?;
+    await_lobby_observer_update(&mut client_events, &client_id, false).await?;
+    assert!(client_room.request_seat().await.is_err());
+    client_room.submit_action(TestGameAction::Increment).await?;
+    await_counter_state(&mut host_events, 1).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_custom_entries_cannot_use_engine_keys() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    assert!(is_engine_key(b"game_state"));
+    assert!(is_engine_key(b"action.some-peer.1"));
+    assert!(!is_engine_key(b"house_rules"));
+    assert!(ENGINE_KEY_PREFIXES.contains(&b"peer.".as_slice()));
+
+    let (host_room, ticket_string, _host_id, _host_events) = setup_test_room("host").await?;
+    let (client_room, _client_events) = join_test_room("client", &ticket_string, 3).await?;
+    let error = host_room
+        .set_custom_entry(b"peer.someone", "spoofed")
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Key 'peer.someone' is reserved by the engine"
+    );
+
+    host_room
+        .set_custom_entry(b"house_rules", "no takebacks")
+        .await?;
+    tokio::time::timeout(std::time::Duration::from_secs(30), async {
+        while client_room
+            .get_custom_entry(b"house_rules")
+            .await?
+            .is_none()
+        {
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        anyhow::Ok(())
+    })
+    .await??;
+    let rules = client_room.get_custom_entry(b"house_rules").await?.unwrap();
+    assert_eq!(rules.as_ref(), b"no takebacks");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_host_marks_silent_peers_offline() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let policy = HeartbeatPolicy::new(std::time::Duration::from_millis(200), 3);
+    let config = RoomConfig::default().heartbeat(policy);
+    let (host_room, mut host_events) =
+        GameRoom::create_with_config(TestGame, None, None, config.clone()).await?;
+    host_room.announce_presence("host").await?;
+    await_lobby_contains(&mut host_events, &host_room.id()).await?;
+    let ticket_string = host_room.ticket().await?.to_string();
+
+    let (client_room, mut client_events) =
+        GameRoom::join_with_config(TestGame, &ticket_string, None, config).await?;
+    client_room.announce_presence("client").await?;
+    await_lobby_contains(&mut host_events, &client_room.id()).await?;
+    await_lobby_contains(&mut client_events, &client_room.id()).await?;
+
+    // A peer that keeps beating stays online well past the timeout.
+    tokio::time::sleep(policy.interval * policy.missed_beats * 2).await;
+    let peers = host_room.get_peer_list().await?;
+    assert_eq!(
+        peers.get(&client_room.id()).unwrap().status,
+        PeerStatus::Online
+    );
+
+    // Dropping the receiver stops the client's event loop, and with it the heartbeats,
+    // while its endpoint stays connected.
+    drop(client_events);
+    await_lobby_status_update(&mut host_events, &client_room.id(), PeerStatus::Offline).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_network_stats_report_connected_peers() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let config = RoomConfig::default().network_stats(std::time::Duration::from_millis(200));
+    let (host_room, mut host_events) =
+        GameRoom::create_with_config(TestGame, None, None, config).await?;
+    host_room.announce_presence("host").await?;
+    await_lobby_contains(&mut host_events, &host_room.id()).await?;
+    let ticket_string = host_room.ticket().await?.to_string();
+    let (client_room, _client_events) = join_test_room("client", &ticket_string, 3).await?;
+
+    let client_id = client_room.id();
+    let stats = loop {
+        if let UiEvent::NetworkStats(stats) = await_event(&mut host_events).await?
+            && let Some(stats) = stats.into_iter().find(|stats| stats.peer == client_id)
+        {
+            break stats;
+        }
+    };
+    assert!(stats.bytes_sent > 0);
+    assert!(stats.bytes_received > 0);
+
+    let on_demand = host_room.network_stats().await?;
+    assert!(on_demand.iter().any(|stats| stats.peer == client_id));
+    assert!(on_demand.iter().all(|stats| stats.peer != host_room.id()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_connection_info_shows_the_path_to_a_peer() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let config = RoomConfig::default().network_stats(std::time::Duration::from_millis(200));
+    let (host_room, mut host_events) =
+        GameRoom::create_with_config(TestGame, None, None, config).await?;
+    host_room.announce_presence("host").await?;
+    await_lobby_contains(&mut host_events, &host_room.id()).await?;
+    let ticket_string = host_room.ticket().await?.to_string();
+    let (client_room, _client_events) = join_test_room("client", &ticket_string, 3).await?;
+
+    let client_id = client_room.id();
+    let path = loop {
+        if let UiEvent::ConnectionPathChanged {
+            peer,
+            path: Some(path),
+        } = await_event(&mut host_events).await?
+            && peer == client_id
+        {
+            break path;
+        }
+    };
+    let info = host_room.connection_info(&client_id).await?;
+    assert_eq!(info.stats.peer, client_id);
+    assert!(info.stats.path.is_some());
+    if let ConnectionPath::Direct(addr) = path {
+        assert!(info.addrs.contains(&addr));
+    }
+    assert!(info.stats.last_activity.is_some());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rooms_connect_without_relays_or_discovery() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let network = IrohConfig::default()
+        .relay_mode(p2p_game_engine::iroh::RelayMode::Disabled)
+        .public_discovery(false)
+        .bind_v4(std::net::SocketAddrV4::new(
+            std::net::Ipv4Addr::LOCALHOST,
+            0,
+        ))
+        .bind_v6(None);
+    let config = RoomConfig::default().network(network);
+    let (host_room, mut host_events) =
+        GameRoom::create_with_config(TestGame, None, None, config.clone()).await?;
+    host_room.announce_presence("host").await?;
+    await_lobby_contains(&mut host_events, &host_room.id()).await?;
+    host_room.set_ready(true).await?;
+    let ticket_string = host_room.ticket().await?.to_string();
+
+    let (client_room, mut client_events) =
+        GameRoom::join_with_config(TestGame, &ticket_string, None, config).await?;
+    client_room.announce_presence("client").await?;
+    await_lobby_contains(&mut client_events, &client_room.id()).await?;
+    client_room.set_ready(true).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+
+    host_room.start_game().await?;
+    await_game_start(&mut client_events).await?;
+    client_room.submit_action(TestGameAction::Increment).await?;
+    await_counter_state(&mut host_events, 1).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_peers_open_streams_on_game_protocols() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let config = RoomConfig::default().network(IrohConfig::lan().stream_protocol("echo"));
+    let (host_room, mut host_events) =
+        GameRoom::create_with_config(TestGame, None, None, config.clone()).await?;
+    host_room.announce_presence("host").await?;
+    await_lobby_contains(&mut host_events, &host_room.id()).await?;
+    let ticket = host_room.ticket().await?.to_string();
+    let (client_room, mut client_events) =
+        GameRoom::join_with_config(TestGame, &ticket, None, config).await?;
+    client_room.announce_presence("client").await?;
+    await_lobby_contains(&mut client_events, &client_room.id()).await?;
+    await_lobby_contains(&mut host_events, &client_room.id()).await?;
+
+    assert!(host_room.accept_streams("voice").is_err());
+    let mut incoming = host_room.accept_streams("echo")?;
+    let echo = tokio::spawn(async move {
+        let mut stream = incoming.recv().await.expect("a stream");
+        let request = stream.recv.read_to_end(1024).await?;
+        stream.send.write_all(&request).await?;
+        stream.send.finish()?;
+        anyhow::Ok((stream.peer, stream.protocol))
+    });
+
+    let mut stream = client_room.open_stream(&host_room.id(), "echo").await?;
+    stream.send.write_all(b"ping").await?;
+    stream.send.finish()?;
+    let reply = tokio::time::timeout(
+        std::time::Duration::from_secs(10),
+        stream.recv.read_to_end(1024),
+    )
+    .await??;
+    assert_eq!(reply, b"ping");
+    let (peer, protocol) = echo.await??;
+    assert_eq!((peer, protocol.as_str()), (client_room.id(), "echo"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_lan_rooms_hand_out_direct_addresses() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let config = RoomConfig::default().network(IrohConfig::lan());
+    assert!(config.network.is_lan_only());
+    let (host_room, mut host_events) =
+        GameRoom::create_with_config(TestGame, None, None, config.clone()).await?;
+    host_room.announce_presence("host").await?;
+    await_lobby_contains(&mut host_events, &host_room.id()).await?;
+
+    let ticket = host_room.ticket().await?;
+    for addr in &ticket.doc_ticket.nodes {
+        assert!(addr.ip_addrs().next().is_some());
+        assert!(addr.relay_urls().next().is_none());
+    }
+
+    let (client_room, mut client_events) =
+        GameRoom::join_with_config(TestGame, &ticket.to_string(), None, config).await?;
+    client_room.announce_presence("client").await?;
+    await_lobby_contains(&mut client_events, &client_room.id()).await?;
+    await_lobby_contains(&mut host_events, &client_room.id()).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_host_compacts_old_chat_and_actions() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let policy = CompactionPolicy::new(std::time::Duration::from_secs(3600), 2)
+        .every(std::time::Duration::from_millis(200));
+    let (room, mut events) = GameRoom::create_with_config(
+        TestGame,
+        None,
+        None,
+        RoomConfig::default().compaction(policy),
+    )
+    .await?;
+    room.announce_presence("host").await?;
+    await_lobby_contains(&mut events, &room.id()).await?;
+    room.set_ready(true).await?;
+    room.start_game().await?;
+    for count in 1..=5 {
+        room.submit_action(TestGameAction::Increment).await?;
+        await_counter_state(&mut events, count).await?;
+        room.send_chat(&format!("message {count}")).await?;
+    }
+
+    tokio::time::timeout(std::time::Duration::from_secs(30), async {
+        loop {
+            let stats = room.doc_stats().await?;
+            if room.get_chat_history().await?.len() <= 2 && stats.action_entries <= 6 {
+                return anyhow::Ok(());
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+    })
+    .await??;
+    let history = room.get_chat_history().await?;
+    assert!(history.iter().any(|msg| msg.message == "message 5"));
+
+    // Sequence numbers carry on after the journal has been trimmed.
+    room.submit_action(TestGameAction::Increment).await?;
+    await_counter_state(&mut events, 6).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_chat_is_compacted_on_its_own_policy() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let policy = CompactionPolicy::new(std::time::Duration::from_secs(3600), 100)
+        .chat(std::time::Duration::from_secs(3600), 1)
+        .every(std::time::Duration::from_millis(200));
+    let (room, mut events) = GameRoom::create_with_config(
+        TestGame,
+        None,
+        None,
+        RoomConfig::default().compaction(policy),
+    )
+    .await?;
+    room.announce_presence("host").await?;
+    await_lobby_contains(&mut events, &room.id()).await?;
+    room.set_ready(true).await?;
+    room.start_game().await?;
+    for count in 1..=3 {
+        room.submit_action(TestGameAction::Increment).await?;
+        await_counter_state(&mut events, count).await?;
+        room.send_chat(&format!("message {count}")).await?;
+    }
+
+    tokio::time::timeout(std::time::Duration::from_secs(30), async {
+        while room.get_chat_history().await?.len() > 1 {
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        anyhow::Ok(())
+    })
+    .await??;
+    assert_eq!(room.get_chat_history().await?[0].message, "message 3");
+    // The game document keeps its whole journal.
+    assert!(room.doc_stats().await?.action_entries >= 3);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_large_game_states_sync_through_blobs() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let config = RoomConfig::default().state_blob_threshold(0);
+    let (host_room, mut host_events) =
+        GameRoom::create_with_config(TestGame, None, None, config.clone()).await?;
+    host_room.announce_presence("host").await?;
+    await_lobby_contains(&mut host_events, &host_room.id()).await?;
+    host_room.set_ready(true).await?;
+    let ticket_string = host_room.ticket().await?.to_string();
+
+    let (client_room, mut client_events) =
+        GameRoom::join_with_config(TestGame, &ticket_string, None, config).await?;
+    client_room.announce_presence("client").await?;
+    await_lobby_contains(&mut client_events, &client_room.id()).await?;
+    client_room.set_ready(true).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+
+    host_room.start_game().await?;
+    await_game_start(&mut client_events).await?;
+    client_room.submit_action(TestGameAction::Increment).await?;
+    await_counter_state(&mut host_events, 1).await?;
+    await_counter_state(&mut client_events, 1).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_joiners_use_the_hosts_compression() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let config = RoomConfig::default()
+        .compression(Compression::Lz4)
+        .state_blob_threshold(0);
+    let (host_room, mut host_events) =
+        GameRoom::create_with_config(TestGame, None, None, config).await?;
+    host_room.announce_presence("host").await?;
+    await_lobby_contains(&mut host_events, &host_room.id()).await?;
+    host_room.set_ready(true).await?;
+    let ticket_string = host_room.ticket().await?.to_string();
+
+    // The client doesn't ask for compression, it picks it up from the room.
+    let (client_room, mut client_events) = GameRoom::join(TestGame, &ticket_string, None).await?;
+    client_room.announce_presence("client").await?;
+    await_lobby_contains(&mut client_events, &client_room.id()).await?;
+    client_room.set_ready(true).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+
+    let before = client_room.doc_stats().await?.blob_bytes;
+    let long_message = "all work and no play ".repeat(500);
+    client_room.send_chat(&long_message).await?;
+    loop {
+        if let UiEvent::Chat { msg, .. } = await_event(&mut host_events).await? {
+            assert_eq!(msg.message, long_message);
+            break;
+        }
+    }
+    let added = client_room.doc_stats().await?.blob_bytes - before;
+    assert!(added < long_message.len() as u64 / 4);
+
+    host_room.start_game().await?;
+    await_game_start(&mut client_events).await?;
+    client_room.submit_action(TestGameAction::Increment).await?;
+    await_counter_state(&mut host_events, 1).await?;
+    await_counter_state(&mut client_events, 1).await?;
+    Ok(())
+}
+
+const COMPRESSIONS: [Compression; 3] = [Compression::None, Compression::Lz4, Compression::Zstd];
+
+/// Pseudo-random bytes, the same for every run.
+fn noise(seed: u64, len: usize) -> Vec<u8> {
+    let mut state = seed.max(1);
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+#[test]
+fn test_compression_round_trips() -> anyhow::Result<()> {
+    let mut values = vec![
+        Vec::new(),
+        b"a".to_vec(),
+        b"all work and no play".to_vec(),
+        b"all work and no play ".repeat(5000),
+        vec![0; 100_000],
+    ];
+    for len in [1, 4, 5, 12, 13, 15, 16, 255, 256, 270, 65_536, 70_000] {
+        values.push(noise(len as u64, len));
+        // Runs of noise repeated, so matches overlap what they copy.
+        values.push(noise(len as u64, len.min(7)).repeat(len / 7 + 1));
+    }
+    for compression in COMPRESSIONS {
+        for value in &values {
+            let packed = compression.encode(value)?;
+            assert_eq!(compression.decode(packed.into())?, value.as_slice());
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_compression_never_panics_on_corrupt_values() -> anyhow::Result<()> {
+    for compression in COMPRESSIONS {
+        for seed in 1..500 {
+            let garbage = noise(seed, (seed % 64) as usize);
+            if let Ok(value) = compression.decode(garbage.into()) {
+                assert!(value.len() <= MAX_DECOMPRESSED_LEN);
+            }
+
+            // Flip a byte of, or cut short, a value that did decompress.
+            let original = noise(seed, 64).repeat(8);
+            let mut packed = compression.encode(&original)?;
+            let at = seed as usize % packed.len();
+            packed[at] ^= seed as u8 | 1;
+            compression.decode(packed.clone().into()).ok();
+            packed.truncate(at);
+            compression.decode(packed.into()).ok();
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_compressed_values_expanding_past_the_limit_are_refused() -> anyhow::Result<()> {
+    // An LZ4 value states its size up front.
+    let mut huge = u32::MAX.to_le_bytes().to_vec();
+    huge.extend_from_slice(&[0x1f, 0]);
+    assert!(Compression::Lz4.decode(huge.into()).is_err());
+    let mut wrong_size = Compression::Lz4.encode(b"all work and no play")?;
+    wrong_size[0] += 1;
+    assert!(Compression::Lz4.decode(wrong_size.into()).is_err());
+
+    // So does a Zstandard frame, and one that doesn't is refused too.
+    let oversized = vec![0; MAX_DECOMPRESSED_LEN + 1];
+    let packed = Compression::Zstd.encode(&oversized)?;
+    assert!(Compression::Zstd.decode(packed.into()).is_err());
+    let mut unstated = Vec::new();
+    let mut encoder = zstd::stream::Encoder::new(&mut unstated, 0)?;
+    encoder.include_contentsize(false)?;
+    std::io::Write::write_all(&mut encoder, b"all work and no play")?;
+    encoder.finish()?;
+    assert!(Compression::Zstd.decode(unstated.into()).is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_joiners_use_the_hosts_wire_format() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    for wire_format in [WireFormat::Json, WireFormat::Cbor] {
+        let config = RoomConfig::default()
+            .wire_format(wire_format)
+            .state_blob_threshold(0);
+        let (host_room, mut host_events) =
+            GameRoom::create_with_config(TestGame, None, None, config).await?;
+        host_room.announce_presence("host").await?;
+        await_lobby_contains(&mut host_events, &host_room.id()).await?;
+        host_room.set_ready(true).await?;
+        let ticket_string = host_room.ticket().await?.to_string();
+
+        // The client doesn't ask for a format, it picks it up from the room.
+        let (client_room, mut client_events) =
+            GameRoom::join(TestGame, &ticket_string, None).await?;
+        client_room.announce_presence("client").await?;
+        await_lobby_contains(&mut client_events, &client_room.id()).await?;
+        client_room.set_ready(true).await?;
+        await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+
+        client_room.send_chat("hello").await?;
+        loop {
+            if let UiEvent::Chat { msg, .. } = await_event(&mut host_events).await? {
+                assert_eq!(msg.message, "hello");
+                break;
+            }
+        }
+
+        host_room.start_game().await?;
+        await_game_start(&mut client_events).await?;
+        client_room.submit_action(TestGameAction::Increment).await?;
+        await_counter_state(&mut host_events, 1).await?;
+        await_counter_state(&mut client_events, 1).await?;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_encrypted_rooms_need_the_secret() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let secret = RoomSecret::generate();
+    let config = RoomConfig::default().secret(secret.clone());
+    let (host_room, mut host_events) =
+        GameRoom::create_with_config(TestGame, None, None, config).await?;
+    host_room.announce_presence("host").await?;
+    await_lobby_contains(&mut host_events, &host_room.id()).await?;
+    host_room.set_ready(true).await?;
+    let ticket = host_room.ticket().await?;
+    assert!(ticket.secret.is_none());
+
+    // A leaked ticket, or a guessed secret, is not enough to get in.
+    let leaked = GameRoom::join(TestGame, &ticket.to_string(), None).await;
+    assert!(leaked.is_err());
+    let config = RoomConfig::default().secret(RoomSecret::new("hunter2"));
+    let guessed = GameRoom::join_with_config(TestGame, &ticket.to_string(), None, config).await;
+    assert!(guessed.is_err());
+
+    let invite = ticket.with_secret(secret).to_string();
+    let (client_room, mut client_events) = GameRoom::join(TestGame, &invite, None).await?;
+    client_room.announce_presence("client").await?;
+    await_lobby_contains(&mut client_events, &client_room.id()).await?;
+    client_room.set_ready(true).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+
+    client_room.send_chat("only for us").await?;
+    loop {
+        if let UiEvent::Chat { msg, .. } = await_event(&mut host_events).await? {
+            assert_eq!(msg.message, "only for us");
+            break;
+        }
+    }
+    host_room.start_game().await?;
+    await_game_start(&mut client_events).await?;
+    client_room.submit_action(TestGameAction::Increment).await?;
+    await_counter_state(&mut host_events, 1).await?;
+    await_counter_state(&mut client_events, 1).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_encrypted_values_cannot_be_moved_to_another_key() -> anyhow::Result<()> {
+    let network = testing::SimNetwork::new()
+        .room_config(RoomConfig::default().secret(RoomSecret::new("hunter2")));
+    let [host] = spawn_sim_peers(&network, TestGame).await?;
+    host.room
+        .set_custom_entry(b"house_rules", "no takebacks")
+        .await?;
+    host.forge_copy(b"house_rules", b"table_rules").await?;
+    assert!(host.room.get_custom_entry(b"table_rules").await.is_err());
+    assert_eq!(
+        host.room.get_custom_entry(b"house_rules").await?,
+        Some("no takebacks".into())
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_host_rolls_back_to_a_checkpoint() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, _host_id, mut host_events) = setup_test_room("host").await?;
+    let (client_room, mut client_events) = join_test_room("client", &ticket_string, 3).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+    host_room.start_game().await?;
+    await_game_start(&mut client_events).await?;
+
+    client_room.submit_action(TestGameAction::Increment).await?;
+    await_counter_state(&mut host_events, 1).await?;
+    host_room.checkpoint("round one").await?;
+    assert!(client_room.checkpoint("mine").await.is_err());
+    assert!(host_room.rollback("missing").await.is_err());
+
+    for count in 2..=3 {
+        client_room.submit_action(TestGameAction::Increment).await?;
+        await_counter_state(&mut host_events, count).await?;
+    }
+    host_room.rollback("round one").await?;
+    loop {
+        if let UiEvent::RolledBack { label } = await_event(&mut client_events).await? {
+            assert_eq!(label, "round one");
+            break;
+        }
+    }
+    tokio::time::timeout(std::time::Duration::from_secs(10), async {
+        while client_room.get_game_state().await?.counter != 1 {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        anyhow::Ok(())
+    })
+    .await??;
+
+    // Play carries on from the restored state.
+    client_room.submit_action(TestGameAction::Increment).await?;
+    await_counter_state(&mut host_events, 2).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_agreed_proposals_are_applied_by_the_host() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, _host_id, mut host_events) = setup_test_room("host").await?;
+    let (client_room, mut client_events) = join_test_room("client", &ticket_string, 3).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+    host_room.start_game().await?;
+    await_game_start(&mut client_events).await?;
+    for count in 1..=2 {
+        client_room.submit_action(TestGameAction::Increment).await?;
+        await_counter_state(&mut host_events, count).await?;
+    }
+
+    async fn await_proposal(
+        events: &mut tokio::sync::mpsc::Receiver<UiEvent<TestGame>>,
+    ) -> anyhow::Result<ProposalRequest> {
+        loop {
+            if let UiEvent::ProposalMade(request) = await_event(events).await? {
+                return Ok(request);
+            }
+        }
+    }
+    async fn await_outcome(
+        events: &mut tokio::sync::mpsc::Receiver<UiEvent<TestGame>>,
+        id: &str,
+    ) -> anyhow::Result<ProposalOutcome> {
+        loop {
+            if let UiEvent::ProposalResolved(outcome) = await_event(events).await?
+                && outcome.id == id
+            {
+                return Ok(outcome);
+            }
+        }
+    }
+
+    // A takeback needs the other player's approval, then undoes the last action.
+    let id = client_room.propose(Proposal::Takeback).await?;
+    let request = await_proposal(&mut host_events).await?;
+    assert_eq!(request.id, id);
+    assert_eq!(request.from, client_room.id());
+    host_room.respond(&id, true).await?;
+    assert!(await_outcome(&mut client_events, &id).await?.accepted);
+    assert_eq!(client_room.get_game_state().await?.counter, 1);
+
+    // A refused draw offer changes nothing.
+    let id = host_room.propose(Proposal::Draw).await?;
+    assert_eq!(await_proposal(&mut client_events).await?.id, id);
+    client_room.respond(&id, false).await?;
+    assert!(!await_outcome(&mut host_events, &id).await?.accepted);
+    assert_eq!(host_room.get_app_state().await?, AppState::InGame);
+
+    // An accepted one finishes the game.
+    let id = host_room.propose(Proposal::Draw).await?;
+    await_proposal(&mut client_events).await?;
+    client_room.respond(&id, true).await?;
+    assert!(await_outcome(&mut host_events, &id).await?.accepted);
+    let result = host_room
+        .get_result()
+        .await?
+        .expect("game should be finished");
+    assert_eq!(result.reason, FinishReason::Agreed);
+    assert!(result.winners.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_room_decisions_are_settled_by_majority_vote() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, _host_id, mut host_events) = setup_test_room("host").await?;
+    let (client_room, mut client_events) = join_test_room("client", &ticket_string, 3).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+    host_room.start_game().await?;
+    await_game_start(&mut client_events).await?;
+    client_room.submit_action(TestGameAction::Increment).await?;
+    await_counter_state(&mut host_events, 1).await?;
+
+    async fn await_result(
+        events: &mut tokio::sync::mpsc::Receiver<UiEvent<TestGame>>,
+        id: &str,
+    ) -> anyhow::Result<VoteResult> {
+        loop {
+            if let UiEvent::VoteResult(result) = await_event(events).await?
+                && result.id == id
+            {
+                return Ok(result);
+            }
+        }
+    }
+
+    // Half of the room is not a majority, so a restart waits for the host's vote.
+    let id = client_room.call_vote(VoteKind::Restart).await?;
+    loop {
+        if let UiEvent::VoteCalled(call) = await_event(&mut host_events).await? {
+            assert_eq!(call.id, id);
+            assert_eq!(call.from, client_room.id());
+            break;
+        }
+    }
+    host_room.cast_vote(&id, true).await?;
+    let result = await_result(&mut client_events, &id).await?;
+    assert!(result.passed);
+    assert_eq!((result.yes, result.no), (2, 0));
+    await_room_counter_state(&client_room, 0).await?;
+
+    // The peer being kicked has no say, and is left watching.
+    let client_id = client_room.id();
+    let id = host_room.call_vote(VoteKind::Kick(client_id)).await?;
+    assert!(await_result(&mut host_events, &id).await?.passed);
+    await_lobby_observer_update(&mut client_events, &client_id, true).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_turn_changes_are_published_by_the_host() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, host_id, mut host_events) = setup_test_room("host").await?;
+    let (client_room, mut client_events) = join_test_room("client", &ticket_string, 3).await?;
+    let client_id = client_room.id();
+    await_lobby_ready_update(&mut host_events, &client_id, true).await?;
+    host_room.start_game().await?;
+    await_game_start(&mut client_events).await?;
+    assert_eq!(client_room.turns().await?, None);
+
+    async fn await_turn(
+        events: &mut tokio::sync::mpsc::Receiver<UiEvent<TestGame>>,
+    ) -> anyhow::Result<(EndpointId, u32)> {
+        loop {
+            if let UiEvent::TurnChanged { player, round } = await_event(events).await? {
+                return Ok((player, round));
+            }
+        }
+    }
+
+    let order = vec![host_id, client_id];
+    client_room
+        .submit_action(TestGameAction::TakeTurns(order.clone()))
+        .await?;
+    assert_eq!(await_turn(&mut client_events).await?, (host_id, 1));
+    host_room.submit_action(TestGameAction::Increment).await?;
+    assert_eq!(await_turn(&mut client_events).await?, (client_id, 1));
+    client_room.submit_action(TestGameAction::Increment).await?;
+    assert_eq!(await_turn(&mut client_events).await?, (host_id, 2));
+
+    let turns = client_room
+        .turns()
+        .await?
+        .expect("turns should be published");
+    assert_eq!(turns.order(), order.as_slice());
+    assert!(turns.is_turn(&host_id));
+    assert_eq!(turns.round(), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_players_are_told_when_it_is_their_turn() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, host_id, mut host_events) = setup_test_room("host").await?;
+    let (client_room, mut client_events) = join_test_room("client", &ticket_string, 3).await?;
+    let client_id = client_room.id();
+    await_lobby_ready_update(&mut host_events, &client_id, true).await?;
+    host_room.start_game().await?;
+    await_game_start(&mut client_events).await?;
+
+    async fn await_your_turn(
+        events: &mut tokio::sync::mpsc::Receiver<UiEvent<TestGame>>,
+    ) -> anyhow::Result<(EndpointId, u32, Option<std::time::SystemTime>)> {
+        loop {
+            if let UiEvent::YourTurn {
+                player,
+                round,
+                deadline,
+            } = await_event(events).await?
+            {
+                return Ok((player, round, deadline));
+            }
+        }
+    }
+
+    let started = std::time::SystemTime::now();
+    client_room
+        .submit_action(TestGameAction::TakeTurns(vec![host_id, client_id]))
+        .await?;
+    let (player, round, deadline) = await_your_turn(&mut host_events).await?;
+    assert_eq!((player, round), (host_id, 1));
+    let deadline = deadline.expect("the test game limits each turn");
+    assert!(deadline >= started + TURN_TIME_LIMIT - std::time::Duration::from_secs(5));
+    assert!(deadline <= std::time::SystemTime::now() + TURN_TIME_LIMIT);
+
+    // The client hears nothing about the host's turn, only about its own.
+    host_room.submit_action(TestGameAction::Increment).await?;
+    let (player, round, _) = await_your_turn(&mut client_events).await?;
+    assert_eq!((player, round), (client_id, 1));
+    client_room.submit_action(TestGameAction::Increment).await?;
+    let (player, round, _) = await_your_turn(&mut host_events).await?;
+    assert_eq!((player, round), (host_id, 2));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_host_moves_the_game_through_its_own_phases() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, _host_id, mut host_events) = setup_test_room("host").await?;
+    let (client_room, mut client_events) = join_test_room("client", &ticket_string, 3).await?;
+    let client_id = client_room.id();
+    await_lobby_ready_update(&mut host_events, &client_id, true).await?;
+    assert!(host_room.set_phase(TestPhase::Scoring).await.is_err());
+    host_room.start_game().await?;
+    await_game_start(&mut client_events).await?;
+
+    assert!(client_room.set_phase(TestPhase::Scoring).await.is_err());
+    host_room.set_phase(TestPhase::Scoring).await?;
+    loop {
+        if let UiEvent::AppState(state) = await_event(&mut client_events).await? {
+            assert_eq!(state, AppState::Custom(TestPhase::Scoring));
+            break;
+        }
+    }
+    assert!(client_room.get_app_state().await?.is_in_game());
+
+    // The game's own phases are still part of the game.
+    client_room.submit_action(TestGameAction::Increment).await?;
+    await_counter_state(&mut host_events, 1).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_host_ticks_timed_games_without_actions() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let config = RoomConfig::default().tick(std::time::Duration::from_millis(100));
+    let (room, mut events) = GameRoom::create_with_config(TestGame, None, None, config).await?;
+    room.announce_presence("host").await?;
+    await_lobby_contains(&mut events, &room.id()).await?;
+    room.set_ready(true).await?;
+    room.start_game().await?;
+    room.submit_action(TestGameAction::StartClock(
+        std::time::Duration::from_millis(500),
+    ))
+    .await?;
+
+    let mut seen_running = false;
+    loop {
+        if let UiEvent::GameState(TestGameState {
+            clock: Some(clock), ..
+        }) = await_event(&mut events).await?
+        {
+            if clock.is_zero() {
+                break;
+            }
+            seen_running |= clock < std::time::Duration::from_millis(500);
+        }
+    }
+    assert!(seen_running);
+    // Nothing is left to count down, so the state settles.
+    assert_eq!(room.get_game_state().await?.counter, 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_zero_intervals_leave_the_room_running() -> anyhow::Result<()> {
+    let config = RoomConfig {
+        tick: Some(std::time::Duration::ZERO),
+        network_stats: Some(std::time::Duration::ZERO),
+        heartbeat: Some(HeartbeatPolicy {
+            interval: std::time::Duration::ZERO,
+            missed_beats: 0,
+        }),
+        compaction: Some(CompactionPolicy {
+            interval: std::time::Duration::ZERO,
+            ..CompactionPolicy::default()
+        }),
+        channel_capacity: 0,
+        queue_limit: 0,
+        ..RoomConfig::default()
+    };
+    // The room clock is moved on rather than waited out, and the waits are generous, so a
+    // loaded run with the timers at their floor still gets through.
+    let network = testing::SimNetwork::new()
+        .room_config(config)
+        .timeout(std::time::Duration::from_secs(120));
+    let [host] = start_sim_game(&network, TestGame).await?;
+    let mut subscriber = host.room.subscribe(EventFilter::default());
+    let countdown = std::time::Duration::from_secs(3600);
+    // Move the clock on once the countdown has ticked, as the game clock only starts timing
+    // at its first tick, so the skip lands on exactly one tick.
+    let run_down = async || {
+        host.room
+            .submit_action(TestGameAction::StartClock(countdown))
+            .await?;
+        host.wait_until(async |room| {
+            let clock = room.get_game_state().await?.clock;
+            let ticked = clock.is_some_and(|clock| !clock.is_zero() && clock < countdown);
+            Ok(ticked.then_some(()))
+        })
+        .await?;
+        host.room.advance_clock(countdown);
+        host.wait_until(async |room| {
+            let clock = room.get_game_state().await?.clock;
+            Ok(clock.is_some_and(|clock| clock.is_zero()).then_some(()))
+        })
+        .await
+    };
+    run_down().await?;
+    assert!(await_event(&mut subscriber).await.is_ok());
+
+    // Backgrounding spaces the heartbeats out, and the clock keeps running.
+    host.room.set_activity(Activity::Background).await?;
+    run_down().await?;
+    while let Ok(event) = subscriber.try_recv() {
+        assert!(!matches!(
+            event,
+            UiEvent::Error(UiError::EventLoopRestarted)
+        ));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ticked_states_reach_peers_between_writes() -> anyhow::Result<()> {
+    let cadence = StateCadence::new(std::time::Duration::from_secs(5));
+    let config = RoomConfig::default()
+        .tick(std::time::Duration::from_millis(50))
+        .state_cadence(cadence);
+    let network = testing::SimNetwork::new().room_config(config);
+    let [host, mut client] = start_sim_game(&network, TestGame).await?;
+    let countdown = std::time::Duration::from_secs(20);
+    host.room
+        .submit_action(TestGameAction::StartClock(countdown))
+        .await?;
+
+    // The host writes the state at most every five seconds, so a clock that keeps moving
+    // for the client is coming over the real-time channel.
+    let mut clocks = HashSet::new();
+    while clocks.len() < 5 {
+        let clock = client
+            .expect_event(|event| match event {
+                UiEvent::GameState(TestGameState {
+                    clock: Some(clock), ..
+                }) if clock < countdown => Some(clock),
+                _ => None,
+            })
+            .await?;
+        clocks.insert(clock);
+    }
+    // The host's own view keeps up with its ticks too.
+    let ticked = host
+        .room
+        .get_game_state()
+        .await?
+        .clock
+        .expect("a running clock");
+    assert!(ticked < countdown);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ticked_states_reach_slow_peers_less_often() -> anyhow::Result<()> {
+    let cadence = StateCadence::new(std::time::Duration::from_secs(60))
+        .max_interval(std::time::Duration::from_secs(60));
+    let config = RoomConfig::default()
+        .tick(std::time::Duration::from_millis(50))
+        .state_cadence(cadence);
+    let network = testing::SimNetwork::new().room_config(config);
+    let [host, mut fast, mut slow] = start_sim_game(&network, TestGame).await?;
+    host.room
+        .simulate_rtt(&fast.id(), std::time::Duration::from_millis(50))?;
+    host.room
+        .simulate_rtt(&slow.id(), std::time::Duration::from_secs(60))?;
+    let countdown = std::time::Duration::from_secs(20);
+    host.room
+        .submit_action(TestGameAction::StartClock(countdown))
+        .await?;
+
+    fn ticked_clock(
+        event: UiEvent<TestGame>,
+        countdown: std::time::Duration,
+    ) -> Option<std::time::Duration> {
+        match event {
+            UiEvent::GameState(TestGameState {
+                clock: Some(clock), ..
+            }) if clock < countdown => Some(clock),
+            _ => None,
+        }
+    }
+
+    // Both peers see the clock start moving.
+    let mut slow_clocks = HashSet::from([slow
+        .expect_event(|event| ticked_clock(event, countdown))
+        .await?]);
+    fast.expect_event(|event| ticked_clock(event, countdown))
+        .await?;
+
+    // The fast peer is then sent a state about every tick.
+    let mut fast_clocks = HashSet::new();
+    while fast_clocks.len() < 5 {
+        let clock = fast
+            .expect_event(|event| ticked_clock(event, countdown))
+            .await?;
+        fast_clocks.insert(clock);
+    }
+    // In all that time the slow peer got the one written state and at most one frame.
+    while let Ok(event) = slow.events.try_recv() {
+        slow_clocks.extend(ticked_clock(event, countdown));
+    }
+    assert!(slow_clocks.len() <= 2, "slow peer saw {slow_clocks:?}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_realtime_messages_reach_connected_peers() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, host_id, mut host_events) = setup_test_room("host").await?;
+    let (client_room, mut client_events) = join_test_room("client", &ticket_string, 3).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+
+    async fn await_realtime(
+        events: &mut tokio::sync::mpsc::Receiver<UiEvent<TestGame>>,
+    ) -> anyhow::Result<(EndpointId, bytes::Bytes)> {
+        loop {
+            if let UiEvent::Realtime { from, bytes } = await_event(events).await? {
+                return Ok((from, bytes));
+            }
+        }
+    }
+
+    let received = resend_until(
+        async || client_room.send_realtime(&b"cursor 3,4"[..]).await,
+        async || await_realtime(&mut host_events).await,
+    )
+    .await?;
+    assert_eq!(
+        received,
+        (client_room.id(), bytes::Bytes::from_static(b"cursor 3,4"))
+    );
+
+    host_room.send_realtime(&b"drag 1,1"[..]).await?;
+    let (from, bytes) = await_realtime(&mut client_events).await?;
+    assert_eq!(from, host_id);
+    assert_eq!(&bytes[..], b"drag 1,1");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_background_rooms_hold_back_events_until_active() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, _host_id, mut host_events) = setup_test_room("host").await?;
+    let (client_room, mut client_events) = join_test_room("client", &ticket_string, 3).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+    host_room.start_game().await?;
+    await_game_start(&mut client_events).await?;
+
+    async fn await_realtime(
+        host_room: &GameRoom<TestGame>,
+        events: &mut tokio::sync::mpsc::Receiver<UiEvent<TestGame>>,
+    ) -> anyhow::Result<()> {
+        resend_until(
+            async || host_room.send_realtime(&b"cursor"[..]).await,
+            async || loop {
+                if let UiEvent::Realtime { .. } = await_event(events).await? {
+                    return Ok(());
+                }
+            },
+        )
+        .await
+    }
+    await_realtime(&host_room, &mut client_events).await?;
+
+    client_room.set_activity(Activity::Background).await?;
+    assert_eq!(client_room.activity(), Activity::Background);
+    host_room.send_realtime(&b"cursor"[..]).await?;
+    host_room.submit_action(TestGameAction::Increment).await?;
+    // The game keeps syncing, but the app is not woken for it.
+    await_room_counter_state(&client_room, 1).await?;
+    let quiet = std::time::Duration::from_secs(1);
+    while let Ok(Some(event)) = tokio::time::timeout(quiet, client_events.recv()).await {
+        assert!(
+            !matches!(event, UiEvent::GameState(_) | UiEvent::Realtime { .. }),
+            "unexpected {event} in the background"
+        );
+    }
+
+    client_room.set_activity(Activity::Active).await?;
+    let resumed = std::time::Duration::from_secs(5);
+    tokio::time::timeout(resumed, await_counter_state(&mut client_events, 1)).await??;
+    await_realtime(&host_room, &mut client_events).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rooms_sync_again_after_reconnecting() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, _host_id, mut host_events) = setup_test_room("host").await?;
+    let (client_room, mut client_events) = join_test_room("client", &ticket_string, 3).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+    host_room.start_game().await?;
+    await_game_start(&mut client_events).await?;
+
+    client_room.reconnect().await?;
+    loop {
+        if let UiEvent::Reconnected = await_event(&mut client_events).await? {
+            break;
+        }
+    }
+    host_room.submit_action(TestGameAction::Increment).await?;
+    await_counter_state(&mut client_events, 1).await?;
+    client_room.submit_action(TestGameAction::Increment).await?;
+    await_counter_state(&mut host_events, 2).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_typing_indicators_reach_peers_and_expire() -> anyhow::Result<()> {
+    let network = testing::SimNetwork::new();
+    let [mut host, player] = spawn_sim_peers(&network, TestGame).await?;
+    let typing = |event| match event {
+        UiEvent::PeerTyping { id, typing } => Some((id, typing)),
+        _ => None,
+    };
+
+    let started = resend_until(
+        async || player.room.set_typing(true).await,
+        async || host.expect_event(typing).await,
+    )
+    .await?;
+    assert_eq!(started, (player.id(), true));
+    player.room.set_typing(false).await?;
+    assert_eq!(host.expect_event(typing).await?, (player.id(), false));
+
+    // A peer that goes quiet stops typing by itself.
+    player.room.set_typing(true).await?;
+    assert_eq!(host.expect_event(typing).await?, (player.id(), true));
+    let stopped_at = std::time::Instant::now();
+    assert_eq!(host.expect_event(typing).await?, (player.id(), false));
+    assert!(stopped_at.elapsed() >= std::time::Duration::from_secs(3));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_game_messages_reach_peers() -> anyhow::Result<()> {
+    let network = testing::SimNetwork::new()
+        .room_config(RoomConfig::default().secret(RoomSecret::new("hunter2")));
+    let [mut host, player] = spawn_sim_peers(&network, TestGame).await?;
+
+    let emote = "wave".to_string();
+    let (from, msg) = resend_until(
+        async || player.room.broadcast_game_message(&emote).await,
+        async || {
+            host.expect_event(|event| match event {
+                UiEvent::GameMessage { from, msg } => Some((from, msg)),
+                _ => None,
+            })
+            .await
+        },
+    )
+    .await?;
+    assert_eq!((from, msg), (player.id(), emote));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_forged_actions_are_reported_and_not_applied() -> anyhow::Result<()> {
+    let network = testing::SimNetwork::new();
+    let [mut host, alice, mallory] = start_sim_game(&network, TestGame).await?;
+
+    // Mallory rejoins as Alice, then writes an action for her, under Mallory's own author.
+    let alice_id = alice.id();
+    let alice_before = host.room.get_peer_list().await?[&alice_id].clone();
+    mallory.forge_join(&alice_id, "mallory").await?;
+    mallory
+        .forge_action(&alice_id, TestGameAction::Increment)
+        .await?;
+    let claimed = host
+        .expect_event(|event| match event {
+            UiEvent::ForgedEntry { claimed, .. } => Some(claimed),
+            _ => None,
+        })
+        .await?;
+    assert_eq!(claimed, alice_id);
+    assert_eq!(host.room.get_game_state().await?.counter, 0);
+    assert!(host.room.get_action_log(..).await?.is_empty());
+    assert_eq!(host.room.get_peer_list().await?[&alice_id], alice_before);
+
+    // Alice's own action takes the number Mallory used, rather than queueing behind it.
+    assert_eq!(
+        alice.room.submit_action(TestGameAction::Increment).await?,
+        1
+    );
+    await_room_counter_state(&host.room, 1).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_forged_actions_for_observers_are_left_unanswered() -> anyhow::Result<()> {
+    let network = testing::SimNetwork::new();
+    let [mut host, mut alice, mallory] = start_sim_game(&network, TestGame).await?;
+    let alice_id = alice.id();
+    host.room.demote_to_observer(&alice_id).await?;
+    mallory
+        .wait_until(async |room| {
+            let peers = room.get_peer_list().await?;
+            Ok(peers[&alice_id].is_observer.then_some(()))
+        })
+        .await?;
+
+    // Mallory writes an action for Alice, who is now an observer.
+    mallory
+        .forge_action(&alice_id, TestGameAction::Increment)
+        .await?;
+    let claimed = host
+        .expect_event(|event| match event {
+            UiEvent::ForgedEntry { claimed, .. } => Some(claimed),
+            _ => None,
+        })
+        .await?;
+    assert_eq!(claimed, alice_id);
+
+    // Alice's own request still gets the answer meant for it.
+    let seq = alice
+        .forge_action(&alice_id, TestGameAction::Increment)
+        .await?;
+    let rejected = alice
+        .expect_event(|event| match event {
+            UiEvent::ActionRejected {
+                seq: rejected_seq,
+                error,
+                ..
+            } => Some((rejected_seq, error.to_string())),
+            _ => None,
+        })
+        .await?;
+    assert_eq!(rejected, (seq, "Peer is an observer".to_string()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_action_results_are_only_taken_from_the_host() -> anyhow::Result<()> {
+    let network = testing::SimNetwork::new();
+    let [_host, mut alice, mallory] = start_sim_game(&network, TestGame).await?;
+
+    // Mallory answers Alice's first action before the host can.
+    mallory.forge_rejection(&alice.id(), 1, "forged").await?;
+    alice.room.submit_action(TestGameAction::Increment).await?;
+    let accepted = alice
+        .expect_event(|event| match event {
+            UiEvent::ActionAccepted { .. } => Some(true),
+            UiEvent::ActionRejected { .. } => Some(false),
+            _ => None,
+        })
+        .await?;
+    assert!(accepted);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_private_payloads_only_reach_their_player() -> anyhow::Result<()> {
+    let network = testing::SimNetwork::new();
+    let [host, mut alice, mut bob] = spawn_sim_peers(&network, TestGame).await?;
+    let (alice_id, bob_id) = (alice.id(), bob.id());
+
+    assert!(
+        alice
+            .room
+            .send_private_state(&bob_id, b"peek")
+            .await
+            .is_err()
+    );
+    host.room
+        .send_private_state(&alice_id, b"ace of spades")
+        .await?;
+    host.room
+        .send_private_state(&bob_id, b"two of clubs")
+        .await?;
+
+    let private_state = |event| match event {
+        UiEvent::PrivateState { to, payload } => Some((to, payload)),
+        _ => None,
+    };
+    let (to, payload) = alice.expect_event(private_state).await?;
+    assert_eq!(
+        (to, payload.as_ref()),
+        (alice_id, b"ace of spades".as_slice())
+    );
+    // Bob syncs Alice's payload too, but only ever hears of his own.
+    let (to, payload) = bob.expect_event(private_state).await?;
+    assert_eq!((to, payload.as_ref()), (bob_id, b"two of clubs".as_slice()));
+    assert_eq!(
+        alice.room.get_private_state().await?.as_deref(),
+        Some(b"ace of spades".as_slice())
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_host_distributes_assets_to_peers() -> anyhow::Result<()> {
+    let network = testing::SimNetwork::new().room_config(RoomConfig::default().fetch_assets(true));
+    let [host, mut player] = spawn_sim_peers(&network, TestGame).await?;
+
+    assert!(player.room.publish_asset("map_01", b"rogue").await.is_err());
+    let map = vec![7u8; 64 * 1024];
+    let published = host.room.publish_asset("map_01", &map).await?;
+    assert_eq!(published.size, map.len() as u64);
+
+    let asset = player
+        .expect_event(|event| match event {
+            UiEvent::AssetAvailable(asset) => Some(asset),
+            _ => None,
+        })
+        .await?;
+    assert_eq!(asset, published);
+    assert_eq!(player.room.assets().await?, vec![published]);
+    assert_eq!(player.room.fetch_asset("map_01").await?.as_ref(), map);
+    assert!(player.room.fetch_asset("map_02").await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_predicted_actions_are_corrected_by_the_host() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, _host_id, mut host_events) = setup_test_room("host").await?;
+    let config = RoomConfig::default().predict(true);
+    let (client_room, mut client_events) =
+        GameRoom::join_with_config(TestGame, &ticket_string, None, config).await?;
+    client_room.announce_presence("client").await?;
+    await_lobby_contains(&mut client_events, &client_room.id()).await?;
+    client_room.set_ready(true).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+    host_room.start_game().await?;
+    await_game_start(&mut client_events).await?;
+    host_room.checkpoint("start").await?;
+    host_room.submit_action(TestGameAction::Increment).await?;
+    await_counter_state(&mut client_events, 1).await?;
+
+    // Without its event loop the host stops applying actions, so the prediction stays pending.
+    drop(host_events);
+    client_room.submit_action(TestGameAction::Increment).await?;
+    await_counter_state(&mut client_events, 2).await?;
+    assert_eq!(client_room.get_game_state().await?.counter, 1);
+    assert_eq!(client_room.get_predicted_game_state().unwrap().counter, 2);
+
+    // The host's state moves under the pending action, which is replayed on top of it.
+    host_room.rollback("start").await?;
+    loop {
+        if let UiEvent::PredictionCorrected(state) = await_event(&mut client_events).await? {
+            assert_eq!(state.counter, 1);
+            break;
+        }
+    }
+    assert_eq!(client_room.get_predicted_game_state().unwrap().counter, 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_applied_actions_are_logged_in_order() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, host_id, mut host_events) = setup_test_room("host").await?;
+    let (client_room, mut client_events) = join_test_room("client", &ticket_string, 3).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+    host_room.start_game().await?;
+    await_game_start(&mut client_events).await?;
+
+    client_room.submit_action(TestGameAction::Increment).await?;
+    await_counter_state(&mut host_events, 1).await?;
+    client_room.submit_action(TestGameAction::Reject).await?;
+    await_action_rejected(&mut client_events).await?;
+    host_room.submit_action(TestGameAction::Increment).await?;
+    await_counter_state(&mut host_events, 2).await?;
+    client_room.submit_action(TestGameAction::Increment).await?;
+    await_counter_state(&mut client_events, 3).await?;
+
+    // Rejected actions are left out, and the rest are numbered across the whole room.
+    let log = host_room.get_action_log(..).await?;
+    let entries: Vec<_> = log
+        .iter()
+        .map(|entry| (entry.seq, entry.player, entry.action_seq))
+        .collect();
+    let client_id = client_room.id();
+    assert_eq!(
+        entries,
+        vec![(1, client_id, 1), (2, host_id, 1), (3, client_id, 3)]
+    );
+    assert!(
+        log.windows(2)
+            .all(|pair| pair[0].applied_at <= pair[1].applied_at)
+    );
+
+    let tail = tokio::time::timeout(std::time::Duration::from_secs(10), async {
+        loop {
+            let tail = client_room.get_action_log(2..).await?;
+            if tail.len() == 2 {
+                return anyhow::Ok(tail);
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+    })
+    .await??;
+    assert_eq!(
+        tail.iter().map(|entry| entry.seq).collect::<Vec<_>>(),
+        vec![2, 3]
+    );
+    assert!(matches!(tail[1].action, TestGameAction::Increment));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_replaying_the_action_log_reaches_the_live_state() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, _host_id, mut host_events) = setup_test_room("host").await?;
+    let (client_room, mut client_events) = join_test_room("client", &ticket_string, 3).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+    host_room.start_game().await?;
+    await_game_start(&mut client_events).await?;
+    let initial_state = host_room.get_game_state().await?;
+
+    client_room.submit_action(TestGameAction::Increment).await?;
+    await_counter_state(&mut host_events, 1).await?;
+    client_room.submit_action(TestGameAction::Reject).await?;
+    await_action_rejected(&mut client_events).await?;
+    host_room.submit_action(TestGameAction::Increment).await?;
+    await_counter_state(&mut host_events, 2).await?;
+    client_room.submit_action(TestGameAction::Win).await?;
+    await_room_app_state(&host_room, AppState::Finished).await?;
+
+    let mut engine = Engine::new(TestGame, initial_state);
+    engine.replay(&host_room.get_action_log(..).await?)?;
+    assert_eq!(engine.state(), &host_room.get_game_state().await?);
+    assert!(engine.outcome().is_some());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rounds_collect_every_players_action_before_applying() -> anyhow::Result<()> {
+    let deadline = std::time::Duration::from_secs(3600);
+    let config = RoomConfig::default().rounds(RoundPolicy::new().deadline(deadline));
+    let network = testing::SimNetwork::new().room_config(config);
+    let [host, client] = start_sim_game(&network, TestGame).await?;
+    let initial_state = host.room.get_game_state().await?;
+
+    // Nobody else submits, so the round closes at its deadline with one action.
+    client.room.submit_action(TestGameAction::Increment).await?;
+    host.wait_until(async |room| {
+        room.advance_clock(deadline);
+        Ok((room.get_game_state().await?.counter == 1).then_some(()))
+    })
+    .await?;
+
+    // The second round closes as soon as both players are in.
+    client.room.submit_action(TestGameAction::Increment).await?;
+    host.room.submit_action(TestGameAction::Increment).await?;
+    await_room_counter_state(&host.room, 3).await?;
+
+    // A round's log entries are written just after its state.
+    let log = host
+        .wait_until(async |room| {
+            let log = room.get_action_log(..).await?;
+            Ok((log.len() == 3).then_some(log))
+        })
+        .await?;
+    let rounds: Vec<_> = log.iter().map(|entry| entry.round).collect();
+    assert_eq!(rounds, vec![Some(1), Some(2), Some(2)]);
+    let mut engine = Engine::new(TestGame, initial_state);
+    engine.replay(&log)?;
+    assert_eq!(engine.state(), &host.room.get_game_state().await?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rounds_close_once_the_clock_passes_their_deadline() -> anyhow::Result<()> {
+    let deadline = std::time::Duration::from_secs(3600);
+    let config = RoomConfig::default().rounds(RoundPolicy::new().deadline(deadline));
+    let network = testing::SimNetwork::new().room_config(config);
+    let [host, client] = start_sim_game(&network, TestGame).await?;
+
+    // Nobody else submits, and the hour-long deadline passes without waiting for it.
+    client.room.submit_action(TestGameAction::Increment).await?;
+    host.wait_until(async |room| {
+        room.advance_clock(deadline);
+        Ok((room.get_game_state().await?.counter == 1).then_some(()))
+    })
+    .await?;
+
+    let log = host.room.get_action_log(..).await?;
+    let rounds: Vec<_> = log.iter().map(|entry| entry.round).collect();
+    assert_eq!(rounds, vec![Some(1)]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_committed_actions_are_revealed_once_everyone_commits() -> anyhow::Result<()> {
+    let config = RoomConfig::default().rounds(RoundPolicy::new());
+    let network = testing::SimNetwork::new().room_config(config);
+    let [mut host, client] = start_sim_game(&network, TestGame).await?;
+
+    // The host learns that the client has chosen, but not what.
+    assert_eq!(
+        client.room.commit_action(TestGameAction::Increment).await?,
+        1
+    );
+    let committed = host
+        .expect_event(|event| match event {
+            UiEvent::ActionCommitted { player, commitment } => Some((player, commitment)),
+            _ => None,
+        })
+        .await?;
+    assert_eq!(committed, (client.id(), 1));
+    assert!(host.room.get_action_log(..).await?.is_empty());
+    assert!(
+        client
+            .room
+            .commit_action(TestGameAction::Win)
+            .await
+            .is_err()
+    );
+
+    host.room.commit_action(TestGameAction::Increment).await?;
+    await_room_counter_state(&host.room, 2).await?;
+    let log = host.room.get_action_log(..).await?;
+    assert!(log.iter().all(|entry| entry.round == Some(1)));
+    assert_eq!(log.len(), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_seats_commit_and_reveal_with_everyone_else() -> anyhow::Result<()> {
+    let config = RoomConfig::default().rounds(RoundPolicy::new());
+    let network = testing::SimNetwork::new().room_config(config);
+    let host = network.host(TestGame, "host").await?;
+    let client = network.join(TestGame, &host, "client").await?;
+    let seats = client
+        .room
+        .announce_presence_multi(["client", "Bob"])
+        .await?;
+    let bob = seats[1];
+    host.wait_until(async |room| Ok((room.get_peer_list().await?.len() == 3).then_some(())))
+        .await?;
+    host.room.set_ready(true).await?;
+    for seat in &seats {
+        client.room.set_ready_as(seat, true).await?;
+    }
+    host.wait_until(async |room| {
+        let peers = room.get_peer_list().await?;
+        Ok(peers.values().all(|peer| peer.ready).then_some(()))
+    })
+    .await?;
+    host.room.start_game().await?;
+    client
+        .wait_until(async |room| {
+            Ok(matches!(room.get_app_state().await?, AppState::InGame).then_some(()))
+        })
+        .await?;
+
+    // Nothing is revealed until the seat has committed too.
+    host.room.commit_action(TestGameAction::Increment).await?;
+    client.room.commit_action(TestGameAction::Increment).await?;
+    client
+        .room
+        .commit_action_as(&bob, TestGameAction::Increment)
+        .await?;
+    await_room_counter_state(&host.room, 3).await?;
+    // The round's log entries are written just after its state.
+    host.wait_until(async |room| {
+        let log = room.get_action_log(..).await?;
+        Ok(log.iter().any(|entry| entry.player == bob).then_some(()))
+    })
+    .await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_committed_players_cannot_act_before_revealing() -> anyhow::Result<()> {
+    let network = testing::SimNetwork::new();
+    let [mut host, mut client] = start_sim_game(&network, TestGame).await?;
+    client.room.commit_action(TestGameAction::Increment).await?;
+    host.expect_event(|event| match event {
+        UiEvent::ActionCommitted { player, .. } => Some(player),
+        _ => None,
+    })
+    .await?;
+
+    // The commitment is still closed, so a plain action is refused.
+    let seq = client.room.submit_action(TestGameAction::Win).await?;
+    let rejected = client
+        .expect_event(|event| match event {
+            UiEvent::ActionRejected {
+                seq: rejected_seq, ..
+            } => Some(rejected_seq),
+            _ => None,
+        })
+        .await?;
+    assert_eq!(rejected, seq);
+    assert!(host.room.get_action_log(..).await?.is_empty());
+    assert_eq!(host.room.get_app_state().await?, AppState::InGame);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_finished_match_exports_and_imports() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, _ticket_string, host_id, _host_events) = setup_test_room("host").await?;
+    host_room.start_game().await?;
+    host_room.submit_action(TestGameAction::Increment).await?;
+    await_room_counter_state(&host_room, 1).await?;
+    host_room.submit_action(TestGameAction::Win).await?;
+    await_room_app_state(&host_room, AppState::Finished).await?;
+
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("match.json");
+    host_room.export_match(&path).await?;
+    let imported = Match::import(&TestGame, &path)?;
+
+    assert_eq!(imported.game, TestGame::GAME_NAME);
+    assert_eq!(imported.room_name, host_room.name);
+    assert!(imported.players.contains_key(&host_id));
+    assert!(matches!(
+        imported.roles.get(&host_id),
+        Some(TestPlayerRole::Counter)
+    ));
+    assert_eq!(imported.actions.len(), 2);
+    assert!(matches!(imported.actions[1].action, TestGameAction::Win));
+    assert_eq!(imported.final_state, host_room.get_game_state().await?);
+    assert_eq!(imported.result, host_room.get_result().await?);
+
+    // Files from another game are refused.
+    let contents = std::fs::read_to_string(&path)?;
+    std::fs::write(&path, contents.replace(TestGame::GAME_NAME, "another-game"))?;
+    assert!(Match::import(&TestGame, &path).is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_host_resumes_last_room_without_ticket() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let empty_temp = tempfile::tempdir()?;
+    assert!(
+        GameRoom::resume(TestGame, empty_temp.path().to_path_buf())
+            .await
+            .is_err()
+    );
+
+    let host_temp = tempfile::tempdir()?;
+    let host_dir = host_temp.path().to_path_buf();
+    let (host_room, _ticket_string, host_id, _host_events) =
+        setup_persistent_test_room("host", host_dir.clone()).await?;
+    host_room.start_game().await?;
+    host_room.submit_action(TestGameAction::Increment).await?;
+    await_room_counter_state(&host_room, 1).await?;
+    host_room.stop().await?;
+
+    let (resumed, mut resumed_events) = GameRoom::resume(TestGame, host_dir).await?;
+    assert_eq!(resumed.id(), host_id);
+    assert!(resumed.is_host().await?);
+
+    // The room's current state comes first, before anything new.
+    assert!(matches!(
+        await_event(&mut resumed_events).await?,
+        UiEvent::AppState(AppState::InGame)
+    ));
+    match await_event(&mut resumed_events).await? {
+        UiEvent::GameState(state) => assert_eq!(state.counter, 1),
+        other => panic!("expected the game state, got {other:?}"),
+    }
+    match await_event(&mut resumed_events).await? {
+        UiEvent::Peer(peers) => assert!(peers.contains_key(&host_id)),
+        other => panic!("expected the peer list, got {other:?}"),
+    }
+
+    resumed.submit_action(TestGameAction::Increment).await?;
+    await_counter_state(&mut resumed_events, 2).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_host_resumes_a_room_written_by_an_older_build() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let host_temp = tempfile::tempdir()?;
+    let host_dir = host_temp.path().to_path_buf();
+    let (host_room, _ticket_string, host_id, _host_events) =
+        setup_persistent_test_room("host", host_dir.clone()).await?;
+    host_room.start_game().await?;
+    host_room.submit_action(TestGameAction::Increment).await?;
+    await_room_counter_state(&host_room, 1).await?;
+    let protocol = host_room.ticket().await?.protocol_version;
+
+    // Make the room look as if the previous protocol, and version 0 of the game, wrote it.
+    host_room.set_protocol_version(protocol - 1).await?;
+    host_room.set_versioned_game_state(0, &5u32).await?;
+    await_room_counter_state(&host_room, 5).await?;
+    host_room.stop().await?;
+    let last_room = host_dir.join("last_room.json");
+    let mut saved: serde_json::Value = serde_json::from_slice(&std::fs::read(&last_room)?)?;
+    saved["ticket"]["protocol_version"] = (protocol - 1).into();
+    std::fs::write(&last_room, serde_json::to_vec(&saved)?)?;
+
+    let (resumed, _resumed_events) = GameRoom::resume(TestGame, host_dir).await?;
+    assert_eq!(resumed.id(), host_id);
+    assert_eq!(resumed.get_game_state().await?.counter, 5);
+    let ticket = resumed.ticket().await?;
+    assert_eq!(ticket.protocol_version, protocol);
+
+    // Peers on this build can join once the host has brought the room up to date.
+    let (client_room, _client_events) = join_test_room("client", &ticket.to_string(), 3).await?;
+    resumed.submit_action(TestGameAction::Increment).await?;
+    await_room_counter_state(&client_room, 6).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_game_states_from_a_newer_build_are_refused() -> anyhow::Result<()> {
+    let network = testing::SimNetwork::new();
+    let [host, client] = start_sim_game(&network, TestGame).await?;
+
+    // A newer build's state can't be read back, so it is an error rather than a wrong state.
+    let newer = TestGame::STATE_VERSION + 1;
+    host.room.set_versioned_game_state(newer, &5u32).await?;
+    let error = client
+        .wait_until(async |room| Ok(room.get_game_state().await.err()))
+        .await?;
+    assert!(error.to_string().contains("newer"), "{error}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_encrypted_rooms_resume_without_their_secret_on_disk() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let temp = tempfile::tempdir()?;
+    let host_dir = temp.path().to_path_buf();
+    let key_store = std::sync::Arc::new(MemoryKeyStore::default());
+    let network = IrohConfig::default().key_store(key_store.clone());
+    let secret = RoomSecret::generate();
+    let config = RoomConfig::default()
+        .network(network.clone())
+        .secret(secret.clone());
+    let (host_room, mut host_events) =
+        GameRoom::create_with_config(TestGame, Some(host_dir.clone()), None, config).await?;
+    host_room.announce_presence("host").await?;
+    await_lobby_contains(&mut host_events, &host_room.id()).await?;
+    let host_id = host_room.id();
+    host_room.stop().await?;
+
+    let saved = std::fs::read_to_string(host_dir.join("last_room.json"))?;
+    assert!(!saved.contains(secret.expose()));
+    assert_eq!(key_store.room_secrets.lock().unwrap().len(), 1);
+
+    // Without the key store the secret is gone, so the room can't be read.
+    let forgotten = GameRoom::resume(TestGame, host_dir.clone()).await;
+    assert!(forgotten.is_err());
+
+    let config = RoomConfig::default().network(network);
+    let (resumed, _resumed_events) =
+        GameRoom::resume_with_config(TestGame, host_dir, config).await?;
+    assert_eq!(resumed.id(), host_id);
+    assert!(resumed.is_host().await?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_room_meta_is_published_by_the_host() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, _host_id, mut host_events) = setup_test_room("host").await?;
+    let (client_room, mut client_events) = join_test_room("client", &ticket_string, 3).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+
+    let meta = host_room.get_meta().await?.expect("host publishes details");
+    assert_eq!(meta.name, host_room.name);
+    assert_eq!(meta.game_id, TestGame::GAME_NAME);
+    assert_eq!(meta.max_players, None);
+
+    let meta = meta.description("Friendly games only").max_players(4);
+    host_room.set_meta(meta.clone()).await?;
+    loop {
+        if let UiEvent::MetaUpdated(updated) = await_event(&mut client_events).await? {
+            assert_eq!(updated, meta);
+            break;
+        }
+    }
+    assert_eq!(client_room.get_meta().await?, Some(meta.clone()));
+
+    // Only the host describes the room.
+    assert!(client_room.set_meta(meta.max_players(2)).await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_joins_beyond_max_players_are_denied() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, _host_id, mut host_events) = setup_test_room("host").await?;
+    let meta = host_room.get_meta().await?.expect("host publishes details");
+    host_room.set_meta(meta.max_players(2)).await?;
+    let (first, _first_events) = join_test_room("first", &ticket_string, 3).await?;
+    await_lobby_ready_update(&mut host_events, &first.id(), true).await?;
+
+    let (second, mut second_events) = GameRoom::join(TestGame, &ticket_string, None).await?;
+    second.announce_presence("second").await?;
+    loop {
+        match await_event(&mut second_events).await? {
+            UiEvent::JoinDenied(reason) => {
+                assert_eq!(reason, JoinDeniedReason::RoomFull);
+                break;
+            }
+            UiEvent::Peer(peers) => assert!(!peers.contains_key(&second.id())),
+            _ => {}
+        }
+    }
+    let peers = host_room.get_peer_list().await?;
+    assert_eq!(peers.len(), 2);
+    assert!(!peers.contains_key(&second.id()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_closed_rooms_finish_and_take_no_more_joins() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, _host_id, mut host_events) = setup_test_room("host").await?;
+    let (client_room, mut client_events) = join_test_room("client", &ticket_string, 3).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+
+    // Only the host closes the room.
+    assert!(client_room.close("Leaving").await.is_err());
+    host_room.close("Server shutting down").await?;
+    loop {
+        if let UiEvent::RoomClosed(reason) = await_event(&mut client_events).await? {
+            assert_eq!(reason, "Server shutting down");
+            break;
+        }
+    }
+    assert_eq!(client_room.get_app_state().await?, AppState::Finished);
+    assert_eq!(
+        client_room.get_close_reason().await?.as_deref(),
+        Some("Server shutting down")
+    );
+    assert!(host_room.close("Again").await.is_err());
+
+    let (late, mut late_events) = GameRoom::join(TestGame, &ticket_string, None).await?;
+    late.announce_presence("late").await?;
+    loop {
+        if let UiEvent::JoinDenied(reason) = await_event(&mut late_events).await? {
+            assert_eq!(reason, JoinDeniedReason::RoomClosed);
+            break;
+        }
+    }
+    assert!(!host_room.get_peer_list().await?.contains_key(&late.id()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_public_rooms_can_be_browsed_and_joined() -> anyhow::Result<()> {
+    use futures::StreamExt as _;
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let tracker = LobbyRegistry::new(Vec::new(), &IrohConfig::default()).await?;
+    let mut tracked = tracker.browse_rooms(TestGame::GAME_NAME).await?;
+    let tracker_addr = tracker.addr().await;
+
+    let config = RoomConfig::default().visibility(RoomVisibility::Public);
+    let (host_room, mut host_events) =
+        GameRoom::create_with_config(TestGame, None, Some("Open table"), config).await?;
+    host_room.announce_presence("host").await?;
+    await_lobby_contains(&mut host_events, &host_room.id()).await?;
+    let _advert = host_room.advertise(vec![tracker_addr.clone()]).await?;
+
+    let browser = LobbyRegistry::new(vec![tracker_addr], &IrohConfig::default()).await?;
+    let mut rooms = browser.browse_rooms(TestGame::GAME_NAME).await?;
+    let listing = tokio::time::timeout(std::time::Duration::from_secs(30), rooms.next())
+        .await?
+        .expect("the host's room is listed");
+    assert_eq!(listing.host, host_room.id());
+    assert_eq!(listing.meta.name, "Open table");
+    assert_eq!(listing.players, 1);
+    assert!(tracked.next().await.is_some());
+
+    let (client_room, mut client_events) = GameRoom::join_listing(TestGame, &listing, None).await?;
+    client_room.announce_presence("client").await?;
+    await_lobby_contains(&mut client_events, &client_room.id()).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_friends_see_each_other_come_and_go() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let alice_dir = tempfile::tempdir()?;
+    let bob_dir = tempfile::tempdir()?;
+    let alice = Identity::load_or_create(alice_dir.path(), "alice").await?;
+    let bob = Identity::load_or_create(bob_dir.path(), "bob").await?;
+
+    let mut alice_friends = Friends::load(&alice).await?;
+    alice_friends.add(bob.id(), "bob");
+    alice_friends.save().await?;
+    // Friends are kept in the identity's data directory.
+    let alice_friends = Friends::load(&alice).await?;
+    assert_eq!(alice_friends.list().len(), 1);
+    let mut bob_friends = Friends::load(&bob).await?;
+    bob_friends.add(alice.id(), "alice");
+
+    let (alice_presence, mut alice_events) = Presence::start(
+        &TestGame,
+        &alice,
+        alice_friends,
+        Vec::new(),
+        &IrohConfig::default(),
+    )
+    .await?;
+    let tracker = alice_presence.addr().await;
+    let (bob_presence, mut bob_events) = Presence::start(
+        &TestGame,
+        &bob,
+        bob_friends,
+        vec![tracker],
+        &IrohConfig::default(),
+    )
+    .await?;
+
+    loop {
+        if let UiEvent::FriendOnline(friend) = await_event(&mut alice_events).await? {
+            assert_eq!(friend.id, bob.id());
+            break;
+        }
+    }
+    loop {
+        if let UiEvent::FriendOnline(friend) = await_event(&mut bob_events).await? {
+            assert_eq!(friend.id, alice.id());
+            break;
+        }
+    }
+    let online = alice_presence.friends_online()?;
+    assert_eq!(online.len(), 1);
+    assert_eq!(online[0].name, "bob");
+
+    drop(bob_presence);
+    loop {
+        if let UiEvent::FriendOffline(friend) = await_event(&mut alice_events).await? {
+            assert_eq!(friend.id, bob.id());
+            break;
+        }
+    }
+    assert!(alice_presence.friends_online()?.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_replayed_presence_announcements_are_ignored() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let dirs = [
+        tempfile::tempdir()?,
+        tempfile::tempdir()?,
+        tempfile::tempdir()?,
+    ];
+    let alice = Identity::load_or_create(dirs[0].path(), "alice").await?;
+    let bob = Identity::load_or_create(dirs[1].path(), "bob").await?;
+    let carol = Identity::load_or_create(dirs[2].path(), "carol").await?;
+    let mut friends = Friends::load(&alice).await?;
+    friends.add(bob.id(), "bob");
+    friends.add(carol.id(), "carol");
+    let (presence, mut events) = Presence::start(
+        &TestGame,
+        &alice,
+        friends,
+        Vec::new(),
+        &IrohConfig::default(),
+    )
+    .await?;
+    let forger = testing::PresenceForger::join(&TestGame, presence.addr().await).await?;
+
+    // Carol's announcements are from long ago, or dated in the future.
+    let minute = std::time::Duration::from_secs(60);
+    let now = std::time::SystemTime::now();
+    forger.announce(&carol, now - minute).await?;
+    forger.announce(&carol, now + minute).await?;
+    // Bob's current announcement gets through.
+    let wait = std::time::Duration::from_millis(500);
+    let newest = loop {
+        let sent_at = std::time::SystemTime::now();
+        forger.announce(&bob, sent_at).await?;
+        if let Ok(event) = tokio::time::timeout(wait, await_event(&mut events)).await {
+            match event? {
+                UiEvent::FriendOnline(friend) => {
+                    assert_eq!(friend.id, bob.id());
+                    break sent_at;
+                }
+                event => panic!("unexpected event {event:?}"),
+            }
+        }
+    };
+
+    // Replaying Bob's older announcements does not keep him online.
+    let replayed_from = std::time::Instant::now();
+    let mut replays = 0;
+    loop {
+        replays += 1;
+        let older = newest - std::time::Duration::from_millis(replays);
+        forger.announce(&bob, older).await?;
+        if let Ok(event) = tokio::time::timeout(wait, await_event(&mut events)).await {
+            match event? {
+                UiEvent::FriendOffline(friend) => {
+                    assert_eq!(friend.id, bob.id());
+                    break;
+                }
+                event => panic!("unexpected event {event:?}"),
+            }
+        }
+    }
+    assert!(replayed_from.elapsed() < std::time::Duration::from_secs(10));
+    assert!(presence.friends_online()?.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_subscribers_only_receive_events_passing_their_filter() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, _host_id, mut host_events) = setup_test_room("host").await?;
+    let mut game_states = host_room.subscribe(EventFilter::none().game_state(true));
+    let mut everything_but_chat = host_room.subscribe(EventFilter::default().chat(false));
+    let (client_room, _client_events) = join_test_room("client", &ticket_string, 3).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+
+    host_room.send_chat("hello").await?;
+    host_room.start_game().await?;
+    host_room.submit_action(TestGameAction::Increment).await?;
+    await_room_counter_state(&host_room, 1).await?;
+
+    loop {
+        match await_event(&mut game_states).await? {
+            UiEvent::GameState(state) if state.counter == 1 => break,
+            UiEvent::GameState(_) | UiEvent::GameStarted { .. } => {}
+            other => panic!("Unexpected event for a game state subscriber: {other}"),
+        }
+    }
+    let mut saw_peers = false;
+    loop {
+        match await_event(&mut everything_but_chat).await? {
+            UiEvent::Chat { .. } | UiEvent::ChatUpdated(_) => panic!("Chat was not filtered out"),
+            UiEvent::Peer(_) => saw_peers = true,
+            UiEvent::GameState(state) if state.counter == 1 => break,
+            _ => {}
+        }
+    }
+    assert!(saw_peers);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_event_handlers_are_called_by_the_event_loop() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, _host_id, mut host_events) = setup_test_room("host").await?;
+    let (handled, mut handled_events) = tokio::sync::mpsc::unbounded_channel();
+    host_room.on_event(move |event| {
+        let _ = handled.send(event);
+    });
+    let (client_room, _client_events) = join_test_room("client", &ticket_string, 3).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+
+    host_room.send_chat("hello").await?;
+    loop {
+        let event = tokio::time::timeout(std::time::Duration::from_secs(30), handled_events.recv())
+            .await?
+            .expect("the room dropped its handler");
+        if let UiEvent::Chat { msg, .. } = event {
+            assert_eq!(msg.message, "hello");
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_event_handlers_can_register_handlers() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, _ticket_string, _host_id, _host_events) = setup_test_room("host").await?;
+    let host_room = std::sync::Arc::new(host_room);
+    let (handled, mut handled_events) = tokio::sync::mpsc::unbounded_channel();
+    let room = std::sync::Arc::downgrade(&host_room);
+    let mut registered = false;
+    host_room.on_event(move |event| {
+        if registered || !matches!(event, UiEvent::Chat { .. }) {
+            return;
+        }
+        registered = true;
+        let Some(room) = room.upgrade() else {
+            return;
+        };
+        let handled = handled.clone();
+        room.on_event(move |event| {
+            let _ = handled.send(event);
+        });
+    });
+
+    // The first chat message sets the second handler up, which hears the next one.
+    host_room.send_chat("first").await?;
+    host_room.send_chat("second").await?;
+    loop {
+        let event = tokio::time::timeout(std::time::Duration::from_secs(30), handled_events.recv())
+            .await?
+            .expect("the room dropped its handler");
+        if let UiEvent::Chat { msg, .. } = event {
+            assert_eq!(msg.message, "second");
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_events_survive_a_serde_round_trip() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, _host_id, mut host_events) = setup_test_room("host").await?;
+    let (client_room, mut client_events) = join_test_room("client", &ticket_string, 3).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+
+    host_room.send_chat("hello").await?;
+    host_room.start_game().await?;
+    host_room.submit_action(TestGameAction::Increment).await?;
+    let mut kinds = HashSet::new();
+    loop {
+        let event = await_event(&mut client_events).await?;
+        let json = serde_json::to_value(&event)?;
+        let forwarded: UiEvent<TestGame> = serde_json::from_value(json.clone())?;
+        assert_eq!(serde_json::to_value(&forwarded)?, json);
+        kinds.insert(std::mem::discriminant(&event));
+        if matches!(&event, UiEvent::GameState(state) if state.counter == 1) {
+            break;
+        }
+    }
+    assert!(kinds.len() > 2, "only saw {} kinds of events", kinds.len());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_late_subscribers_start_with_recent_events() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, _host_id, mut host_events) = setup_test_room("host").await?;
+    let (client_room, _client_events) = join_test_room("client", &ticket_string, 3).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+    host_room.send_chat("hello").await?;
+    loop {
+        if let UiEvent::Chat { .. } = await_event(&mut host_events).await? {
+            break;
+        }
+    }
+
+    let recent = host_room.replay_recent(usize::MAX);
+    assert!(recent.iter().any(|event| matches!(event, UiEvent::Peer(_))));
+    assert!(matches!(recent.last(), Some(UiEvent::Chat { .. })));
+    assert_eq!(host_room.replay_recent(1).len(), 1);
+    let mut chat = host_room.subscribe(EventFilter::none().chat(true));
+    let UiEvent::Chat { msg, .. } = await_event(&mut chat).await? else {
+        panic!("Expected the chat message sent before subscribing");
+    };
+    assert_eq!(msg.message, "hello");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_subscribers_that_never_read_stay_within_the_queue_limit() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let config = RoomConfig::default()
+        .channel_capacity(1)
+        .queue_limit(4)
+        .replay_buffer(0);
+    let (host_room, mut host_events) =
+        GameRoom::create_with_config(TestGame, None, None, config).await?;
+    host_room.announce_presence("host").await?;
+    await_peer_list_count(&host_room, 1).await?;
+    let mut stalled = host_room.subscribe(EventFilter::none().chat(true));
+
+    const SENT: usize = 30;
+    for i in 0..SENT {
+        host_room.send_chat(&format!("message {i}")).await?;
+    }
+    let last = format!("message {}", SENT - 1);
+    loop {
+        if let UiEvent::Chat { msg, .. } = await_event(&mut host_events).await?
+            && msg.message == last
+        {
+            break;
+        }
+    }
+
+    // The channel, the subscriber's inbox and its queue hold no more than this between them.
+    let bound = 1 + 4 + 4;
+    let mut chats = 0;
+    let mut missed = 0;
+    while let Ok(Some(event)) =
+        tokio::time::timeout(std::time::Duration::from_secs(1), stalled.recv()).await
+    {
+        match event {
+            UiEvent::Chat { .. } => chats += 1,
+            UiEvent::Lagged { missed: count } => missed += count,
+            other => panic!("Unexpected event for a chat subscriber: {other}"),
+        }
+    }
+    assert!(chats <= bound, "{chats} chat messages were kept");
+    assert!(missed > 0, "the subscriber was never told it lagged");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bursts_of_updates_are_debounced() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, _host_id, mut host_events) = setup_test_room("host").await?;
+    let config = RoomConfig::default()
+        .coalescing(EventCoalescing::keep_all())
+        .debounce(std::time::Duration::from_secs(2));
+    let (client_room, mut client_events) =
+        GameRoom::join_with_config(TestGame, &ticket_string, None, config).await?;
+    client_room.announce_presence("client").await?;
+    await_lobby_contains(&mut client_events, &client_room.id()).await?;
+    client_room.set_ready(true).await?;
+    client_room.set_ready(false).await?;
+    loop {
+        if let UiEvent::Peer(peers) = await_event(&mut client_events).await? {
+            // Both changes land in the same window, so only the result is seen.
+            assert!(!peers.get(&client_room.id()).expect("client in lobby").ready);
+            break;
+        }
+    }
+
+    client_room.set_ready(true).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+    host_room.start_game().await?;
+    for _ in 0..3 {
+        host_room.submit_action(TestGameAction::Increment).await?;
+    }
+    let mut counters = Vec::new();
+    loop {
+        if let UiEvent::GameState(state) = await_event(&mut client_events).await? {
+            counters.push(state.counter);
+            if state.counter == 3 {
+                break;
+            }
+        }
+    }
+    assert!(counters.len() < 4, "every state was reported: {counters:?}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rooms_can_be_built_with_structured_options() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, mut host_events) = GameRoom::builder(TestGame)
+        .name("Builder room")
+        .nickname("Al")
+        .channel_capacity(4)
+        .create()
+        .await?;
+    assert_eq!(host_room.name, "Builder room");
+    await_lobby_contains(&mut host_events, &host_room.id()).await?;
+    let ticket = host_room.ticket().await?.to_string();
+
+    let (client_room, mut client_events) = GameRoom::builder(TestGame)
+        .nickname("Bea")
+        .join(&ticket)
+        .await?;
+    assert_eq!(client_room.name, "Builder room");
+    await_lobby_contains(&mut client_events, &client_room.id()).await?;
+    let peers = await_peer_list_count(&host_room, 2).await?;
+    assert_eq!(peers[&host_room.id()].profile.nickname, "Al");
+    assert_eq!(peers[&client_room.id()].profile.nickname, "Bea");
+
+    // Without a store there is nothing to resume.
+    assert!(GameRoom::builder(TestGame).resume().await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_join_waits_for_initial_sync() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, host_id, _host_events) = setup_test_room("host").await?;
+    host_room.start_game().await?;
+    host_room.submit_action(TestGameAction::Increment).await?;
+    await_room_counter_state(&host_room, 1).await?;
+
+    let (late_room, mut late_events) = GameRoom::join(TestGame, &ticket_string, None).await?;
+    // Everything is readable straight away, without waiting for events.
+    assert_eq!(late_room.get_app_state().await?, AppState::InGame);
+    assert_eq!(late_room.get_game_state().await?.counter, 1);
+    match await_event(&mut late_events).await? {
+        UiEvent::InitialSnapshot {
+            app_state,
+            peers,
+            game_state,
+        } => {
+            assert_eq!(app_state, AppState::InGame);
+            assert!(peers.contains_key(&host_id));
+            assert_eq!(game_state.map(|state| state.counter), Some(1));
+        }
+        other => panic!("Expected the initial snapshot first, got {other}"),
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_joiners_that_do_not_wait_see_sync_progress() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, _host_id, _host_events) = setup_test_room("host").await?;
+    for message in ["one", "two", "three"] {
+        host_room.send_chat(message).await?;
+    }
+    host_room.start_game().await?;
+    host_room.submit_action(TestGameAction::Increment).await?;
+    await_room_counter_state(&host_room, 1).await?;
+
+    let config = RoomConfig::default().await_initial_sync(false);
+    let (_late_room, mut late_events) =
+        GameRoom::join_with_config(TestGame, &ticket_string, None, config).await?;
+    let mut progress = None;
+    loop {
+        match await_event(&mut late_events).await? {
+            UiEvent::Syncing {
+                entries_done,
+                entries_total,
+            } => {
+                assert!(entries_done <= entries_total);
+                progress = Some(entries_total);
+            }
+            UiEvent::InitialSnapshot {
+                app_state,
+                game_state,
+                ..
+            } => {
+                assert_eq!(app_state, AppState::InGame);
+                assert_eq!(game_state.map(|state| state.counter), Some(1));
+                break;
+            }
+            _ => {}
+        }
+    }
+    assert!(progress.is_some_and(|total| total > 0));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_lockstep_players_apply_each_others_actions() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, mut host_events) =
+        GameRoom::create_with_config(TestGame, None, None, RoomConfig::default().lockstep(true))
+            .await?;
+    host_room.announce_presence("host").await?;
+    await_lobby_contains(&mut host_events, &host_room.id()).await?;
+    host_room.set_ready(true).await?;
+    let ticket_string = host_room.ticket().await?.to_string();
+    let (client_room, mut client_events) = join_test_room("client", &ticket_string, 1).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+
+    host_room.start_game().await?;
+    await_game_start(&mut client_events).await?;
+    client_room.submit_action(TestGameAction::Increment).await?;
+    await_counter_state(&mut host_events, 1).await?;
+    host_room.submit_action(TestGameAction::Increment).await?;
+    await_counter_state(&mut client_events, 2).await?;
+
+    // The host never saw the actions as requests, so it logged none of them.
+    assert!(host_room.get_action_log(..).await?.is_empty());
+    assert_eq!(client_room.get_game_state().await?.counter, 2);
+    assert!(host_room.rollback("start").await.is_err());
+    Ok(())
+}
+
+/// A lock-step game that tallies words in a `HashMap`, whose order differs between peers.
+#[derive(Debug, Clone)]
+struct TallyGame {
+    canonical: bool,
+}
+
+impl GameLogic for TallyGame {
+    const GAME_NAME: &'static str = "TallyGame";
+    type GameState = HashMap<String, u32>;
+    type GameAction = String;
+    type PlayerRole = ();
+    type PlayerLeaveReason = ();
+    type Phase = ();
+    type GameMessage = ();
+    type GameError = HostObserverError;
+
+    fn canonical_state(&self, state: &Self::GameState) -> Option<Vec<u8>> {
+        let sorted: std::collections::BTreeMap<_, _> = state.iter().collect();
+        self.canonical
+            .then(|| postcard::to_stdvec(&sorted).ok())
+            .flatten()
+    }
+
+    fn assign_roles(
+        &self,
+        players: &PeerMap,
+    ) -> Result<HashMap<EndpointId, Self::PlayerRole>, Self::GameError> {
+        Ok(players.keys().map(|id| (*id, ())).collect())
+    }
+
+    fn validate_start(
+        &self,
+        _players: &PeerMap,
+        _roles: &HashMap<EndpointId, Self::PlayerRole>,
+    ) -> Result<(), Self::GameError> {
+        Ok(())
+    }
+
+    fn initial_state(
+        &self,
+        _players: &PeerMap,
+        _roles: &HashMap<EndpointId, Self::PlayerRole>,
+    ) -> Result<Self::GameState, Self::GameError> {
+        Ok((0..16).map(|word| (format!("word{word}"), 0)).collect())
+    }
+
+    fn apply_action(
+        &self,
+        current_state: &mut Self::GameState,
+        _player_id: &EndpointId,
+        action: &Self::GameAction,
+    ) -> Result<(), Self::GameError> {
+        *current_state.entry(action.clone()).or_default() += 1;
+        Ok(())
+    }
+
+    fn handle_player_disconnect(
+        &self,
+        _players: &mut PeerMap,
+        _player_id: &EndpointId,
+        _current_state: &mut Self::GameState,
+    ) -> Result<ConnectionEffect, Self::GameError> {
+        Ok(ConnectionEffect::NoChange)
+    }
+
+    fn handle_player_reconnect(
+        &self,
+        _players: &mut PeerMap,
+        _player_id: &EndpointId,
+        _current_state: &mut Self::GameState,
+    ) -> Result<ConnectionEffect, Self::GameError> {
+        Ok(ConnectionEffect::NoChange)
+    }
+
+    fn handle_player_forfeit(
+        &self,
+        _players: &mut PeerMap,
+        _player_id: &EndpointId,
+        _current_state: &mut Self::GameState,
+    ) -> Result<ConnectionEffect, Self::GameError> {
+        Ok(ConnectionEffect::NoChange)
+    }
+}
+
+#[tokio::test]
+async fn test_lockstep_digests_use_the_games_canonical_state() -> anyhow::Result<()> {
+    let config = RoomConfig::default().lockstep(true);
+    let network = testing::SimNetwork::new().room_config(config);
+    let start = async |game: TallyGame| {
+        let [host, client] = spawn_sim_peers(&network, game).await?;
+        ready_sim_peers(&[&host, &client]).await?;
+        let started = host.room.start_game().await;
+        anyhow::Ok((host, client, started))
+    };
+
+    // Without a canonical state the players could never agree, so the game doesn't start.
+    let (_host, _client, started) = start(TallyGame { canonical: false }).await?;
+    assert!(started.is_err());
+
+    // Each peer's copy of the tally is in its own order, yet every step's digest matches.
+    let (host, client, started) = start(TallyGame { canonical: true }).await?;
+    started?;
+    for (turn, word) in ["word3", "word11", "new", "word3"].into_iter().enumerate() {
+        let (player, other) = match turn % 2 {
+            0 => (&host, &client),
+            _ => (&client, &host),
+        };
+        player.room.submit_action(word.to_string()).await?;
+        let expected = player.room.get_game_state().await?;
+        other
+            .wait_until(async |room| Ok((room.get_game_state().await? == expected).then_some(())))
+            .await?;
+    }
+    assert_eq!(host.room.get_game_state().await?["word3"], 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_host_rejects_actions_over_the_rate_limit() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let limits =
+        RateLimits::default().actions(RateLimit::new(1, std::time::Duration::from_secs(60)));
+    let (host_room, mut host_events) = GameRoom::create_with_config(
+        TestGame,
+        None,
+        None,
+        RoomConfig::default().rate_limits(limits),
+    )
+    .await?;
+    host_room.announce_presence("host").await?;
+    await_lobby_contains(&mut host_events, &host_room.id()).await?;
+    host_room.set_ready(true).await?;
+    let ticket_string = host_room.ticket().await?.to_string();
+    let (client_room, mut client_events) = join_test_room("client", &ticket_string, 3).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+    host_room.start_game().await?;
+    await_game_start(&mut client_events).await?;
+
+    client_room.submit_action(TestGameAction::Increment).await?;
+    assert_eq!(await_action_accepted(&mut client_events).await?.0, 1);
+    client_room.submit_action(TestGameAction::Increment).await?;
+    let (seq, error) = await_action_rejected(&mut client_events).await?;
+    assert_eq!(seq, 2);
+    assert_eq!(error.to_string(), "Rate limit exceeded");
+    loop {
+        if let UiEvent::RateLimited { peer } = await_event(&mut host_events).await? {
+            assert_eq!(peer, client_room.id());
+            break;
+        }
+    }
+
+    // The host's own players are never limited.
+    for count in 2..=3 {
+        host_room.submit_action(TestGameAction::Increment).await?;
+        await_counter_state(&mut host_events, count).await?;
+    }
+
+    // The bucket refills by the room's clock.
+    host_room.advance_clock(std::time::Duration::from_secs(60));
+    client_room.submit_action(TestGameAction::Increment).await?;
+    assert_eq!(await_action_accepted(&mut client_events).await?.0, 3);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tickets_carry_every_online_peers_address() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (host_room, ticket_string, host_id, mut host_events) = setup_test_room("host").await?;
+    let (client_room, _client_events) = join_test_room("client", &ticket_string, 3).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+
+    let node_ids = |ticket: RoomTicket| -> Vec<_> {
+        ticket.doc_ticket.nodes.iter().map(|node| node.id).collect()
+    };
+    let from_client = node_ids(client_room.ticket().await?);
+    assert!(from_client.contains(&client_room.id()));
+    assert!(from_client.contains(&host_id));
+    assert!(node_ids(host_room.ticket().await?).contains(&client_room.id()));
+    Ok(())
+}
+
+#[cfg(feature = "ffi")]
+#[tokio::test]
+async fn test_foreign_games_host_and_join_through_the_ffi() -> anyhow::Result<()> {
+    use p2p_game_engine::ffi::{FfiError, FfiEvent, FfiPeer, FfiRoom, ForeignGameLogic};
+    use std::sync::Arc;
+
+    /// A counter whose state is one byte, bumped by every action, which names a winner that
+    /// is not a peer once it reaches two.
+    struct Counter(&'static str);
+
+    impl ForeignGameLogic for Counter {
+        fn game_name(&self) -> String {
+            self.0.to_string()
+        }
+        fn assign_roles(&self, players: Vec<FfiPeer>) -> Result<HashMap<String, String>, FfiError> {
+            Ok(players
+                .into_iter()
+                .map(|peer| (peer.id, "player".to_string()))
+                .collect())
+        }
+        fn validate_start(&self, _players: Vec<FfiPeer>) -> Result<(), FfiError> {
+            Ok(())
+        }
+        fn initial_state(&self, _players: Vec<FfiPeer>) -> Result<Vec<u8>, FfiError> {
+            Ok(vec![0])
+        }
+        fn apply_action(
+            &self,
+            state: Vec<u8>,
+            _player: String,
+            _action: Vec<u8>,
+        ) -> Result<Vec<u8>, FfiError> {
+            Ok(vec![state[0] + 1])
+        }
+        fn winners(&self, state: Vec<u8>) -> Option<Vec<String>> {
+            (state[0] >= 2).then(|| vec!["nobody".to_string()])
+        }
+    }
+
+    let await_ffi_event = async |room: &FfiRoom, matches: &dyn Fn(&FfiEvent) -> bool| {
+        tokio::time::timeout(std::time::Duration::from_secs(30), async {
+            while let Some(event) = room.next_event().await {
+                if matches(&event) {
+                    return Ok(event);
+                }
+            }
+            Err(anyhow::anyhow!("The room stopped"))
+        })
+        .await?
+    };
+
+    let host = FfiRoom::create(Arc::new(Counter("counter")), None, None).await?;
+    host.announce_presence("host".to_string()).await?;
+    let ticket = host.ticket().await?;
+    assert!(RoomTicket::parse(&ticket)?.game_type.ends_with("counter"));
+    // Foreign games are told apart by their own names.
+    assert!(
+        FfiRoom::join(Arc::new(Counter("chess")), ticket.clone(), None)
+            .await
+            .is_err()
+    );
+
+    let client = FfiRoom::join(Arc::new(Counter("counter")), ticket, None).await?;
+    client.announce_presence("client".to_string()).await?;
+    await_ffi_event(
+        &host,
+        &|event| matches!(event, FfiEvent::Peers { peers } if peers.len() == 2),
+    )
+    .await?;
+    host.set_ready(true).await?;
+    client.set_ready(true).await?;
+    await_ffi_event(
+        &host,
+        &|event| matches!(event, FfiEvent::Peers { peers } if peers.iter().all(|peer| peer.ready)),
+    )
+    .await?;
+    host.start_game().await?;
+    await_ffi_event(&client, &|event| {
+        matches!(event, FfiEvent::GameStarted { .. })
+    })
+    .await?;
+
+    client.submit_action(Vec::new()).await?;
+    await_ffi_event(
+        &client,
+        &|event| matches!(event, FfiEvent::GameState { state } if state == &[1]),
+    )
+    .await?;
+    assert_eq!(host.game_state().await?, vec![1]);
+
+    // A winner that is not a peer rejects the action rather than being dropped.
+    let seq = client.submit_action(Vec::new()).await?;
+    let rejected = await_ffi_event(&client, &|event| {
+        matches!(event, FfiEvent::ActionRejected { seq: rejected, .. } if *rejected == seq)
+    })
+    .await?;
+    assert!(matches!(rejected, FfiEvent::ActionRejected { error, .. } if error.contains("nobody")));
+    assert_eq!(host.game_state().await?, vec![1]);
+    Ok(())
+}
+
+#[cfg(feature = "bridge")]
+#[tokio::test]
+async fn test_bridge_drives_the_room_over_json_rpc() -> anyhow::Result<()> {
+    use futures::{SinkExt, StreamExt};
+    use p2p_game_engine::bridge::Bridge;
+    use serde_json::{Value, json};
+    use tokio_tungstenite::tungstenite::Message;
+
+    let (host_room, _ticket_string, _host_id, _host_events) = setup_test_room("host").await?;
+    let bridge = Bridge::serve(std::sync::Arc::new(host_room), "127.0.0.1:0").await?;
+    let url = format!("ws://{}/?token={}", bridge.local_addr(), bridge.token());
+    let (mut socket, _) = tokio_tungstenite::connect_async(url).await?;
+
+    // Read messages until one matches, skipping the room's other events.
+    let mut call = async |request: Option<Value>, matches: &dyn Fn(&Value) -> bool| {
+        if let Some(request) = request {
+            socket.send(Message::text(request.to_string())).await?;
+        }
+        loop {
+            let Some(Message::Text(text)) = socket.next().await.transpose()? else {
+                continue;
+            };
+            let message: Value = serde_json::from_str(&text)?;
+            if matches(&message) {
+                return anyhow::Ok(message);
+            }
+        }
+    };
+    let id = |id: i64| move |message: &Value| message["id"] == id;
+
+    let state = json!({ "jsonrpc": "2.0", "id": 1, "method": "get_app_state" });
+    assert_eq!(call(Some(state), &id(1)).await?["result"], "Lobby");
+    let unknown = json!({ "jsonrpc": "2.0", "id": 2, "method": "fly" });
+    assert_eq!(call(Some(unknown), &id(2)).await?["error"]["code"], -32601);
+
+    let chat = json!({
+        "jsonrpc": "2.0",
+        "id": 3,
+        "method": "send_chat",
+        "params": { "message": "hello" },
+    });
+    assert_eq!(call(Some(chat), &id(3)).await?["result"], Value::Null);
+    let event = call(None, &|message| message["params"]["Chat"].is_object()).await?;
+    assert_eq!(event["params"]["Chat"]["msg"]["message"], "hello");
+    Ok(())
+}
+
+#[cfg(feature = "bridge")]
+#[tokio::test]
+async fn test_bridge_refuses_clients_without_the_token_or_from_other_origins() -> anyhow::Result<()>
+{
+    use p2p_game_engine::bridge::{Bridge, BridgeConfig};
+    use tokio_tungstenite::tungstenite::{Error, client::IntoClientRequest};
+
+    let (host_room, _ticket_string, _host_id, _host_events) = setup_test_room("host").await?;
+    let config = BridgeConfig::default()
+        .token("s3cret/+=")
+        .allow_origin("http://localhost:5173");
+    let bridge =
+        Bridge::serve_with_config(std::sync::Arc::new(host_room), "127.0.0.1:0", config).await?;
+    let addr = bridge.local_addr();
+    let status = |result: Result<_, Error>| match result {
+        Err(Error::Http(response)) => Some(response.status().as_u16()),
+        _ => None,
+    };
+
+    let no_token = tokio_tungstenite::connect_async(format!("ws://{addr}")).await;
+    assert_eq!(status(no_token), Some(401));
+    let wrong_token = tokio_tungstenite::connect_async(format!("ws://{addr}/?token=guess")).await;
+    assert_eq!(status(wrong_token), Some(401));
+    let (_socket, _) =
+        tokio_tungstenite::connect_async(format!("ws://{addr}/?token=s3cret%2F%2B%3D")).await?;
+
+    let from = |origin: &str| {
+        let mut request = format!("ws://{addr}").into_client_request()?;
+        request
+            .headers_mut()
+            .insert("authorization", "Bearer s3cret/+=".parse()?);
+        request.headers_mut().insert("origin", origin.parse()?);
+        anyhow::Ok(request)
+    };
+    let hijacked = tokio_tungstenite::connect_async(from("https://evil.example")?).await;
+    assert_eq!(status(hijacked), Some(403));
+    let (_socket, _) = tokio_tungstenite::connect_async(from("http://localhost:5173")?).await?;
+    Ok(())
+}
+
+#[cfg(feature = "ratings")]
+#[tokio::test]
+async fn test_ratings_follow_finished_games() -> anyhow::Result<()> {
+    use p2p_game_engine::ratings::Ratings;
+
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let temp = tempfile::tempdir()?;
+    let mut identity = Identity::load_or_create(temp.path(), "host").await?;
+    let (host_room, mut host_events) =
+        GameRoom::create_with_identity(TestGame, &mut identity, None, RoomConfig::default())
+            .await?;
+    await_lobby_contains(&mut host_events, &identity.id()).await?;
+    let ticket_string = host_room.ticket().await?.to_string();
+    let (client_room, mut client_events) = join_test_room("client", &ticket_string, 3).await?;
+    let client_id = client_room.id();
+    await_lobby_ready_update(&mut host_events, &client_id, true).await?;
+    host_room.set_ready(true).await?;
+    host_room.start_game().await?;
+    await_game_start(&mut client_events).await?;
+    host_room.submit_action(TestGameAction::Win).await?;
+    loop {
+        if let UiEvent::GameOver(_) = await_event(&mut host_events).await? {
+            break;
+        }
+    }
+
+    let mut ratings = Ratings::load(&identity).await?;
+    assert_eq!(ratings.get_rating(&client_id), 1200);
+    ratings.record_history(&identity).await?;
+    let (host_rating, client_rating) = (
+        ratings.get_rating(&identity.id()),
+        ratings.get_rating(&client_id),
+    );
+    assert_eq!((host_rating, client_rating), (1216, 1184));
+
+    // Games already rated are not rated again, and ratings survive a reload.
+    let mut reloaded = Ratings::load(&identity).await?;
+    reloaded.record_history(&identity).await?;
+    assert_eq!(reloaded.get_rating(&identity.id()), host_rating);
+
+    // A shared rating travels with the profile.
+    reloaded.share(&mut identity);
+    host_room
+        .announce_presence(identity.profile.clone())
+        .await?;
+    tokio::time::timeout(std::time::Duration::from_secs(10), async {
+        loop {
+            let players = client_room.get_peer_list().await?;
+            if players[&identity.id()].profile.rating == Some(host_rating) {
+                return anyhow::Ok(());
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+    })
+    .await??;
+    Ok(())
+}
+
+#[cfg(feature = "runner")]
+#[tokio::test]
+async fn test_host_runner_stops_once_the_room_is_idle() -> anyhow::Result<()> {
+    use p2p_game_engine::runner::{HostRunner, StopReason};
+
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let (ticket_tx, ticket_rx) = tokio::sync::oneshot::channel();
+    let runner = HostRunner::new(TestGame, RoomConfig::default())
+        .idle_timeout(std::time::Duration::from_secs(2))
+        .on_ticket(move |ticket| {
+            ticket_tx.send(ticket.to_string()).ok();
+        });
+    let running = tokio::spawn(runner.run_until_stopped());
+
+    let ticket_string = ticket_rx.await?;
+    let (client_room, _client_events) = join_test_room("client", &ticket_string, 3).await?;
+    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+    assert!(!running.is_finished(), "stopped with a player online");
+
+    client_room
+        .leave(&LeaveReason::ApplicationClosed, true)
+        .await?;
+    let reason = tokio::time::timeout(std::time::Duration::from_secs(30), running).await???;
+    assert_eq!(reason, StopReason::Idle);
+    Ok(())
+}
+
+#[cfg(feature = "runner")]
+#[tokio::test]
+async fn test_host_runner_with_no_idle_time_stops_straight_away() -> anyhow::Result<()> {
+    use p2p_game_engine::runner::{HostRunner, StopReason};
+
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let runner = HostRunner::new(TestGame, RoomConfig::default())
+        .idle_timeout(std::time::Duration::ZERO)
+        .run_until_stopped();
+    let reason = tokio::time::timeout(std::time::Duration::from_secs(30), runner).await??;
+    assert_eq!(reason, StopReason::Idle);
+    Ok(())
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_blocking_rooms_play_without_an_async_runtime() -> anyhow::Result<()> {
+    use p2p_game_engine::sync::BlockingGameRoom;
+
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    fn await_event(
+        room: &mut BlockingGameRoom<TestGame>,
+        mut matches: impl FnMut(&UiEvent<TestGame>) -> bool,
+    ) -> anyhow::Result<()> {
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(30);
+        while std::time::Instant::now() < deadline {
+            if let Some(event) = room.poll_event(std::time::Duration::from_millis(100))
+                && matches(&event)
+            {
+                return Ok(());
+            }
+        }
+        anyhow::bail!("Timed out waiting for an event")
+    }
+
+    fn enter_lobby(room: &mut BlockingGameRoom<TestGame>, name: &str) -> anyhow::Result<()> {
+        room.announce_presence(name)?;
+        let id = room.id();
+        await_event(room, |event| match event {
+            UiEvent::Peer(peers) => peers.contains_key(&id),
+            _ => false,
+        })?;
+        room.set_ready(true)
+    }
+
+    let mut host = BlockingGameRoom::create(TestGame, None, RoomConfig::default())?;
+    enter_lobby(&mut host, "host")?;
+    let ticket = host.ticket()?.to_string();
+    let mut client = BlockingGameRoom::join(TestGame, &ticket, RoomConfig::default())?;
+    enter_lobby(&mut client, "client")?;
+    let client_id = client.id();
+    await_event(&mut host, |event| match event {
+        UiEvent::Peer(peers) => peers.get(&client_id).is_some_and(|peer| peer.ready),
+        _ => false,
+    })?;
+
+    host.start_game()?;
+    await_event(&mut client, |event| {
+        matches!(event, UiEvent::GameStarted { .. })
+    })?;
+    client.submit_action(TestGameAction::Increment)?;
+    await_event(
+        &mut host,
+        |event| matches!(event, UiEvent::GameState(state) if state.counter == 1),
+    )?;
+    assert_eq!(host.get_game_state()?.counter, 1);
+    // Nothing else is waiting once the latest state has been seen.
+    while host.try_event().is_some() {}
+
+    client.leave(&LeaveReason::ApplicationClosed)?;
+    host.leave(&LeaveReason::ApplicationClosed)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sim_network_injects_sync_delays_and_crashes() -> anyhow::Result<()> {
+    let network = testing::SimNetwork::new();
+    let [mut host, client, crashed] = spawn_sim_peers(&network, TestGame).await?;
+
+    // A message sent while the client is cut off only arrives once it syncs again.
+    client.delay_sync().await?;
+    client.room.send_chat("sent while cut off").await?;
+    client.resume_sync().await?;
+    let message = host
+        .expect_event(|event| match event {
+            UiEvent::Chat { msg, .. } => Some(msg.message),
+            _ => None,
+        })
+        .await?;
+    assert_eq!(message, "sent while cut off");
+
+    let crashed_id = crashed.id();
+    crashed.crash().await?;
+    host.wait_until(async |room| {
+        let peers = room.get_peer_list().await?;
+        let offline = peers.get(&crashed_id).map(|peer| peer.status) == Some(PeerStatus::Offline);
+        Ok(offline.then_some(()))
+    })
+    .await?;
+    client
+        .wait_until(async |room| {
+            let peers = room.get_peer_list().await?;
+            let offline =
+                peers.get(&crashed_id).map(|peer| peer.status) == Some(PeerStatus::Offline);
+            Ok(offline.then_some(()))
+        })
+        .await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_faults_replay_and_fake_network_events() -> anyhow::Result<()> {
+    let network = testing::SimNetwork::new();
+    let [host, mut client] = spawn_sim_peers(&network, TestGame).await?;
+    let host_id = host.id();
+
+    // Every entry the host receives from the client arrives twice.
+    let client_id = client.id();
+    host.room.faults().add(
+        testing::Fault::Duplicate,
+        usize::MAX,
+        move |event| matches!(event, LiveEvent::InsertRemote { from, .. } if *from == client_id),
+    );
+    ready_sim_peers(&[&host, &client]).await?;
+    host.room.start_game().await?;
+    await_room_app_state(&client.room, AppState::InGame).await?;
+    client.room.submit_action(TestGameAction::Increment).await?;
+    let seq = client.room.submit_action(TestGameAction::Increment).await?;
+    // The host answers once it has applied the last action, and the duplicates before it.
+    client
+        .expect_event(|event| match event {
+            UiEvent::ActionAccepted { seq: accepted, .. } => (accepted == seq).then_some(()),
+            _ => None,
+        })
+        .await?;
+    assert_eq!(host.room.get_game_state().await?.counter, 2);
+    host.room.faults().clear();
+
+    client.room.faults().fail_sync(host_id, "injected")?;
+    let reason = client
+        .expect_event(|event| match event {
+            UiEvent::Error(UiError::SyncFailed(reason)) => Some(reason),
+            _ => None,
+        })
+        .await?;
+    assert_eq!(reason, "injected");
+
+    client
+        .room
+        .faults()
+        .inject(LiveEvent::NeighborDown(host_id))?;
+    client
+        .expect_event(|event| match event {
+            UiEvent::Host(HostEvent::Offline) => Some(()),
+            _ => None,
+        })
+        .await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_event_loop_restarts_after_a_panic() -> anyhow::Result<()> {
+    let network = testing::SimNetwork::new();
+    let [mut host, client] = spawn_sim_peers(&network, TestGame).await?;
+
+    let client_id = client.id();
+    host.room.faults().add(
+        testing::Fault::Panic,
+        1,
+        move |event| matches!(event, LiveEvent::InsertRemote { from, .. } if *from == client_id),
+    );
+    client.room.set_ready(true).await?;
+    host.expect_event(|event| match event {
+        UiEvent::Error(UiError::EventLoopRestarted) => Some(()),
+        _ => None,
+    })
+    .await?;
+
+    client.room.send_chat("still there?").await?;
+    let message = host
+        .expect_event(|event| match event {
+            UiEvent::Chat { msg, .. } => Some(msg.message),
+            _ => None,
+        })
+        .await?;
+    assert_eq!(message, "still there?");
+    Ok(())
+}
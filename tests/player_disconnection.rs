@@ -10,8 +10,11 @@
 //! - inform connected peers.
 //! - host sets their status to Offline.
 
+#![allow(clippy::await_holding_lock)]
+
 mod common;
 use common::*;
+use iroh_docs::engine::LiveEvent;
 
 static PERSISTENT_ROOM_TEST_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
 
@@ -363,6 +366,12 @@ async fn test_client_peer_forfeits() -> anyhow::Result<()> {
     await_game_start(&mut client_events).await?;
 
     client_room.forfeit().await?;
+    loop {
+        if let UiEvent::PlayerForfeited { id } = await_event(&mut host_events).await? {
+            assert_eq!(id, client_id);
+            break;
+        }
+    }
     await_lobby_observer_update(&mut host_events, &client_id, true).await?;
 
     let result = client_room.submit_action(TestGameAction::Increment).await;
@@ -438,3 +447,165 @@ async fn test_host_forfeits() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_watchers_follow_app_state_and_host_connection() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    let timeout = std::time::Duration::from_secs(30);
+    let (host_room, ticket_string, _host_id, mut host_events) = setup_test_room("peer1").await?;
+    let (client_room, _client_events) = join_test_room("peer2", &ticket_string, 3).await?;
+    await_lobby_ready_update(&mut host_events, &client_room.id(), true).await?;
+
+    let mut app_state = client_room.watch_app_state();
+    let mut host_online = client_room.watch_host_online();
+    assert_eq!(*app_state.borrow(), AppState::Lobby);
+    assert!(*host_online.borrow());
+
+    host_room.start_game().await?;
+    tokio::time::timeout(
+        timeout,
+        app_state.wait_for(|state| *state == AppState::InGame),
+    )
+    .await??;
+
+    // Dropping the host pauses the game without any document update.
+    drop(host_room);
+    tokio::time::timeout(timeout, host_online.wait_for(|online| !online)).await??;
+    assert_eq!(*app_state.borrow_and_update(), AppState::Paused);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_host_hands_over_before_leaving() -> anyhow::Result<()> {
+    let _room_guard = PERSISTENT_ROOM_TEST_LOCK.lock().unwrap();
+    // The host picks its successor, so the game carries on without being paused.
+    let (host_room, ticket_string, _host_id, mut host_events) = setup_test_room("host").await?;
+    let (client_room1, mut client_events1) = join_test_room("client1", &ticket_string, 3).await?;
+    let (client_room2, _client_events2) = join_test_room("client2", &ticket_string, 3).await?;
+
+    await_lobby_ready_update(&mut host_events, &client_room1.id(), true).await?;
+    await_lobby_ready_update(&mut host_events, &client_room2.id(), true).await?;
+
+    host_room.start_game().await?;
+    await_game_start(&mut client_events1).await?;
+
+    host_room.transfer_host(&client_room2.id()).await?;
+    let changed = HostEvent::Changed {
+        to: "client2".to_string(),
+    };
+    await_host_event(&mut host_events, changed.clone()).await?;
+    await_host_event(&mut client_events1, changed).await?;
+    await_is_host(&client_room2, true).await?;
+    assert!(!host_room.is_host().await?);
+    assert!(host_room.transfer_host(&client_room1.id()).await.is_err());
+
+    host_room
+        .leave(&LeaveReason::ApplicationClosed, true)
+        .await?;
+    client_room1
+        .submit_action(TestGameAction::Increment)
+        .await?;
+    await_counter_state(&mut client_events1, 1).await?;
+    assert!(*client_room1.watch_host_online().borrow());
+    assert_eq!(client_room1.get_app_state().await?, AppState::InGame);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_peers_are_reported_joining_changing_and_leaving() -> anyhow::Result<()> {
+    let network = testing::SimNetwork::new();
+    let mut host = network.host(TestGame, "host").await?;
+    let client = network.join(TestGame, &host, "client").await?;
+    let client_id = client.id();
+
+    host.expect_event(|event| match event {
+        UiEvent::PeerJoined(peer) if peer.id == client_id => Some(()),
+        _ => None,
+    })
+    .await?;
+
+    client.room.announce_presence("renamed").await?;
+    let nickname = host
+        .expect_event(|event| match event {
+            UiEvent::PeerUpdated(peer) if peer.id == client_id => Some(peer.profile.nickname),
+            _ => None,
+        })
+        .await?;
+    assert_eq!(nickname, "renamed");
+
+    client
+        .room
+        .leave(&LeaveReason::ApplicationClosed, true)
+        .await?;
+    let reason = host
+        .expect_event(|event| match event {
+            UiEvent::PeerLeft { id, reason } if id == client_id => Some(reason),
+            _ => None,
+        })
+        .await?;
+    assert_eq!(reason, Some(LeaveReason::ApplicationClosed));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_leaving_mid_game_tells_the_game_and_the_room() -> anyhow::Result<()> {
+    let network = testing::SimNetwork::new();
+    let [mut host, client] = start_sim_game(&network, TestGame).await?;
+    let client_id = client.id();
+
+    client
+        .room
+        .leave(&LeaveReason::ApplicationClosed, true)
+        .await?;
+    let reason = host
+        .expect_event(|event| match event {
+            UiEvent::PeerLeft { id, reason } if id == client_id => Some(reason),
+            _ => None,
+        })
+        .await?;
+    assert_eq!(reason, Some(LeaveReason::ApplicationClosed));
+    // The host lets the game deal with the player it lost.
+    let state = host
+        .wait_until(async |room| {
+            let state = room.get_game_state().await?;
+            Ok((!state.away.is_empty()).then_some(state))
+        })
+        .await?;
+    assert_eq!(state.away, vec![client_id]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_game_hears_of_players_dropping_out_and_back() -> anyhow::Result<()> {
+    let network = testing::SimNetwork::new();
+    let [host, client] = start_sim_game(&network, TestGame).await?;
+    let client_id = client.id();
+
+    // A neighbour coming up that never went down is not a reconnection.
+    host.room
+        .faults()
+        .inject(LiveEvent::NeighborUp(client_id))?;
+    host.room
+        .faults()
+        .inject(LiveEvent::NeighborDown(client_id))?;
+    let state = client
+        .wait_until(async |room| {
+            let state = room.get_game_state().await?;
+            Ok((!state.away.is_empty()).then_some(state))
+        })
+        .await?;
+    assert_eq!(state.away, vec![client_id]);
+
+    host.room
+        .faults()
+        .inject(LiveEvent::NeighborUp(client_id))?;
+    client
+        .wait_until(async |room| {
+            let state = room.get_game_state().await?;
+            Ok(state.away.is_empty().then_some(()))
+        })
+        .await?;
+    let peers = host.room.get_peer_list().await?;
+    assert_eq!(peers[&client_id].status, PeerStatus::Online);
+    Ok(())
+}